@@ -0,0 +1,110 @@
+//! Build script recording build provenance for `symcurve -V --verbose` and the run manifest:
+//! the git commit, the build date, the enabled cargo features and the versions of the linked
+//! libraries, as `SYMCURVE_*` compile-time environment variables.
+
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Libraries whose versions are recorded, as named in `Cargo.lock`.
+const LIBRARIES: [&str; 4] = ["noodles-fasta", "noodles-core", "flate2", "clap"];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rustc-env=SYMCURVE_GIT_COMMIT={}", git_commit());
+    println!("cargo:rustc-env=SYMCURVE_BUILD_DATE={}", build_date());
+    println!("cargo:rustc-env=SYMCURVE_FEATURES={}", features());
+    println!("cargo:rustc-env=SYMCURVE_LIBRARIES={}", libraries());
+}
+
+/// The commit the crate was built from, with `-dirty` if the tree had changes, or `unknown`
+/// outside a git checkout.
+fn git_commit() -> String {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    match git(&["rev-parse", "--short=12", "HEAD"]) {
+        Some(commit) if !commit.is_empty() => {
+            match git(&["status", "--porcelain", "--untracked-files=no"]) {
+                Some(status) if !status.is_empty() => format!("{}-dirty", commit),
+                _ => commit,
+            }
+        }
+        _ => "unknown".to_string(),
+    }
+}
+
+/// The UTC build date as `YYYY-MM-DD`, from `SOURCE_DATE_EPOCH` for reproducible builds.
+fn build_date() -> String {
+    let seconds = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse::<i64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs() as i64)
+        });
+    // days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let z = seconds.div_euclid(86_400) + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// The enabled cargo features, comma-separated.
+fn features() -> String {
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_ascii_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+    features.join(",")
+}
+
+/// The locked versions of the recorded libraries, as comma-separated `name=version` pairs.
+fn libraries() -> String {
+    let lock = fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let mut versions = Vec::new();
+    let mut name = None;
+    for line in lock.lines() {
+        if let Some(value) = line.strip_prefix("name = ") {
+            name = Some(value.trim_matches('"').to_string());
+        } else if let Some(value) = line.strip_prefix("version = ") {
+            if let Some(name) = name
+                .take()
+                .filter(|name| LIBRARIES.contains(&name.as_str()))
+            {
+                versions.push((name, value.trim_matches('"').to_string()));
+            }
+        }
+    }
+    LIBRARIES
+        .iter()
+        .map(|library| {
+            let version = versions
+                .iter()
+                .find(|(name, _)| name == library)
+                .map_or("unknown", |(_, version)| version.as_str());
+            format!("{}={}", library, version)
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
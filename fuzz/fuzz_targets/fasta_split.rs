@@ -0,0 +1,25 @@
+//! Parses arbitrary bytes as FASTA and splits every record on runs of `N`, checking that each
+//! piece can be sliced out of its record and holds no `N`.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use symcurve::fasta::split_seq_by_n;
+use symcurve::fasta::validate::check_record;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = noodles_fasta::Reader::new(data);
+    for (index, result) in (1..).zip(reader.records()) {
+        let Ok(record) = result else {
+            break;
+        };
+        let _ = check_record(
+            index,
+            &String::from_utf8_lossy(record.name()),
+            record.sequence().as_ref(),
+        );
+        for piece in split_seq_by_n(record) {
+            assert!(piece.start <= piece.end);
+            assert!(!piece.sequence().as_ref().contains(&b'N'));
+        }
+    }
+});
@@ -0,0 +1,16 @@
+//! Streams arbitrary bytes through `StreamingReader`, checking that no base it yields is a line
+//! break.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use symcurve::fasta::StreamingReader;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = StreamingReader::new(data);
+    while let Ok(Some(_name)) = reader.next_record() {
+        for base in reader.bases() {
+            let base = base.unwrap();
+            assert!(base != b'\n' && base != b'\r');
+        }
+    }
+});
@@ -0,0 +1,14 @@
+//! Parses arbitrary text as JSON, as read back from manifests and matrix and parameter files;
+//! anything that parses must print and parse again to the same value.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use symcurve::json::Json;
+
+fuzz_target!(|text: &str| {
+    let Ok(json) = text.parse::<Json>() else {
+        return;
+    };
+    let reparsed: Json = json.to_string().parse().unwrap();
+    assert_eq!(reparsed.to_string(), json.to_string());
+});
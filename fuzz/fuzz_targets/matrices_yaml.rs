@@ -0,0 +1,23 @@
+//! Loads arbitrary text as a matrices YAML file; anything that loads must write back out and load
+//! again to the same matrices.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use symcurve::curve::matrix::{write_matrices_yaml, Matrices};
+
+fuzz_target!(|text: &str| {
+    let Ok(matrices) = Matrices::parse_yaml(text) else {
+        return;
+    };
+    let mut written = Vec::new();
+    write_matrices_yaml(
+        &mut written,
+        "",
+        &matrices.twist,
+        &matrices.roll,
+        &matrices.tilt,
+    )
+    .unwrap();
+    let reread = Matrices::parse_yaml(std::str::from_utf8(&written).unwrap()).unwrap();
+    assert_eq!(reread, matrices);
+});
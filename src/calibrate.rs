@@ -0,0 +1,735 @@
+//! # Calibrate module
+//! The `symcurve calibrate` subcommand, which tunes how symcurve tracks are turned into a call of
+//! nucleosome occupancy against an experimental map such as MNase-seq coverage.
+//!
+//! The predictor is a weighted sum of the given tracks, each first scaled to zero mean and unit
+//! standard deviation, called occupied above a threshold. Positions are sampled every
+//! `--stride` bases where every track and the truth have a value, and a position counts as truly
+//! occupied when the truth is above `--truth-threshold` (its median if not given). Every
+//! combination of weights on a grid is scored by the area under its ROC curve; for the best one,
+//! the threshold with the highest F1 score is the operating point. The report goes to stdout, the
+//! ROC and PR curves of the best weights optionally to a TSV file, and the weights, scaling and
+//! threshold to a JSON calibration file that [`Calibration::from_json`] reads back.
+
+use crate::json::Json;
+use crate::stats::streaming::RunningStats;
+use crate::view::{read_track, Interval, Region};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The most points written for each curve; longer curves are thinned evenly.
+pub const CURVE_POINTS: usize = 200;
+
+/// The settings of a calibration run.
+///
+/// # Fields
+///
+/// * `tracks`: The symcurve tracks combined into the predictor.
+/// * `truth`: The experimental occupancy track.
+/// * `truth_threshold`: The truth value above which a position is occupied; the median if `None`.
+/// * `region`: The region to calibrate over, or `None` for every shared chromosome.
+/// * `stride`: The distance in bases between sampled positions.
+/// * `weight_step`: The step of the weight grid.
+/// * `curves`: Where the ROC and PR curves of the best weights are written, if anywhere.
+/// * `output`: Where the calibration file is written.
+#[derive(Clone, Debug)]
+pub struct CalibrateParams {
+    pub tracks: Vec<PathBuf>,
+    pub truth: PathBuf,
+    pub truth_threshold: Option<f64>,
+    pub region: Option<Region>,
+    pub stride: u64,
+    pub weight_step: f64,
+    pub curves: Option<PathBuf>,
+    pub output: PathBuf,
+}
+
+/// Values at sampled positions.
+///
+/// # Fields
+///
+/// * `tracks`: The value of each predictor track, one vector per track.
+/// * `truth`: The truth value.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Samples {
+    pub tracks: Vec<Vec<f64>>,
+    pub truth: Vec<f64>,
+}
+
+impl Samples {
+    /// The number of sampled positions.
+    pub fn len(&self) -> usize {
+        self.truth.len()
+    }
+
+    /// Whether no position was sampled.
+    pub fn is_empty(&self) -> bool {
+        self.truth.is_empty()
+    }
+}
+
+/// Groups the intervals of a track by chromosome, sorted by start.
+fn by_chrom(intervals: Vec<(String, Interval)>) -> HashMap<String, Vec<Interval>> {
+    let mut chroms: HashMap<String, Vec<Interval>> = HashMap::new();
+    for (chrom, interval) in intervals {
+        chroms.entry(chrom).or_default().push(interval);
+    }
+    for intervals in chroms.values_mut() {
+        intervals.sort_by_key(|interval| interval.start);
+    }
+    chroms
+}
+
+/// Finds the value at a position in sorted intervals, moving `cursor` forward; positions must be
+/// asked for in increasing order. Returns `None` outside every interval and for `NaN`.
+fn value_at(intervals: &[Interval], cursor: &mut usize, pos: u64) -> Option<f64> {
+    while *cursor < intervals.len() && intervals[*cursor].end <= pos {
+        *cursor += 1;
+    }
+    let interval = intervals.get(*cursor)?;
+    (interval.start <= pos && !interval.value.is_nan()).then_some(interval.value as f64)
+}
+
+/// Samples the tracks and the truth at every `stride`th position of the chromosomes of the truth
+/// that every track also covers, keeping the positions where all of them have a value.
+///
+/// # Arguments
+///
+/// * `tracks` - The intervals of each predictor track.
+/// * `truth` - The intervals of the truth track.
+/// * `stride` - The distance in bases between sampled positions.
+pub fn sample(
+    tracks: Vec<Vec<(String, Interval)>>,
+    truth: Vec<(String, Interval)>,
+    stride: u64,
+) -> Samples {
+    let mut order = Vec::new();
+    for (chrom, _) in &truth {
+        if order.last() != Some(chrom) && !order.contains(chrom) {
+            order.push(chrom.clone());
+        }
+    }
+    let tracks: Vec<_> = tracks.into_iter().map(by_chrom).collect();
+    let truth = by_chrom(truth);
+    let mut samples = Samples {
+        tracks: vec![Vec::new(); tracks.len()],
+        truth: Vec::new(),
+    };
+    for chrom in order {
+        let truth_intervals = &truth[&chrom];
+        let Some(track_intervals) = tracks
+            .iter()
+            .map(|track| track.get(&chrom).map(Vec::as_slice))
+            .collect::<Option<Vec<_>>>()
+        else {
+            continue;
+        };
+        let first = truth_intervals[0].start.div_ceil(stride) * stride;
+        let last = truth_intervals.iter().map(|i| i.end).max().unwrap_or(0);
+        let mut truth_cursor = 0;
+        let mut cursors = vec![0; track_intervals.len()];
+        let mut values = vec![0.0; track_intervals.len()];
+        let mut pos = first;
+        'positions: while pos < last {
+            let here = pos;
+            pos += stride;
+            let Some(truth_value) = value_at(truth_intervals, &mut truth_cursor, here) else {
+                continue;
+            };
+            for (i, intervals) in track_intervals.iter().enumerate() {
+                match value_at(intervals, &mut cursors[i], here) {
+                    Some(value) => values[i] = value,
+                    None => continue 'positions,
+                }
+            }
+            for (track, &value) in samples.tracks.iter_mut().zip(&values) {
+                track.push(value);
+            }
+            samples.truth.push(truth_value);
+        }
+    }
+    samples
+}
+
+/// The weight combinations tried: every vector of multiples of `step` whose absolute values sum
+/// to 1, so that each track can count for or against occupancy.
+pub fn weight_grid(tracks: usize, step: f64) -> Vec<Vec<f64>> {
+    let units = (1.0 / step).round().max(1.0) as i64;
+    let mut grid = Vec::new();
+    let mut current = Vec::with_capacity(tracks);
+    fill_grid(tracks, units, &mut current, &mut grid);
+    grid.into_iter()
+        .map(|weights| {
+            weights
+                .into_iter()
+                .map(|w| w as f64 / units as f64)
+                .collect()
+        })
+        .collect()
+}
+
+/// Adds to `grid` every completion of `current` with `tracks` more integers whose absolute
+/// values sum to `left`.
+fn fill_grid(tracks: usize, left: i64, current: &mut Vec<i64>, grid: &mut Vec<Vec<i64>>) {
+    if tracks == 1 {
+        for w in [left, -left] {
+            current.push(w);
+            grid.push(current.clone());
+            current.pop();
+            if left == 0 {
+                break;
+            }
+        }
+        return;
+    }
+    for w in -left..=left {
+        current.push(w);
+        fill_grid(tracks - 1, left - w.abs(), current, grid);
+        current.pop();
+    }
+}
+
+/// One point of a ROC or PR curve: the counts of positions at or above a threshold.
+///
+/// # Fields
+///
+/// * `threshold`: The predictor score.
+/// * `true_positives`: Occupied positions scoring at least `threshold`.
+/// * `false_positives`: Unoccupied positions scoring at least `threshold`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CurvePoint {
+    pub threshold: f64,
+    pub true_positives: u64,
+    pub false_positives: u64,
+}
+
+/// The ROC and PR curves of a predictor.
+///
+/// # Fields
+///
+/// * `points`: One point per distinct score, from the highest score down.
+/// * `positives`: The number of occupied positions.
+/// * `negatives`: The number of unoccupied positions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Curves {
+    pub points: Vec<CurvePoint>,
+    pub positives: u64,
+    pub negatives: u64,
+}
+
+impl Curves {
+    /// Computes the curves of scores against labels.
+    pub fn new(scores: &[f64], labels: &[bool]) -> Self {
+        let mut order: Vec<usize> = (0..scores.len()).collect();
+        order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+        let mut points: Vec<CurvePoint> = Vec::new();
+        let (mut tp, mut fp) = (0, 0);
+        for (k, &i) in order.iter().enumerate() {
+            if labels[i] {
+                tp += 1;
+            } else {
+                fp += 1;
+            }
+            let last_of_score = order
+                .get(k + 1)
+                .is_none_or(|&next| scores[next] != scores[i]);
+            if last_of_score {
+                points.push(CurvePoint {
+                    threshold: scores[i],
+                    true_positives: tp,
+                    false_positives: fp,
+                });
+            }
+        }
+        Self {
+            points,
+            positives: tp,
+            negatives: fp,
+        }
+    }
+
+    /// The true positive rate (recall) at a point.
+    pub fn recall(&self, point: &CurvePoint) -> f64 {
+        point.true_positives as f64 / self.positives.max(1) as f64
+    }
+
+    /// The false positive rate at a point.
+    pub fn false_positive_rate(&self, point: &CurvePoint) -> f64 {
+        point.false_positives as f64 / self.negatives.max(1) as f64
+    }
+
+    /// The precision at a point.
+    pub fn precision(point: &CurvePoint) -> f64 {
+        let called = point.true_positives + point.false_positives;
+        point.true_positives as f64 / called.max(1) as f64
+    }
+
+    /// The F1 score at a point.
+    pub fn f1(&self, point: &CurvePoint) -> f64 {
+        let precision = Self::precision(point);
+        let recall = self.recall(point);
+        if precision + recall == 0.0 {
+            0.0
+        } else {
+            2.0 * precision * recall / (precision + recall)
+        }
+    }
+
+    /// The area under the ROC curve, by the trapezoid rule.
+    pub fn auc_roc(&self) -> f64 {
+        let mut area = 0.0;
+        let (mut x, mut y) = (0.0, 0.0);
+        for point in &self.points {
+            let (nx, ny) = (self.false_positive_rate(point), self.recall(point));
+            area += (nx - x) * (y + ny) / 2.0;
+            (x, y) = (nx, ny);
+        }
+        area
+    }
+
+    /// The area under the PR curve, as average precision: the precision at each point weighted
+    /// by the recall it adds.
+    pub fn auc_pr(&self) -> f64 {
+        let mut area = 0.0;
+        let mut recall = 0.0;
+        for point in &self.points {
+            let next = self.recall(point);
+            area += (next - recall) * Self::precision(point);
+            recall = next;
+        }
+        area
+    }
+
+    /// The point with the highest F1 score.
+    pub fn best_point(&self) -> Option<&CurvePoint> {
+        self.points
+            .iter()
+            .max_by(|a, b| self.f1(a).total_cmp(&self.f1(b)))
+    }
+
+    /// Writes the curves as TSV, thinned to at most [`CURVE_POINTS`] points.
+    pub fn write_tsv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "threshold\tfpr\ttpr\tprecision\tf1")?;
+        let every = self.points.len().div_ceil(CURVE_POINTS).max(1);
+        let last = self.points.len().saturating_sub(1);
+        for (i, point) in self.points.iter().enumerate() {
+            if i % every != 0 && i != last {
+                continue;
+            }
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}",
+                point.threshold,
+                self.false_positive_rate(point),
+                self.recall(point),
+                Self::precision(point),
+                self.f1(point)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// How one track enters the predictor.
+///
+/// # Fields
+///
+/// * `path`: The track.
+/// * `mean`: Its mean over the sampled positions.
+/// * `sd`: Its standard deviation over the sampled positions.
+/// * `weight`: Its weight once scaled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrackScaling {
+    pub path: PathBuf,
+    pub mean: f64,
+    pub sd: f64,
+    pub weight: f64,
+}
+
+/// A calibrated occupancy predictor and how well it did.
+///
+/// # Fields
+///
+/// * `tracks`: The tracks combined, with their scaling and weights.
+/// * `threshold`: The score above which a position is called occupied.
+/// * `truth_threshold`: The truth value that separated occupied from unoccupied positions.
+/// * `samples`: The number of positions sampled.
+/// * `auc_roc`: The area under the ROC curve.
+/// * `auc_pr`: The area under the PR curve.
+/// * `precision`: The precision at the threshold.
+/// * `recall`: The recall at the threshold.
+/// * `f1`: The F1 score at the threshold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Calibration {
+    pub tracks: Vec<TrackScaling>,
+    pub threshold: f64,
+    pub truth_threshold: f64,
+    pub samples: u64,
+    pub auc_roc: f64,
+    pub auc_pr: f64,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+}
+
+impl Calibration {
+    /// The predictor score of one position from the values of the tracks, in order.
+    pub fn score(&self, values: &[f64]) -> f64 {
+        self.tracks
+            .iter()
+            .zip(values)
+            .map(|(track, &value)| track.weight * (value - track.mean) / track.sd)
+            .sum()
+    }
+
+    /// Whether a position with these track values is called occupied.
+    pub fn predicts(&self, values: &[f64]) -> bool {
+        self.score(values) >= self.threshold
+    }
+
+    /// Converts the calibration to JSON.
+    pub fn to_json(&self) -> Json {
+        let tracks = self
+            .tracks
+            .iter()
+            .map(|track| {
+                Json::object()
+                    .with("path", track.path.to_string_lossy().as_ref())
+                    .with("mean", track.mean)
+                    .with("sd", track.sd)
+                    .with("weight", track.weight)
+            })
+            .collect::<Vec<_>>();
+        Json::object()
+            .with("symcurve_version", env!("CARGO_PKG_VERSION"))
+            .with("tracks", tracks)
+            .with("threshold", self.threshold)
+            .with("truth_threshold", self.truth_threshold)
+            .with("samples", self.samples)
+            .with("auc_roc", self.auc_roc)
+            .with("auc_pr", self.auc_pr)
+            .with("precision", self.precision)
+            .with("recall", self.recall)
+            .with("f1", self.f1)
+    }
+
+    /// Reads a calibration back from its JSON. Returns `None` if the JSON doesn't have the
+    /// expected shape.
+    pub fn from_json(json: &Json) -> Option<Self> {
+        let tracks = json
+            .get("tracks")?
+            .as_array()?
+            .iter()
+            .map(|track| {
+                Some(TrackScaling {
+                    path: PathBuf::from(track.get("path")?.as_str()?),
+                    mean: track.get("mean")?.as_f64()?,
+                    sd: track.get("sd")?.as_f64()?,
+                    weight: track.get("weight")?.as_f64()?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let number = |key: &str| json.get(key)?.as_f64();
+        Some(Self {
+            tracks,
+            threshold: number("threshold")?,
+            truth_threshold: number("truth_threshold")?,
+            samples: number("samples")? as u64,
+            auc_roc: number("auc_roc")?,
+            auc_pr: number("auc_pr")?,
+            precision: number("precision")?,
+            recall: number("recall")?,
+            f1: number("f1")?,
+        })
+    }
+
+    /// Reads a calibration file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if the file is not a calibration written by symcurve.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a symcurve calibration file", path.display()),
+            )
+        };
+        let json: Json = fs::read_to_string(path)?.parse().map_err(|_| invalid())?;
+        Self::from_json(&json).ok_or_else(invalid)
+    }
+}
+
+/// Finds the best predictor for sampled values.
+///
+/// # Arguments
+///
+/// * `samples` - The sampled track and truth values.
+/// * `paths` - The tracks, in the order of `samples.tracks`.
+/// * `truth_threshold` - The truth value above which a position is occupied; the median if `None`.
+/// * `weight_step` - The step of the weight grid.
+///
+/// # Returns
+///
+/// The calibration and the curves of its weights, or `None` if the samples are all occupied or
+/// all unoccupied, when there is nothing to calibrate.
+pub fn fit(
+    samples: &Samples,
+    paths: &[PathBuf],
+    truth_threshold: Option<f64>,
+    weight_step: f64,
+) -> Option<(Calibration, Curves)> {
+    let truth_threshold = truth_threshold.unwrap_or_else(|| {
+        let mut sorted = samples.truth.clone();
+        sorted.sort_by(f64::total_cmp);
+        sorted.get(sorted.len() / 2).copied().unwrap_or(0.0)
+    });
+    let labels: Vec<bool> = samples.truth.iter().map(|&v| v > truth_threshold).collect();
+    if labels.iter().all(|&l| l) || labels.iter().all(|&l| !l) {
+        return None;
+    }
+    let scaled: Vec<(f64, f64, Vec<f64>)> = samples
+        .tracks
+        .iter()
+        .map(|values| {
+            let mut stats = RunningStats::new();
+            values.iter().for_each(|&v| stats.add(v));
+            let sd = if stats.sd() > 0.0 { stats.sd() } else { 1.0 };
+            let z = values.iter().map(|&v| (v - stats.mean()) / sd).collect();
+            (stats.mean(), sd, z)
+        })
+        .collect();
+    let mut best: Option<(Vec<f64>, Curves)> = None;
+    let mut scores = vec![0.0; samples.len()];
+    for weights in weight_grid(samples.tracks.len(), weight_step) {
+        scores.fill(0.0);
+        for (weight, (_, _, z)) in weights.iter().zip(&scaled) {
+            for (score, &value) in scores.iter_mut().zip(z) {
+                *score += weight * value;
+            }
+        }
+        let curves = Curves::new(&scores, &labels);
+        if best
+            .as_ref()
+            .is_none_or(|(_, current)| curves.auc_roc() > current.auc_roc())
+        {
+            best = Some((weights, curves));
+        }
+    }
+    let (weights, curves) = best?;
+    let point = *curves.best_point()?;
+    let calibration = Calibration {
+        tracks: paths
+            .iter()
+            .zip(&scaled)
+            .zip(weights)
+            .map(|((path, (mean, sd, _)), weight)| TrackScaling {
+                path: path.clone(),
+                mean: *mean,
+                sd: *sd,
+                weight,
+            })
+            .collect(),
+        threshold: point.threshold,
+        truth_threshold,
+        samples: samples.len() as u64,
+        auc_roc: curves.auc_roc(),
+        auc_pr: curves.auc_pr(),
+        precision: Curves::precision(&point),
+        recall: curves.recall(&point),
+        f1: curves.f1(&point),
+    };
+    Some((calibration, curves))
+}
+
+/// Runs `symcurve calibrate`: samples the tracks against the truth, fits the predictor, writes
+/// the calibration file and any curves, and reports the best operating point.
+///
+/// # Errors
+///
+/// Fails if a track cannot be read, or with an `InvalidData` error if no position has values in
+/// every track and the truth, or the truth does not separate occupied from unoccupied positions.
+pub fn calibrate<W: Write>(params: &CalibrateParams, writer: &mut W) -> io::Result<()> {
+    let region = params.region.as_ref();
+    let tracks = params
+        .tracks
+        .iter()
+        .map(|path| read_track(path, region))
+        .collect::<io::Result<Vec<_>>>()?;
+    let truth = read_track(&params.truth, region)?;
+    let samples = sample(tracks, truth, params.stride);
+    if samples.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no position has a value in every track and the truth",
+        ));
+    }
+    let (calibration, curves) = fit(
+        &samples,
+        &params.tracks,
+        params.truth_threshold,
+        params.weight_step,
+    )
+    .ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the truth threshold leaves every sampled position on one side",
+        )
+    })?;
+    fs::write(&params.output, calibration.to_json().pretty())?;
+    if let Some(path) = &params.curves {
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        curves.write_tsv(&mut file)?;
+        file.flush()?;
+    }
+    writeln!(writer, "samples\t{}", calibration.samples)?;
+    writeln!(writer, "truth_threshold\t{}", calibration.truth_threshold)?;
+    for track in &calibration.tracks {
+        writeln!(writer, "weight\t{}\t{}", track.path.display(), track.weight)?;
+    }
+    writeln!(writer, "threshold\t{}", calibration.threshold)?;
+    writeln!(writer, "auc_roc\t{}", calibration.auc_roc)?;
+    writeln!(writer, "auc_pr\t{}", calibration.auc_pr)?;
+    writeln!(writer, "precision\t{}", calibration.precision)?;
+    writeln!(writer, "recall\t{}", calibration.recall)?;
+    writeln!(writer, "f1\t{}", calibration.f1)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    fn interval(start: u64, end: u64, value: f32) -> Interval {
+        Interval { start, end, value }
+    }
+
+    #[test]
+    fn test_weight_grid() {
+        assert_eq!(weight_grid(1, 0.5), vec![vec![1.0], vec![-1.0]]);
+        let grid = weight_grid(2, 0.5);
+        assert_eq!(grid.len(), 8);
+        assert!(grid.contains(&vec![0.5, -0.5]));
+        assert!(grid.contains(&vec![0.0, 1.0]));
+        for weights in weight_grid(3, 0.25) {
+            assert_relative_eq!(weights.iter().map(|w: &f64| w.abs()).sum::<f64>(), 1.0);
+        }
+    }
+
+    #[test]
+    fn test_curves() {
+        let scores = [0.9, 0.8, 0.7, 0.6, 0.5, 0.4];
+        let labels = [true, true, false, true, false, false];
+        let curves = Curves::new(&scores, &labels);
+        assert_eq!(curves.points.len(), 6);
+        assert_eq!((curves.positives, curves.negatives), (3, 3));
+        // 8 of the 9 positive-negative pairs are ordered correctly
+        assert_relative_eq!(curves.auc_roc(), 8.0 / 9.0);
+        assert_relative_eq!(curves.auc_pr(), (1.0 + 1.0 + 0.75) / 3.0);
+        let best = curves.best_point().unwrap();
+        assert_eq!(best.threshold, 0.6);
+        assert_relative_eq!(curves.f1(best), 6.0 / 7.0);
+        // ties share one point
+        let curves = Curves::new(&[1.0, 1.0, 0.0], &[true, false, false]);
+        assert_eq!(curves.points.len(), 2);
+        assert_relative_eq!(curves.auc_roc(), 0.75);
+        let mut out = Vec::new();
+        curves.write_tsv(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "threshold\tfpr\ttpr\tprecision\tf1\n1\t0.5\t1\t0.5\t0.6666666666666666\n0\t1\t1\t0.3333333333333333\t0.5\n"
+        );
+    }
+
+    #[test]
+    fn test_sample() {
+        let track = vec![
+            ("chr1".to_string(), interval(0, 50, 1.0)),
+            ("chr1".to_string(), interval(50, 100, f32::NAN)),
+            ("chr2".to_string(), interval(0, 100, 3.0)),
+        ];
+        let truth = vec![
+            ("chr1".to_string(), interval(0, 100, 5.0)),
+            ("chr3".to_string(), interval(0, 100, 5.0)),
+        ];
+        let samples = sample(vec![track], truth, 10);
+        // chr1 0, 10, .., 40 only: chr1 is NaN from 50 and chr3 has no track values
+        assert_eq!(samples.tracks, vec![vec![1.0; 5]]);
+        assert_eq!(samples.truth, vec![5.0; 5]);
+    }
+
+    #[test]
+    fn test_fit() {
+        // the truth follows the second track inversely and ignores the first
+        let n = 200;
+        let noise: Vec<f64> = (0..n).map(|i| ((i * 37) % 11) as f64).collect();
+        let signal: Vec<f64> = (0..n).map(|i| (i as f64 * 0.3).sin()).collect();
+        let samples = Samples {
+            tracks: vec![noise, signal.clone()],
+            truth: signal.iter().map(|s| -s).collect(),
+        };
+        let paths = [PathBuf::from("a.bw"), PathBuf::from("b.bw")];
+        let (calibration, _) = fit(&samples, &paths, None, 0.25).unwrap();
+        assert_eq!(calibration.tracks[0].weight, 0.0);
+        assert_eq!(calibration.tracks[1].weight, -1.0);
+        assert_relative_eq!(calibration.auc_roc, 1.0);
+        assert_relative_eq!(calibration.f1, 1.0);
+        assert_eq!(calibration.samples, 200);
+        let read_back =
+            Calibration::from_json(&calibration.to_json().to_string().parse().unwrap()).unwrap();
+        assert_eq!(read_back, calibration);
+        // a position with a low second track is called occupied
+        assert!(read_back.predicts(&[5.0, -1.0]));
+        assert!(!read_back.predicts(&[5.0, 1.0]));
+        // nothing to calibrate when the truth is constant
+        let flat = Samples {
+            tracks: vec![vec![1.0, 2.0]],
+            truth: vec![1.0, 1.0],
+        };
+        assert!(fit(&flat, &paths[..1], None, 0.5).is_none());
+    }
+
+    #[test]
+    fn test_calibrate() {
+        let dir = std::env::temp_dir().join("symcurve_test_calibrate");
+        fs::create_dir_all(&dir).unwrap();
+        let mut track = String::new();
+        let mut truth = String::new();
+        for i in 0..100 {
+            let value = (i as f64 * 0.5).sin();
+            track.push_str(&format!("chr1\t{}\t{}\t{}\n", i * 10, i * 10 + 10, value));
+            truth.push_str(&format!(
+                "chr1\t{}\t{}\t{}\n",
+                i * 10,
+                i * 10 + 10,
+                value * 4.0
+            ));
+        }
+        let params = CalibrateParams {
+            tracks: vec![dir.join("curvature.bedGraph")],
+            truth: dir.join("mnase.bedGraph"),
+            truth_threshold: Some(0.0),
+            region: None,
+            stride: 10,
+            weight_step: 0.5,
+            curves: Some(dir.join("curves.tsv")),
+            output: dir.join("calibration.json"),
+        };
+        fs::write(&params.tracks[0], track).unwrap();
+        fs::write(&params.truth, truth).unwrap();
+        let mut out = Vec::new();
+        calibrate(&params, &mut out).unwrap();
+        let report = String::from_utf8(out).unwrap();
+        assert!(report.starts_with("samples\t100\ntruth_threshold\t0\n"));
+        assert!(report.contains("auc_roc\t1\n"));
+        let calibration = Calibration::read(&params.output).unwrap();
+        assert_eq!(calibration.tracks[0].weight, 1.0);
+        let curves = fs::read_to_string(dir.join("curves.tsv")).unwrap();
+        assert!(curves.starts_with("threshold\tfpr\ttpr\tprecision\tf1\n"));
+        assert!(Calibration::read(&params.tracks[0]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,317 @@
+//! # Checksum module
+//! Streaming MD5 and SHA-256 digests of output files, recorded in the run manifest so workflow
+//! systems can verify transfer integrity and cache hits.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Incremental MD5 (RFC 1321) hasher.
+///
+/// # Fields
+///
+/// * `state`: The four 32-bit chaining values.
+/// * `buffer`: Bytes not yet making up a full 64-byte block.
+/// * `length`: Total number of bytes fed in so far.
+#[derive(Clone, Debug)]
+pub struct Md5 {
+    state: [u32; 4],
+    buffer: Vec<u8>,
+    length: u64,
+}
+
+const MD5_SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9,
+    14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15,
+    21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+impl Default for Md5 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Md5 {
+    /// Constructor for `Md5`.
+    pub fn new() -> Self {
+        Self {
+            state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+            buffer: Vec::with_capacity(64),
+            length: 0,
+        }
+    }
+
+    /// Feeds more bytes into the digest.
+    pub fn update(&mut self, data: &[u8]) {
+        self.length += data.len() as u64;
+        self.buffer.extend_from_slice(data);
+        let full = self.buffer.len() / 64 * 64;
+        for block in self.buffer[..full].chunks_exact(64) {
+            md5_compress(&mut self.state, block);
+        }
+        self.buffer.drain(..full);
+    }
+
+    /// Finishes the digest and returns it as lowercase hex.
+    pub fn hex_digest(mut self) -> String {
+        let bit_len = self.length.wrapping_mul(8);
+        let mut tail = vec![0x80u8];
+        while (self.buffer.len() + tail.len()) % 64 != 56 {
+            tail.push(0);
+        }
+        tail.extend_from_slice(&bit_len.to_le_bytes());
+        self.update(&tail);
+        self.state
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+/// Helper to `Md5::update()` that mixes one 64-byte block into the state.
+fn md5_compress(state: &mut [u32; 4], block: &[u8]) {
+    let m: Vec<u32> = block
+        .chunks_exact(4)
+        .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .collect();
+    let [mut a, mut b, mut c, mut d] = *state;
+    for (i, &shift) in MD5_SHIFTS.iter().enumerate() {
+        let (f, g) = match i / 16 {
+            0 => ((b & c) | (!b & d), i),
+            1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+            2 => (b ^ c ^ d, (3 * i + 5) % 16),
+            _ => (c ^ (b | !d), (7 * i) % 16),
+        };
+        let k = ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32;
+        let rotated = a
+            .wrapping_add(f)
+            .wrapping_add(k)
+            .wrapping_add(m[g])
+            .rotate_left(shift);
+        a = d;
+        d = c;
+        c = b;
+        b = b.wrapping_add(rotated);
+    }
+    state[0] = state[0].wrapping_add(a);
+    state[1] = state[1].wrapping_add(b);
+    state[2] = state[2].wrapping_add(c);
+    state[3] = state[3].wrapping_add(d);
+}
+
+/// Incremental SHA-256 (FIPS 180-4) hasher.
+///
+/// # Fields
+///
+/// * `state`: The eight 32-bit chaining values.
+/// * `buffer`: Bytes not yet making up a full 64-byte block.
+/// * `length`: Total number of bytes fed in so far.
+#[derive(Clone, Debug)]
+pub struct Sha256 {
+    state: [u32; 8],
+    buffer: Vec<u8>,
+    length: u64,
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sha256 {
+    /// Constructor for `Sha256`.
+    pub fn new() -> Self {
+        Self {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            buffer: Vec::with_capacity(64),
+            length: 0,
+        }
+    }
+
+    /// Feeds more bytes into the digest.
+    pub fn update(&mut self, data: &[u8]) {
+        self.length += data.len() as u64;
+        self.buffer.extend_from_slice(data);
+        let full = self.buffer.len() / 64 * 64;
+        for block in self.buffer[..full].chunks_exact(64) {
+            sha256_compress(&mut self.state, block);
+        }
+        self.buffer.drain(..full);
+    }
+
+    /// Finishes the digest and returns it as lowercase hex.
+    pub fn hex_digest(mut self) -> String {
+        let bit_len = self.length.wrapping_mul(8);
+        let mut tail = vec![0x80u8];
+        while (self.buffer.len() + tail.len()) % 64 != 56 {
+            tail.push(0);
+        }
+        tail.extend_from_slice(&bit_len.to_be_bytes());
+        self.update(&tail);
+        self.state
+            .iter()
+            .flat_map(|word| word.to_be_bytes())
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+/// Helper to `Sha256::update()` that mixes one 64-byte block into the state.
+fn sha256_compress(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    for (i, c) in block.chunks_exact(4).enumerate() {
+        w[i] = u32::from_be_bytes([c[0], c[1], c[2], c[3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for (&k, &word) in SHA256_K.iter().zip(w.iter()) {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(k)
+            .wrapping_add(word);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+    for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *word = word.wrapping_add(value);
+    }
+}
+
+/// The MD5 and SHA-256 digests of a file, as lowercase hex.
+///
+/// # Fields
+///
+/// * `md5`: The MD5 digest.
+/// * `sha256`: The SHA-256 digest.
+/// * `bytes`: The size of the file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileDigests {
+    pub md5: String,
+    pub sha256: String,
+    pub bytes: u64,
+}
+
+/// Computes both digests of a file in a single streaming pass.
+pub fn digest_file(path: &Path) -> io::Result<FileDigests> {
+    let mut file = File::open(path)?;
+    let mut md5 = Md5::new();
+    let mut sha256 = Sha256::new();
+    let mut bytes = 0;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        md5.update(&buf[..n]);
+        sha256.update(&buf[..n]);
+        bytes += n as u64;
+    }
+    Ok(FileDigests {
+        md5: md5.hex_digest(),
+        sha256: sha256.hex_digest(),
+        bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn md5_hex(data: &[u8]) -> String {
+        let mut md5 = Md5::new();
+        md5.update(data);
+        md5.hex_digest()
+    }
+
+    fn sha256_hex(data: &[u8]) -> String {
+        let mut sha256 = Sha256::new();
+        sha256.update(data);
+        sha256.hex_digest()
+    }
+
+    #[test]
+    fn test_md5_known_digests() {
+        assert_eq!(md5_hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(md5_hex(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            md5_hex(b"The quick brown fox jumps over the lazy dog"),
+            "9e107d9d372bb6826bd81d3542a419d6"
+        );
+    }
+
+    #[test]
+    fn test_sha256_known_digests() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            sha256_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn test_incremental_matches_one_shot() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let mut md5 = Md5::new();
+        let mut sha256 = Sha256::new();
+        for chunk in data.chunks(37) {
+            md5.update(chunk);
+            sha256.update(chunk);
+        }
+        assert_eq!(md5.hex_digest(), md5_hex(&data));
+        assert_eq!(sha256.hex_digest(), sha256_hex(&data));
+    }
+
+    #[test]
+    fn test_digest_file() {
+        let path = std::env::temp_dir().join("symcurve_test_digest_file.txt");
+        std::fs::write(&path, b"abc").unwrap();
+        let digests = digest_file(&path).unwrap();
+        assert_eq!(digests.md5, "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(digests.bytes, 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,432 @@
+//! # Chunk module
+//! Intra-chromosome chunking, so that one long sequence can be spread over all cores.
+//!
+//! A sequence piece is read in chunks of bases, each sent with the two bases before it, which its
+//! first triplets overlap, to a worker thread. Workers look up the twist, roll and tilt of every
+//! triplet of their chunk and turn them into bend vectors, which is where the time goes. The
+//! reading thread keeps the running twist sum, a cheap addition per base, and hands each chunk the
+//! sum at its start, so the bend vectors are exactly those of one sequential pass.
+//!
+//! The vectors are stitched back in order on the reading thread, where the coordinates, rolling
+//! means and distances are summed along the whole piece as a sequential pass sums them. The values
+//! therefore match those of a `CurveIter` over the piece bit for bit, in either edge mode, and the
+//! piece is never held in memory whole: at most two chunks per thread are in flight.
+//!
+//! The chunk size trades scheduling overhead against cache use. By default it is chosen from the
+//! size of the per-core L2 cache and the available parallelism; `--chunk-size` overrides it.
+
+use crate::curve::iters::{curve_from_blocks, sum_twists, triplet_block, TripletBlock};
+use crate::curve::matrix::RollType;
+use crate::pipeline::PipelineParams;
+use crate::resources::Resources;
+use crate::stats::format_bytes;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::mem;
+use std::ops::Range;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread;
+
+/// The smallest chunk size chosen automatically, in bases.
+pub const MIN_CHUNK_SIZE: usize = 16 * 1024;
+
+/// The largest chunk size chosen automatically, in bases.
+pub const MAX_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// The cache size assumed when it cannot be read from the system, in bytes.
+pub const DEFAULT_CACHE_BYTES: usize = 1024 * 1024;
+
+/// Bytes of working set per base of a chunk: the base itself and its `f64` value.
+const BYTES_PER_BASE: usize = 1 + std::mem::size_of::<f64>();
+
+/// How a chunk size was arrived at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkSource {
+    /// Given with `--chunk-size`.
+    User,
+    /// Chosen from the cache size, in bytes.
+    Auto { cache_bytes: usize },
+}
+
+/// How sequences are split into chunks and how many threads compute them.
+///
+/// # Fields
+///
+/// * `size`: The number of values per chunk.
+/// * `threads`: The number of worker threads.
+/// * `source`: How `size` was chosen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkPlan {
+    pub size: usize,
+    pub threads: usize,
+    pub source: ChunkSource,
+}
+
+impl ChunkPlan {
+    /// Plans chunking for this machine, or the container the process runs in, with the chunk size
+    /// given on the command line if any.
+    pub fn detect(chunk_size: Option<usize>) -> Self {
+        let threads = Resources::detect().threads;
+        match chunk_size {
+            Some(size) => Self {
+                size,
+                threads,
+                source: ChunkSource::User,
+            },
+            None => Self::auto(threads, l2_cache_bytes()),
+        }
+    }
+
+    /// Chooses a chunk size so that a chunk's bases and values fill about half of the cache,
+    /// leaving room for the iterator buffers and the output writer.
+    ///
+    /// # Arguments
+    ///
+    /// * `threads` - The number of worker threads.
+    /// * `cache_bytes` - The per-core cache size, if known.
+    pub fn auto(threads: usize, cache_bytes: Option<usize>) -> Self {
+        let cache_bytes = cache_bytes.unwrap_or(DEFAULT_CACHE_BYTES);
+        let size = (cache_bytes / 2 / BYTES_PER_BASE).clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE);
+        Self {
+            // round down to a whole number of KiB so the reported value reads cleanly
+            size: size / 1024 * 1024,
+            threads: threads.max(1),
+            source: ChunkSource::Auto { cache_bytes },
+        }
+    }
+}
+
+impl fmt::Display for ChunkPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "chunk size: {} bases, {} threads",
+            self.size, self.threads
+        )?;
+        match self.source {
+            ChunkSource::User => write!(f, " (--chunk-size)"),
+            ChunkSource::Auto { cache_bytes } => {
+                write!(f, " (auto, {} cache per core)", format_bytes(cache_bytes))
+            }
+        }
+    }
+}
+
+/// The size of the per-core L2 cache in bytes, read from sysfs.
+///
+/// Returns `None` on platforms without sysfs or when no L2 cache is listed.
+pub fn l2_cache_bytes() -> Option<usize> {
+    let dir = fs::read_dir("/sys/devices/system/cpu/cpu0/cache").ok()?;
+    dir.flatten().find_map(|entry| {
+        let read = |name: &str| fs::read_to_string(entry.path().join(name)).ok();
+        if read("level")?.trim() != "2" || read("type")?.trim() == "Instruction" {
+            return None;
+        }
+        parse_cache_size(&read("size")?)
+    })
+}
+
+/// Helper to `l2_cache_bytes()` that parses sysfs cache sizes such as `2048K`.
+fn parse_cache_size(size: &str) -> Option<usize> {
+    let size = size.trim();
+    let (digits, multiplier) = match size.chars().last()? {
+        'K' => (&size[..size.len() - 1], 1024),
+        'M' => (&size[..size.len() - 1], 1024 * 1024),
+        _ => (size, 1),
+    };
+    Some(digits.parse::<usize>().ok()? * multiplier)
+}
+
+/// Splits `values` values into consecutive chunks of `size` values, the last one possibly shorter.
+pub fn chunk_ranges(values: usize, size: usize) -> Vec<Range<usize>> {
+    (0..values)
+        .step_by(size.max(1))
+        .map(|start| start..(start + size).min(values))
+        .collect()
+}
+
+/// Computes the curvature of a gap-free sequence piece in chunks on several threads.
+///
+/// The values are exactly those of a sequential `CurveIter` over the piece.
+///
+/// # Arguments
+///
+/// * `seq` - The bases of the piece, uppercase `A`, `C`, `G` and `T` only.
+/// * `params` - The parameters of the run.
+/// * `plan` - The chunk size and number of threads.
+pub fn chunked_curvature(seq: &[u8], params: &PipelineParams, plan: &ChunkPlan) -> Vec<f64> {
+    with_chunked_curve(seq.iter().cloned(), params, plan, |curve| curve.collect())
+}
+
+/// Streams the curvature of a gap-free sequence piece computed in chunks on several threads.
+///
+/// The bases are read as the values are taken, so the piece need not be in memory. The values
+/// are exactly those of a sequential `CurveIter` over the piece.
+///
+/// # Arguments
+///
+/// * `bases` - The bases of the piece, uppercase `A`, `C`, `G` and `T` only.
+/// * `params` - The parameters of the run.
+/// * `plan` - The chunk size and number of threads.
+/// * `each` - Takes the values, in order. The worker threads stop once it returns.
+pub fn with_chunked_curve<I, F, T>(
+    bases: I,
+    params: &PipelineParams,
+    plan: &ChunkPlan,
+    each: F,
+) -> T
+where
+    I: Iterator<Item = u8>,
+    F: FnOnce(&mut dyn Iterator<Item = f64>) -> T,
+{
+    let threads = plan.threads.max(1);
+    let (jobs, job_queue) = mpsc::channel::<(usize, Vec<u8>, f64)>();
+    let job_queue = Mutex::new(job_queue);
+    let (results, blocks) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let results = results.clone();
+            let job_queue = &job_queue;
+            scope.spawn(move || {
+                // the queue closes when the stitching side is done or gives up
+                while let Ok((index, bases, twist_sum)) = job_queue.lock().unwrap().recv() {
+                    let block = triplet_block(&bases, params.roll_type.clone(), twist_sum);
+                    if results.send((index, block)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(results);
+        let mut stitched = StitchedBlocks {
+            bases,
+            roll_type: &params.roll_type,
+            size: plan.size.max(1),
+            ahead: 2 * threads,
+            twist_sum: 0.0,
+            carry: Vec::new(),
+            read_all: false,
+            sent: 0,
+            taken: 0,
+            jobs: Some(jobs),
+            blocks,
+            arrived: BTreeMap::new(),
+        };
+        let mut curve = curve_from_blocks(
+            &mut stitched,
+            params.step_b,
+            params.step_two,
+            params.step_c,
+            params.curve_scale,
+            params.edge_mode,
+            params.convention,
+        );
+        each(&mut curve)
+    })
+}
+
+/// Helper to `with_chunked_curve()` that reads the bases in chunks, sends them to the workers
+/// and yields the triplet blocks that come back, in the order of the chunks.
+///
+/// # Fields
+///
+/// * `bases`: The bases of the piece still to be read.
+/// * `roll_type`: The type of roll, for the running twist sum.
+/// * `size`: The number of bases per chunk.
+/// * `ahead`: The most chunks sent but not yet taken.
+/// * `twist_sum`: The twist sum of the triplets of the chunks sent so far.
+/// * `carry`: The last two bases read, which the first triplets of the next chunk start with.
+/// * `read_all`: Whether `bases` has run out.
+/// * `sent`: The number of chunks sent to the workers.
+/// * `taken`: The number of blocks yielded.
+/// * `jobs`: The queue of chunks for the workers, with the index and starting twist sum of each.
+/// * `blocks`: The blocks the workers have computed, in the order they finish.
+/// * `arrived`: Blocks that finished ahead of the next one to yield, by index.
+struct StitchedBlocks<'a, I> {
+    bases: I,
+    roll_type: &'a RollType,
+    size: usize,
+    ahead: usize,
+    twist_sum: f64,
+    carry: Vec<u8>,
+    read_all: bool,
+    sent: usize,
+    taken: usize,
+    jobs: Option<Sender<(usize, Vec<u8>, f64)>>,
+    blocks: Receiver<(usize, TripletBlock)>,
+    arrived: BTreeMap<usize, TripletBlock>,
+}
+
+impl<I: Iterator<Item = u8>> StitchedBlocks<'_, I> {
+    /// Reads the next chunk and sends it to the workers, or notes that the bases have run out.
+    fn send_chunk(&mut self) {
+        let mut chunk = mem::take(&mut self.carry);
+        let overlap = chunk.len();
+        chunk.extend(self.bases.by_ref().take(self.size));
+        let read = chunk.len() - overlap;
+        self.read_all = read < self.size;
+        if read == 0 {
+            return;
+        }
+        let twist_sum = self.twist_sum;
+        self.twist_sum = sum_twists(&chunk, self.roll_type, twist_sum);
+        self.carry = chunk[chunk.len().saturating_sub(2)..].to_vec();
+        if let Some(jobs) = &self.jobs {
+            // a closed queue means a worker panicked, which the scope reports
+            let _ = jobs.send((self.sent, chunk, twist_sum));
+        }
+        self.sent += 1;
+    }
+}
+
+impl<I: Iterator<Item = u8>> Iterator for StitchedBlocks<'_, I> {
+    type Item = TripletBlock;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.read_all && self.sent < self.taken + self.ahead {
+            self.send_chunk();
+        }
+        if self.read_all {
+            // let idle workers finish as soon as the last chunks are done
+            self.jobs = None;
+        }
+        if self.taken == self.sent {
+            return None;
+        }
+        loop {
+            if let Some(block) = self.arrived.remove(&self.taken) {
+                self.taken += 1;
+                return Some(block);
+            }
+            let (index, block) = self.blocks.recv().ok()?;
+            self.arrived.insert(index, block);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::iters::{CurveIter, EdgeMode, MeanConvention};
+    use crate::property;
+
+    #[test]
+    fn test_auto_chunk_size() {
+        let plan = ChunkPlan::auto(8, Some(2048 * 1024));
+        assert_eq!(plan.size, 113 * 1024);
+        assert_eq!(plan.threads, 8);
+        assert_eq!(
+            plan.to_string(),
+            "chunk size: 115712 bases, 8 threads (auto, 2.0 MiB cache per core)"
+        );
+        // tiny and huge caches are clamped, unknown ones get the default
+        assert_eq!(ChunkPlan::auto(1, Some(1024)).size, MIN_CHUNK_SIZE);
+        assert_eq!(ChunkPlan::auto(1, Some(1 << 30)).size, MAX_CHUNK_SIZE);
+        assert_eq!(
+            ChunkPlan::auto(0, None),
+            ChunkPlan::auto(1, Some(DEFAULT_CACHE_BYTES))
+        );
+        let plan = ChunkPlan::detect(Some(5000));
+        assert_eq!((plan.size, plan.source), (5000, ChunkSource::User));
+    }
+
+    #[test]
+    fn test_parse_cache_size() {
+        assert_eq!(parse_cache_size("2048K\n"), Some(2 * 1024 * 1024));
+        assert_eq!(parse_cache_size("32M"), Some(32 * 1024 * 1024));
+        assert_eq!(parse_cache_size("512"), Some(512));
+        assert_eq!(parse_cache_size("big"), None);
+    }
+
+    #[test]
+    fn test_chunk_ranges() {
+        assert_eq!(chunk_ranges(10, 4), vec![0..4, 4..8, 8..10]);
+        assert_eq!(chunk_ranges(8, 4), vec![0..4, 4..8]);
+        assert!(chunk_ranges(0, 4).is_empty());
+    }
+
+    #[test]
+    fn test_chunked_matches_sequential() {
+        let seq: Vec<u8> = (0..5000u32)
+            .map(|i| b"ACGT"[((i * 7 + i / 3) % 4) as usize])
+            .collect();
+        let params = PipelineParams::default();
+        let sequential = chunked_curvature(
+            &seq,
+            &params,
+            &ChunkPlan {
+                size: seq.len(),
+                threads: 1,
+                source: ChunkSource::User,
+            },
+        );
+        assert_eq!(sequential.len(), seq.len() - 42);
+        for size in [1, 2, 97, 1000, 10_000] {
+            let plan = ChunkPlan {
+                size,
+                threads: 4,
+                source: ChunkSource::User,
+            };
+            // stitched chunks match bit for bit
+            assert_eq!(chunked_curvature(&seq, &params, &plan), sequential);
+        }
+    }
+
+    #[test]
+    fn test_chunked_stops_early() {
+        let seq = b"ACGTTTAAAGGCCA".repeat(500);
+        let plan = ChunkPlan {
+            size: 50,
+            threads: 3,
+            source: ChunkSource::User,
+        };
+        let params = PipelineParams::default();
+        let first = with_chunked_curve(seq.iter().cloned(), &params, &plan, |curve| {
+            curve.take(10).collect::<Vec<_>>()
+        });
+        assert_eq!(first, chunked_curvature(&seq, &params, &plan)[..10]);
+    }
+
+    #[test]
+    fn property_stitched_chunks_match_unchunked() {
+        property::check("stitched chunks", |rng| {
+            let length = property::between(rng, 0, 600);
+            let seq = property::bases(rng, length);
+            let params = PipelineParams {
+                step_b: property::between(rng, 1, 8),
+                step_two: [None, Some(property::between(rng, 1, 10))][rng.below(2)],
+                step_c: property::between(rng, 1, 20),
+                edge_mode: [EdgeMode::Drop, EdgeMode::Shrink][rng.below(2)],
+                convention: [MeanConvention::Legacy, MeanConvention::Standard][rng.below(2)],
+                ..PipelineParams::default()
+            };
+            let plan = ChunkPlan {
+                size: property::between(rng, 1, 200),
+                threads: property::between(rng, 2, 4),
+                source: ChunkSource::User,
+            };
+            let unchunked: Vec<f64> = CurveIter::new(
+                seq.iter().cloned(),
+                params.roll_type.clone(),
+                params.step_b,
+                params.step_c,
+                params.curve_scale,
+                params.edge_mode,
+                params.convention,
+            )
+            .with_step_two(params.step_two)
+            .collect();
+            let chunked = chunked_curvature(&seq, &params, &plan);
+            if chunked == unchunked {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{} bases, step_b {}, step_c {}, chunk size {}",
+                    length, params.step_b, params.step_c, plan.size
+                ))
+            }
+        });
+    }
+}
@@ -2,94 +2,1180 @@
 //!
 //! The main arguments to the symcurve CLI are the input and output file paths.
 //! These should be provided as positional arguments. The other arguments are optional
-//! but have constraints and default values.
+//! but have constraints and default values. The flags that set how curvature is computed (the
+//! matrices, roll type, preset, curve steps and scale, anchor, edge mode, mean convention and
+//! float format) may also follow a subcommand that computes curvature, e.g.
+//! `symcurve sweep in.fa --param curve-step=5..30:5 --curve-step-one 8`.
 //!
 //! ```text
 //! Symmetry of DNA curvature.
 //!
-//! Usage: symcurve [OPTIONS] <INPUT> <OUTPUT>
+//! Usage: symcurve [OPTIONS] [INPUT] [OUTPUT]
+//!        symcurve <COMMAND>
+//!
+//! Commands:
+//!   view            print values for a region of an existing bigWig or bedGraph output
+//!   info            print how an existing output or run manifest was generated
+//!   sweep           compare curvature over a grid of parameter values on one sequence
+//!   compare         write the curvature difference between two genomes over chain-aligned regions
+//!   explain         attribute the curvature of a region to its bases, as TSV
+//!   scan-mutations  report the largest curvature change of every point mutation in a region
+//!   design          search for oligos of a given length and GC content with extreme curvature
+//!   motifs          write a position frequency matrix of the sequence around curvature peak summits
+//!   metagenome      summarize curvature per bin of an assembly of many short contigs
+//!   reads           write one row of curvature statistics per read of a FASTQ or unaligned BAM file
+//!   calibrate       fit weights and a threshold that call nucleosome occupancy from tracks against an
+//!                   experimental map
+//!   train           fit a roll matrix that separates positive from negative sequences by curvature
+//!   crossval        cross-validate how well a matrix file separates labeled regions, over
+//!                   chromosome folds
+//!   serve           serve curvature of requested regions over HTTP, recomputed with the parameters in
+//!                   each request
+//!   man             print the man page, or write the pages of every command into a directory
+//!   help            Print this message or the help of the given subcommand(s)
 //!
 //! Arguments:
-//!   <INPUT>   FASTA input file path
-//!   <OUTPUT>  bigWig output file path
+//!   [INPUT]   FASTA input file path, plain or gzipped, or - for standard input
+//!   [OUTPUT]  output file path, bigWig unless --output-format or its extension say otherwise
 //!
 //! Options:
 //!   -v, --verbose                            verbose setting
 //!   -m, --matrices <MATRICES>                optional matrices YAML file
+//!       --roll-type <ROLL_TYPE>              built-in roll matrix, when no matrices file is given
+//!                                            [default: simple] [possible values: simple, active]
+//!       --matrix-set <MATRIX_SET>            also write the per-position mean and standard deviation of
+//!                                            curvature across comma-separated matrix sets: simple, active or
+//!                                            a YAML matrix file
+//!       --archive-member <ARCHIVE_MEMBER>    member to read when INPUT is a tar, gzipped tar or zip
+//!                                            archive; by default every FASTA member is read in order
+//!       --on-malformed <ON_MALFORMED>        what to do with a malformed FASTA record [default: error]
+//!                                            [possible values: error, skip]
+//!       --regions <REGION>                   only compute this region, as chrom or chrom:start-end (1-based,
+//!                                            inclusive), read through the FASTA index; repeat for more regions
+//!       --preset <PRESET>                    set the curve steps, smoothing and symcurve window together; flags
+//!                                            given explicitly override it [possible values: nucleosome,
+//!                                            promoter, fine, coarse]
 //!       --curve-step <CURVE_STEP>            curve step [default: 15]
 //!       --curve-scale <CURVE_SCALE>          curve scale [default: 0.33335]
 //!       --curve-step-one <CURVE_STEP_ONE>    curve step one [default: 6]
 //!       --curve-step-two <CURVE_STEP_TWO>    curve step two [default: 4]
 //!       --symcurve-win <SYMCURVE_WIN>        symcurve window [default: 101]
 //!       --symcurve-step <SYMCURVE_STEP>      symcurve step [default: 1]
+//!       --symcurve-track                     also write the mirror symmetry of curvature in each symcurve
+//!                                            window as a second track, to OUTPUT with .symcurve before its
+//!                                            extension
+//!       --symmetry-axis                      also write the inferred symmetry axis (dyad candidate) of each
+//!                                            symmetry window as BED
 //!       --min-linker-size <MIN_LINKER_SIZE>  minimum linker size [default: 30]
+//!       --anchor <ANCHOR>                    position each value is reported at [default: center]
+//!                                            [possible values: center, start]
+//!       --stranded                           write separate plus/minus strand outputs, to OUTPUT with .plus
+//!                                            and .minus before its extension in place of OUTPUT
+//!       --bend-vectors <BEND_VECTORS>        optional per-position bend vector TSV output path
+//!       --magnitude-phase <MAGNITUDE_PHASE>  optional per-window curvature magnitude and signed direction
+//!                                            change TSV output path
+//!       --envelope <ENVELOPE>                optional TSV output path for the rolling minimum and maximum of
+//!                                            curvature
+//!       --envelope-window <ENVELOPE_WINDOW>  number of values in each envelope window [default: 1001]
+//!       --arrow <ARROW>                      optional Arrow IPC stream output path for chrom, pos and value
+//!                                            columns; - for stdout
+//!       --sqlite <SQLITE>                    optional SQLite database output path, with values in a table
+//!                                            keyed by chrom and pos
+//!       --masked-bed <MASKED_BED>            optional BED output path for the soft-masked (lowercase)
+//!                                            intervals of the input
+//!       --issues                             also write warnings and errors as JSON lines keyed by contig and
+//!                                            coordinate to OUTPUT with .issues.jsonl for its extension
+//!       --manifest                           also write the size, md5 and sha256 of each output to a run
+//!                                            manifest at OUTPUT with .manifest.json appended
+//!       --warning-limit <WARNING_LIMIT>      most warnings of one kind shown per contig on stderr, the rest
+//!                                            summarized in one line; 0 shows them all [default: 10]
+//!       --mem-stats                          report peak memory and buffer sizes at the end of the run
+//!       --timings                            report wall-clock time per pipeline stage
+//!       --tui                                show a live monitor of contig progress, throughput, warnings and
+//!                                            recent curvature on stderr
+//!       --stats-json <STATS_JSON>            optional JSON output path for a summary of the curvature
+//!                                            distribution: moments, percentiles and a t-digest sketch
+//!       --stats-file <STATS_FILE>            optional local JSON file accumulating usage statistics across
+//!                                            runs: bases processed, runs per output format and throughput;
+//!                                            nothing is sent over the network
+//!       --notify-url <URL>                   POST the run manifest with the outcome to an http:// URL, or write it to
+//!                                            a file:// done-file, when the run finishes or fails
+//!       --trace <TRACE>                      optional span trace output path (Chrome JSON or folded stacks)
+//!       --trace-format <TRACE_FORMAT>        span trace format [default: chrome] [possible values: chrome, folded]
+//!       --force                              overwrite existing output files
+//!       --output-dir <OUTPUT_DIR>            directory for outputs; OUTPUT becomes a file name template
+//!                                            where {input} is the input file stem
+//!       --work-dir <WORK_DIR>                directory relative output paths are resolved in and temporary
+//!                                            files are kept in; output paths must then be relative
+//!       --emit-outputs <EMIT_OUTPUTS>        list the files produced on stdout when the run finishes
+//!                                            [possible values: json]
+//!       --output-format <OUTPUT_FORMAT>      format of OUTPUT, from its extension if not given: .bedGraph or
+//!                                            .bg for bedgraph, .tsv or .txt for tsv, anything else for
+//!                                            bigwig [possible values: bigwig, bedgraph, tsv]
+//!       --na-value <NA_VALUE>                text written at uncomputable positions [default: nan]
+//!                                            [possible values: nan, na, ., -1, omit]
+//!       --float-format <FLOAT_FORMAT>        how numbers are written in text outputs: shortest, fixed:N or
+//!                                            sci:N decimal places [default: shortest]
+//!       --compression <COMPRESSION>          how TSV and bedGraph tracks are compressed, adding .gz or
+//!                                            .zst to their paths [default: none] [possible values: none,
+//!                                            gzip, zstd]
+//!       --span <SPAN>                        number of bases each bigWig value covers; other formats only
+//!                                            take 1 [default: 1]
+//!       --aggregate <AGGREGATE>              how the values in each bigWig span are combined [default:
+//!                                            mean] [possible values: mean, max]
+//!       --edge-mode <EDGE_MODE>              how windows are handled at sequence ends [default: drop]
+//!                                            [possible values: drop, shrink]
+//!       --mean-convention <MEAN_CONVENTION>  rolling mean averaging convention [default: legacy]
+//!                                            [possible values: legacy, standard]
+//!       --sort-contigs <SORT_CONTIGS>        order sequences are written in [default: input]
+//!                                            [possible values: input, name, length]
+//!       --dict                               also write a Picard-style sequence dictionary next to the output
+//!       --memoize                            reuse values across exactly repeated windows in repetitive sequence
+//!       --duplicate-contigs <DUPLICATE_CONTIGS>
+//!                                            check for contigs with identical sequence [default: off]
+//!                                            [possible values: off, warn, reuse]
+//!       --binarize <BINARIZE>                also write a thresholded copy of the track, 1 above BINARIZE and 0 elsewhere
+//!       --binarize-format <BINARIZE_FORMAT>  form of the thresholded output [default: track] [possible values: track, bed]
+//!       --preview <PREVIEW>                  write only a quick preview track: every Nth window, or the
+//!                                            first length of each sequence with a kb, Mb or Gb suffix
+//!       --bed-name <TEMPLATE>                name column template for BED outputs, using {chrom}, {start},
+//!                                            {end} and {index}
+//!       --bed-score <BED_SCORE>              statistic of the interval's values written to the BED score column
+//!                                            [possible values: max, mean, neglog10p]
+//!       --derive <NAME=EXPR>                 also write a track computed per position from an expression over
+//!                                            curve, sym and pos, e.g. score='curve * sym' (repeatable)
+//!       --filter <EXPR>                      only write positions where the expression is true,
+//!                                            e.g. 'curve > 5 && sym > 0.3'
+//!       --strand-divergence                  also write the per-position divergence between forward and
+//!                                            reverse-complement curvature
+//!       --clip <MIN:MAX>                     clip values to MIN:MAX before writing, counting the positions clipped
+//!       --composition-correction <COVARIATES>
+//!                                            write curvature residuals after regressing out local composition
+//!                                            learned from the input [possible values: gc, dinucleotide]
+//!       --background <BACKGROUND>            background curvature values; also write a -log10(p) signal track
+//!                                            against them
+//!       --track-name <TRACK_NAME>            track name shown by genome browsers (bedGraph outputs)
+//!       --track-description <TRACK_DESCRIPTION>
+//!                                            track description shown by genome browsers (bedGraph outputs)
+//!       --track-color <R,G,B>                track color (bedGraph outputs)
+//!       --view-limits <MIN:MAX>              default vertical range of the track (bedGraph outputs)
+//!       --auto-scale <AUTO_SCALE>            whether browsers rescale the track to the values in view
+//!                                            (bedGraph outputs) [possible values: on, off]
+//!       --chunked                            split long sequences into chunks computed in parallel
+//!       --chunk-size <CHUNK_SIZE>            bases per chunk when chunking, chosen from the cache size and
+//!                                            core count if not given
+//!       --max-memory <SIZE>                  cap on computed values waiting to be written, e.g. 512M or 2G;
+//!                                            computation waits when it is reached; a quarter of the memory
+//!                                            limit of the container if not given
+//!       --spill-dir <SPILL_DIR>              directory for a temporary file that records held for
+//!                                            --sort-contigs or --composition-correction are spilled to rather
+//!                                            than kept in memory
+//!       --self-test                          check the pipeline against embedded benchmark sequences and exit
+//!   -V, --version                            print version; with --verbose also the git commit, build
+//!                                            date, cargo features and library versions
 //!   -h, --help                               Print help
-//!   -V, --version                            Print version
 //! ```
 
-use clap::Parser;
+use crate::chunk::ChunkPlan;
+use crate::curve::composition::Covariates;
+use crate::curve::ensemble::MatrixSet;
+use crate::curve::iters::{EdgeMode, MeanConvention};
+use crate::curve::matrix::{BuiltinRoll, Matrices, RollType};
+use crate::dedup::DuplicateMode;
+use crate::design::Objective;
+use crate::expr::{DerivedTrack, Expr};
+use crate::fasta::validate::OnMalformed;
+use crate::motif::MotifFormat;
+use crate::notify::NotifyTarget;
+use crate::numeric::{count, parse_real};
+use crate::output::bed::{NameTemplate, ScoreStat};
+use crate::output::bigwig::Aggregate;
+use crate::output::binarize::BinarizeFormat;
+use crate::output::clip::Clip;
+use crate::output::compress::Compression;
+use crate::output::layout::EmitOutputs;
+use crate::output::preview::Preview;
+use crate::output::queue::parse_memory_size;
+use crate::output::track::{parse_track_text, AutoScale, TrackColor, TrackLine, ViewLimits};
+use crate::output::{Anchor, FloatFormat, NaValue, OutputFormat, SortContigs};
+use crate::pipeline::PipelineParams;
+use crate::provenance::Provenance;
+use crate::resources::Resources;
+use crate::run::RunOptions;
+use crate::signal::CancelToken;
+use crate::sweep::ParamRange;
+use crate::trace::TraceFormat;
+use crate::view::Region;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use std::ffi::OsString;
+use std::io;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 #[derive(Parser, Debug)]
 #[command(version = env!("CARGO_PKG_VERSION"), about = "Symmetry of DNA curvature.", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+#[command(disable_version_flag = true)]
 pub struct Cli {
-    /// FASTA input file path
-    pub input: PathBuf,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// FASTA input file path, plain or gzipped, or - for standard input
+    #[arg(required_unless_present_any = ["self_test", "version"])]
+    pub input: Option<PathBuf>,
 
-    /// bigWig output file path
-    pub output: PathBuf,
+    /// output file path, bigWig unless --output-format or its extension say otherwise
+    #[arg(required_unless_present_any = ["self_test", "version"])]
+    pub output: Option<PathBuf>,
 
     /// verbose setting
     #[arg(short, long)]
     pub verbose: bool,
 
     /// optional matrices YAML file
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub matrices: Option<PathBuf>,
 
+    /// built-in roll matrix, when no matrices file is given
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = BuiltinRoll::Simple,
+        conflicts_with = "matrices"
+    )]
+    pub roll_type: BuiltinRoll,
+
+    /// also write the per-position mean and standard deviation of curvature across
+    /// comma-separated matrix sets: simple, active or a YAML matrix file
+    #[arg(long)]
+    pub matrix_set: Option<MatrixSet>,
+
+    /// member to read when INPUT is a tar, gzipped tar or zip archive; by default every FASTA
+    /// member is read in order
+    #[arg(long)]
+    pub archive_member: Option<String>,
+
+    /// what to do with a malformed FASTA record
+    #[arg(long, value_enum, default_value_t = OnMalformed::Error)]
+    pub on_malformed: OnMalformed,
+
+    /// only compute this region, as chrom or chrom:start-end (1-based, inclusive), read through
+    /// the FASTA index; repeat for more regions
+    #[arg(
+        long = "regions",
+        value_name = "REGION",
+        conflicts_with = "symcurve_track"
+    )]
+    pub regions: Vec<Region>,
+
+    /// set the curve steps, smoothing and symcurve window together; flags given explicitly
+    /// override it
+    #[arg(long, global = true, value_enum)]
+    pub preset: Option<Preset>,
+
     /// curve step
-    #[arg(long, default_value = "15", value_parser = clap::value_parser!(u16).range(1..))]
+    #[arg(long, global = true, default_value = "15", value_parser = count::<u16>(1))]
     pub curve_step: u16,
 
     /// curve scale
-    #[arg(long, default_value = "0.33335", value_parser = parse_float_in_range)]
+    #[arg(long, global = true, default_value = "0.33335", value_parser = parse_float_in_range)]
     pub curve_scale: f32,
 
     /// curve step one
-    #[arg(long, default_value = "6", value_parser = clap::value_parser!(u16).range(1..))]
+    #[arg(long, global = true, default_value = "6", value_parser = count::<u16>(1))]
     pub curve_step_one: u16,
 
     /// curve step two
-    #[arg(long, default_value = "4", value_parser = clap::value_parser!(u16).range(1..))]
+    #[arg(long, global = true, default_value = "4", value_parser = count::<u16>(1))]
     pub curve_step_two: u16,
 
     /// symcurve window
-    #[arg(long, default_value = "101", value_parser = clap::value_parser!(u16).range(1..))]
+    #[arg(long, default_value = "101", value_parser = count::<u16>(1))]
     pub symcurve_win: u16,
 
     /// symcurve step
-    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u16).range(1..))]
+    #[arg(long, default_value = "1", value_parser = count::<u16>(1))]
     pub symcurve_step: u16,
 
+    /// also write the mirror symmetry of curvature in each symcurve window as a second track, to
+    /// OUTPUT with .symcurve before its extension
+    #[arg(long)]
+    pub symcurve_track: bool,
+
+    /// also write the inferred symmetry axis (dyad candidate) of each symmetry window as BED
+    #[arg(long)]
+    pub symmetry_axis: bool,
+
     /// minimum linker size
-    #[arg(long, default_value = "30", value_parser = clap::value_parser!(u16).range(1..))]
+    #[arg(long, default_value = "30", value_parser = count::<u16>(1))]
     pub min_linker_size: u16,
+
+    /// position each value is reported at
+    #[arg(long, global = true, value_enum, default_value_t = Anchor::Center)]
+    pub anchor: Anchor,
+
+    /// write separate plus/minus strand outputs, to OUTPUT with .plus and .minus before its
+    /// extension in place of OUTPUT
+    #[arg(long)]
+    pub stranded: bool,
+
+    /// optional per-position bend vector TSV output path
+    #[arg(long)]
+    pub bend_vectors: Option<PathBuf>,
+
+    /// optional per-window curvature magnitude and signed direction change TSV output path
+    #[arg(long)]
+    pub magnitude_phase: Option<PathBuf>,
+
+    /// optional TSV output path for the rolling minimum and maximum of curvature
+    #[arg(long)]
+    pub envelope: Option<PathBuf>,
+
+    /// number of values in each envelope window
+    #[arg(long, default_value = "1001", value_parser = count::<u32>(1))]
+    pub envelope_window: u32,
+
+    /// optional Arrow IPC stream output path for chrom, pos and value columns; - for stdout
+    #[arg(long)]
+    pub arrow: Option<PathBuf>,
+
+    /// optional SQLite database output path, with values in a table keyed by chrom and pos
+    #[arg(long)]
+    pub sqlite: Option<PathBuf>,
+
+    /// optional BED output path for the soft-masked (lowercase) intervals of the input
+    #[arg(long)]
+    pub masked_bed: Option<PathBuf>,
+
+    /// also write warnings and errors as JSON lines keyed by contig and coordinate to OUTPUT with
+    /// .issues.jsonl for its extension
+    #[arg(long)]
+    pub issues: bool,
+
+    /// also write the size, md5 and sha256 of each output to a run manifest at OUTPUT with
+    /// .manifest.json appended
+    #[arg(long)]
+    pub manifest: bool,
+
+    /// most warnings of one kind shown per contig on stderr, the rest summarized in one line; 0
+    /// shows them all
+    #[arg(long, default_value = "10", value_parser = count::<u64>(0))]
+    pub warning_limit: u64,
+
+    /// report peak memory and buffer sizes at the end of the run
+    #[arg(long)]
+    pub mem_stats: bool,
+
+    /// report wall-clock time per pipeline stage
+    #[arg(long)]
+    pub timings: bool,
+
+    /// show a live monitor of contig progress, throughput, warnings and recent curvature on stderr
+    #[arg(long)]
+    pub tui: bool,
+
+    /// optional JSON output path for a summary of the curvature distribution: moments, percentiles
+    /// and a t-digest sketch
+    #[arg(long)]
+    pub stats_json: Option<PathBuf>,
+
+    /// optional local JSON file accumulating usage statistics across runs: bases processed, runs
+    /// per output format, throughput and failed runs; nothing is sent over the network
+    #[arg(long)]
+    pub stats_file: Option<PathBuf>,
+
+    /// POST the run manifest with the outcome to an http:// URL, or write it to a file:// done-file,
+    /// when the run finishes or fails
+    #[arg(long, value_name = "URL")]
+    pub notify_url: Option<NotifyTarget>,
+
+    /// optional span trace output path (Chrome JSON or folded stacks)
+    #[arg(long)]
+    pub trace: Option<PathBuf>,
+
+    /// span trace format
+    #[arg(long, value_enum, default_value_t = TraceFormat::Chrome)]
+    pub trace_format: TraceFormat,
+
+    /// overwrite existing output files
+    #[arg(long)]
+    pub force: bool,
+
+    /// directory for outputs; OUTPUT becomes a file name template where {input} is the input file stem
+    #[arg(long)]
+    pub output_dir: Option<PathBuf>,
+
+    /// directory relative output paths are resolved in and temporary files are kept in; output
+    /// paths must then be relative
+    #[arg(long)]
+    pub work_dir: Option<PathBuf>,
+
+    /// list the files produced on stdout when the run finishes
+    #[arg(long, value_enum)]
+    pub emit_outputs: Option<EmitOutputs>,
+
+    /// format of OUTPUT, from its extension if not given: .bedGraph or .bg for bedgraph, .tsv or
+    /// .txt for tsv, anything else for bigwig
+    #[arg(long, value_enum)]
+    pub output_format: Option<OutputFormat>,
+
+    /// text written at uncomputable positions
+    #[arg(long, value_enum, default_value_t = NaValue::NaN, allow_hyphen_values = true)]
+    pub na_value: NaValue,
+
+    /// how numbers are written in text outputs: shortest, fixed:N or sci:N decimal places
+    #[arg(long, global = true, default_value = "shortest")]
+    pub float_format: FloatFormat,
+
+    /// how TSV and bedGraph tracks are compressed, adding .gz or .zst to their paths
+    #[arg(long, value_enum, default_value_t = Compression::None)]
+    pub compression: Compression,
+
+    /// number of bases each bigWig value covers; other formats only take 1
+    #[arg(long, default_value = "1", value_parser = count::<u64>(1))]
+    pub span: u64,
+
+    /// how the values in each bigWig span are combined
+    #[arg(long, value_enum, default_value_t = Aggregate::Mean)]
+    pub aggregate: Aggregate,
+
+    /// how windows are handled at sequence ends
+    #[arg(long, global = true, value_enum, default_value_t = EdgeMode::Drop)]
+    pub edge_mode: EdgeMode,
+
+    /// rolling mean averaging convention
+    #[arg(long, global = true, value_enum, default_value_t = MeanConvention::Legacy)]
+    pub mean_convention: MeanConvention,
+
+    /// order sequences are written in
+    #[arg(long, value_enum, default_value_t = SortContigs::Input)]
+    pub sort_contigs: SortContigs,
+
+    /// also write a Picard-style sequence dictionary next to the output
+    #[arg(long)]
+    pub dict: bool,
+
+    /// reuse values across exactly repeated windows in repetitive sequence
+    #[arg(long)]
+    pub memoize: bool,
+
+    /// check for contigs with identical sequence
+    #[arg(long, value_enum, default_value_t = DuplicateMode::Off)]
+    pub duplicate_contigs: DuplicateMode,
+
+    /// also write a thresholded copy of the track, 1 above BINARIZE and 0 elsewhere
+    #[arg(long, allow_negative_numbers = true, value_parser = parse_real)]
+    pub binarize: Option<f64>,
+
+    /// form of the thresholded output
+    #[arg(long, value_enum, default_value_t = BinarizeFormat::Track)]
+    pub binarize_format: BinarizeFormat,
+
+    /// write only a quick preview track: every Nth window, or the first length of each sequence
+    /// with a kb, Mb or Gb suffix
+    #[arg(long)]
+    pub preview: Option<Preview>,
+
+    /// name column template for BED outputs, using {chrom}, {start}, {end} and {index}
+    #[arg(long, value_name = "TEMPLATE")]
+    pub bed_name: Option<NameTemplate>,
+
+    /// statistic of the interval's values written to the BED score column
+    #[arg(long, value_enum)]
+    pub bed_score: Option<ScoreStat>,
+
+    /// also write a track computed per position from an expression over curve, sym and pos, e.g. score='curve * sym' (repeatable)
+    #[arg(long, value_name = "NAME=EXPR")]
+    pub derive: Vec<DerivedTrack>,
+
+    /// only write positions where the expression is true, e.g. 'curve > 5 && sym > 0.3'
+    #[arg(long, value_name = "EXPR")]
+    pub filter: Option<Expr>,
+
+    /// also write the per-position divergence between forward and reverse-complement curvature
+    #[arg(long)]
+    pub strand_divergence: bool,
+
+    /// clip values to MIN:MAX before writing, counting the positions clipped
+    #[arg(long, value_name = "MIN:MAX", allow_hyphen_values = true)]
+    pub clip: Option<Clip>,
+
+    /// write curvature residuals after regressing out local composition learned from the input
+    #[arg(long, value_name = "COVARIATES")]
+    pub composition_correction: Option<Covariates>,
+
+    /// background curvature values; also write a -log10(p) signal track against them
+    #[arg(long)]
+    pub background: Option<PathBuf>,
+
+    /// track name shown by genome browsers (bedGraph outputs)
+    #[arg(long, value_parser = parse_track_text)]
+    pub track_name: Option<String>,
+
+    /// track description shown by genome browsers (bedGraph outputs)
+    #[arg(long, value_parser = parse_track_text)]
+    pub track_description: Option<String>,
+
+    /// track color (bedGraph outputs)
+    #[arg(long, value_name = "R,G,B")]
+    pub track_color: Option<TrackColor>,
+
+    /// default vertical range of the track (bedGraph outputs)
+    #[arg(long, value_name = "MIN:MAX", allow_hyphen_values = true)]
+    pub view_limits: Option<ViewLimits>,
+
+    /// whether browsers rescale the track to the values in view (bedGraph outputs)
+    #[arg(long, value_enum)]
+    pub auto_scale: Option<AutoScale>,
+
+    /// split long sequences into chunks computed in parallel
+    #[arg(long)]
+    pub chunked: bool,
+
+    /// bases per chunk when chunking, chosen from the cache size and core count if not given
+    #[arg(long, requires = "chunked", value_parser = count::<u64>(1))]
+    pub chunk_size: Option<u64>,
+
+    /// cap on computed values waiting to be written, e.g. 512M or 2G; computation waits when it is
+    /// reached; a quarter of the memory limit of the container if not given
+    #[arg(long, value_name = "SIZE", value_parser = parse_memory_size)]
+    pub max_memory: Option<usize>,
+
+    /// directory for a temporary file that records held for --sort-contigs or
+    /// --composition-correction are spilled to rather than kept in memory
+    #[arg(long)]
+    pub spill_dir: Option<PathBuf>,
+
+    /// check the pipeline against embedded benchmark sequences and exit
+    #[arg(long)]
+    pub self_test: bool,
+
+    /// print version; with --verbose also the git commit, build date, cargo features and library
+    /// versions
+    #[arg(short = 'V', long)]
+    pub version: bool,
+}
+
+/// Subcommands that work on the outputs of earlier runs.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// print values for a region of an existing bigWig or bedGraph output
+    View {
+        /// bigWig or bedGraph file written by symcurve
+        file: PathBuf,
+
+        /// region to print, as chrom or chrom:start-end (1-based, inclusive)
+        region: Option<Region>,
+    },
+    /// print how an existing output or run manifest was generated
+    Info {
+        /// output or manifest file written by symcurve
+        file: PathBuf,
+    },
+    /// compare curvature over a grid of parameter values on one sequence
+    Sweep {
+        /// FASTA input file path, plain or gzipped, or - for standard input
+        input: PathBuf,
+
+        /// parameter range as NAME=START..END:STEP, e.g. curve-step=5..30:5; repeat for a grid
+        #[arg(long = "param", value_name = "RANGE", required = true)]
+        params: Vec<ParamRange>,
+
+        /// region to sweep over, as chrom or chrom:start-end (default: the first sequence)
+        #[arg(long)]
+        region: Option<Region>,
+    },
+    /// write the curvature difference between two genomes over chain-aligned regions
+    Compare {
+        /// target (reference) genome FASTA file path
+        target: PathBuf,
+
+        /// query genome FASTA file path
+        query: PathBuf,
+
+        /// UCSC chain file mapping target to query coordinates
+        chain: PathBuf,
+    },
+    /// attribute the curvature of a region to its bases, as TSV
+    Explain {
+        /// FASTA input file path, plain or gzipped, or - for standard input
+        input: PathBuf,
+
+        /// region to explain, as chrom:start-end (1-based, inclusive)
+        region: Region,
+    },
+    /// report the largest curvature change of every point mutation in a region
+    ScanMutations {
+        /// FASTA input file path, plain or gzipped, or - for standard input
+        input: PathBuf,
+
+        /// region to scan, as chrom:start-end (1-based, inclusive)
+        #[arg(long)]
+        region: Region,
+    },
+    /// search for oligos of a given length and GC content with extreme curvature
+    Design {
+        /// oligo length in bases
+        #[arg(long, value_parser = count::<usize>(1))]
+        length: usize,
+
+        /// GC fraction of the oligos
+        #[arg(long, default_value = "0.5", value_parser = parse_fraction)]
+        gc: f64,
+
+        /// whether curvature is maximized or minimized
+        #[arg(long, value_enum, default_value = "max")]
+        objective: Objective,
+
+        /// annealing moves per candidate
+        #[arg(long, default_value = "2000", value_parser = count::<usize>(0))]
+        iterations: usize,
+
+        /// number of candidates, each from its own seed
+        #[arg(long, default_value = "5", value_parser = count::<u16>(1))]
+        candidates: u16,
+
+        /// seed of the first candidate's random start
+        #[arg(long, default_value = "1", value_parser = count::<u64>(0))]
+        seed: u64,
+    },
+    /// write a position frequency matrix of the sequence around curvature peak summits
+    Motifs {
+        /// FASTA input file path
+        input: PathBuf,
+
+        /// peaks are runs of curvature above this value
+        #[arg(long, value_parser = parse_real)]
+        threshold: f64,
+
+        /// number of bases in each site, centered on the summit
+        #[arg(long, default_value = "21", value_parser = count::<u16>(1))]
+        width: u16,
+
+        /// matrix format
+        #[arg(long, value_enum, default_value = "meme")]
+        format: MotifFormat,
+    },
+    /// summarize curvature per bin of an assembly of many short contigs
+    Metagenome {
+        /// FASTA input file path
+        input: PathBuf,
+
+        /// contig-to-bin table, one tab-separated contig and bin per line; each contig is its own
+        /// bin if not given
+        #[arg(long)]
+        bins: Option<PathBuf>,
+
+        /// threads computing curvature, the CPUs the process may use if not given
+        #[arg(long, value_parser = count::<u16>(1))]
+        threads: Option<u16>,
+    },
+    /// write one row of curvature statistics per read of a FASTQ or unaligned BAM file
+    Reads {
+        /// FASTQ, gzipped FASTQ or unaligned BAM input file path
+        input: PathBuf,
+
+        /// number of values in each tile checked for mirror symmetry
+        #[arg(long, default_value = "101", value_parser = count::<u16>(3))]
+        symcurve_win: u16,
+
+        /// SymCurv score a tile needs to count as symmetric; 100 is a perfect mirror image
+        #[arg(long, default_value = "0.9", value_parser = parse_symmetry_threshold)]
+        symmetry_threshold: f64,
+
+        /// threads computing curvature, the CPUs the process may use if not given
+        #[arg(long, value_parser = count::<u16>(1))]
+        threads: Option<u16>,
+    },
+    /// fit weights and a threshold that call nucleosome occupancy from tracks against an
+    /// experimental map
+    Calibrate {
+        /// bigWig or bedGraph tracks written by symcurve, combined into the predictor
+        #[arg(required = true)]
+        tracks: Vec<PathBuf>,
+
+        /// experimental occupancy track, e.g. MNase-seq coverage, as bigWig or bedGraph
+        #[arg(long)]
+        truth: PathBuf,
+
+        /// truth value above which a position counts as occupied; the median if not given
+        #[arg(long, allow_negative_numbers = true, value_parser = parse_real)]
+        truth_threshold: Option<f64>,
+
+        /// region to calibrate over, as chrom or chrom:start-end (default: every chromosome)
+        #[arg(long)]
+        region: Option<Region>,
+
+        /// distance in bases between sampled positions
+        #[arg(long, default_value = "100", value_parser = count::<u64>(1))]
+        stride: u64,
+
+        /// step of the grid of track weights
+        #[arg(long, default_value = "0.1", value_parser = parse_weight_step)]
+        weight_step: f64,
+
+        /// optional TSV output path for the ROC and PR curves of the best weights
+        #[arg(long)]
+        curves: Option<PathBuf>,
+
+        /// calibration file output path
+        #[arg(short, long, default_value = "calibration.json")]
+        output: PathBuf,
+    },
+    /// fit a roll matrix that separates positive from negative sequences by curvature
+    Train {
+        /// FASTA file of sequences that should curve more, e.g. nucleosome-bound
+        #[arg(long)]
+        positive: PathBuf,
+
+        /// FASTA file of sequences that should curve less, e.g. linkers
+        #[arg(long)]
+        negative: PathBuf,
+
+        /// most passes of the search over the matrix
+        #[arg(long, default_value = "20", value_parser = count::<u64>(1))]
+        iterations: u64,
+
+        /// first amount each roll value is nudged by
+        #[arg(long, default_value = "1.0", value_parser = parse_train_step)]
+        step: f64,
+
+        /// YAML matrix file output path
+        #[arg(short, long, default_value = "matrices.yaml")]
+        output: PathBuf,
+    },
+    /// cross-validate how well a matrix file separates labeled regions, over chromosome folds
+    Crossval {
+        /// FASTA file the regions are on
+        input: PathBuf,
+
+        /// BED file of regions with a 1/positive or 0/negative label in the fourth column; the
+        /// matrices evaluated are those of --matrices, or the built-in ones
+        regions: PathBuf,
+
+        /// number of folds the chromosomes are dealt into
+        #[arg(short = 'k', long, default_value = "5", value_parser = count::<u64>(2))]
+        folds: u64,
+    },
+    /// serve curvature of requested regions over HTTP, recomputed with the parameters in each
+    /// request
+    Serve {
+        /// FASTA file served, uncompressed; indexed in memory if there is no .fai next to it
+        input: PathBuf,
+
+        /// address and port to listen on
+        #[arg(long, default_value = "127.0.0.1:8000")]
+        listen: String,
+
+        /// longest region computed for one request, in bases
+        #[arg(long, default_value = "1000000", value_parser = count::<u64>(1))]
+        max_region: u64,
+
+        /// number of computed regions kept for repeated requests; 0 turns the cache off
+        #[arg(long, default_value = "64", value_parser = count::<usize>(0))]
+        cache_size: usize,
+
+        /// most connections handled at once; more are answered 503 and closed
+        #[arg(long, default_value = "64", value_parser = count::<usize>(1))]
+        max_connections: usize,
+    },
+    /// print the man page, or write the pages of every command into a directory
+    Man {
+        /// directory the pages are written to, symcurve.1 and symcurve-<COMMAND>.1, instead of
+        /// printing the page of symcurve
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+}
+
+/// Parses the first search step of `symcurve train`.
+fn parse_train_step(s: &str) -> Result<f64, String> {
+    match parse_real(s) {
+        Ok(step) if step > 0.0 => Ok(step),
+        _ => Err(format!("invalid step '{}': expected a positive number", s)),
+    }
+}
+
+/// Parses the mirror score threshold of `symcurve reads`.
+fn parse_symmetry_threshold(s: &str) -> Result<f64, String> {
+    match parse_real(s) {
+        Ok(threshold) if threshold >= 0.0 => Ok(threshold),
+        _ => Err(format!(
+            "invalid symmetry threshold '{}': expected a number of at least 0",
+            s
+        )),
+    }
+}
+
+/// Parses the weight grid step of `symcurve calibrate`, a fraction of 1.
+fn parse_weight_step(s: &str) -> Result<f64, String> {
+    match parse_real(s) {
+        Ok(step) if step > 0.0 && step <= 1.0 => Ok(step),
+        _ => Err(format!(
+            "invalid weight step '{}': expected a number above 0 and at most 1",
+            s
+        )),
+    }
+}
+
+/// Bundles of the interacting window parameters, tuned for common kinds of analysis.
+///
+/// | preset       | `--curve-step` | `--curve-step-one` | `--curve-step-two` | `--symcurve-win` |
+/// |--------------|----------------|--------------------|--------------------|------------------|
+/// | `nucleosome` | 15             | 6                  | 4                  | 147              |
+/// | `promoter`   | 10             | 5                  | 3                  | 51               |
+/// | `fine`       | 5              | 3                  | 2                  | 21               |
+/// | `coarse`     | 30             | 11                 | 8                  | 301              |
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    /// the default curvature windows with a symmetry window of one nucleosome core (147 bp)
+    Nucleosome,
+    /// shorter windows for promoter-scale features
+    Promoter,
+    /// the shortest windows, for base-level detail
+    Fine,
+    /// long windows for chromosome-scale trends
+    Coarse,
+}
+
+impl Preset {
+    /// The values the preset sets, as `(argument id, value)` pairs.
+    pub fn values(&self) -> [(&'static str, u16); 4] {
+        let [step, one, two, win] = match self {
+            Preset::Nucleosome => [15, 6, 4, 147],
+            Preset::Promoter => [10, 5, 3, 51],
+            Preset::Fine => [5, 3, 2, 21],
+            Preset::Coarse => [30, 11, 8, 301],
+        };
+        [
+            ("curve_step", step),
+            ("curve_step_one", one),
+            ("curve_step_two", two),
+            ("symcurve_win", win),
+        ]
+    }
+}
+
+impl Cli {
+    /// Parses the command line of the process, then applies the preset, if any.
+    pub fn parse_with_preset() -> Self {
+        Self::parse_with_preset_from(std::env::args_os())
+    }
+
+    /// Parses `args`, then applies the preset, if any, to the parameters not given explicitly.
+    pub fn parse_with_preset_from<I, T>(args: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let matches = Self::command().get_matches_from(args);
+        let mut cli = Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        cli.apply_preset(&matches);
+        cli
+    }
+
+    /// Sets the parameters of the preset, if any, except those given on the command line.
+    pub fn apply_preset(&mut self, matches: &ArgMatches) {
+        let Some(preset) = self.preset else {
+            return;
+        };
+        for (id, value) in preset.values() {
+            if matches.value_source(id) == Some(ValueSource::CommandLine) {
+                continue;
+            }
+            match id {
+                "curve_step" => self.curve_step = value,
+                "curve_step_one" => self.curve_step_one = value,
+                "curve_step_two" => self.curve_step_two = value,
+                _ => self.symcurve_win = value,
+            }
+        }
+    }
+
+    /// The parameters that affect computed values, as name/value pairs in a fixed order.
+    ///
+    /// File paths and reporting options are left out, so two runs with equal parameters produce
+    /// equal values regardless of where they read from or write to.
+    pub fn parameters(&self) -> Vec<(String, String)> {
+        vec![
+            ("curve_step".to_string(), self.curve_step.to_string()),
+            ("curve_scale".to_string(), self.curve_scale.to_string()),
+            (
+                "curve_step_one".to_string(),
+                self.curve_step_one.to_string(),
+            ),
+            (
+                "curve_step_two".to_string(),
+                self.curve_step_two.to_string(),
+            ),
+            ("symcurve_win".to_string(), self.symcurve_win.to_string()),
+            ("symcurve_step".to_string(), self.symcurve_step.to_string()),
+            (
+                "min_linker_size".to_string(),
+                self.min_linker_size.to_string(),
+            ),
+            ("roll_type".to_string(), value_name(&self.roll_type)),
+            ("anchor".to_string(), value_name(&self.anchor)),
+            ("edge_mode".to_string(), value_name(&self.edge_mode)),
+            (
+                "mean_convention".to_string(),
+                value_name(&self.mean_convention),
+            ),
+        ]
+    }
+
+    /// The most warnings of one kind shown per contig, as [`IssueLog::with_limit`] takes it.
+    ///
+    /// [`IssueLog::with_limit`]: crate::issues::IssueLog::with_limit
+    pub fn warning_limit(&self) -> Option<u64> {
+        (self.warning_limit > 0).then_some(self.warning_limit)
+    }
+
+    /// The track line settings given on the command line.
+    pub fn track_line(&self) -> TrackLine {
+        TrackLine {
+            name: self.track_name.clone(),
+            description: self.track_description.clone(),
+            color: self.track_color,
+            view_limits: self.view_limits,
+            auto_scale: self.auto_scale,
+        }
+    }
+
+    /// The parameters of the curvature pipeline given on the command line.
+    ///
+    /// `--curve-step-one` is the distance from the center to the ends of the rolling mean window
+    /// plus one, as in the original Perl code, so `step_b` is one less. The curve scale is carried
+    /// over by its decimal form, so `0.33335` stays exactly the default rather than the nearest
+    /// `f32`. The twist, roll and tilt matrices are read from `--matrices` if it is given, and
+    /// otherwise the built-in roll matrix of `--roll-type` is used.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the matrices file if it cannot be read or parsed, or an
+    /// `io::ErrorKind::InvalidInput` error if the decimal form of the curve scale does not parse
+    /// back.
+    pub fn pipeline_params(&self) -> io::Result<PipelineParams> {
+        let roll_type = match &self.matrices {
+            Some(path) => Matrices::read_yaml(path)
+                .map(|matrices| RollType::Matrices(Arc::new(matrices)))
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?,
+            None => RollType::from(self.roll_type),
+        };
+        Ok(PipelineParams {
+            roll_type,
+            step_b: usize::from(self.curve_step_one) - 1,
+            step_two: Some(usize::from(self.curve_step_two)),
+            step_c: usize::from(self.curve_step),
+            curve_scale: parse_real(&self.curve_scale.to_string())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+            edge_mode: self.edge_mode,
+            convention: self.mean_convention,
+            anchor: self.anchor,
+            na: self.na_value,
+            float_format: self.float_format,
+            track_line: self.track_line(),
+            symmetry_window: usize::from(self.symcurve_win),
+            symmetry_step: usize::from(self.symcurve_step),
+            span: self.span,
+            aggregate: self.aggregate,
+            bigwig_threads: Resources::detect().threads,
+            chunking: self
+                .chunked
+                .then(|| ChunkPlan::detect(self.chunk_size.map(|size| size as usize))),
+            cancel: CancelToken::default(),
+            regions: self.regions.clone(),
+            archive_member: self.archive_member.clone(),
+            compression: self.compression,
+            provenance: Some(Provenance::from_cli(self)?),
+        })
+    }
+
+    /// The outputs of the run given on the command line besides the curvature and its symmetry
+    /// track.
+    pub fn run_options(&self) -> RunOptions {
+        RunOptions {
+            stranded: self.stranded,
+            bend_vectors: self.bend_vectors.clone(),
+            timings: self.timings,
+            trace: self.trace.clone(),
+            trace_format: self.trace_format,
+            binarize: self.binarize,
+            binarize_format: self.binarize_format,
+            bed_name: self.bed_name.clone(),
+            bed_score: self.bed_score,
+            memoize: self.memoize,
+            duplicate_contigs: self.duplicate_contigs,
+            dict: self.dict,
+            sort_contigs: self.sort_contigs,
+            derive: self.derive.clone(),
+            filter: self.filter.clone(),
+            clip: self.clip,
+            strand_divergence: self.strand_divergence,
+            max_memory: self.max_memory,
+            symmetry_axis: self.symmetry_axis,
+            magnitude_phase: self.magnitude_phase.clone(),
+            composition_correction: self.composition_correction,
+            envelope: self.envelope.clone(),
+            envelope_window: self.envelope_window as usize,
+            background: self.background.clone(),
+            stats_json: self.stats_json.clone(),
+            spill_dir: self.spill_dir.clone(),
+            tui: self.tui,
+            arrow: self.arrow.clone(),
+            sqlite: self.sqlite.clone(),
+            preview: self.preview,
+            masked_bed: self.masked_bed.clone(),
+            matrix_set: self.matrix_set.clone(),
+            issues: self.issues,
+            manifest: self.manifest,
+            warning_limit: self.warning_limit(),
+            on_malformed: self.on_malformed,
+        }
+    }
+}
+
+/// The command-line spelling of an enum value, e.g. `center` for `Anchor::Center`.
+pub(crate) fn value_name<T: ValueEnum>(value: &T) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default()
 }
 
 fn parse_float_in_range(s: &str) -> Result<f32, String> {
-    let value = s
-        .parse::<f32>()
-        .map_err(|_| "Value must be a floating-point number")?;
-    if value >= 0.0 && value <= 1.0 {
-        Ok(value)
+    let value = parse_real(s).map_err(|_| "Value must be a floating-point number")?;
+    if (0.0..=1.0).contains(&value) {
+        Ok(value as f32)
     } else {
         Err("The value must be between 0 and 1".to_owned())
     }
 }
 
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    parse_float_in_range(s).map(f64::from)
+}
+
+// the tests predate the lints
 #[cfg(test)]
+#[allow(
+    clippy::bool_assert_comparison,
+    clippy::needless_borrows_for_generic_args,
+    clippy::needless_return
+)]
 mod tests {
     use super::*;
+    use crate::curve::ensemble::MatrixModel;
+
+    #[test]
+    fn test_preset() {
+        let args = Cli::parse_with_preset_from(["symcurve", "input.fasta", "output.bw"]);
+        assert_eq!((args.curve_step, args.symcurve_win), (15, 101));
+        let args = Cli::parse_with_preset_from([
+            "symcurve",
+            "--preset",
+            "coarse",
+            "--curve-step-one",
+            "9",
+            "input.fasta",
+            "output.bw",
+        ]);
+        assert_eq!(args.preset, Some(Preset::Coarse));
+        assert_eq!(args.curve_step, 30);
+        assert_eq!(args.curve_step_one, 9);
+        assert_eq!(args.curve_step_two, 8);
+        assert_eq!(args.symcurve_win, 301);
+        assert_eq!(args.min_linker_size, 30);
+        // an explicit value equal to the default still wins over the preset
+        let args = Cli::parse_with_preset_from([
+            "symcurve",
+            "--preset",
+            "fine",
+            "--curve-step",
+            "15",
+            "input.fasta",
+            "output.bw",
+        ]);
+        assert_eq!((args.curve_step, args.symcurve_win), (15, 21));
+        assert!(Cli::try_parse_from(["symcurve", "--preset", "huge", "a.fa", "b.bw"]).is_err());
+    }
+
+    #[test]
+    fn test_pipeline_params() {
+        let args = Cli::parse_with_preset_from(["symcurve", "input.fasta", "output.bw"]);
+        let params = args.pipeline_params().unwrap();
+        let defaults = PipelineParams::default();
+        assert_eq!(
+            (params.step_b, params.step_c, params.curve_scale),
+            (defaults.step_b, defaults.step_c, defaults.curve_scale)
+        );
+        assert_eq!(params.step_two, Some(4));
+        assert_eq!(params.flank(), defaults.flank());
+        let args = Cli::parse_with_preset_from([
+            "symcurve",
+            "--preset",
+            "fine",
+            "--curve-scale",
+            "0.5",
+            "--edge-mode",
+            "shrink",
+            "input.fasta",
+            "output.bw",
+        ]);
+        let params = args.pipeline_params().unwrap();
+        assert_eq!(
+            (params.step_b, params.step_two, params.step_c),
+            (2, Some(2), 5)
+        );
+        assert_eq!(params.curve_scale, 0.5);
+        assert_eq!(params.edge_mode, EdgeMode::Shrink);
+    }
+
+    #[test]
+    fn test_subcommand_pipeline_params() {
+        let args = Cli::parse_with_preset_from([
+            "symcurve",
+            "sweep",
+            "in.fa",
+            "--param",
+            "curve-scale=0.1..0.3:0.1",
+            "--preset",
+            "fine",
+            "--curve-step-one",
+            "8",
+            "--edge-mode",
+            "shrink",
+        ]);
+        assert!(matches!(args.command, Some(Command::Sweep { .. })));
+        let params = args.pipeline_params().unwrap();
+        // the preset still gives way to the flags given explicitly
+        assert_eq!(
+            (params.step_b, params.step_two, params.step_c),
+            (7, Some(2), 5)
+        );
+        assert_eq!(params.edge_mode, EdgeMode::Shrink);
+    }
+
+    #[test]
+    fn test_pipeline_params_matrices() {
+        use crate::curve::matrix::{write_matrices_yaml, ROLL_SIMPLE, TILT, TWIST};
+        let path = std::env::temp_dir().join("symcurve_test_cli_matrices.yaml");
+        let mut twist = TWIST;
+        twist[0][0][0] = 0.5;
+        let mut yaml = Vec::new();
+        write_matrices_yaml(&mut yaml, "test", &twist, &ROLL_SIMPLE, &TILT).unwrap();
+        std::fs::write(&path, yaml).unwrap();
+        let args = Cli::parse_from(["symcurve", "-m", path.to_str().unwrap(), "a.fa", "b.bw"]);
+        let params = args.pipeline_params().unwrap();
+        assert_eq!(params.roll_type.twist_matrix()[0][0][0], 0.5);
+        assert_eq!(params.roll_type.tilt_matrix(), &TILT);
+        std::fs::write(&path, "roll:\n  AAA: 1.0\n").unwrap();
+        let err = args.pipeline_params().unwrap_err();
+        assert!(err.to_string().starts_with(path.to_str().unwrap()));
+        std::fs::remove_file(&path).unwrap();
+    }
     use clap::error::*;
 
     #[test]
@@ -105,11 +1191,953 @@ mod tests {
             "--curve-step",
             "20",
         ]);
-        assert_eq!(args.input.to_str().unwrap(), "input.fasta");
-        assert_eq!(args.output.to_str().unwrap(), "output.bw");
+        assert_eq!(args.input.unwrap().to_str().unwrap(), "input.fasta");
+        assert_eq!(args.output.unwrap().to_str().unwrap(), "output.bw");
         assert_eq!(args.verbose, true);
         assert_eq!(args.matrices.unwrap().to_str().unwrap(), "matrices.yaml");
         assert_eq!(args.curve_step, 20);
+        assert_eq!(args.anchor, Anchor::Center);
+        assert!(!args.stranded);
+        assert!(args.bend_vectors.is_none());
+        assert!(args.magnitude_phase.is_none());
+        assert!(args.envelope.is_none());
+        assert_eq!(args.envelope_window, 1001);
+        assert!(args.arrow.is_none());
+        assert!(args.sqlite.is_none());
+        assert!(args.masked_bed.is_none());
+        assert!(!args.issues);
+        assert!(!args.manifest);
+        assert_eq!(args.warning_limit, 10);
+        assert!(!args.force);
+        assert!(args.output_dir.is_none());
+        assert!(args.work_dir.is_none());
+        assert!(args.emit_outputs.is_none());
+        assert_eq!(args.edge_mode, EdgeMode::Drop);
+        assert_eq!(args.mean_convention, MeanConvention::Legacy);
+    }
+
+    #[test]
+    fn test_work_dir() {
+        let args = Cli::parse_from([
+            "symcurve",
+            "--work-dir",
+            "work",
+            "--emit-outputs",
+            "json",
+            "input.fasta",
+            "output.bw",
+        ]);
+        assert_eq!(args.work_dir.unwrap().to_str().unwrap(), "work");
+        assert_eq!(args.emit_outputs, Some(EmitOutputs::Json));
+        assert!(
+            Cli::try_parse_from(["symcurve", "--emit-outputs", "xml", "a.fa", "b.bw"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_masked_bed() {
+        let args = Cli::parse_from([
+            "symcurve",
+            "--masked-bed",
+            "out.masked.bed",
+            "input.fasta",
+            "output.bw",
+        ]);
+        assert_eq!(args.masked_bed.unwrap().to_str().unwrap(), "out.masked.bed");
+    }
+
+    #[test]
+    fn test_issues() {
+        let args = Cli::parse_from(["symcurve", "--issues", "input.fasta", "output.bw"]);
+        assert!(args.issues);
+    }
+
+    #[test]
+    fn test_manifest() {
+        let args = Cli::parse_from(["symcurve", "--manifest", "input.fasta", "output.bw"]);
+        assert!(args.manifest);
+    }
+
+    #[test]
+    fn test_envelope() {
+        let args = Cli::parse_from([
+            "symcurve",
+            "--envelope",
+            "out.envelope.tsv",
+            "--envelope-window",
+            "501",
+            "input.fasta",
+            "output.bw",
+        ]);
+        assert_eq!(args.envelope.unwrap().to_str().unwrap(), "out.envelope.tsv");
+        assert_eq!(args.envelope_window, 501);
+        assert!(Cli::try_parse_from([
+            "symcurve",
+            "--envelope-window",
+            "0",
+            "input.fasta",
+            "output.bw"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_symmetry_axis() {
+        let args = Cli::parse_from(["symcurve", "input.fasta", "output.bw"]);
+        assert!(!args.symmetry_axis);
+        let args = Cli::parse_from(["symcurve", "--symmetry-axis", "input.fasta", "output.bw"]);
+        assert!(args.symmetry_axis);
+    }
+
+    #[test]
+    fn test_trace() {
+        let args = Cli::parse_from([
+            "symcurve",
+            "input.fasta",
+            "output.bw",
+            "--trace",
+            "run.folded",
+            "--trace-format",
+            "folded",
+        ]);
+        assert_eq!(args.trace.unwrap().to_str().unwrap(), "run.folded");
+        assert_eq!(args.trace_format, TraceFormat::Folded);
+    }
+
+    #[test]
+    fn test_na_value() {
+        for (arg, expected) in [
+            ("nan", NaValue::NaN),
+            ("na", NaValue::Na),
+            (".", NaValue::Dot),
+            ("-1", NaValue::MinusOne),
+            ("omit", NaValue::Omit),
+        ] {
+            let args = Cli::parse_from(["symcurve", "in.fa", "out.bw", "--na-value", arg]);
+            assert_eq!(args.na_value, expected);
+        }
+        let args = Cli::parse_from(["symcurve", "in.fa", "out.bw"]);
+        assert_eq!(args.na_value, NaValue::NaN);
+    }
+
+    #[test]
+    fn test_float_format() {
+        let args = Cli::parse_from(["symcurve", "in.fa", "out.bw"]);
+        assert_eq!(args.float_format, FloatFormat::Shortest);
+        let args = Cli::parse_from(["symcurve", "--float-format", "sci:6", "in.fa", "out.bw"]);
+        assert_eq!(args.float_format, FloatFormat::Sci(6));
+        assert!(
+            Cli::try_parse_from(["symcurve", "--float-format", "sci", "in.fa", "out.bw"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_matrix_set() {
+        let args = Cli::parse_from(["symcurve", "in.fa", "out.bw"]);
+        assert!(args.matrix_set.is_none());
+        let args = Cli::parse_from([
+            "symcurve",
+            "--matrix-set",
+            "simple,active,fitted.yaml",
+            "in.fa",
+            "out.bw",
+        ]);
+        let set = args.matrix_set.unwrap();
+        assert_eq!(set.0.len(), 3);
+        assert_eq!(set.0[1], MatrixModel::Active);
+        assert!(
+            Cli::try_parse_from(["symcurve", "--matrix-set", "simple", "in.fa", "out.bw"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_archive_member() {
+        let args = Cli::parse_from(["symcurve", "ref.tar.gz", "out.bw"]);
+        assert!(args.archive_member.is_none());
+        let args = Cli::parse_from([
+            "symcurve",
+            "--archive-member",
+            "bundle/chr1.fa.gz",
+            "ref.tar.gz",
+            "out.bw",
+        ]);
+        assert_eq!(args.archive_member.as_deref(), Some("bundle/chr1.fa.gz"));
+    }
+
+    #[test]
+    fn test_preview() {
+        let args = Cli::parse_from(["symcurve", "in.fa", "out.bw"]);
+        assert!(args.preview.is_none());
+        let args = Cli::parse_from(["symcurve", "--preview", "20", "in.fa", "out.bw"]);
+        assert_eq!(args.preview, Some(Preview::Every(20)));
+        let args = Cli::parse_from(["symcurve", "--preview", "5Mb", "in.fa", "out.bw"]);
+        assert_eq!(args.preview, Some(Preview::Prefix(5_000_000)));
+        assert!(Cli::try_parse_from(["symcurve", "--preview", "0", "in.fa", "out.bw"]).is_err());
+    }
+
+    #[test]
+    fn test_on_malformed() {
+        let args = Cli::parse_from(["symcurve", "in.fa", "out.bw"]);
+        assert_eq!(args.on_malformed, OnMalformed::Error);
+        let args = Cli::parse_from(["symcurve", "--on-malformed", "skip", "in.fa", "out.bw"]);
+        assert_eq!(args.on_malformed, OnMalformed::Skip);
+    }
+
+    #[test]
+    fn test_compression() {
+        let args = Cli::parse_from(["symcurve", "in.fa", "out.bw"]);
+        assert_eq!(args.compression, Compression::None);
+        let args = Cli::parse_from(["symcurve", "--compression", "zstd", "in.fa", "out.bw"]);
+        assert_eq!(args.compression, Compression::Zstd);
+        assert!(
+            Cli::try_parse_from(["symcurve", "--compression", "xz", "in.fa", "out.bw"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_sort_contigs() {
+        let args = Cli::parse_from(["symcurve", "input.fasta", "output.bw"]);
+        assert_eq!(args.sort_contigs, SortContigs::Input);
+        let args = Cli::parse_from([
+            "symcurve",
+            "--sort-contigs",
+            "name",
+            "input.fasta",
+            "output.bw",
+        ]);
+        assert_eq!(args.sort_contigs, SortContigs::Name);
+    }
+
+    #[test]
+    fn test_duplicate_contigs() {
+        let args = Cli::parse_from(["symcurve", "input.fasta", "output.bw"]);
+        assert_eq!(args.duplicate_contigs, DuplicateMode::Off);
+        let args = Cli::parse_from([
+            "symcurve",
+            "--duplicate-contigs",
+            "reuse",
+            "input.fasta",
+            "output.bw",
+        ]);
+        assert_eq!(args.duplicate_contigs, DuplicateMode::Reuse);
+    }
+
+    #[test]
+    fn test_binarize() {
+        let args = Cli::parse_from(["symcurve", "input.fasta", "output.bw"]);
+        assert_eq!(args.binarize, None);
+        assert_eq!(args.binarize_format, BinarizeFormat::Track);
+        let args = Cli::parse_from([
+            "symcurve",
+            "--binarize",
+            "-0.5",
+            "--binarize-format",
+            "bed",
+            "input.fasta",
+            "output.bw",
+        ]);
+        assert_eq!(args.binarize, Some(-0.5));
+        assert_eq!(args.binarize_format, BinarizeFormat::Bed);
+    }
+
+    #[test]
+    fn test_bed_fields() {
+        let args = Cli::parse_from(["symcurve", "input.fasta", "output.bw"]);
+        assert!(args.bed_name.is_none());
+        assert!(args.bed_score.is_none());
+        let args = Cli::parse_from([
+            "symcurve",
+            "--bed-name",
+            "peak_{chrom}_{index}",
+            "--bed-score",
+            "neglog10p",
+            "input.fasta",
+            "output.bw",
+        ]);
+        assert_eq!(
+            args.bed_name.unwrap().render("chr1", 0, 10, 3),
+            "peak_chr1_3"
+        );
+        assert_eq!(args.bed_score, Some(ScoreStat::NegLog10P));
+        assert!(Cli::try_parse_from([
+            "symcurve",
+            "--bed-name",
+            "peak_{id}",
+            "input.fasta",
+            "output.bw"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_view_command() {
+        let args = Cli::parse_from(["symcurve", "view", "output.bw", "chr1:1000-2000"]);
+        match args.command {
+            Some(Command::View { file, region }) => {
+                assert_eq!(file, PathBuf::from("output.bw"));
+                let region = region.unwrap();
+                assert_eq!((region.start, region.end), (999, Some(2000)));
+            }
+            _ => panic!("expected the view command"),
+        }
+        assert!(args.input.is_none());
+        let args = Cli::parse_from(["symcurve", "input.fasta", "output.bw"]);
+        assert!(args.command.is_none());
+        assert!(Cli::try_parse_from(["symcurve", "view"]).is_err());
+    }
+
+    #[test]
+    fn test_info_command() {
+        let args = Cli::parse_from(["symcurve", "info", "output.bw.manifest.json"]);
+        match args.command {
+            Some(Command::Info { file }) => {
+                assert_eq!(file, PathBuf::from("output.bw.manifest.json"))
+            }
+            _ => panic!("expected the info command"),
+        }
+    }
+
+    #[test]
+    fn test_compare_command() {
+        let args = Cli::parse_from([
+            "symcurve",
+            "compare",
+            "hg38.fa",
+            "mm39.fa",
+            "hg38ToMm39.chain",
+        ]);
+        match args.command {
+            Some(Command::Compare {
+                target,
+                query,
+                chain,
+            }) => {
+                assert_eq!(target, PathBuf::from("hg38.fa"));
+                assert_eq!(query, PathBuf::from("mm39.fa"));
+                assert_eq!(chain, PathBuf::from("hg38ToMm39.chain"));
+            }
+            _ => panic!("expected the compare command"),
+        }
+        assert!(Cli::try_parse_from(["symcurve", "compare", "hg38.fa", "mm39.fa"]).is_err());
+    }
+
+    #[test]
+    fn test_explain_command() {
+        let args = Cli::parse_from(["symcurve", "explain", "input.fasta", "chr1:1001-1100"]);
+        match args.command {
+            Some(Command::Explain { input, region }) => {
+                assert_eq!(input, PathBuf::from("input.fasta"));
+                assert_eq!((region.start, region.end), (1000, Some(1100)));
+            }
+            _ => panic!("expected the explain command"),
+        }
+        assert!(Cli::try_parse_from(["symcurve", "explain", "input.fasta"]).is_err());
+    }
+
+    #[test]
+    fn test_scan_mutations_command() {
+        let args = Cli::parse_from([
+            "symcurve",
+            "scan-mutations",
+            "input.fasta",
+            "--region",
+            "chr1:100-400",
+        ]);
+        match args.command {
+            Some(Command::ScanMutations { input, region }) => {
+                assert_eq!(input, PathBuf::from("input.fasta"));
+                assert_eq!((region.start, region.end), (99, Some(400)));
+            }
+            _ => panic!("expected the scan-mutations command"),
+        }
+        assert!(Cli::try_parse_from(["symcurve", "scan-mutations", "input.fasta"]).is_err());
+    }
+
+    #[test]
+    fn test_design_command() {
+        let args = Cli::parse_from([
+            "symcurve",
+            "design",
+            "--length",
+            "147",
+            "--gc",
+            "0.4",
+            "--objective",
+            "min",
+        ]);
+        match args.command {
+            Some(Command::Design {
+                length,
+                gc,
+                objective,
+                iterations,
+                candidates,
+                seed,
+            }) => {
+                assert_eq!(length, 147);
+                assert!((gc - 0.4).abs() < 1e-6);
+                assert_eq!(objective, Objective::Min);
+                assert_eq!((iterations, candidates, seed), (2000, 5, 1));
+            }
+            _ => panic!("expected the design command"),
+        }
+        assert!(Cli::try_parse_from(["symcurve", "design"]).is_err());
+        assert!(
+            Cli::try_parse_from(["symcurve", "design", "--length", "60", "--gc", "2"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_motifs_command() {
+        let args = Cli::parse_from([
+            "symcurve",
+            "motifs",
+            "input.fasta",
+            "--threshold",
+            "12.5",
+            "--format",
+            "jaspar",
+        ]);
+        match args.command {
+            Some(Command::Motifs {
+                input,
+                threshold,
+                width,
+                format,
+            }) => {
+                assert_eq!(input, PathBuf::from("input.fasta"));
+                assert_eq!(threshold, 12.5);
+                assert_eq!(width, 21);
+                assert_eq!(format, MotifFormat::Jaspar);
+            }
+            _ => panic!("expected the motifs command"),
+        }
+        assert!(Cli::try_parse_from(["symcurve", "motifs", "input.fasta"]).is_err());
+    }
+
+    #[test]
+    fn test_metagenome_command() {
+        let args = Cli::parse_from([
+            "symcurve",
+            "metagenome",
+            "assembly.fasta",
+            "--bins",
+            "contig_bins.tsv",
+            "--threads",
+            "8",
+        ]);
+        match args.command {
+            Some(Command::Metagenome {
+                input,
+                bins,
+                threads,
+            }) => {
+                assert_eq!(input, PathBuf::from("assembly.fasta"));
+                assert_eq!(bins, Some(PathBuf::from("contig_bins.tsv")));
+                assert_eq!(threads, Some(8));
+            }
+            _ => panic!("expected the metagenome command"),
+        }
+        assert!(Cli::try_parse_from(["symcurve", "metagenome", "a.fa", "--threads", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_calibrate_command() {
+        let args = Cli::parse_from([
+            "symcurve",
+            "calibrate",
+            "curvature.bw",
+            "symcurve.bw",
+            "--truth",
+            "mnase.bw",
+            "--truth-threshold",
+            "-0.5",
+        ]);
+        match args.command {
+            Some(Command::Calibrate {
+                tracks,
+                truth,
+                truth_threshold,
+                stride,
+                weight_step,
+                output,
+                ..
+            }) => {
+                assert_eq!(tracks.len(), 2);
+                assert_eq!(truth, PathBuf::from("mnase.bw"));
+                assert_eq!(truth_threshold, Some(-0.5));
+                assert_eq!(stride, 100);
+                assert_eq!(weight_step, 0.1);
+                assert_eq!(output, PathBuf::from("calibration.json"));
+            }
+            _ => panic!("expected the calibrate command"),
+        }
+        assert!(Cli::try_parse_from(["symcurve", "calibrate", "--truth", "m.bw"]).is_err());
+        assert!(Cli::try_parse_from([
+            "symcurve",
+            "calibrate",
+            "c.bw",
+            "--truth",
+            "m.bw",
+            "--weight-step",
+            "0"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_train_command() {
+        let args = Cli::parse_from([
+            "symcurve",
+            "train",
+            "--positive",
+            "bound.fa",
+            "--negative",
+            "linker.fa",
+        ]);
+        match args.command {
+            Some(Command::Train {
+                positive,
+                negative,
+                iterations,
+                step,
+                output,
+            }) => {
+                assert_eq!(positive, PathBuf::from("bound.fa"));
+                assert_eq!(negative, PathBuf::from("linker.fa"));
+                assert_eq!(iterations, 20);
+                assert_eq!(step, 1.0);
+                assert_eq!(output, PathBuf::from("matrices.yaml"));
+            }
+            _ => panic!("expected the train command"),
+        }
+        assert!(Cli::try_parse_from(["symcurve", "train", "--positive", "b.fa"]).is_err());
+        assert!(Cli::try_parse_from([
+            "symcurve",
+            "train",
+            "--positive",
+            "b.fa",
+            "--negative",
+            "l.fa",
+            "--step",
+            "-1"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_reads_command() {
+        let args = Cli::parse_from(["symcurve", "reads", "run.bam"]);
+        match args.command {
+            Some(Command::Reads {
+                input,
+                symcurve_win,
+                symmetry_threshold,
+                threads,
+            }) => {
+                assert_eq!(input, PathBuf::from("run.bam"));
+                assert_eq!(symcurve_win, 101);
+                assert_eq!(symmetry_threshold, 0.9);
+                assert_eq!(threads, None);
+            }
+            _ => panic!("expected the reads command"),
+        }
+        assert!(Cli::try_parse_from([
+            "symcurve",
+            "reads",
+            "run.bam",
+            "--symmetry-threshold",
+            "-0.5"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_serve_command() {
+        let args = Cli::parse_from(["symcurve", "serve", "genome.fa"]);
+        match args.command {
+            Some(Command::Serve {
+                input,
+                listen,
+                max_region,
+                cache_size,
+                max_connections,
+            }) => {
+                assert_eq!(input, PathBuf::from("genome.fa"));
+                assert_eq!(listen, "127.0.0.1:8000");
+                assert_eq!(max_region, 1_000_000);
+                assert_eq!(cache_size, 64);
+                assert_eq!(max_connections, 64);
+            }
+            _ => panic!("expected the serve command"),
+        }
+        // the curvature flags given to serve are the defaults of its requests
+        let args = Cli::parse_from(["symcurve", "serve", "x.fa", "--curve-step-one", "8"]);
+        assert_eq!(args.pipeline_params().unwrap().step_b, 7);
+    }
+
+    #[test]
+    fn test_output_format() {
+        let args = Cli::parse_from(["symcurve", "in.fa", "out.bw"]);
+        assert_eq!(args.output_format, None);
+        let args = Cli::parse_from(["symcurve", "--output-format", "bedgraph", "in.fa", "out"]);
+        assert_eq!(args.output_format, Some(OutputFormat::BedGraph));
+        assert!(Cli::try_parse_from(["symcurve", "--output-format", "wig", "a.fa", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_man_command() {
+        let args = Cli::parse_from(["symcurve", "man"]);
+        assert!(matches!(args.command, Some(Command::Man { dir: None })));
+        let args = Cli::parse_from(["symcurve", "man", "--dir", "share/man/man1"]);
+        match args.command {
+            Some(Command::Man { dir }) => assert_eq!(dir, Some(PathBuf::from("share/man/man1"))),
+            _ => panic!("expected the man command"),
+        }
+    }
+
+    #[test]
+    fn test_crossval_command() {
+        let args = Cli::parse_from(["symcurve", "crossval", "genome.fa", "labels.bed"]);
+        assert_eq!(args.matrices, None);
+        match args.command {
+            Some(Command::Crossval {
+                input,
+                regions,
+                folds,
+            }) => {
+                assert_eq!(input, PathBuf::from("genome.fa"));
+                assert_eq!(regions, PathBuf::from("labels.bed"));
+                assert_eq!(folds, 5);
+            }
+            _ => panic!("expected the crossval command"),
+        }
+        assert!(Cli::try_parse_from(["symcurve", "crossval", "g.fa", "l.bed", "-k", "1"]).is_err());
+        // the matrices evaluated are given as for a run
+        let args = Cli::parse_from(["symcurve", "crossval", "g.fa", "l.bed", "-m", "m.yaml"]);
+        assert_eq!(args.matrices, Some(PathBuf::from("m.yaml")));
+    }
+
+    #[test]
+    fn test_sweep_command() {
+        let args = Cli::parse_from([
+            "symcurve",
+            "sweep",
+            "input.fasta",
+            "--param",
+            "curve-step=5..30:5",
+            "--param",
+            "curve-scale=0.3..0.4:0.05",
+            "--region",
+            "chr1:1-5000",
+        ]);
+        match args.command {
+            Some(Command::Sweep {
+                input,
+                params,
+                region,
+            }) => {
+                assert_eq!(input, PathBuf::from("input.fasta"));
+                assert_eq!(params.len(), 2);
+                assert_eq!(params[0].values.len(), 6);
+                assert_eq!(region.unwrap().end, Some(5000));
+            }
+            _ => panic!("expected the sweep command"),
+        }
+        assert!(Cli::try_parse_from(["symcurve", "sweep", "input.fasta"]).is_err());
+    }
+
+    #[test]
+    fn test_derive() {
+        let args = Cli::parse_from([
+            "symcurve",
+            "--derive",
+            "score=curve * sym",
+            "--derive",
+            "hot=curve > 5",
+            "input.fasta",
+            "output.bw",
+        ]);
+        assert_eq!(args.derive.len(), 2);
+        assert_eq!(args.derive[0].name, "score");
+        assert_eq!(args.derive[1].expr.eval(&[6.0, 0.0, 0.0]), 1.0);
+        assert!(Cli::try_parse_from([
+            "symcurve",
+            "--derive",
+            "bad=curve +",
+            "input.fasta",
+            "output.bw"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        let args = Cli::parse_from(["symcurve", "input.fasta", "output.bw"]);
+        assert!(args.filter.is_none());
+        let args = Cli::parse_from([
+            "symcurve",
+            "--filter",
+            "curve > 5 && sym > 0.3",
+            "input.fasta",
+            "output.bw",
+        ]);
+        assert!(args.filter.unwrap().is_true(&[6.0, 0.5, 0.0]));
+    }
+
+    #[test]
+    fn test_strand_divergence() {
+        let args = Cli::parse_from(["symcurve", "input.fasta", "output.bw"]);
+        assert!(!args.strand_divergence);
+        let args = Cli::parse_from([
+            "symcurve",
+            "--strand-divergence",
+            "input.fasta",
+            "output.bw",
+        ]);
+        assert!(args.strand_divergence);
+    }
+
+    #[test]
+    fn test_clip() {
+        let args = Cli::parse_from(["symcurve", "input.fasta", "output.bw"]);
+        assert!(args.clip.is_none());
+        let args = Cli::parse_from(["symcurve", "--clip", "-5:20", "input.fasta", "output.bw"]);
+        assert_eq!(
+            args.clip,
+            Some(Clip {
+                min: -5.0,
+                max: 20.0
+            })
+        );
+        assert!(
+            Cli::try_parse_from(["symcurve", "--clip", "20", "input.fasta", "output.bw"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_background() {
+        let args = Cli::parse_from(["symcurve", "input.fasta", "output.bw"]);
+        assert!(args.background.is_none());
+        let args = Cli::parse_from([
+            "symcurve",
+            "--background",
+            "shuffled.bedGraph",
+            "input.fasta",
+            "output.bw",
+        ]);
+        assert_eq!(
+            args.background.unwrap().to_str().unwrap(),
+            "shuffled.bedGraph"
+        );
+    }
+
+    #[test]
+    fn test_composition_correction() {
+        let args = Cli::parse_from(["symcurve", "input.fasta", "output.bw"]);
+        assert!(args.composition_correction.is_none());
+        let args = Cli::parse_from([
+            "symcurve",
+            "--composition-correction",
+            "dinucleotide",
+            "input.fasta",
+            "output.bw",
+        ]);
+        assert_eq!(args.composition_correction, Some(Covariates::Dinucleotide));
+        assert!(Cli::try_parse_from([
+            "symcurve",
+            "--composition-correction",
+            "at",
+            "input.fasta",
+            "output.bw"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_track_line() {
+        let args = Cli::parse_from(["symcurve", "input.fasta", "output.bedgraph"]);
+        assert_eq!(args.track_line(), TrackLine::default());
+        let args = Cli::parse_from([
+            "symcurve",
+            "--track-name",
+            "curvature",
+            "--track-color",
+            "0,0,255",
+            "--view-limits",
+            "-2:10",
+            "--auto-scale",
+            "off",
+            "input.fasta",
+            "output.bedgraph",
+        ]);
+        assert_eq!(
+            args.track_line().format("bedGraph"),
+            "track type=bedGraph name=\"curvature\" color=0,0,255 viewLimits=-2:10 autoScale=off"
+        );
+        assert!(Cli::try_parse_from([
+            "symcurve",
+            "--track-color",
+            "blue",
+            "input.fasta",
+            "output.bedgraph"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_chunking() {
+        let args = Cli::parse_from(["symcurve", "input.fasta", "output.bw"]);
+        assert!(!args.chunked);
+        assert!(args.chunk_size.is_none());
+        let args = Cli::parse_from([
+            "symcurve",
+            "--chunked",
+            "--chunk-size",
+            "100000",
+            "input.fasta",
+            "output.bw",
+        ]);
+        assert_eq!(args.chunk_size, Some(100000));
+        let plan = args.pipeline_params().unwrap().chunking.unwrap();
+        assert_eq!(plan.size, 100000);
+        assert!(Cli::try_parse_from([
+            "symcurve",
+            "--chunk-size",
+            "100000",
+            "input.fasta",
+            "output.bw"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_max_memory() {
+        let args = Cli::parse_from(["symcurve", "input.fasta", "output.bw"]);
+        assert!(args.max_memory.is_none());
+        let args = Cli::parse_from(["symcurve", "--max-memory", "2G", "input.fasta", "output.bw"]);
+        assert_eq!(args.max_memory, Some(2 << 30));
+        assert!(Cli::try_parse_from([
+            "symcurve",
+            "--max-memory",
+            "lots",
+            "input.fasta",
+            "output.bw"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_warning_limit() {
+        let args = Cli::parse_from(["symcurve", "a.fa", "b.bw"]);
+        assert_eq!(args.warning_limit(), Some(10));
+        let args = Cli::parse_from(["symcurve", "--warning-limit", "0", "a.fa", "b.bw"]);
+        assert_eq!(args.warning_limit(), None);
+    }
+
+    #[test]
+    fn test_regions() {
+        let args = Cli::parse_from(["symcurve", "a.fa", "b.bw"]);
+        assert!(args.pipeline_params().unwrap().regions.is_empty());
+        let args = Cli::parse_from([
+            "symcurve",
+            "--regions",
+            "chr1:1,000,000-2,000,000",
+            "--regions",
+            "chr2",
+            "a.fa",
+            "b.bw",
+        ]);
+        let regions = args.pipeline_params().unwrap().regions;
+        assert_eq!(regions.len(), 2);
+        assert_eq!(
+            (regions[0].start, regions[0].end),
+            (999_999, Some(2_000_000))
+        );
+        assert_eq!((regions[1].chrom.as_str(), regions[1].end), ("chr2", None));
+        assert!(Cli::try_parse_from([
+            "symcurve",
+            "--regions",
+            "chr1:1-10",
+            "--symcurve-track",
+            "a.fa",
+            "b.bw"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_roll_type() {
+        let args = Cli::parse_from(["symcurve", "a.fa", "b.bw"]);
+        assert!(matches!(
+            args.pipeline_params().unwrap().roll_type,
+            RollType::Simple
+        ));
+        let args = Cli::parse_from(["symcurve", "--roll-type", "active", "a.fa", "b.bw"]);
+        assert!(matches!(
+            args.pipeline_params().unwrap().roll_type,
+            RollType::Active
+        ));
+        assert!(args
+            .parameters()
+            .contains(&("roll_type".to_string(), "active".to_string())));
+        assert!(
+            Cli::try_parse_from(["symcurve", "--roll-type", "custom", "a.fa", "b.bw"]).is_err()
+        );
+        // a matrices file brings its own roll matrix
+        assert!(Cli::try_parse_from([
+            "symcurve",
+            "--roll-type",
+            "active",
+            "-m",
+            "m.yaml",
+            "a.fa",
+            "b.bw"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_span() {
+        let args = Cli::parse_from(["symcurve", "a.fa", "b.bw"]);
+        assert_eq!((args.span, args.aggregate), (1, Aggregate::Mean));
+        let args = Cli::parse_from([
+            "symcurve",
+            "--span",
+            "10",
+            "--aggregate",
+            "max",
+            "a.fa",
+            "b.bw",
+        ]);
+        let params = args.pipeline_params().unwrap();
+        assert_eq!((params.span, params.aggregate), (10, Aggregate::Max));
+        assert!(Cli::try_parse_from(["symcurve", "--span", "0", "a.fa", "b.bw"]).is_err());
+    }
+
+    #[test]
+    fn test_version() {
+        let args = Cli::parse_from(["symcurve", "-V", "--verbose"]);
+        assert!(args.version && args.verbose);
+        assert_eq!(args.input, None);
+    }
+
+    #[test]
+    fn test_self_test() {
+        let args = Cli::parse_from(["symcurve", "--self-test"]);
+        assert!(args.self_test);
+        assert!(args.input.is_none());
+        assert!(Cli::try_parse_from(["symcurve"]).is_err());
+        assert!(Cli::try_parse_from(["symcurve", "input.fasta"]).is_err());
+    }
+
+    #[test]
+    fn test_anchor() {
+        let args = Cli::parse_from(["symcurve", "input.fasta", "output.bw", "--anchor", "start"]);
+        assert_eq!(args.anchor, Anchor::Start);
+        let args_result =
+            Cli::try_parse_from(["symcurve", "input.fasta", "output.bw", "--anchor", "end"]);
+        assert!(args_result.is_err());
     }
 
     #[test]
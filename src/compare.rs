@@ -0,0 +1,311 @@
+//! # Compare module
+//! The `symcurve compare` subcommand, which computes curvature on two genomes and writes the
+//! difference over the regions a UCSC chain file aligns between them, for studies of how well
+//! curvature is conserved.
+//!
+//! Chains map target (reference) coordinates to query coordinates in ungapped blocks. For every
+//! target base in a block the output row holds the target value, the query value at the aligned
+//! base and their difference, `query - target`. Bases without a value in either genome are left
+//! out. Where the query aligns on the minus strand, the query value is that of the forward-strand
+//! base at the mirrored position; the curvature of a reverse complement is close to, but not
+//! exactly, the forward curvature read backwards (see `--strand-divergence`).
+
+use crate::fasta::StreamingReader;
+use crate::pipeline::{position_values, PipelineParams};
+use crate::text::clean_line;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+/// One ungapped aligned block of a chain.
+///
+/// # Fields
+///
+/// * `t_start`: The 0-based start of the block on the target.
+/// * `q_start`: The 0-based start of the block on the query, on the query's aligned strand.
+/// * `size`: The number of aligned bases.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Block {
+    pub t_start: u64,
+    pub q_start: u64,
+    pub size: u64,
+}
+
+/// An alignment chain between a target and a query sequence.
+///
+/// # Fields
+///
+/// * `t_name`: The target sequence name.
+/// * `q_name`: The query sequence name.
+/// * `q_size`: The length of the query sequence.
+/// * `q_reverse`: Whether the query aligns on its minus strand.
+/// * `blocks`: The aligned blocks, in order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chain {
+    pub t_name: String,
+    pub q_name: String,
+    pub q_size: u64,
+    pub q_reverse: bool,
+    pub blocks: Vec<Block>,
+}
+
+impl Chain {
+    /// The aligned base pairs, as 0-based forward-strand `(target, query)` positions.
+    pub fn pairs(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.blocks.iter().flat_map(move |block| {
+            (0..block.size).map(move |i| {
+                let q = block.q_start + i;
+                let q = if self.q_reverse {
+                    // past the end of the query, which write_differential reports
+                    self.q_size.checked_sub(q + 1).unwrap_or(u64::MAX)
+                } else {
+                    q
+                };
+                (block.t_start + i, q)
+            })
+        })
+    }
+}
+
+/// Builds an `InvalidData` error for a chain file line.
+fn chain_error(line: usize, why: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("chain line {}: {}", line, why),
+    )
+}
+
+/// Reads the chains of a UCSC chain file.
+///
+/// # Errors
+///
+/// Returns an `io::ErrorKind::InvalidData` error naming the line if the file is malformed.
+pub fn read_chains<R: BufRead>(reader: R) -> io::Result<Vec<Chain>> {
+    let mut chains: Vec<Chain> = Vec::new();
+    // the target and query positions of the next block of the current chain
+    let mut next: Option<(u64, u64)> = None;
+    for (number, line) in reader.lines().enumerate() {
+        let (line, number) = (line?, number + 1);
+        let fields: Vec<&str> = clean_line(&line).split_whitespace().collect();
+        let parse = |field: &str| {
+            field
+                .parse::<u64>()
+                .map_err(|_| chain_error(number, &format!("'{}' is not a number", field)))
+        };
+        match fields.as_slice() {
+            [] => continue,
+            [first, ..] if first.starts_with('#') => continue,
+            ["chain", _, t_name, _, "+", t_start, _, q_name, q_size, q_strand, q_start, ..] => {
+                chains.push(Chain {
+                    t_name: t_name.to_string(),
+                    q_name: q_name.to_string(),
+                    q_size: parse(q_size)?,
+                    q_reverse: *q_strand == "-",
+                    blocks: Vec::new(),
+                });
+                next = Some((parse(t_start)?, parse(q_start)?));
+            }
+            ["chain", ..] => {
+                return Err(chain_error(
+                    number,
+                    "expected a header with 12 fields and target strand +",
+                ))
+            }
+            [size, gaps @ ..] if gaps.is_empty() || gaps.len() == 2 => {
+                let chain = chains.last_mut();
+                let (Some(chain), Some((t, q))) = (chain, next) else {
+                    return Err(chain_error(number, "alignment data outside a chain"));
+                };
+                let size = parse(size)?;
+                chain.blocks.push(Block {
+                    t_start: t,
+                    q_start: q,
+                    size,
+                });
+                next = match gaps {
+                    [dt, dq] => Some((t + size + parse(dt)?, q + size + parse(dq)?)),
+                    // the last block ends the chain
+                    _ => None,
+                };
+            }
+            _ => return Err(chain_error(number, "expected 1 or 3 fields")),
+        }
+    }
+    Ok(chains)
+}
+
+/// Computes per-base curvature for the sequences of a FASTA file that are named in `names`.
+pub fn genome_values<R: BufRead>(
+    reader: R,
+    names: &HashSet<&str>,
+    params: &PipelineParams,
+) -> io::Result<HashMap<String, Vec<f64>>> {
+    let mut fasta = StreamingReader::new(reader);
+    let mut values = HashMap::new();
+    while let Some(name) = fasta.next_record()? {
+        if !names.contains(name.as_str()) {
+            continue;
+        }
+        let seq = fasta.bases().collect::<io::Result<Vec<u8>>>()?;
+        values.insert(name, position_values(&seq, params));
+    }
+    Ok(values)
+}
+
+/// Writes the differential curvature rows of the aligned bases of a set of chains.
+///
+/// Rows are tab-separated: target `chrom`, 1-based target position, target value, query value and
+/// `query - target`, with a header.
+///
+/// # Errors
+///
+/// Returns an `io::ErrorKind::InvalidData` error if a chain reaches past the end of a sequence.
+pub fn write_differential<W: Write>(
+    writer: &mut W,
+    chains: &[Chain],
+    target: &HashMap<String, Vec<f64>>,
+    query: &HashMap<String, Vec<f64>>,
+    params: &PipelineParams,
+) -> io::Result<()> {
+    writeln!(writer, "chrom\tpos\ttarget\tquery\tdifference")?;
+    let float = params.float_format;
+    for chain in chains {
+        let (Some(t_values), Some(q_values)) =
+            (target.get(&chain.t_name), query.get(&chain.q_name))
+        else {
+            continue;
+        };
+        for (t, q) in chain.pairs() {
+            let (Some(&tv), Some(&qv)) = (t_values.get(t as usize), q_values.get(q as usize))
+            else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "chain {} to {} reaches past the end of a sequence",
+                        chain.t_name, chain.q_name
+                    ),
+                ));
+            };
+            if tv.is_nan() || qv.is_nan() {
+                continue;
+            }
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}",
+                chain.t_name,
+                t + 1,
+                float.format(tv),
+                float.format(qv),
+                float.format(qv - tv)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs `symcurve compare`.
+///
+/// # Arguments
+///
+/// * `target` - The target (reference) genome FASTA.
+/// * `query` - The query genome FASTA.
+/// * `chain` - The chain file mapping target to query.
+/// * `params` - The parameters curvature is computed with.
+/// * `writer` - Where the rows are written.
+pub fn compare<W: Write>(
+    target: &Path,
+    query: &Path,
+    chain: &Path,
+    params: &PipelineParams,
+    writer: &mut W,
+) -> io::Result<()> {
+    let chains = read_chains(BufReader::new(File::open(chain)?))?;
+    let t_names = chains.iter().map(|c| c.t_name.as_str()).collect();
+    let q_names = chains.iter().map(|c| c.q_name.as_str()).collect();
+    let t_values = genome_values(BufReader::new(File::open(target)?), &t_names, params)?;
+    let q_values = genome_values(BufReader::new(File::open(query)?), &q_names, params)?;
+    write_differential(writer, &chains, &t_values, &q_values, params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHAIN: &str = "\
+chain 1000 chr1 100 + 10 38 chrA 80 + 0 26 1
+10 5 3
+13
+
+chain 500 chr1 100 + 50 54 chrB 10 - 2 6 2
+4
+";
+
+    #[test]
+    fn test_read_chains() {
+        let chains = read_chains(CHAIN.as_bytes()).unwrap();
+        assert_eq!(chains.len(), 2);
+        assert_eq!(
+            chains[0].blocks,
+            vec![
+                Block {
+                    t_start: 10,
+                    q_start: 0,
+                    size: 10
+                },
+                Block {
+                    t_start: 25,
+                    q_start: 13,
+                    size: 13
+                },
+            ]
+        );
+        assert!(chains[1].q_reverse);
+        let pairs: Vec<(u64, u64)> = chains[1].pairs().collect();
+        assert_eq!(pairs, vec![(50, 7), (51, 6), (52, 5), (53, 4)]);
+        assert!(read_chains("10 5 3\n".as_bytes()).is_err());
+        let err =
+            read_chains("chain 1 chr1 100 + 0 10 chrA 80 + 0 10 1\n1 2\n".as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn test_write_differential() {
+        let chains = read_chains("chain 1 chr1 6 + 1 4 chrA 5 - 0 3 1\n3\n".as_bytes()).unwrap();
+        let target = HashMap::from([("chr1".to_string(), vec![0.0, 1.0, 2.0, f64::NAN, 4.0, 5.0])]);
+        let query = HashMap::from([("chrA".to_string(), vec![9.0, 9.0, 3.5, 2.0, 1.5])]);
+        let mut out = Vec::new();
+        write_differential(
+            &mut out,
+            &chains,
+            &target,
+            &query,
+            &PipelineParams::default(),
+        )
+        .unwrap();
+        // target 1..4 aligns to query 4, 3, 2 on the minus strand; target 3 has no value
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "chrom\tpos\ttarget\tquery\tdifference\nchr1\t2\t1\t1.5\t0.5\nchr1\t3\t2\t2\t0\n"
+        );
+        let short = HashMap::from([("chrA".to_string(), vec![1.0])]);
+        let mut out = Vec::new();
+        assert!(write_differential(
+            &mut out,
+            &chains,
+            &target,
+            &short,
+            &PipelineParams::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_genome_values() {
+        let fasta = b">chr1\nACGT\n>chr2\nACGTACGT\n";
+        let names = HashSet::from(["chr2"]);
+        let values = genome_values(&fasta[..], &names, &PipelineParams::default()).unwrap();
+        assert_eq!(values.len(), 1);
+        assert_eq!(values["chr2"].len(), 8);
+    }
+}
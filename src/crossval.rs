@@ -0,0 +1,457 @@
+//! # Crossval module
+//! The `symcurve crossval` subcommand, which measures how well a set of matrices tells labeled
+//! regions apart by curvature, with k-fold cross-validation over chromosomes.
+//!
+//! Each region of a BED file labeled positive or negative is scored by its mean curvature. The
+//! chromosomes are dealt into `--folds` folds in name order, so that no fold is evaluated with a
+//! threshold chosen on regions of its own chromosomes. For each fold, the threshold with the
+//! highest F1 score on the other folds calls the held-out regions, and the area under the ROC
+//! curve, accuracy, precision, recall and F1 are reported per fold and as mean and standard
+//! deviation over the folds. Running it once per matrix file compares parameter sets on the same
+//! folds.
+//!
+//! Only the roll matrix of a matrix file changes the curvature for now; its twist and tilt
+//! sections are validated but the built-in ones are used.
+
+use crate::calibrate::{CurvePoint, Curves};
+use crate::curve::matrix::{Matrices, NucMatrix};
+use crate::fasta::StreamingReader;
+use crate::stats::streaming::RunningStats;
+use crate::text::clean_line;
+use crate::train::mean_curvature;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The settings of a cross-validation run.
+///
+/// # Fields
+///
+/// * `input`: The FASTA file the regions are on.
+/// * `regions`: The BED file of labeled regions.
+/// * `matrices`: The YAML matrix file evaluated, or `None` for the built-in matrices.
+/// * `folds`: The number of folds.
+#[derive(Clone, Debug)]
+pub struct CrossvalParams {
+    pub input: PathBuf,
+    pub regions: PathBuf,
+    pub matrices: Option<PathBuf>,
+    pub folds: usize,
+}
+
+/// A region with its label.
+///
+/// # Fields
+///
+/// * `chrom`: The chromosome name.
+/// * `start`: The 0-based start.
+/// * `end`: The exclusive end.
+/// * `positive`: Whether the region is labeled positive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LabeledRegion {
+    pub chrom: String,
+    pub start: u64,
+    pub end: u64,
+    pub positive: bool,
+}
+
+/// Parses the label of a region: `1`, `positive` or `pos` for positive and `0`, `negative` or
+/// `neg` for negative, in any case.
+fn parse_label(label: &str) -> Option<bool> {
+    match label.to_ascii_lowercase().as_str() {
+        "1" | "positive" | "pos" => Some(true),
+        "0" | "negative" | "neg" => Some(false),
+        _ => None,
+    }
+}
+
+/// Reads labeled regions from BED text with the label in the fourth column. `track`, `browser`
+/// and `#` lines are skipped.
+///
+/// # Errors
+///
+/// Returns an `io::ErrorKind::InvalidData` error naming the line if it has fewer than four
+/// columns, bad coordinates or an unknown label.
+pub fn read_labeled_regions<R: BufRead>(reader: R) -> io::Result<Vec<LabeledRegion>> {
+    let mut regions = Vec::new();
+    for (number, line) in (1..).zip(reader.lines()) {
+        let line = line?;
+        let trimmed = clean_line(&line).trim_start();
+        if trimmed.is_empty()
+            || trimmed.starts_with('#')
+            || trimmed.starts_with("track")
+            || trimmed.starts_with("browser")
+        {
+            continue;
+        }
+        let invalid = |message: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", number, message),
+            )
+        };
+        let fields: Vec<&str> = trimmed.split('\t').collect();
+        if fields.len() < 4 {
+            return Err(invalid("expected chrom, start, end and label columns"));
+        }
+        let start: u64 = fields[1].parse().map_err(|_| invalid("bad start"))?;
+        let end: u64 = fields[2].parse().map_err(|_| invalid("bad end"))?;
+        if end <= start {
+            return Err(invalid("the end must be after the start"));
+        }
+        let positive = parse_label(fields[3])
+            .ok_or_else(|| invalid("the label must be 1/positive or 0/negative"))?;
+        regions.push(LabeledRegion {
+            chrom: fields[0].to_string(),
+            start,
+            end,
+            positive,
+        });
+    }
+    Ok(regions)
+}
+
+/// Scores every region by its mean curvature, reading the FASTA file one record at a time.
+///
+/// # Returns
+///
+/// The scores in the order of `regions`; `None` for regions on a sequence that is not in the file
+/// or too short for a curvature value.
+pub fn score_regions<R: BufRead>(
+    reader: R,
+    regions: &[LabeledRegion],
+    roll: &Arc<NucMatrix>,
+) -> io::Result<Vec<Option<f64>>> {
+    let mut by_chrom: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, region) in regions.iter().enumerate() {
+        by_chrom.entry(&region.chrom).or_default().push(i);
+    }
+    let mut scores = vec![None; regions.len()];
+    let mut fasta = StreamingReader::new(reader);
+    while let Some(name) = fasta.next_record()? {
+        let Some(indexes) = by_chrom.get(name.as_str()) else {
+            continue;
+        };
+        let last = indexes.iter().map(|&i| regions[i].end).max().unwrap_or(0);
+        let mut bases = Vec::new();
+        for base in fasta.bases().take(last as usize) {
+            bases.push(base?.to_ascii_uppercase());
+        }
+        for &i in indexes {
+            let region = &regions[i];
+            let end = (region.end as usize).min(bases.len());
+            let start = (region.start as usize).min(end);
+            scores[i] = mean_curvature(&bases[start..end], roll);
+        }
+    }
+    Ok(scores)
+}
+
+/// Deals chromosomes into folds in name order.
+///
+/// # Returns
+///
+/// The fold of each chromosome.
+pub fn assign_folds<'a, I>(chroms: I, folds: usize) -> BTreeMap<&'a str, usize>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut assigned: BTreeMap<&str, usize> = chroms.into_iter().map(|c| (c, 0)).collect();
+    for (i, fold) in assigned.values_mut().enumerate() {
+        *fold = i % folds;
+    }
+    assigned
+}
+
+/// The metrics of one held-out fold.
+///
+/// # Fields
+///
+/// * `chromosomes`: The number of chromosomes in the fold.
+/// * `regions`: The number of scored regions in the fold.
+/// * `threshold`: The threshold chosen on the other folds.
+/// * `auc_roc`: The area under the ROC curve of the fold, NaN if it has only one label.
+/// * `accuracy`: The fraction of regions called correctly at the threshold.
+/// * `precision`: The precision at the threshold.
+/// * `recall`: The recall at the threshold.
+/// * `f1`: The F1 score at the threshold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FoldMetrics {
+    pub chromosomes: usize,
+    pub regions: usize,
+    pub threshold: f64,
+    pub auc_roc: f64,
+    pub accuracy: f64,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+}
+
+impl FoldMetrics {
+    /// The metric values in report column order, after the counts and threshold.
+    fn values(&self) -> [f64; 5] {
+        [
+            self.auc_roc,
+            self.accuracy,
+            self.precision,
+            self.recall,
+            self.f1,
+        ]
+    }
+}
+
+/// Evaluates scored, labeled regions fold by fold.
+///
+/// # Arguments
+///
+/// * `scores` - The score of each region.
+/// * `labels` - Whether each region is positive.
+/// * `folds` - The fold of each region.
+/// * `chromosomes` - The number of chromosomes of each fold.
+///
+/// # Returns
+///
+/// The metrics of each fold, or `None` for a fold whose other folds lack either label.
+pub fn evaluate(
+    scores: &[f64],
+    labels: &[bool],
+    folds: &[usize],
+    chromosomes: &[usize],
+) -> Vec<Option<FoldMetrics>> {
+    (0..chromosomes.len())
+        .map(|fold| {
+            let pick = |held_out: bool| -> (Vec<f64>, Vec<bool>) {
+                (0..scores.len())
+                    .filter(|&i| (folds[i] == fold) == held_out)
+                    .map(|i| (scores[i], labels[i]))
+                    .unzip()
+            };
+            let (train_scores, train_labels) = pick(false);
+            let training = Curves::new(&train_scores, &train_labels);
+            if training.positives == 0 || training.negatives == 0 {
+                return None;
+            }
+            let threshold = training.best_point()?.threshold;
+            let (test_scores, test_labels) = pick(true);
+            let test = Curves::new(&test_scores, &test_labels);
+            let (mut tp, mut fp, mut correct) = (0u64, 0u64, 0usize);
+            for (&score, &label) in test_scores.iter().zip(&test_labels) {
+                let called = score >= threshold;
+                tp += (called && label) as u64;
+                fp += (called && !label) as u64;
+                correct += (called == label) as usize;
+            }
+            let point = CurvePoint {
+                threshold,
+                true_positives: tp,
+                false_positives: fp,
+            };
+            let auc_roc = if test.positives > 0 && test.negatives > 0 {
+                test.auc_roc()
+            } else {
+                f64::NAN
+            };
+            Some(FoldMetrics {
+                chromosomes: chromosomes[fold],
+                regions: test_scores.len(),
+                threshold,
+                auc_roc,
+                accuracy: correct as f64 / test_scores.len().max(1) as f64,
+                precision: Curves::precision(&point),
+                recall: test.recall(&point),
+                f1: test.f1(&point),
+            })
+        })
+        .collect()
+}
+
+/// Writes the cross-validation report: a TSV row per fold, then the mean and standard deviation
+/// of each metric over the folds that could be evaluated. Metrics that are NaN for a fold are
+/// left out of its mean.
+pub fn write_report<W: Write>(metrics: &[Option<FoldMetrics>], writer: &mut W) -> io::Result<()> {
+    writeln!(
+        writer,
+        "fold\tchromosomes\tregions\tthreshold\tauc_roc\taccuracy\tprecision\trecall\tf1"
+    )?;
+    let mut summary: Vec<RunningStats> = (0..5).map(|_| RunningStats::new()).collect();
+    for (fold, fold_metrics) in (1..).zip(metrics) {
+        let Some(m) = fold_metrics else {
+            writeln!(writer, "{}\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tNA", fold)?;
+            continue;
+        };
+        write!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            fold, m.chromosomes, m.regions, m.threshold
+        )?;
+        for (stats, value) in summary.iter_mut().zip(m.values()) {
+            write!(writer, "\t{}", value)?;
+            if value.is_finite() {
+                stats.add(value);
+            }
+        }
+        writeln!(writer)?;
+    }
+    for (label, sd) in [("mean", false), ("sd", true)] {
+        write!(writer, "{}\t\t\t", label)?;
+        for stats in &summary {
+            let value = if sd { stats.sd() } else { stats.mean() };
+            write!(writer, "\t{}", value)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Runs `symcurve crossval`.
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be read, the matrix file or regions are invalid, no region
+/// can be scored, or there are fewer chromosomes with scored regions than folds.
+pub fn crossval<W: Write>(params: &CrossvalParams, writer: &mut W) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let matrices = match &params.matrices {
+        Some(path) => Matrices::read_yaml(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?,
+        None => Matrices::default(),
+    };
+    let regions = read_labeled_regions(BufReader::new(File::open(&params.regions)?))?;
+    let roll = Arc::new(matrices.roll);
+    let scores = score_regions(BufReader::new(File::open(&params.input)?), &regions, &roll)?;
+    let scored: Vec<(&LabeledRegion, f64)> = regions
+        .iter()
+        .zip(&scores)
+        .filter_map(|(region, score)| score.map(|s| (region, s)))
+        .collect();
+    if scored.is_empty() {
+        return Err(invalid("no region could be scored".to_string()));
+    }
+    let fold_of = assign_folds(scored.iter().map(|(r, _)| r.chrom.as_str()), params.folds);
+    if fold_of.len() < params.folds {
+        return Err(invalid(format!(
+            "{} folds need at least as many chromosomes with scored regions, found {}",
+            params.folds,
+            fold_of.len()
+        )));
+    }
+    let mut chromosomes = vec![0; params.folds];
+    for &fold in fold_of.values() {
+        chromosomes[fold] += 1;
+    }
+    let folds: Vec<usize> = scored
+        .iter()
+        .map(|(r, _)| fold_of[r.chrom.as_str()])
+        .collect();
+    let labels: Vec<bool> = scored.iter().map(|(r, _)| r.positive).collect();
+    let values: Vec<f64> = scored.iter().map(|&(_, s)| s).collect();
+    let skipped = regions.len() - scored.len();
+    if skipped > 0 {
+        eprintln!(
+            "warning: {} of {} regions were not scored: their sequence is missing or too short",
+            skipped,
+            regions.len()
+        );
+    }
+    write_report(&evaluate(&values, &labels, &folds, &chromosomes), writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::matrix::ROLL_SIMPLE;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_read_labeled_regions() {
+        let bed = "track name=labels\n# comment\nchr1\t0\t100\tpositive\nchr2\t5\t50\t0\textra\n";
+        let regions = read_labeled_regions(bed.as_bytes()).unwrap();
+        assert_eq!(regions.len(), 2);
+        assert!(regions[0].positive);
+        assert_eq!((regions[1].start, regions[1].end), (5, 50));
+        assert!(!regions[1].positive);
+        let err = read_labeled_regions("chr1\t0\t10\tmaybe\n".as_bytes()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 1: the label must be 1/positive or 0/negative"
+        );
+        assert!(read_labeled_regions("chr1\t10\t10\t1\n".as_bytes()).is_err());
+        assert!(read_labeled_regions("chr1\t0\t10\n".as_bytes()).is_err());
+        let bed = "\u{feff}chr1\t0\t100\tpos \r\n\r\n";
+        let regions = read_labeled_regions(bed.as_bytes()).unwrap();
+        assert_eq!(
+            (regions[0].chrom.as_str(), regions[0].positive),
+            ("chr1", true)
+        );
+    }
+
+    #[test]
+    fn test_assign_folds() {
+        let folds = assign_folds(["chr2", "chr1", "chr3", "chr1"], 2);
+        assert_eq!(folds.len(), 3);
+        assert_eq!(folds["chr1"], 0);
+        assert_eq!(folds["chr2"], 1);
+        assert_eq!(folds["chr3"], 0);
+    }
+
+    #[test]
+    fn test_score_regions() {
+        let fasta = format!(">chr1\n{}\n>chr2\nACGT\n", "AAAATTTTGC".repeat(10));
+        let regions = vec![
+            LabeledRegion {
+                chrom: "chr1".to_string(),
+                start: 0,
+                end: 60,
+                positive: true,
+            },
+            LabeledRegion {
+                chrom: "chr2".to_string(),
+                start: 0,
+                end: 4,
+                positive: false,
+            },
+            LabeledRegion {
+                chrom: "chr3".to_string(),
+                start: 0,
+                end: 60,
+                positive: false,
+            },
+        ];
+        let roll = Arc::new(ROLL_SIMPLE);
+        let scores = score_regions(fasta.as_bytes(), &regions, &roll).unwrap();
+        let bases = "AAAATTTTGC".repeat(6);
+        assert_eq!(scores[0], mean_curvature(bases.as_bytes(), &roll));
+        assert!(scores[0].is_some());
+        assert_eq!(scores[1..], [None, None]);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let scores = [0.9, 0.8, 0.2, 0.1, 0.7, 0.6, 0.3, 0.65];
+        let labels = [true, true, false, false, true, true, false, false];
+        let folds = [0, 0, 0, 0, 1, 1, 1, 1];
+        let metrics = evaluate(&scores, &labels, &folds, &[1, 1]);
+        let first = metrics[0].unwrap();
+        // the threshold is chosen on the second fold alone
+        assert_eq!(first.threshold, 0.6);
+        assert_eq!(first.regions, 4);
+        assert_relative_eq!(first.auc_roc, 1.0);
+        assert_relative_eq!(first.accuracy, 1.0);
+        let second = metrics[1].unwrap();
+        assert_eq!(second.threshold, 0.8);
+        assert_relative_eq!(second.accuracy, 0.5);
+        assert_relative_eq!(second.recall, 0.0);
+        let mut out = Vec::new();
+        write_report(&metrics, &mut out).unwrap();
+        let report = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[1].starts_with("1\t1\t4\t0.6\t1\t1\t1\t1\t1"));
+        assert!(lines[3].starts_with("mean\t\t\t\t"));
+        // a fold whose other folds have one label cannot be evaluated
+        let metrics = evaluate(&[0.1, 0.2], &[true, false], &[0, 1], &[1, 1]);
+        assert_eq!(metrics, vec![None, None]);
+    }
+}
@@ -2,7 +2,14 @@
 //! This module contains functions for calculation of DNA curvature, and is divided into
 //! several submodules.
 
+pub mod composition;
+pub mod divergence;
+pub mod ensemble;
+pub mod envelope;
 #[allow(dead_code)]
 pub mod iters;
 #[allow(dead_code)]
 pub mod matrix;
+pub mod memo;
+pub mod metric;
+pub mod symmetry;
@@ -0,0 +1,444 @@
+//! Composition bias correction of curvature.
+//!
+//! Raw curvature strongly tracks sequence composition: AT-rich stretches bend more than GC-rich
+//! ones, whatever their arrangement. `--composition-correction` fits a linear regression of
+//! curvature on the composition of the window behind each value, learned from the input itself,
+//! and writes the residuals, leaving the curvature composition alone does not explain.
+//!
+//! Composition is measured over the bases behind a value in drop mode, `2 * flank + 1` bases
+//! centered on it, clipped to the sequence piece near its ends.
+
+use crate::curve::iters::{CurveIter, EdgeMode};
+use crate::pipeline::{pieces, PipelineParams};
+use clap::ValueEnum;
+
+/// The composition covariates curvature is regressed on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Covariates {
+    /// the GC fraction of the window
+    Gc,
+    /// the frequencies of the 16 dinucleotides in the window
+    Dinucleotide,
+}
+
+impl Covariates {
+    /// The number of regression coefficients, including the intercept.
+    ///
+    /// Dinucleotide frequencies sum to 1, so the last one (`TT`) is left out to keep the fit
+    /// determined.
+    pub fn coefficients(&self) -> usize {
+        match self {
+            Covariates::Gc => 2,
+            Covariates::Dinucleotide => 16,
+        }
+    }
+}
+
+/// The index of a base in `ACGT` order.
+fn base_index(base: u8) -> usize {
+    match base {
+        b'A' => 0,
+        b'C' => 1,
+        b'G' => 2,
+        _ => 3,
+    }
+}
+
+/// Prefix counts of the composition of a sequence piece, for the composition of any window in
+/// constant time.
+///
+/// # Fields
+///
+/// * `covariates`: The covariates counted.
+/// * `counts`: For each covariate, the number of occurrences before each position.
+struct PrefixCounts {
+    covariates: Covariates,
+    counts: Vec<Vec<u32>>,
+}
+
+impl PrefixCounts {
+    /// Counts the composition of a piece of `A`, `C`, `G` and `T` bases.
+    fn new(piece: &[u8], covariates: Covariates) -> Self {
+        let kinds = match covariates {
+            Covariates::Gc => 1,
+            Covariates::Dinucleotide => 16,
+        };
+        let mut counts = vec![vec![0u32; piece.len() + 1]; kinds];
+        for (i, &base) in piece.iter().enumerate() {
+            let kind = match covariates {
+                Covariates::Gc => matches!(base, b'C' | b'G').then_some(0),
+                // dinucleotides are counted at their first base
+                Covariates::Dinucleotide => piece
+                    .get(i + 1)
+                    .map(|&next| 4 * base_index(base) + base_index(next)),
+            };
+            for (k, count) in counts.iter_mut().enumerate() {
+                count[i + 1] = count[i] + u32::from(kind == Some(k));
+            }
+        }
+        Self { covariates, counts }
+    }
+
+    /// The covariates of the window `start..end`, led by the intercept term.
+    fn features(&self, start: usize, end: usize) -> Vec<f64> {
+        let mut features = Vec::with_capacity(self.covariates.coefficients());
+        features.push(1.0);
+        let (end, kinds) = match self.covariates {
+            Covariates::Gc => (end, 1),
+            Covariates::Dinucleotide => (end.saturating_sub(1).max(start), 15),
+        };
+        let total = (end - start).max(1) as f64;
+        for count in &self.counts[..kinds] {
+            features.push(f64::from(count[end] - count[start]) / total);
+        }
+        features
+    }
+}
+
+/// The covariates behind each curvature value of a sequence piece.
+///
+/// # Arguments
+///
+/// * `piece` - A piece of `A`, `C`, `G` and `T` bases.
+/// * `count` - The number of curvature values computed for the piece.
+/// * `params` - The parameters the values were computed with.
+/// * `covariates` - The covariates to measure.
+fn piece_features(
+    piece: &[u8],
+    count: usize,
+    params: &PipelineParams,
+    covariates: Covariates,
+) -> impl Iterator<Item = Vec<f64>> {
+    let len = piece.len();
+    let prefix = PrefixCounts::new(piece, covariates);
+    let offset = params.flank();
+    let extent = CurveIter::<std::iter::Empty<u8>>::rolling_extent(
+        params.step_b,
+        params.step_two,
+        params.convention,
+    );
+    let half = CurveIter::<std::iter::Empty<u8>>::flank(extent, params.step_c, EdgeMode::Drop);
+    (0..count).map(move |i| {
+        let center = i + offset;
+        let start = center.saturating_sub(half);
+        let end = (center + half + 1).min(len);
+        prefix.features(start, end)
+    })
+}
+
+/// The gap-free pieces of a record, uppercased, each with the range of the record's per-position
+/// values that hold its curvature values in order.
+fn record_pieces<'a>(
+    bases: &'a [u8],
+    len: usize,
+    params: &'a PipelineParams,
+) -> impl Iterator<Item = (Vec<u8>, std::ops::Range<usize>)> + 'a {
+    let flank = params.flank();
+    pieces(bases).map(move |(start, piece)| {
+        let first = start + params.anchor.position(0, flank as u64) as usize;
+        let count = piece.len().saturating_sub(2 * flank);
+        let first = first.min(len);
+        let piece = piece.iter().map(u8::to_ascii_uppercase).collect();
+        (piece, first..(first + count).min(len))
+    })
+}
+
+/// Accumulates the normal equations of a least-squares fit of curvature on composition.
+///
+/// # Fields
+///
+/// * `covariates`: The covariates fitted on.
+/// * `xtx`: The running `XᵀX`, row-major.
+/// * `xty`: The running `Xᵀy`.
+/// * `n`: The number of values added.
+#[derive(Clone, Debug)]
+pub struct CompositionFit {
+    covariates: Covariates,
+    xtx: Vec<f64>,
+    xty: Vec<f64>,
+    n: u64,
+}
+
+impl CompositionFit {
+    /// Constructor for `CompositionFit`.
+    pub fn new(covariates: Covariates) -> Self {
+        let dim = covariates.coefficients();
+        Self {
+            covariates,
+            xtx: vec![0.0; dim * dim],
+            xty: vec![0.0; dim],
+            n: 0,
+        }
+    }
+
+    /// Adds one observation; `NaN` values are ignored.
+    pub fn add(&mut self, features: &[f64], value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        let dim = self.xty.len();
+        for (i, &xi) in features.iter().enumerate() {
+            self.xty[i] += xi * value;
+            for (j, &xj) in features.iter().enumerate() {
+                self.xtx[i * dim + j] += xi * xj;
+            }
+        }
+        self.n += 1;
+    }
+
+    /// Adds the curvature values of a sequence piece.
+    ///
+    /// # Arguments
+    ///
+    /// * `piece` - A piece of `A`, `C`, `G` and `T` bases.
+    /// * `values` - The curvature values of the piece.
+    /// * `params` - The parameters the values were computed with.
+    pub fn add_piece(&mut self, piece: &[u8], values: &[f64], params: &PipelineParams) {
+        let features = piece_features(piece, values.len(), params, self.covariates);
+        for (features, &value) in features.zip(values) {
+            self.add(&features, value);
+        }
+    }
+
+    /// Adds the curvature values of a whole record, one per base as [`position_values`] places
+    /// them, piece by piece.
+    ///
+    /// [`position_values`]: crate::pipeline::position_values
+    pub fn add_record(&mut self, bases: &[u8], values: &[f64], params: &PipelineParams) {
+        for (piece, range) in record_pieces(bases, values.len(), params) {
+            self.add_piece(&piece, &values[range], params);
+        }
+    }
+
+    /// Solves the fit.
+    ///
+    /// Covariates that do not vary, such as the GC fraction of a sequence without any `G` or `C`,
+    /// get a coefficient of 0.
+    ///
+    /// # Returns
+    ///
+    /// The model, or `None` if no values were added.
+    pub fn solve(&self) -> Option<CompositionModel> {
+        if self.n == 0 {
+            return None;
+        }
+        let dim = self.xty.len();
+        // a slight ridge on the covariates keeps the system solvable when some never vary
+        let ridge = 1e-9 * self.n as f64;
+        let mut a: Vec<Vec<f64>> = (0..dim)
+            .map(|i| {
+                let mut row = self.xtx[i * dim..(i + 1) * dim].to_vec();
+                if i > 0 {
+                    row[i] += ridge;
+                }
+                row.push(self.xty[i]);
+                row
+            })
+            .collect();
+        // Gaussian elimination with partial pivoting
+        for col in 0..dim {
+            let pivot = (col..dim).max_by(|&x, &y| a[x][col].abs().total_cmp(&a[y][col].abs()))?;
+            a.swap(col, pivot);
+            if a[col][col] == 0.0 {
+                return None;
+            }
+            for row in col + 1..dim {
+                let factor = a[row][col] / a[col][col];
+                let (above, below) = a.split_at_mut(row);
+                for (x, p) in below[0][col..].iter_mut().zip(&above[col][col..]) {
+                    *x -= factor * p;
+                }
+            }
+        }
+        let mut coefficients = vec![0.0; dim];
+        for row in (0..dim).rev() {
+            let known: f64 = (row + 1..dim).map(|k| a[row][k] * coefficients[k]).sum();
+            coefficients[row] = (a[row][dim] - known) / a[row][row];
+        }
+        Some(CompositionModel {
+            covariates: self.covariates,
+            coefficients,
+        })
+    }
+}
+
+/// A fitted model of curvature from composition.
+///
+/// # Fields
+///
+/// * `covariates`: The covariates the model uses.
+/// * `coefficients`: The intercept followed by one coefficient per covariate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompositionModel {
+    pub covariates: Covariates,
+    pub coefficients: Vec<f64>,
+}
+
+impl CompositionModel {
+    /// The curvature predicted from composition alone.
+    pub fn predict(&self, features: &[f64]) -> f64 {
+        features
+            .iter()
+            .zip(&self.coefficients)
+            .map(|(x, b)| x * b)
+            .sum()
+    }
+
+    /// Replaces the curvature values of a sequence piece with their residuals.
+    ///
+    /// # Arguments
+    ///
+    /// * `piece` - A piece of `A`, `C`, `G` and `T` bases.
+    /// * `values` - The curvature values of the piece, corrected in place.
+    /// * `params` - The parameters the values were computed with.
+    pub fn correct_piece(&self, piece: &[u8], values: &mut [f64], params: &PipelineParams) {
+        let features = piece_features(piece, values.len(), params, self.covariates);
+        for (features, value) in features.zip(values.iter_mut()) {
+            *value -= self.predict(&features);
+        }
+    }
+
+    /// Replaces the curvature values of a whole record, one per base as [`position_values`]
+    /// places them, with their residuals.
+    ///
+    /// [`position_values`]: crate::pipeline::position_values
+    pub fn correct_record(&self, bases: &[u8], values: &mut [f64], params: &PipelineParams) {
+        for (piece, range) in record_pieces(bases, values.len(), params) {
+            self.correct_piece(&piece, &mut values[range], params);
+        }
+    }
+}
+
+/// Computes residual curvature for a set of sequence pieces, learning the model from them.
+///
+/// # Arguments
+///
+/// * `pieces` - Pieces of `A`, `C`, `G` and `T` bases.
+/// * `params` - The curvature parameters.
+/// * `covariates` - The covariates to correct for.
+///
+/// # Returns
+///
+/// The residual curvature values of each piece, and the fitted model (`None` if no piece was long
+/// enough for a value, in which case the values are left as they are).
+pub fn residual_curvature(
+    pieces: &[&[u8]],
+    params: &PipelineParams,
+    covariates: Covariates,
+) -> (Vec<Vec<f64>>, Option<CompositionModel>) {
+    let mut values: Vec<Vec<f64>> = pieces
+        .iter()
+        .map(|piece| {
+            CurveIter::new(
+                piece.iter().cloned(),
+                params.roll_type.clone(),
+                params.step_b,
+                params.step_c,
+                params.curve_scale,
+                params.edge_mode,
+                params.convention,
+            )
+            .with_step_two(params.step_two)
+            .collect()
+        })
+        .collect();
+    let mut fit = CompositionFit::new(covariates);
+    for (piece, values) in pieces.iter().zip(&values) {
+        fit.add_piece(piece, values, params);
+    }
+    let model = fit.solve();
+    if let Some(model) = &model {
+        for (piece, values) in pieces.iter().zip(values.iter_mut()) {
+            model.correct_piece(piece, values, params);
+        }
+    }
+    (values, model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Anchor;
+    use crate::pipeline::position_values;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_prefix_features() {
+        let prefix = PrefixCounts::new(b"AACGGT", Covariates::Gc);
+        assert_eq!(prefix.features(0, 6), vec![1.0, 0.5]);
+        assert_eq!(prefix.features(2, 5), vec![1.0, 1.0]);
+        let prefix = PrefixCounts::new(b"AACG", Covariates::Dinucleotide);
+        let features = prefix.features(0, 4);
+        assert_eq!(features.len(), 16);
+        // AA, AC and CG, one each, after the intercept
+        assert_relative_eq!(features[1], 1.0 / 3.0);
+        assert_relative_eq!(features[2], 1.0 / 3.0);
+        assert_relative_eq!(features[7], 1.0 / 3.0);
+        assert_relative_eq!(features.iter().skip(1).sum::<f64>(), 1.0);
+    }
+
+    #[test]
+    fn test_fit_recovers_linear_model() {
+        let mut fit = CompositionFit::new(Covariates::Gc);
+        for i in 0..20 {
+            let gc = i as f64 / 20.0;
+            fit.add(&[1.0, gc], 3.0 - 2.0 * gc);
+        }
+        fit.add(&[1.0, 0.5], f64::NAN);
+        let model = fit.solve().unwrap();
+        assert_relative_eq!(model.coefficients[0], 3.0, epsilon = 1e-6);
+        assert_relative_eq!(model.coefficients[1], -2.0, epsilon = 1e-6);
+        assert!(CompositionFit::new(Covariates::Gc).solve().is_none());
+    }
+
+    #[test]
+    fn test_fit_constant_covariate() {
+        // no variation in GC: the intercept takes the mean
+        let mut fit = CompositionFit::new(Covariates::Gc);
+        for value in [1.0, 2.0, 3.0] {
+            fit.add(&[1.0, 0.0], value);
+        }
+        let model = fit.solve().unwrap();
+        assert_relative_eq!(model.coefficients[0], 2.0, epsilon = 1e-6);
+        assert_relative_eq!(model.coefficients[1], 0.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_residual_curvature() {
+        let seq = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATCCCAACATTTTGACTTTTTGGGAGGGCACTAGCACC";
+        let params = PipelineParams::default();
+        for covariates in [Covariates::Gc, Covariates::Dinucleotide] {
+            let (values, model) = residual_curvature(&[&seq[..], &seq[10..]], &params, covariates);
+            assert!(model.is_some());
+            assert_eq!(values[0].len(), seq.len() - 42);
+            // least-squares residuals with an intercept average to zero
+            let all: Vec<f64> = values.concat();
+            let mean = all.iter().sum::<f64>() / all.len() as f64;
+            assert_relative_eq!(mean, 0.0, epsilon = 1e-6);
+        }
+        let (values, model) = residual_curvature(&[b"ACGT"], &params, Covariates::Gc);
+        assert!(model.is_none());
+        assert!(values[0].is_empty());
+    }
+
+    #[test]
+    fn test_record_matches_pieces() {
+        let seq = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATCCCAACATTTTGACTTTTTGGGAGGGCACTAGCACC";
+        let record = [&seq[..], b"NN", &seq[10..]].concat();
+        for anchor in [Anchor::Center, Anchor::Start] {
+            let params = PipelineParams {
+                anchor,
+                ..PipelineParams::default()
+            };
+            let (expected, model) =
+                residual_curvature(&[&seq[..], &seq[10..]], &params, Covariates::Gc);
+            let mut values = position_values(&record, &params);
+            let mut fit = CompositionFit::new(Covariates::Gc);
+            fit.add_record(&record, &values, &params);
+            assert_eq!(fit.solve(), model);
+            model.unwrap().correct_record(&record, &mut values, &params);
+            let corrected: Vec<f64> = values.into_iter().filter(|v| !v.is_nan()).collect();
+            assert_eq!(corrected, expected.concat());
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Strand divergence of curvature.
+//!
+//! The roll matrices are not symmetric under reverse complementing, so the curvature computed on
+//! the reverse complement of a sequence, mapped back onto forward coordinates, generally differs
+//! from the forward-strand curvature. `--strand-divergence` writes the per-position absolute
+//! difference between the two, which shows how strand-asymmetric the chosen matrix makes the
+//! prediction and where.
+
+use crate::curve::iters::{CurveIter, EdgeMode, MeanConvention};
+use crate::curve::matrix::RollType;
+use crate::fasta::reverse_complement;
+use std::path::{Path, PathBuf};
+
+/// Computes the per-position divergence between forward and reverse-complement curvature.
+///
+/// # Arguments
+///
+/// * `seq` - The forward-strand sequence.
+/// * `roll_type` - The type of roll (either simple or activated).
+/// * `step_b` - Half of the rolling mean window size minus one.
+/// * `step_two` - The half-width of the fully weighted part of the legacy rolling mean window, if
+///   set.
+/// * `step_c` - The distance from the midpoint to the ends of the curvature window.
+/// * `curve_scale` - The factor curvature is scaled by.
+/// * `edge_mode` - How windows are handled at the sequence ends.
+/// * `convention` - The averaging convention of the rolling mean.
+///
+/// # Returns
+///
+/// `|forward - reverse|` for each curvature value in forward order, where `reverse` is the value
+/// of the reverse-complement window covering the same bases.
+#[allow(clippy::too_many_arguments)]
+pub fn strand_divergence(
+    seq: &[u8],
+    roll_type: RollType,
+    step_b: usize,
+    step_two: Option<usize>,
+    step_c: usize,
+    curve_scale: f64,
+    edge_mode: EdgeMode,
+    convention: MeanConvention,
+) -> Vec<f64> {
+    let curvature = |seq: Vec<u8>| -> Vec<f64> {
+        CurveIter::new(
+            seq.into_iter(),
+            roll_type.clone(),
+            step_b,
+            step_c,
+            curve_scale,
+            edge_mode,
+            convention,
+        )
+        .with_step_two(step_two)
+        .collect()
+    };
+    let forward = curvature(seq.to_vec());
+    let reverse = curvature(reverse_complement(seq));
+    // windows are symmetric, so the reverse track lines up with the forward one once reversed
+    forward
+        .iter()
+        .zip(reverse.iter().rev())
+        .map(|(f, r)| (f - r).abs())
+        .collect()
+}
+
+/// Derives the path of the divergence track from the main output path, so that `out.bw` becomes
+/// `out.divergence.bw`.
+pub fn divergence_path(output: &Path) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match output.extension() {
+        Some(ext) => format!("{}.divergence.{}", stem, ext.to_string_lossy()),
+        None => format!("{}.divergence", stem),
+    };
+    output.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEQ: &[u8] = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+
+    fn divergence(seq: &[u8], edge_mode: EdgeMode) -> Vec<f64> {
+        strand_divergence(
+            seq,
+            RollType::Simple,
+            5,
+            None,
+            15,
+            0.33335,
+            edge_mode,
+            MeanConvention::Legacy,
+        )
+    }
+
+    #[test]
+    fn test_strand_divergence() {
+        let values = divergence(SEQ, EdgeMode::Drop);
+        assert_eq!(values.len(), 8);
+        assert!(values.iter().all(|v| *v >= 0.0));
+        // swapping the strands mirrors the track
+        let mut swapped = divergence(&reverse_complement(SEQ), EdgeMode::Drop);
+        swapped.reverse();
+        for (a, b) in values.iter().zip(swapped.iter()) {
+            approx::assert_abs_diff_eq!(a, b, epsilon = 1e-12);
+        }
+        // one value per triplet center when windows shrink at the ends
+        assert_eq!(divergence(SEQ, EdgeMode::Shrink).len(), SEQ.len() - 2);
+    }
+
+    #[test]
+    fn test_divergence_path() {
+        assert_eq!(
+            divergence_path(Path::new("dir/out.bw")),
+            PathBuf::from("dir/out.divergence.bw")
+        );
+        assert_eq!(
+            divergence_path(Path::new("out")),
+            PathBuf::from("out.divergence")
+        );
+    }
+}
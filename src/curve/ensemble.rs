@@ -0,0 +1,238 @@
+//! Ensembles of matrix sets.
+//!
+//! The roll values are estimates, and the published sets disagree. `--matrix-set simple,active`
+//! computes the curvature under each set and writes the per-position mean and standard deviation
+//! across them, next to the output as `.mean` and `.sd` tracks. Where the standard deviation is
+//! high, a peak depends on the choice of model rather than the sequence. Sets other than the
+//! built-in ones are given as paths to YAML matrix files.
+
+use crate::curve::matrix::{Matrices, RollType};
+use crate::pipeline::{position_values, PipelineParams};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// One member of an ensemble.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MatrixModel {
+    /// The built-in simple roll matrix.
+    Simple,
+    /// The built-in nucleosome-activated roll matrix.
+    Active,
+    /// The matrices of a YAML matrix file.
+    File(PathBuf),
+}
+
+impl MatrixModel {
+    /// Resolves the model to a roll type, reading its file if it has one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the file if it cannot be read or parsed.
+    pub fn roll_type(&self) -> io::Result<RollType> {
+        match self {
+            MatrixModel::Simple => Ok(RollType::Simple),
+            MatrixModel::Active => Ok(RollType::Active),
+            MatrixModel::File(path) => {
+                let matrices = Matrices::read_yaml(path)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+                Ok(RollType::Matrices(Arc::new(matrices)))
+            }
+        }
+    }
+}
+
+impl fmt::Display for MatrixModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatrixModel::Simple => write!(f, "simple"),
+            MatrixModel::Active => write!(f, "active"),
+            MatrixModel::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// The matrix sets of an ensemble, written as a comma-separated list.
+///
+/// # Fields
+///
+/// * `0`: The members, in the order given.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatrixSet(pub Vec<MatrixModel>);
+
+impl MatrixSet {
+    /// Resolves every member to a roll type.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error reading a matrix file.
+    pub fn roll_types(&self) -> io::Result<Vec<RollType>> {
+        self.0.iter().map(MatrixModel::roll_type).collect()
+    }
+}
+
+impl fmt::Display for MatrixSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self.0.iter().map(|m| m.to_string()).collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+impl FromStr for MatrixSet {
+    type Err = String;
+
+    /// Parses `simple` and `active` as the built-in sets and anything else as a YAML matrix file,
+    /// e.g. `simple,active,fitted.yaml`. At least two distinct members are needed for a standard
+    /// deviation.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut models = Vec::new();
+        for name in s.split(',').map(str::trim) {
+            let model = match name.to_ascii_lowercase().as_str() {
+                "" => return Err(format!("invalid matrix set '{}': empty member", s)),
+                "simple" => MatrixModel::Simple,
+                "active" => MatrixModel::Active,
+                _ => MatrixModel::File(PathBuf::from(name)),
+            };
+            if models.contains(&model) {
+                return Err(format!(
+                    "invalid matrix set '{}': {} is given twice",
+                    s, model
+                ));
+            }
+            models.push(model);
+        }
+        if models.len() < 2 {
+            return Err(format!(
+                "invalid matrix set '{}': expected at least two members, e.g. simple,active",
+                s
+            ));
+        }
+        Ok(MatrixSet(models))
+    }
+}
+
+/// Computes the per-position mean and standard deviation of curvature across roll types.
+///
+/// Each member's values are those of [`position_values`] with its roll type in place of
+/// `params.roll_type`, so positions without a value under the run's parameters have none here.
+///
+/// # Arguments
+///
+/// * `seq` - The bases of the sequence, in any case, with gaps as any non-`ACGT` byte.
+/// * `roll_types` - The members of the ensemble.
+/// * `params` - The parameters of the run, other than the roll type.
+///
+/// # Returns
+///
+/// The mean and the population standard deviation at each position, in sequence order.
+pub fn ensemble(
+    seq: &[u8],
+    roll_types: &[RollType],
+    params: &PipelineParams,
+) -> (Vec<f64>, Vec<f64>) {
+    let tracks: Vec<Vec<f64>> = roll_types
+        .iter()
+        .map(|roll_type| {
+            let params = PipelineParams {
+                roll_type: roll_type.clone(),
+                ..params.clone()
+            };
+            position_values(seq, &params)
+        })
+        .collect();
+    let len = tracks.first().map_or(0, Vec::len);
+    let n = tracks.len() as f64;
+    (0..len)
+        .map(|i| {
+            let mean = tracks.iter().map(|t| t[i]).sum::<f64>() / n;
+            let variance = tracks.iter().map(|t| (t[i] - mean).powi(2)).sum::<f64>() / n;
+            (mean, variance.sqrt())
+        })
+        .unzip()
+}
+
+/// Derives the paths of the mean and standard deviation tracks from the main output path, so
+/// that `out.bw` gives `out.mean.bw` and `out.sd.bw`.
+pub fn ensemble_paths(output: &Path) -> (PathBuf, PathBuf) {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let path = |kind: &str| {
+        let name = match output.extension() {
+            Some(ext) => format!("{}.{}.{}", stem, kind, ext.to_string_lossy()),
+            None => format!("{}.{}", stem, kind),
+        };
+        output.with_file_name(name)
+    };
+    (path("mean"), path("sd"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    const SEQ: &[u8] = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATCNNNNacgtacgt";
+
+    fn curvature(roll_type: RollType) -> Vec<f64> {
+        let params = PipelineParams {
+            roll_type,
+            ..PipelineParams::default()
+        };
+        position_values(SEQ, &params)
+    }
+
+    #[test]
+    fn test_parse() {
+        let set: MatrixSet = "simple, Active,fitted.yaml".parse().unwrap();
+        assert_eq!(
+            set.0,
+            vec![
+                MatrixModel::Simple,
+                MatrixModel::Active,
+                MatrixModel::File(PathBuf::from("fitted.yaml"))
+            ]
+        );
+        assert_eq!(set.to_string(), "simple,active,fitted.yaml");
+        assert!("simple".parse::<MatrixSet>().is_err());
+        assert!("simple,simple".parse::<MatrixSet>().is_err());
+        assert!("simple,,active".parse::<MatrixSet>().is_err());
+        let err = MatrixModel::File(PathBuf::from("no/such.yaml"))
+            .roll_type()
+            .unwrap_err();
+        assert!(err.to_string().starts_with("no/such.yaml: "));
+    }
+
+    #[test]
+    fn test_ensemble() {
+        let params = PipelineParams::default();
+        let roll_types = [RollType::Simple, RollType::Active];
+        let (mean, sd) = ensemble(SEQ, &roll_types, &params);
+        let simple = curvature(RollType::Simple);
+        let active = curvature(RollType::Active);
+        assert_eq!(mean.len(), SEQ.len());
+        assert!(mean.iter().any(|v| !v.is_nan()));
+        for i in 0..mean.len() {
+            if simple[i].is_nan() {
+                assert!(mean[i].is_nan() && sd[i].is_nan());
+                continue;
+            }
+            assert_relative_eq!(mean[i], (simple[i] + active[i]) / 2.0);
+            assert_relative_eq!(sd[i], (simple[i] - active[i]).abs() / 2.0);
+        }
+        // identical members do not disagree
+        let (_, sd) = ensemble(SEQ, &[RollType::Simple, RollType::Simple], &params);
+        assert!(sd.iter().filter(|v| !v.is_nan()).all(|&v| v == 0.0));
+    }
+
+    #[test]
+    fn test_ensemble_paths() {
+        assert_eq!(
+            ensemble_paths(Path::new("dir/out.bw")),
+            (
+                PathBuf::from("dir/out.mean.bw"),
+                PathBuf::from("dir/out.sd.bw")
+            )
+        );
+    }
+}
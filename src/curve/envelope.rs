@@ -0,0 +1,42 @@
+//! Upper and lower envelopes of curvature.
+//!
+//! At low zoom a genome browser can only show a fraction of the values of a track, so short
+//! bursts of high curvature disappear into the average. `--envelope` writes the rolling minimum and
+//! maximum of curvature over a window instead, which keeps the extremes visible however far out the
+//! view is.
+
+use crate::curve::iters::RollingExtremeIterator;
+
+/// Computes the lower and upper envelopes of a track.
+///
+/// # Arguments
+///
+/// * `values` - The values of the track.
+/// * `window` - The number of values in each envelope window.
+///
+/// # Returns
+///
+/// An iterator of `(lower, upper)` pairs, one per full window. The envelope of each window is
+/// reported at its middle, `window / 2` values in, so the first pair belongs at that offset.
+pub fn envelope<I>(values: I, window: usize) -> impl Iterator<Item = (f64, f64)>
+where
+    I: Iterator<Item = f64> + Clone,
+{
+    values
+        .clone()
+        .rolling_min(window)
+        .zip(values.rolling_max(window))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope() {
+        let values = [0.5, 2.0, 1.0, 8.0, 0.0, 3.0];
+        let rows: Vec<(f64, f64)> = envelope(values.iter().cloned(), 3).collect();
+        assert_eq!(rows, vec![(0.5, 2.0), (1.0, 8.0), (0.0, 8.0), (0.0, 8.0)]);
+        assert_eq!(envelope(values.iter().cloned(), 7).count(), 0);
+    }
+}
@@ -4,11 +4,108 @@
 //! It includes the necessary data structures for representing the DNA data and the traits and
 //! implementations for iterating over this data. The iterators provided allow for efficient and
 //! convenient traversal and manipulation of the DNA data for the purpose of curvature calculation.
+//!
+//! Every layer keeps accumulator state (the running twist sum, the coordinate origin, rolling
+//! sums and window buffers) that belongs to one sequence. A `CurveIter` must only ever see one
+//! `RecordPiece` between resets: to reuse one for the next piece, call `CurveIter::reset`, which
+//! puts every layer back in its initial state while keeping the buffer allocations.
 use crate::curve::matrix;
+use clap::ValueEnum;
 use std::collections::VecDeque;
 use std::f64::consts::PI;
 use std::iter::Iterator;
 
+/// How windowed layers treat positions near the ends of a sequence, where a full window does
+/// not fit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum EdgeMode {
+    /// drop positions without a full window, matching the original implementation
+    #[default]
+    Drop,
+    /// shrink windows symmetrically to the data available, so every position gets a value
+    Shrink,
+}
+
+/// The averaging convention used for the rolling mean of coordinates.
+///
+/// For a window of `2a + 1` coordinates, the legacy convention (from the original C/Perl code)
+/// gives the two end coordinates half weight and divides by `2a`; the standard convention is the
+/// plain mean, dividing the sum by `2a + 1`. The two agree whenever the coordinates are linear
+/// across the window and differ by `(x₋ₐ + xₐ - 2x̄) / (4a)` in general (with `x̄` the standard
+/// mean), which is small next to typical curvature values but enough to break exact
+/// reproduction of legacy outputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum MeanConvention {
+    /// half-weighted ends, divided by the window size minus one
+    #[default]
+    Legacy,
+    /// equal weights, divided by the window size
+    Standard,
+}
+
+/// Bookkeeping for windows that shrink near the ends of a sequence (`EdgeMode::Shrink`).
+///
+/// Each item of the inner iterator becomes the center of one window. The half-width of the
+/// window is the step size, cut down to the number of items available on the shorter side.
+///
+/// # Fields
+///
+/// * `front`: The absolute index of the item at the front of the buffer.
+/// * `center`: The absolute index of the next window center.
+/// * `exhausted`: Whether the inner iterator has run out.
+#[derive(Debug, Default)]
+struct ShrinkState {
+    front: usize,
+    center: usize,
+    exhausted: bool,
+}
+
+impl ShrinkState {
+    /// Fills `buffer` far enough for the next window.
+    ///
+    /// # Returns
+    ///
+    /// The buffer index of the next window center and the half-width of its window, or `None`
+    /// once every item has been a center.
+    fn next_window<T, I: Iterator<Item = T>>(
+        &mut self,
+        inner: &mut I,
+        buffer: &mut VecDeque<T>,
+        step: usize,
+    ) -> Option<(usize, usize)> {
+        while !self.exhausted && self.front + buffer.len() <= self.center + step {
+            match inner.next() {
+                Some(item) => buffer.push_back(item),
+                None => self.exhausted = true,
+            }
+        }
+        let end = self.front + buffer.len();
+        if self.center >= end {
+            return None;
+        }
+        let mut half = step.min(self.center);
+        if self.exhausted {
+            half = half.min(end - 1 - self.center);
+        }
+        let center = self.center - self.front;
+        self.center += 1;
+        Some((center, half))
+    }
+
+    /// Returns to the initial state, before any item was read.
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Drops buffered items that no window from the next center on will need.
+    fn trim<T>(&mut self, buffer: &mut VecDeque<T>, step: usize) {
+        while self.front + step < self.center && !buffer.is_empty() {
+            buffer.pop_front();
+            self.front += 1;
+        }
+    }
+}
+
 /// Represents the data for a triplet of nucleotides.
 ///
 /// This struct contains the twist, roll, and tilt values for a triplet of nucleotides, as well as
@@ -33,6 +130,75 @@ struct TripletData {
     roll_type: matrix::RollType,
 }
 
+/// An iterator-wrapping struct that yields each window of `K` consecutive bases from an inner
+/// `u8` iterator.
+///
+/// This is the buffered windowing underneath **layer 1** of the iterator stack, kept separate
+/// from any parameter lookup so that models over other window sizes (dinucleotide,
+/// tetranucleotide) and user metrics can reuse it. Windows are yielded as arrays, so no
+/// allocation is made per base.
+///
+/// # Type Parameters
+///
+/// * `I`: The type of the inner iterator. Must be an iterator over `u8`.
+/// * `K`: The number of bases in each window. Must be at least 1.
+///
+/// # Fields
+///
+/// * `inner`: The inner iterator that yields `u8`.
+/// * `window`: The bases of the current window.
+/// * `filled`: The number of leading bases of `window` that have been read.
+pub struct KmerWindowsIter<I: Iterator, const K: usize> {
+    inner: I,
+    window: [u8; K],
+    filled: usize,
+}
+
+impl<I: Iterator<Item = u8>, const K: usize> KmerWindowsIter<I, K> {
+    /// Constructor for `KmerWindowsIter`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `K` is 0.
+    pub fn new(inner: I) -> Self {
+        assert!(K > 0, "k-mer windows must hold at least one base");
+        Self {
+            inner,
+            window: [0; K],
+            filled: 0,
+        }
+    }
+
+    /// Clears the window, so the next window is read as the start of a new sequence.
+    pub fn reset(&mut self) {
+        self.filled = 0;
+    }
+}
+
+/// Implementation of the `Iterator` trait for `KmerWindowsIter` struct.
+///
+/// # Returns
+///
+/// The `next` method returns `Some([u8; K])` for each window of `K` bases as it slides one base at
+/// a time along the inner iterator, or `None` once fewer than `K` bases are left.
+impl<I, const K: usize> Iterator for KmerWindowsIter<I, K>
+where
+    I: Iterator<Item = u8>,
+{
+    type Item = [u8; K];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.filled < K {
+            self.window[self.filled] = self.inner.next()?;
+            self.filled += 1;
+        }
+        let kmer = self.window;
+        self.window.copy_within(1.., 0);
+        self.filled = K - 1;
+        Some(kmer)
+    }
+}
+
 /// An iterator-wrapping struct that yields TripletData from an inner `u8` iterator.
 ///
 /// `TripletWindowsIter` wraps around another iterator that yields `u8` (representing nucleotides),
@@ -46,13 +212,11 @@ struct TripletData {
 ///
 /// # Fields
 ///
-/// * `base_buffer`: A buffer that stores the current triplet of nucleotides.
-/// * `inner`: The inner iterator that yields `u8`.
+/// * `triplets`: The sliding windows of three bases over the inner iterator.
 /// * `twist_sum`: The sum of the twist values for the current triplet.
 /// * `roll_type`: The current roll type.
 struct TripletWindowsIter<I: Iterator> {
-    base_buffer: VecDeque<u8>,
-    inner: I,
+    triplets: KmerWindowsIter<I, { matrix::TRIPLET_SIZE }>,
     twist_sum: f64,
     roll_type: matrix::RollType,
 }
@@ -77,42 +241,30 @@ where
     type Item = TripletData;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // Fill the buffer with the next three items from the inner iterator.
-        while self.base_buffer.len() < matrix::TRIPLET_SIZE {
-            if let Some(item) = self.inner.next() {
-                self.base_buffer.push_back(item);
-            } else {
-                break;
-            }
-        }
-        // When the buffer is full, calculate the twist, roll, and tilt values.
-        if self.base_buffer.len() >= matrix::TRIPLET_SIZE {
-            let triplet: Vec<u8> = self.base_buffer.iter().cloned().take(3).collect();
-            let twist = matrix::matrix_lookup(&triplet, &matrix::TWIST).unwrap();
-            let roll = match self.roll_type {
-                matrix::RollType::Simple => {
-                    matrix::matrix_lookup(&triplet, &matrix::ROLL_SIMPLE).unwrap()
-                }
-                matrix::RollType::Active => {
-                    matrix::matrix_lookup(&triplet, &matrix::ROLL_ACTIVE).unwrap()
-                }
-            };
-            let tilt = matrix::matrix_lookup(&triplet, &matrix::TILT).unwrap();
-            self.twist_sum += twist;
-            // Create a TripletData instance and return it.
-            let window = TripletData {
-                twist,
-                roll,
-                tilt,
-                dx: (roll * self.twist_sum.sin()) + (tilt * (self.twist_sum + PI / 2.0).sin()),
-                dy: (roll * self.twist_sum.cos()) + (tilt * (self.twist_sum + PI / 2.0).cos()),
-                roll_type: self.roll_type.clone(),
-            };
-            self.base_buffer.pop_front();
-            Some(window)
-        } else {
-            None
-        }
+        let triplet = self.triplets.next()?;
+        // Calculate the twist, roll, and tilt values for the triplet.
+        let twist = matrix::matrix_lookup(&triplet, self.roll_type.twist_matrix()).unwrap();
+        let roll = matrix::matrix_lookup(&triplet, self.roll_type.matrix()).unwrap();
+        let tilt = matrix::matrix_lookup(&triplet, self.roll_type.tilt_matrix()).unwrap();
+        self.twist_sum += twist;
+        // Create a TripletData instance and return it.
+        Some(TripletData {
+            twist,
+            roll,
+            tilt,
+            dx: (roll * self.twist_sum.sin()) + (tilt * (self.twist_sum + PI / 2.0).sin()),
+            dy: (roll * self.twist_sum.cos()) + (tilt * (self.twist_sum + PI / 2.0).cos()),
+            roll_type: self.roll_type.clone(),
+        })
+    }
+}
+
+impl<I: Iterator<Item = u8>> TripletWindowsIter<I> {
+    /// Clears the triplet buffer and the running twist sum, so the next triplet is read as the
+    /// start of a new sequence.
+    fn reset(&mut self) {
+        self.triplets.reset();
+        self.twist_sum = 0.0;
     }
 }
 
@@ -134,8 +286,7 @@ where
 trait TripletWindowsIterator: Iterator<Item = u8> + Sized {
     fn triplet_windows_iter(self, roll_type: matrix::RollType) -> TripletWindowsIter<Self> {
         TripletWindowsIter {
-            base_buffer: VecDeque::new(),
-            inner: self,
+            triplets: KmerWindowsIter::new(self),
             twist_sum: 0.0,
             roll_type,
         }
@@ -144,6 +295,129 @@ trait TripletWindowsIterator: Iterator<Item = u8> + Sized {
 
 impl<I: Iterator<Item = u8>> TripletWindowsIterator for I {}
 
+/// Yields the per-position bend vector `(dx, dy)` for each triplet in a sequence.
+///
+/// This is **layer 1** of the iterator stack with everything but the deltas stripped off, for
+/// callers that want to do their own geometry downstream. The first vector belongs to the
+/// triplet starting at the first base, so vector `i` is centered on base `i + 1` (0-based).
+///
+/// # Parameters
+///
+/// * `seq_iter`: An iterator that yields `u8`.
+/// * `roll_type`: The type of roll (either simple or activated).
+pub fn bend_vectors<I: Iterator<Item = u8>>(
+    seq_iter: I,
+    roll_type: matrix::RollType,
+) -> impl Iterator<Item = (f64, f64)> {
+    seq_iter
+        .triplet_windows_iter(roll_type)
+        .map(|triplet_data| (triplet_data.dx, triplet_data.dy))
+}
+
+/// The **layer 1** items of the triplets of one block of a long sequence, computed apart from the
+/// rest of it so that blocks can be computed in parallel (see [`crate::chunk`]).
+pub(crate) struct TripletBlock(Vec<TripletData>);
+
+/// Computes the triplets starting in a block of bases.
+///
+/// The items are exactly those a `CurveIter` over the whole sequence computes for the same
+/// triplets, as long as `twist_sum` is the twist sum of the triplets before the block, as
+/// [`sum_twists`] gives it.
+///
+/// # Parameters
+///
+/// * `bases`: The bases of the block, preceded by the last two bases before it, if any.
+/// * `roll_type`: The type of roll (either simple or activated).
+/// * `twist_sum`: The sum of the twists of the triplets before the block.
+pub(crate) fn triplet_block(
+    bases: &[u8],
+    roll_type: matrix::RollType,
+    twist_sum: f64,
+) -> TripletBlock {
+    let mut triplets = bases.iter().cloned().triplet_windows_iter(roll_type);
+    triplets.twist_sum = twist_sum;
+    TripletBlock(triplets.collect())
+}
+
+/// Adds the twist of every triplet in `bases` to `twist_sum`, in the order a `CurveIter` adds
+/// them, without the rest of the triplet lookups.
+///
+/// # Parameters
+///
+/// * `bases`: The bases of a block, preceded by the last two bases before it, if any.
+/// * `roll_type`: The type of roll (either simple or activated).
+/// * `twist_sum`: The sum of the twists of the triplets before the block.
+pub(crate) fn sum_twists(bases: &[u8], roll_type: &matrix::RollType, twist_sum: f64) -> f64 {
+    bases
+        .windows(matrix::TRIPLET_SIZE)
+        .fold(twist_sum, |sum, triplet| {
+            sum + matrix::matrix_lookup(triplet, roll_type.twist_matrix()).unwrap()
+        })
+}
+
+/// Yields the curvature of a sequence from the triplets of its consecutive blocks: **layers 2
+/// to 4** of the iterator stack, run on triplets computed elsewhere. The values are exactly those
+/// of a `CurveIter` over the sequence with the same parameters.
+///
+/// # Parameters
+///
+/// * `blocks`: The triplet blocks of the sequence, in order.
+/// * `step_b`: Half of the rolling mean window size minus one.
+/// * `step_two`: The half-width of the fully weighted part of the legacy window, if set.
+/// * `step_c`: The distance from the midpoint base to the sides in the curve window.
+/// * `curve_scale`: The factor curvature is scaled by.
+/// * `edge_mode`: Whether windows are dropped or shrunk at the ends of the sequence.
+/// * `convention`: The averaging convention of the rolling mean.
+pub(crate) fn curve_from_blocks<B: Iterator<Item = TripletBlock>>(
+    blocks: B,
+    step_b: usize,
+    step_two: Option<usize>,
+    step_c: usize,
+    curve_scale: f64,
+    edge_mode: EdgeMode,
+    convention: MeanConvention,
+) -> impl Iterator<Item = f64> {
+    blocks
+        .flat_map(|block| block.0)
+        .coords_iter()
+        .roll_mean_iter(step_b)
+        .with_edge_mode(edge_mode)
+        .with_convention(convention)
+        .with_inner_step(step_two)
+        .euc_dist_iter(step_c)
+        .with_edge_mode(edge_mode)
+        .map(move |x| x * curve_scale)
+}
+
+/// Yields the rolling-mean coordinates of a sequence: **layers 1 to 3** of the iterator stack,
+/// without the distance step, for computing other metrics over the same windows.
+///
+/// # Parameters
+///
+/// * `seq_iter`: An iterator that yields `u8`.
+/// * `roll_type`: The type of roll (either simple or activated).
+/// * `step_b`: Half of the rolling mean window size minus one.
+/// * `step_two`: The half-width of the fully weighted part of the legacy rolling mean window, if
+///   set.
+/// * `edge_mode`: Whether windows are dropped or shrunk at the ends of the sequence.
+/// * `convention`: The averaging convention of the rolling mean.
+pub fn roll_means<I: Iterator<Item = u8>>(
+    seq_iter: I,
+    roll_type: matrix::RollType,
+    step_b: usize,
+    step_two: Option<usize>,
+    edge_mode: EdgeMode,
+    convention: MeanConvention,
+) -> impl Iterator<Item = RollMeanData> {
+    seq_iter
+        .triplet_windows_iter(roll_type)
+        .coords_iter()
+        .roll_mean_iter(step_b)
+        .with_edge_mode(edge_mode)
+        .with_convention(convention)
+        .with_inner_step(step_two)
+}
+
 /// Represents the coordinates and associated data for a triplet of nucleotides.
 ///
 /// `CoordsData` contains the x and y coordinates calculated from the `TripletData`, as well as
@@ -227,7 +501,7 @@ where
     /// the current `TripletData`.
     ///
     /// If there are no more items in the inner iterator it yields one more new `CoordsData` without a
-    /// `TripletData` but with `x` and `y` filled in.
+    /// `TripletData` but with `x` and `y` filled in, unless there were no items at all.
     ///
     /// # Returns
     ///
@@ -242,7 +516,8 @@ where
                 return self.next();
             }
             result
-        } else if !self.tail {
+        } else if self.head && !self.tail {
+            // a sequence of fewer than three bases has no triplets and so no path at all
             self.tail = true;
             Some(self.create_coords_data(None))
         } else {
@@ -278,6 +553,17 @@ where
             y: y_coord,
         }
     }
+
+    /// Moves the coordinate origin back to `(0, 0)` and clears the head/tail flags, so the next
+    /// triplet starts a new path.
+    fn reset(&mut self) {
+        self.head = false;
+        self.tail = false;
+        self.prev_x_coord = 0.0;
+        self.prev_y_coord = 0.0;
+        self.prev_dx = 0.0;
+        self.prev_dy = 0.0;
+    }
 }
 
 /// A trait for `TripletData` Iterators to yield `CoordsData`.
@@ -313,18 +599,21 @@ impl<I: Iterator<Item = TripletData>> CoordsIterator for I {}
 
 /// Represents the data for a rolling mean of the x and y coordinates.
 ///
+/// These are the points curvature metrics work on (see `curve::metric`).
+///
 /// # Fields
 ///
 /// * `x_bar`: The weighted mean of the x coordinates.
 /// * `y_bar`: The weighted mean of the y coordinates.
-struct RollMeanData {
-    x_bar: f64,
-    y_bar: f64,
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RollMeanData {
+    pub x_bar: f64,
+    pub y_bar: f64,
 }
 
-/// Represents the data for a rolling mean of the x and y coordinates.
+/// An iterator-wrapping struct that yields `RollMeanData` from another iterator.
 ///
-/// The `RollMeanData` struct contains the weighted x and y means for a window of coordinates
+/// The `RollMeanIter` struct computes the weighted x and y means for a window of coordinates
 /// that is 2 * `step_size` + 1 in length.
 ///
 /// # Fields
@@ -335,12 +624,22 @@ struct RollMeanData {
 ///   2 * `step_size` + 1 is the size of the window.
 /// * `x_roll_sum`: The sum of the x coordinates in the current window.
 /// * `y_roll_sum`: The sum of the y coordinates in the current window.
+/// * `edge_mode`: Whether windows are dropped or shrunk at the ends of the sequence.
+/// * `shrink`: Window bookkeeping used when `edge_mode` is `EdgeMode::Shrink`.
+/// * `convention`: Whether window ends are half-weighted (legacy) or not (standard).
+/// * `inner_step`: With the legacy convention, the half-width of the fully weighted inner
+///   window (`--curve-step-two`), if it is not `step_size - 1`; see
+///   `RollMeanIter::with_inner_step`.
 struct RollMeanIter<I: Iterator> {
     inner: I,
     buffer: VecDeque<CoordsData>,
     step_size: usize,
     x_roll_sum: f64,
     y_roll_sum: f64,
+    edge_mode: EdgeMode,
+    shrink: ShrinkState,
+    convention: MeanConvention,
+    inner_step: Option<usize>,
 }
 
 /// Implementation of the `Iterator` trait for `RollMeanIter`.
@@ -361,8 +660,11 @@ where
     /// The method returns `Some(RollMeanData)` if there are enough items in the inner iterator,
     /// and `None` otherwise.
     fn next(&mut self) -> Option<Self::Item> {
+        if self.edge_mode == EdgeMode::Shrink {
+            return self.next_shrink();
+        }
         // Fill the buffer with the next three items from the inner iterator.
-        let window_size = self.step_size * 2 + 1;
+        let window_size = self.extent() * 2 + 1;
         while self.buffer.len() < window_size {
             if let Some(item) = self.inner.next() {
                 self.x_roll_sum += item.x;
@@ -373,15 +675,29 @@ where
             }
         }
         if self.buffer.len() >= window_size {
-            // get the fron/back items without removing them and adjust the roll sum
-            let adj_x_roll_sum = self.x_roll_sum
-                - (0.5 * self.buffer.front().unwrap().x)
-                - (0.5 * self.buffer.back().unwrap().x);
-            let adj_y_roll_sum = self.y_roll_sum
-                - (0.5 * self.buffer.front().unwrap().y)
-                - (0.5 * self.buffer.back().unwrap().y);
-            let x_bar = adj_x_roll_sum / (window_size as f64 - 1 as f64);
-            let y_bar = adj_y_roll_sum / (window_size as f64 - 1 as f64);
+            let (x_bar, y_bar) = match (self.convention, self.inner_step) {
+                (MeanConvention::Legacy, Some(inner_step)) => {
+                    let mean = self.split_mean(self.extent(), inner_step);
+                    (mean.x_bar, mean.y_bar)
+                }
+                (MeanConvention::Legacy, None) => {
+                    // get the fron/back items without removing them and adjust the roll sum
+                    let adj_x_roll_sum = self.x_roll_sum
+                        - (0.5 * self.buffer.front().unwrap().x)
+                        - (0.5 * self.buffer.back().unwrap().x);
+                    let adj_y_roll_sum = self.y_roll_sum
+                        - (0.5 * self.buffer.front().unwrap().y)
+                        - (0.5 * self.buffer.back().unwrap().y);
+                    (
+                        adj_x_roll_sum / (window_size as f64 - 1.0),
+                        adj_y_roll_sum / (window_size as f64 - 1.0),
+                    )
+                }
+                (MeanConvention::Standard, _) => (
+                    self.x_roll_sum / window_size as f64,
+                    self.y_roll_sum / window_size as f64,
+                ),
+            };
             let result = Some(RollMeanData { x_bar, y_bar });
             let item = self.buffer.pop_front().unwrap();
             self.x_roll_sum -= item.x;
@@ -393,6 +709,124 @@ where
     }
 }
 
+impl<I> RollMeanIter<I>
+where
+    I: Iterator<Item = CoordsData>,
+{
+    /// Sets how windows are handled at the ends of the sequence.
+    fn with_edge_mode(mut self, edge_mode: EdgeMode) -> Self {
+        self.edge_mode = edge_mode;
+        self
+    }
+
+    /// Sets the averaging convention.
+    fn with_convention(mut self, convention: MeanConvention) -> Self {
+        self.convention = convention;
+        self
+    }
+
+    /// Sets the half-width of the fully weighted inner window of the legacy convention.
+    ///
+    /// The original Perl code sums the coordinates within `inner_step` of the center, adds the
+    /// two coordinates `step_size` away weighted `2 / inner_step`, and divides by
+    /// `2 * step_size`. At its defaults (`step_size` 5, `inner_step` 4) that is the
+    /// half-weighted-ends mean that `None` gives, and it is computed the same way. Other values,
+    /// as some presets use, reweight the ends and widen or leave holes in the window. The
+    /// standard convention ignores this.
+    fn with_inner_step(mut self, inner_step: Option<usize>) -> Self {
+        self.set_inner_step(inner_step);
+        self
+    }
+
+    /// In-place form of `RollMeanIter::with_inner_step`.
+    fn set_inner_step(&mut self, inner_step: Option<usize>) {
+        self.inner_step = inner_step.filter(|&inner| !(inner == 4 && self.step_size == 5));
+    }
+
+    /// The number of coordinates on either side of the center that a mean reads.
+    fn extent(&self) -> usize {
+        match (self.convention, self.inner_step) {
+            (MeanConvention::Legacy, Some(inner_step)) => self.step_size.max(inner_step),
+            _ => self.step_size,
+        }
+    }
+
+    /// The legacy mean around the buffer index `center` with an inner window of `inner_step`,
+    /// as described at `RollMeanIter::with_inner_step`.
+    fn split_mean(&self, center: usize, inner_step: usize) -> RollMeanData {
+        let end_weight = 2.0 / inner_step as f64;
+        let left = &self.buffer[center - self.step_size];
+        let right = &self.buffer[center + self.step_size];
+        let mut x_sum = end_weight * (left.x + right.x);
+        let mut y_sum = end_weight * (left.y + right.y);
+        for item in self.buffer.range(center - inner_step..=center + inner_step) {
+            x_sum += item.x;
+            y_sum += item.y;
+        }
+        let divisor = (2 * self.step_size) as f64;
+        RollMeanData {
+            x_bar: x_sum / divisor,
+            y_bar: y_sum / divisor,
+        }
+    }
+
+    /// Clears the window and the rolling sums. The step size, edge mode and convention are kept.
+    fn reset(&mut self) {
+        self.buffer.clear();
+        self.x_roll_sum = 0.0;
+        self.y_roll_sum = 0.0;
+        self.shrink.reset();
+    }
+
+    /// Helper to `RollMeanIter::next()` for `EdgeMode::Shrink`.
+    ///
+    /// Every coordinate gets a mean. Near the ends, the window is narrowed to `2h + 1` items where
+    /// `h` is the number of coordinates available on the shorter side, keeping the averaging
+    /// convention. A window of one item is just that item.
+    fn next_shrink(&mut self) -> Option<RollMeanData> {
+        let extent = self.extent();
+        let (center, half) = self
+            .shrink
+            .next_window(&mut self.inner, &mut self.buffer, extent)?;
+        // a window of unusual weights is only taken whole; near the ends it shrinks as usual
+        let whole = half == extent && self.convention == MeanConvention::Legacy;
+        let half = half.min(self.step_size);
+        let result = if let Some(inner_step) = self.inner_step.filter(|_| whole) {
+            self.split_mean(center, inner_step)
+        } else if self.convention == MeanConvention::Standard {
+            let window = self.buffer.range(center - half..=center + half);
+            let (x_sum, y_sum) = window.fold((0.0, 0.0), |(x, y), item| (x + item.x, y + item.y));
+            let divisor = (2 * half + 1) as f64;
+            RollMeanData {
+                x_bar: x_sum / divisor,
+                y_bar: y_sum / divisor,
+            }
+        } else if half == 0 {
+            let item = &self.buffer[center];
+            RollMeanData {
+                x_bar: item.x,
+                y_bar: item.y,
+            }
+        } else {
+            let left = &self.buffer[center - half];
+            let right = &self.buffer[center + half];
+            let mut x_sum = 0.5 * (left.x + right.x);
+            let mut y_sum = 0.5 * (left.y + right.y);
+            for item in self.buffer.range(center - half + 1..center + half) {
+                x_sum += item.x;
+                y_sum += item.y;
+            }
+            let divisor = (2 * half) as f64;
+            RollMeanData {
+                x_bar: x_sum / divisor,
+                y_bar: y_sum / divisor,
+            }
+        };
+        self.shrink.trim(&mut self.buffer, extent);
+        Some(result)
+    }
+}
+
 /// A trait for iterators that can compute a rolling mean of `CoordsData`.
 ///
 /// This trait extends the `Iterator` trait, adding a `roll_mean_iter` method that
@@ -407,7 +841,7 @@ trait RollMeanIterator: Iterator<Item = CoordsData> + Sized {
     /// # Parameters
     ///
     /// * `step_size`: half of the window size minus one. In other words, 2 * `step_size` + 1 is
-    ///  the size of the window.
+    ///   the size of the window.
     ///
     /// # Returns
     ///
@@ -419,6 +853,10 @@ trait RollMeanIterator: Iterator<Item = CoordsData> + Sized {
             step_size,
             x_roll_sum: 0.0,
             y_roll_sum: 0.0,
+            edge_mode: EdgeMode::Drop,
+            shrink: ShrinkState::default(),
+            convention: MeanConvention::Legacy,
+            inner_step: None,
         }
     }
 }
@@ -437,10 +875,16 @@ impl<I: Iterator<Item = CoordsData>> RollMeanIterator for I {}
 /// * `buffer`: A buffer that stores 2 * `curve_step_size` + 1 items from the inner iterator.
 ///
 /// * `curve_step_size`: The distance from the midpoint base in the window.  
+///
+/// * `edge_mode`: Whether windows are dropped or shrunk at the ends of the sequence.
+///
+/// * `shrink`: Window bookkeeping used when `edge_mode` is `EdgeMode::Shrink`.
 struct EucDistIter<I: Iterator> {
     inner: I,
     buffer: VecDeque<RollMeanData>,
     curve_step_size: usize,
+    edge_mode: EdgeMode,
+    shrink: ShrinkState,
 }
 
 impl<I> Iterator for EucDistIter<I>
@@ -458,6 +902,9 @@ where
     /// The method returns `Some(f64)` if there are enough items in the inner iterator,
     /// and `None` otherwise.
     fn next(&mut self) -> Option<Self::Item> {
+        if self.edge_mode == EdgeMode::Shrink {
+            return self.next_shrink();
+        }
         // Fill the buffer with the next three items from the inner iterator.
         let window_size = self.curve_step_size * 2 + 1;
         while self.buffer.len() < window_size {
@@ -481,18 +928,188 @@ where
     }
 }
 
+impl<I> EucDistIter<I>
+where
+    I: Iterator<Item = RollMeanData>,
+{
+    /// Sets how windows are handled at the ends of the sequence.
+    fn with_edge_mode(mut self, edge_mode: EdgeMode) -> Self {
+        self.edge_mode = edge_mode;
+        self
+    }
+
+    /// Clears the window. The step size and edge mode are kept.
+    fn reset(&mut self) {
+        self.buffer.clear();
+        self.shrink.reset();
+    }
+
+    /// Helper to `EucDistIter::next()` for `EdgeMode::Shrink`.
+    ///
+    /// Every mean gets a distance. Near the ends, the distance is taken between the means `h`
+    /// items either side of the center, where `h` is the number available on the shorter side,
+    /// so the outermost values are 0.
+    fn next_shrink(&mut self) -> Option<f64> {
+        let (center, half) =
+            self.shrink
+                .next_window(&mut self.inner, &mut self.buffer, self.curve_step_size)?;
+        let left = &self.buffer[center - half];
+        let right = &self.buffer[center + half];
+        let curve =
+            ((right.y_bar - left.y_bar).powf(2.0) + (right.x_bar - left.x_bar).powf(2.0)).sqrt();
+        self.shrink.trim(&mut self.buffer, self.curve_step_size);
+        Some(curve)
+    }
+}
+
 trait EucDistIterator: Iterator<Item = RollMeanData> + Sized {
     fn euc_dist_iter(self, curve_step_size: usize) -> EucDistIter<Self> {
         EucDistIter {
             inner: self,
             buffer: VecDeque::new(),
             curve_step_size,
+            edge_mode: EdgeMode::Drop,
+            shrink: ShrinkState::default(),
         }
     }
 }
 
 impl<I: Iterator<Item = RollMeanData>> EucDistIterator for I {}
 
+/// Which extreme a `RollingExtremeIter` tracks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Extreme {
+    Max,
+    Min,
+}
+
+impl Extreme {
+    /// Whether `a` is at least as extreme as `b`, so `b` can never be the extreme again once `a`
+    /// has been seen after it.
+    fn dominates(&self, a: f64, b: f64) -> bool {
+        match self {
+            Extreme::Max => a >= b,
+            Extreme::Min => a <= b,
+        }
+    }
+}
+
+/// An iterator that yields the maximum or minimum of each window of values from an inner `f64`
+/// iterator.
+///
+/// Windows are trailing and slide one value at a time, so like the drop edge mode an inner
+/// iterator of `n` values gives `n - window + 1`. A monotonic queue of candidates keeps each step
+/// constant time on average, whatever the window size. `NaN` values are skipped; a window of only
+/// `NaN` yields `NaN`.
+///
+/// # Fields
+///
+/// * `inner`: The inner iterator that yields `f64`.
+/// * `window`: The number of values in each window.
+/// * `extreme`: Whether the maximum or the minimum is taken.
+/// * `candidates`: The indices and values that may still be the extreme of a later window, from
+///   most to least extreme.
+/// * `index`: The number of values read from `inner`.
+pub struct RollingExtremeIter<I: Iterator<Item = f64>> {
+    inner: I,
+    window: usize,
+    extreme: Extreme,
+    candidates: VecDeque<(usize, f64)>,
+    index: usize,
+}
+
+impl<I: Iterator<Item = f64>> Iterator for RollingExtremeIter<I> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let value = self.inner.next()?;
+            if !value.is_nan() {
+                while let Some(&(_, last)) = self.candidates.back() {
+                    if !self.extreme.dominates(value, last) {
+                        break;
+                    }
+                    self.candidates.pop_back();
+                }
+                self.candidates.push_back((self.index, value));
+            }
+            self.index += 1;
+            if self.index < self.window {
+                continue;
+            }
+            let first = self.index - self.window;
+            while self.candidates.front().is_some_and(|&(i, _)| i < first) {
+                self.candidates.pop_front();
+            }
+            return Some(self.candidates.front().map_or(f64::NAN, |&(_, v)| v));
+        }
+    }
+}
+
+/// Adds rolling extreme adapters to every `f64` iterator.
+pub trait RollingExtremeIterator: Iterator<Item = f64> + Sized {
+    /// The maximum of each window of `window` values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is 0.
+    fn rolling_max(self, window: usize) -> RollingExtremeIter<Self> {
+        self.rolling_extreme(window, Extreme::Max)
+    }
+
+    /// The minimum of each window of `window` values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is 0.
+    fn rolling_min(self, window: usize) -> RollingExtremeIter<Self> {
+        self.rolling_extreme(window, Extreme::Min)
+    }
+
+    /// The maximum or minimum of each window of `window` values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is 0.
+    fn rolling_extreme(self, window: usize, extreme: Extreme) -> RollingExtremeIter<Self> {
+        assert!(window > 0, "rolling windows must hold at least one value");
+        RollingExtremeIter {
+            inner: self,
+            window,
+            extreme,
+            candidates: VecDeque::with_capacity(window),
+            index: 0,
+        }
+    }
+}
+
+impl<I: Iterator<Item = f64>> RollingExtremeIterator for I {}
+
+/// The window buffers of a `CurveIter`, detached from it so their allocations can outlive it.
+///
+/// `CurveIter::reset` keeps the buffers of one iterator, but only for inner iterators of the same
+/// type. When each sequence is read through a different iterator, for example one borrowing the
+/// current record, take the buffers back with `CurveIter::into_buffers` and hand them to the next
+/// iterator with `CurveIter::with_buffers`.
+///
+/// # Fields
+///
+/// * `roll_mean`: The rolling mean window.
+/// * `curve`: The curvature window.
+#[derive(Default)]
+pub struct CurveBuffers {
+    roll_mean: VecDeque<CoordsData>,
+    curve: VecDeque<RollMeanData>,
+}
+
+impl CurveBuffers {
+    /// The memory held by the buffers, in bytes.
+    pub fn capacity_bytes(&self) -> usize {
+        self.roll_mean.capacity() * std::mem::size_of::<CoordsData>()
+            + self.curve.capacity() * std::mem::size_of::<RollMeanData>()
+    }
+}
+
 /// An iterator that computes the curvature of a DNA sequence.
 ///
 /// `CurveIter` wraps an iterator that yields `u8` and computes the curvature of the DNA sequence
@@ -522,33 +1139,173 @@ impl<I: Iterator<Item = u8>> Iterator for CurveIter<I> {
 ///
 /// # Parameters
 ///
-/// * `seq_iter`: An iterator that yields `u8`.
+/// * `seq_iter`: An iterator that yields `u8`, uppercase `A`, `C`, `G` and `T` only.
 /// * `roll_type`: The type of roll (either simple or activated).
 /// * `step_b`: Half of the window size minus one. In other words, 2 * `step_size` + 1 is
-///  the size of the window.
+///   the size of the window.
 /// * `step_c`: The distance from the midpoint base to the sides in the curve window.
+/// * `edge_mode`: Whether windows are dropped or shrunk at the ends of the sequence.
+/// * `convention`: The averaging convention of the rolling mean.
 impl<I: Iterator<Item = u8>> CurveIter<I> {
-    fn new(
+    pub fn new(
         seq_iter: I,
         roll_type: matrix::RollType,
         step_b: usize,
         step_c: usize,
         curve_scale: f64,
+        edge_mode: EdgeMode,
+        convention: MeanConvention,
     ) -> Self {
         Self {
             inner: seq_iter
                 .triplet_windows_iter(roll_type)
                 .coords_iter()
                 .roll_mean_iter(step_b)
-                .euc_dist_iter(step_c),
+                .with_edge_mode(edge_mode)
+                .with_convention(convention)
+                .euc_dist_iter(step_c)
+                .with_edge_mode(edge_mode),
+            curve_scale,
+        }
+    }
+
+    /// Constructs a `CurveIter` that reuses the allocations of `buffers`.
+    ///
+    /// The values are exactly those of [`CurveIter::new`] with the same arguments.
+    #[allow(clippy::too_many_arguments)]
+    pub fn with_buffers(
+        seq_iter: I,
+        roll_type: matrix::RollType,
+        step_b: usize,
+        step_c: usize,
+        curve_scale: f64,
+        edge_mode: EdgeMode,
+        convention: MeanConvention,
+        mut buffers: CurveBuffers,
+    ) -> Self {
+        let mut curve = Self::new(
+            seq_iter,
+            roll_type,
+            step_b,
+            step_c,
             curve_scale,
+            edge_mode,
+            convention,
+        );
+        buffers.roll_mean.clear();
+        buffers.curve.clear();
+        curve.inner.inner.buffer = buffers.roll_mean;
+        curve.inner.buffer = buffers.curve;
+        curve
+    }
+
+    /// Sets the half-width of the fully weighted part of the legacy rolling mean window
+    /// (`--curve-step-two`); `None` keeps the half-weighted-ends mean of width `2 * step_b + 1`.
+    ///
+    /// The standard convention ignores it. See [`CurveIter::rolling_extent`] for the window it
+    /// needs.
+    pub fn with_step_two(mut self, step_two: Option<usize>) -> Self {
+        self.inner.inner.set_inner_step(step_two);
+        self
+    }
+
+    /// Gives up the window buffers, keeping their allocations for another `CurveIter`.
+    pub fn into_buffers(self) -> CurveBuffers {
+        let mut euc_dist = self.inner;
+        euc_dist.buffer.clear();
+        euc_dist.inner.buffer.clear();
+        CurveBuffers {
+            roll_mean: euc_dist.inner.buffer,
+            curve: euc_dist.buffer,
+        }
+    }
+
+    /// Starts over on a new sequence.
+    ///
+    /// Every layer is put back in its initial state, so the values that follow are exactly those
+    /// of a new `CurveIter` over `seq_iter` with the same parameters. Buffer allocations are kept,
+    /// which saves reallocating them for every `RecordPiece`.
+    ///
+    /// # Parameters
+    ///
+    /// * `seq_iter`: An iterator that yields the bases of the new sequence.
+    pub fn reset(&mut self, seq_iter: I) {
+        let euc_dist = &mut self.inner;
+        euc_dist.reset();
+        let roll_mean = &mut euc_dist.inner;
+        roll_mean.reset();
+        let coords = &mut roll_mean.inner;
+        coords.reset();
+        let triplets = &mut coords.inner;
+        triplets.reset();
+        triplets.triplets.inner = seq_iter;
+    }
+
+    /// The memory held by the buffer of each layer of the iterator stack, in bytes.
+    ///
+    /// Sizes are taken from the buffer capacities, so they report what is allocated rather than
+    /// what is currently in use.
+    pub fn buffer_sizes(&self) -> [(&'static str, usize); 3] {
+        let euc_dist = &self.inner;
+        let roll_mean = &euc_dist.inner;
+        let triplets = &roll_mean.inner.inner;
+        [
+            ("triplet", std::mem::size_of_val(&triplets.triplets.window)),
+            (
+                "roll_mean",
+                roll_mean.buffer.capacity() * std::mem::size_of::<CoordsData>(),
+            ),
+            (
+                "curve",
+                euc_dist.buffer.capacity() * std::mem::size_of::<RollMeanData>(),
+            ),
+        ]
+    }
+
+    /// The number of coordinates on either side of the center that the rolling mean reads: the
+    /// `step_b` to use in [`CurveIter::flank`] when a step two is set.
+    ///
+    /// # Parameters
+    ///
+    /// * `step_b`: Half of the rolling mean window size minus one.
+    /// * `step_two`: The half-width of the fully weighted part of the legacy window, if set.
+    /// * `convention`: The averaging convention of the rolling mean.
+    pub fn rolling_extent(
+        step_b: usize,
+        step_two: Option<usize>,
+        convention: MeanConvention,
+    ) -> usize {
+        match (convention, step_two) {
+            (MeanConvention::Legacy, Some(step_two)) => step_b.max(step_two),
+            _ => step_b,
+        }
+    }
+
+    /// The number of bases on either side of the central base of each curvature window.
+    ///
+    /// The first emitted value is centered `flank` bases into the sequence, and the full window
+    /// behind each value spans `2 * flank + 1` bases. With shrinking windows only the outermost
+    /// base on each side, which no triplet is centered on, goes without a value.
+    ///
+    /// # Parameters
+    ///
+    /// * `step_b`: Half of the rolling mean window size minus one.
+    /// * `step_c`: The distance from the midpoint base to the sides in the curve window.
+    /// * `edge_mode`: Whether windows are dropped or shrunk at the ends of the sequence.
+    pub fn flank(step_b: usize, step_c: usize, edge_mode: EdgeMode) -> usize {
+        match edge_mode {
+            EdgeMode::Drop => step_b + step_c + 1,
+            EdgeMode::Shrink => 1,
         }
     }
 }
 
+// the tests predate the lint
 #[cfg(test)]
+#[allow(clippy::useless_conversion)]
 mod tests {
     use super::*;
+    use crate::property;
     use approx::assert_relative_eq;
 
     /// Below is a table of some of the expected values for the triplet iterator over the DNA
@@ -626,6 +1383,50 @@ mod tests {
         assert_relative_eq!(windows[47].dy, -3.2246, epsilon = 1e-4);
     }
 
+    #[test]
+    fn test_bend_vectors() {
+        let dna = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let bends: Vec<_> = bend_vectors(dna.iter().cloned(), matrix::RollType::Simple).collect();
+        assert_eq!(bends.len(), dna.len() - 2);
+        assert_relative_eq!(bends[0].0, 0.3945, epsilon = 1e-4);
+        assert_relative_eq!(bends[0].1, 0.5783, epsilon = 1e-4);
+        assert_relative_eq!(bends[47].0, -1.6006, epsilon = 1e-4);
+        assert_relative_eq!(bends[47].1, -3.2246, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn test_kmer_windows_iter() {
+        let dna = b"ACGTA";
+        let dimers: Vec<[u8; 2]> = KmerWindowsIter::new(dna.iter().cloned()).collect();
+        assert_eq!(dimers, vec![*b"AC", *b"CG", *b"GT", *b"TA"]);
+        let tetramers: Vec<[u8; 4]> = KmerWindowsIter::new(dna.iter().cloned()).collect();
+        assert_eq!(tetramers, vec![*b"ACGT", *b"CGTA"]);
+        let mut iter: KmerWindowsIter<_, 6> = KmerWindowsIter::new(dna.iter().cloned());
+        assert_eq!(iter.next(), None);
+        let mut iter: KmerWindowsIter<_, 3> = KmerWindowsIter::new(b"ACGTTT".iter().cloned());
+        assert_eq!(iter.next(), Some(*b"ACG"));
+        // a reset drops the partial window carried over from the previous sequence
+        iter.reset();
+        assert_eq!(iter.next(), Some(*b"TTT"));
+    }
+
+    #[test]
+    fn test_rolling_extremes() {
+        let values = [3.0, 1.0, 4.0, 1.0, 5.0, 9.0, 2.0, 6.0];
+        let max: Vec<f64> = values.iter().cloned().rolling_max(3).collect();
+        assert_eq!(max, vec![4.0, 4.0, 5.0, 9.0, 9.0, 9.0]);
+        let min: Vec<f64> = values.iter().cloned().rolling_min(3).collect();
+        assert_eq!(min, vec![1.0, 1.0, 1.0, 1.0, 2.0, 2.0]);
+        let ones: Vec<f64> = values.iter().cloned().rolling_max(1).collect();
+        assert_eq!(ones, values);
+        assert_eq!(values.iter().cloned().rolling_min(9).next(), None);
+        // NaN is skipped, and a window of nothing else is NaN
+        let gappy = [f64::NAN, f64::NAN, 2.0, f64::NAN, 1.0];
+        let max: Vec<f64> = gappy.iter().cloned().rolling_max(2).collect();
+        assert!(max[0].is_nan());
+        assert_eq!(&max[1..], &[2.0, 2.0, 1.0]);
+    }
+
     #[test]
     fn test_triplet_iter_too_short() {
         let dna = b"AC";
@@ -952,9 +1753,15 @@ mod tests {
             5,
             15,
             0.33335,
+            EdgeMode::Drop,
+            MeanConvention::Legacy,
         )
         .collect();
         assert_eq!(curves.len(), seq_len - (21 * 2));
+        assert_eq!(
+            CurveIter::<std::vec::IntoIter<u8>>::flank(5, 15, EdgeMode::Drop),
+            21
+        );
         assert_relative_eq!(curves[0], 6.3674, epsilon = 1e-4);
         assert_relative_eq!(curves[1], 5.9168, epsilon = 1e-4);
         assert_relative_eq!(curves[2], 5.4776, epsilon = 1e-4);
@@ -964,4 +1771,452 @@ mod tests {
         assert_relative_eq!(curves[6], 3.3483, epsilon = 1e-4);
         assert_relative_eq!(curves[7], 3.1042, epsilon = 1e-4);
     }
+
+    #[test]
+    fn test_rollmean_iter_shrink() {
+        let rolls: Vec<_> = get_some_coords()
+            .into_iter()
+            .roll_mean_iter(2)
+            .with_edge_mode(EdgeMode::Shrink)
+            .collect();
+        // one mean per coordinate instead of dropping 2 on each side
+        assert_eq!(rolls.len(), 12);
+        // the very ends are just the coordinate itself
+        assert_relative_eq!(rolls[0].x_bar, 1.0, epsilon = 1e-4);
+        assert_relative_eq!(rolls[11].x_bar, 12.0, epsilon = 1e-4);
+        // x̄₂ = (½x₁ + x₂ + ½x₃)/2 = (0.5 + 2 + 1.5)/2 = 2
+        assert_relative_eq!(rolls[1].x_bar, 2.0, epsilon = 1e-4);
+        // y̅₁₁ = (½y₁₀ + y₁₁ + ½y₁₂)/2 = 10
+        assert_relative_eq!(rolls[10].y_bar, 10.0, epsilon = 1e-4);
+        // full windows in the interior match the dropping iterator
+        let full: Vec<_> = get_some_coords().into_iter().roll_mean_iter(2).collect();
+        for (shrunk, dropped) in rolls[2..10].iter().zip(full.iter()) {
+            assert_relative_eq!(shrunk.x_bar, dropped.x_bar, epsilon = 1e-12);
+            assert_relative_eq!(shrunk.y_bar, dropped.y_bar, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_eucdist_iter_shrink() {
+        let euc_dists: Vec<_> = get_some_means()
+            .into_iter()
+            .euc_dist_iter(2)
+            .with_edge_mode(EdgeMode::Shrink)
+            .collect();
+        assert_eq!(euc_dists.len(), 9);
+        assert_relative_eq!(euc_dists[0], 0.0, epsilon = 1e-4);
+        // √((5.0-3.0)² + (0.0-0.0)²) = 2
+        assert_relative_eq!(euc_dists[1], 2.0, epsilon = 1e-4);
+        assert_relative_eq!(euc_dists[2], 10.7703, epsilon = 1e-4);
+        assert_relative_eq!(euc_dists[6], 10.0, epsilon = 1e-4);
+        assert_relative_eq!(euc_dists[8], 0.0, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn test_curve_iter_shrink() {
+        let seq = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let curves: Vec<_> = CurveIter::new(
+            seq.iter().cloned(),
+            matrix::RollType::Simple,
+            5,
+            15,
+            0.33335,
+            EdgeMode::Shrink,
+            MeanConvention::Legacy,
+        )
+        .collect();
+        // only the triplet layer still loses one base on each side
+        assert_eq!(curves.len(), seq.len() - 2);
+        assert_eq!(
+            CurveIter::<std::vec::IntoIter<u8>>::flank(5, 15, EdgeMode::Shrink),
+            1
+        );
+        // values with full windows are unchanged
+        assert_relative_eq!(curves[20], 6.3674, epsilon = 1e-4);
+        assert_relative_eq!(curves[27], 3.1042, epsilon = 1e-4);
+        // a sequence shorter than the full window still gets values
+        let short: Vec<_> = CurveIter::new(
+            b"ACGTTGCA".iter().cloned(),
+            matrix::RollType::Simple,
+            5,
+            15,
+            0.33335,
+            EdgeMode::Shrink,
+            MeanConvention::Legacy,
+        )
+        .collect();
+        assert_eq!(short.len(), 6);
+    }
+
+    #[test]
+    fn test_rollmean_iter_standard() {
+        let rolls: Vec<_> = get_some_coords()
+            .into_iter()
+            .roll_mean_iter(2)
+            .with_convention(MeanConvention::Standard)
+            .collect();
+        assert_eq!(rolls.len(), 8);
+        // x is linear, so both conventions agree: (1 + 2 + 3 + 4 + 5)/5 = 3
+        assert_relative_eq!(rolls[0].x_bar, 3.0, epsilon = 1e-4);
+        // y steps from 0 to 10 between the 6th and 7th items
+        // standard: ȳ₅ = (0 + 0 + 0 + 0 + 10)/5 = 2
+        assert_relative_eq!(rolls[2].y_bar, 2.0, epsilon = 1e-4);
+        // legacy:   ȳ₅ = (½0 + 0 + 0 + 0 + ½10)/4 = 1.25
+        let legacy: Vec<_> = get_some_coords().into_iter().roll_mean_iter(2).collect();
+        assert_relative_eq!(legacy[2].y_bar, 1.25, epsilon = 1e-4);
+        // standard: ȳ₆ = (0 + 0 + 0 + 10 + 10)/5 = 4
+        assert_relative_eq!(rolls[3].y_bar, 4.0, epsilon = 1e-4);
+        // legacy:   ȳ₆ = (½0 + 0 + 0 + 10 + ½10)/4 = 3.75
+        assert_relative_eq!(legacy[3].y_bar, 3.75, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn test_rollmean_iter_standard_shrink() {
+        let rolls: Vec<_> = get_some_coords()
+            .into_iter()
+            .roll_mean_iter(2)
+            .with_edge_mode(EdgeMode::Shrink)
+            .with_convention(MeanConvention::Standard)
+            .collect();
+        assert_eq!(rolls.len(), 12);
+        assert_relative_eq!(rolls[0].x_bar, 1.0, epsilon = 1e-4);
+        // ȳ₆ with a full window, (0 + 0 + 0 + 10 + 10)/5 = 4
+        assert_relative_eq!(rolls[5].y_bar, 4.0, epsilon = 1e-4);
+        // ȳ₁₁ shrunk to three items, (10 + 10 + 10)/3 = 10
+        assert_relative_eq!(rolls[10].y_bar, 10.0, epsilon = 1e-4);
+    }
+
+    fn new_curve_iter<I: Iterator<Item = u8>>(seq_iter: I, edge_mode: EdgeMode) -> CurveIter<I> {
+        CurveIter::new(
+            seq_iter,
+            matrix::RollType::Simple,
+            5,
+            15,
+            0.33335,
+            edge_mode,
+            MeanConvention::Legacy,
+        )
+    }
+
+    #[test]
+    fn test_curve_iter_reset() {
+        let first = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC".to_vec();
+        let second = b"GGCAAAAAACGGCAAAAAACGGCAAAAAACGGCAAAAAACGGCAAAAAAC".to_vec();
+        for edge_mode in [EdgeMode::Drop, EdgeMode::Shrink] {
+            let expected: Vec<f64> =
+                new_curve_iter(second.clone().into_iter(), edge_mode).collect();
+            // reset part way through, leaving state in every layer
+            let mut curve_iter = new_curve_iter(first.clone().into_iter(), edge_mode);
+            assert_eq!(curve_iter.by_ref().take(3).count(), 3);
+            curve_iter.reset(second.clone().into_iter());
+            assert_eq!(curve_iter.by_ref().collect::<Vec<f64>>(), expected);
+            // and again after running to the end
+            curve_iter.reset(second.clone().into_iter());
+            assert_eq!(curve_iter.collect::<Vec<f64>>(), expected);
+        }
+    }
+
+    #[test]
+    fn test_curve_buffers_reused() {
+        let first = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC".to_vec();
+        let second = b"GGCAAAAAACGGCAAAAAACGGCAAAAAACGGCAAAAAACGGCAAAAAAC";
+        for edge_mode in [EdgeMode::Drop, EdgeMode::Shrink] {
+            let expected: Vec<f64> = new_curve_iter(second.iter().copied(), edge_mode).collect();
+            // stop part way through, then move the buffers to an iterator of another type
+            let mut curve_iter = new_curve_iter(first.clone().into_iter(), edge_mode);
+            assert_eq!(curve_iter.by_ref().take(3).count(), 3);
+            let buffers = curve_iter.into_buffers();
+            let bytes = buffers.capacity_bytes();
+            assert!(bytes > 0);
+            let curve_iter = CurveIter::with_buffers(
+                second.iter().copied(),
+                matrix::RollType::Simple,
+                5,
+                15,
+                0.33335,
+                edge_mode,
+                MeanConvention::Legacy,
+                buffers,
+            );
+            assert_eq!(curve_iter.collect::<Vec<f64>>(), expected);
+        }
+    }
+
+    #[test]
+    fn test_records_isolated() {
+        use crate::fasta::StreamingReader;
+        let records = [
+            ("chr1", "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC"),
+            ("chr2", "GGCAAAAAACGGCAAAAAACGGCAAAAAACGGCAAAAAACGGCAAAAAAC"),
+            ("chr3", "TTAGGGTTAGGGTTAGGGTTAGGGTTAGGGTTAGGGTTAGGGTTAGGG"),
+        ];
+        let concatenated: String = records
+            .iter()
+            .map(|(name, seq)| format!(">{}\n{}\n", name, seq))
+            .collect();
+        // all records from one file through one reused iterator
+        let mut reader = StreamingReader::new(concatenated.as_bytes());
+        let mut together = Vec::new();
+        let mut curve_iter = new_curve_iter(Vec::new().into_iter(), EdgeMode::Drop);
+        while let Some(name) = reader.next_record().unwrap() {
+            let bases: Vec<u8> = reader.bases().map(|b| b.unwrap()).collect();
+            curve_iter.reset(bases.into_iter());
+            together.push((name, curve_iter.by_ref().collect::<Vec<f64>>()));
+        }
+        // each record from its own file through its own iterator
+        for ((name, values), (expected_name, seq)) in together.iter().zip(records.iter()) {
+            let single = format!(">{}\n{}\n", expected_name, seq);
+            let mut reader = StreamingReader::new(single.as_bytes());
+            assert_eq!(
+                reader.next_record().unwrap().as_deref(),
+                Some(*expected_name)
+            );
+            let bases = reader.bases().map(|b| b.unwrap());
+            let expected: Vec<f64> = new_curve_iter(bases, EdgeMode::Drop).collect();
+            assert_eq!(name, expected_name);
+            assert_eq!(values, &expected);
+        }
+        assert_eq!(together.len(), 3);
+    }
+
+    /// A random sequence length, step sizes, edge mode and convention for a curvature run.
+    fn random_params(rng: &mut property::Rng) -> (usize, usize, usize, EdgeMode, MeanConvention) {
+        let length = property::between(rng, 0, 200);
+        let step_b = property::between(rng, 1, 8);
+        let step_c = property::between(rng, 1, 20);
+        let edge_mode = [EdgeMode::Drop, EdgeMode::Shrink][rng.below(2)];
+        let convention = [MeanConvention::Legacy, MeanConvention::Standard][rng.below(2)];
+        (length, step_b, step_c, edge_mode, convention)
+    }
+
+    /// The rolling mean of `coords` centered on `center` over `half` items on either side,
+    /// computed directly from its definition.
+    fn brute_force_mean(
+        coords: &[(f64, f64)],
+        center: usize,
+        half: usize,
+        convention: MeanConvention,
+    ) -> (f64, f64) {
+        let window = &coords[center - half..=center + half];
+        let sum = |weights: &dyn Fn(usize) -> f64| {
+            window
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(x, y), (i, c)| {
+                    (x + weights(i) * c.0, y + weights(i) * c.1)
+                })
+        };
+        match convention {
+            MeanConvention::Legacy if half > 0 => {
+                let (x, y) = sum(&|i| if i == 0 || i == 2 * half { 0.5 } else { 1.0 });
+                (x / (2 * half) as f64, y / (2 * half) as f64)
+            }
+            _ => {
+                let (x, y) = sum(&|_| 1.0);
+                (x / (2 * half + 1) as f64, y / (2 * half + 1) as f64)
+            }
+        }
+    }
+
+    #[test]
+    fn property_curve_length() {
+        property::check("curve length", |rng| {
+            let (length, step_b, step_c, edge_mode, convention) = random_params(rng);
+            let step_two = [None, Some(property::between(rng, 1, 10))][rng.below(2)];
+            let seq = property::bases(rng, length);
+            let values = CurveIter::new(
+                seq.iter().cloned(),
+                matrix::RollType::Simple,
+                step_b,
+                step_c,
+                0.33335,
+                edge_mode,
+                convention,
+            )
+            .with_step_two(step_two)
+            .count();
+            let extent =
+                CurveIter::<std::iter::Empty<u8>>::rolling_extent(step_b, step_two, convention);
+            let flank = CurveIter::<std::iter::Empty<u8>>::flank(extent, step_c, edge_mode);
+            let expected = length.saturating_sub(2 * flank);
+            if values == expected {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{} bases, step_b {}, step_two {:?}, step_c {}, {:?}: {} values, expected {}",
+                    length, step_b, step_two, step_c, edge_mode, values, expected
+                ))
+            }
+        });
+    }
+
+    #[test]
+    fn test_step_two_defaults() {
+        // the Perl defaults give the half-weighted-ends mean exactly
+        let dna = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATCGGATCCAAT";
+        let curve = |step_two| -> Vec<f64> {
+            new_curve_iter(dna.iter().cloned(), EdgeMode::Drop)
+                .with_step_two(step_two)
+                .collect()
+        };
+        assert_eq!(curve(Some(4)), curve(None));
+        // a narrower inner window reweights the ends, a wider one needs more coordinates
+        assert_ne!(curve(Some(3)), curve(None));
+        assert_eq!(curve(Some(3)).len(), curve(None).len());
+        assert_eq!(curve(Some(8)).len(), curve(None).len() - 6);
+    }
+
+    /// The legacy mean of the original Perl code with `$stepone - 1 = step_b` and
+    /// `$steptwo = step_two`: the coordinates within `step_two` of the center plus those
+    /// `step_b` away weighted `2 / step_two`, over `2 * step_b`.
+    fn perl_mean(
+        coords: &[(f64, f64)],
+        center: usize,
+        step_b: usize,
+        step_two: usize,
+    ) -> (f64, f64) {
+        let mut sum = (0.0, 0.0);
+        for c in &coords[center - step_two..=center + step_two] {
+            sum = (sum.0 + c.0, sum.1 + c.1);
+        }
+        for c in [coords[center - step_b], coords[center + step_b]] {
+            sum = (
+                sum.0 + c.0 / (step_two as f64 / 2.0),
+                sum.1 + c.1 / (step_two as f64 / 2.0),
+            );
+        }
+        let divisor = (step_b * 2) as f64;
+        (sum.0 / divisor, sum.1 / divisor)
+    }
+
+    #[test]
+    fn property_rolling_mean_is_brute_force_mean() {
+        property::check("rolling mean", |rng| {
+            let (length, step_b, _, edge_mode, convention) = random_params(rng);
+            let step_two = [None, Some(property::between(rng, 1, 10))][rng.below(2)];
+            let seq = property::bases(rng, length);
+            let coords: Vec<(f64, f64)> = seq
+                .iter()
+                .cloned()
+                .triplet_windows_iter(matrix::RollType::Simple)
+                .coords_iter()
+                .map(|c| (c.x, c.y))
+                .collect();
+            let means: Vec<RollMeanData> = seq
+                .iter()
+                .cloned()
+                .triplet_windows_iter(matrix::RollType::Simple)
+                .coords_iter()
+                .roll_mean_iter(step_b)
+                .with_edge_mode(edge_mode)
+                .with_convention(convention)
+                .with_inner_step(step_two)
+                .collect();
+            let extent =
+                CurveIter::<std::iter::Empty<u8>>::rolling_extent(step_b, step_two, convention);
+            let centers: Vec<(usize, usize)> = match edge_mode {
+                EdgeMode::Drop => (extent..coords.len().saturating_sub(extent))
+                    .map(|center| (center, extent))
+                    .collect(),
+                EdgeMode::Shrink => (0..coords.len())
+                    .map(|center| (center, extent.min(center).min(coords.len() - 1 - center)))
+                    .collect(),
+            };
+            if means.len() != centers.len() {
+                return Err(format!(
+                    "{} coordinates, step {}, step two {:?}: {} means, expected {}",
+                    coords.len(),
+                    step_b,
+                    step_two,
+                    means.len(),
+                    centers.len()
+                ));
+            }
+            for (mean, &(center, half)) in means.iter().zip(&centers) {
+                let (x, y) = match step_two {
+                    Some(step_two) if convention == MeanConvention::Legacy && half == extent => {
+                        perl_mean(&coords, center, step_b, step_two)
+                    }
+                    _ => brute_force_mean(&coords, center, half.min(step_b), convention),
+                };
+                if !property::close(mean.x_bar, x, 1e-9) || !property::close(mean.y_bar, y, 1e-9) {
+                    return Err(format!(
+                        "{:?} {:?} step two {:?} mean at {} is ({}, {}), expected ({}, {})",
+                        edge_mode, convention, step_two, center, mean.x_bar, mean.y_bar, x, y
+                    ));
+                }
+            }
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn property_rolling_extreme_is_brute_force_extreme() {
+        property::check("rolling extreme", |rng| {
+            let length = property::between(rng, 0, 100);
+            let window = property::between(rng, 1, 20);
+            let values: Vec<f64> = (0..length)
+                .map(|_| match rng.below(10) {
+                    0 => f64::NAN,
+                    _ => rng.unit() * 10.0 - 5.0,
+                })
+                .collect();
+            let maxima: Vec<f64> = values.iter().cloned().rolling_max(window).collect();
+            let minima: Vec<f64> = values.iter().cloned().rolling_min(window).collect();
+            let expected = |pick: fn(f64, f64) -> f64| -> Vec<f64> {
+                if values.len() < window {
+                    return Vec::new();
+                }
+                values
+                    .windows(window)
+                    .map(|w| {
+                        w.iter()
+                            .filter(|v| !v.is_nan())
+                            .cloned()
+                            .reduce(pick)
+                            .unwrap_or(f64::NAN)
+                    })
+                    .collect()
+            };
+            let same = |a: &[f64], b: &[f64]| {
+                a.len() == b.len() && a.iter().zip(b).all(|(x, y)| property::close(*x, *y, 0.0))
+            };
+            if same(&maxima, &expected(f64::max)) && same(&minima, &expected(f64::min)) {
+                Ok(())
+            } else {
+                Err(format!("{} values, window {}", length, window))
+            }
+        });
+    }
+
+    #[test]
+    fn property_reset_matches_new() {
+        property::check("reset matches new", |rng| {
+            let (length, step_b, step_c, edge_mode, convention) = random_params(rng);
+            let first_length = property::between(rng, 0, 200);
+            let first = property::bases(rng, first_length);
+            let second = property::bases(rng, length);
+            let new = |seq: Vec<u8>| {
+                CurveIter::new(
+                    seq.into_iter(),
+                    matrix::RollType::Simple,
+                    step_b,
+                    step_c,
+                    0.33335,
+                    edge_mode,
+                    convention,
+                )
+            };
+            let mut reused = new(first);
+            reused.by_ref().for_each(drop);
+            reused.reset(second.clone().into_iter());
+            let reused: Vec<f64> = reused.collect();
+            let fresh: Vec<f64> = new(second).collect();
+            if reused == fresh {
+                Ok(())
+            } else {
+                Err(format!("{} bases, {:?}", length, edge_mode))
+            }
+        });
+    }
 }
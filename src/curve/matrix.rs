@@ -1,10 +1,19 @@
 //! This module contains some constants/matrices for curvature calculation.
+use crate::text::{clean_line, not_a_number};
+use clap::ValueEnum;
 use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
 
 /// The number of nucleotides in a triplet, which is also the number of dimensions in the
 /// nucleotide matrices used for triplet -> value lookup.
 pub const TRIPLET_SIZE: usize = 3;
 
+/// The bases in the order they index the matrices.
+pub const MATRIX_BASES: [u8; 4] = *b"ATGC";
+
 /// A type alias for a 3D matrix sized 4x4x4 of f64 values. The first dimension is the
 /// first nucleotide in a triplet, the second dimension is the second nucleotide in a triplet,
 /// and the third dimension is the third nucleotide in a triplet.
@@ -77,6 +86,7 @@ pub const ROLL_SIMPLE: NucMatrix = [
     ],
 ];
 
+/// The error of [`matrix_lookup`] for a key that is not a triplet of `A`, `C`, `G` and `T`.
 #[derive(Debug)]
 pub struct MatrixLookupError {
     details: String,
@@ -88,10 +98,283 @@ impl fmt::Display for MatrixLookupError {
     }
 }
 
+impl std::error::Error for MatrixLookupError {}
+
+/// Which roll matrix curvature is computed with.
 #[derive(Debug, Clone)]
-pub(crate) enum RollType {
+pub enum RollType {
     Simple,
     Active,
+    /// A roll matrix other than the built-in ones, e.g. one fitted by `symcurve train`.
+    Custom(Arc<NucMatrix>),
+    /// The twist, roll and tilt matrices of a YAML matrix file.
+    Matrices(Arc<Matrices>),
+}
+
+/// The built-in roll matrices, as chosen with `--roll-type`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum BuiltinRoll {
+    /// the simple roll matrix
+    #[default]
+    Simple,
+    /// the nucleosome-activated roll matrix
+    Active,
+}
+
+impl From<BuiltinRoll> for RollType {
+    fn from(roll: BuiltinRoll) -> Self {
+        match roll {
+            BuiltinRoll::Simple => RollType::Simple,
+            BuiltinRoll::Active => RollType::Active,
+        }
+    }
+}
+
+impl RollType {
+    /// The roll matrix of this type.
+    pub fn matrix(&self) -> &NucMatrix {
+        match self {
+            RollType::Simple => &ROLL_SIMPLE,
+            RollType::Active => &ROLL_ACTIVE,
+            RollType::Custom(matrix) => matrix,
+            RollType::Matrices(matrices) => &matrices.roll,
+        }
+    }
+
+    /// The twist matrix used with this roll type, the built-in one unless a matrix file gives
+    /// another.
+    pub fn twist_matrix(&self) -> &NucMatrix {
+        match self {
+            RollType::Matrices(matrices) => &matrices.twist,
+            _ => &TWIST,
+        }
+    }
+
+    /// The tilt matrix used with this roll type, the built-in one unless a matrix file gives
+    /// another.
+    pub fn tilt_matrix(&self) -> &NucMatrix {
+        match self {
+            RollType::Matrices(matrices) => &matrices.tilt,
+            _ => &TILT,
+        }
+    }
+}
+
+/// The version of the YAML matrix file format written by [`write_matrices_yaml`].
+///
+/// Files without a `schema_version` key predate it and are read as version 1, whose layout they
+/// share. A file of a later version is refused rather than misread.
+pub const MATRIX_SCHEMA_VERSION: u32 = 1;
+
+/// Writes a set of matrices in the YAML matrix file format: a `schema_version` key, then a mapping
+/// for each of `twist`, `roll` and `tilt` from every triplet, e.g. `AAA`, to its value.
+///
+/// # Arguments
+///
+/// * `writer` - Where the YAML is written.
+/// * `comment` - Written as `#` comment lines at the top, one per line of the text.
+/// * `twist` - The twist matrix.
+/// * `roll` - The roll matrix.
+/// * `tilt` - The tilt matrix.
+pub fn write_matrices_yaml<W: Write>(
+    writer: &mut W,
+    comment: &str,
+    twist: &NucMatrix,
+    roll: &NucMatrix,
+    tilt: &NucMatrix,
+) -> io::Result<()> {
+    for line in comment.lines() {
+        writeln!(writer, "# {}", line)?;
+    }
+    writeln!(writer, "schema_version: {}", MATRIX_SCHEMA_VERSION)?;
+    for (name, matrix) in [("twist", twist), ("roll", roll), ("tilt", tilt)] {
+        writeln!(writer, "{}:", name)?;
+        for (i, &first) in MATRIX_BASES.iter().enumerate() {
+            for (j, &second) in MATRIX_BASES.iter().enumerate() {
+                for (k, &third) in MATRIX_BASES.iter().enumerate() {
+                    let triplet = [first, second, third];
+                    writeln!(
+                        writer,
+                        "  {}: {}",
+                        String::from_utf8_lossy(&triplet),
+                        matrix[i][j][k]
+                    )?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A set of twist, roll and tilt matrices, as read from a YAML matrix file.
+///
+/// # Fields
+///
+/// * `twist`: The twist matrix.
+/// * `roll`: The roll matrix.
+/// * `tilt`: The tilt matrix.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matrices {
+    pub twist: NucMatrix,
+    pub roll: NucMatrix,
+    pub tilt: NucMatrix,
+}
+
+impl Default for Matrices {
+    /// The built-in matrices, with the simple roll matrix.
+    fn default() -> Self {
+        Self {
+            twist: TWIST,
+            roll: ROLL_SIMPLE,
+            tilt: TILT,
+        }
+    }
+}
+
+impl Matrices {
+    /// Parses the YAML matrix file format written by [`write_matrices_yaml`].
+    ///
+    /// An optional top-level `schema_version` key, before the sections, gives the version of the
+    /// format (see [`MATRIX_SCHEMA_VERSION`]). Each of the top-level `twist`, `roll` and `tilt`
+    /// keys maps all 64 triplets to a value; a section that is left out keeps its built-in matrix.
+    /// Triplet keys are case-insensitive. Comments and blank lines are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message with the line number if a line is not a section or a triplet entry, the
+    /// schema version is not a whole number, comes after a section or is newer than this version of
+    /// symcurve reads, a section is unknown or repeated, a key is not a triplet of A, C, G and T, a
+    /// triplet is given twice, a value is not a finite number, or a section does not give all 64
+    /// triplets.
+    pub fn parse_yaml(text: &str) -> Result<Self, String> {
+        let mut matrices = Self::default();
+        let mut seen_sections: Vec<&str> = Vec::new();
+        // the section being read, with the triplets it has given so far
+        let mut section: Option<&str> = None;
+        let mut given = [[[false; 4]; 4]; 4];
+        let mut count = 0;
+        let mut schema_version = None;
+        let finish = |section: Option<&str>, count: usize| match section {
+            Some(name) if count != 64 => Err(format!(
+                "section '{}' gives {} of the 64 triplets",
+                name, count
+            )),
+            _ => Ok(()),
+        };
+        for (number, line) in (1..).zip(text.lines()) {
+            let content = clean_line(line)
+                .split('#')
+                .next()
+                .unwrap_or_default()
+                .trim_end();
+            if content.trim().is_empty() {
+                continue;
+            }
+            let at = |message: String| format!("line {}: {}", number, message);
+            let (key, value) = content
+                .split_once(':')
+                .ok_or_else(|| at(format!("expected 'key: value', found '{}'", content.trim())))?;
+            let (key, value) = (key.trim(), value.trim());
+            if !content.starts_with([' ', '\t']) && key == "schema_version" {
+                if schema_version.is_some() || !seen_sections.is_empty() {
+                    return Err(at(
+                        "schema_version must be given once, before the sections".to_string()
+                    ));
+                }
+                let version: u32 = value
+                    .parse()
+                    .map_err(|_| at(format!("schema_version '{}' is not a whole number", value)))?;
+                schema_version = Some(Self::check_schema_version(version).map_err(at)?);
+                continue;
+            }
+            if !content.starts_with([' ', '\t']) {
+                if !value.is_empty() {
+                    return Err(at(format!("section '{}' must be a mapping", key)));
+                }
+                let name = match key {
+                    "twist" => "twist",
+                    "roll" => "roll",
+                    "tilt" => "tilt",
+                    _ => {
+                        return Err(at(format!(
+                            "unknown section '{}': expected twist, roll or tilt",
+                            key
+                        )))
+                    }
+                };
+                if seen_sections.contains(&name) {
+                    return Err(at(format!("section '{}' is given twice", name)));
+                }
+                finish(section, count)?;
+                seen_sections.push(name);
+                section = Some(name);
+                given = [[[false; 4]; 4]; 4];
+                count = 0;
+                continue;
+            }
+            let Some(name) = section else {
+                return Err(at(format!("entry '{}' is outside a section", key)));
+            };
+            let ixs: Vec<usize> = key
+                .bytes()
+                .filter_map(|b| {
+                    MATRIX_BASES
+                        .iter()
+                        .position(|&base| base == b.to_ascii_uppercase())
+                })
+                .collect();
+            if key.len() != TRIPLET_SIZE || ixs.len() != TRIPLET_SIZE {
+                return Err(at(format!("'{}' is not a triplet of A, C, G and T", key)));
+            }
+            let (i, j, k) = (ixs[0], ixs[1], ixs[2]);
+            if given[i][j][k] {
+                return Err(at(format!("triplet {} is given twice in '{}'", key, name)));
+            }
+            let value: f64 = value
+                .parse()
+                .ok()
+                .filter(|v: &f64| v.is_finite())
+                .ok_or_else(|| at(not_a_number(value)))?;
+            given[i][j][k] = true;
+            count += 1;
+            let matrix = match name {
+                "twist" => &mut matrices.twist,
+                "roll" => &mut matrices.roll,
+                _ => &mut matrices.tilt,
+            };
+            matrix[i][j][k] = value;
+        }
+        finish(section, count)?;
+        Ok(matrices)
+    }
+
+    /// Checks that a matrix file of schema version `version` can be read, and gives the version
+    /// it is read as.
+    ///
+    /// Every version so far has the layout of version 1, so older files need no changes; a later
+    /// version that changes the layout converts older files where they are parsed.
+    fn check_schema_version(version: u32) -> Result<u32, String> {
+        match version {
+            1..=MATRIX_SCHEMA_VERSION => Ok(version),
+            0 => Err("schema_version 0 is not a version; versions start at 1".to_string()),
+            _ => Err(format!(
+                "schema_version {} is newer than version {} that this symcurve reads; upgrade \
+                 symcurve to use this file",
+                version, MATRIX_SCHEMA_VERSION
+            )),
+        }
+    }
+
+    /// Reads a YAML matrix file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, or an `io::ErrorKind::InvalidData` error if it
+    /// does not parse with [`Matrices::parse_yaml`].
+    pub fn read_yaml(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse_yaml(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
 }
 
 /// Looks up a value in a nucleotide matrix based on a triplet of nucleotides.
@@ -103,7 +386,7 @@ pub(crate) enum RollType {
 /// # Arguments
 ///
 /// * `triplet` - A slice of u8 representing a triplet of nucleotides. Each u8 should be the ASCII
-/// value of 'A', 'C', 'G', or 'T'.
+///   value of 'A', 'C', 'G', or 'T'.
 /// * `matrix` - A reference to a `NucMatrix` to look up the value in.
 ///
 /// # Returns
@@ -116,17 +399,16 @@ pub(crate) enum RollType {
 ///
 /// Returns a `MatrixLookupError` if the triplet is not of length 3.  An unrecognized nucleotide
 /// will cause this error because the triplet will not be of length 3.
-pub(crate) fn matrix_lookup(triplet: &[u8], matrix: &NucMatrix) -> Result<f64, MatrixLookupError> {
+pub fn matrix_lookup(triplet: &[u8], matrix: &NucMatrix) -> Result<f64, MatrixLookupError> {
     let ixs: Vec<usize> = triplet
         .iter()
-        .map(|&x| match x {
+        .filter_map(|&x| match x {
             b'A' => Some(0),
             b'T' => Some(1),
             b'G' => Some(2),
             b'C' => Some(3),
             _ => None,
         })
-        .flatten()
         .collect();
     if ixs.len() != 3 {
         return Err(MatrixLookupError {
@@ -168,6 +450,128 @@ mod tests {
         assert!(matrix_lookup(b"AAN", &ROLL_ACTIVE).is_err());
     }
 
+    #[test]
+    fn test_roll_type_matrix() {
+        let mut custom = ROLL_SIMPLE;
+        custom[0][0][0] = 2.5;
+        let roll_type = RollType::Custom(Arc::new(custom));
+        assert_relative_eq!(matrix_lookup(b"AAA", roll_type.matrix()).unwrap(), 2.5);
+        assert_relative_eq!(RollType::Active.matrix()[1][2][0], 7.7);
+        assert_eq!(RollType::Active.twist_matrix(), &TWIST);
+        let mut matrices = Matrices::default();
+        matrices.twist[0][0][0] = 0.5;
+        matrices.tilt[0][0][0] = -1.5;
+        let roll_type = RollType::Matrices(Arc::new(matrices));
+        assert_relative_eq!(roll_type.twist_matrix()[0][0][0], 0.5);
+        assert_relative_eq!(roll_type.tilt_matrix()[0][0][0], -1.5);
+        assert_eq!(roll_type.matrix(), &ROLL_SIMPLE);
+    }
+
+    #[test]
+    fn test_write_matrices_yaml() {
+        let mut out = Vec::new();
+        write_matrices_yaml(&mut out, "fitted\nby hand", &TWIST, &ROLL_SIMPLE, &TILT).unwrap();
+        let yaml = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = yaml.lines().collect();
+        assert_eq!(lines.len(), 3 + 3 * 65);
+        assert_eq!(
+            lines[..5],
+            [
+                "# fitted",
+                "# by hand",
+                "schema_version: 1",
+                "twist:",
+                "  AAA: 0.598647428"
+            ]
+        );
+        assert_eq!(lines[68], "roll:");
+        assert!(lines.contains(&"  CCA: 0.7"));
+        assert_eq!(lines[133..135], ["tilt:", "  AAA: 0"]);
+    }
+
+    #[test]
+    fn test_parse_yaml() {
+        let mut roll = ROLL_ACTIVE;
+        roll[3][2][1] = 12.5;
+        let mut out = Vec::new();
+        write_matrices_yaml(&mut out, "test", &TWIST, &roll, &TILT).unwrap();
+        let matrices = Matrices::parse_yaml(&String::from_utf8(out).unwrap()).unwrap();
+        assert_eq!(matrices.roll, roll);
+        assert_eq!(matrices.twist, TWIST);
+        // left out sections keep the built-in matrices
+        assert_eq!(
+            Matrices::parse_yaml("# nothing\n").unwrap(),
+            Matrices::default()
+        );
+    }
+
+    #[test]
+    fn test_parse_yaml_errors() {
+        let err = |text: &str| Matrices::parse_yaml(text).unwrap_err();
+        assert_eq!(
+            err("bend:\n  AAA: 1\n"),
+            "line 1: unknown section 'bend': expected twist, roll or tilt"
+        );
+        assert_eq!(
+            err("roll:\n  AAN: 1\n"),
+            "line 2: 'AAN' is not a triplet of A, C, G and T"
+        );
+        assert_eq!(
+            err("roll:\n  AAA: 1\n  aaa: 2\n"),
+            "line 3: triplet aaa is given twice in 'roll'"
+        );
+        assert_eq!(err("roll:\n  AAA: x\n"), "line 2: 'x' is not a number");
+        assert_eq!(
+            err("roll:\n  AAA: 0,5\n"),
+            "line 2: '0,5' is not a number: use '.' as the decimal point"
+        );
+        assert_eq!(
+            err("roll:\n  AAA: 1\ntilt:\n"),
+            "section 'roll' gives 1 of the 64 triplets"
+        );
+        assert_eq!(
+            err("  AAA: 1\n"),
+            "line 1: entry 'AAA' is outside a section"
+        );
+    }
+
+    #[test]
+    fn test_parse_yaml_schema_version() {
+        let mut roll = ROLL_SIMPLE;
+        roll[0][1][2] = 3.5;
+        let mut out = Vec::new();
+        write_matrices_yaml(&mut out, "test", &TWIST, &roll, &TILT).unwrap();
+        let yaml = String::from_utf8(out).unwrap();
+        // files from before schema versions are read as version 1
+        let unversioned = yaml.replace("schema_version: 1\n", "");
+        assert_eq!(
+            Matrices::parse_yaml(&unversioned).unwrap(),
+            Matrices::parse_yaml(&yaml).unwrap()
+        );
+        assert_eq!(Matrices::parse_yaml(&yaml).unwrap().roll, roll);
+        // as saved by a Windows editor
+        let windows = format!("\u{feff}{}", yaml.replace('\n', " \r\n"));
+        assert_eq!(Matrices::parse_yaml(&windows).unwrap().roll, roll);
+        let err = |text: &str| Matrices::parse_yaml(text).unwrap_err();
+        assert_eq!(
+            err("schema_version: 2\n"),
+            "line 1: schema_version 2 is newer than version 1 that this symcurve reads; \
+             upgrade symcurve to use this file"
+        );
+        assert_eq!(
+            err("schema_version: 0\n"),
+            "line 1: schema_version 0 is not a version; versions start at 1"
+        );
+        assert_eq!(
+            err("schema_version: one\n"),
+            "line 1: schema_version 'one' is not a whole number"
+        );
+        assert_eq!(
+            err("schema_version: 1\nschema_version: 1\n"),
+            "line 2: schema_version must be given once, before the sections"
+        );
+    }
+
     #[test]
     fn test_matrix_lookup_error_display() {
         let error = MatrixLookupError {
@@ -0,0 +1,269 @@
+//! Memoized curvature for repetitive sequence.
+//!
+//! Each curvature value depends only on the bases in its window: the coordinates behind it are
+//! cumulative, but the distance between two rolling means does not change when the whole path is
+//! shifted or rotated. Telomeric and satellite repeats, poly-A runs and microsatellites therefore
+//! produce the same window content, and the same value, over and over again.
+//!
+//! The memoization here is keyed on window content. While scanning the sequence, a window whose
+//! bases match the window one period earlier (for any period up to [`MAX_PERIOD`]) reuses that
+//! window's value instead of being computed. Matches are found by comparing bases directly rather
+//! than by hashing, so there are no collisions to worry about, and the comparisons are done
+//! incrementally so each base is looked at once per period. Everything else is computed with the
+//! usual streaming `CurveIter`, one stretch of consecutive non-repeating windows at a time.
+//!
+//! Reused values come from a different stretch of the cumulative coordinates, so they may differ
+//! from a straight computation in the last few bits of the `f64`, but not beyond that.
+
+use crate::curve::iters::{CurveIter, EdgeMode, MeanConvention};
+use crate::curve::matrix::RollType;
+
+/// The longest repeat period that is detected. Covers homopolymers, microsatellites and the
+/// telomeric and HSat2/3 satellite repeats.
+pub const MAX_PERIOD: usize = 12;
+
+/// Counts of how curvature values were produced.
+///
+/// # Fields
+///
+/// * `computed`: Values computed by the iterator stack.
+/// * `reused`: Values copied from an identical window one period earlier.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoStats {
+    pub computed: usize,
+    pub reused: usize,
+}
+
+/// For each window, the period of the repeat it belongs to, or `0` if it has to be computed.
+///
+/// # Arguments
+///
+/// * `seq` - The sequence.
+/// * `window` - The number of bases behind each curvature value.
+fn repeat_periods(seq: &[u8], window: usize) -> Vec<usize> {
+    let mut periods = Vec::with_capacity(seq.len() + 1 - window);
+    // runs[p] is the number of consecutive bases ending here that equal the base p earlier
+    let mut runs = [0usize; MAX_PERIOD + 1];
+    for (j, base) in seq.iter().enumerate() {
+        for (p, run) in runs.iter_mut().enumerate().skip(1) {
+            if j >= p && seq[j - p] == *base {
+                *run += 1;
+            } else {
+                *run = 0;
+            }
+        }
+        if j + 1 >= window {
+            let period = (1..=MAX_PERIOD).find(|&p| runs[p] >= window).unwrap_or(0);
+            periods.push(period);
+        }
+    }
+    periods
+}
+
+/// Computes the curvature of a sequence, reusing values across repeated windows.
+///
+/// Produces the same values as `CurveIter` with `EdgeMode::Drop`: one per window of
+/// `2 * (extent + step_c) + 3` bases, where `extent` is the
+/// [`rolling_extent`](CurveIter::rolling_extent), so none for sequences shorter than one window.
+///
+/// # Arguments
+///
+/// * `seq` - The sequence.
+/// * `roll_type` - The type of roll (either simple or activated).
+/// * `step_b` - Half of the rolling mean window size minus one.
+/// * `step_two` - The half-width of the fully weighted part of the legacy rolling mean window, if
+///   set.
+/// * `step_c` - The distance from the midpoint base to the sides in the curve window.
+/// * `curve_scale` - The factor curvature values are scaled by.
+/// * `convention` - The averaging convention of the rolling mean.
+/// * `stats` - Counts of computed and reused values, added to.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn memoized_curvature(
+    seq: &[u8],
+    roll_type: RollType,
+    step_b: usize,
+    step_two: Option<usize>,
+    step_c: usize,
+    curve_scale: f64,
+    convention: MeanConvention,
+    stats: &mut MemoStats,
+) -> Vec<f64> {
+    let extent = CurveIter::<std::iter::Empty<u8>>::rolling_extent(step_b, step_two, convention);
+    let window = 2 * (extent + step_c) + 3;
+    if seq.len() < window {
+        return Vec::new();
+    }
+    let periods = repeat_periods(seq, window);
+    let mut values = Vec::with_capacity(periods.len());
+    let mut i = 0;
+    while i < periods.len() {
+        if periods[i] > 0 {
+            values.push(values[i - periods[i]]);
+            stats.reused += 1;
+            i += 1;
+            continue;
+        }
+        let end = periods[i..]
+            .iter()
+            .position(|&p| p > 0)
+            .map_or(periods.len(), |len| i + len);
+        values.extend(
+            CurveIter::new(
+                seq[i..end + window - 1].iter().cloned(),
+                roll_type.clone(),
+                step_b,
+                step_c,
+                curve_scale,
+                EdgeMode::Drop,
+                convention,
+            )
+            .with_step_two(step_two),
+        );
+        stats.computed += end - i;
+        i = end;
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn direct(seq: &[u8]) -> Vec<f64> {
+        CurveIter::new(
+            seq.iter().cloned(),
+            RollType::Simple,
+            5,
+            15,
+            0.33335,
+            EdgeMode::Drop,
+            MeanConvention::Legacy,
+        )
+        .collect()
+    }
+
+    fn memoized(seq: &[u8], stats: &mut MemoStats) -> Vec<f64> {
+        memoized_curvature(
+            seq,
+            RollType::Simple,
+            5,
+            None,
+            15,
+            0.33335,
+            MeanConvention::Legacy,
+            stats,
+        )
+    }
+
+    /// A sequence alternating between unique-ish sequence and repeats of several periods.
+    fn mixed_sequence() -> Vec<u8> {
+        let unique = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let mut seq = unique.to_vec();
+        seq.extend(b"TTAGGG".repeat(100));
+        seq.extend(unique);
+        seq.extend([b'A'; 200]);
+        seq.extend(b"CA".repeat(40));
+        seq.extend(unique);
+        seq
+    }
+
+    #[test]
+    fn test_repeat_periods() {
+        let mut seq = b"ACGTTGCA".to_vec();
+        seq.extend(b"CAG".repeat(5));
+        // windows of 6 bases: the repeat covers windows starting at 8 through 17, and the
+        // first three of those have nothing earlier to match
+        let periods = repeat_periods(&seq, 6);
+        assert_eq!(periods.len(), seq.len() - 5);
+        assert_eq!(&periods[..11], &[0; 11]);
+        assert_eq!(&periods[11..], &[3; 7]);
+        assert_eq!(repeat_periods(&[b'A'; 8], 6), vec![0, 1, 1]);
+    }
+
+    #[test]
+    fn test_memoized_matches_direct() {
+        let seq = mixed_sequence();
+        let mut stats = MemoStats::default();
+        let expected = direct(&seq);
+        let values = memoized(&seq, &mut stats);
+        assert_eq!(values.len(), expected.len());
+        for (value, expected) in values.iter().zip(expected.iter()) {
+            assert!((value - expected).abs() < 1e-9, "{} != {}", value, expected);
+        }
+        assert_eq!(stats.computed + stats.reused, values.len());
+        assert!(stats.reused > stats.computed);
+    }
+
+    #[test]
+    fn test_memoized_step_two() {
+        let seq = mixed_sequence();
+        let expected: Vec<f64> = CurveIter::new(
+            seq.iter().cloned(),
+            RollType::Simple,
+            5,
+            15,
+            0.33335,
+            EdgeMode::Drop,
+            MeanConvention::Legacy,
+        )
+        .with_step_two(Some(8))
+        .collect();
+        let mut stats = MemoStats::default();
+        let values = memoized_curvature(
+            &seq,
+            RollType::Simple,
+            5,
+            Some(8),
+            15,
+            0.33335,
+            MeanConvention::Legacy,
+            &mut stats,
+        );
+        assert_eq!(values.len(), expected.len());
+        for (value, expected) in values.iter().zip(expected.iter()) {
+            assert!((value - expected).abs() < 1e-9, "{} != {}", value, expected);
+        }
+        assert!(stats.reused > 0);
+    }
+
+    #[test]
+    fn test_memoized_short_and_unique() {
+        let mut stats = MemoStats::default();
+        assert!(memoized(b"ACGT", &mut stats).is_empty());
+        let unique = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        assert_eq!(memoized(unique, &mut stats), direct(unique));
+        assert_eq!(stats.reused, 0);
+        assert_eq!(stats.computed, 8);
+    }
+
+    /// Benchmark on telomeric `(TTAGGG)n` and HSat3 `(CATTC)n` centromeric satellite sequence.
+    /// Run with `cargo test --release -- --ignored bench_ --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_repeats() {
+        for (name, unit) in [
+            ("telomeric", &b"TTAGGG"[..]),
+            ("centromeric", &b"CATTC"[..]),
+        ] {
+            let seq = unit.repeat(2_000_000);
+            let start = Instant::now();
+            let expected = direct(&seq);
+            let direct_time = start.elapsed();
+            let mut stats = MemoStats::default();
+            let start = Instant::now();
+            let values = memoized(&seq, &mut stats);
+            let memo_time = start.elapsed();
+            assert_eq!(values.len(), expected.len());
+            println!(
+                "{}: {} bp, direct {:?}, memoized {:?} ({} computed, {} reused)",
+                name,
+                seq.len(),
+                direct_time,
+                memo_time,
+                stats.computed,
+                stats.reused
+            );
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! Pluggable curvature metrics.
+//!
+//! symcurve's built-in curvature is the distance between the rolling-mean coordinates
+//! `step_c` positions either side of each center. The `CurveMetric` trait opens that last step
+//! up: a metric gets the whole window of rolling-mean coordinates around a center and returns one
+//! value, while symcurve keeps doing the FASTA reading, triplet lookup, coordinate and rolling
+//! mean computation and output. Research code can prototype a new curvature definition in its
+//! own crate by implementing the trait and registering it:
+//!
+//! ```
+//! use symcurve::curve::iters::RollMeanData;
+//! use symcurve::curve::metric::{CurveMetric, MetricRegistry};
+//!
+//! /// Length of the path through the window, relative to its end-to-end distance.
+//! struct Tortuosity;
+//!
+//! impl CurveMetric for Tortuosity {
+//!     fn name(&self) -> &str {
+//!         "tortuosity"
+//!     }
+//!
+//!     fn compute(&self, window: &[RollMeanData]) -> f64 {
+//!         let dist = |a: &RollMeanData, b: &RollMeanData| {
+//!             (b.x_bar - a.x_bar).hypot(b.y_bar - a.y_bar)
+//!         };
+//!         let path: f64 = window.windows(2).map(|w| dist(&w[0], &w[1])).sum();
+//!         path / dist(&window[0], &window[window.len() - 1])
+//!     }
+//! }
+//!
+//! let mut registry = MetricRegistry::with_builtins();
+//! registry.register(Box::new(Tortuosity));
+//! assert!(registry.get("tortuosity").is_some());
+//! ```
+
+use crate::curve::iters::{roll_means, EdgeMode, MeanConvention, RollMeanData};
+use crate::curve::matrix::RollType;
+use std::collections::VecDeque;
+
+/// A curvature metric computed from a window of rolling-mean coordinates.
+///
+/// Metrics must be `Send + Sync` so one registry can be shared by worker threads.
+pub trait CurveMetric: Send + Sync {
+    /// The name the metric is registered and selected under.
+    fn name(&self) -> &str;
+
+    /// Computes the metric for one window.
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - The `2 * step_c + 1` rolling-mean coordinates centered on the position the
+    ///   value is for, in sequence order.
+    fn compute(&self, window: &[RollMeanData]) -> f64;
+}
+
+/// The built-in curvature: the Euclidean distance between the two ends of the window.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EndToEndDistance;
+
+impl CurveMetric for EndToEndDistance {
+    fn name(&self) -> &str {
+        "distance"
+    }
+
+    fn compute(&self, window: &[RollMeanData]) -> f64 {
+        let (left, right) = (&window[0], &window[window.len() - 1]);
+        ((right.y_bar - left.y_bar).powf(2.0) + (right.x_bar - left.x_bar).powf(2.0)).sqrt()
+    }
+}
+
+/// The accumulated direction change along the window: the sum of the signed turning angles
+/// between successive steps of the rolling-mean path, in radians, counterclockwise positive.
+///
+/// Together with [`EndToEndDistance`] this describes the local geometry: how far apart the ends of
+/// the window are and how much, and which way, the path bends between them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TurningAngle;
+
+impl CurveMetric for TurningAngle {
+    fn name(&self) -> &str {
+        "turning"
+    }
+
+    fn compute(&self, window: &[RollMeanData]) -> f64 {
+        let steps: Vec<(f64, f64)> = window
+            .windows(2)
+            .map(|w| (w[1].x_bar - w[0].x_bar, w[1].y_bar - w[0].y_bar))
+            .collect();
+        steps
+            .windows(2)
+            .map(|s| {
+                let ((ax, ay), (bx, by)) = (s[0], s[1]);
+                (ax * by - ay * bx).atan2(ax * bx + ay * by)
+            })
+            .sum()
+    }
+}
+
+/// The metrics available by name.
+///
+/// # Fields
+///
+/// * `metrics`: The registered metrics, in registration order.
+#[derive(Default)]
+pub struct MetricRegistry {
+    metrics: Vec<Box<dyn CurveMetric>>,
+}
+
+impl MetricRegistry {
+    /// Constructor for an empty `MetricRegistry`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry holding the built-in metrics.
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        registry.register(Box::new(EndToEndDistance));
+        registry.register(Box::new(TurningAngle));
+        registry
+    }
+
+    /// Registers a metric, replacing any registered under the same name.
+    pub fn register(&mut self, metric: Box<dyn CurveMetric>) {
+        self.metrics.retain(|m| m.name() != metric.name());
+        self.metrics.push(metric);
+    }
+
+    /// Looks up a metric by name.
+    pub fn get(&self, name: &str) -> Option<&dyn CurveMetric> {
+        self.metrics
+            .iter()
+            .find(|metric| metric.name() == name)
+            .map(|metric| metric.as_ref())
+    }
+
+    /// The names of the registered metrics, in registration order.
+    pub fn names(&self) -> Vec<&str> {
+        self.metrics.iter().map(|metric| metric.name()).collect()
+    }
+}
+
+/// An iterator that applies a `CurveMetric` to each full window of rolling-mean coordinates.
+///
+/// # Fields
+///
+/// * `inner`: The inner iterator that yields `RollMeanData`.
+/// * `buffer`: The current window.
+/// * `window_size`: `2 * step_c + 1`.
+/// * `metric`: The metric applied to each window.
+/// * `scale`: The factor values are scaled by.
+pub struct MetricIter<'m, I: Iterator<Item = RollMeanData>> {
+    inner: I,
+    buffer: VecDeque<RollMeanData>,
+    window_size: usize,
+    metric: &'m dyn CurveMetric,
+    scale: f64,
+}
+
+impl<I: Iterator<Item = RollMeanData>> Iterator for MetricIter<'_, I> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffer.len() < self.window_size {
+            self.buffer.push_back(self.inner.next()?);
+        }
+        let value = self.metric.compute(self.buffer.make_contiguous()) * self.scale;
+        self.buffer.pop_front();
+        Some(value)
+    }
+}
+
+/// Computes a metric along a sequence.
+///
+/// Windows are dropped at the ends of the sequence as with `EdgeMode::Drop`, so with the
+/// `EndToEndDistance` metric the values are those of the built-in curvature.
+///
+/// # Arguments
+///
+/// * `seq_iter` - An iterator that yields the bases.
+/// * `roll_type` - The type of roll (either simple or activated).
+/// * `step_b` - Half of the rolling mean window size minus one.
+/// * `step_c` - The distance from the midpoint to the ends of the metric window.
+/// * `scale` - The factor values are scaled by.
+/// * `convention` - The averaging convention of the rolling mean.
+/// * `metric` - The metric.
+pub fn metric_values<'m, I: Iterator<Item = u8>>(
+    seq_iter: I,
+    roll_type: RollType,
+    step_b: usize,
+    step_c: usize,
+    scale: f64,
+    convention: MeanConvention,
+    metric: &'m dyn CurveMetric,
+) -> MetricIter<'m, impl Iterator<Item = RollMeanData>> {
+    MetricIter {
+        inner: roll_means(
+            seq_iter,
+            roll_type,
+            step_b,
+            None,
+            EdgeMode::Drop,
+            convention,
+        ),
+        buffer: VecDeque::with_capacity(2 * step_c + 1),
+        window_size: 2 * step_c + 1,
+        metric,
+        scale,
+    }
+}
+
+/// Computes the curvature magnitude and the accumulated direction change of each window.
+///
+/// # Arguments
+///
+/// * `seq_iter` - An iterator that yields the bases.
+/// * `roll_type` - The type of roll (either simple or activated).
+/// * `step_b` - Half of the rolling mean window size minus one.
+/// * `step_two` - The half-width of the fully weighted part of the legacy rolling mean window, if
+///   set.
+/// * `step_c` - The distance from the midpoint to the ends of the window.
+/// * `curve_scale` - The factor the magnitude is scaled by.
+/// * `convention` - The averaging convention of the rolling mean.
+///
+/// # Returns
+///
+/// For each window, as with `EdgeMode::Drop`, the chord distance scaled by `curve_scale` (the
+/// curvature value) and the signed [`TurningAngle`] in radians.
+pub fn magnitude_phase<I: Iterator<Item = u8>>(
+    seq_iter: I,
+    roll_type: RollType,
+    step_b: usize,
+    step_two: Option<usize>,
+    step_c: usize,
+    curve_scale: f64,
+    convention: MeanConvention,
+) -> impl Iterator<Item = (f64, f64)> {
+    let mut inner = roll_means(
+        seq_iter,
+        roll_type,
+        step_b,
+        step_two,
+        EdgeMode::Drop,
+        convention,
+    );
+    let window_size = 2 * step_c + 1;
+    let mut buffer = VecDeque::with_capacity(window_size);
+    std::iter::from_fn(move || {
+        while buffer.len() < window_size {
+            buffer.push_back(inner.next()?);
+        }
+        let window = buffer.make_contiguous();
+        let magnitude = EndToEndDistance.compute(window) * curve_scale;
+        let phase = TurningAngle.compute(window);
+        buffer.pop_front();
+        Some((magnitude, phase))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::iters::CurveIter;
+
+    const SEQ: &[u8] = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+
+    /// Sums the x coordinate over the window, as a stand-in third-party metric.
+    struct SumX;
+
+    impl CurveMetric for SumX {
+        fn name(&self) -> &str {
+            "sum-x"
+        }
+
+        fn compute(&self, window: &[RollMeanData]) -> f64 {
+            window.iter().map(|point| point.x_bar).sum()
+        }
+    }
+
+    #[test]
+    fn test_builtin_matches_curve_iter() {
+        let expected: Vec<f64> = CurveIter::new(
+            SEQ.iter().cloned(),
+            RollType::Simple,
+            5,
+            15,
+            0.33335,
+            EdgeMode::Drop,
+            MeanConvention::Legacy,
+        )
+        .collect();
+        let registry = MetricRegistry::with_builtins();
+        let metric = registry.get("distance").unwrap();
+        let values: Vec<f64> = metric_values(
+            SEQ.iter().cloned(),
+            RollType::Simple,
+            5,
+            15,
+            0.33335,
+            MeanConvention::Legacy,
+            metric,
+        )
+        .collect();
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = MetricRegistry::with_builtins();
+        registry.register(Box::new(SumX));
+        assert_eq!(registry.names(), vec!["distance", "turning", "sum-x"]);
+        // registering again replaces rather than duplicates
+        registry.register(Box::new(SumX));
+        assert_eq!(registry.names(), vec!["distance", "turning", "sum-x"]);
+        assert!(registry.get("nope").is_none());
+        let values: Vec<f64> = metric_values(
+            SEQ.iter().cloned(),
+            RollType::Simple,
+            5,
+            15,
+            1.0,
+            MeanConvention::Legacy,
+            registry.get("sum-x").unwrap(),
+        )
+        .collect();
+        assert_eq!(values.len(), 8);
+    }
+
+    #[test]
+    fn test_turning_angle() {
+        let point = |x_bar, y_bar| RollMeanData { x_bar, y_bar };
+        // a left turn then another left turn, a quarter circle each
+        let window = [
+            point(0.0, 0.0),
+            point(1.0, 0.0),
+            point(1.0, 1.0),
+            point(0.0, 1.0),
+        ];
+        approx::assert_abs_diff_eq!(TurningAngle.compute(&window), std::f64::consts::PI);
+        let window = [point(0.0, 0.0), point(1.0, 0.0), point(1.0, -1.0)];
+        approx::assert_abs_diff_eq!(TurningAngle.compute(&window), -std::f64::consts::FRAC_PI_2);
+        let straight = [point(0.0, 0.0), point(1.0, 1.0), point(2.0, 2.0)];
+        assert_eq!(TurningAngle.compute(&straight), 0.0);
+    }
+
+    #[test]
+    fn test_magnitude_phase() {
+        let expected: Vec<f64> = CurveIter::new(
+            SEQ.iter().cloned(),
+            RollType::Simple,
+            5,
+            15,
+            0.33335,
+            EdgeMode::Drop,
+            MeanConvention::Legacy,
+        )
+        .collect();
+        let joint: Vec<(f64, f64)> = magnitude_phase(
+            SEQ.iter().cloned(),
+            RollType::Simple,
+            5,
+            None,
+            15,
+            0.33335,
+            MeanConvention::Legacy,
+        )
+        .collect();
+        let magnitudes: Vec<f64> = joint.iter().map(|&(m, _)| m).collect();
+        assert_eq!(magnitudes, expected);
+        assert!(joint.iter().all(|&(_, phase)| phase.is_finite()));
+    }
+}
@@ -0,0 +1,399 @@
+//! Mirror symmetry of curvature.
+//!
+//! A curvature profile that reads the same in both directions around a point suggests a dyad:
+//! the axis a nucleosome or other symmetric DNA-binding complex would sit on. This module scores
+//! a stretch of curvature values around a given axis with the SymCurv score of the original perl
+//! script (`SYMCURV` in `perl/SymCurv_prediction_v22.pl`), and infers the axis position within a
+//! window as the one with the best score.
+//!
+//! Axes may fall on a base or between two bases, so positions are handled in half-base units
+//! (`center2` is twice the 0-based axis position).
+//!
+//! The symmetry track (`--symcurve-win`, `--symcurve-step`) slides a window along the curvature
+//! track and scores each window around its center with [`mirror_score`].
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+/// The inferred symmetry axis of a window.
+///
+/// # Fields
+///
+/// * `position`: The 0-based axis position relative to the start of the window; a `.5` position
+///   lies between two bases.
+/// * `score`: The SymCurv score around the axis, as [`mirror_score`] gives it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Axis {
+    pub position: f64,
+    pub score: f64,
+}
+
+impl Axis {
+    /// The 0-based, half-open interval of the bases the axis lies on: one base for an axis on a
+    /// base, or the two bases either side of an axis between them.
+    pub fn bases(&self) -> (u64, u64) {
+        let start = self.position.floor() as u64;
+        let end = self.position.ceil() as u64 + 1;
+        (start, end)
+    }
+}
+
+/// The smallest rise from the dyad to its two neighbours, summed, that counts as a minimum.
+const MIN_RISE: f64 = 0.01;
+
+/// The score of a perfect mirror image, whose differences sum to 0.
+const MIRROR_SCORE: f64 = 100.0;
+
+/// Scores the symmetry of values around an axis as the perl script does.
+///
+/// The sum of `|left - right|` over the values at equal distances either side of the axis is
+/// weighted by how sharp a minimum the axis is: the score is `weight / Σ|left - right|`, where
+/// `weight` is `1 / ((before - dyad) + (after - dyad))` if the dyad value is below both its
+/// neighbours and that rise is at least 0.01, and 0 otherwise. A perfect mirror image scores 100
+/// whatever its shape. An axis between two bases takes the mean of the two as its dyad value and
+/// the bases either side of the pair as its neighbours.
+///
+/// # Arguments
+///
+/// * `values` - The values.
+/// * `center2` - Twice the 0-based axis position.
+/// * `half_width` - The number of pairs compared, i.e. the distance reached on each side.
+///
+/// # Returns
+///
+/// The score, or `None` if the pairs or the neighbours of the dyad do not fit inside `values` or
+/// any value used is `NaN`.
+pub fn mirror_score(values: &[f64], center2: usize, half_width: usize) -> Option<f64> {
+    let c = center2 / 2;
+    let (dyad, before, after) = if center2.is_multiple_of(2) {
+        (
+            *values.get(c)?,
+            *values.get(c.checked_sub(1)?)?,
+            *values.get(c + 1)?,
+        )
+    } else {
+        let dyad = (*values.get(c)? + *values.get(c + 1)?) / 2.0;
+        (dyad, *values.get(c.checked_sub(1)?)?, *values.get(c + 2)?)
+    };
+    if dyad.is_nan() || before.is_nan() || after.is_nan() {
+        return None;
+    }
+    let mut diff = 0.0;
+    for k in 1..=half_width {
+        // on base c the pairs are (c - k, c + k); between c and c + 1, (c + 1 - k, c + k)
+        let right = center2 / 2 + k;
+        let left = center2.checked_sub(right)?;
+        let (l, r) = (*values.get(left)?, *values.get(right)?);
+        if l.is_nan() || r.is_nan() {
+            return None;
+        }
+        diff += (l - r).abs();
+    }
+    let rise = (before - dyad) + (after - dyad);
+    let weight = if dyad < before && dyad < after && rise >= MIN_RISE {
+        1.0 / rise
+    } else {
+        0.0
+    };
+    if diff == 0.0 {
+        Some(MIRROR_SCORE)
+    } else {
+        Some(weight / diff)
+    }
+}
+
+/// Infers the symmetry axis of a window as the axis with the best mirror score.
+///
+/// Every axis, on a base or between two, with `half_width` values on both sides inside the window
+/// is tried. Ties go to the axis nearest the middle of the window.
+///
+/// # Arguments
+///
+/// * `values` - The values of the window.
+/// * `half_width` - The number of pairs compared for each axis.
+///
+/// # Returns
+///
+/// The best axis, or `None` if no axis could be scored.
+pub fn symmetry_axis(values: &[f64], half_width: usize) -> Option<Axis> {
+    let middle2 = values.len().saturating_sub(1);
+    let mut best: Option<(usize, f64)> = None;
+    for center2 in 2 * half_width..(2 * values.len()).saturating_sub(2 * half_width + 1) {
+        let Some(score) = mirror_score(values, center2, half_width) else {
+            continue;
+        };
+        let better = match best {
+            None => true,
+            Some((best2, best_score)) => {
+                score > best_score
+                    || (score == best_score && center2.abs_diff(middle2) < best2.abs_diff(middle2))
+            }
+        };
+        if better {
+            best = Some((center2, score));
+        }
+    }
+    best.map(|(center2, score)| Axis {
+        position: center2 as f64 / 2.0,
+        score,
+    })
+}
+
+/// Mirror scores of a track over sliding windows, fed one value at a time.
+///
+/// Windows of `window` values start every `step` values, and each is scored around its center:
+/// on its middle value if `window` is odd, or between its two middle values if it is even. A
+/// window holding a `NaN` scores `NaN`.
+///
+/// # Fields
+///
+/// * `values`: The last `window` values taken.
+/// * `window`: The number of values in each window.
+/// * `step`: The distance between the starts of consecutive windows.
+/// * `taken`: The number of values taken since the start or the last reset.
+#[derive(Clone, Debug)]
+pub struct SymmetryScores {
+    values: VecDeque<f64>,
+    window: usize,
+    step: usize,
+    taken: u64,
+}
+
+impl SymmetryScores {
+    /// Constructor for `SymmetryScores`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` or `step` is 0.
+    pub fn new(window: usize, step: usize) -> Self {
+        assert!(window > 0 && step > 0, "window and step must be at least 1");
+        Self {
+            values: VecDeque::with_capacity(window),
+            window,
+            step,
+            taken: 0,
+        }
+    }
+
+    /// The offset of a window's center from its first value, rounded down for even windows.
+    pub fn center_offset(&self) -> u64 {
+        (self.window as u64 - 1) / 2
+    }
+
+    /// The 0-based index of the center of the last window completed, as [`Self::center_offset`]
+    /// places it, or `None` if the track is not yet a window long.
+    pub fn last_center(&self) -> Option<u64> {
+        let start = self.taken.checked_sub(self.window as u64)?;
+        Some(start + self.center_offset())
+    }
+
+    /// Takes the next value of the track.
+    ///
+    /// # Returns
+    ///
+    /// The score of the window the value completes, or `None` if the track is not yet a window
+    /// long or the window does not start on the stride.
+    pub fn push(&mut self, value: f64) -> Option<f64> {
+        if self.values.len() == self.window {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+        self.taken += 1;
+        let start = self.taken.checked_sub(self.window as u64)?;
+        if start % self.step as u64 != 0 {
+            return None;
+        }
+        let values = self.values.make_contiguous();
+        Some(mirror_score(values, self.window - 1, self.window / 2).unwrap_or(f64::NAN))
+    }
+
+    /// Takes a run of `NaN` values. Every window they complete scores `NaN`, so nothing is
+    /// returned, and a run longer than a window costs no more than a window.
+    pub fn push_missing(&mut self, count: u64) {
+        let pushed = count.min(self.window as u64);
+        for _ in 0..pushed {
+            self.push(f64::NAN);
+        }
+        self.taken += count - pushed;
+    }
+
+    /// Forgets the values taken, so the next value starts a new track.
+    pub fn reset(&mut self) {
+        self.values.clear();
+        self.taken = 0;
+    }
+}
+
+/// Iterator over the mirror scores of the windows of a track, as [`SymmetryScores`] scores them.
+///
+/// One score is yielded per window, for windows starting at values `0`, `step`, `2 * step` and
+/// so on; the window starting at value `i * step` is centered on value
+/// `i * step + (window - 1) / 2`.
+pub struct SymmetryIter<I: Iterator<Item = f64>> {
+    inner: I,
+    scores: SymmetryScores,
+}
+
+impl<I: Iterator<Item = f64>> Iterator for SymmetryIter<I> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let value = self.inner.next()?;
+            if let Some(score) = self.scores.push(value) {
+                return Some(score);
+            }
+        }
+    }
+}
+
+/// A trait for `f64` Iterators to yield the mirror scores of sliding windows over them.
+///
+/// # Methods
+///
+/// * `symmetry_iter`: Takes the window size and step and returns a `SymmetryIter`.
+pub trait SymmetryIterator: Iterator<Item = f64> + Sized {
+    fn symmetry_iter(self, window: usize, step: usize) -> SymmetryIter<Self> {
+        SymmetryIter {
+            inner: self,
+            scores: SymmetryScores::new(window, step),
+        }
+    }
+}
+
+impl<I: Iterator<Item = f64>> SymmetryIterator for I {}
+
+/// Derives the path of the symmetry axis BED file from the main output path, so that `out.bw`
+/// becomes `out.axis.bed`.
+pub fn axis_path(output: &Path) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    output.with_file_name(format!("{}.axis.bed", stem))
+}
+
+/// Derives the path of the symmetry track from the main output path, so that `out.bw` becomes
+/// `out.symcurve.bw`.
+pub fn symcurve_path(output: &Path) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    match output.extension() {
+        Some(extension) => {
+            output.with_file_name(format!("{}.symcurve.{}", stem, extension.to_string_lossy()))
+        }
+        None => output.with_file_name(format!("{}.symcurve", stem)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_mirror_score() {
+        let values = [3.0, 2.0, 1.0, 2.5, 4.0, 5.0];
+        // on base 2, a minimum: weight 1 / (1 + 1.5) over |2 - 2.5| + |3 - 4|
+        assert_relative_eq!(mirror_score(&values, 4, 2).unwrap(), 0.4 / 1.5);
+        // on base 3, not a minimum
+        assert_eq!(mirror_score(&values, 6, 2), Some(0.0));
+        // between bases 1 and 2: dyad 1.5, neighbours 3 and 2.5, pairs (1, 2) and (0, 3)
+        assert_relative_eq!(mirror_score(&values, 3, 1).unwrap(), 1.0 / 2.5);
+        // a perfect mirror image scores 100, minimum or not
+        assert_eq!(mirror_score(&[1.0, 2.0, 3.0, 2.0, 1.0], 4, 2), Some(100.0));
+        // too shallow a minimum
+        assert_eq!(mirror_score(&[1.0, 0.998, 1.005], 2, 1), Some(0.0));
+        // reaching past either end
+        assert_eq!(mirror_score(&values, 4, 3), None);
+        assert_eq!(mirror_score(&values, 10, 2), None);
+        assert_eq!(mirror_score(&values, 0, 0), None);
+        assert_eq!(mirror_score(&[f64::NAN, 1.0, 1.0], 2, 1), None);
+    }
+
+    #[test]
+    fn test_mirror_score_perl() {
+        // SYMCURV of perl/SymCurv_prediction_v22.pl, with its window of 101, on these values
+        let values: Vec<f64> = (0..240).map(|i| ((i * 37) % 101) as f64 / 100.0).collect();
+        let expected = [
+            (101, 0.039603960396039604),
+            (102, 0.0),
+            (110, 0.07444353457902182),
+            (115, 0.06383617085112767),
+            (120, 0.0),
+            (137, 0.07383288664436916),
+        ];
+        for (dyad, score) in expected {
+            assert_relative_eq!(
+                mirror_score(&values, 2 * dyad, 50).unwrap(),
+                score,
+                max_relative = 1e-12
+            );
+        }
+    }
+
+    #[test]
+    fn test_symmetry_axis() {
+        // mirror image around base 5
+        let values = [9.0, 0.0, 1.0, 4.0, 2.0, 7.0, 2.0, 4.0, 1.0, 3.0];
+        let axis = symmetry_axis(&values, 3).unwrap();
+        assert_eq!(axis.position, 5.0);
+        assert_eq!(axis.score, 100.0);
+        assert_eq!(axis.bases(), (5, 6));
+        // mirror image around the gap between bases 3 and 4
+        let values = [5.0, 1.0, 2.0, 3.0, 3.0, 2.0, 1.0, 8.0];
+        let axis = symmetry_axis(&values, 3).unwrap();
+        assert_eq!(axis.position, 3.5);
+        assert_eq!(axis.bases(), (3, 5));
+        assert!(symmetry_axis(&values, 4).is_none());
+    }
+
+    #[test]
+    fn test_symmetry_iter() {
+        let values = [9.0, 1.0, 2.0, 1.0, 4.0, 2.0, 2.0, 4.0];
+        let scores: Vec<f64> = values.iter().copied().symmetry_iter(3, 1).collect();
+        assert_eq!(scores.len(), 6);
+        assert_eq!(scores[1], 100.0);
+        assert_eq!(scores[0], 1.0 / 63.0);
+        // an even window is centered between its two middle values
+        let scores: Vec<f64> = values.iter().copied().symmetry_iter(4, 2).collect();
+        assert_eq!(
+            scores,
+            vec![mirror_score(&values[..4], 3, 2).unwrap(), 0.0, 100.0]
+        );
+        let scores: Vec<f64> = [1.0, f64::NAN, 1.0, 2.0, 1.0, 3.0]
+            .into_iter()
+            .symmetry_iter(3, 1)
+            .collect();
+        assert!(scores[0].is_nan() && scores[1].is_nan());
+        assert_eq!(&scores[2..], &[100.0, 1.0 / 3.0]);
+    }
+
+    #[test]
+    fn test_symmetry_scores_missing() {
+        let mut scores = SymmetryScores::new(3, 2);
+        assert_eq!(scores.center_offset(), 1);
+        scores.push_missing(1_000_000);
+        assert_eq!(scores.last_center(), Some(999_998));
+        // the window completed by the next value starts at 999_998, on the stride
+        assert!(scores.push(1.0).unwrap().is_nan());
+        assert_eq!(scores.push(1.0), None);
+        assert_eq!(scores.push(1.0), Some(100.0));
+        assert_eq!(scores.push(5.0), None);
+        assert_eq!(scores.push(1.0), Some(100.0));
+        scores.reset();
+        assert_eq!(scores.push(1.0), None);
+    }
+
+    #[test]
+    fn test_axis_path() {
+        assert_eq!(
+            axis_path(Path::new("dir/out.bw")),
+            PathBuf::from("dir/out.axis.bed")
+        );
+        assert_eq!(
+            symcurve_path(Path::new("dir/out.bw")),
+            PathBuf::from("dir/out.symcurve.bw")
+        );
+        assert_eq!(
+            symcurve_path(Path::new("out")),
+            PathBuf::from("out.symcurve")
+        );
+    }
+}
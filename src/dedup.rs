@@ -0,0 +1,175 @@
+//! # Dedup module
+//! Detection of contigs with identical sequence.
+//!
+//! Patched assemblies often carry the same sequence under several names (fix patches, alternate
+//! haplotypes, unlocalized copies). With `--duplicate-contigs` each contig's sequence is hashed as
+//! it streams past; a contig whose sequence has been seen before is reported, and in `reuse` mode
+//! its output is copied from the earlier contig instead of being computed again.
+
+use crate::checksum::Sha256;
+use crate::issues::{Issue, Severity};
+use crate::warnings::WarningKind;
+use clap::ValueEnum;
+use std::collections::HashMap;
+
+/// What to do about contigs whose sequence duplicates an earlier contig.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum DuplicateMode {
+    /// don't check for duplicates
+    #[default]
+    Off,
+    /// warn about duplicates but compute them anyway
+    Warn,
+    /// warn about duplicates and reuse the earlier contig's result
+    Reuse,
+}
+
+/// Streaming digest of a contig's sequence.
+///
+/// The length is kept alongside the SHA-256 so that the digest reads well in messages and an
+/// empty contig is easy to spot.
+///
+/// # Fields
+///
+/// * `hasher`: The running SHA-256 of the bases.
+/// * `len`: The number of bases seen.
+#[derive(Clone, Debug)]
+pub struct ContigDigest {
+    hasher: Sha256,
+    len: u64,
+}
+
+impl ContigDigest {
+    /// Constructor for `ContigDigest`.
+    pub fn new() -> Self {
+        Self {
+            hasher: Sha256::new(),
+            len: 0,
+        }
+    }
+
+    /// Adds bases of the contig.
+    pub fn update(&mut self, bases: &[u8]) {
+        self.hasher.update(bases);
+        self.len += bases.len() as u64;
+    }
+
+    /// Finishes the digest as `length:sha256`.
+    pub fn finish(self) -> String {
+        format!("{}:{}", self.len, self.hasher.hex_digest())
+    }
+}
+
+impl Default for ContigDigest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The contigs seen so far, keyed on the digest of their sequence.
+///
+/// Each digest maps to the name of the first contig with that sequence and whatever result was
+/// kept for it (`()` when results are not reused).
+///
+/// # Fields
+///
+/// * `seen`: The first contig and its result for each digest.
+#[derive(Debug)]
+pub struct DuplicateContigs<T> {
+    seen: HashMap<String, (String, T)>,
+}
+
+impl<T> DuplicateContigs<T> {
+    /// Constructor for `DuplicateContigs`.
+    pub fn new() -> Self {
+        Self {
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Looks up an earlier contig with the same sequence, returning its name and result.
+    pub fn earlier(&self, digest: &str) -> Option<(&str, &T)> {
+        self.seen
+            .get(digest)
+            .map(|(name, result)| (name.as_str(), result))
+    }
+
+    /// Records a contig and its result. The first contig recorded for a digest is kept.
+    pub fn record(&mut self, digest: String, name: &str, result: T) {
+        self.seen
+            .entry(digest)
+            .or_insert_with(|| (name.to_string(), result));
+    }
+}
+
+impl<T> Default for DuplicateContigs<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The warning reported for a duplicate contig.
+pub fn duplicate_warning(name: &str, earlier: &str, mode: DuplicateMode) -> Issue {
+    let action = match mode {
+        DuplicateMode::Reuse => "; reusing its result",
+        _ => "",
+    };
+    Issue::new(
+        Severity::Warning,
+        WarningKind::DuplicateContigs.code(),
+        format!("same sequence as {}{}", earlier, action),
+    )
+    .in_contig(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(seq: &[u8]) -> String {
+        let mut digest = ContigDigest::new();
+        for chunk in seq.chunks(3) {
+            digest.update(chunk);
+        }
+        digest.finish()
+    }
+
+    #[test]
+    fn test_contig_digest() {
+        assert_eq!(
+            digest(b""),
+            "0:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(digest(b"ACGTACGT"), digest(b"ACGTACGT"));
+        assert_ne!(digest(b"ACGTACGT"), digest(b"ACGTACGA"));
+        assert!(digest(b"ACGTACGT").starts_with("8:"));
+    }
+
+    #[test]
+    fn test_duplicate_contigs() {
+        let mut contigs = DuplicateContigs::new();
+        assert_eq!(contigs.earlier(&digest(b"ACGT")), None);
+        contigs.record(digest(b"ACGT"), "chr1", vec![1.0]);
+        contigs.record(digest(b"ACGT"), "chr1_fix", vec![2.0]);
+        contigs.record(digest(b"TTTT"), "chr2", vec![3.0]);
+        assert_eq!(
+            contigs.earlier(&digest(b"ACGT")),
+            Some(("chr1", &vec![1.0]))
+        );
+        assert_eq!(
+            contigs.earlier(&digest(b"TTTT")),
+            Some(("chr2", &vec![3.0]))
+        );
+    }
+
+    #[test]
+    fn test_duplicate_warning() {
+        assert_eq!(
+            duplicate_warning("chr1_fix", "chr1", DuplicateMode::Warn).to_string(),
+            "warning: chr1_fix: same sequence as chr1"
+        );
+        assert!(duplicate_warning("chr1_fix", "chr1", DuplicateMode::Reuse)
+            .to_string()
+            .ends_with("reusing its result"));
+    }
+}
@@ -0,0 +1,322 @@
+//! # Design module
+//! The `symcurve design` subcommand, which searches for oligos of a fixed length and GC content
+//! with as much (or as little) curvature as possible, for designing bent or straight DNA
+//! constructs.
+//!
+//! The search is simulated annealing from a random start. Moves either swap two bases or swap a
+//! base for its complement (`A`↔`T`, `C`↔`G`), so every candidate keeps the requested GC content
+//! exactly. The score of a candidate is its mean curvature. Each candidate is an independent run
+//! with its own seed, so results are reproducible for a given `--seed`.
+
+use crate::pipeline::{position_values, PipelineParams};
+use clap::ValueEnum;
+use std::io::{self, Write};
+
+/// Whether curvature is maximized or minimized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Objective {
+    /// search for the most curved sequences
+    Max,
+    /// search for the straightest sequences
+    Min,
+}
+
+/// A small xorshift pseudo-random generator, good enough for annealing moves and reproducible
+/// across platforms.
+///
+/// # Fields
+///
+/// * `state`: The generator state, never 0.
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Constructor for `Rng`.
+    pub(crate) fn new(seed: u64) -> Self {
+        // splitmix64 spreads nearby seeds apart and never gives the all-zero state
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Self {
+            state: (z ^ (z >> 31)) | 1,
+        }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// A number below `n`.
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// A number in `[0, 1)`.
+    pub(crate) fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// The settings of a design search.
+///
+/// # Fields
+///
+/// * `length`: The oligo length.
+/// * `gc`: The GC fraction; the GC count is rounded to the nearest base.
+/// * `objective`: Whether curvature is maximized or minimized.
+/// * `iterations`: The number of annealing moves per candidate.
+/// * `candidates`: The number of candidates.
+/// * `seed`: The seed of the first candidate; later ones use the following seeds.
+#[derive(Clone, Debug)]
+pub struct DesignParams {
+    pub length: usize,
+    pub gc: f64,
+    pub objective: Objective,
+    pub iterations: usize,
+    pub candidates: usize,
+    pub seed: u64,
+}
+
+/// A designed oligo.
+///
+/// # Fields
+///
+/// * `sequence`: The bases.
+/// * `score`: The mean curvature.
+/// * `profile`: The per-base curvature, `NaN` where there is no value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candidate {
+    pub sequence: Vec<u8>,
+    pub score: f64,
+    pub profile: Vec<f64>,
+}
+
+/// The mean of the non-`NaN` values of a profile.
+fn mean_value(profile: &[f64]) -> f64 {
+    let (sum, n) = profile
+        .iter()
+        .filter(|v| !v.is_nan())
+        .fold((0.0, 0), |(sum, n), v| (sum + v, n + 1));
+    sum / n as f64
+}
+
+/// A random sequence with exactly `gc_count` `G` or `C` bases.
+fn random_sequence(rng: &mut Rng, length: usize, gc_count: usize) -> Vec<u8> {
+    let mut seq: Vec<u8> = (0..length)
+        .map(|i| {
+            let pair = if i < gc_count { b"GC" } else { b"AT" };
+            pair[rng.below(2)]
+        })
+        .collect();
+    // shuffle so GC is spread along the sequence
+    for i in (1..length).rev() {
+        seq.swap(i, rng.below(i + 1));
+    }
+    seq
+}
+
+/// Runs one annealing search.
+fn anneal(params: &DesignParams, curve: &PipelineParams, seed: u64) -> Candidate {
+    let mut rng = Rng::new(seed);
+    let gc_count = (params.gc * params.length as f64).round() as usize;
+    let mut seq = random_sequence(&mut rng, params.length, gc_count);
+    // annealing minimizes, so maximizing curvature minimizes its negation
+    let sign = match params.objective {
+        Objective::Max => -1.0,
+        Objective::Min => 1.0,
+    };
+    let energy = |seq: &[u8]| sign * mean_value(&position_values(seq, curve));
+    let mut current = energy(&seq);
+    let mut best = (seq.clone(), current);
+    // start hot enough to take typical worsening moves, and cool geometrically
+    let (start, end): (f64, f64) = (0.5, 1e-3);
+    let cooling = (end / start).powf(1.0 / params.iterations.max(1) as f64);
+    let mut temperature = start;
+    for _ in 0..params.iterations {
+        let i = rng.below(params.length);
+        let undo = if rng.below(2) == 0 {
+            let j = rng.below(params.length);
+            seq.swap(i, j);
+            (i, j, None)
+        } else {
+            let old = seq[i];
+            seq[i] = match old {
+                b'A' => b'T',
+                b'T' => b'A',
+                b'C' => b'G',
+                _ => b'C',
+            };
+            (i, i, Some(old))
+        };
+        let next = energy(&seq);
+        let accept = next <= current || rng.unit() < ((current - next) / temperature).exp();
+        if accept {
+            current = next;
+            if current < best.1 {
+                best = (seq.clone(), current);
+            }
+        } else {
+            match undo {
+                (i, j, None) => seq.swap(i, j),
+                (i, _, Some(old)) => seq[i] = old,
+            }
+        }
+        temperature *= cooling;
+    }
+    let profile = position_values(&best.0, curve);
+    Candidate {
+        score: mean_value(&profile),
+        sequence: best.0,
+        profile,
+    }
+}
+
+/// Designs candidate oligos.
+///
+/// # Returns
+///
+/// The candidates, best first.
+///
+/// # Errors
+///
+/// Returns an error message if the oligo is too short to have a curvature value or the GC
+/// fraction is outside 0 to 1.
+pub fn design(params: &DesignParams, curve: &PipelineParams) -> Result<Vec<Candidate>, String> {
+    if !(0.0..=1.0).contains(&params.gc) {
+        return Err(format!("GC fraction {} is not between 0 and 1", params.gc));
+    }
+    if mean_value(&position_values(&vec![b'A'; params.length], curve)).is_nan() {
+        return Err(format!(
+            "an oligo of {} bases is too short for a curvature value",
+            params.length
+        ));
+    }
+    let mut candidates: Vec<Candidate> = (0..params.candidates as u64)
+        .map(|i| anneal(params, curve, params.seed.wrapping_add(i)))
+        .collect();
+    candidates.sort_by(|a, b| match params.objective {
+        Objective::Max => b.score.total_cmp(&a.score),
+        Objective::Min => a.score.total_cmp(&b.score),
+    });
+    Ok(candidates)
+}
+
+/// Writes candidates as a tab-separated table with a header: id, sequence, GC fraction, mean
+/// curvature, and the profile as comma-separated values (`NaN` where there is no value).
+pub fn write_candidates<W: Write>(
+    writer: &mut W,
+    candidates: &[Candidate],
+    curve: &PipelineParams,
+) -> io::Result<()> {
+    let float = curve.float_format;
+    writeln!(writer, "id\tsequence\tgc\tmean_curvature\tprofile")?;
+    for (i, candidate) in candidates.iter().enumerate() {
+        let gc = candidate
+            .sequence
+            .iter()
+            .filter(|&&b| matches!(b, b'G' | b'C'))
+            .count() as f64
+            / candidate.sequence.len() as f64;
+        let profile: Vec<String> = candidate.profile.iter().map(|&v| float.format(v)).collect();
+        writeln!(
+            writer,
+            "oligo{}\t{}\t{}\t{}\t{}",
+            i + 1,
+            String::from_utf8_lossy(&candidate.sequence),
+            float.format(gc),
+            float.format(candidate.score),
+            profile.join(",")
+        )?;
+    }
+    Ok(())
+}
+
+/// Runs `symcurve design` with curvature computed with `curve`, and writes the candidates.
+pub fn run<W: Write>(
+    params: &DesignParams,
+    curve: &PipelineParams,
+    writer: &mut W,
+) -> io::Result<()> {
+    let candidates =
+        design(params, curve).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    write_candidates(writer, &candidates, curve)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(objective: Objective) -> DesignParams {
+        DesignParams {
+            length: 60,
+            gc: 0.4,
+            objective,
+            iterations: 300,
+            candidates: 2,
+            seed: 7,
+        }
+    }
+
+    #[test]
+    fn test_design_keeps_gc() {
+        let curve = PipelineParams::default();
+        let candidates = design(&params(Objective::Max), &curve).unwrap();
+        assert_eq!(candidates.len(), 2);
+        for candidate in &candidates {
+            assert_eq!(candidate.sequence.len(), 60);
+            let gc = candidate
+                .sequence
+                .iter()
+                .filter(|&&b| matches!(b, b'G' | b'C'))
+                .count();
+            assert_eq!(gc, 24);
+            assert_eq!(candidate.profile.len(), 60);
+        }
+        assert!(candidates[0].score >= candidates[1].score);
+        // the same seed gives the same result
+        let again = design(&params(Objective::Max), &curve).unwrap();
+        assert_eq!(again[0].sequence, candidates[0].sequence);
+    }
+
+    #[test]
+    fn test_design_objective() {
+        let curve = PipelineParams::default();
+        let max = design(&params(Objective::Max), &curve).unwrap();
+        let min = design(&params(Objective::Min), &curve).unwrap();
+        assert!(max[0].score > min[0].score);
+    }
+
+    #[test]
+    fn test_design_invalid() {
+        let curve = PipelineParams::default();
+        let short = DesignParams {
+            length: 20,
+            ..params(Objective::Max)
+        };
+        assert!(design(&short, &curve).is_err());
+        let gc = DesignParams {
+            gc: 1.5,
+            ..params(Objective::Max)
+        };
+        assert!(design(&gc, &curve).is_err());
+    }
+
+    #[test]
+    fn test_write_candidates() {
+        let candidate = Candidate {
+            sequence: b"ACGT".to_vec(),
+            score: 2.5,
+            profile: vec![f64::NAN, 2.0, 3.0, f64::NAN],
+        };
+        let mut out = Vec::new();
+        write_candidates(&mut out, &[candidate], &PipelineParams::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "id\tsequence\tgc\tmean_curvature\tprofile\noligo1\tACGT\t0.5\t2.5\tNaN,2,3,NaN\n"
+        );
+    }
+}
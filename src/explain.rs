@@ -0,0 +1,212 @@
+//! # Explain module
+//! The `symcurve explain` subcommand, which attributes the curvature of a region to the bases
+//! behind it, to answer "which bases make this peak?".
+//!
+//! The score of a region is its mean curvature. The contribution of a base is how much the score
+//! drops when the base is replaced: the score minus the mean score over the three substitutions
+//! at that position. A positive contribution means the base raises the region's curvature. Every
+//! base a value in the region depends on is attributed, so the rows reach one window flank either
+//! side of the region.
+//!
+//! Each base takes three recomputations of the region, so the cost grows with the square of its
+//! length; this is meant for peaks and other short regions, not whole chromosomes.
+
+use crate::fasta::open_input;
+use crate::pipeline::{position_values, PipelineParams};
+use crate::sweep::read_region;
+use crate::view::Region;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// The contribution of one base to the score of a region.
+///
+/// # Fields
+///
+/// * `position`: The 0-based position of the base within the sequence given to [`contributions`].
+/// * `base`: The base.
+/// * `contribution`: The score minus the mean score with the base substituted, or `NaN` for a
+///   base that is not `A`, `C`, `G` or `T`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contribution {
+    pub position: usize,
+    pub base: u8,
+    pub contribution: f64,
+}
+
+/// The mean of the non-`NaN` values at `range` of a sequence's per-base curvature.
+fn region_score(seq: &[u8], range: &std::ops::Range<usize>, params: &PipelineParams) -> f64 {
+    let values = position_values(seq, params);
+    let (sum, n) = values[range.clone()]
+        .iter()
+        .filter(|v| !v.is_nan())
+        .fold((0.0, 0), |(sum, n), v| (sum + v, n + 1));
+    sum / n as f64
+}
+
+/// Computes the contribution of each base of a sequence to the score of a range of it.
+///
+/// # Arguments
+///
+/// * `seq` - The sequence, including the bases either side of the range that its values depend on.
+/// * `range` - The positions whose values make up the score.
+/// * `params` - The curvature parameters.
+///
+/// # Returns
+///
+/// The score and one contribution per base of `seq`, or `None` if the range has no values.
+pub fn contributions(
+    seq: &[u8],
+    range: std::ops::Range<usize>,
+    params: &PipelineParams,
+) -> Option<(f64, Vec<Contribution>)> {
+    let score = region_score(seq, &range, params);
+    if score.is_nan() {
+        return None;
+    }
+    let mut mutant = seq.to_vec();
+    let rows = seq
+        .iter()
+        .enumerate()
+        .map(|(position, &base)| {
+            let upper = base.to_ascii_uppercase();
+            let contribution = if matches!(upper, b'A' | b'C' | b'G' | b'T') {
+                let others = b"ACGT".iter().filter(|&&b| b != upper);
+                let total: f64 = others
+                    .map(|&other| {
+                        mutant[position] = other;
+                        region_score(&mutant, &range, params)
+                    })
+                    .sum();
+                mutant[position] = base;
+                score - total / 3.0
+            } else {
+                f64::NAN
+            };
+            Contribution {
+                position,
+                base,
+                contribution,
+            }
+        })
+        .collect();
+    Some((score, rows))
+}
+
+/// Runs `symcurve explain`.
+///
+/// Writes a `#` comment line with the region's score, then a tab-separated table of `chrom`,
+/// 1-based position, base and contribution.
+///
+/// # Arguments
+///
+/// * `path` - The FASTA file.
+/// * `region` - The region to explain, which must have an end.
+/// * `params` - The parameters curvature is computed with.
+/// * `writer` - Where the table is written.
+pub fn explain<W: Write>(
+    path: &Path,
+    region: &Region,
+    params: &PipelineParams,
+    writer: &mut W,
+) -> io::Result<()> {
+    let invalid = |why: &str| io::Error::new(io::ErrorKind::InvalidInput, why.to_string());
+    let end = region
+        .end
+        .ok_or_else(|| invalid("the region needs a start and an end"))?;
+    let flank = params.flank() as u64;
+    let context = Region {
+        chrom: region.chrom.clone(),
+        start: region.start.saturating_sub(flank),
+        end: Some(end + flank),
+    };
+    let seq = read_region(open_input(path)?, Some(&context))?;
+    let first = (region.start - context.start) as usize;
+    let last = (first + (end - region.start) as usize).min(seq.len());
+    let (score, rows) = contributions(&seq, first.min(last)..last, params)
+        .ok_or_else(|| invalid("the region has no curvature values"))?;
+    writeln!(
+        writer,
+        "# {}:{}-{} mean curvature {}",
+        region.chrom,
+        region.start + 1,
+        end,
+        params.float_format.format(score)
+    )?;
+    writeln!(writer, "chrom\tpos\tbase\tcontribution")?;
+    for row in rows {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            region.chrom,
+            context.start + row.position as u64 + 1,
+            row.base as char,
+            params.float_format.format(row.contribution)
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    const SEQ: &[u8] = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+
+    #[test]
+    fn test_contributions() {
+        let params = PipelineParams::default();
+        let (score, rows) = contributions(SEQ, 25..29, &params).unwrap();
+        assert_eq!(rows.len(), SEQ.len());
+        let values = position_values(SEQ, &params);
+        assert_relative_eq!(score, values[25..29].iter().sum::<f64>() / 4.0);
+        // the first bases are behind no value in the range
+        assert!(rows[0].contribution.abs() < 1e-9);
+        assert!(rows.iter().any(|row| row.contribution.abs() > 1e-6));
+        assert!(contributions(SEQ, 0..5, &params).is_none());
+    }
+
+    #[test]
+    fn test_explain_params() {
+        let path = std::env::temp_dir().join("symcurve_test_explain_params.fa");
+        let seq = SEQ.repeat(2);
+        std::fs::write(&path, [&b">chr1\n"[..], &seq, b"\n"].concat()).unwrap();
+        let region: Region = "chr1:46-55".parse().unwrap();
+        // the score is that of the parameters given, not the defaults
+        let mut scores = Vec::new();
+        for params in [
+            PipelineParams::default(),
+            PipelineParams {
+                step_b: 7,
+                step_two: Some(4),
+                ..Default::default()
+            },
+        ] {
+            let mut out = Vec::new();
+            explain(&path, &region, &params, &mut out).unwrap();
+            let text = String::from_utf8(out).unwrap();
+            let written: f64 = text
+                .lines()
+                .next()
+                .and_then(|line| line.strip_prefix("# chr1:46-55 mean curvature "))
+                .unwrap()
+                .parse()
+                .unwrap();
+            let (score, _) = contributions(&seq, 45..55, &params).unwrap();
+            assert_relative_eq!(written, score, epsilon = 1e-9);
+            scores.push(written);
+        }
+        assert!((scores[0] - scores[1]).abs() > 1e-6);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_contributions_gap() {
+        let params = PipelineParams::default();
+        let mut seq = SEQ.to_vec();
+        seq[0] = b'N';
+        let (_, rows) = contributions(&seq, 25..29, &params).unwrap();
+        assert!(rows[0].contribution.is_nan());
+        assert_eq!(rows[1].base, b'C');
+    }
+}
@@ -0,0 +1,576 @@
+//! # Expression module
+//! A small arithmetic expression language for derived tracks, so users can combine the computed
+//! tracks (e.g. `curve * sym`, or `curve > 5 && sym > 0.8` as a custom threshold) without writing
+//! Rust. Expressions are parsed once, with variable names resolved to slots, and then evaluated
+//! per position.
+//!
+//! The language has numbers, variables, `+ - * / ^`, comparisons (`< <= > >= == !=`), `&&`, `||`,
+//! `!`, parentheses, `cond ? a : b`, and the functions `abs`, `sqrt`, `exp`, `ln`, `log10`, `min`,
+//! `max` and `isnan`. Comparisons and logic give `1` for true and `0` for false, and anything
+//! non-zero counts as true. `NaN` propagates through arithmetic, so positions without a value stay
+//! missing unless an expression tests for them with `isnan`.
+//!
+//! Expressions are used both for derived tracks (`--derive`) and for filtering which positions
+//! are written (`--filter`), where a position is kept only if the expression is true there.
+
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The variables derived track expressions can refer to, in slot order.
+pub const VARIABLES: [&str; 3] = ["curve", "sym", "pos"];
+
+#[derive(Debug)]
+pub struct ExprError {
+    details: String,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error: {}", self.details)
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+impl ExprError {
+    fn new(details: impl Into<String>) -> Self {
+        Self {
+            details: details.into(),
+        }
+    }
+}
+
+/// Binary operators, in the `Expr::Binary` node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    /// Binding power on the left and right, for precedence climbing. `^` binds right to left.
+    fn binding_power(&self) -> (u8, u8) {
+        match self {
+            BinaryOp::Or => (1, 2),
+            BinaryOp::And => (3, 4),
+            BinaryOp::Eq | BinaryOp::Ne => (5, 6),
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => (7, 8),
+            BinaryOp::Add | BinaryOp::Sub => (9, 10),
+            BinaryOp::Mul | BinaryOp::Div => (11, 12),
+            BinaryOp::Pow => (16, 15),
+        }
+    }
+
+    fn apply(&self, a: f64, b: f64) -> f64 {
+        let truth = |t: bool| if t { 1.0 } else { 0.0 };
+        match self {
+            BinaryOp::Add => a + b,
+            BinaryOp::Sub => a - b,
+            BinaryOp::Mul => a * b,
+            BinaryOp::Div => a / b,
+            BinaryOp::Pow => a.powf(b),
+            BinaryOp::Lt => truth(a < b),
+            BinaryOp::Le => truth(a <= b),
+            BinaryOp::Gt => truth(a > b),
+            BinaryOp::Ge => truth(a >= b),
+            BinaryOp::Eq => truth(a == b),
+            BinaryOp::Ne => truth(a != b),
+            BinaryOp::And => truth(a != 0.0 && b != 0.0),
+            BinaryOp::Or => truth(a != 0.0 || b != 0.0),
+        }
+    }
+}
+
+/// Built-in functions, with their arity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Function {
+    Abs,
+    Sqrt,
+    Exp,
+    Ln,
+    Log10,
+    Min,
+    Max,
+    IsNan,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "abs" => Function::Abs,
+            "sqrt" => Function::Sqrt,
+            "exp" => Function::Exp,
+            "ln" => Function::Ln,
+            "log10" => Function::Log10,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            "isnan" => Function::IsNan,
+            _ => return None,
+        })
+    }
+
+    fn arity(&self) -> usize {
+        match self {
+            Function::Min | Function::Max => 2,
+            _ => 1,
+        }
+    }
+
+    fn apply(&self, args: &[f64]) -> f64 {
+        match self {
+            Function::Abs => args[0].abs(),
+            Function::Sqrt => args[0].sqrt(),
+            Function::Exp => args[0].exp(),
+            Function::Ln => args[0].ln(),
+            Function::Log10 => args[0].log10(),
+            // NaN-propagating, unlike f64::min/max
+            Function::Min if args[0].is_nan() || args[1].is_nan() => f64::NAN,
+            Function::Max if args[0].is_nan() || args[1].is_nan() => f64::NAN,
+            Function::Min => args[0].min(args[1]),
+            Function::Max => args[0].max(args[1]),
+            Function::IsNan => {
+                if args[0].is_nan() {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// A parsed expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    /// A variable, by its slot in `VARIABLES`.
+    Variable(usize),
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Evaluates the expression.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - The value of each variable at this position, in `VARIABLES` order.
+    pub fn eval(&self, values: &[f64]) -> f64 {
+        match self {
+            Expr::Number(n) => *n,
+            Expr::Variable(slot) => values[*slot],
+            Expr::Negate(e) => -e.eval(values),
+            Expr::Not(e) => {
+                let v = e.eval(values);
+                if v.is_nan() {
+                    v
+                } else if v == 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(values), b.eval(values));
+                if a.is_nan() || b.is_nan() {
+                    f64::NAN
+                } else {
+                    op.apply(a, b)
+                }
+            }
+            Expr::Call(function, args) => {
+                let args: Vec<f64> = args.iter().map(|arg| arg.eval(values)).collect();
+                function.apply(&args)
+            }
+            Expr::Conditional(cond, a, b) => {
+                let c = cond.eval(values);
+                if c.is_nan() {
+                    f64::NAN
+                } else if c != 0.0 {
+                    a.eval(values)
+                } else {
+                    b.eval(values)
+                }
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// Whether the expression is true at a position: non-zero and not `NaN`.
+    pub fn is_true(&self, values: &[f64]) -> bool {
+        let value = self.eval(values);
+        !value.is_nan() && value != 0.0
+    }
+}
+
+/// Finds the runs of positions an output filter keeps.
+///
+/// # Arguments
+///
+/// * `filter` - The filter expression.
+/// * `rows` - The value of each variable at each position, in `VARIABLES` order.
+///
+/// # Returns
+///
+/// The 0-based, half-open index ranges of the maximal runs of positions where `filter` is true,
+/// in order.
+pub fn kept_ranges<I>(filter: &Expr, rows: I) -> Vec<Range<usize>>
+where
+    I: IntoIterator<Item = [f64; VARIABLES.len()]>,
+{
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (i, row) in rows.into_iter().enumerate() {
+        if !filter.is_true(&row) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(last) if last.end == i => last.end = i + 1,
+            _ => ranges.push(i..i + 1),
+        }
+    }
+    ranges
+}
+
+impl FromStr for Expr {
+    type Err = ExprError;
+
+    /// Parses an expression over the variables in `VARIABLES`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = ExprParser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let expr = parser.expr(0)?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(ExprError::new(format!("unexpected {:?}", token))),
+        }
+    }
+}
+
+/// Tokens of the expression language.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Op(&'static str),
+}
+
+/// Splits an expression into tokens.
+fn tokenize(s: &str) -> Result<Vec<Token>, ExprError> {
+    const OPS: [&str; 19] = [
+        "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "^", "<", ">", "!", "(", ")", ",",
+        "?", ":",
+    ];
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        let len = if c.is_ascii_digit() || c == '.' {
+            let mut len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            // exponent, as in 1e-3
+            if rest[len..].starts_with(['e', 'E']) {
+                let exp = &rest[len + 1..];
+                let sign = usize::from(exp.starts_with(['+', '-']));
+                let digits = exp[sign..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(exp.len() - sign);
+                if digits > 0 {
+                    len += 1 + sign + digits;
+                }
+            }
+            let number = rest[..len]
+                .parse()
+                .map_err(|_| ExprError::new(format!("invalid number {}", &rest[..len])))?;
+            tokens.push(Token::Number(number));
+            len
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].to_string()));
+            len
+        } else if let Some(op) = OPS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            op.len()
+        } else {
+            return Err(ExprError::new(format!("unexpected character '{}'", c)));
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Precedence-climbing parser over the tokens of an expression.
+///
+/// # Fields
+///
+/// * `tokens`: The tokens.
+/// * `pos`: The index of the next token.
+struct ExprParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl ExprParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), ExprError> {
+        match self.next() {
+            Some(Token::Op(found)) if found == op => Ok(()),
+            Some(token) => Err(ExprError::new(format!(
+                "expected '{}', found {:?}",
+                op, token
+            ))),
+            None => Err(ExprError::new(format!("expected '{}' at the end", op))),
+        }
+    }
+
+    /// Parses an expression whose operators all bind tighter than `min_power`.
+    fn expr(&mut self, min_power: u8) -> Result<Expr, ExprError> {
+        let mut lhs = self.prefix()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op("?")) if min_power == 0 => {
+                    self.pos += 1;
+                    let a = self.expr(0)?;
+                    self.expect(":")?;
+                    let b = self.expr(0)?;
+                    return Ok(Expr::Conditional(Box::new(lhs), Box::new(a), Box::new(b)));
+                }
+                Some(Token::Op(op)) => match *op {
+                    "+" => BinaryOp::Add,
+                    "-" => BinaryOp::Sub,
+                    "*" => BinaryOp::Mul,
+                    "/" => BinaryOp::Div,
+                    "^" => BinaryOp::Pow,
+                    "<" => BinaryOp::Lt,
+                    "<=" => BinaryOp::Le,
+                    ">" => BinaryOp::Gt,
+                    ">=" => BinaryOp::Ge,
+                    "==" => BinaryOp::Eq,
+                    "!=" => BinaryOp::Ne,
+                    "&&" => BinaryOp::And,
+                    "||" => BinaryOp::Or,
+                    _ => break,
+                },
+                _ => break,
+            };
+            let (left, right) = op.binding_power();
+            if left < min_power {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.expr(right)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    /// Parses a number, variable, call, parenthesized expression, or unary operator.
+    fn prefix(&mut self) -> Result<Expr, ExprError> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Op("-")) => Ok(Expr::Negate(Box::new(self.expr(13)?))),
+            Some(Token::Op("!")) => Ok(Expr::Not(Box::new(self.expr(13)?))),
+            Some(Token::Op("(")) => {
+                let expr = self.expr(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Name(name)) => {
+                if let Some(slot) = VARIABLES.iter().position(|v| *v == name) {
+                    return Ok(Expr::Variable(slot));
+                }
+                let function = Function::from_name(&name).ok_or_else(|| {
+                    ExprError::new(format!(
+                        "unknown name {} (variables are {})",
+                        name,
+                        VARIABLES.join(", ")
+                    ))
+                })?;
+                self.expect("(")?;
+                let mut args = vec![self.expr(0)?];
+                while self.peek() == Some(&Token::Op(",")) {
+                    self.pos += 1;
+                    args.push(self.expr(0)?);
+                }
+                self.expect(")")?;
+                if args.len() != function.arity() {
+                    return Err(ExprError::new(format!(
+                        "{} takes {} argument(s), got {}",
+                        name,
+                        function.arity(),
+                        args.len()
+                    )));
+                }
+                Ok(Expr::Call(function, args))
+            }
+            Some(token) => Err(ExprError::new(format!("unexpected {:?}", token))),
+            None => Err(ExprError::new("unexpected end of expression")),
+        }
+    }
+}
+
+/// A derived track given on the command line as `NAME=EXPR`.
+///
+/// # Fields
+///
+/// * `name`: The track name, used in the output file name.
+/// * `expr`: The expression computing it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DerivedTrack {
+    pub name: String,
+    pub expr: Expr,
+}
+
+impl DerivedTrack {
+    /// Derives the path of this track's output from the main output path, inserting the name
+    /// before the extension so that `out.bw` gets `out.score.bw`.
+    pub fn path(&self, output: &Path) -> PathBuf {
+        let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match output.extension() {
+            Some(ext) => format!("{}.{}.{}", stem, self.name, ext.to_string_lossy()),
+            None => format!("{}.{}", stem, self.name),
+        };
+        output.with_file_name(name)
+    }
+}
+
+impl FromStr for DerivedTrack {
+    type Err = ExprError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, expr) = s
+            .split_once('=')
+            .filter(|(name, _)| !name.is_empty() && !name.ends_with(['<', '>', '!', '=']))
+            .ok_or_else(|| ExprError::new(format!("expected NAME=EXPR, got {}", s)))?;
+        let name = name.trim();
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(ExprError::new(format!("invalid track name {}", name)));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            expr: expr.parse()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(text: &str, curve: f64, sym: f64) -> f64 {
+        text.parse::<Expr>().unwrap().eval(&[curve, sym, 100.0])
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(eval("curve * sym", 4.0, 0.5), 2.0);
+        assert_eq!(eval("1 + 2 * 3", 0.0, 0.0), 7.0);
+        assert_eq!(eval("(1 + 2) * 3", 0.0, 0.0), 9.0);
+        assert_eq!(eval("2 ^ 3 ^ 2", 0.0, 0.0), 512.0);
+        assert_eq!(eval("-2 ^ 2", 0.0, 0.0), -4.0);
+        assert_eq!(eval("10 - 4 - 3", 0.0, 0.0), 3.0);
+        assert_eq!(eval("1.5e1 / 3", 0.0, 0.0), 5.0);
+        assert_eq!(eval("pos / 4", 0.0, 0.0), 25.0);
+        assert_eq!(eval("max(curve, sym) + min(1, abs(-3))", 4.0, 0.5), 5.0);
+        assert_eq!(eval("sqrt(16) + log10(100) + ln(exp(1))", 0.0, 0.0), 7.0);
+    }
+
+    #[test]
+    fn test_logic() {
+        assert_eq!(eval("curve > 5 && sym > 0.8", 6.0, 0.9), 1.0);
+        assert_eq!(eval("curve > 5 && sym > 0.8", 6.0, 0.5), 0.0);
+        assert_eq!(eval("curve > 5 || sym > 0.8", 1.0, 0.9), 1.0);
+        assert_eq!(eval("!(curve >= 5)", 4.0, 0.0), 1.0);
+        assert_eq!(eval("1 + 1 == 2", 0.0, 0.0), 1.0);
+        assert_eq!(eval("curve > 5 ? curve : 0", 6.0, 0.0), 6.0);
+        assert_eq!(eval("curve > 5 ? curve : 0", 4.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_nan() {
+        assert!(eval("curve * sym", f64::NAN, 0.5).is_nan());
+        assert!(eval("curve > 5", f64::NAN, 0.5).is_nan());
+        assert!(eval("max(curve, 1)", f64::NAN, 0.5).is_nan());
+        assert_eq!(eval("isnan(curve) ? -1 : curve", f64::NAN, 0.5), -1.0);
+    }
+
+    #[test]
+    fn test_kept_ranges() {
+        let filter: Expr = "curve > 5 && sym > 0.3".parse().unwrap();
+        let rows = [
+            [6.0, 0.5, 0.0],
+            [6.0, 0.5, 1.0],
+            [6.0, 0.1, 2.0],
+            [f64::NAN, 0.5, 3.0],
+            [7.0, 0.9, 4.0],
+        ];
+        assert_eq!(kept_ranges(&filter, rows), vec![0..2, 4..5]);
+        assert!(filter.is_true(&rows[4]));
+        assert!(!filter.is_true(&rows[3]));
+    }
+
+    #[test]
+    fn test_errors() {
+        for text in [
+            "",
+            "curve +",
+            "(curve",
+            "foo * 2",
+            "max(1)",
+            "curve $ 2",
+            "1 2",
+            "a ? 1",
+        ] {
+            assert!(text.parse::<Expr>().is_err(), "{}", text);
+        }
+        let err = "foo".parse::<Expr>().unwrap_err();
+        assert!(err.to_string().contains("variables are curve, sym, pos"));
+    }
+
+    #[test]
+    fn test_derived_track() {
+        let track: DerivedTrack = "score=curve * sym".parse().unwrap();
+        assert_eq!(track.name, "score");
+        assert_eq!(track.expr.eval(&[2.0, 3.0, 0.0]), 6.0);
+        assert!("curve * sym".parse::<DerivedTrack>().is_err());
+        assert!("=curve".parse::<DerivedTrack>().is_err());
+        assert!("a b=curve".parse::<DerivedTrack>().is_err());
+        assert_eq!(
+            track.path(Path::new("results/out.bw")),
+            PathBuf::from("results/out.score.bw")
+        );
+    }
+}
@@ -1,5 +1,16 @@
 //! Functions for working with FASTA files.
 
+pub mod archive;
+pub mod index;
+pub mod mask;
+pub mod validate;
+
+use crate::fasta::archive::{open_archive, stream_archive, ArchiveKind};
+use crate::text::skip_bom;
+use flate2::read::MultiGzDecoder;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
 use std::rc::Rc;
 
 use noodles_core::Position;
@@ -76,6 +87,224 @@ pub fn split_seq_by_n(record: Record) -> Vec<RecordPiece> {
     records
 }
 
+/// The input path that stands for standard input, so FASTA text can be piped in, e.g.
+/// `zcat genome.fa.gz | symcurve - out.bw`.
+pub const STDIN: &str = "-";
+
+/// Opens FASTA input for reading, from standard input if `path` is [`STDIN`].
+///
+/// Genomes are usually distributed gzipped, so gzip and BGZF input, recognized by its magic bytes
+/// rather than its extension so piped input works too, is decompressed as it is read. Tar, gzipped
+/// tar and zip archives, also recognized by their magic bytes, are read as the FASTA members they
+/// hold, one after another (see [`archive`]).
+///
+/// # Errors
+///
+/// Returns any error from opening the file or reading its first bytes.
+pub fn open_input(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    open_input_member(path, None)
+}
+
+/// Opens FASTA input for reading as [`open_input`] does, reading only `member` of an archive if
+/// it is given (`--archive-member`).
+///
+/// # Errors
+///
+/// Returns any error from opening the file or reading its first bytes, an
+/// `io::ErrorKind::InvalidInput` error if `member` is given and the input is not an archive, or
+/// an `io::ErrorKind::Unsupported` error for a zip archive on standard input.
+pub fn open_input_member(path: &Path, member: Option<&str>) -> io::Result<Box<dyn BufRead>> {
+    let mut reader: Box<dyn BufRead + Send> = if path.as_os_str() == STDIN {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(path)?))
+    };
+    match ArchiveKind::sniff(reader.fill_buf()?) {
+        Some(ArchiveKind::Zip) if path.as_os_str() != STDIN => Ok(Box::new(BufReader::new(
+            open_archive(path, ArchiveKind::Zip, member)?,
+        ))),
+        Some(kind) => Ok(Box::new(BufReader::new(stream_archive(
+            reader, kind, member,
+        )?))),
+        None => match member {
+            Some(member) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("not a tar or zip archive, so it has no member {}", member),
+            )),
+            None => decompress(reader),
+        },
+    }
+}
+
+/// Helper to `open_input_member()` that wraps a reader in a decoder if it starts with the gzip
+/// magic bytes. BGZF files are gzip files of many members, which the decoder reads one after
+/// another.
+fn decompress<R: BufRead + 'static>(mut reader: R) -> io::Result<Box<dyn BufRead>> {
+    if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// A FASTA reader that streams bases without holding whole lines or records in memory.
+///
+/// `noodles_fasta::Reader` reads each record's sequence into a single allocation, and line-based
+/// readers allocate a full line at a time. Some genomes ship with a whole chromosome on one line,
+/// so this reader works directly on the `BufRead` buffer instead: memory use is bounded by the
+/// buffer size no matter how long the lines are.
+///
+/// A byte order mark at the start of the input, CRLF line endings and spaces or tabs at the ends
+/// of sequence lines are skipped, so they neither hide the first record nor shift coordinates.
+/// Only blank lines may come before the first header.
+///
+/// # Fields
+///
+/// * `inner`: The buffered source of FASTA text.
+/// * `started`: Whether the start of the input, where a byte order mark may be, has been read.
+/// * `in_records`: Whether a header has been read, after which the lines up to the next header
+///   are the bases of its record.
+pub struct StreamingReader<R: BufRead> {
+    inner: R,
+    started: bool,
+    in_records: bool,
+}
+
+impl<R: BufRead> StreamingReader<R> {
+    /// Constructor for `StreamingReader`.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            started: false,
+            in_records: false,
+        }
+    }
+
+    /// Advances to the next record and returns its name (the header up to the first whitespace).
+    ///
+    /// Any bases of the current record that were not read are skipped. Returns `Ok(None)` at the
+    /// end of the input.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from reading, or an `io::ErrorKind::InvalidData` error naming the line
+    /// if anything but blank lines comes before the first header, as with text that is not FASTA.
+    pub fn next_record(&mut self) -> io::Result<Option<String>> {
+        if !self.started {
+            skip_bom(&mut self.inner)?;
+            self.started = true;
+        }
+        let mut at_line_start = true;
+        let mut line = 1;
+        loop {
+            let buf = self.inner.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            if at_line_start && buf[0] == b'>' {
+                break;
+            }
+            let end = buf.iter().position(|&b| b == b'\n');
+            if !self.in_records
+                && buf[..end.unwrap_or(buf.len())]
+                    .iter()
+                    .any(|b| !b.is_ascii_whitespace())
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: expected a '>' header before any sequence", line),
+                ));
+            }
+            // skip to the end of the current line without copying it anywhere
+            match end {
+                Some(i) => {
+                    self.inner.consume(i + 1);
+                    at_line_start = true;
+                    line += 1;
+                }
+                None => {
+                    let n = buf.len();
+                    self.inner.consume(n);
+                    at_line_start = false;
+                }
+            }
+        }
+        self.in_records = true;
+        let mut header = Vec::new();
+        self.inner.read_until(b'\n', &mut header)?;
+        let header = String::from_utf8_lossy(&header[1..]);
+        let name = header.split_whitespace().next().unwrap_or_default();
+        Ok(Some(name.to_string()))
+    }
+
+    /// Returns an iterator over the bases of the current record, ending at the next header or the
+    /// end of the input. Line breaks, spaces and tabs are skipped.
+    pub fn bases(&mut self) -> Bases<'_, R> {
+        Bases {
+            inner: &mut self.inner,
+            at_line_start: true,
+        }
+    }
+}
+
+/// Iterator over the bases of one record from a `StreamingReader`.
+///
+/// # Fields
+///
+/// * `inner`: The buffered source of FASTA text.
+/// * `at_line_start`: Whether the next byte starts a line, where a `>` begins the next record.
+pub struct Bases<'a, R: BufRead> {
+    inner: &'a mut R,
+    at_line_start: bool,
+}
+
+impl<R: BufRead> Iterator for Bases<'_, R> {
+    type Item = io::Result<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let buf = match self.inner.fill_buf() {
+                Ok(buf) => buf,
+                Err(e) => return Some(Err(e)),
+            };
+            let &byte = buf.first()?;
+            if self.at_line_start && byte == b'>' {
+                return None;
+            }
+            self.inner.consume(1);
+            match byte {
+                b'\n' => self.at_line_start = true,
+                b'\r' | b' ' | b'\t' => {}
+                _ => {
+                    self.at_line_start = false;
+                    return Some(Ok(byte));
+                }
+            }
+        }
+    }
+}
+
+/// Reverse complement a nucleotide sequence.
+///
+/// Case is preserved and anything other than `A`, `C`, `G`, or `T` (such as `N`) is kept as-is,
+/// so the result lines up position-for-position with the reversed input.
+pub fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+    seq.iter()
+        .rev()
+        .map(|&b| match b {
+            b'A' => b'T',
+            b'C' => b'G',
+            b'G' => b'C',
+            b'T' => b'A',
+            b'a' => b't',
+            b'c' => b'g',
+            b'g' => b'c',
+            b't' => b'a',
+            _ => b,
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -132,4 +361,124 @@ mod tests {
             .collect();
         assert_eq!(split_records.len(), 0);
     }
+
+    #[test]
+    fn test_reverse_complement() {
+        assert_eq!(reverse_complement(b"AACGTN"), b"NACGTT".to_vec());
+        assert_eq!(reverse_complement(b"acgT"), b"Acgt".to_vec());
+        assert_eq!(reverse_complement(b""), b"".to_vec());
+    }
+
+    #[test]
+    fn test_streaming_reader() {
+        let src = b">sq0 first one\nACGT\nAC\r\n>sq1\nNNNN\n\n>sq2\n";
+        let mut reader = StreamingReader::new(&src[..]);
+        assert_eq!(reader.next_record().unwrap(), Some("sq0".to_string()));
+        let bases: Vec<u8> = reader.bases().map(|b| b.unwrap()).collect();
+        assert_eq!(bases, b"ACGTAC".to_vec());
+        // skipping over the unread bases of sq1
+        assert_eq!(reader.next_record().unwrap(), Some("sq1".to_string()));
+        assert_eq!(reader.next_record().unwrap(), Some("sq2".to_string()));
+        assert_eq!(reader.bases().count(), 0);
+        assert_eq!(reader.next_record().unwrap(), None);
+    }
+
+    #[test]
+    fn test_streaming_reader_windows_text() {
+        let src = b"\xEF\xBB\xBF>sq0 first\r\nACGT  \r\nAC\t\r\n\r\n>sq1\r\nGG\r\n";
+        let mut reader = StreamingReader::new(&src[..]);
+        assert_eq!(reader.next_record().unwrap(), Some("sq0".to_string()));
+        let bases: Vec<u8> = reader.bases().map(|b| b.unwrap()).collect();
+        assert_eq!(bases, b"ACGTAC".to_vec());
+        assert_eq!(reader.next_record().unwrap(), Some("sq1".to_string()));
+        assert_eq!(reader.bases().count(), 2);
+    }
+
+    #[test]
+    fn test_streaming_reader_no_header() {
+        // blank lines may come first
+        let mut reader = StreamingReader::new(&b"\n  \r\n>sq0\nAC\n"[..]);
+        assert_eq!(reader.next_record().unwrap(), Some("sq0".to_string()));
+        for (src, line) in [
+            (&b"ACGT\n>sq0\nAC\n"[..], 1),
+            (&b"\n\nACGT\n>sq0\nAC\n"[..], 3),
+            (&b"\x89PNG\r\n\x1a\n\0\0"[..], 1),
+        ] {
+            let err = StreamingReader::new(src).next_record().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(err.to_string().starts_with(&format!("line {}:", line)));
+        }
+    }
+
+    /// A `Read` that produces a single-line FASTA record of a given length without ever holding
+    /// it in memory.
+    struct SingleLineFasta {
+        header: &'static [u8],
+        remaining: usize,
+        pos: usize,
+    }
+
+    impl io::Read for SingleLineFasta {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.pos < self.header.len() {
+                let n = buf.len().min(self.header.len() - self.pos);
+                buf[..n].copy_from_slice(&self.header[self.pos..self.pos + n]);
+                self.pos += n;
+                return Ok(n);
+            }
+            let n = buf.len().min(self.remaining);
+            for (i, b) in buf[..n].iter_mut().enumerate() {
+                *b = b"ACGT"[(self.remaining - i) % 4];
+            }
+            self.remaining -= n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_streaming_reader_100mb_single_line() {
+        let length = 100_000_000;
+        let source = SingleLineFasta {
+            header: b">chr1 unwrapped\n",
+            remaining: length,
+            pos: 0,
+        };
+        let mut reader = StreamingReader::new(io::BufReader::new(source));
+        assert_eq!(reader.next_record().unwrap(), Some("chr1".to_string()));
+        assert_eq!(reader.bases().count(), length);
+        assert_eq!(reader.next_record().unwrap(), None);
+    }
+
+    #[test]
+    fn test_open_input_gzip() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::{Read, Write};
+        let dir = std::env::temp_dir().join("symcurve_test_open_input_gzip");
+        std::fs::create_dir_all(&dir).unwrap();
+        let fasta = ">chr1\nACGTACGT\n>chr2\nTTGG\n";
+        // two gzip members, as in a BGZF file
+        let mut gzipped = Vec::new();
+        for part in [&fasta[..14], &fasta[14..]] {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(part.as_bytes()).unwrap();
+            gzipped.extend(encoder.finish().unwrap());
+        }
+        // detected by content, whatever the extension
+        for (name, bytes) in [("in.fa.gz", &gzipped), ("in.fa", &gzipped)] {
+            let path = dir.join(name);
+            std::fs::write(&path, bytes).unwrap();
+            let mut text = String::new();
+            open_input(&path)
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            assert_eq!(text, fasta);
+        }
+        let path = dir.join("plain.fa");
+        std::fs::write(&path, fasta).unwrap();
+        let mut reader = StreamingReader::new(open_input(&path).unwrap());
+        assert_eq!(reader.next_record().unwrap().as_deref(), Some("chr1"));
+        assert!(open_input(&dir.join("missing.fa")).is_err());
+    }
 }
@@ -0,0 +1,797 @@
+//! Reading FASTA from tar and zip archives.
+//!
+//! Reference bundles are often shipped as a `.tar.gz` or `.zip` of several FASTA files. Rather than
+//! extracting them first, an archive can be given as the input: its FASTA members, optionally
+//! gzipped, are read in archive order as if they were one FASTA file. `--archive-member` picks out
+//! a single member instead. Tar archives are streamed, so a compressed bundle is decompressed once
+//! and nothing is written to disk.
+
+use flate2::read::{DeflateDecoder, MultiGzDecoder};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Take};
+use std::path::{Path, PathBuf};
+
+/// The size of a tar header and the unit tar contents are padded to.
+const TAR_BLOCK: u64 = 512;
+
+/// The file name extensions of FASTA members, after any `.gz`.
+const FASTA_EXTENSIONS: [&str; 4] = ["fa", "fasta", "fna", "fas"];
+
+/// The kinds of archive that can be read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveKind {
+    /// Recognizes an archive by its first bytes, whatever its file name: the signature of a zip
+    /// local header or empty directory, or the `ustar` magic of a tar header, as is or gzipped.
+    /// Returns `None` for anything else, which is read as FASTA.
+    pub fn sniff(head: &[u8]) -> Option<Self> {
+        if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+            Some(ArchiveKind::Zip)
+        } else if is_tar_header(head) {
+            Some(ArchiveKind::Tar)
+        } else if head.starts_with(&[0x1f, 0x8b]) {
+            // enough of the head is there to decompress the first tar header, if it is one
+            let mut block = Vec::new();
+            let _ = MultiGzDecoder::new(head)
+                .take(TAR_BLOCK)
+                .read_to_end(&mut block);
+            is_tar_header(&block).then_some(ArchiveKind::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether a block starts with a tar header of the POSIX (`ustar`) format, which GNU tar and
+/// bsdtar both write.
+fn is_tar_header(block: &[u8]) -> bool {
+    block.get(257..262) == Some(&b"ustar"[..])
+}
+
+/// Whether a member holds FASTA, judging by its name. Directories and the `._` resource files
+/// macOS adds to archives are not.
+pub fn is_fasta_member(name: &str) -> bool {
+    let base = name.rsplit('/').next().unwrap_or(name);
+    if base.starts_with("._") {
+        return false;
+    }
+    let base = base.to_ascii_lowercase();
+    let base = base.strip_suffix(".gz").unwrap_or(&base);
+    match base.rsplit_once('.') {
+        Some((stem, ext)) => !stem.is_empty() && FASTA_EXTENSIONS.contains(&ext),
+        None => false,
+    }
+}
+
+/// Opens an archive for reading its FASTA members as one stream.
+///
+/// # Arguments
+///
+/// * `path` - The archive.
+/// * `kind` - What kind of archive it is.
+/// * `member` - The path of the one member to read, or `None` for every FASTA member.
+///
+/// # Errors
+///
+/// Opening fails if the file cannot be read or, for zip archives, if its directory is missing,
+/// uses zip64, or names no matching member. Tar archives are streamed, so the same problems
+/// surface as errors from `read` instead.
+pub fn open_archive(
+    path: &Path,
+    kind: ArchiveKind,
+    member: Option<&str>,
+) -> io::Result<Box<dyn Read + Send>> {
+    let file = File::open(path)?;
+    match kind {
+        ArchiveKind::Zip => Ok(Box::new(ZipReader::new(path, file, member)?)),
+        _ => stream_archive(BufReader::new(file), kind, member),
+    }
+}
+
+/// Reads the FASTA members of a tar archive as one stream, from any reader, such as standard
+/// input.
+///
+/// # Arguments
+///
+/// * `inner` - The archive.
+/// * `kind` - What kind of archive it is.
+/// * `member` - The path of the one member to read, or `None` for every FASTA member.
+///
+/// # Errors
+///
+/// Returns an `io::ErrorKind::Unsupported` error for zip archives, whose directory is at their end
+/// and can only be read from a file.
+pub fn stream_archive<R: Read + Send + 'static>(
+    inner: R,
+    kind: ArchiveKind,
+    member: Option<&str>,
+) -> io::Result<Box<dyn Read + Send>> {
+    match kind {
+        ArchiveKind::Tar => Ok(Box::new(TarReader::new(inner, member))),
+        ArchiveKind::TarGz => Ok(Box::new(TarReader::new(MultiGzDecoder::new(inner), member))),
+        ArchiveKind::Zip => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "zip archives can only be read from a file",
+        )),
+    }
+}
+
+/// Wraps a member's contents in a gzip decoder if its name says it is gzipped.
+fn member_contents<R: Read>(name: &str, contents: R) -> MemberContents<R> {
+    if name.to_ascii_lowercase().ends_with(".gz") {
+        MemberContents::Gzip(MultiGzDecoder::new(contents))
+    } else {
+        MemberContents::Plain(contents)
+    }
+}
+
+/// The contents of the member being read.
+enum MemberContents<R: Read> {
+    Plain(R),
+    Gzip(MultiGzDecoder<R>),
+}
+
+impl<R: Read> MemberContents<R> {
+    /// Gets back the reader of the raw contents.
+    fn into_inner(self) -> R {
+        match self {
+            MemberContents::Plain(inner) => inner,
+            MemberContents::Gzip(decoder) => decoder.into_inner(),
+        }
+    }
+}
+
+impl<R: Read> Read for MemberContents<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            MemberContents::Plain(inner) => inner.read(buf),
+            MemberContents::Gzip(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+/// Joins members into one FASTA stream, adding a line break between them where a member does
+/// not end with one, so that the next member's first header starts a line.
+///
+/// # Fields
+///
+/// * `last`: The last byte of the stream so far.
+/// * `pending_newline`: Whether a line break is owed before the next member's contents.
+#[derive(Default)]
+struct Joiner {
+    last: Option<u8>,
+    pending_newline: bool,
+}
+
+impl Joiner {
+    /// Notes the start of a new member.
+    fn start_member(&mut self) {
+        self.pending_newline = self.last.is_some_and(|b| b != b'\n');
+    }
+
+    /// Writes any owed line break into `buf`, returning whether one was written.
+    fn take_newline(&mut self, buf: &mut [u8]) -> bool {
+        if self.pending_newline && !buf.is_empty() {
+            buf[0] = b'\n';
+            self.pending_newline = false;
+            self.last = Some(b'\n');
+            return true;
+        }
+        false
+    }
+
+    /// Notes bytes passed through from a member.
+    fn pass(&mut self, bytes: &[u8]) {
+        if let Some(&b) = bytes.last() {
+            self.last = Some(b);
+        }
+    }
+}
+
+/// Streams the FASTA members of a tar archive.
+///
+/// # Fields
+///
+/// * `inner`: The archive, when between members.
+/// * `current`: The member being read, with the padding that follows it.
+/// * `member`: The one member to read, if given.
+/// * `found`: Whether any member was read.
+/// * `done`: Whether the end of the archive was reached.
+/// * `joiner`: Keeps members' records apart.
+struct TarReader<R: Read> {
+    inner: Option<R>,
+    current: Option<(MemberContents<Take<R>>, u64)>,
+    member: Option<String>,
+    found: bool,
+    done: bool,
+    joiner: Joiner,
+}
+
+impl<R: Read> TarReader<R> {
+    fn new(inner: R, member: Option<&str>) -> Self {
+        Self {
+            inner: Some(inner),
+            current: None,
+            member: member.map(|m| m.trim_start_matches("./").to_string()),
+            found: false,
+            done: false,
+            joiner: Joiner::default(),
+        }
+    }
+
+    /// Whether a member is one to read.
+    fn wanted(&self, name: &str) -> bool {
+        match &self.member {
+            Some(member) => name.trim_start_matches("./") == member,
+            None => is_fasta_member(name),
+        }
+    }
+
+    /// Finishes the current member, skipping what was not read of it and its padding.
+    fn close_member(&mut self) -> io::Result<()> {
+        if let Some((contents, padding)) = self.current.take() {
+            let take = contents.into_inner();
+            let rest = take.limit() + padding;
+            let mut inner = take.into_inner();
+            skip(&mut inner, rest)?;
+            self.inner = Some(inner);
+        }
+        Ok(())
+    }
+
+    /// Moves to the next member to read, or to the end of the archive.
+    fn next_member(&mut self) -> io::Result<()> {
+        self.close_member()?;
+        let mut inner = self.inner.take().expect("between members");
+        let mut long_name = None;
+        loop {
+            let Some(header) = read_tar_header(&mut inner)? else {
+                self.inner = Some(inner);
+                self.done = true;
+                return match &self.member {
+                    Some(member) if !self.found => Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("the archive has no member {}", member),
+                    )),
+                    None if !self.found => Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "the archive has no FASTA members",
+                    )),
+                    _ => Ok(()),
+                };
+            };
+            let padding = (TAR_BLOCK - header.size % TAR_BLOCK) % TAR_BLOCK;
+            match header.kind {
+                // GNU long names and pax extended headers name the entry that follows
+                b'L' | b'x' => {
+                    let mut data = Vec::new();
+                    (&mut inner).take(header.size).read_to_end(&mut data)?;
+                    skip(&mut inner, padding)?;
+                    long_name = if header.kind == b'L' {
+                        let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+                        Some(String::from_utf8_lossy(&data[..end]).into_owned())
+                    } else {
+                        pax_path(&data).or(long_name)
+                    };
+                }
+                b'0' | 0 | b'7' => {
+                    let name = long_name.take().unwrap_or(header.name);
+                    if self.wanted(&name) {
+                        let contents = member_contents(&name, inner.take(header.size));
+                        self.current = Some((contents, padding));
+                        self.found = true;
+                        self.joiner.start_member();
+                        return Ok(());
+                    }
+                    skip(&mut inner, header.size + padding)?;
+                }
+                _ => {
+                    long_name = None;
+                    skip(&mut inner, header.size + padding)?;
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for TarReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.done {
+                return Ok(0);
+            }
+            if self.joiner.take_newline(buf) {
+                return Ok(1);
+            }
+            if let Some((contents, _)) = &mut self.current {
+                let n = contents.read(buf)?;
+                if n > 0 || buf.is_empty() {
+                    self.joiner.pass(&buf[..n]);
+                    return Ok(n);
+                }
+            }
+            self.next_member()?;
+        }
+    }
+}
+
+/// The parts of a tar header that matter here.
+struct TarHeader {
+    name: String,
+    size: u64,
+    kind: u8,
+}
+
+/// Reads the next tar header, or `None` at the end-of-archive marker or the end of the input.
+///
+/// # Errors
+///
+/// Returns an `InvalidData` error if the block is not a valid tar header.
+fn read_tar_header<R: Read>(inner: &mut R) -> io::Result<Option<TarHeader>> {
+    let mut block = [0u8; TAR_BLOCK as usize];
+    let mut filled = 0;
+    while filled < block.len() {
+        match inner.read(&mut block[filled..])? {
+            0 if filled == 0 => return Ok(None),
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => filled += n,
+        }
+    }
+    if block.iter().all(|&b| b == 0) {
+        return Ok(None);
+    }
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a valid tar archive");
+    // the checksum is taken with its own field read as spaces
+    let sum: u64 = block
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| {
+            if (148..156).contains(&i) {
+                32
+            } else {
+                b as u64
+            }
+        })
+        .sum();
+    if tar_number(&block[148..156]).ok_or_else(invalid)? != sum {
+        return Err(invalid());
+    }
+    let text = |field: &[u8]| {
+        let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+        String::from_utf8_lossy(&field[..end]).into_owned()
+    };
+    let mut name = text(&block[0..100]);
+    if &block[257..262] == b"ustar" {
+        let prefix = text(&block[345..500]);
+        if !prefix.is_empty() {
+            name = format!("{}/{}", prefix, name);
+        }
+    }
+    Ok(Some(TarHeader {
+        name,
+        size: tar_number(&block[124..136]).ok_or_else(invalid)?,
+        kind: block[156],
+    }))
+}
+
+/// Parses a numeric tar field: octal text, or big-endian binary when the high bit of the first
+/// byte is set, as GNU tar writes sizes of 8 GiB and more.
+fn tar_number(field: &[u8]) -> Option<u64> {
+    if field[0] & 0x80 != 0 {
+        let mut value = (field[0] & 0x7F) as u64;
+        for &b in &field[1..] {
+            value = value.checked_mul(256)? | b as u64;
+        }
+        return Some(value);
+    }
+    let text = std::str::from_utf8(field).ok()?;
+    let text = text.trim_matches(|c: char| c == '\0' || c == ' ');
+    if text.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(text, 8).ok()
+}
+
+/// Finds the `path` record of a pax extended header, whose records are `<length> <key>=<value>\n`.
+fn pax_path(data: &[u8]) -> Option<String> {
+    let mut rest = data;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|&b| b == b' ')?;
+        let length: usize = std::str::from_utf8(&rest[..space]).ok()?.parse().ok()?;
+        let record = rest.get(space + 1..length)?;
+        if let Some(value) = record.strip_prefix(b"path=") {
+            let value = value.strip_suffix(b"\n").unwrap_or(value);
+            return Some(String::from_utf8_lossy(value).into_owned());
+        }
+        rest = &rest[length..];
+    }
+    None
+}
+
+/// Reads and discards `count` bytes.
+fn skip<R: Read>(inner: &mut R, count: u64) -> io::Result<()> {
+    let skipped = io::copy(&mut inner.take(count), &mut io::sink())?;
+    if skipped < count {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+/// A member listed in a zip archive's central directory.
+///
+/// # Fields
+///
+/// * `name`: Its path in the archive.
+/// * `method`: How it is compressed: 0 for stored, 8 for deflate.
+/// * `compressed_size`: Its size in the archive.
+/// * `header_offset`: Where its local header is.
+struct ZipEntry {
+    name: String,
+    method: u16,
+    compressed_size: u64,
+    header_offset: u64,
+}
+
+/// Reads the FASTA members of a zip archive, one after another.
+///
+/// # Fields
+///
+/// * `path`: The archive, reopened for each member.
+/// * `entries`: The members still to read, in reverse order.
+/// * `current`: The member being read.
+/// * `joiner`: Keeps members' records apart.
+struct ZipReader {
+    path: PathBuf,
+    entries: Vec<ZipEntry>,
+    current: Option<Box<dyn Read + Send>>,
+    joiner: Joiner,
+}
+
+impl ZipReader {
+    fn new(path: &Path, mut file: File, member: Option<&str>) -> io::Result<Self> {
+        let mut entries = read_zip_directory(&mut file)?;
+        match member {
+            Some(member) => {
+                let member = member.trim_start_matches("./");
+                entries.retain(|entry| entry.name == member);
+                if entries.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("the archive has no member {}", member),
+                    ));
+                }
+            }
+            None => {
+                entries.retain(|entry| is_fasta_member(&entry.name));
+                if entries.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "the archive has no FASTA members",
+                    ));
+                }
+            }
+        }
+        for entry in &entries {
+            if entry.method != 0 && entry.method != 8 {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!(
+                        "member {} uses zip compression method {}; only stored and deflate are \
+                         supported",
+                        entry.name, entry.method
+                    ),
+                ));
+            }
+        }
+        entries.reverse();
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+            current: None,
+            joiner: Joiner::default(),
+        })
+    }
+
+    /// Opens the next member, returning `false` when there are none left.
+    fn next_member(&mut self) -> io::Result<bool> {
+        let Some(entry) = self.entries.pop() else {
+            self.current = None;
+            return Ok(false);
+        };
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(entry.header_offset))?;
+        let mut header = [0u8; 30];
+        file.read_exact(&mut header)?;
+        if header[..4] != [0x50, 0x4B, 0x03, 0x04] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the zip entry for {} is damaged", entry.name),
+            ));
+        }
+        let name_length = u16::from_le_bytes([header[26], header[27]]) as i64;
+        let extra_length = u16::from_le_bytes([header[28], header[29]]) as i64;
+        file.seek(SeekFrom::Current(name_length + extra_length))?;
+        let raw = BufReader::new(file).take(entry.compressed_size);
+        self.current = Some(if entry.method == 8 {
+            Box::new(member_contents(&entry.name, DeflateDecoder::new(raw)))
+        } else {
+            Box::new(member_contents(&entry.name, raw))
+        });
+        self.joiner.start_member();
+        Ok(true)
+    }
+}
+
+impl Read for ZipReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.joiner.take_newline(buf) {
+                return Ok(1);
+            }
+            if let Some(current) = &mut self.current {
+                let n = current.read(buf)?;
+                if n > 0 || buf.is_empty() {
+                    self.joiner.pass(&buf[..n]);
+                    return Ok(n);
+                }
+            }
+            if !self.next_member()? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+/// Reads the central directory of a zip archive.
+///
+/// # Errors
+///
+/// Returns an `InvalidData` error if the end-of-directory record cannot be found, and an
+/// `Unsupported` error for zip64 archives.
+fn read_zip_directory(file: &mut File) -> io::Result<Vec<ZipEntry>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    // the end record is 22 bytes plus a comment of up to 64 KiB
+    let length = file.seek(SeekFrom::End(0))?;
+    let tail_length = length.min(22 + 0xFFFF);
+    file.seek(SeekFrom::Start(length - tail_length))?;
+    let mut tail = vec![0u8; tail_length as usize];
+    file.read_exact(&mut tail)?;
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| tail[i..i + 4] == [0x50, 0x4B, 0x05, 0x06])
+        .ok_or_else(|| invalid("not a valid zip archive"))?;
+    let record = &tail[end..];
+    let u16_at = |b: &[u8], i: usize| u16::from_le_bytes([b[i], b[i + 1]]);
+    let u32_at = |b: &[u8], i: usize| u32::from_le_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]]);
+    let count = u16_at(record, 10);
+    let size = u32_at(record, 12);
+    let offset = u32_at(record, 16);
+    if count == 0xFFFF || size == u32::MAX || offset == u32::MAX {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "zip64 archives are not supported",
+        ));
+    }
+    file.seek(SeekFrom::Start(offset as u64))?;
+    let mut directory = vec![0u8; size as usize];
+    file.read_exact(&mut directory)?;
+    let mut entries = Vec::with_capacity(count as usize);
+    let mut i = 0;
+    for _ in 0..count {
+        let header = directory
+            .get(i..i + 46)
+            .filter(|h| h[..4] == [0x50, 0x4B, 0x01, 0x02])
+            .ok_or_else(|| invalid("the zip directory is damaged"))?;
+        let name_length = u16_at(header, 28) as usize;
+        let extra_length = u16_at(header, 30) as usize;
+        let comment_length = u16_at(header, 32) as usize;
+        let name = directory
+            .get(i + 46..i + 46 + name_length)
+            .ok_or_else(|| invalid("the zip directory is damaged"))?;
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            method: u16_at(header, 10),
+            compressed_size: u32_at(header, 20) as u64,
+            header_offset: u32_at(header, 42) as u64,
+        });
+        i += 46 + name_length + extra_length + comment_length;
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::{DeflateEncoder, GzEncoder};
+    use flate2::Compression;
+    use std::fs;
+    use std::io::Write;
+
+    /// Builds a tar archive of `(name, contents)` members.
+    fn tar(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for &(name, contents) in members {
+            let mut header = [0u8; 512];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[100..107].copy_from_slice(b"0000644");
+            header[124..135].copy_from_slice(format!("{:011o}", contents.len()).as_bytes());
+            header[156] = b'0';
+            header[257..263].copy_from_slice(b"ustar\0");
+            header[148..156].fill(b' ');
+            let sum: u32 = header.iter().map(|&b| b as u32).sum();
+            header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+            out.extend_from_slice(&header);
+            out.extend_from_slice(contents);
+            out.resize(out.len().div_ceil(512) * 512, 0);
+        }
+        out.resize(out.len() + 1024, 0);
+        out
+    }
+
+    /// Builds a zip archive of `(name, contents, deflate)` members.
+    fn zip(members: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut directory = Vec::new();
+        for &(name, contents, deflate) in members {
+            let data = if deflate {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(contents).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                contents.to_vec()
+            };
+            let method: u16 = if deflate { 8 } else { 0 };
+            let offset = out.len() as u32;
+            out.extend_from_slice(&[0x50, 0x4B, 0x03, 0x04, 20, 0, 0, 0]);
+            out.extend_from_slice(&method.to_le_bytes());
+            out.extend_from_slice(&[0; 8]);
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            out.extend_from_slice(&[0, 0]);
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&data);
+            directory.extend_from_slice(&[0x50, 0x4B, 0x01, 0x02, 20, 0, 20, 0, 0, 0]);
+            directory.extend_from_slice(&method.to_le_bytes());
+            directory.extend_from_slice(&[0; 8]);
+            directory.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            directory.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            directory.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            directory.extend_from_slice(&[0; 12]);
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+        let offset = out.len() as u32;
+        out.extend_from_slice(&directory);
+        out.extend_from_slice(&[0x50, 0x4B, 0x05, 0x06, 0, 0, 0, 0]);
+        out.extend_from_slice(&(members.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(members.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        out.extend_from_slice(&offset.to_le_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn read_all(path: &Path, member: Option<&str>) -> io::Result<String> {
+        let kind = ArchiveKind::sniff(&fs::read(path)?).unwrap_or(ArchiveKind::Tar);
+        let mut text = String::new();
+        open_archive(path, kind, member)?.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    #[test]
+    fn test_sniff() {
+        let contents = tar(&[("chr1.fa", b">chr1\nACGT\n")]);
+        assert_eq!(ArchiveKind::sniff(&contents), Some(ArchiveKind::Tar));
+        assert_eq!(
+            ArchiveKind::sniff(&gzip(&contents)),
+            Some(ArchiveKind::TarGz)
+        );
+        let contents = zip(&[("chr1.fa", b">chr1\nACGT\n", true)]);
+        assert_eq!(ArchiveKind::sniff(&contents), Some(ArchiveKind::Zip));
+        assert_eq!(ArchiveKind::sniff(&gzip(b">chr1\nACGT\n")), None);
+        assert_eq!(ArchiveKind::sniff(b">chr1\nACGT\n"), None);
+        assert_eq!(ArchiveKind::sniff(b""), None);
+        assert!(is_fasta_member("bundle/chr1.fa"));
+        assert!(is_fasta_member("chrM.fasta.gz"));
+        assert!(!is_fasta_member("bundle/._chr1.fa"));
+        assert!(!is_fasta_member("README.md"));
+        assert!(!is_fasta_member(".fa"));
+    }
+
+    #[test]
+    fn test_tar() {
+        let dir = std::env::temp_dir().join("symcurve_test_archive_tar");
+        fs::create_dir_all(&dir).unwrap();
+        let contents = tar(&[
+            ("bundle/README", b"not a sequence\n"),
+            ("bundle/chr1.fa", b">chr1\nACGT"),
+            ("bundle/chr2.fa.gz", &gzip(b">chr2\nGGCC\n")),
+            ("bundle/chrM.fna", b">chrM\nTTAA\n"),
+        ]);
+        let path = dir.join("bundle.tar");
+        fs::write(&path, &contents).unwrap();
+        // the missing line break after chr1 is supplied
+        assert_eq!(
+            read_all(&path, None).unwrap(),
+            ">chr1\nACGT\n>chr2\nGGCC\n>chrM\nTTAA\n"
+        );
+        assert_eq!(
+            read_all(&path, Some("bundle/chr2.fa.gz")).unwrap(),
+            ">chr2\nGGCC\n"
+        );
+        let err = read_all(&path, Some("chr3.fa")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        // the same, gzipped as a whole
+        let path = dir.join("bundle.tar.gz");
+        fs::write(&path, gzip(&contents)).unwrap();
+        assert_eq!(
+            read_all(&path, Some("./bundle/chrM.fna")).unwrap(),
+            ">chrM\nTTAA\n"
+        );
+        let path = dir.join("empty.tar");
+        fs::write(&path, tar(&[("notes.txt", b"x")])).unwrap();
+        assert!(read_all(&path, None).is_err());
+        let path = dir.join("bad.tar");
+        fs::write(&path, [1u8; 1024]).unwrap();
+        assert_eq!(
+            read_all(&path, None).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_zip() {
+        let dir = std::env::temp_dir().join("symcurve_test_archive_zip");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bundle.zip");
+        fs::write(
+            &path,
+            zip(&[
+                ("chr1.fa", b">chr1\nACGT", false),
+                ("docs/", b"", false),
+                ("chr2.fasta", b">chr2\nGGCCGGCCGGCC\n", true),
+                ("chr3.fa.gz", &gzip(b">chr3\nAT\n"), true),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(
+            read_all(&path, None).unwrap(),
+            ">chr1\nACGT\n>chr2\nGGCCGGCCGGCC\n>chr3\nAT\n"
+        );
+        assert_eq!(read_all(&path, Some("chr3.fa.gz")).unwrap(), ">chr3\nAT\n");
+        assert_eq!(
+            read_all(&path, Some("chr9.fa")).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        fs::write(&path, b"not a zip").unwrap();
+        assert!(read_all(&path, None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_tar_numbers() {
+        assert_eq!(tar_number(b"00000001750\0"), Some(1000));
+        assert_eq!(tar_number(b"     17 \0"), Some(15));
+        assert_eq!(
+            tar_number(&[0x80, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0]),
+            Some(0x2_0000_0000)
+        );
+        assert_eq!(
+            pax_path(b"28 path=bundle/long/chr1.fa\n"),
+            Some("bundle/long/chr1.fa".to_string())
+        );
+    }
+}
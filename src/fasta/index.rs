@@ -0,0 +1,281 @@
+//! FASTA index (`.fai`) random access.
+//!
+//! Recomputing a region on demand needs its bases without reading the whole genome first. A
+//! `samtools faidx` index gives, for each sequence, where its bases start in the file and how its
+//! lines are wrapped, which is enough to seek straight to any base. When there is no `.fai` next
+//! to the FASTA file, the same index is built by one pass over it.
+
+use crate::text::{clean_line, skip_bom};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// The index entry of one sequence.
+///
+/// # Fields
+///
+/// * `name`: The sequence name.
+/// * `length`: The number of bases.
+/// * `offset`: The byte offset of the first base in the file.
+/// * `line_bases`: The number of bases on each full line.
+/// * `line_width`: The number of bytes of each full line, including its line ending.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FaiRecord {
+    pub name: String,
+    pub length: u64,
+    pub offset: u64,
+    pub line_bases: u64,
+    pub line_width: u64,
+}
+
+impl FaiRecord {
+    /// The byte offset of a 0-based base position.
+    fn byte_offset(&self, pos: u64) -> u64 {
+        if self.line_bases == 0 {
+            return self.offset;
+        }
+        self.offset + pos / self.line_bases * self.line_width + pos % self.line_bases
+    }
+}
+
+/// A FASTA index.
+///
+/// # Fields
+///
+/// * `records`: The sequences, in file order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FastaIndex {
+    pub records: Vec<FaiRecord>,
+}
+
+/// An `InvalidData` error.
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl FastaIndex {
+    /// The path of the index of a FASTA file, `genome.fa.fai` for `genome.fa`.
+    pub fn fai_path(fasta: &Path) -> PathBuf {
+        let mut name = fasta.as_os_str().to_owned();
+        name.push(".fai");
+        PathBuf::from(name)
+    }
+
+    /// Reads a `.fai` file: tab-separated name, length, offset, line bases and line width. CRLF
+    /// line endings, a byte order mark and trailing whitespace are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error naming the line if a line does not have five fields or a
+    /// field is not a number.
+    pub fn read_fai<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut records = Vec::new();
+        for (number, line) in (1..).zip(reader.lines()) {
+            let line = line?;
+            let line = clean_line(&line);
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let bad = || invalid(format!("FASTA index line {}: expected 5 fields", number));
+            if fields.len() < 5 {
+                return Err(bad());
+            }
+            let number_at = |i: usize| fields[i].parse::<u64>().map_err(|_| bad());
+            records.push(FaiRecord {
+                name: fields[0].to_string(),
+                length: number_at(1)?,
+                offset: number_at(2)?,
+                line_bases: number_at(3)?,
+                line_width: number_at(4)?,
+            });
+        }
+        Ok(Self { records })
+    }
+
+    /// Builds the index by reading FASTA text once.
+    ///
+    /// A byte order mark at the start is skipped, and CRLF line endings and trailing spaces or
+    /// tabs are part of each line's width but not of its bases, as [`FastaIndex::fetch`] expects.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidData` error if a sequence's lines are wrapped unevenly, since such a
+    /// file cannot be indexed.
+    pub fn build<R: BufRead>(mut reader: R) -> io::Result<Self> {
+        let mut records: Vec<FaiRecord> = Vec::new();
+        let mut offset = skip_bom(&mut reader)? as u64;
+        let mut line = Vec::new();
+        // whether the current sequence has had a line shorter than the full width
+        let mut short_line = false;
+        loop {
+            line.clear();
+            let width = reader.read_until(b'\n', &mut line)? as u64;
+            if width == 0 {
+                break;
+            }
+            let start = offset;
+            offset += width;
+            if line[0] == b'>' {
+                let header = String::from_utf8_lossy(&line[1..]);
+                let name = header.split_whitespace().next().unwrap_or_default();
+                records.push(FaiRecord {
+                    name: name.to_string(),
+                    length: 0,
+                    offset,
+                    line_bases: 0,
+                    line_width: 0,
+                });
+                short_line = false;
+                continue;
+            }
+            let Some(record) = records.last_mut() else {
+                continue;
+            };
+            let bases = line
+                .iter()
+                .rposition(|b| !b.is_ascii_whitespace())
+                .map_or(0, |last| last + 1) as u64;
+            if bases == 0 {
+                short_line = true;
+                continue;
+            }
+            if record.line_bases == 0 {
+                record.line_bases = bases;
+                record.line_width = width;
+                record.offset = start;
+            } else if short_line || bases > record.line_bases {
+                return Err(invalid(format!(
+                    "sequence {} has lines of different lengths and cannot be indexed",
+                    record.name
+                )));
+            }
+            short_line |= bases < record.line_bases || width != record.line_width;
+            record.length += bases;
+        }
+        Ok(Self { records })
+    }
+
+    /// Loads the index of a FASTA file from its `.fai`, or builds it if there is none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is gzipped, since only plain FASTA can be read at random, or
+    /// if the index cannot be read or built.
+    pub fn load(fasta: &Path) -> io::Result<Self> {
+        let mut file = File::open(fasta)?;
+        let mut magic = [0u8; 2];
+        if file.read(&mut magic)? == 2 && magic == [0x1f, 0x8b] {
+            return Err(invalid(
+                "random access needs an uncompressed FASTA file".to_string(),
+            ));
+        }
+        let fai = Self::fai_path(fasta);
+        if fai.exists() {
+            return Self::read_fai(BufReader::new(File::open(fai)?));
+        }
+        file.seek(SeekFrom::Start(0))?;
+        Self::build(BufReader::new(file))
+    }
+
+    /// The entry of a sequence.
+    pub fn get(&self, name: &str) -> Option<&FaiRecord> {
+        self.records.iter().find(|record| record.name == name)
+    }
+
+    /// Reads the bases of a region of a sequence.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The FASTA file.
+    /// * `record` - The index entry of the sequence.
+    /// * `start` - The 0-based start.
+    /// * `end` - The exclusive end; clamped to the sequence length.
+    pub fn fetch<R: Read + Seek>(
+        reader: &mut R,
+        record: &FaiRecord,
+        start: u64,
+        end: u64,
+    ) -> io::Result<Vec<u8>> {
+        let end = end.min(record.length);
+        if start >= end {
+            return Ok(Vec::new());
+        }
+        let first = record.byte_offset(start);
+        let last = record.byte_offset(end - 1) + 1;
+        reader.seek(SeekFrom::Start(first))?;
+        let mut bytes = Vec::with_capacity((last - first) as usize);
+        reader.take(last - first).read_to_end(&mut bytes)?;
+        bytes.retain(|b| !b.is_ascii_whitespace());
+        if bytes.len() as u64 != end - start {
+            return Err(invalid(format!(
+                "sequence {} does not match its index",
+                record.name
+            )));
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const FASTA: &str = ">chr1 first\nACGTA\nCCGGT\nAA\n>chr2\r\nTTTT\r\nGG\r\n>empty\n";
+
+    #[test]
+    fn test_build() {
+        let index = FastaIndex::build(FASTA.as_bytes()).unwrap();
+        assert_eq!(index.records.len(), 3);
+        let chr1 = index.get("chr1").unwrap();
+        assert_eq!(
+            (chr1.length, chr1.offset, chr1.line_bases, chr1.line_width),
+            (12, 12, 5, 6)
+        );
+        let chr2 = index.get("chr2").unwrap();
+        assert_eq!((chr2.length, chr2.line_bases, chr2.line_width), (6, 4, 6));
+        assert_eq!(index.get("empty").unwrap().length, 0);
+        // the same as samtools faidx writes
+        let fai = "chr1\t12\t12\t5\t6\nchr2\t6\t34\t4\t6\nempty\t0\t51\t0\t0\n";
+        assert_eq!(FastaIndex::read_fai(fai.as_bytes()).unwrap(), index);
+        assert!(FastaIndex::build(">bad\nACG\nACGT\n".as_bytes()).is_err());
+        assert!(FastaIndex::build(">bad\nACGT\nAC\nACGT\n".as_bytes()).is_err());
+        assert!(FastaIndex::read_fai("chr1\t12\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_fetch() {
+        let index = FastaIndex::build(FASTA.as_bytes()).unwrap();
+        let mut file = Cursor::new(FASTA.as_bytes());
+        let chr1 = index.get("chr1").unwrap();
+        assert_eq!(
+            FastaIndex::fetch(&mut file, chr1, 0, 100).unwrap(),
+            b"ACGTACCGGTAA"
+        );
+        assert_eq!(FastaIndex::fetch(&mut file, chr1, 3, 8).unwrap(), b"TACCG");
+        assert!(FastaIndex::fetch(&mut file, chr1, 12, 20)
+            .unwrap()
+            .is_empty());
+        let chr2 = index.get("chr2").unwrap();
+        assert_eq!(FastaIndex::fetch(&mut file, chr2, 2, 6).unwrap(), b"TTGG");
+    }
+
+    #[test]
+    fn test_windows_text() {
+        let fasta = b"\xEF\xBB\xBF>chr1\r\nACGTA \r\nCCGGT \r\nAA\r\n";
+        let index = FastaIndex::build(&fasta[..]).unwrap();
+        let chr1 = index.get("chr1").unwrap();
+        assert_eq!(
+            (chr1.length, chr1.offset, chr1.line_bases, chr1.line_width),
+            (12, 10, 5, 8)
+        );
+        let mut file = Cursor::new(&fasta[..]);
+        assert_eq!(
+            FastaIndex::fetch(&mut file, chr1, 0, 12).unwrap(),
+            b"ACGTACCGGTAA"
+        );
+        let fai = "\u{feff}chr1\t12\t10\t5\t8\r\n\r\n";
+        assert_eq!(FastaIndex::read_fai(fai.as_bytes()).unwrap(), index);
+    }
+}
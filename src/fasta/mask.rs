@@ -0,0 +1,80 @@
+//! Soft-masked intervals.
+//!
+//! Repeat-masked assemblies mark repeats by writing them in lowercase. The curvature pipeline
+//! uppercases bases as it goes, so the masking is noted on the way past: `--masked-bed` writes the
+//! lowercase intervals of each sequence as BED, ready to intersect with curvature peaks without
+//! going back to the RepeatMasker output.
+
+use std::ops::Range;
+
+/// Collects the runs of lowercase bases in a sequence as it streams past.
+///
+/// # Fields
+///
+/// * `position`: The 0-based position of the next base.
+/// * `start`: Where the current run of lowercase bases started, if in one.
+/// * `intervals`: The finished runs.
+#[derive(Debug, Default)]
+pub struct MaskTracker {
+    position: usize,
+    start: Option<usize>,
+    intervals: Vec<Range<usize>>,
+}
+
+impl MaskTracker {
+    /// Constructor for `MaskTracker`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Notes the next base of the sequence.
+    pub fn push(&mut self, base: u8) {
+        match (base.is_ascii_lowercase(), self.start) {
+            (true, None) => self.start = Some(self.position),
+            (false, Some(start)) => {
+                self.intervals.push(start..self.position);
+                self.start = None;
+            }
+            _ => {}
+        }
+        self.position += 1;
+    }
+
+    /// Notes a run of bases.
+    pub fn extend(&mut self, bases: &[u8]) {
+        for &base in bases {
+            self.push(base);
+        }
+    }
+
+    /// Ends the sequence and returns its lowercase intervals, 0-based and half-open, in order.
+    /// The tracker is left ready for the next sequence.
+    pub fn finish(&mut self) -> Vec<Range<usize>> {
+        if let Some(start) = self.start.take() {
+            self.intervals.push(start..self.position);
+        }
+        self.position = 0;
+        std::mem::take(&mut self.intervals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::bed::write_intervals;
+
+    #[test]
+    fn test_mask_tracker() {
+        let mut tracker = MaskTracker::new();
+        tracker.extend(b"acGTNnnACgt");
+        assert_eq!(tracker.finish(), vec![0..2, 5..7, 9..11]);
+        tracker.extend(b"ACGT");
+        assert!(tracker.finish().is_empty());
+        tracker.extend(b"AC");
+        tracker.extend(b"gt");
+        let intervals = tracker.finish();
+        let mut out = Vec::new();
+        write_intervals(&mut out, "chr2", 0, intervals).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "chr2\t2\t4\n");
+    }
+}
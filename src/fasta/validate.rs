@@ -0,0 +1,201 @@
+//! Checks for malformed FASTA records.
+//!
+//! A damaged record in the middle of a genome (a header without a name, a stray byte from a bad
+//! transfer or a careless edit) normally stops the run. With `--on-malformed skip` the record is
+//! left out instead: a warning names it and says what is wrong and where, it is counted in the end
+//! of run summary, and it is listed in the manifest, so the gap in the output is never silent.
+
+use clap::ValueEnum;
+use std::fmt;
+use std::io;
+
+/// What to do with a malformed record.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnMalformed {
+    /// stop the run with an error
+    #[default]
+    Error,
+    /// leave the record out with a warning and continue
+    Skip,
+}
+
+/// What is wrong with a record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Malformation {
+    /// The header has no name before the first whitespace.
+    EmptyName,
+    /// A byte that is not a letter, at a 0-based offset into the sequence.
+    StrayByte { byte: u8, offset: u64 },
+}
+
+impl fmt::Display for Malformation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Malformation::EmptyName => write!(f, "the header has no name"),
+            Malformation::StrayByte { byte, offset }
+                if byte.is_ascii_graphic() || *byte == b' ' =>
+            {
+                write!(
+                    f,
+                    "stray character '{}' at base {}",
+                    *byte as char,
+                    offset + 1
+                )
+            }
+            Malformation::StrayByte { byte, offset } => {
+                write!(f, "stray byte 0x{:02X} at base {}", byte, offset + 1)
+            }
+        }
+    }
+}
+
+/// A malformed record, as reported in warnings and the manifest.
+///
+/// # Fields
+///
+/// * `index`: The 1-based position of the record in the input.
+/// * `name`: The record's name, empty if the header has none.
+/// * `problem`: What is wrong with it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MalformedRecord {
+    pub index: u64,
+    pub name: String,
+    pub problem: Malformation,
+}
+
+impl MalformedRecord {
+    /// A label for the record that works even when it has no name.
+    pub fn label(&self) -> String {
+        if self.name.is_empty() {
+            format!("record {}", self.index)
+        } else {
+            format!("record {} ({})", self.index, self.name)
+        }
+    }
+}
+
+impl fmt::Display for MalformedRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "malformed FASTA {}: {}", self.label(), self.problem)
+    }
+}
+
+impl From<MalformedRecord> for io::Error {
+    fn from(record: MalformedRecord) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, record.to_string())
+    }
+}
+
+/// Checks a record's name and sequence.
+///
+/// Sequences may hold any letters, upper or lower case: IUPAC codes and soft-masking are handled
+/// downstream. Anything else, including spaces inside a line, is reported at its first occurrence.
+///
+/// # Arguments
+///
+/// * `index` - The 1-based position of the record in the input.
+/// * `name` - The record's name, the header up to the first whitespace.
+/// * `sequence` - The bases, with line breaks already removed.
+///
+/// # Errors
+///
+/// Returns the first problem found.
+pub fn check_record(index: u64, name: &str, sequence: &[u8]) -> Result<(), MalformedRecord> {
+    let malformed = |problem| MalformedRecord {
+        index,
+        name: name.to_string(),
+        problem,
+    };
+    if name.is_empty() {
+        return Err(malformed(Malformation::EmptyName));
+    }
+    match sequence.iter().position(|b| !b.is_ascii_alphabetic()) {
+        Some(offset) => Err(malformed(Malformation::StrayByte {
+            byte: sequence[offset],
+            offset: offset as u64,
+        })),
+        None => Ok(()),
+    }
+}
+
+/// Checks the bases of a record as they are read, for pipelines that stream records rather than
+/// read them whole and so cannot leave one out.
+///
+/// # Arguments
+///
+/// * `index` - The 1-based position of the record in the input.
+/// * `name` - The record's name, which [`check_record`] is expected to have checked already.
+/// * `bases` - The bases, with line breaks already removed.
+///
+/// # Returns
+///
+/// The bases, ending with an `io::ErrorKind::InvalidData` error at the first problem found.
+pub fn checked_bases<'a, I>(
+    index: u64,
+    name: &'a str,
+    bases: I,
+) -> impl Iterator<Item = io::Result<u8>> + 'a
+where
+    I: Iterator<Item = io::Result<u8>> + 'a,
+{
+    (0u64..).zip(bases).map(move |(offset, base)| {
+        let base = base?;
+        if !base.is_ascii_alphabetic() {
+            return Err(MalformedRecord {
+                index,
+                name: name.to_string(),
+                problem: Malformation::StrayByte { byte: base, offset },
+            }
+            .into());
+        }
+        Ok(base)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_record() {
+        assert!(check_record(1, "chr1", b"ACGTNacgtnRYKM").is_ok());
+        let err = check_record(3, "chr3", b"ACGT#ACGT").unwrap_err();
+        assert_eq!(
+            err.problem,
+            Malformation::StrayByte {
+                byte: b'#',
+                offset: 4
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "malformed FASTA record 3 (chr3): stray character '#' at base 5"
+        );
+        let err = check_record(4, "chr4", b"AC\x00GT").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "malformed FASTA record 4 (chr4): stray byte 0x00 at base 3"
+        );
+        let err = check_record(7, "", b"ACGT").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "malformed FASTA record 7: the header has no name"
+        );
+    }
+
+    #[test]
+    fn test_checked_bases() {
+        let bases = b"ACGTNacgt".iter().map(|&b| Ok(b));
+        let read: io::Result<Vec<u8>> = checked_bases(1, "chr1", bases).collect();
+        assert_eq!(read.unwrap(), b"ACGTNacgt");
+        let bases = b"ACG*T".iter().map(|&b| Ok(b));
+        let err = checked_bases(2, "chr2", bases)
+            .collect::<io::Result<Vec<u8>>>()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "malformed FASTA record 2 (chr2): stray character '*' at base 4"
+        );
+    }
+}
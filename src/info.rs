@@ -0,0 +1,348 @@
+//! # Info module
+//! The `symcurve info` subcommand, which describes how an existing output was generated: the
+//! version, parameters and matrices recorded in it, and which contigs it covers.
+//!
+//! Run manifests are recognized by parsing as JSON and bigWig files by their magic number, with
+//! the provenance in their extension header; anything else is read as a text track (bedGraph or
+//! per-position TSV) with the provenance in its `#` comment lines.
+
+use crate::checksum::digest_file;
+use crate::json::Json;
+use crate::manifest::Manifest;
+use crate::provenance::{Provenance, RecordedProvenance};
+use crate::view::bigwig::{is_bigwig, BigWigReader};
+use std::fs;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+/// The span and number of bases with values on one contig of an output.
+///
+/// # Fields
+///
+/// * `name`: The contig name.
+/// * `start`: The 0-based start of the first value.
+/// * `end`: The 0-based, exclusive end of the last value.
+/// * `bases`: The number of bases with a value (missing values don't count).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ContigCoverage {
+    pub name: String,
+    pub start: u64,
+    pub end: u64,
+    pub bases: u64,
+}
+
+/// Adds a run of bases to the coverage of the contig it is on, keeping contigs in the order
+/// they are first seen.
+fn add_coverage(
+    contigs: &mut Vec<ContigCoverage>,
+    name: &str,
+    start: u64,
+    end: u64,
+    has_value: bool,
+) {
+    let contig = match contigs.iter_mut().rposition(|contig| contig.name == name) {
+        Some(i) => &mut contigs[i],
+        None => {
+            contigs.push(ContigCoverage {
+                name: name.to_string(),
+                start,
+                end,
+                bases: 0,
+            });
+            contigs.last_mut().unwrap()
+        }
+    };
+    contig.start = contig.start.min(start);
+    contig.end = contig.end.max(end);
+    if has_value {
+        contig.bases += end - start;
+    }
+}
+
+/// Reads the header comments and coverage of a text track in one streaming pass.
+///
+/// Rows with an integer third column are taken as bedGraph (`chrom start end value`, 0-based,
+/// half-open); anything else as one position per row (`chrom pos value...`, 1-based). Values that
+/// don't parse as a number, or are `NaN`, count as missing.
+///
+/// # Returns
+///
+/// The comment, `track` and `browser` lines before the first row, and the contig coverage.
+fn read_text_track<R: BufRead>(reader: R) -> io::Result<(Vec<String>, Vec<ContigCoverage>)> {
+    let mut header = Vec::new();
+    let mut in_header = true;
+    let mut contigs = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        in_header = in_header
+            && (line.starts_with('#') || line.starts_with("track") || line.starts_with("browser"));
+        if in_header {
+            header.push(line);
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 3 || line.starts_with('#') || line.starts_with("track") {
+            continue;
+        }
+        let Ok(first) = fields[1].parse::<u64>() else {
+            // a column header line
+            continue;
+        };
+        let (start, end, value) = match (fields[2].parse::<u64>(), fields.get(3)) {
+            (Ok(end), Some(value)) => (first, end, *value),
+            _ => (first.saturating_sub(1), first, fields[2]),
+        };
+        let has_value = value.parse::<f64>().is_ok_and(|v| !v.is_nan());
+        add_coverage(&mut contigs, fields[0], start, end, has_value);
+    }
+    Ok((header, contigs))
+}
+
+/// Writes the recorded provenance, flagging a parameter hash that doesn't match.
+fn write_provenance<W: Write>(
+    writer: &mut W,
+    recorded: Option<&RecordedProvenance>,
+) -> io::Result<()> {
+    let Some(recorded) = recorded else {
+        return writeln!(writer, "provenance: not recorded");
+    };
+    let provenance = &recorded.provenance;
+    writeln!(writer, "symcurve version: {}", provenance.version)?;
+    let status = if recorded.hash_matches() {
+        "matches parameters"
+    } else {
+        "DOES NOT MATCH parameters"
+    };
+    writeln!(
+        writer,
+        "parameter hash: {} ({})",
+        recorded.parameter_hash.as_deref().unwrap_or("none"),
+        status
+    )?;
+    writeln!(writer, "matrices: {}", provenance.matrices)?;
+    writeln!(writer, "parameters:")?;
+    for (name, value) in &provenance.parameters {
+        writeln!(writer, "  {}={}", name, value)?;
+    }
+    Ok(())
+}
+
+/// Writes the contig coverage table.
+fn write_coverage<W: Write>(writer: &mut W, contigs: &[ContigCoverage]) -> io::Result<()> {
+    writeln!(writer, "contigs: {}", contigs.len())?;
+    for contig in contigs {
+        writeln!(
+            writer,
+            "  {}\t{}-{}\t{} bp with values",
+            contig.name,
+            contig.start + 1,
+            contig.end,
+            contig.bases
+        )?;
+    }
+    Ok(())
+}
+
+/// Describes a manifest, checking the outputs it lists against their recorded checksums.
+fn manifest_info<W: Write>(writer: &mut W, json: &Json) -> io::Result<()> {
+    let manifest = Manifest::from_json(json)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a symcurve manifest"))?;
+    writeln!(writer, "format: manifest")?;
+    let recorded = json.get("provenance").and_then(Provenance::from_json);
+    if recorded.is_none() {
+        writeln!(writer, "symcurve version: {}", manifest.version)?;
+    }
+    write_provenance(writer, recorded.as_ref())?;
+    if let Some(build) = &manifest.build {
+        writeln!(
+            writer,
+            "build: commit {}, built {}",
+            build.commit, build.date
+        )?;
+    }
+    writeln!(writer, "outputs: {}", manifest.outputs.len())?;
+    for output in &manifest.outputs {
+        let status = match digest_file(&output.path) {
+            Ok(digests) if digests == output.digests => "verified",
+            Ok(_) => "CHANGED since the run",
+            Err(_) => "missing",
+        };
+        writeln!(
+            writer,
+            "  {}\t{} bytes\tsha256:{}\t{}",
+            output.path.display(),
+            output.digests.bytes,
+            output.digests.sha256,
+            status
+        )?;
+    }
+    Ok(())
+}
+
+/// Describes a bigWig file: its contigs and how much of each has values.
+fn bigwig_info<W: Write>(writer: &mut W, path: &Path) -> io::Result<()> {
+    let mut reader = BigWigReader::open(path)?;
+    writeln!(writer, "format: bigWig")?;
+    let recorded = reader
+        .comments()?
+        .and_then(|comments| Provenance::from_comments(comments.lines(), "#"));
+    write_provenance(writer, recorded.as_ref())?;
+    let mut contigs = Vec::new();
+    for chrom in reader.chroms().to_vec() {
+        let mut coverage = ContigCoverage {
+            name: chrom.name.clone(),
+            start: chrom.length as u64,
+            ..ContigCoverage::default()
+        };
+        for interval in reader.values(&chrom.name, 0, chrom.length as u64)? {
+            coverage.start = coverage.start.min(interval.start);
+            coverage.end = coverage.end.max(interval.end);
+            if !interval.value.is_nan() {
+                coverage.bases += interval.end - interval.start;
+            }
+        }
+        if coverage.end > 0 {
+            contigs.push(coverage);
+        }
+    }
+    write_coverage(writer, &contigs)
+}
+
+/// Prints how an output or manifest was generated.
+///
+/// # Arguments
+///
+/// * `path` - A manifest, bigWig file, or text track written by symcurve.
+/// * `writer` - Where the description is written.
+pub fn info<W: Write>(path: &Path, writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "file: {}", path.display())?;
+    if is_bigwig(path)? {
+        return bigwig_info(writer, path);
+    }
+    // manifests are small, but tracks can be genome-sized, so only JSON is read whole
+    let mut start = [0u8; 1];
+    let is_json = File::open(path)?.read(&mut start)? == 1 && start[0] == b'{';
+    if is_json {
+        let json = fs::read_to_string(path)?
+            .parse::<Json>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        return manifest_info(writer, &json);
+    }
+    writeln!(writer, "format: text")?;
+    let (header, contigs) = read_text_track(BufReader::new(File::open(path)?))?;
+    let recorded = Provenance::from_comments(header.iter().map(String::as_str), "#");
+    write_provenance(writer, recorded.as_ref())?;
+    write_coverage(writer, &contigs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provenance::Provenance;
+
+    fn provenance() -> Provenance {
+        Provenance {
+            version: "0.1.0".to_string(),
+            parameters: vec![("curve_step".to_string(), "15".to_string())],
+            matrices: "builtin".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_text_coverage() {
+        let bedgraph = "track type=bedGraph\nchr1\t0\t10\t1.5\nchr1\t10\t20\tnan\nchr2\t5\t8\t2\n";
+        let (header, contigs) = read_text_track(bedgraph.as_bytes()).unwrap();
+        assert_eq!(header, vec!["track type=bedGraph".to_string()]);
+        assert_eq!(
+            contigs,
+            vec![
+                ContigCoverage {
+                    name: "chr1".to_string(),
+                    start: 0,
+                    end: 20,
+                    bases: 10
+                },
+                ContigCoverage {
+                    name: "chr2".to_string(),
+                    start: 5,
+                    end: 8,
+                    bases: 3
+                },
+            ]
+        );
+        let tsv = "chrom\tpos\tdx\tdy\nchr1\t2\t0.5\t-1\nchr1\t3\tNA\t1\n";
+        assert_eq!(
+            read_text_track(tsv.as_bytes()).unwrap().1,
+            vec![ContigCoverage {
+                name: "chr1".to_string(),
+                start: 1,
+                end: 3,
+                bases: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn test_info_text() {
+        let path = std::env::temp_dir().join("symcurve_test_info.bedGraph");
+        let mut contents = Vec::new();
+        provenance().write_comments(&mut contents, "#").unwrap();
+        contents.extend(b"chr1\t0\t10\t1.5\n");
+        fs::write(&path, contents).unwrap();
+        let mut out = Vec::new();
+        info(&path, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("format: text\nsymcurve version: 0.1.0\n"));
+        assert!(text.contains("(matches parameters)"));
+        assert!(text.contains("parameters:\n  curve_step=15\n"));
+        assert!(text.ends_with("contigs: 1\n  chr1\t1-10\t10 bp with values\n"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_info_bigwig() {
+        use crate::output::bigwig::BigWigWriter;
+        let path = std::env::temp_dir().join("symcurve_test_info.bw");
+        let mut writer = BigWigWriter::new(File::create(&path).unwrap())
+            .unwrap()
+            .with_provenance(&provenance());
+        writer.start_chrom("chr1").unwrap();
+        writer.push_missing(2).unwrap();
+        writer.push(1.5).unwrap();
+        writer.finish().unwrap();
+        let mut out = Vec::new();
+        info(&path, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("format: bigWig\nsymcurve version: 0.1.0\n"));
+        assert!(text.contains("(matches parameters)"));
+        assert!(text.ends_with("contigs: 1\n  chr1\t3-3\t1 bp with values\n"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_info_manifest() {
+        let dir = std::env::temp_dir().join("symcurve_test_info_manifest");
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.bedGraph");
+        fs::write(&output, "chr1\t0\t10\t1.5\n").unwrap();
+        let mut manifest = Manifest::new();
+        manifest.provenance = Some(provenance());
+        manifest.add_output(&output).unwrap();
+        let path = dir.join("out.bedGraph.manifest.json");
+        manifest.write(&path).unwrap();
+        let describe = || {
+            let mut out = Vec::new();
+            info(&path, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let text = describe();
+        assert!(text.contains("format: manifest\n"));
+        assert!(text.contains("(matches parameters)"));
+        assert!(text.contains("outputs: 1\n"));
+        assert!(text.ends_with("\tverified\n"));
+        fs::write(&output, "chr1\t0\t10\t2.5\n").unwrap();
+        assert!(describe().ends_with("\tCHANGED since the run\n"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
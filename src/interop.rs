@@ -0,0 +1,136 @@
+//! # Interop module
+//! Adapters for feeding sequences that are already in memory into the curvature pipeline, without
+//! copying their bytes or writing them out as FASTA first.
+//!
+//! Sequences are borrowed as [`TextSlice`]s, the `&[u8]` alias rust-bio uses, so a
+//! `bio::io::fasta::Record` plugs in through its `id()` and `seq()` accessors:
+//!
+//! ```
+//! use symcurve::interop::run_records;
+//!
+//! // with rust-bio: records.iter().map(|r| (r.id(), r.seq()))
+//! let records = [("seq1", &b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC"[..])];
+//! let mut out = Vec::new();
+//! let summary = run_records(records, &mut out, &Default::default()).unwrap();
+//! assert_eq!(summary.values, 8);
+//! ```
+
+use crate::pipeline::{write_record, PipelineParams, PipelineSummary};
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+/// A borrowed sequence of bases, as in rust-bio.
+pub type TextSlice<'a> = &'a [u8];
+
+/// A named sequence held in memory.
+pub trait SequenceRecord {
+    /// The name written in the first column of the output.
+    fn name(&self) -> Cow<'_, str>;
+
+    /// The bases of the sequence.
+    fn sequence(&self) -> TextSlice<'_>;
+}
+
+impl<N: AsRef<str>, S: AsRef<[u8]>> SequenceRecord for (N, S) {
+    fn name(&self) -> Cow<'_, str> {
+        Cow::Borrowed(self.0.as_ref())
+    }
+
+    fn sequence(&self) -> TextSlice<'_> {
+        self.1.as_ref()
+    }
+}
+
+impl SequenceRecord for noodles_fasta::Record {
+    fn name(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.definition().name())
+    }
+
+    fn sequence(&self) -> TextSlice<'_> {
+        self.sequence().as_ref()
+    }
+}
+
+impl<T: SequenceRecord + ?Sized> SequenceRecord for &T {
+    fn name(&self) -> Cow<'_, str> {
+        (**self).name()
+    }
+
+    fn sequence(&self) -> TextSlice<'_> {
+        (**self).sequence()
+    }
+}
+
+/// Adapts a borrowed sequence to the base iterator the pipeline reads, without copying it.
+pub fn bases(seq: TextSlice<'_>) -> impl Iterator<Item = io::Result<u8>> + '_ {
+    seq.iter().copied().map(Ok)
+}
+
+/// Runs the curvature pipeline over sequences held in memory.
+///
+/// The output is the same as [`run_pipeline`](crate::run_pipeline) would write for the same
+/// records read from FASTA.
+///
+/// # Arguments
+///
+/// * `records` - The sequences, in output order.
+/// * `writer` - Where the rows are written.
+/// * `params` - The parameters of the run.
+///
+/// # Errors
+///
+/// Returns any error from writing.
+pub fn run_records<I, W>(
+    records: I,
+    mut writer: W,
+    params: &PipelineParams,
+) -> io::Result<PipelineSummary>
+where
+    I: IntoIterator,
+    I::Item: SequenceRecord,
+    W: Write,
+{
+    let mut summary = PipelineSummary::default();
+    for record in records {
+        let name = record.name();
+        write_record(
+            &mut writer,
+            &name,
+            bases(record.sequence()),
+            params,
+            &mut summary,
+        )?;
+    }
+    writer.flush()?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_pipeline;
+
+    const SEQ: &[u8] = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+
+    #[test]
+    fn test_run_records_matches_fasta() {
+        let params = PipelineParams::default();
+        let fasta = [&b">chr1\n"[..], SEQ, b"NN\n>chr2\nACGT\n"].concat();
+        let mut expected = Vec::new();
+        run_pipeline(&fasta[..], &mut expected, &params).unwrap();
+
+        let with_gap = [SEQ, b"NN"].concat();
+        let records = vec![("chr1", with_gap.as_slice()), ("chr2", &b"ACGT"[..])];
+        let mut out = Vec::new();
+        let summary = run_records(&records, &mut out, &params).unwrap();
+        assert_eq!(out, expected);
+        assert_eq!(summary.records, 2);
+        assert_eq!(summary.bases, 56);
+
+        let mut reader = noodles_fasta::Reader::new(&fasta[..]);
+        let noodles_records: Vec<_> = reader.records().map(|r| r.unwrap()).collect();
+        let mut out = Vec::new();
+        run_records(&noodles_records, &mut out, &params).unwrap();
+        assert_eq!(out, expected);
+    }
+}
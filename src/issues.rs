@@ -0,0 +1,485 @@
+//! # Issues module
+//! Warnings and errors as structured records, for pipelines that triage problem regions
+//! programmatically instead of parsing log lines.
+//!
+//! Each issue is reported twice: as a human-readable line on stderr, and, with `--issues`, as one
+//! JSON object per line in `OUTPUT.issues.jsonl`. Every record has the same keys (`severity`,
+//! `kind`, `contig`, `start`, `end`, `count` and `message`), with `null` where an issue has no
+//! contig or coordinates, so the file loads straight into a data frame. Coordinates are 0-based
+//! and half-open as in BED, and the human line shows them 1-based as regions are written on the
+//! command line.
+//!
+//! A badly assembled genome can raise the same warning at millions of positions, so with
+//! `--warning-limit` only the first few warnings of each kind in each contig are shown on stderr,
+//! and the rest are summarized in one line per kind and contig when the log is finished. The JSON
+//! lines always have every issue. Errors are always shown.
+
+use crate::fasta::validate::{Malformation, MalformedRecord};
+use crate::json::Json;
+use crate::pipeline::PipelineSummary;
+use crate::warnings::WarningKind;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// How serious an issue is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The run carried on.
+    Warning,
+    /// The run stopped.
+    Error,
+}
+
+impl Severity {
+    /// The name used in both forms of the report.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// A warning or error, with where it happened.
+///
+/// # Fields
+///
+/// * `severity`: Whether the run carried on.
+/// * `kind`: A stable snake_case category, e.g. `ambiguous_bases`.
+/// * `contig`: The sequence it happened in, if any.
+/// * `span`: The 0-based, half-open coordinates in the sequence, if known.
+/// * `count`: How many occurrences the issue stands for.
+/// * `message`: What happened.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Issue {
+    pub severity: Severity,
+    pub kind: String,
+    pub contig: Option<String>,
+    pub span: Option<Range<u64>>,
+    pub count: u64,
+    pub message: String,
+}
+
+impl Issue {
+    /// An issue with no location.
+    pub fn new(severity: Severity, kind: &str, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            kind: kind.to_string(),
+            contig: None,
+            span: None,
+            count: 1,
+            message: message.into(),
+        }
+    }
+
+    /// A warning of one of the counted kinds, in a sequence.
+    pub fn warning(kind: WarningKind, count: u64, contig: &str) -> Self {
+        Self {
+            count,
+            message: format!("{} {}", count, kind.description()),
+            ..Self::new(Severity::Warning, kind.code(), "").in_contig(contig)
+        }
+    }
+
+    /// A warning for a malformed record that was skipped, at the stray byte if that is the
+    /// problem.
+    pub fn malformed(record: &MalformedRecord) -> Self {
+        let mut issue = Self::new(
+            Severity::Warning,
+            WarningKind::MalformedRecords.code(),
+            format!("{} skipped: {}", record.label(), record.problem),
+        );
+        if !record.name.is_empty() {
+            issue = issue.in_contig(&record.name);
+        }
+        if let Malformation::StrayByte { offset, .. } = record.problem {
+            issue.span = Some(offset..offset + 1);
+        }
+        issue
+    }
+
+    /// Sets the contig.
+    pub fn in_contig(mut self, contig: &str) -> Self {
+        self.contig = Some(contig.to_string());
+        self
+    }
+
+    /// Sets the coordinates.
+    pub fn at(mut self, span: Range<u64>) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    /// The issue as one JSON object.
+    pub fn to_json(&self) -> Json {
+        let optional = |value: Option<u64>| value.map_or(Json::Null, Json::from);
+        Json::object()
+            .with("severity", self.severity.name())
+            .with("kind", self.kind.as_str())
+            .with(
+                "contig",
+                self.contig.as_deref().map_or(Json::Null, Json::from),
+            )
+            .with("start", optional(self.span.as_ref().map(|s| s.start)))
+            .with("end", optional(self.span.as_ref().map(|s| s.end)))
+            .with("count", self.count)
+            .with("message", self.message.as_str())
+    }
+}
+
+impl fmt::Display for Issue {
+    /// The human-readable line, e.g. `warning: chr1:1001-1200: 200 ambiguous bases`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.severity.name())?;
+        match (&self.contig, &self.span) {
+            (Some(contig), Some(span)) => {
+                write!(f, "{}:{}-{}: ", contig, span.start + 1, span.end)?
+            }
+            (Some(contig), None) => write!(f, "{}: ", contig)?,
+            _ => {}
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Reports issues to stderr and, optionally, as JSON lines.
+///
+/// The default log writes no JSON lines and shows every warning.
+///
+/// # Fields
+///
+/// * `jsonl`: Where the JSON lines go, if anywhere.
+/// * `reported`: The number of issues reported so far.
+/// * `limit`: The most warnings of one kind in one contig shown on stderr, or `None` for all.
+/// * `shown`: For each kind and contig, the warnings reported so far, and the number of those
+///   not shown with the occurrences they stand for.
+pub struct IssueLog<W: Write> {
+    jsonl: Option<W>,
+    reported: u64,
+    limit: Option<u64>,
+    shown: BTreeMap<(String, String), (u64, u64, u64)>,
+}
+
+impl<W: Write> Default for IssueLog<W> {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl<W: Write> IssueLog<W> {
+    /// Constructor for `IssueLog`.
+    pub fn new(jsonl: Option<W>) -> Self {
+        Self {
+            jsonl,
+            reported: 0,
+            limit: None,
+            shown: BTreeMap::new(),
+        }
+    }
+
+    /// Shows at most `limit` warnings of each kind in each contig on stderr, or all of them if
+    /// `limit` is `None`.
+    pub fn with_limit(mut self, limit: Option<u64>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Reports an issue: the human-readable line to `human`, unless the limit of its kind and
+    /// contig has been reached, and the JSON line if enabled.
+    pub fn report_to<H: Write>(&mut self, issue: &Issue, human: &mut H) -> io::Result<()> {
+        let suppressed = match (self.limit, issue.severity) {
+            (Some(limit), Severity::Warning) => {
+                let key = (issue.kind.clone(), issue.contig.clone().unwrap_or_default());
+                let (seen, hidden, occurrences) = self.shown.entry(key).or_default();
+                *seen += 1;
+                if *seen > limit {
+                    *hidden += 1;
+                    *occurrences += issue.count;
+                }
+                *seen > limit
+            }
+            _ => false,
+        };
+        if !suppressed {
+            writeln!(human, "{}", issue)?;
+        }
+        if let Some(jsonl) = &mut self.jsonl {
+            writeln!(jsonl, "{}", issue.to_json())?;
+        }
+        self.reported += 1;
+        Ok(())
+    }
+
+    /// Reports an issue, with the human-readable line on stderr.
+    pub fn report(&mut self, issue: &Issue) -> io::Result<()> {
+        self.report_to(issue, &mut io::stderr().lock())
+    }
+
+    /// The number of issues reported.
+    pub fn reported(&self) -> u64 {
+        self.reported
+    }
+
+    /// Writes one line to `human` for each kind and contig with warnings that were not shown,
+    /// e.g. `warning: chr1: 4990 more ambiguous_bases warnings not shown (61020 occurrences)`.
+    /// Each is summarized once.
+    pub fn summarize_to<H: Write>(&mut self, human: &mut H) -> io::Result<()> {
+        for ((kind, contig), (_, hidden, occurrences)) in &mut self.shown {
+            if *hidden == 0 {
+                continue;
+            }
+            let mut issue = Issue::new(
+                Severity::Warning,
+                kind,
+                format!(
+                    "{} more {} warnings not shown ({} occurrences)",
+                    hidden, kind, occurrences
+                ),
+            );
+            if !contig.is_empty() {
+                issue = issue.in_contig(contig);
+            }
+            writeln!(human, "{}", issue)?;
+            *hidden = 0;
+            *occurrences = 0;
+        }
+        Ok(())
+    }
+
+    /// Summarizes the warnings not shown on stderr, then flushes and gives back the JSON lines
+    /// writer.
+    pub fn finish(mut self) -> io::Result<Option<W>> {
+        self.summarize_to(&mut io::stderr().lock())?;
+        if let Some(jsonl) = &mut self.jsonl {
+            jsonl.flush()?;
+        }
+        Ok(self.jsonl)
+    }
+}
+
+/// Collects the runs of ambiguity codes in a sequence as it streams past, as [`ambiguous_runs`]
+/// finds them in a whole one.
+///
+/// # Fields
+///
+/// * `position`: The 0-based position of the next base.
+/// * `runs`: The runs so far.
+#[derive(Debug, Default)]
+pub struct AmbiguityTracker {
+    position: u64,
+    runs: Vec<Range<u64>>,
+}
+
+impl AmbiguityTracker {
+    /// Constructor for `AmbiguityTracker`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Notes the next base of the sequence.
+    pub fn push(&mut self, base: u8) {
+        let pos = self.position;
+        self.position += 1;
+        if matches!(base.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T' | b'N') {
+            return;
+        }
+        match self.runs.last_mut() {
+            Some(run) if run.end == pos => run.end += 1,
+            _ => self.runs.push(pos..pos + 1),
+        }
+    }
+
+    /// Ends the sequence and returns its runs, in order. The tracker is left ready for the next
+    /// sequence.
+    pub fn finish(&mut self) -> Vec<Range<u64>> {
+        self.position = 0;
+        std::mem::take(&mut self.runs)
+    }
+}
+
+/// The runs of ambiguity codes in a sequence: bases other than `A`, `C`, `G`, `T` and `N`, in
+/// either case, as 0-based half-open ranges.
+pub fn ambiguous_runs(seq: &[u8]) -> Vec<Range<u64>> {
+    let mut tracker = AmbiguityTracker::new();
+    for &base in seq {
+        tracker.push(base);
+    }
+    tracker.finish()
+}
+
+/// The issues of one sequence: a warning at each run of ambiguity codes, and one for the
+/// sequence if it is too short for a value.
+///
+/// # Arguments
+///
+/// * `chrom` - The name of the sequence.
+/// * `ambiguous` - The runs of ambiguity codes in it, as [`ambiguous_runs`] gives them.
+/// * `summary` - What the pipeline processed of the sequence alone.
+pub fn sequence_issues(
+    chrom: &str,
+    ambiguous: Vec<Range<u64>>,
+    summary: &PipelineSummary,
+) -> Vec<Issue> {
+    let mut issues: Vec<Issue> = ambiguous
+        .into_iter()
+        .map(|run| Issue::warning(WarningKind::AmbiguousBases, run.end - run.start, chrom).at(run))
+        .collect();
+    if summary.short_records > 0 {
+        issues.push(Issue::warning(
+            WarningKind::ShortContigs,
+            summary.short_records,
+            chrom,
+        ));
+    }
+    issues
+}
+
+/// Derives the path of the issues file from the main output path, so that `out.bw` becomes
+/// `out.issues.jsonl`.
+pub fn issues_path(output: &Path) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    output.with_file_name(format!("{}.issues.jsonl", stem))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_issue_forms() {
+        let issue = Issue::warning(WarningKind::AmbiguousBases, 200, "chr1").at(1000..1200);
+        assert_eq!(
+            issue.to_string(),
+            "warning: chr1:1001-1200: 200 ambiguous bases (treated as gaps)"
+        );
+        assert_eq!(
+            issue.to_json().to_string(),
+            "{\"severity\":\"warning\",\"kind\":\"ambiguous_bases\",\"contig\":\"chr1\",\
+             \"start\":1000,\"end\":1200,\"count\":200,\
+             \"message\":\"200 ambiguous bases (treated as gaps)\"}"
+        );
+        let issue = Issue::new(Severity::Error, "io", "disk full");
+        assert_eq!(issue.to_string(), "error: disk full");
+        let json = issue.to_json();
+        assert_eq!(json.get("contig"), Some(&Json::Null));
+        assert_eq!(json.get("start"), Some(&Json::Null));
+    }
+
+    #[test]
+    fn test_malformed_issue() {
+        let issue = Issue::malformed(&MalformedRecord {
+            index: 3,
+            name: "chr3".to_string(),
+            problem: Malformation::StrayByte {
+                byte: b'#',
+                offset: 4,
+            },
+        });
+        assert_eq!(issue.kind, "malformed_records");
+        assert_eq!(issue.span, Some(4..5));
+        assert_eq!(
+            issue.to_string(),
+            "warning: chr3:5-5: record 3 (chr3) skipped: stray character '#' at base 5"
+        );
+    }
+
+    #[test]
+    fn test_issue_log() {
+        let mut log = IssueLog::new(Some(Vec::new()));
+        let mut human = Vec::new();
+        log.report_to(
+            &Issue::warning(WarningKind::ShortContigs, 1, "chrUn_1"),
+            &mut human,
+        )
+        .unwrap();
+        log.report_to(&Issue::new(Severity::Error, "io", "disk full"), &mut human)
+            .unwrap();
+        assert_eq!(log.reported(), 2);
+        assert_eq!(
+            String::from_utf8(human).unwrap(),
+            "warning: chrUn_1: 1 sequences too short for a value\nerror: disk full\n"
+        );
+        let jsonl = String::from_utf8(log.finish().unwrap().unwrap()).unwrap();
+        let records: Vec<Json> = jsonl.lines().map(|l| l.parse().unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[1].get("severity").and_then(Json::as_str),
+            Some("error")
+        );
+    }
+
+    #[test]
+    fn test_issue_log_limit() {
+        let mut log = IssueLog::new(Some(Vec::new())).with_limit(Some(2));
+        let mut human = Vec::new();
+        for (contig, count) in [
+            ("chr1", 5),
+            ("chr2", 1),
+            ("chr1", 1),
+            ("chr1", 3),
+            ("chr1", 4),
+        ] {
+            let issue = Issue::warning(WarningKind::AmbiguousBases, count, contig);
+            log.report_to(&issue, &mut human).unwrap();
+        }
+        log.report_to(&Issue::new(Severity::Error, "io", "disk full"), &mut human)
+            .unwrap();
+        log.summarize_to(&mut human).unwrap();
+        log.summarize_to(&mut human).unwrap();
+        assert_eq!(
+            String::from_utf8(human).unwrap(),
+            "warning: chr1: 5 ambiguous bases (treated as gaps)\n\
+             warning: chr2: 1 ambiguous bases (treated as gaps)\n\
+             warning: chr1: 1 ambiguous bases (treated as gaps)\n\
+             error: disk full\n\
+             warning: chr1: 2 more ambiguous_bases warnings not shown (7 occurrences)\n"
+        );
+        assert_eq!(log.reported(), 6);
+        let jsonl = String::from_utf8(log.finish().unwrap().unwrap()).unwrap();
+        assert_eq!(jsonl.lines().count(), 6);
+    }
+
+    #[test]
+    fn test_ambiguous_runs() {
+        assert_eq!(ambiguous_runs(b"ACRYGTNNkACGTW"), vec![2..4, 8..9, 13..14]);
+        assert!(ambiguous_runs(b"ACGTNacgtn").is_empty());
+    }
+
+    #[test]
+    fn test_sequence_issues() {
+        let mut tracker = AmbiguityTracker::new();
+        for &base in b"ACRYGT" {
+            tracker.push(base);
+        }
+        let summary = PipelineSummary {
+            records: 1,
+            bases: 6,
+            ambiguous_bases: 2,
+            short_records: 1,
+            ..PipelineSummary::default()
+        };
+        let issues = sequence_issues("chr1", tracker.finish(), &summary);
+        let lines: Vec<String> = issues.iter().map(Issue::to_string).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "warning: chr1:3-4: 2 ambiguous bases (treated as gaps)",
+                "warning: chr1: 1 sequences too short for a value",
+            ]
+        );
+        tracker.push(b'W');
+        assert_eq!(tracker.finish(), vec![0..1]);
+    }
+
+    #[test]
+    fn test_issues_path() {
+        assert_eq!(
+            issues_path(Path::new("dir/out.bw")),
+            PathBuf::from("dir/out.issues.jsonl")
+        );
+    }
+}
@@ -1,3 +1,80 @@
+//! Symmetry of DNA curvature, as a library.
+//!
+//! The `symcurve` binary is a thin layer over this crate, so downstream Rust tools can compute
+//! curvature without shelling out to it. The pieces most callers need are re-exported here: the
+//! [`CurveIter`] stack with its [`RollType`] and matrix types, the symmetry scores over a
+//! curvature track, FASTA reading, the in-memory [`compute_track`] and the whole-file
+//! [`run_pipeline`].
+//!
+//! ```
+//! use symcurve::{CurveIter, EdgeMode, MeanConvention, RollType, StreamingReader};
+//!
+//! let fasta = b">seq1\nCCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC\n";
+//! let mut reader = StreamingReader::new(&fasta[..]);
+//! let name = reader.next_record().unwrap().unwrap();
+//! let bases: Vec<u8> = reader.bases().map(Result::unwrap).collect();
+//! let curve: Vec<f64> = CurveIter::new(
+//!     bases.into_iter(),
+//!     RollType::Simple,
+//!     5,
+//!     15,
+//!     0.33335,
+//!     EdgeMode::Drop,
+//!     MeanConvention::Legacy,
+//! )
+//! .collect();
+//! assert_eq!((name.as_str(), curve.len()), ("seq1", 8));
+//! ```
+
+pub mod calibrate;
+pub mod checksum;
+pub mod chunk;
 pub mod cli;
+pub mod compare;
+pub mod crossval;
 pub mod curve;
+pub mod dedup;
+pub mod design;
+pub mod explain;
+pub mod expr;
 pub mod fasta;
+pub mod info;
+pub mod interop;
+pub mod issues;
+pub mod json;
+pub mod man;
+pub mod manifest;
+pub mod metagenome;
+pub mod monitor;
+pub mod motif;
+pub mod mutagenesis;
+pub mod notify;
+pub mod numeric;
+pub mod output;
+pub mod pipeline;
+#[cfg(test)]
+pub(crate) mod property;
+pub mod provenance;
+pub mod reads;
+pub mod resources;
+pub mod run;
+pub mod selftest;
+pub mod serve;
+pub mod signal;
+pub mod stats;
+pub mod sweep;
+pub mod text;
+pub mod trace;
+pub mod track;
+pub mod train;
+pub mod view;
+pub mod warnings;
+
+pub use curve::iters::{bend_vectors, CurveIter, EdgeMode, MeanConvention};
+pub use curve::matrix::{
+    matrix_lookup, BuiltinRoll, Matrices, MatrixLookupError, NucMatrix, RollType,
+};
+pub use curve::symmetry::{mirror_score, SymmetryIter, SymmetryIterator, SymmetryScores};
+pub use fasta::{reverse_complement, split_seq_by_n, Bases, RecordPiece, StreamingReader};
+pub use pipeline::{run_pipeline, PipelineParams, PipelineSummary};
+pub use track::{compute_track, CurveTrack};
@@ -1,8 +1,448 @@
-mod cli;
-use cli::Cli;
-use clap::Parser;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Instant;
+use symcurve::calibrate::{self, CalibrateParams};
+use symcurve::cli::{Cli, Command};
+use symcurve::crossval::{self, CrossvalParams};
+use symcurve::curve::symmetry::symcurve_path;
+use symcurve::design::{self, DesignParams};
+use symcurve::fasta::validate::MalformedRecord;
+use symcurve::manifest::Manifest;
+use symcurve::notify::{self, notification};
+use symcurve::output::layout::OutputLayout;
+use symcurve::output::{resolve_output_path, OutputFormat};
+use symcurve::provenance::{BuildInfo, Provenance};
+use symcurve::reads::{self, SummaryParams};
+use symcurve::run::{self, RunOptions, RunReport};
+use symcurve::serve::{self, ServeParams};
+use symcurve::stats::usage;
+use symcurve::train::{self, TrainParams};
+use symcurve::{
+    compare, explain, info, man, metagenome, motif, mutagenesis, selftest, signal, sweep, view,
+};
 
-// still basically a hello-world
-fn main() {
-    Cli::parse();
+fn main() -> ExitCode {
+    let cli = Cli::parse_with_preset();
+    if let Some(command) = &cli.command {
+        let (file, result) = match command {
+            Command::View { file, region } => (
+                file.as_path(),
+                view::view(file, region.as_ref(), &mut io::stdout().lock()),
+            ),
+            Command::Info { file } => (file.as_path(), info::info(file, &mut io::stdout().lock())),
+            Command::Sweep {
+                input,
+                params,
+                region,
+            } => (
+                input.as_path(),
+                cli.pipeline_params().and_then(|curve| {
+                    sweep::sweep(
+                        input,
+                        region.as_ref(),
+                        params,
+                        &curve,
+                        &mut io::stdout().lock(),
+                    )
+                }),
+            ),
+            Command::Compare {
+                target,
+                query,
+                chain,
+            } => (
+                chain.as_path(),
+                cli.pipeline_params().and_then(|curve| {
+                    compare::compare(target, query, chain, &curve, &mut io::stdout().lock())
+                }),
+            ),
+            Command::Explain { input, region } => (
+                input.as_path(),
+                cli.pipeline_params().and_then(|curve| {
+                    explain::explain(input, region, &curve, &mut io::stdout().lock())
+                }),
+            ),
+            Command::ScanMutations { input, region } => (
+                input.as_path(),
+                cli.pipeline_params().and_then(|curve| {
+                    mutagenesis::scan(input, region, &curve, &mut io::stdout().lock())
+                }),
+            ),
+            Command::Design {
+                length,
+                gc,
+                objective,
+                iterations,
+                candidates,
+                seed,
+            } => {
+                let params = DesignParams {
+                    length: *length,
+                    gc: *gc,
+                    objective: *objective,
+                    iterations: *iterations,
+                    candidates: usize::from(*candidates),
+                    seed: *seed,
+                };
+                (
+                    Path::new("design"),
+                    cli.pipeline_params()
+                        .and_then(|curve| design::run(&params, &curve, &mut io::stdout().lock())),
+                )
+            }
+            Command::Motifs {
+                input,
+                threshold,
+                width,
+                format,
+            } => (
+                input.as_path(),
+                cli.pipeline_params().and_then(|curve| {
+                    motif::motifs(
+                        input,
+                        *threshold,
+                        usize::from(*width),
+                        *format,
+                        &curve,
+                        &mut io::stdout().lock(),
+                    )
+                }),
+            ),
+            Command::Metagenome {
+                input,
+                bins,
+                threads,
+            } => (
+                input.as_path(),
+                cli.pipeline_params().and_then(|curve| {
+                    metagenome::metagenome(
+                        input,
+                        bins.as_deref(),
+                        threads.map(usize::from),
+                        &curve,
+                        &mut io::stdout().lock(),
+                    )
+                }),
+            ),
+            Command::Reads {
+                input,
+                symcurve_win,
+                symmetry_threshold,
+                threads,
+            } => (
+                input.as_path(),
+                cli.pipeline_params().and_then(|curve| {
+                    let params = SummaryParams {
+                        curve,
+                        window: usize::from(*symcurve_win),
+                        threshold: *symmetry_threshold,
+                    };
+                    reads::reads(
+                        input,
+                        &params,
+                        threads.map(usize::from),
+                        &mut io::BufWriter::new(io::stdout().lock()),
+                    )
+                }),
+            ),
+            Command::Calibrate {
+                tracks,
+                truth,
+                truth_threshold,
+                region,
+                stride,
+                weight_step,
+                curves,
+                output,
+            } => {
+                let params = CalibrateParams {
+                    tracks: tracks.clone(),
+                    truth: truth.clone(),
+                    truth_threshold: *truth_threshold,
+                    region: region.clone(),
+                    stride: *stride,
+                    weight_step: *weight_step,
+                    curves: curves.clone(),
+                    output: output.clone(),
+                };
+                (
+                    truth.as_path(),
+                    calibrate::calibrate(&params, &mut io::stdout().lock()),
+                )
+            }
+            Command::Train {
+                positive,
+                negative,
+                iterations,
+                step,
+                output,
+            } => {
+                let params = TrainParams {
+                    positive: positive.clone(),
+                    negative: negative.clone(),
+                    iterations: *iterations as usize,
+                    step: *step,
+                    output: output.clone(),
+                };
+                (
+                    positive.as_path(),
+                    train::train(&params, &mut io::stdout().lock()),
+                )
+            }
+            Command::Crossval {
+                input,
+                regions,
+                folds,
+            } => {
+                let params = CrossvalParams {
+                    input: input.clone(),
+                    regions: regions.clone(),
+                    matrices: cli.matrices.clone(),
+                    folds: *folds as usize,
+                };
+                (
+                    input.as_path(),
+                    crossval::crossval(&params, &mut io::stdout().lock()),
+                )
+            }
+            Command::Serve {
+                input,
+                listen,
+                max_region,
+                cache_size,
+                max_connections,
+            } => (
+                input.as_path(),
+                cli.pipeline_params().and_then(|curve| {
+                    serve::serve(&ServeParams {
+                        input: input.clone(),
+                        listen: listen.clone(),
+                        max_region: *max_region,
+                        cache_size: *cache_size,
+                        max_connections: *max_connections,
+                        curve,
+                    })
+                }),
+            ),
+            Command::Man { dir } => (
+                dir.as_deref().unwrap_or(Path::new("man")),
+                man::man(dir.as_deref(), &mut io::stdout().lock()),
+            ),
+        };
+        return match result {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("error: {}: {}", file.display(), e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+    if cli.version {
+        return match BuildInfo::current().write(&mut io::stdout().lock(), cli.verbose) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(_) => ExitCode::FAILURE,
+        };
+    }
+    if cli.self_test {
+        let report = selftest::run_self_test();
+        print!("{}", report);
+        return if report.passed() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+    // clap requires both paths when there is no subcommand and no --self-test
+    let (Some(input), Some(output)) = (&cli.input, &cli.output) else {
+        return ExitCode::FAILURE;
+    };
+    let mut layout = OutputLayout::new(cli.work_dir.clone());
+    let mut options = cli.run_options();
+    let output = resolve_output_path(output, input, cli.output_dir.as_deref());
+    let (output, temp_dir) = match resolve_layout(&layout, &output, &mut options) {
+        Ok(resolved) => resolved,
+        Err(e) => return fail_run(&cli, &e.to_string()),
+    };
+    let format = cli
+        .output_format
+        .unwrap_or_else(|| OutputFormat::from_path(&output));
+    let params = match cli.pipeline_params() {
+        Ok(params) => params,
+        Err(e) => return fail_run(&cli, &e.to_string()),
+    };
+    if let Some(plan) = params.chunking.filter(|_| cli.verbose) {
+        eprintln!("{}", plan);
+    }
+    let symcurve = cli.symcurve_track.then(|| symcurve_path(&output));
+    let started = Instant::now();
+    signal::install_interrupt_handler();
+    let result = run::run(
+        input,
+        &output,
+        symcurve.as_deref(),
+        format,
+        &params,
+        &options,
+        cli.force,
+    );
+    if let Some(dir) = temp_dir {
+        // only removed if the run left nothing in it
+        let _ = fs::remove_dir(dir);
+    }
+    match result {
+        Ok(RunReport {
+            summary,
+            outputs,
+            memory,
+            timings,
+            memo,
+            clip,
+            warnings,
+            skipped,
+            ..
+        }) => {
+            if !warnings.is_empty() {
+                eprint!("{}", warnings);
+            }
+            if cli.mem_stats {
+                eprint!("{}", memory);
+            }
+            if cli.timings {
+                eprint!("{}", timings);
+            }
+            if cli.verbose {
+                eprintln!(
+                    "{}: {} records, {} bases, {} values",
+                    input.display(),
+                    summary.records,
+                    summary.bases,
+                    summary.values
+                );
+                if cli.memoize {
+                    eprintln!(
+                        "{}: {} values computed, {} reused",
+                        input.display(),
+                        memo.computed,
+                        memo.reused
+                    );
+                }
+                if cli.clip.is_some() {
+                    eprint!("{}: {}", input.display(), clip);
+                }
+            }
+            if let Some(emit) = cli.emit_outputs {
+                for path in &outputs {
+                    let kind = options.output_kind(path, &output, symcurve.as_deref());
+                    layout.record(kind, path);
+                }
+                if let Err(e) = layout.emit(&mut io::stdout().lock(), emit) {
+                    return fail_run(&cli, &e.to_string());
+                }
+            }
+            if let Some(path) = &cli.stats_file {
+                // usage statistics are a convenience; failing to record them never fails the run
+                if let Err(e) = usage::record_run(path, format, summary.bases, started.elapsed()) {
+                    eprintln!("warning: {}: {}", path.display(), e);
+                }
+            }
+            send_notification(&cli, &outputs, &skipped, Ok(()));
+            ExitCode::SUCCESS
+        }
+        Err(e) => fail_run(&cli, &format!("{}: {}", input.display(), e)),
+    }
+}
+
+/// Reports a run that failed with `message`, counting it in `--stats-file` and notifying
+/// `--notify-url`, if given. Every failed run ends here, however early it failed.
+fn fail_run(cli: &Cli, message: &str) -> ExitCode {
+    eprintln!("error: {}", message);
+    if let Some(path) = &cli.stats_file {
+        if let Err(e) = usage::record_failed_run(path) {
+            eprintln!("warning: {}: {}", path.display(), e);
+        }
+    }
+    send_notification(cli, &[], &[], Err(message));
+    ExitCode::FAILURE
+}
+
+/// Resolves the output paths of a run inside `--work-dir`, if given, and makes the directories
+/// they and temporary files are kept in.
+///
+/// # Returns
+///
+/// The resolved main output, and the temporary directory if it was made for the run, to be
+/// removed again after it.
+fn resolve_layout(
+    layout: &OutputLayout,
+    output: &Path,
+    options: &mut RunOptions,
+) -> io::Result<(PathBuf, Option<PathBuf>)> {
+    let Some(work_dir) = layout.work_dir() else {
+        return Ok((output.to_path_buf(), None));
+    };
+    let output = if output.as_os_str() == "-" {
+        output.to_path_buf()
+    } else {
+        layout.resolve(output)?
+    };
+    for path in [
+        &mut options.bend_vectors,
+        &mut options.trace,
+        &mut options.magnitude_phase,
+        &mut options.envelope,
+        &mut options.stats_json,
+        &mut options.arrow,
+        &mut options.sqlite,
+        &mut options.masked_bed,
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|path| path.as_os_str() != "-")
+    {
+        *path = layout.resolve(path)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::create_dir_all(work_dir)?;
+    let made = options.spill_dir.is_none();
+    let temp_dir = layout.temp_dir(options.spill_dir.as_deref());
+    if let (true, Some(dir)) = (made, &temp_dir) {
+        fs::create_dir_all(dir)?;
+    }
+    options.spill_dir = temp_dir.clone();
+    Ok((output, temp_dir.filter(|_| made)))
+}
+
+/// Sends the notification of `--notify-url`, if given, with the manifest of the outputs written
+/// and the malformed records left out. Failing to notify is reported as a warning and never fails
+/// the run.
+fn send_notification(
+    cli: &Cli,
+    outputs: &[PathBuf],
+    skipped: &[MalformedRecord],
+    outcome: Result<(), &str>,
+) {
+    let Some(target) = &cli.notify_url else {
+        return;
+    };
+    let mut manifest = Manifest::new();
+    // a matrices file that cannot be read has already failed the run
+    manifest.provenance = Provenance::from_cli(cli).ok();
+    for record in skipped {
+        manifest.add_skipped(record);
+    }
+    let sent = outputs
+        .iter()
+        .filter(|path| path.as_os_str() != "-")
+        .try_for_each(|path| manifest.add_output(path))
+        .and_then(|()| notify::notify(target, &notification(manifest.to_json(), outcome)));
+    if let Err(e) = sent {
+        eprintln!("warning: {}: {}", target, e);
+    }
 }
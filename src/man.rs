@@ -0,0 +1,271 @@
+//! # Man module
+//! Man pages for symcurve and its subcommands, rendered as roff from the command line definition
+//! in [`cli`](crate::cli), so they always list the options of the build they come from.
+//!
+//! `symcurve man` prints the page of the main command; `symcurve man --dir DIR` writes
+//! `symcurve.1` and one `symcurve-<command>.1` page per subcommand into `DIR`, ready to install
+//! under a `man1` directory, e.g. in the tree of an environment module.
+
+use crate::cli::Cli;
+use clap::{Arg, Command, CommandFactory};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Escapes text for roff: backslashes are doubled as `\e`, hyphens are made literal minus
+/// signs, and a line that would start with a control character is guarded.
+fn escape(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    escaped
+        .lines()
+        .map(|line| {
+            if line.starts_with('.') || line.starts_with('\'') {
+                format!("\\&{}", line)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The man page name of a command, e.g. `symcurve-view` for `symcurve view`.
+fn page_name(cmd: &Command) -> String {
+    cmd.get_bin_name()
+        .unwrap_or_else(|| cmd.get_name())
+        .replace(' ', "-")
+}
+
+/// Renders the roff of one option or argument as a tagged paragraph.
+fn render_arg(page: &mut String, arg: &Arg) {
+    let value_names: Vec<String> = arg
+        .get_value_names()
+        .map(|names| names.iter().map(|name| name.to_string()).collect())
+        .unwrap_or_else(|| vec![arg.get_id().to_string().to_uppercase()]);
+    let values = value_names
+        .iter()
+        .map(|name| format!("\\fI{}\\fR", escape(name)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut tag = Vec::new();
+    if let Some(short) = arg.get_short() {
+        tag.push(format!("\\fB\\-{}\\fR", short));
+    }
+    if let Some(long) = arg.get_long() {
+        tag.push(format!("\\fB\\-\\-{}\\fR", escape(long)));
+    }
+    page.push_str(".TP\n");
+    if arg.is_positional() {
+        let (open, close) = if arg.is_required_set() {
+            ("<", ">")
+        } else {
+            ("[", "]")
+        };
+        page.push_str(&format!("{}{}{}\n", open, values, close));
+    } else if arg.get_action().takes_values() {
+        page.push_str(&format!("{} {}\n", tag.join(", "), values));
+    } else {
+        page.push_str(&format!("{}\n", tag.join(", ")));
+    }
+    if let Some(help) = arg.get_long_help().or(arg.get_help()) {
+        page.push_str(&escape(&help.to_string()));
+        page.push('\n');
+    }
+    let defaults: Vec<String> = arg
+        .get_default_values()
+        .iter()
+        .map(|value| value.to_string_lossy().into_owned())
+        .collect();
+    if !defaults.is_empty() && arg.get_action().takes_values() {
+        page.push_str(&format!(
+            ".RS\n[default: {}]\n.RE\n",
+            escape(&defaults.join(", "))
+        ));
+    }
+    let possible: Vec<_> = arg
+        .get_possible_values()
+        .into_iter()
+        .filter(|value| !value.is_hide_set())
+        .collect();
+    if !possible.is_empty() && arg.get_action().takes_values() {
+        page.push_str(".RS\nPossible values:\n.RS\n");
+        for value in possible {
+            page.push_str(&format!(
+                ".IP \\(bu 2\n\\fB{}\\fR",
+                escape(value.get_name())
+            ));
+            if let Some(help) = value.get_help() {
+                page.push_str(&format!(": {}", escape(&help.to_string())));
+            }
+            page.push('\n');
+        }
+        page.push_str(".RE\n.RE\n");
+    }
+}
+
+/// Renders the man page of a command.
+///
+/// # Arguments
+///
+/// * `cmd` - The command, built with [`Command::build`] so that its usage, bin name and
+///   generated `--help` and `--version` flags are complete.
+/// * `version` - The version shown in the page footer.
+///
+/// # Returns
+///
+/// The page, as roff.
+pub fn render(cmd: &Command, version: &str) -> String {
+    let name = page_name(cmd);
+    let mut page = String::new();
+    page.push_str(&format!(
+        ".TH {} 1 \"\" \"symcurve {}\" \"User Commands\"\n",
+        escape(&name.to_uppercase()),
+        version
+    ));
+    page.push_str(".SH NAME\n");
+    let about = cmd.get_about().map(|about| about.to_string());
+    match &about {
+        Some(about) => page.push_str(&format!("{} \\- {}\n", escape(&name), escape(about))),
+        None => page.push_str(&format!("{}\n", escape(&name))),
+    }
+    page.push_str(".SH SYNOPSIS\n");
+    let usage = cmd.clone().render_usage().to_string();
+    let usage = usage.trim_start_matches("Usage:").trim();
+    for line in usage.lines() {
+        page.push_str(&format!("\\fB{}\\fR\n.br\n", escape(line.trim())));
+    }
+    if let Some(long_about) = cmd.get_long_about().or(cmd.get_about()) {
+        page.push_str(".SH DESCRIPTION\n");
+        page.push_str(&escape(&long_about.to_string()));
+        page.push('\n');
+    }
+    let (positionals, options): (Vec<&Arg>, Vec<&Arg>) = cmd
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set())
+        .partition(|arg| arg.is_positional());
+    if !positionals.is_empty() {
+        page.push_str(".SH ARGUMENTS\n");
+        positionals
+            .into_iter()
+            .for_each(|arg| render_arg(&mut page, arg));
+    }
+    if !options.is_empty() {
+        page.push_str(".SH OPTIONS\n");
+        options
+            .into_iter()
+            .for_each(|arg| render_arg(&mut page, arg));
+    }
+    let subcommands: Vec<&Command> = cmd
+        .get_subcommands()
+        .filter(|sub| !sub.is_hide_set() && sub.get_name() != "help")
+        .collect();
+    if !subcommands.is_empty() {
+        page.push_str(".SH COMMANDS\n");
+        for sub in &subcommands {
+            page.push_str(&format!(".TP\n\\fB{}\\fR\n", escape(sub.get_name())));
+            if let Some(about) = sub.get_about() {
+                page.push_str(&escape(&about.to_string()));
+                page.push('\n');
+            }
+            page.push_str(&format!("See \\fB{}\\fR(1).\n", escape(&page_name(sub))));
+        }
+    }
+    page
+}
+
+/// Writes the page of a command and of each of its subcommands into a directory.
+///
+/// # Returns
+///
+/// The paths written, the command's own page first.
+pub fn write_pages(cmd: &Command, version: &str, dir: &Path) -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    let mut written = Vec::new();
+    let path = dir.join(format!("{}.1", page_name(cmd)));
+    fs::write(&path, render(cmd, version))?;
+    written.push(path);
+    for sub in cmd.get_subcommands() {
+        if sub.is_hide_set() || sub.get_name() == "help" {
+            continue;
+        }
+        written.extend(write_pages(sub, version, dir)?);
+    }
+    Ok(written)
+}
+
+/// Runs `symcurve man`.
+///
+/// # Arguments
+///
+/// * `dir` - Where to write the pages of every command, listing the files written on `out`;
+///   without it, the page of the main command is written on `out` instead.
+/// * `out` - Where the page or the list of files goes.
+///
+/// # Errors
+///
+/// Returns any error from writing.
+pub fn man<W: Write>(dir: Option<&Path>, out: &mut W) -> io::Result<()> {
+    let mut cmd = Cli::command();
+    cmd.build();
+    let version = env!("CARGO_PKG_VERSION");
+    match dir {
+        Some(dir) => {
+            for path in write_pages(&cmd, version, dir)? {
+                writeln!(out, "{}", path.display())?;
+            }
+            Ok(())
+        }
+        None => out.write_all(render(&cmd, version).as_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn built() -> Command {
+        let mut cmd = Cli::command();
+        cmd.build();
+        cmd
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a-b \\n"), "a\\-b \\en");
+        assert_eq!(escape(".start\n'quote"), "\\&.start\n\\&'quote");
+    }
+
+    #[test]
+    fn test_render_lists_every_option() {
+        let cmd = built();
+        let page = render(&cmd, "1.0.0");
+        assert!(page.starts_with(".TH SYMCURVE 1 \"\" \"symcurve 1.0.0\""));
+        assert!(page.contains(".SH SYNOPSIS\n\\fBsymcurve [OPTIONS] [INPUT] [OUTPUT]\\fR"));
+        for arg in cmd.get_arguments().filter(|arg| !arg.is_hide_set()) {
+            if let Some(long) = arg.get_long() {
+                let tag = format!("\\fB\\-\\-{}\\fR", escape(long));
+                assert!(page.contains(&tag), "no --{} in the page", long);
+            }
+        }
+        assert!(page.contains("\\fBview\\fR\n"));
+        assert!(page.contains("See \\fBsymcurve\\-view\\fR(1)."));
+        // defaults and possible values of value enums are shown
+        assert!(page.contains("[default: drop]"));
+        assert!(page.contains(".IP \\(bu 2\n\\fBshrink\\fR"));
+    }
+
+    #[test]
+    fn test_write_pages() {
+        let dir = std::env::temp_dir().join("symcurve_test_man");
+        let _ = fs::remove_dir_all(&dir);
+        let cmd = built();
+        let written = write_pages(&cmd, "1.0.0", &dir).unwrap();
+        assert_eq!(written[0], dir.join("symcurve.1"));
+        assert!(written.contains(&dir.join("symcurve-view.1")));
+        assert!(!written.contains(&dir.join("symcurve-help.1")));
+        let view = fs::read_to_string(dir.join("symcurve-view.1")).unwrap();
+        assert!(view.starts_with(".TH SYMCURVE\\-VIEW 1"));
+        assert!(view.contains(".SH ARGUMENTS\n"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,237 @@
+//! # Manifest module
+//! The run manifest is a JSON sidecar written next to the main output describing what a run
+//! produced, so workflow systems can verify outputs without re-deriving them.
+
+use crate::checksum::{digest_file, FileDigests};
+use crate::fasta::validate::MalformedRecord;
+use crate::json::Json;
+use crate::provenance::{BuildInfo, Provenance};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// One file produced by a run.
+///
+/// # Fields
+///
+/// * `path`: Where the file was written.
+/// * `digests`: The size and checksums of the file as written.
+#[derive(Clone, Debug)]
+pub struct OutputRecord {
+    pub path: PathBuf,
+    pub digests: FileDigests,
+}
+
+/// An input record left out of the outputs because it was malformed.
+///
+/// # Fields
+///
+/// * `index`: The 1-based position of the record in the input.
+/// * `name`: The record's name, empty if the header has none.
+/// * `reason`: What was wrong with it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SkippedRecord {
+    pub index: u64,
+    pub name: String,
+    pub reason: String,
+}
+
+/// The manifest of a run.
+///
+/// # Fields
+///
+/// * `version`: The symcurve version that produced the outputs.
+/// * `build`: How the binary that produced the outputs was built, if known.
+/// * `provenance`: The parameters and matrices the outputs were computed with, if known.
+/// * `outputs`: The files produced, in the order they were finished.
+/// * `skipped`: The input records left out because they were malformed.
+#[derive(Clone, Debug)]
+pub struct Manifest {
+    pub version: String,
+    pub build: Option<BuildInfo>,
+    pub provenance: Option<Provenance>,
+    pub outputs: Vec<OutputRecord>,
+    pub skipped: Vec<SkippedRecord>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Manifest {
+    /// Constructor for `Manifest`.
+    pub fn new() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            build: Some(BuildInfo::current()),
+            provenance: None,
+            outputs: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Checksums a finished output file and records it.
+    pub fn add_output(&mut self, path: &Path) -> io::Result<()> {
+        let digests = digest_file(path)?;
+        self.outputs.push(OutputRecord {
+            path: path.to_path_buf(),
+            digests,
+        });
+        Ok(())
+    }
+
+    /// Records an input record that was left out because it was malformed.
+    pub fn add_skipped(&mut self, record: &MalformedRecord) {
+        self.skipped.push(SkippedRecord {
+            index: record.index,
+            name: record.name.clone(),
+            reason: record.problem.to_string(),
+        });
+    }
+
+    /// Converts the manifest to JSON. Skipped records are listed only if there are any, so
+    /// manifests of clean runs are unchanged.
+    pub fn to_json(&self) -> Json {
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|output| {
+                Json::object()
+                    .with("path", output.path.to_string_lossy().into_owned())
+                    .with("bytes", output.digests.bytes)
+                    .with("md5", output.digests.md5.as_str())
+                    .with("sha256", output.digests.sha256.as_str())
+            })
+            .collect::<Vec<_>>();
+        let mut json = Json::object().with("symcurve_version", self.version.as_str());
+        if let Some(build) = &self.build {
+            json = json.with("build", build.to_json());
+        }
+        if let Some(provenance) = &self.provenance {
+            json = json.with("provenance", provenance.to_json());
+        }
+        json = json.with("outputs", outputs);
+        if !self.skipped.is_empty() {
+            let skipped = self
+                .skipped
+                .iter()
+                .map(|record| {
+                    Json::object()
+                        .with("index", record.index)
+                        .with("name", record.name.as_str())
+                        .with("reason", record.reason.as_str())
+                })
+                .collect::<Vec<_>>();
+            json = json.with("skipped_records", skipped);
+        }
+        json
+    }
+
+    /// Reads a manifest back from its JSON. Returns `None` if the JSON doesn't have the expected
+    /// shape.
+    pub fn from_json(json: &Json) -> Option<Self> {
+        let outputs = json
+            .get("outputs")?
+            .as_array()?
+            .iter()
+            .map(|output| {
+                Some(OutputRecord {
+                    path: PathBuf::from(output.get("path")?.as_str()?),
+                    digests: FileDigests {
+                        md5: output.get("md5")?.as_str()?.to_string(),
+                        sha256: output.get("sha256")?.as_str()?.to_string(),
+                        bytes: output.get("bytes")?.as_f64()? as u64,
+                    },
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let skipped = match json.get("skipped_records") {
+            Some(skipped) => skipped
+                .as_array()?
+                .iter()
+                .map(|record| {
+                    Some(SkippedRecord {
+                        index: record.get("index")?.as_f64()? as u64,
+                        name: record.get("name")?.as_str()?.to_string(),
+                        reason: record.get("reason")?.as_str()?.to_string(),
+                    })
+                })
+                .collect::<Option<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+        Some(Self {
+            version: json.get("symcurve_version")?.as_str()?.to_string(),
+            build: json.get("build").and_then(BuildInfo::from_json),
+            provenance: json
+                .get("provenance")
+                .and_then(Provenance::from_json)
+                .map(|recorded| recorded.provenance),
+            outputs,
+            skipped,
+        })
+    }
+
+    /// Writes the manifest as pretty-printed JSON.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_json().pretty())
+    }
+}
+
+/// Derives the path of the manifest sidecar for an output.
+pub fn manifest_path(output: &Path) -> PathBuf {
+    let mut name = output.file_name().unwrap_or_default().to_os_string();
+    name.push(".manifest.json");
+    output.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fasta::validate::check_record;
+
+    #[test]
+    fn test_manifest_checksums() {
+        let dir = std::env::temp_dir().join("symcurve_test_manifest_checksums");
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.bedgraph");
+        fs::write(&output, b"abc").unwrap();
+        let mut manifest = Manifest::new();
+        manifest.add_output(&output).unwrap();
+        let path = manifest_path(&output);
+        assert_eq!(path, dir.join("out.bedgraph.manifest.json"));
+        manifest.write(&path).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        assert!(written.contains("\"md5\": \"900150983cd24fb0d6963f7d28e17f72\""));
+        assert!(written.contains(
+            "\"sha256\": \"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\""
+        ));
+        assert!(written.contains("\"bytes\": 3"));
+        let read_back = Manifest::from_json(&written.parse().unwrap()).unwrap();
+        assert_eq!(read_back.version, manifest.version);
+        assert_eq!(read_back.build, Some(BuildInfo::current()));
+        assert_eq!(read_back.outputs[0].path, output);
+        assert_eq!(read_back.outputs[0].digests, manifest.outputs[0].digests);
+        assert!(!written.contains("skipped_records"));
+        assert!(read_back.skipped.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_manifest_skipped() {
+        let mut manifest = Manifest::new();
+        let record = check_record(5, "chr5", b"ACGT ACGT").unwrap_err();
+        manifest.add_skipped(&record);
+        let json = manifest.to_json();
+        let read_back = Manifest::from_json(&json.to_string().parse().unwrap()).unwrap();
+        assert_eq!(
+            read_back.skipped,
+            vec![SkippedRecord {
+                index: 5,
+                name: "chr5".to_string(),
+                reason: "stray character ' ' at base 5".to_string(),
+            }]
+        );
+    }
+}
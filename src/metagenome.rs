@@ -0,0 +1,452 @@
+//! # Metagenome module
+//! The `symcurve metagenome` subcommand, a throughput mode for assemblies of millions of short
+//! contigs, such as metagenome assemblies, where per-record overheads rather than the curvature
+//! itself dominate the run time.
+//!
+//! Records are read into batches of about [`BATCH_BASES`] bases, so threads pick up many small
+//! contigs at a time rather than contending for one each. Each thread keeps its own window
+//! buffers and summaries for its whole run, nothing is reported per record, and instead of a
+//! track of one row per base the output is one row per bin: the contigs assigned to a genome bin
+//! by a binning tool's contig-to-bin table, or each contig on its own when no table is given.
+
+use crate::curve::iters::{CurveBuffers, CurveIter};
+use crate::fasta::StreamingReader;
+use crate::output::natural_cmp;
+use crate::pipeline::PipelineParams;
+use crate::resources::Resources;
+use crate::stats::streaming::RunningStats;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::mem;
+use std::path::Path;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+
+/// The number of bases read into a batch before it is handed to a thread.
+pub const BATCH_BASES: usize = 1024 * 1024;
+
+/// The bin of contigs missing from the contig-to-bin table.
+pub const UNBINNED: &str = "unbinned";
+
+/// The curvature summary of the contigs of one bin.
+///
+/// # Fields
+///
+/// * `contigs`: The number of contigs.
+/// * `bases`: The number of bases, including gaps.
+/// * `short_contigs`: The number of contigs too short for any value.
+/// * `values`: The statistics of the curvature values.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BinSummary {
+    pub contigs: u64,
+    pub bases: u64,
+    pub short_contigs: u64,
+    pub values: RunningStats,
+}
+
+impl BinSummary {
+    /// Combines another summary of the same bin into this one.
+    pub fn merge(&mut self, other: &BinSummary) {
+        self.contigs += other.contigs;
+        self.bases += other.bases;
+        self.short_contigs += other.short_contigs;
+        self.values.merge(&other.values);
+    }
+}
+
+/// Reads a contig-to-bin table, as written by binning tools such as MetaBAT 2 and DAS Tool: one
+/// contig name and bin name per line, separated by a tab. Blank lines and lines starting with `#`
+/// are skipped.
+///
+/// # Errors
+///
+/// Returns an `InvalidData` error for a line without a bin or a contig assigned to two bins.
+pub fn read_bin_map<R: BufRead>(reader: R) -> io::Result<HashMap<String, String>> {
+    let mut bins = HashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", i + 1, message),
+            )
+        };
+        let Some((contig, bin)) = line.split_once('\t') else {
+            return Err(invalid(format!("expected a contig and a bin: {}", line)));
+        };
+        let bin = bin.split('\t').next().unwrap_or_default().trim();
+        if let Some(previous) = bins.insert(contig.to_string(), bin.to_string()) {
+            if previous != bin {
+                return Err(invalid(format!(
+                    "contig {} is in both {} and {}",
+                    contig, previous, bin
+                )));
+            }
+        }
+    }
+    Ok(bins)
+}
+
+/// A batch of consecutive records.
+///
+/// # Fields
+///
+/// * `names`: The record names.
+/// * `bases`: The bases of every record, concatenated.
+/// * `ends`: The end of each record in `bases`.
+#[derive(Default)]
+struct Batch {
+    names: Vec<String>,
+    bases: Vec<u8>,
+    ends: Vec<usize>,
+}
+
+impl Batch {
+    /// The records of the batch, as name and bases.
+    fn records(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        let starts = std::iter::once(0).chain(self.ends.iter().copied());
+        self.names
+            .iter()
+            .zip(starts.zip(&self.ends))
+            .map(|(name, (start, &end))| (name.as_str(), &self.bases[start..end]))
+    }
+}
+
+/// The per-thread state of a run.
+///
+/// # Fields
+///
+/// * `buffers`: The window buffers, reused for every piece of every record.
+/// * `bins`: The summaries so far, by bin.
+#[derive(Default)]
+struct Worker {
+    buffers: CurveBuffers,
+    bins: HashMap<String, BinSummary>,
+}
+
+impl Worker {
+    /// Adds the curvature of one record to the summary of its bin.
+    fn add_record(
+        &mut self,
+        name: &str,
+        seq: &[u8],
+        params: &PipelineParams,
+        bin_map: Option<&HashMap<String, String>>,
+    ) {
+        let bin = match bin_map {
+            Some(map) => map.get(name).map_or(UNBINNED, String::as_str),
+            None => name,
+        };
+        let summary = match self.bins.get_mut(bin) {
+            Some(summary) => summary,
+            None => self.bins.entry(bin.to_string()).or_default(),
+        };
+        let values_before = summary.values.count();
+        for piece in seq.split(|base| !matches!(base, b'A' | b'C' | b'G' | b'T')) {
+            let mut curve = CurveIter::with_buffers(
+                piece.iter().copied(),
+                params.roll_type.clone(),
+                params.step_b,
+                params.step_c,
+                params.curve_scale,
+                params.edge_mode,
+                params.convention,
+                mem::take(&mut self.buffers),
+            )
+            .with_step_two(params.step_two);
+            curve.by_ref().for_each(|value| summary.values.add(value));
+            self.buffers = curve.into_buffers();
+        }
+        summary.contigs += 1;
+        summary.bases += seq.len() as u64;
+        if summary.values.count() == values_before {
+            summary.short_contigs += 1;
+        }
+    }
+}
+
+/// Summarizes the curvature of every record of a FASTA file by bin.
+///
+/// # Arguments
+///
+/// * `reader` - The FASTA text.
+/// * `params` - The curvature parameters.
+/// * `bin_map` - The bin of each contig; contigs not in it go to [`UNBINNED`]. Without one, each
+///   contig is its own bin.
+/// * `threads` - The number of threads computing curvature, besides the one reading.
+///
+/// # Returns
+///
+/// The summary of each bin, in natural order of bin names.
+///
+/// # Errors
+///
+/// Returns any error from reading.
+pub fn summarize<R: BufRead>(
+    reader: R,
+    params: &PipelineParams,
+    bin_map: Option<&HashMap<String, String>>,
+    threads: usize,
+) -> io::Result<Vec<(String, BinSummary)>> {
+    let (sender, receiver) = mpsc::sync_channel::<Batch>(2 * threads.max(1));
+    let receiver = Mutex::new(receiver);
+    let (read, workers) = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads.max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut worker = Worker::default();
+                    loop {
+                        let next = receiver.lock().unwrap().recv();
+                        let Ok(batch) = next else {
+                            break;
+                        };
+                        for (name, seq) in batch.records() {
+                            worker.add_record(name, seq, params, bin_map);
+                        }
+                    }
+                    worker.bins
+                })
+            })
+            .collect();
+        let read = read_batches(reader, |batch| sender.send(batch).is_ok());
+        drop(sender);
+        let workers: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        (read, workers)
+    });
+    read?;
+    let mut bins: HashMap<String, BinSummary> = HashMap::new();
+    for worker in workers {
+        for (bin, summary) in worker {
+            bins.entry(bin).or_default().merge(&summary);
+        }
+    }
+    let mut bins: Vec<_> = bins.into_iter().collect();
+    bins.sort_by(|a, b| natural_cmp(&a.0, &b.0));
+    Ok(bins)
+}
+
+/// Helper to `summarize()` that reads the records into batches and hands each full batch to
+/// `send`, stopping early if it returns `false`.
+fn read_batches<R: BufRead, F: FnMut(Batch) -> bool>(reader: R, mut send: F) -> io::Result<()> {
+    let mut fasta = StreamingReader::new(reader);
+    let mut batch = Batch::default();
+    while let Some(name) = fasta.next_record()? {
+        for base in fasta.bases() {
+            batch.bases.push(base?.to_ascii_uppercase());
+        }
+        batch.names.push(name);
+        batch.ends.push(batch.bases.len());
+        if batch.bases.len() >= BATCH_BASES && !send(mem::take(&mut batch)) {
+            return Ok(());
+        }
+    }
+    if !batch.names.is_empty() {
+        send(batch);
+    }
+    Ok(())
+}
+
+/// Writes bin summaries as a tab-separated table with a header: bin, contigs, bases, contigs
+/// without values, values, and the mean, standard deviation, minimum and maximum curvature.
+pub fn write_bins<W: Write>(
+    writer: &mut W,
+    bins: &[(String, BinSummary)],
+    params: &PipelineParams,
+) -> io::Result<()> {
+    let float = params.float_format;
+    writeln!(
+        writer,
+        "bin\tcontigs\tbases\tshort_contigs\tvalues\tmean\tsd\tmin\tmax"
+    )?;
+    for (bin, summary) in bins {
+        let stats = &summary.values;
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            bin,
+            summary.contigs,
+            summary.bases,
+            summary.short_contigs,
+            stats.count(),
+            float.format(stats.mean()),
+            float.format(stats.sd()),
+            float.format(stats.min()),
+            float.format(stats.max())
+        )?;
+    }
+    Ok(())
+}
+
+/// Runs `symcurve metagenome`.
+///
+/// # Arguments
+///
+/// * `path` - The FASTA file.
+/// * `bins` - The contig-to-bin table, if any.
+/// * `threads` - The number of threads computing curvature; all the cores the process may use if
+///   not given.
+/// * `params` - The parameters curvature is computed with.
+/// * `writer` - Where the table is written.
+pub fn metagenome<W: Write>(
+    path: &Path,
+    bins: Option<&Path>,
+    threads: Option<usize>,
+    params: &PipelineParams,
+    writer: &mut W,
+) -> io::Result<()> {
+    let bin_map = match bins {
+        Some(bins) => Some(read_bin_map(BufReader::new(File::open(bins)?))?),
+        None => None,
+    };
+    let threads = threads.unwrap_or_else(|| Resources::detect().threads);
+    let reader = BufReader::with_capacity(1 << 20, File::open(path)?);
+    let summaries = summarize(reader, params, bin_map.as_ref(), threads)?;
+    write_bins(writer, &summaries, params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::position_values;
+    use approx::assert_relative_eq;
+    use std::time::Instant;
+
+    const SEQ: &str = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+
+    #[test]
+    fn test_read_bin_map() {
+        let table = "# contig\tbin\nk1\tbin.1\nk2\tbin.2\textra\n\nk3\tbin.1\nk1\tbin.1\n";
+        let bins = read_bin_map(table.as_bytes()).unwrap();
+        assert_eq!(bins.len(), 3);
+        assert_eq!(bins["k2"], "bin.2");
+        assert!(read_bin_map("k1\tbin.1\nk1\tbin.2\n".as_bytes()).is_err());
+        assert!(read_bin_map("k1 bin.1\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_summarize_bins() {
+        let fasta = format!(
+            ">k1\n{}\n>k2\n{}NN{}\n>k3\nACGT\n>k10\n{}\n",
+            SEQ,
+            SEQ,
+            SEQ.to_lowercase(),
+            SEQ
+        );
+        let params = PipelineParams::default();
+        // each contig on its own, in natural order
+        let bins = summarize(fasta.as_bytes(), &params, None, 2).unwrap();
+        let names: Vec<&str> = bins.iter().map(|(bin, _)| bin.as_str()).collect();
+        assert_eq!(names, ["k1", "k2", "k3", "k10"]);
+        assert_eq!(bins[1].1.bases, 102);
+        assert_eq!(bins[1].1.values.count(), 16);
+        assert_eq!(bins[2].1.short_contigs, 1);
+        // grouped, with the same values as computing each contig alone
+        let map: HashMap<String, String> = [("k1", "bin.1"), ("k10", "bin.1"), ("k2", "bin.2")]
+            .iter()
+            .map(|(contig, bin)| (contig.to_string(), bin.to_string()))
+            .collect();
+        let bins = summarize(fasta.as_bytes(), &params, Some(&map), 3).unwrap();
+        let names: Vec<&str> = bins.iter().map(|(bin, _)| bin.as_str()).collect();
+        assert_eq!(names, ["bin.1", "bin.2", UNBINNED]);
+        let bin1 = bins[0].1;
+        assert_eq!((bin1.contigs, bin1.bases, bin1.short_contigs), (2, 100, 0));
+        let expected: Vec<f64> = position_values(SEQ.as_bytes(), &params)
+            .into_iter()
+            .filter(|v| !v.is_nan())
+            .collect();
+        let mean = expected.iter().sum::<f64>() / expected.len() as f64;
+        assert_eq!(bin1.values.count(), 16);
+        assert_relative_eq!(bin1.values.mean(), mean, epsilon = 1e-12);
+        assert_eq!(bins[2].1.contigs, 1);
+    }
+
+    #[test]
+    fn test_summarize_params() {
+        // the values are those of the parameters given, step two included
+        let params = PipelineParams {
+            step_b: 7,
+            step_two: Some(4),
+            ..Default::default()
+        };
+        let fasta = format!(">k1\n{}\n", SEQ.repeat(2));
+        let bins = summarize(fasta.as_bytes(), &params, None, 1).unwrap();
+        let expected: Vec<f64> = position_values(SEQ.repeat(2).as_bytes(), &params)
+            .into_iter()
+            .filter(|v| !v.is_nan())
+            .collect();
+        let mean = expected.iter().sum::<f64>() / expected.len() as f64;
+        assert_eq!(bins[0].1.values.count(), expected.len() as u64);
+        assert_relative_eq!(bins[0].1.values.mean(), mean, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_write_bins() {
+        let mut summary = BinSummary {
+            contigs: 2,
+            bases: 10,
+            ..Default::default()
+        };
+        summary.values.add(1.0);
+        summary.values.add(3.0);
+        let mut out = Vec::new();
+        let bins = [("bin.1".to_string(), summary)];
+        write_bins(&mut out, &bins, &PipelineParams::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "bin\tcontigs\tbases\tshort_contigs\tvalues\tmean\tsd\tmin\tmax\nbin.1\t2\t10\t0\t2\t2\t1\t1\t3\n"
+        );
+    }
+
+    /// Benchmark on a synthetic assembly of 5 million contigs of 100 to 500 bp in 500 bins,
+    /// batched over all cores against one record at a time on one thread.
+    /// Run with `cargo test --release -- --ignored bench_ --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_many_contigs() {
+        let mut fasta = Vec::new();
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        for i in 0..5_000_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let len = 100 + (state % 401) as usize;
+            writeln!(fasta, ">contig_{}", i).unwrap();
+            let seq: Vec<u8> = (0..len)
+                .map(|j| b"ACGT"[((state >> (j % 60)) as usize + j) % 4])
+                .collect();
+            fasta.extend_from_slice(&seq);
+            fasta.push(b'\n');
+        }
+        let map: HashMap<String, String> = (0..5_000_000)
+            .map(|i| (format!("contig_{}", i), format!("bin.{}", i % 500)))
+            .collect();
+        let params = PipelineParams::default();
+        let threads = Resources::detect().threads;
+        let start = Instant::now();
+        let bins = summarize(&fasta[..], &params, Some(&map), threads).unwrap();
+        let batched = start.elapsed();
+        let start = Instant::now();
+        let single = summarize(&fasta[..], &params, Some(&map), 1).unwrap();
+        let one_thread = start.elapsed();
+        assert_eq!(bins.len(), 500);
+        assert_eq!(bins.iter().map(|(_, s)| s.contigs).sum::<u64>(), 5_000_000);
+        assert_eq!(single.len(), 500);
+        let start = Instant::now();
+        let mut sink = io::sink();
+        crate::pipeline::run_pipeline(&fasta[..], &mut sink, &params).unwrap();
+        let per_base = start.elapsed();
+        println!(
+            "5M contigs, {} MB: {} threads {:?}, 1 thread {:?}, per-base track {:?}",
+            fasta.len() / 1_000_000,
+            threads,
+            batched,
+            one_thread,
+            per_base
+        );
+    }
+}
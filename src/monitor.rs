@@ -0,0 +1,257 @@
+//! # Monitor module
+//! The live terminal monitor shown with `--tui`: per-contig progress, throughput, warning counts
+//! and a sparkline of recent curvature values, redrawn in place on stderr, for following long
+//! interactive runs on HPC login nodes.
+//!
+//! Frames are plain text drawn with ANSI cursor movement, which every terminal emulator and
+//! multiplexer understands, so no terminal UI library is needed. Redraws are limited to a few per
+//! second so the monitor costs nothing measurable however fast values arrive.
+
+use crate::warnings::Warnings;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// The number of recent values shown in the sparkline.
+pub const SPARKLINE_LEN: usize = 60;
+
+/// The number of contigs listed, the current one and those just finished.
+pub const CONTIGS_SHOWN: usize = 8;
+
+/// The shortest time between redraws.
+pub const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The bars of a sparkline, lowest first.
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The progress of one contig.
+///
+/// # Fields
+///
+/// * `name`: The contig name.
+/// * `done`: The number of bases processed.
+/// * `total`: The length of the contig.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContigProgress {
+    pub name: String,
+    pub done: u64,
+    pub total: u64,
+}
+
+/// The state shown by the monitor.
+///
+/// # Fields
+///
+/// * `started`: When the run started.
+/// * `last_draw`: When the monitor was last drawn.
+/// * `contigs`: The current contig, last, after those most recently finished.
+/// * `finished`: The number of contigs finished.
+/// * `bases`: The number of bases processed over all contigs.
+/// * `recent`: The most recent values, oldest first.
+pub struct Monitor {
+    started: Instant,
+    last_draw: Option<Instant>,
+    contigs: VecDeque<ContigProgress>,
+    finished: u64,
+    bases: u64,
+    recent: VecDeque<f64>,
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Monitor {
+    /// Constructor for `Monitor`.
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_draw: None,
+            contigs: VecDeque::with_capacity(CONTIGS_SHOWN),
+            finished: 0,
+            bases: 0,
+            recent: VecDeque::with_capacity(SPARKLINE_LEN),
+        }
+    }
+
+    /// Starts following a contig of `length` bases, finishing the current one.
+    pub fn start_contig(&mut self, name: &str, length: u64) {
+        if self.contigs.len() == CONTIGS_SHOWN {
+            self.contigs.pop_front();
+        }
+        if !self.contigs.is_empty() {
+            self.finished += 1;
+        }
+        self.contigs.push_back(ContigProgress {
+            name: name.to_string(),
+            done: 0,
+            total: length,
+        });
+    }
+
+    /// Records progress on the current contig: `bases` more bases, with these values.
+    pub fn advance(&mut self, bases: u64, values: &[f64]) {
+        if let Some(contig) = self.contigs.back_mut() {
+            contig.done = (contig.done + bases).min(contig.total);
+        }
+        self.bases += bases;
+        for &value in values.iter().filter(|v| !v.is_nan()) {
+            if self.recent.len() == SPARKLINE_LEN {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(value);
+        }
+    }
+
+    /// Whether enough time has passed since the last redraw for another; if so, the redraw is
+    /// counted as done.
+    pub fn due(&mut self) -> bool {
+        let now = Instant::now();
+        match self.last_draw {
+            Some(last) if now.duration_since(last) < REDRAW_INTERVAL => false,
+            _ => {
+                self.last_draw = Some(now);
+                true
+            }
+        }
+    }
+
+    /// Renders a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `warnings` - The warnings so far.
+    /// * `elapsed` - The time since the run started.
+    /// * `width` - The width of the progress bars and the sparkline, in characters.
+    pub fn render(&self, warnings: &Warnings, elapsed: Duration, width: usize) -> String {
+        let seconds = elapsed.as_secs_f64().max(1e-9);
+        let mut frame = format!(
+            "symcurve  {:.0}s  {} contigs done  {:.2} Mbp  {:.2} Mbp/s\n\n",
+            elapsed.as_secs_f64(),
+            self.finished,
+            self.bases as f64 / 1e6,
+            self.bases as f64 / 1e6 / seconds
+        );
+        let name_width = self.contigs.iter().map(|c| c.name.len()).max().unwrap_or(0);
+        for contig in &self.contigs {
+            let fraction = if contig.total == 0 {
+                1.0
+            } else {
+                contig.done as f64 / contig.total as f64
+            };
+            frame.push_str(&format!(
+                "  {:<name_width$}  {}  {:>5.1}%\n",
+                contig.name,
+                progress_bar(fraction, width),
+                100.0 * fraction
+            ));
+        }
+        let recent: Vec<f64> = self.recent.iter().copied().collect();
+        frame.push_str(&format!("\n  recent  {}\n\n", sparkline(&recent)));
+        if warnings.is_empty() {
+            frame.push_str("  no warnings\n");
+        } else {
+            for (kind, count) in warnings.iter() {
+                frame.push_str(&format!("  {:>10} {}\n", count, kind.description()));
+            }
+        }
+        frame
+    }
+
+    /// Redraws the monitor in place if a redraw is due.
+    pub fn draw<W: Write>(&mut self, writer: &mut W, warnings: &Warnings) -> io::Result<()> {
+        if !self.due() {
+            return Ok(());
+        }
+        let frame = self.render(warnings, self.started.elapsed(), SPARKLINE_LEN);
+        // cursor to the top left, clear the screen below it
+        write!(writer, "\x1b[H\x1b[J{}", frame)?;
+        writer.flush()
+    }
+}
+
+/// Draws values as a sparkline scaled between their minimum and maximum, one bar per value.
+/// `NaN` values are drawn as spaces.
+pub fn sparkline(values: &[f64]) -> String {
+    let (min, max) = values
+        .iter()
+        .filter(|v| !v.is_nan())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+            (min.min(v), max.max(v))
+        });
+    values
+        .iter()
+        .map(|&v| {
+            if v.is_nan() {
+                ' '
+            } else if max > min {
+                let level = ((v - min) / (max - min) * (BARS.len() - 1) as f64).round();
+                BARS[level as usize]
+            } else {
+                BARS[0]
+            }
+        })
+        .collect()
+}
+
+/// Draws a progress bar `width` characters wide.
+pub fn progress_bar(fraction: f64, width: usize) -> String {
+    let filled = (fraction.clamp(0.0, 1.0) * width as f64).round() as usize;
+    format!("[{}{}]", "#".repeat(filled), "-".repeat(width - filled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::warnings::WarningKind;
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[0.0, 1.0, f64::NAN, 0.5, 2.0]), "▁▅ ▃█");
+        assert_eq!(sparkline(&[3.0, 3.0]), "▁▁");
+        assert_eq!(sparkline(&[]), "");
+        assert_eq!(progress_bar(0.25, 8), "[##------]");
+        assert_eq!(progress_bar(2.0, 4), "[####]");
+    }
+
+    #[test]
+    fn test_render() {
+        let mut monitor = Monitor::new();
+        monitor.start_contig("chr1", 1000);
+        monitor.advance(1000, &[1.0, 2.0]);
+        monitor.start_contig("chr2", 4000);
+        monitor.advance(1000, &[f64::NAN, 0.0]);
+        let mut warnings = Warnings::new();
+        let frame = monitor.render(&warnings, Duration::from_secs(2), 10);
+        assert_eq!(
+            frame,
+            "symcurve  2s  1 contigs done  0.00 Mbp  0.00 Mbp/s\n\n\
+             \x20 chr1  [##########]  100.0%\n\
+             \x20 chr2  [###-------]   25.0%\n\n\
+             \x20 recent  ▅█▁\n\n\
+             \x20 no warnings\n"
+        );
+        warnings.record(WarningKind::ShortContigs, 3, "chr9");
+        let frame = monitor.render(&warnings, Duration::from_secs(2), 10);
+        assert!(frame.ends_with("           3 sequences too short for a value\n"));
+        // only the most recent contigs are listed
+        for i in 0..20 {
+            monitor.start_contig(&format!("scaffold_{}", i), 10);
+        }
+        let frame = monitor.render(&warnings, Duration::from_secs(2), 10);
+        assert_eq!(frame.matches("scaffold_").count(), CONTIGS_SHOWN);
+        assert!(frame.contains("  21 contigs done"));
+    }
+
+    #[test]
+    fn test_redraw_limited() {
+        let mut monitor = Monitor::new();
+        assert!(monitor.due());
+        assert!(!monitor.due());
+        let mut out = Vec::new();
+        monitor.draw(&mut out, &Warnings::new()).unwrap();
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+//! # Motif module
+//! The `symcurve motifs` subcommand, which calls curvature peaks, extracts the sequence around
+//! each summit and summarizes it as a position frequency matrix, so users can see which sequence
+//! features drive high curvature.
+//!
+//! Peaks are the runs of values above a threshold, as for `--binarize`, and the summit of a peak
+//! is its highest value (the first, on ties). Sites are the `width` bases centered on each summit;
+//! summits too close to the end of a sequence for a full site are skipped. The matrix is written
+//! in MEME minimal motif format, for MEME suite tools such as Tomtom, or in JASPAR format.
+
+use crate::fasta::StreamingReader;
+use crate::output::binarize::above_threshold_intervals;
+use crate::pipeline::{position_values, PipelineParams};
+use clap::ValueEnum;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// The formats a matrix can be written in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum MotifFormat {
+    /// MEME minimal motif format, as letter probabilities
+    #[default]
+    Meme,
+    /// JASPAR format, as counts
+    Jaspar,
+}
+
+/// Finds the summit of each peak.
+///
+/// # Arguments
+///
+/// * `values` - The values the peaks were called from.
+/// * `peaks` - The peaks, as index ranges into `values`.
+///
+/// # Returns
+///
+/// The index of the highest value of each peak.
+pub fn summits(values: &[f64], peaks: &[Range<usize>]) -> Vec<usize> {
+    peaks
+        .iter()
+        .map(|peak| {
+            let mut best = peak.start;
+            for i in peak.clone() {
+                if values[i] > values[best] {
+                    best = i;
+                }
+            }
+            best
+        })
+        .collect()
+}
+
+/// A position frequency matrix: the count of each base at each position of a set of aligned
+/// sites.
+///
+/// # Fields
+///
+/// * `counts`: The counts of `A`, `C`, `G` and `T` at each position.
+/// * `sites`: The number of sites added.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrequencyMatrix {
+    pub counts: Vec<[u32; 4]>,
+    pub sites: u32,
+}
+
+impl FrequencyMatrix {
+    /// Constructor for `FrequencyMatrix`.
+    pub fn new(width: usize) -> Self {
+        Self {
+            counts: vec![[0; 4]; width],
+            sites: 0,
+        }
+    }
+
+    /// Adds a site of the matrix's width. Bases other than `A`, `C`, `G` and `T` are not counted.
+    pub fn add(&mut self, site: &[u8]) {
+        for (column, base) in self.counts.iter_mut().zip(site) {
+            match base.to_ascii_uppercase() {
+                b'A' => column[0] += 1,
+                b'C' => column[1] += 1,
+                b'G' => column[2] += 1,
+                b'T' => column[3] += 1,
+                _ => {}
+            }
+        }
+        self.sites += 1;
+    }
+
+    /// Writes the matrix in MEME minimal motif format.
+    pub fn write_meme<W: Write>(&self, writer: &mut W, name: &str) -> io::Result<()> {
+        writeln!(writer, "MEME version 4\n")?;
+        writeln!(writer, "ALPHABET= ACGT\n")?;
+        writeln!(writer, "strands: +\n")?;
+        writeln!(writer, "Background letter frequencies")?;
+        writeln!(writer, "A 0.25 C 0.25 G 0.25 T 0.25\n")?;
+        writeln!(writer, "MOTIF {}", name)?;
+        writeln!(
+            writer,
+            "letter-probability matrix: alength= 4 w= {} nsites= {} E= 0",
+            self.counts.len(),
+            self.sites
+        )?;
+        for column in &self.counts {
+            let total = column.iter().sum::<u32>().max(1) as f64;
+            let row: Vec<String> = column
+                .iter()
+                .map(|&count| format!("{:.6}", f64::from(count) / total))
+                .collect();
+            writeln!(writer, " {}", row.join("  "))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the matrix in JASPAR format.
+    pub fn write_jaspar<W: Write>(&self, writer: &mut W, name: &str) -> io::Result<()> {
+        writeln!(writer, ">{}\t{}", name, name)?;
+        for (row, base) in "ACGT".chars().enumerate() {
+            let counts: Vec<String> = self
+                .counts
+                .iter()
+                .map(|column| format!("{:>4}", column[row]))
+                .collect();
+            writeln!(writer, "{}  [{} ]", base, counts.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Calls peaks on each sequence of a FASTA file and counts the sites around their summits.
+///
+/// # Arguments
+///
+/// * `reader` - The FASTA text.
+/// * `threshold` - Peaks are runs of values above this.
+/// * `width` - The number of bases in each site.
+/// * `params` - The curvature parameters.
+pub fn summit_matrix<R: BufRead>(
+    reader: R,
+    threshold: f64,
+    width: usize,
+    params: &PipelineParams,
+) -> io::Result<FrequencyMatrix> {
+    let mut fasta = StreamingReader::new(reader);
+    let mut matrix = FrequencyMatrix::new(width);
+    while fasta.next_record()?.is_some() {
+        let seq = fasta.bases().collect::<io::Result<Vec<u8>>>()?;
+        let values = position_values(&seq, params);
+        let peaks = above_threshold_intervals(values.iter().copied(), threshold);
+        for summit in summits(&values, &peaks) {
+            let Some(start) = summit.checked_sub(width / 2) else {
+                continue;
+            };
+            if let Some(site) = seq.get(start..start + width) {
+                matrix.add(site);
+            }
+        }
+    }
+    Ok(matrix)
+}
+
+/// Runs `symcurve motifs`.
+///
+/// # Arguments
+///
+/// * `path` - The FASTA file.
+/// * `threshold` - Peaks are runs of values above this.
+/// * `width` - The number of bases in each site.
+/// * `format` - The format the matrix is written in.
+/// * `params` - The parameters curvature is computed with.
+/// * `writer` - Where the matrix is written.
+pub fn motifs<W: Write>(
+    path: &Path,
+    threshold: f64,
+    width: usize,
+    format: MotifFormat,
+    params: &PipelineParams,
+    writer: &mut W,
+) -> io::Result<()> {
+    let matrix = summit_matrix(BufReader::new(File::open(path)?), threshold, width, params)?;
+    if matrix.sites == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no peaks above {} with a full site", threshold),
+        ));
+    }
+    let name = "curvature_summits";
+    match format {
+        MotifFormat::Meme => matrix.write_meme(writer, name),
+        MotifFormat::Jaspar => matrix.write_jaspar(writer, name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summits() {
+        let values = [0.0, 3.0, 5.0, 4.0, 0.0, 6.0, 6.0];
+        assert_eq!(summits(&values, &[1..4, 5..7]), vec![2, 5]);
+    }
+
+    #[test]
+    fn test_frequency_matrix() {
+        let mut matrix = FrequencyMatrix::new(3);
+        matrix.add(b"ACG");
+        matrix.add(b"AnT");
+        assert_eq!(
+            matrix.counts,
+            vec![[2, 0, 0, 0], [0, 1, 0, 0], [0, 0, 1, 1]]
+        );
+        assert_eq!(matrix.sites, 2);
+        let mut out = Vec::new();
+        matrix.write_jaspar(&mut out, "m").unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            ">m\tm\nA  [   2    0    0 ]\nC  [   0    1    0 ]\nG  [   0    0    1 ]\nT  [   0    0    1 ]\n"
+        );
+        let mut out = Vec::new();
+        matrix.write_meme(&mut out, "m").unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("MEME version 4\n\nALPHABET= ACGT\n"));
+        assert!(text.contains("letter-probability matrix: alength= 4 w= 3 nsites= 2 E= 0\n"));
+        assert!(text.ends_with(" 0.000000  0.000000  0.500000  0.500000\n"));
+    }
+
+    #[test]
+    fn test_summit_matrix() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let fasta = format!(">chr1\n{}\n>chr2\n{}\n", seq, seq);
+        let params = PipelineParams::default();
+        let matrix = summit_matrix(fasta.as_bytes(), 0.0, 11, &params).unwrap();
+        // one peak per sequence, both over the same bases
+        assert_eq!(matrix.sites, 2);
+        assert!(matrix.counts.iter().all(|column| column.contains(&2)));
+        let matrix = summit_matrix(fasta.as_bytes(), 1e9, 11, &params).unwrap();
+        assert_eq!(matrix.sites, 0);
+    }
+}
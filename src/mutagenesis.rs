@@ -0,0 +1,194 @@
+//! # Mutagenesis module
+//! The `symcurve scan-mutations` subcommand, an in-silico saturation mutagenesis scan: every
+//! position of a region is mutated to each of the three other bases, the local curvature is
+//! recomputed, and the largest change is reported, giving a map of which positions the curvature
+//! is most sensitive to.
+//!
+//! A mutation can only change the values whose windows cover it, one window flank either side,
+//! so each mutant is recomputed over just that stretch rather than the whole region.
+
+use crate::fasta::open_input;
+use crate::pipeline::{position_values, PipelineParams};
+use crate::sweep::read_region;
+use crate::view::Region;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// The bases mutations are made to, in column order.
+const BASES: &[u8; 4] = b"ACGT";
+
+/// The effect of the mutations at one position.
+///
+/// # Fields
+///
+/// * `position`: The 0-based position within the sequence given to [`scan_mutations`].
+/// * `reference`: The base in the sequence.
+/// * `changes`: For mutation to `A`, `C`, `G` and `T`, the largest absolute change of any value;
+///   0 for the reference base itself, and `NaN` throughout if the reference is not one of them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MutationEffect {
+    pub position: usize,
+    pub reference: u8,
+    pub changes: [f64; 4],
+}
+
+impl MutationEffect {
+    /// The largest change of the three mutations, with the base that causes it, or `.` and `NaN`
+    /// if the reference is not `A`, `C`, `G` or `T`.
+    pub fn max_change(&self) -> (u8, f64) {
+        BASES
+            .iter()
+            .zip(self.changes)
+            .filter(|&(&base, change)| base != self.reference && !change.is_nan())
+            .fold((b'.', f64::NAN), |best, (&base, change)| {
+                if best.1.is_nan() || change > best.1 {
+                    (base, change)
+                } else {
+                    best
+                }
+            })
+    }
+}
+
+/// Scans the mutations at a range of positions of a sequence.
+///
+/// # Arguments
+///
+/// * `seq` - The sequence, uppercase, including the bases either side of `positions` that the
+///   affected values depend on.
+/// * `positions` - The positions to mutate.
+/// * `params` - The curvature parameters.
+pub fn scan_mutations(
+    seq: &[u8],
+    positions: Range<usize>,
+    params: &PipelineParams,
+) -> Vec<MutationEffect> {
+    let flank = params.flank();
+    positions
+        .map(|position| {
+            let reference = seq[position];
+            let mut changes = [f64::NAN; 4];
+            if BASES.contains(&reference) {
+                let local =
+                    position.saturating_sub(2 * flank)..(position + 2 * flank + 1).min(seq.len());
+                let original = position_values(&seq[local.clone()], params);
+                let mut mutant = seq[local.clone()].to_vec();
+                for (change, &base) in changes.iter_mut().zip(BASES) {
+                    mutant[position - local.start] = base;
+                    let values = position_values(&mutant, params);
+                    *change = original
+                        .iter()
+                        .zip(&values)
+                        .filter(|(a, b)| !a.is_nan() && !b.is_nan())
+                        .map(|(a, b)| (a - b).abs())
+                        .fold(0.0, f64::max);
+                }
+            }
+            MutationEffect {
+                position,
+                reference,
+                changes,
+            }
+        })
+        .collect()
+}
+
+/// Runs `symcurve scan-mutations`.
+///
+/// Writes a tab-separated table with a header: `chrom`, 1-based position, reference base, the
+/// change for each of `A`, `C`, `G` and `T`, then the largest change and the base causing it.
+///
+/// # Arguments
+///
+/// * `path` - The FASTA file.
+/// * `region` - The region to scan, which must have an end.
+/// * `params` - The parameters curvature is computed with.
+/// * `writer` - Where the table is written.
+pub fn scan<W: Write>(
+    path: &Path,
+    region: &Region,
+    params: &PipelineParams,
+    writer: &mut W,
+) -> io::Result<()> {
+    let end = region.end.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the region needs a start and an end",
+        )
+    })?;
+    let reach = 2 * params.flank() as u64;
+    let context = Region {
+        chrom: region.chrom.clone(),
+        start: region.start.saturating_sub(reach),
+        end: Some(end + reach),
+    };
+    let seq = read_region(open_input(path)?, Some(&context))?;
+    let first = ((region.start - context.start) as usize).min(seq.len());
+    let last = (first + (end - region.start) as usize).min(seq.len());
+    let float = params.float_format;
+    writeln!(writer, "chrom\tpos\tref\tA\tC\tG\tT\tmax_change\tmax_alt")?;
+    for effect in scan_mutations(&seq, first..last, params) {
+        let (alt, max) = effect.max_change();
+        write!(
+            writer,
+            "{}\t{}\t{}",
+            region.chrom,
+            context.start + effect.position as u64 + 1,
+            effect.reference as char
+        )?;
+        for change in effect.changes {
+            write!(writer, "\t{}", float.format(change))?;
+        }
+        writeln!(writer, "\t{}\t{}", float.format(max), alt as char)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    const SEQ: &[u8] = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATCCCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+
+    #[test]
+    fn test_scan_mutations() {
+        check_scan(&PipelineParams::default());
+        // a wider rolling mean reaches further, and the recomputation follows it
+        check_scan(&PipelineParams {
+            step_b: 7,
+            step_two: Some(4),
+            ..Default::default()
+        });
+    }
+
+    fn check_scan(params: &PipelineParams) {
+        let effects = scan_mutations(SEQ, 45..55, params);
+        assert_eq!(effects.len(), 10);
+        for effect in &effects {
+            let own = BASES.iter().position(|&b| b == effect.reference).unwrap();
+            assert_eq!(effect.changes[own], 0.0);
+            assert!(effect.max_change().1 > 0.0);
+        }
+        // the local recomputation agrees with recomputing the whole sequence
+        let effect = effects[5];
+        let mut mutant = SEQ.to_vec();
+        let (alt, max) = effect.max_change();
+        mutant[effect.position] = alt;
+        let full = position_values(SEQ, params)
+            .iter()
+            .zip(position_values(&mutant, params))
+            .filter(|(a, b)| !a.is_nan() && !b.is_nan())
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        assert_relative_eq!(max, full, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_scan_mutations_gap() {
+        let effects = scan_mutations(b"ACGNACGT", 3..4, &PipelineParams::default());
+        assert!(effects[0].changes.iter().all(|c| c.is_nan()));
+        assert_eq!(effects[0].max_change().0, b'.');
+    }
+}
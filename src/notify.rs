@@ -0,0 +1,261 @@
+//! # Notify module
+//! Notification when a run finishes or fails (`--notify-url`), so Slack webhooks and pipeline
+//! monitors can follow long runs without wrapper scripts.
+//!
+//! The notification is the run manifest JSON with the outcome added: a `status` of `success` or
+//! `failure` and, on failure, the `error` message. An `http://` URL receives it as the body of a
+//! POST request; a `file://` URL names a done-file it is written to, atomically, for monitors
+//! that watch the filesystem. `https://` is not supported, since the crate has no TLS
+//! implementation; point it at a local relay, or use a done-file.
+
+use crate::json::Json;
+use crate::output::atomic::AtomicFile;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How long connecting, sending and waiting for the response may each take.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where a notification is sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NotifyTarget {
+    /// POSTed to an HTTP endpoint.
+    Http {
+        host: String,
+        port: u16,
+        path: String,
+    },
+    /// Written to a done-file.
+    File(PathBuf),
+}
+
+impl FromStr for NotifyTarget {
+    type Err = String;
+
+    /// Parses `http://HOST[:PORT][/PATH]` or `file:///PATH`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("file://") {
+            if path.is_empty() {
+                return Err(format!("invalid URL '{}': no file path", s));
+            }
+            return Ok(NotifyTarget::File(PathBuf::from(path)));
+        }
+        if s.starts_with("https://") {
+            return Err(format!(
+                "invalid URL '{}': https is not supported; use an http:// relay or a file:// done-file",
+                s
+            ));
+        }
+        let rest = s.strip_prefix("http://").ok_or_else(|| {
+            format!(
+                "invalid URL '{}': expected http://HOST[:PORT]/PATH or file:///PATH",
+                s
+            )
+        })?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port
+                    .parse::<u16>()
+                    .map_err(|_| format!("invalid URL '{}': bad port '{}'", s, port))?;
+                (host, port)
+            }
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("invalid URL '{}': no host", s));
+        }
+        Ok(NotifyTarget::Http {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for NotifyTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotifyTarget::Http { host, port, path } => {
+                write!(f, "http://{}:{}{}", host, port, path)
+            }
+            NotifyTarget::File(path) => write!(f, "file://{}", path.display()),
+        }
+    }
+}
+
+/// Builds the notification for a run: the manifest JSON with the outcome added.
+///
+/// # Arguments
+///
+/// * `manifest` - The run manifest, as from `Manifest::to_json`.
+/// * `outcome` - `Ok` if the run succeeded, or the error message it failed with.
+pub fn notification(manifest: Json, outcome: Result<(), &str>) -> Json {
+    match outcome {
+        Ok(()) => manifest.with("status", "success"),
+        Err(error) => manifest.with("status", "failure").with("error", error),
+    }
+}
+
+/// Sends a notification.
+///
+/// # Errors
+///
+/// Returns any error from connecting or writing, or an error if the endpoint does not answer with
+/// a 2xx status.
+pub fn notify(target: &NotifyTarget, notification: &Json) -> io::Result<()> {
+    let body = notification.pretty();
+    match target {
+        NotifyTarget::File(path) => {
+            let mut file = AtomicFile::create(path)?;
+            file.write_all(body.as_bytes())?;
+            file.commit()
+        }
+        NotifyTarget::Http { host, port, path } => {
+            let addr = (host.as_str(), *port)
+                .to_socket_addrs()?
+                .next()
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, format!("{} not found", host))
+                })?;
+            let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+            stream.set_read_timeout(Some(TIMEOUT))?;
+            stream.set_write_timeout(Some(TIMEOUT))?;
+            write!(
+                stream,
+                "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: symcurve/{}\r\n\
+                 Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                path,
+                host,
+                env!("CARGO_PKG_VERSION"),
+                body.len(),
+                body
+            )?;
+            stream.flush()?;
+            let mut status_line = String::new();
+            BufReader::new(stream).read_line(&mut status_line)?;
+            let status = status_line.split_whitespace().nth(1).unwrap_or_default();
+            if status.starts_with('2') && status.len() == 3 {
+                Ok(())
+            } else {
+                Err(io::Error::other(format!(
+                    "{} answered '{}'",
+                    target,
+                    status_line.trim_end()
+                )))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(
+            "http://localhost:8080/hooks/run".parse(),
+            Ok(NotifyTarget::Http {
+                host: "localhost".to_string(),
+                port: 8080,
+                path: "/hooks/run".to_string(),
+            })
+        );
+        assert_eq!(
+            "http://monitor".parse(),
+            Ok(NotifyTarget::Http {
+                host: "monitor".to_string(),
+                port: 80,
+                path: "/".to_string(),
+            })
+        );
+        assert_eq!(
+            "file:///scratch/run1.done".parse(),
+            Ok(NotifyTarget::File(PathBuf::from("/scratch/run1.done")))
+        );
+        assert!("https://hooks.example.com/x"
+            .parse::<NotifyTarget>()
+            .is_err());
+        assert!("http://host:port/".parse::<NotifyTarget>().is_err());
+        assert!("ftp://host/".parse::<NotifyTarget>().is_err());
+        assert!("file://".parse::<NotifyTarget>().is_err());
+    }
+
+    #[test]
+    fn test_notification() {
+        let json = notification(Json::object().with("symcurve_version", "0.1.0"), Ok(()));
+        assert_eq!(json.get("status").and_then(Json::as_str), Some("success"));
+        let json = notification(Json::object(), Err("chr1: broken pipe"));
+        assert_eq!(json.get("status").and_then(Json::as_str), Some("failure"));
+        assert_eq!(
+            json.get("error").and_then(Json::as_str),
+            Some("chr1: broken pipe")
+        );
+    }
+
+    #[test]
+    fn test_notify_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for status in ["204 No Content", "500 Internal Server Error"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                // read the headers, then as much body as Content-Length promises
+                let complete = |request: &[u8]| {
+                    let text = String::from_utf8_lossy(request);
+                    let Some((headers, body)) = text.split_once("\r\n\r\n") else {
+                        return false;
+                    };
+                    let length = headers
+                        .lines()
+                        .find_map(|line| line.strip_prefix("Content-Length: "))
+                        .and_then(|length| length.parse::<usize>().ok());
+                    length == Some(body.len())
+                };
+                while !complete(&request) {
+                    let n = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                write!(stream, "HTTP/1.1 {}\r\n\r\n", status).unwrap();
+                requests.push(String::from_utf8(request).unwrap());
+            }
+            requests
+        });
+        let target: NotifyTarget = format!("http://127.0.0.1:{}/done", port).parse().unwrap();
+        let json = notification(Json::object(), Ok(()));
+        notify(&target, &json).unwrap();
+        let err = notify(&target, &json).unwrap_err();
+        assert!(err.to_string().contains("500 Internal Server Error"));
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("POST /done HTTP/1.1\r\n"));
+        assert!(requests[0].contains("Content-Type: application/json\r\n"));
+        assert!(requests[0].contains("\"status\": \"success\""));
+    }
+
+    #[test]
+    fn test_notify_file() {
+        let dir = std::env::temp_dir().join("symcurve_test_notify_file");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("run.done");
+        let target = NotifyTarget::File(path.clone());
+        notify(&target, &notification(Json::object(), Err("failed"))).unwrap();
+        let json: Json = fs::read_to_string(&path).unwrap().parse().unwrap();
+        assert_eq!(json.get("error").and_then(Json::as_str), Some("failed"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
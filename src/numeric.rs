@@ -0,0 +1,140 @@
+//! # Numeric module
+//! Parsing of the numbers given on the command line, in the forms people write genome-scale
+//! values in.
+//!
+//! Counts, such as window sizes, steps and region lengths, accept `_` digit separators
+//! (`1_000_000`), scientific notation (`1e6`) and the decimal suffixes `k`, `M` and `G`, in either
+//! case and optionally followed by `b` or `bp` (`5k`, `2Mb`, `1.5kb`), as long as the result is
+//! a whole number. Real numbers accept the separators and scientific notation (`1e-3`).
+
+/// Removes `_` digit separators, which may only stand between two digits.
+fn strip_separators(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    for (i, &b) in bytes.iter().enumerate() {
+        if b == b'_' {
+            let digit = |j: Option<&u8>| j.is_some_and(u8::is_ascii_digit);
+            if !digit(i.checked_sub(1).and_then(|j| bytes.get(j))) || !digit(bytes.get(i + 1)) {
+                return None;
+            }
+        }
+    }
+    Some(s.replace('_', ""))
+}
+
+/// Parses a real number, e.g. `0.5`, `1e-3` or `1_000.5`.
+///
+/// # Errors
+///
+/// Returns a message naming the text if it is not a finite number.
+pub fn parse_real(s: &str) -> Result<f64, String> {
+    strip_separators(s.trim())
+        .and_then(|text| text.parse::<f64>().ok())
+        .filter(|value| value.is_finite())
+        .ok_or_else(|| format!("invalid number '{}': expected e.g. 0.5, 1e-3 or 1_000", s))
+}
+
+/// Parses a count, e.g. `150`, `1_000_000`, `1e6`, `5k` or `2Mb`.
+///
+/// # Errors
+///
+/// Returns a message naming the text if it is not a whole number of at least 0 that fits a
+/// `u64`.
+pub fn parse_count(s: &str) -> Result<u64, String> {
+    let invalid = || {
+        format!(
+            "invalid count '{}': expected a whole number such as 150, 1_000_000, 1e6, 5k or 2M",
+            s
+        )
+    };
+    let lower = s.trim().to_ascii_lowercase();
+    let number = lower
+        .strip_suffix("bp")
+        .or_else(|| lower.strip_suffix('b'))
+        .unwrap_or(&lower);
+    let (number, scale) = match number.chars().last() {
+        Some('k') => (&number[..number.len() - 1], 1e3),
+        Some('m') => (&number[..number.len() - 1], 1e6),
+        Some('g') => (&number[..number.len() - 1], 1e9),
+        _ => (number, 1.0),
+    };
+    let number = strip_separators(number).ok_or_else(invalid)?;
+    // plain digits are parsed exactly, beyond the 53 bits a float holds
+    if scale == 1.0 && !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) {
+        return number.parse().map_err(|_| invalid());
+    }
+    if number.starts_with(['-', '+']) || number.contains(['i', 'n']) {
+        return Err(invalid());
+    }
+    let value = number.parse::<f64>().map_err(|_| invalid())? * scale;
+    if value.fract() != 0.0 || !(0.0..=u64::MAX as f64).contains(&value) {
+        return Err(invalid());
+    }
+    Ok(value as u64)
+}
+
+/// A command line parser for counts of type `T` of at least `min`, accepting the forms of
+/// [`parse_count`].
+///
+/// ```
+/// let parse = symcurve::numeric::count::<u16>(1);
+/// assert_eq!(parse("1_000"), Ok(1000));
+/// assert!(parse("0").is_err());
+/// assert!(parse("100k").is_err());
+/// ```
+pub fn count<T>(min: u64) -> impl Fn(&str) -> Result<T, String> + Clone + Send + Sync + 'static
+where
+    T: TryFrom<u64> + Clone + Send + Sync + 'static,
+{
+    move |s: &str| {
+        let value = parse_count(s)?;
+        if value < min {
+            return Err(format!("{} is not at least {}", s, min));
+        }
+        T::try_from(value).map_err(|_| format!("{} is too large", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_count() {
+        assert_eq!(parse_count("150"), Ok(150));
+        assert_eq!(parse_count("1_000_000"), Ok(1_000_000));
+        assert_eq!(parse_count("1e6"), Ok(1_000_000));
+        assert_eq!(parse_count("5k"), Ok(5_000));
+        assert_eq!(parse_count("5K"), Ok(5_000));
+        assert_eq!(parse_count("2M"), Ok(2_000_000));
+        assert_eq!(parse_count("2Mb"), Ok(2_000_000));
+        assert_eq!(parse_count("1.5kb"), Ok(1_500));
+        assert_eq!(parse_count("3G"), Ok(3_000_000_000));
+        assert_eq!(parse_count("120bp"), Ok(120));
+        assert_eq!(parse_count("18446744073709551615"), Ok(u64::MAX));
+        for bad in [
+            "", "k", "1.5", "1e-3", "-5", "+5", "5x", "1__0", "_10", "10_", "inf", "nan", "1e30",
+        ] {
+            assert!(parse_count(bad).is_err(), "{} parsed", bad);
+        }
+    }
+
+    #[test]
+    fn test_parse_real() {
+        assert_eq!(parse_real("0.5"), Ok(0.5));
+        assert_eq!(parse_real("1e-3"), Ok(0.001));
+        assert_eq!(parse_real("-2.5E2"), Ok(-250.0));
+        assert_eq!(parse_real("1_000.5"), Ok(1000.5));
+        for bad in ["", "abc", "1_", "inf", "NaN", "1,5"] {
+            assert!(parse_real(bad).is_err(), "{} parsed", bad);
+        }
+    }
+
+    #[test]
+    fn test_count() {
+        let parse = count::<u16>(3);
+        assert_eq!(parse("5"), Ok(5));
+        assert_eq!(parse("2"), Err("2 is not at least 3".to_string()));
+        assert_eq!(parse("1M"), Err("1M is too large".to_string()));
+        assert!(parse("x").unwrap_err().starts_with("invalid count 'x'"));
+    }
+}
@@ -0,0 +1,665 @@
+//! # Output module
+//! This module contains the pieces shared by the output formats: mapping computed values back
+//! onto sequence positions, and the options that control how those positions are reported.
+//!
+//! Sequence coordinates are `u64` throughout, since some plant and amphibian contigs are longer
+//! than 32-bit tools can address. Formats that store 32-bit coordinates check them with
+//! [`check_bigwig_length`] and fail with a clear error rather than wrapping.
+
+pub mod arrow;
+pub mod atomic;
+pub mod bed;
+pub mod bedgraph;
+pub mod bigwig;
+pub mod binarize;
+pub mod clip;
+pub mod compress;
+pub mod dict;
+pub mod layout;
+pub mod preview;
+pub mod pvalue;
+pub mod queue;
+pub mod spill;
+pub mod sqlite;
+pub mod track;
+pub mod tsv;
+pub mod zstd;
+
+use crate::fasta::STDIN;
+use clap::ValueEnum;
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Where an emitted value is anchored relative to the window it was computed over.
+///
+/// Browser tracks conventionally place a windowed value at the center of its window, while some
+/// downstream tools expect the value at the first base of the window instead.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Anchor {
+    /// Report the value at the central base of its window.
+    #[default]
+    Center,
+    /// Report the value at the first base of its window.
+    Start,
+}
+
+impl Anchor {
+    /// Maps the index of an emitted value to a 0-based position in the sequence.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The 0-based index of the value in the emitted track, which is also the
+    ///   0-based position of the first base of its window.
+    /// * `flank` - The number of bases on either side of the central base of the window.
+    ///
+    /// # Returns
+    ///
+    /// The 0-based sequence position the value should be reported at.
+    pub fn position(&self, index: u64, flank: u64) -> u64 {
+        match self {
+            Anchor::Center => index + flank,
+            Anchor::Start => index,
+        }
+    }
+}
+
+/// What text formats write at positions where no value could be computed.
+///
+/// Uncomputable positions (the flanks of each sequence piece and runs of `N`) are carried through
+/// the pipeline as `f64::NAN`; this controls how they are rendered, since downstream parsers are
+/// strict about different conventions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum NaValue {
+    /// write `NaN`
+    #[default]
+    #[value(name = "nan")]
+    NaN,
+    /// write `NA`, as R expects
+    #[value(name = "na")]
+    Na,
+    /// write `.`, as bedtools expects
+    #[value(name = ".")]
+    Dot,
+    /// write `-1`
+    #[value(name = "-1")]
+    MinusOne,
+    /// leave the line out altogether
+    Omit,
+}
+
+impl NaValue {
+    /// The text written for a missing value, or `None` if the line should be omitted.
+    pub fn text(&self) -> Option<&'static str> {
+        match self {
+            NaValue::NaN => Some("NaN"),
+            NaValue::Na => Some("NA"),
+            NaValue::Dot => Some("."),
+            NaValue::MinusOne => Some("-1"),
+            NaValue::Omit => None,
+        }
+    }
+
+    /// Formats a value, substituting the missing-value text for `NaN`. Returns `None` if the
+    /// value is missing and the line should be omitted.
+    pub fn format(&self, value: f64, float: FloatFormat) -> Option<String> {
+        if value.is_nan() {
+            self.text().map(str::to_string)
+        } else {
+            Some(float.format(value))
+        }
+    }
+}
+
+/// How numbers are written in text outputs.
+///
+/// Numbers are always written with `.` as the decimal separator whatever the locale, since Rust's
+/// formatting does not consult it. The fixed and scientific forms also give every value the same
+/// number of digits, which some fixed-width parsers rely on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FloatFormat {
+    /// The shortest text that reads back as the same value.
+    #[default]
+    Shortest,
+    /// A fixed number of decimal places, e.g. `fixed:4` gives `1.2346`.
+    Fixed(usize),
+    /// Scientific notation with a number of mantissa decimal places, e.g. `sci:3` gives
+    /// `1.235e+00`.
+    Sci(usize),
+}
+
+impl FloatFormat {
+    /// Formats a value. Infinities are written as `inf` and `-inf`.
+    pub fn format(&self, value: f64) -> String {
+        let mut text = String::new();
+        self.write_to(&mut text, value).unwrap();
+        text
+    }
+
+    /// Appends a formatted value to `out`, as [`FloatFormat::format`] would return it, without
+    /// allocating a `String` per value for the shortest and fixed forms.
+    pub fn write_to<W: fmt::Write>(&self, out: &mut W, value: f64) -> fmt::Result {
+        if value.is_infinite() {
+            return out.write_str(if value > 0.0 { "inf" } else { "-inf" });
+        }
+        match self {
+            FloatFormat::Shortest => write!(out, "{}", value),
+            FloatFormat::Fixed(places) => write!(out, "{:.*}", places, value),
+            FloatFormat::Sci(places) => {
+                // Rust writes `1.235e0`; use the C form with a signed two-digit exponent
+                let text = format!("{:.*e}", places, value);
+                let (mantissa, exponent) = text.split_once('e').unwrap();
+                let exponent: i32 = exponent.parse().unwrap();
+                let sign = if exponent < 0 { '-' } else { '+' };
+                write!(out, "{}e{}{:02}", mantissa, sign, exponent.abs())
+            }
+        }
+    }
+}
+
+impl FromStr for FloatFormat {
+    type Err = String;
+
+    /// Parses `shortest`, `fixed:N` or `sci:N`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "shortest" {
+            return Ok(FloatFormat::Shortest);
+        }
+        let invalid = || {
+            format!(
+                "invalid float format '{}': expected shortest, fixed:N or sci:N",
+                s
+            )
+        };
+        let (style, places) = s.split_once(':').ok_or_else(invalid)?;
+        let places: usize = places
+            .parse()
+            .ok()
+            .filter(|&p| p <= 17)
+            .ok_or_else(invalid)?;
+        match style {
+            "fixed" => Ok(FloatFormat::Fixed(places)),
+            "sci" => Ok(FloatFormat::Sci(places)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// The order sequences are written in.
+///
+/// bigWig files and track hubs list chromosomes in the order they were written, which renders
+/// best in karyotype order rather than however the FASTA happens to be laid out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SortContigs {
+    /// keep the order of the input FASTA
+    #[default]
+    Input,
+    /// natural name order, so chr2 comes before chr10
+    Name,
+    /// longest first
+    Length,
+}
+
+impl SortContigs {
+    /// Sorts contigs into output order. The sort is stable, so contigs that compare equal keep
+    /// their input order.
+    ///
+    /// # Arguments
+    ///
+    /// * `contigs` - The contigs, in input order.
+    /// * `key` - Gets the name and length of a contig.
+    pub fn sort<T, F>(&self, contigs: &mut [T], key: F)
+    where
+        F: Fn(&T) -> (&str, u64),
+    {
+        match self {
+            SortContigs::Input => {}
+            SortContigs::Name => contigs.sort_by(|a, b| natural_cmp(key(a).0, key(b).0)),
+            SortContigs::Length => contigs.sort_by_key(|c| std::cmp::Reverse(key(c).1)),
+        }
+    }
+}
+
+/// Compares names with runs of digits compared by their numeric value, so that `chr2` sorts
+/// before `chr10` and `chrX` after both.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    while let (Some(&x), Some(&y)) = (a.first(), b.first()) {
+        let ordering = if x.is_ascii_digit() && y.is_ascii_digit() {
+            let a_len = a.iter().take_while(|c| c.is_ascii_digit()).count();
+            let b_len = b.iter().take_while(|c| c.is_ascii_digit()).count();
+            let (a_digits, b_digits) = (&a[..a_len], &b[..b_len]);
+            a = &a[a_len..];
+            b = &b[b_len..];
+            // compare by value, ignoring leading zeros, then by length so "01" != "1"
+            let a_trimmed = trim_zeros(a_digits);
+            let b_trimmed = trim_zeros(b_digits);
+            a_trimmed
+                .len()
+                .cmp(&b_trimmed.len())
+                .then_with(|| a_trimmed.cmp(b_trimmed))
+                .then_with(|| a_len.cmp(&b_len))
+        } else {
+            a = &a[1..];
+            b = &b[1..];
+            x.cmp(&y)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+/// Strips leading zeros from a run of digits.
+fn trim_zeros(digits: &[u8]) -> &[u8] {
+    let zeros = digits.iter().take_while(|&&c| c == b'0').count();
+    &digits[zeros..]
+}
+
+/// The strand a track was computed on.
+///
+/// Minus-strand values are computed over the reverse complement of the sequence and are mapped
+/// back onto forward-strand coordinates before they are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strand {
+    Plus,
+    Minus,
+}
+
+impl Strand {
+    /// The label used for this strand in output file names.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Strand::Plus => "plus",
+            Strand::Minus => "minus",
+        }
+    }
+
+    /// Maps a 0-based position on this strand to the 0-based forward-strand position.
+    ///
+    /// # Arguments
+    ///
+    /// * `pos` - The 0-based position on this strand.
+    /// * `seq_len` - The length of the sequence the position is in.
+    pub fn forward_position(&self, pos: u64, seq_len: u64) -> u64 {
+        match self {
+            Strand::Plus => pos,
+            Strand::Minus => seq_len - 1 - pos,
+        }
+    }
+}
+
+/// The format of the main output file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// a bigWig track, without zoom levels
+    #[default]
+    #[value(name = "bigwig")]
+    BigWig,
+    /// chrom, start, end, value lines, with equal adjacent values merged
+    #[value(name = "bedgraph")]
+    BedGraph,
+    /// chrom, 1-based position, value rows
+    Tsv,
+}
+
+impl OutputFormat {
+    /// The format an output path asks for: bedGraph for a `.bedGraph` or `.bg` extension, TSV
+    /// rows for a `.tsv` or `.txt` extension, in any case, and bigWig otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().map(|ext| ext.to_ascii_lowercase()) {
+            Some(ext) if ext == "bedgraph" || ext == "bg" => OutputFormat::BedGraph,
+            Some(ext) if ext == "tsv" || ext == "txt" => OutputFormat::Tsv,
+            _ => OutputFormat::BigWig,
+        }
+    }
+}
+
+/// The largest chromosome length a bigWig file can describe. Chromosome sizes and positions are
+/// stored as unsigned 32-bit integers in the format.
+pub const BIGWIG_MAX_LENGTH: u64 = u32::MAX as u64;
+
+/// Checks that a sequence fits in bigWig's 32-bit coordinates.
+///
+/// # Errors
+///
+/// Returns an `io::ErrorKind::InvalidInput` error naming the sequence if it is longer than
+/// [`BIGWIG_MAX_LENGTH`].
+pub fn check_bigwig_length(chrom: &str, length: u64) -> io::Result<()> {
+    if length > BIGWIG_MAX_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "sequence {} is {} bp, longer than the {} bp a bigWig file can address; \
+                 use a text output format for it instead",
+                chrom, length, BIGWIG_MAX_LENGTH
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Converts a coordinate to the 32-bit form stored in bigWig files.
+///
+/// # Errors
+///
+/// Returns an `io::ErrorKind::InvalidInput` error if the coordinate does not fit, rather than
+/// letting it wrap.
+pub fn bigwig_coordinate(chrom: &str, pos: u64) -> io::Result<u32> {
+    u32::try_from(pos).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "position {} on {} is beyond the {} bp a bigWig file can address",
+                pos, chrom, BIGWIG_MAX_LENGTH
+            ),
+        )
+    })
+}
+
+/// Derives the path of a per-strand output file from the requested output path.
+///
+/// The strand label is inserted before the file extension, so that `out.bw` becomes
+/// `out.plus.bw` and `out.minus.bw`. A path without an extension gets the label appended.
+pub fn stranded_path(path: &Path, strand: Strand) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, strand.label(), ext.to_string_lossy()),
+        None => format!("{}.{}", stem, strand.label()),
+    };
+    path.with_file_name(name)
+}
+
+/// Resolves the output path for an input, applying `--output-dir` templating.
+///
+/// Without an output directory the output path is used as given. With one, the output path is
+/// treated as a file name template inside that directory, where `{input}` is replaced by the
+/// input file name without its extension (and without a trailing `.gz`). This lets batch jobs use
+/// one command line for many inputs, e.g. `{input}.bw` with `--output-dir results`. Input read
+/// from standard input has the stem `stdin`.
+pub fn resolve_output_path(output: &Path, input: &Path, output_dir: Option<&Path>) -> PathBuf {
+    match output_dir {
+        Some(dir) => {
+            let file_name = if input.as_os_str() == STDIN {
+                "stdin".into()
+            } else {
+                input.file_name().unwrap_or_default().to_string_lossy()
+            };
+            let file_name = file_name.strip_suffix(".gz").unwrap_or(&file_name);
+            let stem = match file_name.rsplit_once('.') {
+                Some((base, _)) if !base.is_empty() => base,
+                _ => file_name,
+            };
+            let name = output.to_string_lossy().replace("{input}", stem);
+            dir.join(name)
+        }
+        None => output.to_path_buf(),
+    }
+}
+
+/// Refuses to clobber an existing output unless `force` is set.
+///
+/// # Errors
+///
+/// Returns an `io::ErrorKind::AlreadyExists` error naming the file if it exists and `force` is
+/// not set.
+pub fn check_overwrite(path: &Path, force: bool) -> io::Result<()> {
+    if !force && path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "output file {} already exists (use --force to overwrite)",
+                path.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Derives the path of the checkpoint file written next to an output when a run is interrupted.
+pub fn checkpoint_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".checkpoint");
+    path.with_file_name(name)
+}
+
+/// Writes the checkpoint for an interrupted run: the names of the records whose output was
+/// completed and finalized, one per line, so a rerun knows where the partial output stops.
+pub fn write_checkpoint(path: &Path, completed: &[String]) -> io::Result<()> {
+    let mut contents = String::new();
+    for name in completed {
+        contents.push_str(name);
+        contents.push('\n');
+    }
+    fs::write(checkpoint_path(path), contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchor_position() {
+        assert_eq!(Anchor::default(), Anchor::Center);
+        assert_eq!(Anchor::Center.position(0, 21), 21);
+        assert_eq!(Anchor::Center.position(7, 21), 28);
+        assert_eq!(Anchor::Start.position(0, 21), 0);
+        assert_eq!(Anchor::Start.position(7, 21), 7);
+    }
+
+    #[test]
+    fn test_float_format() {
+        assert_eq!(FloatFormat::Shortest.format(0.1), "0.1");
+        assert_eq!(FloatFormat::Fixed(4).format(1.234567), "1.2346");
+        assert_eq!(FloatFormat::Fixed(2).format(-0.5), "-0.50");
+        assert_eq!(FloatFormat::Sci(3).format(1234.5), "1.234e+03");
+        assert_eq!(FloatFormat::Sci(2).format(0.00012), "1.20e-04");
+        assert_eq!(FloatFormat::Fixed(2).format(f64::NEG_INFINITY), "-inf");
+        assert_eq!("fixed:4".parse(), Ok(FloatFormat::Fixed(4)));
+        assert_eq!("sci:6".parse(), Ok(FloatFormat::Sci(6)));
+        assert_eq!("shortest".parse(), Ok(FloatFormat::Shortest));
+        assert!("fixed".parse::<FloatFormat>().is_err());
+        assert!("fixed:-1".parse::<FloatFormat>().is_err());
+        assert!("hex:2".parse::<FloatFormat>().is_err());
+        assert_eq!(
+            NaValue::Na.format(f64::NAN, FloatFormat::Fixed(2)),
+            Some("NA".to_string())
+        );
+    }
+
+    #[test]
+    fn test_stranded_path() {
+        let path = Path::new("results/out.bw");
+        assert_eq!(
+            stranded_path(path, Strand::Plus),
+            PathBuf::from("results/out.plus.bw")
+        );
+        assert_eq!(
+            stranded_path(path, Strand::Minus),
+            PathBuf::from("results/out.minus.bw")
+        );
+        assert_eq!(
+            stranded_path(Path::new("out"), Strand::Plus),
+            PathBuf::from("out.plus")
+        );
+    }
+
+    #[test]
+    fn test_forward_position() {
+        assert_eq!(Strand::Plus.forward_position(3, 10), 3);
+        assert_eq!(Strand::Minus.forward_position(0, 10), 9);
+        assert_eq!(Strand::Minus.forward_position(9, 10), 0);
+    }
+
+    #[test]
+    fn test_write_checkpoint() {
+        let dir = std::env::temp_dir().join("symcurve_test_write_checkpoint");
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.bw");
+        assert_eq!(checkpoint_path(&output), dir.join("out.bw.checkpoint"));
+        write_checkpoint(&output, &["chr1".to_string(), "chr2".to_string()]).unwrap();
+        assert_eq!(
+            fs::read_to_string(checkpoint_path(&output)).unwrap(),
+            "chr1\nchr2\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_output_path() {
+        let input = Path::new("data/genome.fa.gz");
+        assert_eq!(
+            resolve_output_path(Path::new("out.bw"), input, None),
+            PathBuf::from("out.bw")
+        );
+        assert_eq!(
+            resolve_output_path(Path::new("{input}.bw"), input, Some(Path::new("results"))),
+            PathBuf::from("results/genome.bw")
+        );
+        assert_eq!(
+            resolve_output_path(
+                Path::new("{input}.bw"),
+                Path::new("chr1"),
+                Some(Path::new("results"))
+            ),
+            PathBuf::from("results/chr1.bw")
+        );
+        assert_eq!(
+            resolve_output_path(
+                Path::new("{input}.bw"),
+                Path::new("-"),
+                Some(Path::new("results"))
+            ),
+            PathBuf::from("results/stdin.bw")
+        );
+    }
+
+    #[test]
+    fn test_check_overwrite() {
+        let dir = std::env::temp_dir().join("symcurve_test_check_overwrite");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.bw");
+        assert!(check_overwrite(&path, false).is_ok());
+        fs::write(&path, "").unwrap();
+        let err = check_overwrite(&path, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(err.to_string().contains("--force"));
+        assert!(check_overwrite(&path, true).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_output_format_from_path() {
+        assert_eq!(
+            OutputFormat::from_path(Path::new("out.bw")),
+            OutputFormat::BigWig
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("out")),
+            OutputFormat::BigWig
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("out.TSV")),
+            OutputFormat::Tsv
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("out.bedGraph")),
+            OutputFormat::BedGraph
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("dir/out.txt")),
+            OutputFormat::Tsv
+        );
+    }
+
+    #[test]
+    fn test_bigwig_limits() {
+        assert!(check_bigwig_length("chr1", 248_956_422).is_ok());
+        assert!(check_bigwig_length("chr1", BIGWIG_MAX_LENGTH).is_ok());
+        // a 5 Gb amphibian contig
+        let err = check_bigwig_length("scaffold_1", 5_000_000_000).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("scaffold_1 is 5000000000 bp"));
+        assert_eq!(bigwig_coordinate("chr1", 12345).unwrap(), 12345);
+        assert_eq!(
+            bigwig_coordinate("chr1", BIGWIG_MAX_LENGTH).unwrap(),
+            u32::MAX
+        );
+        assert!(bigwig_coordinate("scaffold_1", BIGWIG_MAX_LENGTH + 1).is_err());
+    }
+
+    #[test]
+    fn test_large_coordinates() {
+        let length = 5_000_000_000;
+        assert_eq!(Anchor::Center.position(4_999_999_000, 21), 4_999_999_021);
+        assert_eq!(Strand::Minus.forward_position(0, length), length - 1);
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        assert_eq!(natural_cmp("chr2", "chr10"), Ordering::Less);
+        assert_eq!(natural_cmp("chr10", "chrX"), Ordering::Less);
+        assert_eq!(natural_cmp("chr1", "chr1"), Ordering::Equal);
+        assert_eq!(natural_cmp("chr1", "chr1_random"), Ordering::Less);
+        assert_eq!(natural_cmp("chr01", "chr1"), Ordering::Greater);
+        assert_eq!(natural_cmp("scaffold_9", "scaffold_10"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_sort_contigs() {
+        let contigs = vec![
+            ("chrX", 156),
+            ("chr10", 133),
+            ("chrM", 16),
+            ("chr2", 242),
+            ("chr1", 248),
+            ("chrUn_1", 16),
+        ];
+        let names = |order: SortContigs| {
+            let mut sorted = contigs.clone();
+            order.sort(&mut sorted, |&(name, length)| (name, length));
+            sorted.into_iter().map(|(name, _)| name).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(SortContigs::Input),
+            vec!["chrX", "chr10", "chrM", "chr2", "chr1", "chrUn_1"]
+        );
+        assert_eq!(
+            names(SortContigs::Name),
+            vec!["chr1", "chr2", "chr10", "chrM", "chrUn_1", "chrX"]
+        );
+        assert_eq!(
+            names(SortContigs::Length),
+            vec!["chr1", "chr2", "chrX", "chr10", "chrM", "chrUn_1"]
+        );
+    }
+
+    #[test]
+    fn test_na_value() {
+        assert_eq!(
+            NaValue::default().format(f64::NAN, FloatFormat::Shortest),
+            Some("NaN".to_string())
+        );
+        assert_eq!(
+            NaValue::Na.format(f64::NAN, FloatFormat::Shortest),
+            Some("NA".to_string())
+        );
+        assert_eq!(
+            NaValue::Dot.format(f64::NAN, FloatFormat::Shortest),
+            Some(".".to_string())
+        );
+        assert_eq!(
+            NaValue::MinusOne.format(f64::NAN, FloatFormat::Shortest),
+            Some("-1".to_string())
+        );
+        assert_eq!(NaValue::Omit.format(f64::NAN, FloatFormat::Shortest), None);
+        assert_eq!(
+            NaValue::Omit.format(2.5, FloatFormat::Shortest),
+            Some("2.5".to_string())
+        );
+    }
+}
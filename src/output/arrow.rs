@@ -0,0 +1,571 @@
+//! Arrow IPC stream output.
+//!
+//! Values are written as an Arrow IPC stream of record batches with the columns `chrom` (utf8),
+//! `pos` (uint64, 1-based like the TSV outputs) and `value` (float64, null where no value could be
+//! computed). A Python or R process reading the stream from a pipe, e.g. with
+//! `pyarrow.ipc.open_stream(sys.stdin.buffer)`, gets columnar arrays without parsing any text.
+//!
+//! Arrow messages are described by flatbuffers. The crate has no Arrow or flatbuffers dependency,
+//! so the few message tables needed are built by the small [`FlatBuilder`] below, following the
+//! IPC format specification: each message is a continuation marker, the length of the metadata,
+//! the flatbuffer metadata padded to 8 bytes, then the message body.
+
+use std::io::{self, Write};
+
+/// The number of rows per record batch.
+pub const BATCH_ROWS: usize = 65_536;
+
+/// The marker that starts every message, and with a zero length ends the stream.
+const CONTINUATION: u32 = 0xFFFF_FFFF;
+
+/// `MetadataVersion::V5`.
+const METADATA_V5: i16 = 4;
+
+/// `MessageHeader::Schema`.
+const HEADER_SCHEMA: u8 = 1;
+
+/// `MessageHeader::RecordBatch`.
+const HEADER_RECORD_BATCH: u8 = 3;
+
+/// `Type::Int`.
+const TYPE_INT: u8 = 2;
+
+/// `Type::FloatingPoint`.
+const TYPE_FLOATING_POINT: u8 = 3;
+
+/// `Type::Utf8`.
+const TYPE_UTF8: u8 = 5;
+
+/// `Precision::DOUBLE`.
+const PRECISION_DOUBLE: i16 = 2;
+
+/// Writes values as an Arrow IPC stream.
+///
+/// The schema is written on construction; call [`ArrowWriter::finish`] to end the stream.
+///
+/// # Fields
+///
+/// * `writer`: Where the stream is written.
+/// * `batches`: The number of record batches written.
+pub struct ArrowWriter<W: Write> {
+    writer: W,
+    batches: usize,
+}
+
+impl<W: Write> ArrowWriter<W> {
+    /// Starts a stream, writing its schema.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        write_message(&mut writer, &schema_message(), &[])?;
+        Ok(Self { writer, batches: 0 })
+    }
+
+    /// The number of record batches written.
+    pub fn batches(&self) -> usize {
+        self.batches
+    }
+
+    /// Writes the values of one sequence as record batches of up to `BATCH_ROWS` rows.
+    ///
+    /// # Arguments
+    ///
+    /// * `chrom` - The sequence name written in the `chrom` column.
+    /// * `offset` - The 0-based position of the first value within `chrom`.
+    /// * `values` - One value per position; `NaN` is written as null.
+    pub fn write_chrom(&mut self, chrom: &str, offset: u64, values: &[f64]) -> io::Result<()> {
+        for (i, chunk) in values.chunks(BATCH_ROWS).enumerate() {
+            let first = offset + (i * BATCH_ROWS) as u64 + 1;
+            self.write_batch(chrom, first, chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Helper to `write_chrom()` that writes one record batch.
+    fn write_batch(&mut self, chrom: &str, first: u64, values: &[f64]) -> io::Result<()> {
+        let rows = values.len();
+        if rows * chrom.len() > i32::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: sequence name too long for an Arrow batch", chrom),
+            ));
+        }
+        let offsets_bytes: Vec<u8> = (0..=rows)
+            .flat_map(|i| ((i * chrom.len()) as i32).to_le_bytes())
+            .collect();
+        let chrom_bytes = chrom.as_bytes().repeat(rows);
+        let pos_bytes: Vec<u8> = (0..rows as u64)
+            .flat_map(|i| (first + i).to_le_bytes())
+            .collect();
+        let value_bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let nulls = values.iter().filter(|v| v.is_nan()).count();
+        let validity = if nulls == 0 {
+            Vec::new()
+        } else {
+            validity_bitmap(values)
+        };
+        // validity, offsets and data for chrom; validity and data for pos and value
+        let buffers: [&[u8]; 7] = [
+            &[],
+            &offsets_bytes,
+            &chrom_bytes,
+            &[],
+            &pos_bytes,
+            &validity,
+            &value_bytes,
+        ];
+        let mut body = Vec::new();
+        let mut layout = Vec::with_capacity(buffers.len());
+        for buffer in buffers {
+            layout.push((body.len() as i64, buffer.len() as i64));
+            body.extend_from_slice(buffer);
+            body.resize(padded(body.len()), 0);
+        }
+        let nodes = [
+            (rows as i64, 0),
+            (rows as i64, 0),
+            (rows as i64, nulls as i64),
+        ];
+        let message = record_batch_message(rows as i64, &nodes, &layout, body.len() as i64);
+        write_message(&mut self.writer, &message, &body)?;
+        self.batches += 1;
+        Ok(())
+    }
+
+    /// Ends the stream, returning the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.write_all(&CONTINUATION.to_le_bytes())?;
+        self.writer.write_all(&0u32.to_le_bytes())?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Rounds a length up to a multiple of 8, the alignment Arrow requires of buffers and metadata.
+fn padded(len: usize) -> usize {
+    len.div_ceil(8) * 8
+}
+
+/// A validity bitmap: one bit per value, least significant first, set where the value is not
+/// `NaN`.
+fn validity_bitmap(values: &[f64]) -> Vec<u8> {
+    let mut bitmap = vec![0u8; values.len().div_ceil(8)];
+    for (i, value) in values.iter().enumerate() {
+        if !value.is_nan() {
+            bitmap[i / 8] |= 1 << (i % 8);
+        }
+    }
+    bitmap
+}
+
+/// Writes one encapsulated message: the continuation marker, the padded metadata length, the
+/// metadata and the body.
+fn write_message<W: Write>(writer: &mut W, metadata: &[u8], body: &[u8]) -> io::Result<()> {
+    let mut metadata = metadata.to_vec();
+    metadata.resize(padded(metadata.len()), 0);
+    writer.write_all(&CONTINUATION.to_le_bytes())?;
+    writer.write_all(&(metadata.len() as u32).to_le_bytes())?;
+    writer.write_all(&metadata)?;
+    writer.write_all(body)
+}
+
+/// The flatbuffer `Message` holding the schema.
+fn schema_message() -> Vec<u8> {
+    let mut fb = FlatBuilder::default();
+    let columns = [
+        ("chrom", TYPE_UTF8),
+        ("pos", TYPE_INT),
+        ("value", TYPE_FLOATING_POINT),
+    ];
+    let mut fields = Vec::with_capacity(columns.len());
+    for (name, type_id) in columns {
+        let type_table = match type_id {
+            TYPE_INT => {
+                fb.start_table();
+                fb.add_i32(0, 64);
+                fb.add_u8(1, 0);
+                fb.end_table()
+            }
+            TYPE_FLOATING_POINT => {
+                fb.start_table();
+                fb.add_i16(0, PRECISION_DOUBLE);
+                fb.end_table()
+            }
+            _ => {
+                fb.start_table();
+                fb.end_table()
+            }
+        };
+        let name = fb.create_string(name);
+        let children = fb.create_offset_vector(&[]);
+        fb.start_table();
+        fb.add_offset(0, name);
+        fb.add_u8(1, 1);
+        fb.add_u8(2, type_id);
+        fb.add_offset(3, type_table);
+        fb.add_offset(5, children);
+        fields.push(fb.end_table());
+    }
+    let fields = fb.create_offset_vector(&fields);
+    fb.start_table();
+    // endianness 0 is little-endian
+    fb.add_i16(0, 0);
+    fb.add_offset(1, fields);
+    let schema = fb.end_table();
+    message(fb, HEADER_SCHEMA, schema, 0)
+}
+
+/// The flatbuffer `Message` describing a record batch.
+///
+/// # Arguments
+///
+/// * `rows` - The number of rows.
+/// * `nodes` - The length and null count of each column.
+/// * `buffers` - The offset and length of each buffer in the body.
+/// * `body_length` - The length of the body, padding included.
+fn record_batch_message(
+    rows: i64,
+    nodes: &[(i64, i64)],
+    buffers: &[(i64, i64)],
+    body_length: i64,
+) -> Vec<u8> {
+    let mut fb = FlatBuilder::default();
+    let buffers = fb.create_struct_vector(buffers);
+    let nodes = fb.create_struct_vector(nodes);
+    fb.start_table();
+    fb.add_i64(0, rows);
+    fb.add_offset(1, nodes);
+    fb.add_offset(2, buffers);
+    let batch = fb.end_table();
+    message(fb, HEADER_RECORD_BATCH, batch, body_length)
+}
+
+/// Helper to `schema_message()` and `record_batch_message()` that wraps a header in a `Message`.
+fn message(mut fb: FlatBuilder, header_type: u8, header: usize, body_length: i64) -> Vec<u8> {
+    fb.start_table();
+    fb.add_i16(0, METADATA_V5);
+    fb.add_u8(1, header_type);
+    fb.add_offset(2, header);
+    fb.add_i64(3, body_length);
+    let root = fb.end_table();
+    fb.finish(root)
+}
+
+/// A minimal flatbuffer builder, enough for the Arrow message tables.
+///
+/// Flatbuffers are built back to front, children before the objects that refer to them, so
+/// positions are measured from the end of the buffer. Tables are written with every field given
+/// explicitly and their vtable right before them.
+///
+/// # Fields
+///
+/// * `buf`: The buffer built so far; new data is prepended.
+/// * `min_align`: The largest alignment of anything written, which the finished buffer keeps.
+/// * `table_end`: Where the table being built ends, while one is.
+/// * `table_fields`: The slot and position of each field of the table being built.
+#[derive(Default)]
+struct FlatBuilder {
+    buf: Vec<u8>,
+    min_align: usize,
+    table_end: usize,
+    table_fields: Vec<(usize, usize)>,
+}
+
+impl FlatBuilder {
+    /// The current position, measured from the end of the buffer.
+    fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Prepends bytes.
+    fn prepend(&mut self, bytes: &[u8]) {
+        self.buf.splice(0..0, bytes.iter().copied());
+    }
+
+    /// Pads so that, after `additional` more bytes, a value of `size` bytes is aligned.
+    fn prep(&mut self, size: usize, additional: usize) {
+        self.min_align = self.min_align.max(size);
+        let pad = (size - (self.len() + additional) % size) % size;
+        self.prepend(&vec![0; pad]);
+    }
+
+    /// Prepends an offset to the object at `target`.
+    fn push_offset(&mut self, target: usize) {
+        self.prep(4, 0);
+        let offset = (self.len() + 4 - target) as u32;
+        self.prepend(&offset.to_le_bytes());
+    }
+
+    /// Writes a string, returning its position.
+    fn create_string(&mut self, s: &str) -> usize {
+        self.prep(4, s.len() + 1);
+        self.prepend(&[0]);
+        self.prepend(s.as_bytes());
+        self.prepend(&(s.len() as u32).to_le_bytes());
+        self.len()
+    }
+
+    /// Writes a vector of offsets to objects, returning its position.
+    fn create_offset_vector(&mut self, targets: &[usize]) -> usize {
+        self.prep(4, 4 * targets.len());
+        for &target in targets.iter().rev() {
+            self.push_offset(target);
+        }
+        self.prepend(&(targets.len() as u32).to_le_bytes());
+        self.len()
+    }
+
+    /// Writes a vector of structs of two `i64`s, returning its position.
+    fn create_struct_vector(&mut self, items: &[(i64, i64)]) -> usize {
+        self.prep(4, 16 * items.len());
+        self.prep(8, 16 * items.len());
+        for &(a, b) in items.iter().rev() {
+            self.prepend(&b.to_le_bytes());
+            self.prepend(&a.to_le_bytes());
+        }
+        self.prepend(&(items.len() as u32).to_le_bytes());
+        self.len()
+    }
+
+    /// Starts a table.
+    fn start_table(&mut self) {
+        self.table_end = self.len();
+        self.table_fields.clear();
+    }
+
+    /// Helper to the `add_` functions that prepends a scalar field.
+    fn add_scalar(&mut self, slot: usize, bytes: &[u8]) {
+        self.prep(bytes.len(), 0);
+        self.prepend(bytes);
+        self.table_fields.push((slot, self.len()));
+    }
+
+    fn add_u8(&mut self, slot: usize, value: u8) {
+        self.add_scalar(slot, &[value]);
+    }
+
+    fn add_i16(&mut self, slot: usize, value: i16) {
+        self.add_scalar(slot, &value.to_le_bytes());
+    }
+
+    fn add_i32(&mut self, slot: usize, value: i32) {
+        self.add_scalar(slot, &value.to_le_bytes());
+    }
+
+    fn add_i64(&mut self, slot: usize, value: i64) {
+        self.add_scalar(slot, &value.to_le_bytes());
+    }
+
+    /// Adds a field holding an offset to the object at `target`.
+    fn add_offset(&mut self, slot: usize, target: usize) {
+        self.push_offset(target);
+        self.table_fields.push((slot, self.len()));
+    }
+
+    /// Ends the table, writing its vtable, and returns its position.
+    fn end_table(&mut self) -> usize {
+        // the table starts with the offset to its vtable, patched below
+        self.prep(4, 0);
+        self.prepend(&0i32.to_le_bytes());
+        let table = self.len();
+        let slots = self
+            .table_fields
+            .iter()
+            .map(|&(slot, _)| slot + 1)
+            .max()
+            .unwrap_or(0);
+        let mut vtable = vec![0u16; 2 + slots];
+        vtable[0] = (2 * vtable.len()) as u16;
+        vtable[1] = (table - self.table_end) as u16;
+        for &(slot, position) in &self.table_fields {
+            vtable[2 + slot] = (table - position) as u16;
+        }
+        let bytes: Vec<u8> = vtable.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.prepend(&bytes);
+        // the vtable sits right before the table, at a positive offset back from it
+        let vtable_offset = (self.len() - table) as i32;
+        let at = self.len() - table;
+        self.buf[at..at + 4].copy_from_slice(&vtable_offset.to_le_bytes());
+        table
+    }
+
+    /// Writes the offset to the root table and returns the finished buffer.
+    fn finish(mut self, root: usize) -> Vec<u8> {
+        let align = self.min_align.max(4);
+        self.prep(align, 4);
+        self.push_offset(root);
+        self.buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Just enough flatbuffer reading to check what was written.
+    struct Table<'a> {
+        buf: &'a [u8],
+        pos: usize,
+    }
+
+    impl<'a> Table<'a> {
+        fn root(buf: &'a [u8]) -> Self {
+            Self {
+                buf,
+                pos: u32_at(buf, 0) as usize,
+            }
+        }
+
+        fn field(&self, slot: usize) -> Option<usize> {
+            let vtable = self.pos
+                - i32::from_le_bytes(self.buf[self.pos..self.pos + 4].try_into().unwrap()) as usize;
+            let vtable_len = u16_at(self.buf, vtable) as usize;
+            if 4 + 2 * slot >= vtable_len {
+                return None;
+            }
+            match u16_at(self.buf, vtable + 4 + 2 * slot) {
+                0 => None,
+                offset => Some(self.pos + offset as usize),
+            }
+        }
+
+        fn scalar<const N: usize>(&self, slot: usize) -> [u8; N] {
+            let at = self.field(slot).unwrap();
+            self.buf[at..at + N].try_into().unwrap()
+        }
+
+        fn follow(&self, slot: usize) -> usize {
+            let at = self.field(slot).unwrap();
+            at + u32_at(self.buf, at) as usize
+        }
+
+        fn table(&self, slot: usize) -> Table<'a> {
+            Table {
+                buf: self.buf,
+                pos: self.follow(slot),
+            }
+        }
+
+        fn vector(&self, slot: usize) -> (usize, usize) {
+            let at = self.follow(slot);
+            (u32_at(self.buf, at) as usize, at + 4)
+        }
+
+        fn string(&self, slot: usize) -> &'a str {
+            let (len, at) = self.vector(slot);
+            std::str::from_utf8(&self.buf[at..at + len]).unwrap()
+        }
+    }
+
+    fn u16_at(buf: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(buf[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(buf: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(buf[at..at + 4].try_into().unwrap())
+    }
+
+    fn i64_at(buf: &[u8], at: usize) -> i64 {
+        i64::from_le_bytes(buf[at..at + 8].try_into().unwrap())
+    }
+
+    /// Splits a stream into its messages' metadata and bodies.
+    fn messages(stream: &[u8]) -> Vec<(&[u8], &[u8])> {
+        let mut messages = Vec::new();
+        let mut at = 0;
+        loop {
+            assert_eq!(u32_at(stream, at), CONTINUATION);
+            let len = u32_at(stream, at + 4) as usize;
+            at += 8;
+            if len == 0 {
+                assert_eq!(at, stream.len());
+                return messages;
+            }
+            assert_eq!(len % 8, 0);
+            let metadata = &stream[at..at + len];
+            let body_len = i64::from_le_bytes(Table::root(metadata).scalar(3)) as usize;
+            at += len;
+            messages.push((metadata, &stream[at..at + body_len]));
+            at += body_len;
+        }
+    }
+
+    #[test]
+    fn test_schema() {
+        let stream = ArrowWriter::new(Vec::new()).unwrap().finish().unwrap();
+        let messages = messages(&stream);
+        assert_eq!(messages.len(), 1);
+        let message = Table::root(messages[0].0);
+        assert_eq!(i16::from_le_bytes(message.scalar(0)), METADATA_V5);
+        assert_eq!(message.scalar::<1>(1), [HEADER_SCHEMA]);
+        let schema = message.table(2);
+        let (count, at) = schema.vector(1);
+        let fields: Vec<(String, u8)> = (0..count)
+            .map(|i| {
+                let slot = at + 4 * i;
+                let field = Table {
+                    buf: messages[0].0,
+                    pos: slot + u32_at(messages[0].0, slot) as usize,
+                };
+                assert_eq!(field.vector(5).0, 0);
+                (field.string(0).to_string(), field.scalar::<1>(2)[0])
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("chrom".to_string(), TYPE_UTF8),
+                ("pos".to_string(), TYPE_INT),
+                ("value".to_string(), TYPE_FLOATING_POINT)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_batches() {
+        let mut writer = ArrowWriter::new(Vec::new()).unwrap();
+        let values: Vec<f64> = (0..BATCH_ROWS + 3)
+            .map(|i| if i == 1 { f64::NAN } else { i as f64 })
+            .collect();
+        writer.write_chrom("chr2", 100, &values).unwrap();
+        assert_eq!(writer.batches(), 2);
+        let stream = writer.finish().unwrap();
+        let messages = messages(&stream);
+        assert_eq!(messages.len(), 3);
+        let (metadata, body) = messages[2];
+        let message = Table::root(metadata);
+        assert_eq!(message.scalar::<1>(1), [HEADER_RECORD_BATCH]);
+        let batch = message.table(2);
+        assert_eq!(i64::from_le_bytes(batch.scalar(0)), 3);
+        let (count, nodes) = batch.vector(1);
+        assert_eq!(count, 3);
+        assert_eq!(nodes % 8, 0);
+        let (count, buffers) = batch.vector(2);
+        assert_eq!(count, 7);
+        let buffer = |i: usize| {
+            let offset = i64_at(metadata, buffers + 16 * i) as usize;
+            let len = i64_at(metadata, buffers + 16 * i + 8) as usize;
+            assert_eq!(offset % 8, 0);
+            &body[offset..offset + len]
+        };
+        assert_eq!(buffer(2), b"chr2chr2chr2");
+        assert_eq!(i64_at(buffer(4), 0), 100 + BATCH_ROWS as i64 + 1);
+        assert!(buffer(5).is_empty());
+        assert_eq!(
+            i64_at(buffer(6), 16).to_le_bytes(),
+            ((BATCH_ROWS + 2) as f64).to_le_bytes()
+        );
+        // the first batch holds the null
+        let (metadata, body) = messages[1];
+        let batch = Table::root(metadata).table(2);
+        let (_, nodes) = batch.vector(1);
+        assert_eq!(i64_at(metadata, nodes + 32 + 8), 1);
+        let (_, buffers) = batch.vector(2);
+        let offset = i64_at(metadata, buffers + 16 * 5) as usize;
+        assert_eq!(body[offset], 0b1111_1101);
+    }
+
+    #[test]
+    fn test_validity_bitmap() {
+        assert_eq!(validity_bitmap(&[1.0, f64::NAN, 2.0]), [0b101]);
+        assert_eq!(validity_bitmap(&[0.0; 9]), [0xff, 0x01]);
+    }
+}
@@ -0,0 +1,132 @@
+//! Atomic output files.
+//!
+//! Outputs are written to a temporary file in the destination directory and renamed over the
+//! final path only once they are complete. A rename within one directory is atomic, so a crashed
+//! or killed job never leaves a truncated file at the output path for a downstream pipeline to
+//! mistake for a finished result.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// A file being written that only appears at its final path once committed.
+///
+/// If an `AtomicFile` is dropped without [`AtomicFile::commit`] being called, the temporary file
+/// is removed and the final path is left untouched.
+///
+/// # Fields
+///
+/// * `writer`: Buffered writer over the temporary file. `None` once committed.
+/// * `tmp_path`: Where the file is written while in progress.
+/// * `path`: Where the file is moved to on commit.
+pub struct AtomicFile {
+    writer: Option<BufWriter<File>>,
+    tmp_path: PathBuf,
+    path: PathBuf,
+}
+
+impl AtomicFile {
+    /// Creates the temporary file for an output at `path`.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let tmp_path = temp_path(&path);
+        let file = File::create(&tmp_path)?;
+        Ok(Self {
+            writer: Some(BufWriter::new(file)),
+            tmp_path,
+            path,
+        })
+    }
+
+    /// The final path of the output.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path the output is written to while in progress.
+    pub fn tmp_path(&self) -> &Path {
+        &self.tmp_path
+    }
+
+    /// Flushes and syncs the temporary file, then renames it to the final path.
+    pub fn commit(mut self) -> io::Result<()> {
+        let writer = self.writer.take().expect("writer is present until commit");
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&self.tmp_path, &self.path)
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.as_mut().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.as_mut().unwrap().flush()
+    }
+}
+
+impl Seek for AtomicFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.writer.as_mut().unwrap().seek(pos)
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.writer.take().is_some() {
+            let _ = fs::remove_file(&self.tmp_path);
+        }
+    }
+}
+
+/// Derives a hidden temporary path next to `path`, unique to this process.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}.tmp", name, process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_commit_renames() {
+        let dir = test_dir("symcurve_test_atomic_commit");
+        let path = dir.join("out.tsv");
+        let mut file = AtomicFile::create(&path).unwrap();
+        let tmp_path = file.tmp_path().to_path_buf();
+        assert_eq!(tmp_path.parent(), Some(dir.as_path()));
+        file.write_all(b"chr1\t1\t0.5\n").unwrap();
+        assert!(tmp_path.exists());
+        assert!(!path.exists());
+        file.commit().unwrap();
+        assert!(!tmp_path.exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "chr1\t1\t0.5\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_drop_discards() {
+        let dir = test_dir("symcurve_test_atomic_drop");
+        let path = dir.join("out.tsv");
+        fs::write(&path, "previous\n").unwrap();
+        let tmp_path = {
+            let mut file = AtomicFile::create(&path).unwrap();
+            file.write_all(b"partial").unwrap();
+            file.tmp_path().to_path_buf()
+        };
+        assert!(!tmp_path.exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "previous\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,363 @@
+//! BED interval output.
+//!
+//! BED files use 0-based, half-open coordinates, so an interval over the first ten bases of a
+//! sequence is written as `chrom 0 10`.
+//!
+//! Interval outputs can also carry a name and a score column: `--bed-name` templates the name
+//! from the interval (e.g. `peak_{chrom}_{index}`) and `--bed-score` picks the statistic of the
+//! interval's values written as its score.
+
+use crate::curve::symmetry::Axis;
+use crate::output::FloatFormat;
+use clap::ValueEnum;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::str::FromStr;
+
+/// The placeholders a name template can use.
+pub const NAME_PLACEHOLDERS: [&str; 4] = ["chrom", "start", "end", "index"];
+
+/// A template for the BED name column.
+///
+/// `{chrom}`, `{start}` and `{end}` are replaced by the interval's coordinates as written, and
+/// `{index}` by its 1-based number in the file.
+///
+/// # Fields
+///
+/// * `template`: The template text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NameTemplate {
+    template: String,
+}
+
+impl NameTemplate {
+    /// Fills in the template for one interval.
+    pub fn render(&self, chrom: &str, start: u64, end: u64, index: u64) -> String {
+        self.template
+            .replace("{chrom}", chrom)
+            .replace("{start}", &start.to_string())
+            .replace("{end}", &end.to_string())
+            .replace("{index}", &index.to_string())
+    }
+}
+
+impl FromStr for NameTemplate {
+    type Err = String;
+
+    /// Checks that the template only uses known placeholders and has no whitespace, which would
+    /// split the name column.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s.contains(char::is_whitespace) {
+            return Err("a BED name template must be non-empty with no whitespace".to_owned());
+        }
+        let mut rest = s;
+        while let Some(open) = rest.find('{') {
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed '{{' in BED name template '{}'", s))?;
+            let placeholder = &rest[open + 1..open + close];
+            if !NAME_PLACEHOLDERS.contains(&placeholder) {
+                return Err(format!(
+                    "unknown placeholder {{{}}} in BED name template; expected one of {}",
+                    placeholder,
+                    NAME_PLACEHOLDERS.map(|p| format!("{{{}}}", p)).join(", ")
+                ));
+            }
+            rest = &rest[open + close + 1..];
+        }
+        Ok(Self {
+            template: s.to_owned(),
+        })
+    }
+}
+
+/// The statistic of an interval's values written to the BED score column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ScoreStat {
+    /// the largest value in the interval
+    Max,
+    /// the mean value over the interval
+    Mean,
+    /// -log10 of the empirical p-value of the interval's largest value among all values
+    #[value(name = "neglog10p")]
+    NegLog10P,
+}
+
+impl ScoreStat {
+    /// Computes the score of one interval.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - The values in the interval. `NaN` values are left out.
+    /// * `null` - The values the p-value is computed against, for `NegLog10P`.
+    pub fn score(&self, values: &[f64], null: &EmpiricalNull) -> f64 {
+        let values = values.iter().copied().filter(|v| !v.is_nan());
+        match self {
+            ScoreStat::Max => values.fold(f64::NAN, f64::max),
+            ScoreStat::Mean => {
+                let (sum, n) = values.fold((0.0, 0), |(sum, n), v| (sum + v, n + 1));
+                sum / n as f64
+            }
+            ScoreStat::NegLog10P => -null.p_value(values.fold(f64::NAN, f64::max)).log10(),
+        }
+    }
+}
+
+/// An empirical null distribution for p-values.
+///
+/// # Fields
+///
+/// * `sorted`: The non-`NaN` values, in ascending order.
+#[derive(Clone, Debug, Default)]
+pub struct EmpiricalNull {
+    sorted: Vec<f64>,
+}
+
+impl EmpiricalNull {
+    /// Constructor for `EmpiricalNull`. `NaN` values are left out.
+    pub fn new<I: IntoIterator<Item = f64>>(values: I) -> Self {
+        let mut sorted: Vec<f64> = values.into_iter().filter(|v| !v.is_nan()).collect();
+        sorted.sort_by(f64::total_cmp);
+        Self { sorted }
+    }
+
+    /// The fraction of values at least as large as `value`, with the usual `+1` correction so
+    /// that it is never 0: `(count + 1) / (n + 1)`.
+    pub fn p_value(&self, value: f64) -> f64 {
+        let below = self.sorted.partition_point(|&v| v < value);
+        let at_least = (self.sorted.len() - below) as f64;
+        (at_least + 1.0) / (self.sorted.len() as f64 + 1.0)
+    }
+}
+
+/// The optional name and score columns of interval output.
+///
+/// # Fields
+///
+/// * `name`: The name template, or `None` for no name column (`.` if a score follows).
+/// * `score`: The score statistic, or `None` for no score column.
+/// * `float`: How scores are written.
+#[derive(Clone, Debug, Default)]
+pub struct BedFields {
+    pub name: Option<NameTemplate>,
+    pub score: Option<ScoreStat>,
+    pub float: FloatFormat,
+}
+
+/// Writes intervals of one sequence as three-column BED rows.
+///
+/// # Arguments
+///
+/// * `writer` - Where the rows are written.
+/// * `chrom` - The sequence name written in the first column.
+/// * `offset` - The 0-based position within `chrom` that the interval coordinates are relative to.
+/// * `intervals` - 0-based, half-open intervals relative to `offset`.
+pub fn write_intervals<W, I>(
+    writer: &mut W,
+    chrom: &str,
+    offset: u64,
+    intervals: I,
+) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = Range<usize>>,
+{
+    for interval in intervals {
+        writeln!(
+            writer,
+            "{}\t{}\t{}",
+            chrom,
+            offset + interval.start as u64,
+            offset + interval.end as u64
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes intervals of one sequence as BED rows with name and score columns.
+///
+/// # Arguments
+///
+/// * `writer` - Where the rows are written.
+/// * `chrom` - The sequence name written in the first column.
+/// * `offset` - The 0-based position within `chrom` that the interval coordinates are relative to.
+/// * `intervals` - 0-based, half-open intervals relative to `offset`, indexing `values`.
+/// * `values` - The values the intervals were called from.
+/// * `fields` - Which extra columns to write.
+/// * `null` - The values p-value scores are computed against.
+/// * `index` - The number of intervals written to the file so far; advanced for each row.
+#[allow(clippy::too_many_arguments)]
+pub fn write_named_intervals<W, I>(
+    writer: &mut W,
+    chrom: &str,
+    offset: u64,
+    intervals: I,
+    values: &[f64],
+    fields: &BedFields,
+    null: &EmpiricalNull,
+    index: &mut u64,
+) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = Range<usize>>,
+{
+    for interval in intervals {
+        *index += 1;
+        let start = offset + interval.start as u64;
+        let end = offset + interval.end as u64;
+        write!(writer, "{}\t{}\t{}", chrom, start, end)?;
+        match &fields.name {
+            Some(name) => write!(writer, "\t{}", name.render(chrom, start, end, *index))?,
+            None if fields.score.is_some() => write!(writer, "\t.")?,
+            None => {}
+        }
+        if let Some(score) = fields.score {
+            let value = score.score(&values[interval], null);
+            write!(writer, "\t{}", fields.float.format(value))?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Writes inferred symmetry axes as BED rows of the base or two bases each axis lies on, with the
+/// window's SymCurv score in the score column.
+///
+/// # Arguments
+///
+/// * `writer` - Where the rows are written.
+/// * `chrom` - The sequence name written in the first column.
+/// * `axes` - Pairs of the 0-based start of each window within `chrom` and its axis.
+/// * `float` - How scores are written.
+pub fn write_axes<W, I>(writer: &mut W, chrom: &str, axes: I, float: FloatFormat) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = (u64, Axis)>,
+{
+    for (window_start, axis) in axes {
+        let (start, end) = axis.bases();
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t.\t{}",
+            chrom,
+            window_start + start,
+            window_start + end,
+            float.format(axis.score)
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_intervals() {
+        let mut out = Vec::new();
+        write_intervals(&mut out, "chr1", 100, vec![0..3, 7..8]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "chr1\t100\t103\nchr1\t107\t108\n"
+        );
+    }
+
+    #[test]
+    fn test_name_template() {
+        let template: NameTemplate = "peak_{chrom}_{index}".parse().unwrap();
+        assert_eq!(template.render("chr2", 10, 20, 7), "peak_chr2_7");
+        let template: NameTemplate = "{chrom}:{start}-{end}".parse().unwrap();
+        assert_eq!(template.render("chr2", 10, 20, 7), "chr2:10-20");
+        assert!("peak_{id}".parse::<NameTemplate>().is_err());
+        assert!("peak_{chrom".parse::<NameTemplate>().is_err());
+        assert!("peak {index}".parse::<NameTemplate>().is_err());
+    }
+
+    #[test]
+    fn test_score_stat() {
+        let null = EmpiricalNull::new(vec![1.0, 2.0, 3.0, 4.0, f64::NAN]);
+        let values = [2.0, f64::NAN, 4.0];
+        assert_eq!(ScoreStat::Max.score(&values, &null), 4.0);
+        assert_eq!(ScoreStat::Mean.score(&values, &null), 3.0);
+        // one of four values is at least 4, so p = 2 / 5
+        assert_eq!(null.p_value(4.0), 0.4);
+        assert_eq!(null.p_value(10.0), 0.2);
+        assert_eq!(
+            ScoreStat::NegLog10P.score(&values, &null),
+            -(0.4f64).log10()
+        );
+    }
+
+    #[test]
+    fn test_write_named_intervals() {
+        let values = [0.0, 5.0, 6.0, 0.0, 7.0];
+        let null = EmpiricalNull::new(values);
+        let fields = BedFields {
+            name: Some("peak_{chrom}_{index}".parse().unwrap()),
+            score: Some(ScoreStat::Mean),
+            float: FloatFormat::Fixed(1),
+        };
+        let mut out = Vec::new();
+        let mut index = 0;
+        write_named_intervals(
+            &mut out,
+            "chr1",
+            100,
+            vec![1..3, 4..5],
+            &values,
+            &fields,
+            &null,
+            &mut index,
+        )
+        .unwrap();
+        assert_eq!(index, 2);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "chr1\t101\t103\tpeak_chr1_1\t5.5\nchr1\t104\t105\tpeak_chr1_2\t7.0\n"
+        );
+        let fields = BedFields {
+            name: None,
+            score: Some(ScoreStat::Max),
+            float: FloatFormat::Shortest,
+        };
+        let mut out = Vec::new();
+        write_named_intervals(
+            &mut out,
+            "chr1",
+            0,
+            std::iter::once(1..3),
+            &values,
+            &fields,
+            &null,
+            &mut index,
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "chr1\t1\t3\t.\t6\n");
+    }
+
+    #[test]
+    fn test_write_axes() {
+        let axes = vec![
+            (
+                100,
+                Axis {
+                    position: 5.0,
+                    score: 0.75,
+                },
+            ),
+            (
+                101,
+                Axis {
+                    position: 3.5,
+                    score: 1.0,
+                },
+            ),
+        ];
+        let mut out = Vec::new();
+        write_axes(&mut out, "chr1", axes, FloatFormat::Shortest).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "chr1\t105\t106\t.\t0.75\nchr1\t104\t106\t.\t1\n"
+        );
+    }
+}
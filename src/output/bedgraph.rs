@@ -0,0 +1,225 @@
+//! bedGraph text output.
+//!
+//! bedGraph lines are `chrom`, 0-based start, exclusive end and value, tab-separated. Adjacent
+//! bases whose values are written the same are merged into one line, which on smooth curvature
+//! tracks, or with few decimal places, makes the file several times smaller than one line per
+//! base. Positions without a value are left out, as bedGraph has no missing value.
+
+use crate::output::track::TrackLine;
+use crate::output::FloatFormat;
+use crate::provenance::Provenance;
+use std::io::{self, Write};
+
+/// A line being extended while the next bases have the same value.
+///
+/// # Fields
+///
+/// * `start`: The 0-based start of the run.
+/// * `end`: The end of the run, exclusive.
+/// * `text`: The value as written.
+struct Run {
+    start: u64,
+    end: u64,
+    text: String,
+}
+
+/// Streams per-base values into bedGraph lines.
+///
+/// Values are pushed one base at a time, sequence after sequence, as with
+/// [`BigWigWriter`](crate::output::bigwig::BigWigWriter). Call [`BedGraphWriter::finish`] to
+/// write the last line.
+///
+/// ```
+/// use symcurve::output::bedgraph::BedGraphWriter;
+/// use symcurve::output::FloatFormat;
+///
+/// let mut writer = BedGraphWriter::new(Vec::new(), FloatFormat::Shortest);
+/// writer.start_chrom("chr1").unwrap();
+/// writer.push_missing(5).unwrap();
+/// for value in [0.5, 0.5, 0.25] {
+///     writer.push(value).unwrap();
+/// }
+/// let text = String::from_utf8(writer.finish().unwrap()).unwrap();
+/// assert_eq!(text, "chr1\t5\t7\t0.5\nchr1\t7\t8\t0.25\n");
+/// ```
+///
+/// # Fields
+///
+/// * `inner`: Where the lines are written.
+/// * `float`: How values are written.
+/// * `chrom`: The current sequence.
+/// * `position`: The number of bases pushed for the current sequence.
+/// * `run`: The line being extended, if any.
+/// * `text`: The text of the value being pushed.
+pub struct BedGraphWriter<W: Write> {
+    inner: W,
+    float: FloatFormat,
+    chrom: Option<String>,
+    position: u64,
+    run: Option<Run>,
+    text: String,
+}
+
+impl<W: Write> BedGraphWriter<W> {
+    /// Constructor for `BedGraphWriter`.
+    pub fn new(inner: W, float: FloatFormat) -> Self {
+        Self {
+            inner,
+            float,
+            chrom: None,
+            position: 0,
+            run: None,
+            text: String::new(),
+        }
+    }
+
+    /// Writes a track line, which should come before any value.
+    pub fn write_track_line(&mut self, track: &TrackLine) -> io::Result<()> {
+        writeln!(self.inner, "{}", track.format("bedGraph"))
+    }
+
+    /// Writes the provenance of the run as `#` comment lines, which should come before any value.
+    pub fn write_provenance(&mut self, provenance: &Provenance) -> io::Result<()> {
+        provenance.write_comments(&mut self.inner, "#")
+    }
+
+    /// Ends the current sequence, if any, and starts the next.
+    pub fn start_chrom(&mut self, name: &str) -> io::Result<()> {
+        self.end_run()?;
+        self.chrom = Some(name.to_string());
+        self.position = 0;
+        Ok(())
+    }
+
+    /// Adds the value of the next base of the current sequence; a `NaN` value leaves the base
+    /// without one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::ErrorKind::InvalidInput` error if no sequence was started, or any error
+    /// from writing.
+    pub fn push(&mut self, value: f64) -> io::Result<()> {
+        if self.chrom.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "bedGraph value pushed before any sequence was started",
+            ));
+        }
+        let pos = self.position;
+        self.position += 1;
+        if value.is_nan() {
+            return Ok(());
+        }
+        self.text.clear();
+        // writing to a String cannot fail
+        let _ = self.float.write_to(&mut self.text, value);
+        match &mut self.run {
+            Some(run) if run.end == pos && run.text == self.text => {
+                run.end += 1;
+                return Ok(());
+            }
+            _ => {}
+        }
+        self.end_run()?;
+        self.run = Some(Run {
+            start: pos,
+            end: pos + 1,
+            text: self.text.clone(),
+        });
+        Ok(())
+    }
+
+    /// Skips `count` bases of the current sequence, leaving them without a value.
+    pub fn push_missing(&mut self, count: u64) -> io::Result<()> {
+        if self.chrom.is_none() {
+            return self.push(f64::NAN);
+        }
+        self.position += count;
+        Ok(())
+    }
+
+    /// Writes the last line and gives back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.end_run()?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Writes the line being extended, if any.
+    fn end_run(&mut self) -> io::Result<()> {
+        let (Some(run), Some(chrom)) = (self.run.take(), &self.chrom) else {
+            return Ok(());
+        };
+        writeln!(
+            self.inner,
+            "{}\t{}\t{}\t{}",
+            chrom, run.start, run.end, run.text
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(float: FloatFormat, chroms: &[(&str, &[f64])]) -> Vec<String> {
+        let mut writer = BedGraphWriter::new(Vec::new(), float);
+        for (chrom, values) in chroms {
+            writer.start_chrom(chrom).unwrap();
+            for &value in *values {
+                writer.push(value).unwrap();
+            }
+        }
+        let text = String::from_utf8(writer.finish().unwrap()).unwrap();
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_run_length_merging() {
+        let nan = f64::NAN;
+        let rows = lines(
+            FloatFormat::Shortest,
+            &[
+                ("chr1", &[1.0, 1.0, 2.0, nan, 2.0, 2.0]),
+                ("chr2", &[2.0, 1.5]),
+            ],
+        );
+        assert_eq!(
+            rows,
+            vec![
+                "chr1\t0\t2\t1",
+                "chr1\t2\t3\t2",
+                // a gap splits a run, as does the end of a sequence
+                "chr1\t4\t6\t2",
+                "chr2\t0\t1\t2",
+                "chr2\t1\t2\t1.5",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merging_follows_format() {
+        // values that are written the same are merged
+        let rows = lines(FloatFormat::Fixed(1), &[("chr1", &[1.01, 1.04, 1.06])]);
+        assert_eq!(rows, vec!["chr1\t0\t2\t1.0", "chr1\t2\t3\t1.1"]);
+    }
+
+    #[test]
+    fn test_track_line_and_errors() {
+        let mut writer = BedGraphWriter::new(Vec::new(), FloatFormat::Shortest);
+        assert!(writer.push(1.0).is_err());
+        let track = TrackLine {
+            name: Some("curvature".to_string()),
+            ..Default::default()
+        };
+        writer.write_track_line(&track).unwrap();
+        writer.start_chrom("chr1").unwrap();
+        writer.push_missing(3).unwrap();
+        writer.push(0.5).unwrap();
+        let text = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert_eq!(
+            text,
+            "track type=bedGraph name=\"curvature\"\nchr1\t3\t4\t0.5\n"
+        );
+    }
+}
@@ -0,0 +1,1214 @@
+//! Writing bigWig files.
+//!
+//! The full-resolution data of a bigWig file is a run of independently compressed blocks, each
+//! holding one data section of up to `itemsPerSlot` items from one chromosome. Encoding and
+//! zlib-compressing those blocks is most of the work of writing the file, and since each block is
+//! independent it can be spread over threads, leaving only the header, the chromosome tree and the
+//! R-tree index, which need the finished block offsets and sizes, to be written serially.
+//!
+//! [`BigWigWriter`] streams: blocks are written as the values arrive, and the chromosome tree and
+//! index, which only hold one entry per chromosome and per block, follow the data at the end of
+//! the file, with the header written last. The format locates every part by offset, so this
+//! order reads the same as the UCSC tools' and no chromosome sizes are needed up front.
+//!
+//! Sections are written as bedGraph-type sections (type 1) of `start`, `end`, `value` items, with
+//! runs of equal adjacent values merged into one item and positions without a value left out.
+//! Fields are little-endian, as the format allows either byte order.
+//!
+//! No zoom levels are written: the header lists none, which the format allows, and readers
+//! summarize the full-resolution data for wide views themselves, more slowly than from
+//! precomputed levels. A span of more than one base shrinks that data where it matters.
+//!
+//! With a span of more than one base ([`BigWigWriter::with_span`]), each item covers a span of
+//! bases aligned to the chromosome start and holds the mean or maximum of the values in it, a
+//! middle ground between per-base and binned outputs that browsers draw much faster.
+
+use crate::output::{bigwig_coordinate, check_bigwig_length};
+use crate::provenance::Provenance;
+use crate::view::bigwig::{BIGWIG_MAGIC, CHROM_TREE_MAGIC, EXTENSION_HEADER_BYTES, R_TREE_MAGIC};
+use clap::ValueEnum;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// The number of items per data section used by the UCSC tools, and so by most bigWig files.
+pub const ITEMS_PER_SLOT: usize = 1024;
+
+/// The size of a data section header, in bytes.
+const SECTION_HEADER_BYTES: usize = 24;
+
+/// The size of a bedGraph-type item, in bytes.
+const ITEM_BYTES: usize = 12;
+
+/// The size of the file header, in bytes.
+const HEADER_BYTES: u64 = 64;
+
+/// The size of the total summary, in bytes.
+const SUMMARY_BYTES: u64 = 40;
+
+/// The most children of a node in the chromosome tree or the index, as the UCSC tools use.
+const BLOCK_SIZE: usize = 256;
+
+/// How the values in a span of bases are combined into the one value written for it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Aggregate {
+    /// the mean of the values in the span
+    #[default]
+    Mean,
+    /// the largest value in the span
+    Max,
+}
+
+/// The values of the span being filled.
+///
+/// # Fields
+///
+/// * `start`: The 0-based start of the span.
+/// * `count`: The number of bases in the span with a value.
+/// * `sum`: The sum of the values.
+/// * `max`: The largest value.
+#[derive(Clone, Copy, Debug)]
+struct SpanValues {
+    start: u64,
+    count: u64,
+    sum: f64,
+    max: f64,
+}
+
+impl SpanValues {
+    /// An empty span starting at `start`.
+    fn new(start: u64) -> Self {
+        Self {
+            start,
+            count: 0,
+            sum: 0.0,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// The value written for the span, or `None` if no base in it has a value.
+    fn value(&self, aggregate: Aggregate) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(match aggregate {
+            Aggregate::Mean => self.sum / self.count as f64,
+            Aggregate::Max => self.max,
+        })
+    }
+}
+
+/// One value over a run of bases.
+///
+/// # Fields
+///
+/// * `start`: The 0-based start of the run.
+/// * `end`: The end of the run, exclusive.
+/// * `value`: The value; bigWig stores single precision.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SectionItem {
+    pub start: u32,
+    pub end: u32,
+    pub value: f32,
+}
+
+/// A data section: consecutive items of one chromosome.
+///
+/// # Fields
+///
+/// * `chrom_id`: The id of the chromosome in the file's chromosome tree.
+/// * `items`: The items, in order and not overlapping.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Section {
+    pub chrom_id: u32,
+    pub items: Vec<SectionItem>,
+}
+
+impl Section {
+    /// The start of the first item, or 0 for an empty section.
+    pub fn start(&self) -> u32 {
+        self.items.first().map_or(0, |item| item.start)
+    }
+
+    /// The end of the last item, or 0 for an empty section.
+    pub fn end(&self) -> u32 {
+        self.items.last().map_or(0, |item| item.end)
+    }
+
+    /// Encodes the section as the uncompressed bytes of a bedGraph-type data section.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(SECTION_HEADER_BYTES + self.items.len() * ITEM_BYTES);
+        data.extend(self.chrom_id.to_le_bytes());
+        data.extend(self.start().to_le_bytes());
+        data.extend(self.end().to_le_bytes());
+        // item step and span are only used by the fixedStep and variableStep types
+        data.extend(0u32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend([1u8, 0]);
+        data.extend((self.items.len() as u16).to_le_bytes());
+        for item in &self.items {
+            data.extend(item.start.to_le_bytes());
+            data.extend(item.end.to_le_bytes());
+            data.extend(item.value.to_le_bytes());
+        }
+        data
+    }
+}
+
+/// A data section encoded as the block it is stored as.
+///
+/// # Fields
+///
+/// * `chrom_id`: The id of the chromosome of the section.
+/// * `start`: The start of the section, for the R-tree index.
+/// * `end`: The end of the section, for the R-tree index.
+/// * `data`: The bytes of the block, compressed or not.
+/// * `uncompressed_bytes`: The size of the section before compression, which the header's
+///   `uncompressBufSize` must be at least.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncodedSection {
+    pub chrom_id: u32,
+    pub start: u32,
+    pub end: u32,
+    pub data: Vec<u8>,
+    pub uncompressed_bytes: usize,
+}
+
+/// Splits per-base values into data sections.
+///
+/// # Arguments
+///
+/// * `chrom_id` - The id of the chromosome the values are on.
+/// * `offset` - The 0-based position of the first value.
+/// * `values` - One value per base; `NaN` values are left out.
+/// * `items_per_slot` - The most items in a section.
+pub fn sections<I: Iterator<Item = f64>>(
+    chrom_id: u32,
+    offset: u32,
+    values: I,
+    items_per_slot: usize,
+) -> Vec<Section> {
+    let mut items: Vec<SectionItem> = Vec::new();
+    for (i, value) in values.enumerate() {
+        if value.is_nan() {
+            continue;
+        }
+        let pos = offset + i as u32;
+        let value = value as f32;
+        match items.last_mut() {
+            Some(last) if last.end == pos && last.value == value => last.end += 1,
+            _ => items.push(SectionItem {
+                start: pos,
+                end: pos + 1,
+                value,
+            }),
+        }
+    }
+    items
+        .chunks(items_per_slot.max(1))
+        .map(|chunk| Section {
+            chrom_id,
+            items: chunk.to_vec(),
+        })
+        .collect()
+}
+
+/// Encodes one section, compressing it if `compress` is set.
+pub fn encode_section(section: &Section, compress: bool) -> io::Result<EncodedSection> {
+    let raw = section.encode();
+    let uncompressed_bytes = raw.len();
+    let data = if compress {
+        let mut encoder =
+            ZlibEncoder::new(Vec::with_capacity(raw.len() / 2), Compression::default());
+        encoder.write_all(&raw)?;
+        encoder.finish()?
+    } else {
+        raw
+    };
+    Ok(EncodedSection {
+        chrom_id: section.chrom_id,
+        start: section.start(),
+        end: section.end(),
+        data,
+        uncompressed_bytes,
+    })
+}
+
+/// Encodes sections on several threads.
+///
+/// Threads take the next unencoded section as they finish one, so a few large sections do not
+/// hold up the rest, and the results are in the order of `sections` whatever order they finish in.
+///
+/// # Arguments
+///
+/// * `sections` - The sections to encode.
+/// * `compress` - Whether blocks are zlib-compressed.
+/// * `threads` - The number of threads; with fewer than 2 the sections are encoded in turn.
+///
+/// # Errors
+///
+/// Returns the first error from compression.
+pub fn encode_sections(
+    sections: &[Section],
+    compress: bool,
+    threads: usize,
+) -> io::Result<Vec<EncodedSection>> {
+    if threads < 2 || sections.len() < 2 {
+        return sections
+            .iter()
+            .map(|section| encode_section(section, compress))
+            .collect();
+    }
+    let results: Vec<Mutex<Option<io::Result<EncodedSection>>>> =
+        sections.iter().map(|_| Mutex::default()).collect();
+    let next = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for _ in 0..threads.min(sections.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(section) = sections.get(i) else {
+                    break;
+                };
+                *results[i].lock().unwrap() = Some(encode_section(section, compress));
+            });
+        }
+    });
+    results
+        .into_iter()
+        .map(|result| result.into_inner().unwrap().unwrap())
+        .collect()
+}
+
+/// The summary of all values in a file, stored after its header.
+///
+/// # Fields
+///
+/// * `bases_covered`: The number of bases with a value.
+/// * `min`: The smallest value, or 0 with no values.
+/// * `max`: The largest value, or 0 with no values.
+/// * `sum`: The sum of the values over all covered bases.
+/// * `sum_squares`: The sum of the squared values over all covered bases.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TotalSummary {
+    pub bases_covered: u64,
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+    pub sum_squares: f64,
+}
+
+impl TotalSummary {
+    /// Adds the bases of one item.
+    fn add(&mut self, item: &SectionItem) {
+        let bases = (item.end - item.start) as u64;
+        let value = item.value as f64;
+        if self.bases_covered == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.bases_covered += bases;
+        self.sum += value * bases as f64;
+        self.sum_squares += value * value * bases as f64;
+    }
+
+    /// Encodes the summary as stored in the file.
+    fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(SUMMARY_BYTES as usize);
+        data.extend(self.bases_covered.to_le_bytes());
+        for value in [self.min, self.max, self.sum, self.sum_squares] {
+            data.extend(value.to_le_bytes());
+        }
+        data
+    }
+}
+
+/// Where one block was written, for the R-tree index.
+///
+/// # Fields
+///
+/// * `chrom_id`: The id of the chromosome of the block.
+/// * `start`: The start of the block's section.
+/// * `end`: The end of the block's section.
+/// * `offset`: The file offset of the block.
+/// * `size`: The size of the block, in bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct IndexEntry {
+    chrom_id: u32,
+    start: u32,
+    end: u32,
+    offset: u64,
+    size: u64,
+}
+
+/// Streams per-base values into a bigWig file.
+///
+/// Values are pushed one base at a time, chromosome after chromosome, in the order of the input
+/// sequences; the length of each chromosome is the number of bases pushed for it. Call
+/// [`BigWigWriter::finish`] to write the chromosome tree, the index and the header; a writer
+/// dropped before then leaves an incomplete file.
+///
+/// ```
+/// use symcurve::output::bigwig::BigWigWriter;
+/// use std::io::Cursor;
+///
+/// let mut writer = BigWigWriter::new(Cursor::new(Vec::new())).unwrap();
+/// writer.start_chrom("chr1").unwrap();
+/// writer.push_missing(5).unwrap();
+/// for value in [0.5, 0.5, 0.25] {
+///     writer.push(value).unwrap();
+/// }
+/// let file = writer.finish().unwrap().into_inner();
+/// assert_eq!(&file[..4], &0x888F_FC26u32.to_le_bytes());
+/// ```
+///
+/// # Fields
+///
+/// * `inner`: Where the file is written.
+/// * `compress`: Whether blocks are zlib-compressed.
+/// * `threads`: The number of threads blocks are encoded on.
+/// * `items_per_slot`: The most items in a data section.
+/// * `chroms`: The name and length of each chromosome so far; a chromosome's id is its index.
+/// * `position`: The number of bases pushed for the current chromosome.
+/// * `items`: The items of the section being filled.
+/// * `sections`: Full sections waiting to be encoded.
+/// * `index`: Where each block was written.
+/// * `offset`: The file offset the next block is written at.
+/// * `uncompress_buf_size`: The size of the largest section before compression.
+/// * `summary`: The summary of the values written so far.
+/// * `span`: The number of bases each item covers before runs are merged.
+/// * `aggregate`: How the values in a span are combined.
+/// * `pending`: The span being filled, when `span` is more than one base.
+/// * `comments`: The provenance of the run as `#` comment lines, or empty for none.
+pub struct BigWigWriter<W: Write + Seek> {
+    inner: W,
+    compress: bool,
+    threads: usize,
+    items_per_slot: usize,
+    chroms: Vec<(String, u64)>,
+    position: u64,
+    items: Vec<SectionItem>,
+    sections: Vec<Section>,
+    index: Vec<IndexEntry>,
+    offset: u64,
+    uncompress_buf_size: usize,
+    summary: TotalSummary,
+    span: u64,
+    aggregate: Aggregate,
+    pending: SpanValues,
+    comments: Vec<u8>,
+}
+
+impl<W: Write + Seek> BigWigWriter<W> {
+    /// Starts a bigWig file with compressed blocks of [`ITEMS_PER_SLOT`] items, encoded on one
+    /// thread.
+    ///
+    /// Space for the header is reserved at the start of `inner`, which should be empty.
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        // the header, the total summary and the block count, all written by finish
+        let reserved = HEADER_BYTES + SUMMARY_BYTES + 8;
+        inner.write_all(&vec![0u8; reserved as usize])?;
+        Ok(Self {
+            inner,
+            compress: true,
+            threads: 1,
+            items_per_slot: ITEMS_PER_SLOT,
+            chroms: Vec::new(),
+            position: 0,
+            items: Vec::new(),
+            sections: Vec::new(),
+            index: Vec::new(),
+            offset: reserved,
+            uncompress_buf_size: 0,
+            summary: TotalSummary::default(),
+            span: 1,
+            aggregate: Aggregate::Mean,
+            pending: SpanValues::new(0),
+            comments: Vec::new(),
+        })
+    }
+
+    /// Records the provenance of the run in the file, as the `#` comment lines of
+    /// [`Provenance::write_comments`], for [`BigWigReader::comments`] to read back.
+    ///
+    /// bigWig has no place for metadata, so the lines follow the fields of the extension header,
+    /// whose size the header gives so that readers skip what they do not know. The lines must
+    /// fit in that size, a 16-bit count of bytes, or [`finish`](Self::finish) fails.
+    ///
+    /// [`BigWigReader::comments`]: crate::view::bigwig::BigWigReader::comments
+    pub fn with_provenance(mut self, provenance: &Provenance) -> Self {
+        self.comments.clear();
+        // writing to a Vec cannot fail
+        let _ = provenance.write_comments(&mut self.comments, "#");
+        self
+    }
+
+    /// Sets whether blocks are zlib-compressed.
+    pub fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Sets the number of threads blocks are encoded on.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Sets the most items in a data section.
+    pub fn with_items_per_slot(mut self, items_per_slot: usize) -> Self {
+        self.items_per_slot = items_per_slot.max(1);
+        self
+    }
+
+    /// Writes one item per `span` bases, aligned to the start of each chromosome, with the values
+    /// in it combined by `aggregate`. Spans without any value are left out, and the last span of a
+    /// chromosome ends with it.
+    pub fn with_span(mut self, span: u64, aggregate: Aggregate) -> Self {
+        self.span = span.max(1);
+        self.aggregate = aggregate;
+        self
+    }
+
+    /// Ends the current chromosome, if any, and starts the next.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::ErrorKind::InvalidInput` error if the current chromosome is longer than a
+    /// bigWig file can address, or any error from writing.
+    pub fn start_chrom(&mut self, name: &str) -> io::Result<()> {
+        self.end_chrom()?;
+        self.chroms.push((name.to_string(), 0));
+        Ok(())
+    }
+
+    /// Adds the value of the next base of the current chromosome; a `NaN` value leaves the base
+    /// without one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::ErrorKind::InvalidInput` error if no chromosome was started or the base is
+    /// beyond what a bigWig file can address, or any error from writing.
+    pub fn push(&mut self, value: f64) -> io::Result<()> {
+        let Some((chrom, _)) = self.chroms.last() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "bigWig value pushed before any chromosome was started",
+            ));
+        };
+        let pos = bigwig_coordinate(chrom, self.position)?;
+        if self.span > 1 {
+            self.advance_span(self.position)?;
+            self.position += 1;
+            if !value.is_nan() {
+                let pending = &mut self.pending;
+                pending.count += 1;
+                pending.sum += value;
+                pending.max = pending.max.max(value);
+            }
+            return Ok(());
+        }
+        self.position += 1;
+        if value.is_nan() {
+            return Ok(());
+        }
+        self.add_item(pos, pos + 1, value)
+    }
+
+    /// Adds an item after the last, merging it into the last if it continues it with the same
+    /// value.
+    fn add_item(&mut self, start: u32, end: u32, value: f64) -> io::Result<()> {
+        let value = value as f32;
+        match self.items.last_mut() {
+            Some(last) if last.end == start && last.value == value => {
+                last.end = end;
+                return Ok(());
+            }
+            _ => {}
+        }
+        if self.items.len() == self.items_per_slot {
+            self.close_section()?;
+        }
+        self.items.push(SectionItem { start, end, value });
+        Ok(())
+    }
+
+    /// Writes the span being filled if `position` is past it, and starts the span `position` is
+    /// in.
+    fn advance_span(&mut self, position: u64) -> io::Result<()> {
+        if position < self.pending.start + self.span {
+            return Ok(());
+        }
+        self.write_span(self.pending.start + self.span)?;
+        self.pending = SpanValues::new(position - position % self.span);
+        Ok(())
+    }
+
+    /// Writes the span being filled as an item ending at `end`, if it has any value.
+    fn write_span(&mut self, end: u64) -> io::Result<()> {
+        let Some(value) = self.pending.value(self.aggregate) else {
+            return Ok(());
+        };
+        let chrom = &self.chroms[self.chroms.len() - 1].0;
+        let start = bigwig_coordinate(chrom, self.pending.start)?;
+        let end = bigwig_coordinate(chrom, end - 1)? + 1;
+        self.add_item(start, end, value)
+    }
+
+    /// Skips `count` bases of the current chromosome, leaving them without a value.
+    pub fn push_missing(&mut self, count: u64) -> io::Result<()> {
+        if self.chroms.is_empty() {
+            return self.push(f64::NAN);
+        }
+        if self.span > 1 && count > 0 {
+            self.advance_span(self.position + count - 1)?;
+        }
+        self.position += count;
+        Ok(())
+    }
+
+    /// The summary of the values pushed so far, as of the last closed section.
+    pub fn summary(&self) -> TotalSummary {
+        self.summary
+    }
+
+    /// Writes the rest of the file and gives back the writer. The file has no zoom levels (see
+    /// the [module documentation](self)).
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::ErrorKind::InvalidInput` error if two chromosomes have the same name, the
+    /// last is longer than a bigWig file can address or the provenance is too long for the
+    /// extension header, or any error from writing.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.end_chrom()?;
+        self.write_sections()?;
+        let chrom_tree_offset = self.offset;
+        let chrom_tree = chrom_tree(&self.chroms, chrom_tree_offset)?;
+        self.inner.write_all(&chrom_tree)?;
+        let full_index_offset = chrom_tree_offset + chrom_tree.len() as u64;
+        let index = r_tree(&self.index, full_index_offset, chrom_tree_offset);
+        self.inner.write_all(&index)?;
+        let extension_offset = match self.comments.is_empty() {
+            true => 0,
+            false => {
+                self.inner.write_all(&extension_header(&self.comments)?)?;
+                full_index_offset + index.len() as u64
+            }
+        };
+        self.inner.write_all(&BIGWIG_MAGIC.to_le_bytes())?;
+
+        let mut header = Vec::with_capacity(HEADER_BYTES as usize);
+        header.extend(BIGWIG_MAGIC.to_le_bytes());
+        header.extend(4u16.to_le_bytes());
+        // no zoom levels
+        header.extend(0u16.to_le_bytes());
+        header.extend(chrom_tree_offset.to_le_bytes());
+        // the data follows the header, the summary and the block count
+        header.extend((HEADER_BYTES + SUMMARY_BYTES).to_le_bytes());
+        header.extend(full_index_offset.to_le_bytes());
+        // field counts and autoSql are only used by bigBed
+        header.extend([0u8; 4]);
+        header.extend(0u64.to_le_bytes());
+        header.extend(HEADER_BYTES.to_le_bytes());
+        let buf_size = if self.compress {
+            self.uncompress_buf_size as u32
+        } else {
+            0
+        };
+        header.extend(buf_size.to_le_bytes());
+        header.extend(extension_offset.to_le_bytes());
+        self.inner.seek(SeekFrom::Start(0))?;
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&self.summary.encode())?;
+        self.inner
+            .write_all(&(self.index.len() as u64).to_le_bytes())?;
+        self.inner.seek(SeekFrom::End(0))?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Closes the section of the current chromosome and records its length.
+    fn end_chrom(&mut self) -> io::Result<()> {
+        let Some((chrom, length)) = self.chroms.last_mut() else {
+            return Ok(());
+        };
+        check_bigwig_length(chrom, self.position)?;
+        *length = self.position;
+        if self.span > 1 {
+            self.write_span(self.position.min(self.pending.start + self.span))?;
+            self.pending = SpanValues::new(0);
+        }
+        self.position = 0;
+        self.close_section()
+    }
+
+    /// Moves the items of the section being filled to the sections waiting to be encoded,
+    /// encoding them once there are enough to keep every thread busy.
+    fn close_section(&mut self) -> io::Result<()> {
+        if self.items.is_empty() {
+            return Ok(());
+        }
+        let section = Section {
+            chrom_id: (self.chroms.len() - 1) as u32,
+            items: std::mem::take(&mut self.items),
+        };
+        section.items.iter().for_each(|item| self.summary.add(item));
+        self.sections.push(section);
+        if self.sections.len() >= self.threads * 4 {
+            self.write_sections()?;
+        }
+        Ok(())
+    }
+
+    /// Encodes the waiting sections and writes their blocks.
+    fn write_sections(&mut self) -> io::Result<()> {
+        let sections = std::mem::take(&mut self.sections);
+        for block in encode_sections(&sections, self.compress, self.threads)? {
+            self.inner.write_all(&block.data)?;
+            self.index.push(IndexEntry {
+                chrom_id: block.chrom_id,
+                start: block.start,
+                end: block.end,
+                offset: self.offset,
+                size: block.data.len() as u64,
+            });
+            self.offset += block.data.len() as u64;
+            self.uncompress_buf_size = self.uncompress_buf_size.max(block.uncompressed_bytes);
+        }
+        Ok(())
+    }
+}
+
+/// Encodes the extension header, with no extra indexes and `comments` after its fields.
+///
+/// # Errors
+///
+/// Returns an `io::ErrorKind::InvalidInput` error if the header with the comments is larger than
+/// its 16-bit size can give.
+fn extension_header(comments: &[u8]) -> io::Result<Vec<u8>> {
+    let size = u16::try_from(EXTENSION_HEADER_BYTES as usize + comments.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "provenance of {} bytes is too long for a bigWig extension header",
+                comments.len()
+            ),
+        )
+    })?;
+    let mut header = Vec::with_capacity(size as usize);
+    header.extend(size.to_le_bytes());
+    // the extra index count and the offset of their list
+    header.extend(0u16.to_le_bytes());
+    header.extend(0u64.to_le_bytes());
+    header.resize(EXTENSION_HEADER_BYTES as usize, 0);
+    header.extend(comments);
+    Ok(header)
+}
+
+/// The number of nodes on each level of a tree over `items` items with up to `block_size`
+/// children per node, from the root down to the leaves. An empty tree is a single empty leaf.
+fn tree_levels(items: usize, block_size: usize) -> Vec<usize> {
+    let mut levels = vec![items.div_ceil(block_size).max(1)];
+    while levels[levels.len() - 1] > 1 {
+        levels.push(levels[levels.len() - 1].div_ceil(block_size));
+    }
+    levels.reverse();
+    levels
+}
+
+/// Encodes the chromosome B+ tree.
+///
+/// Chromosomes are keyed by name, in byte order, and keep the ids they were given in the order
+/// they were written, so the data is in id order. Every node is padded to `blockSize` items, so
+/// all nodes have the same size and the offsets of the children are known before they are
+/// written.
+///
+/// # Arguments
+///
+/// * `chroms` - The name and length of each chromosome, in id order.
+/// * `offset` - The file offset the tree is written at.
+///
+/// # Errors
+///
+/// Returns an `io::ErrorKind::InvalidInput` error if two chromosomes have the same name.
+fn chrom_tree(chroms: &[(String, u64)], offset: u64) -> io::Result<Vec<u8>> {
+    let mut items: Vec<(&[u8], u32, u32)> = (0u32..)
+        .zip(chroms)
+        .map(|(id, (name, length))| (name.as_bytes(), id, *length as u32))
+        .collect();
+    items.sort_unstable();
+    if let Some(pair) = items.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "sequence name {} appears more than once; bigWig chromosome names must be unique",
+                String::from_utf8_lossy(pair[0].0)
+            ),
+        ));
+    }
+    let key_size = items
+        .iter()
+        .map(|item| item.0.len())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let block_size = items.len().clamp(1, BLOCK_SIZE);
+    let item_bytes = key_size + 8;
+    let node_bytes = (4 + block_size * item_bytes) as u64;
+    let mut tree = Vec::new();
+    tree.extend(CHROM_TREE_MAGIC.to_le_bytes());
+    tree.extend((block_size as u32).to_le_bytes());
+    tree.extend((key_size as u32).to_le_bytes());
+    tree.extend(8u32.to_le_bytes());
+    tree.extend((items.len() as u64).to_le_bytes());
+    tree.extend(0u64.to_le_bytes());
+    let levels = tree_levels(items.len(), block_size);
+    let mut level_offset = offset + 32;
+    for (depth, &nodes) in levels.iter().enumerate() {
+        let next_offset = level_offset + nodes as u64 * node_bytes;
+        let is_leaf = depth + 1 == levels.len();
+        // the number of chromosomes under each node of the level below
+        let child_items = block_size.pow((levels.len() - depth - 1) as u32);
+        let children = if is_leaf {
+            items.len()
+        } else {
+            levels[depth + 1]
+        };
+        for node in 0..nodes {
+            let first = node * block_size;
+            let count = children.saturating_sub(first).min(block_size);
+            tree.extend([is_leaf as u8, 0]);
+            tree.extend((count as u16).to_le_bytes());
+            for child in first..first + count {
+                let (name, id, length) = items[child * if is_leaf { 1 } else { child_items }];
+                tree.extend(name);
+                tree.extend(std::iter::repeat_n(0u8, key_size - name.len()));
+                if is_leaf {
+                    tree.extend(id.to_le_bytes());
+                    tree.extend(length.to_le_bytes());
+                } else {
+                    tree.extend((next_offset + child as u64 * node_bytes).to_le_bytes());
+                }
+            }
+            tree.extend(std::iter::repeat_n(0u8, (block_size - count) * item_bytes));
+        }
+        level_offset = next_offset;
+    }
+    Ok(tree)
+}
+
+/// Encodes the R-tree index over the blocks, which are in `(chrom_id, start)` order.
+///
+/// Leaf nodes hold 32-byte items pointing at blocks and the other nodes 24-byte items pointing
+/// at their children; every node is padded to `blockSize` items.
+///
+/// # Arguments
+///
+/// * `entries` - Where each block was written.
+/// * `offset` - The file offset the index is written at.
+/// * `data_end` - The file offset just past the last block.
+fn r_tree(entries: &[IndexEntry], offset: u64, data_end: u64) -> Vec<u8> {
+    let block_size = entries.len().clamp(1, BLOCK_SIZE);
+    let leaf_bytes = (4 + block_size * 32) as u64;
+    let node_bytes = (4 + block_size * 24) as u64;
+    let first = entries.first().map_or((0, 0), |e| (e.chrom_id, e.start));
+    let last = entries.last().map_or((0, 0), |e| (e.chrom_id, e.end));
+    let mut tree = Vec::new();
+    tree.extend(R_TREE_MAGIC.to_le_bytes());
+    tree.extend((block_size as u32).to_le_bytes());
+    tree.extend((entries.len() as u64).to_le_bytes());
+    for field in [first.0, first.1, last.0, last.1] {
+        tree.extend(field.to_le_bytes());
+    }
+    tree.extend(data_end.to_le_bytes());
+    tree.extend(1u32.to_le_bytes());
+    tree.extend(0u32.to_le_bytes());
+    let levels = tree_levels(entries.len(), block_size);
+    let mut level_offset = offset + 48;
+    for (depth, &nodes) in levels.iter().enumerate() {
+        let is_leaf = depth + 1 == levels.len();
+        let next_offset =
+            level_offset + nodes as u64 * if is_leaf { leaf_bytes } else { node_bytes };
+        let child_bytes = if depth + 2 == levels.len() {
+            leaf_bytes
+        } else {
+            node_bytes
+        };
+        // the number of blocks under each node of the level below
+        let child_entries = block_size.pow((levels.len() - depth - 1) as u32);
+        let children = if is_leaf {
+            entries.len()
+        } else {
+            levels[depth + 1]
+        };
+        for node in 0..nodes {
+            let first = node * block_size;
+            let count = children.saturating_sub(first).min(block_size);
+            tree.extend([is_leaf as u8, 0]);
+            tree.extend((count as u16).to_le_bytes());
+            for child in first..first + count {
+                let covered = if is_leaf {
+                    &entries[child..child + 1]
+                } else {
+                    let start = child * child_entries;
+                    &entries[start..(start + child_entries).min(entries.len())]
+                };
+                let (start, end) = (&covered[0], &covered[covered.len() - 1]);
+                for field in [start.chrom_id, start.start, end.chrom_id, end.end] {
+                    tree.extend(field.to_le_bytes());
+                }
+                if is_leaf {
+                    tree.extend(start.offset.to_le_bytes());
+                    tree.extend(start.size.to_le_bytes());
+                } else {
+                    tree.extend((next_offset + child as u64 * child_bytes).to_le_bytes());
+                }
+            }
+            let item_bytes = if is_leaf { 32 } else { 24 };
+            tree.extend(std::iter::repeat_n(0u8, (block_size - count) * item_bytes));
+        }
+        level_offset = next_offset;
+    }
+    tree
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::view::bigwig::{BigWigReader, ChromInfo};
+    use crate::view::Interval;
+    use flate2::read::ZlibDecoder;
+    use std::io::{Cursor, Read};
+
+    /// Writes chromosomes of per-base values with `writer` and reads the file back.
+    fn round_trip(
+        writer: BigWigWriter<Cursor<Vec<u8>>>,
+        chroms: &[(&str, Vec<f64>)],
+    ) -> BigWigReader<Cursor<Vec<u8>>> {
+        let mut writer = writer;
+        for (name, values) in chroms {
+            writer.start_chrom(name).unwrap();
+            for &value in values {
+                writer.push(value).unwrap();
+            }
+        }
+        let mut file = writer.finish().unwrap();
+        file.set_position(0);
+        BigWigReader::new(file).unwrap()
+    }
+
+    #[test]
+    fn test_sections() {
+        let values = [f64::NAN, 1.0, 1.0, 2.0, f64::NAN, 2.0, 3.0, 3.0, 3.0];
+        let sections = sections(4, 100, values.into_iter(), 2);
+        assert_eq!(sections.len(), 2);
+        assert_eq!(
+            sections[0].items,
+            vec![
+                SectionItem {
+                    start: 101,
+                    end: 103,
+                    value: 1.0
+                },
+                SectionItem {
+                    start: 103,
+                    end: 104,
+                    value: 2.0
+                },
+            ]
+        );
+        // a gap splits a run even when the values either side are equal
+        assert_eq!((sections[1].start(), sections[1].end()), (105, 109));
+        assert_eq!(sections[1].items.len(), 2);
+        assert_eq!(sections[1].chrom_id, 4);
+    }
+
+    #[test]
+    fn test_encode_section() {
+        let section = Section {
+            chrom_id: 2,
+            items: vec![SectionItem {
+                start: 10,
+                end: 20,
+                value: 1.5,
+            }],
+        };
+        let raw = section.encode();
+        assert_eq!(raw.len(), 36);
+        assert_eq!(&raw[0..4], &2u32.to_le_bytes());
+        assert_eq!(&raw[8..12], &20u32.to_le_bytes());
+        assert_eq!(raw[20], 1);
+        assert_eq!(&raw[22..24], &1u16.to_le_bytes());
+        assert_eq!(&raw[32..36], &1.5f32.to_le_bytes());
+        let encoded = encode_section(&section, true).unwrap();
+        assert_eq!(encoded.uncompressed_bytes, 36);
+        assert_eq!((encoded.start, encoded.end), (10, 20));
+        let mut decoded = Vec::new();
+        ZlibDecoder::new(&encoded.data[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, raw);
+        assert_eq!(encode_section(&section, false).unwrap().data, raw);
+    }
+
+    #[test]
+    fn test_encode_sections_parallel() {
+        let values = (0..50_000).map(|i| ((i / 3) as f64 * 0.01).sin());
+        let sections = sections(0, 0, values, ITEMS_PER_SLOT);
+        assert!(sections.len() > 4);
+        let serial = encode_sections(&sections, true, 1).unwrap();
+        let parallel = encode_sections(&sections, true, 4).unwrap();
+        assert_eq!(serial, parallel);
+        assert!(encode_sections(&[], true, 4).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_write_bigwig() {
+        let chr2: Vec<f64> = (0..3000).map(|i| ((i / 2) as f64 * 0.1).sin()).collect();
+        let chroms = [
+            ("chr2", chr2.clone()),
+            ("chr10", vec![f64::NAN, 1.0, 1.0, f64::NAN]),
+            ("chr1", vec![2.5; 7]),
+        ];
+        let writer = BigWigWriter::new(Cursor::new(Vec::new())).unwrap();
+        let mut reader = round_trip(writer, &chroms);
+        // ids follow the order written, whatever the name order
+        assert_eq!(
+            reader.chroms(),
+            &[
+                ChromInfo {
+                    name: "chr2".to_string(),
+                    id: 0,
+                    length: 3000
+                },
+                ChromInfo {
+                    name: "chr10".to_string(),
+                    id: 1,
+                    length: 4
+                },
+                ChromInfo {
+                    name: "chr1".to_string(),
+                    id: 2,
+                    length: 7
+                },
+            ]
+        );
+        assert_eq!(
+            reader.values("chr10", 0, 4).unwrap(),
+            vec![Interval {
+                start: 1,
+                end: 3,
+                value: 1.0
+            }]
+        );
+        assert_eq!(
+            reader.values("chr1", 2, 5).unwrap(),
+            vec![Interval {
+                start: 2,
+                end: 5,
+                value: 2.5
+            }]
+        );
+        let mut read = vec![f64::NAN; 3000];
+        for interval in reader.values("chr2", 0, 3000).unwrap() {
+            for pos in interval.start..interval.end {
+                read[pos as usize] = interval.value as f64;
+            }
+        }
+        assert!(read.iter().zip(&chr2).all(|(&a, &b)| a == b as f32 as f64));
+    }
+
+    #[test]
+    fn test_write_bigwig_span() {
+        let nan = f64::NAN;
+        let chroms = [
+            (
+                "chr1",
+                vec![1.0, 3.0, nan, 2.0, nan, 2.0, nan, nan, nan, 6.0, 0.0],
+            ),
+            ("chr2", vec![nan, 5.0, 1.0]),
+        ];
+        let interval = |start, end, value| Interval { start, end, value };
+        let writer = BigWigWriter::new(Cursor::new(Vec::new()))
+            .unwrap()
+            .with_span(3, Aggregate::Mean);
+        let mut reader = round_trip(writer, &chroms);
+        // equal spans merge, spans without values are left out and the last span ends with the
+        // chromosome
+        assert_eq!(
+            reader.values("chr1", 0, 11).unwrap(),
+            vec![interval(0, 6, 2.0), interval(9, 11, 3.0),]
+        );
+        assert_eq!(
+            reader.values("chr2", 0, 3).unwrap(),
+            vec![interval(0, 3, 3.0)]
+        );
+        let writer = BigWigWriter::new(Cursor::new(Vec::new()))
+            .unwrap()
+            .with_span(3, Aggregate::Max);
+        let mut reader = round_trip(writer, &chroms);
+        assert_eq!(
+            reader.values("chr1", 0, 11).unwrap(),
+            vec![
+                interval(0, 3, 3.0),
+                interval(3, 6, 2.0),
+                interval(9, 11, 6.0),
+            ]
+        );
+        assert_eq!(reader.chroms()[0].length, 11);
+    }
+
+    #[test]
+    fn test_write_bigwig_span_missing() {
+        let mut writer = BigWigWriter::new(Cursor::new(Vec::new()))
+            .unwrap()
+            .with_span(4, Aggregate::Mean);
+        writer.start_chrom("chr1").unwrap();
+        writer.push(1.0).unwrap();
+        writer.push_missing(2).unwrap();
+        writer.push(3.0).unwrap();
+        writer.push_missing(9).unwrap();
+        writer.push(8.0).unwrap();
+        let mut file = writer.finish().unwrap();
+        file.set_position(0);
+        let mut reader = BigWigReader::new(file).unwrap();
+        assert_eq!(
+            reader.values("chr1", 0, 14).unwrap(),
+            vec![
+                Interval {
+                    start: 0,
+                    end: 4,
+                    value: 2.0
+                },
+                Interval {
+                    start: 12,
+                    end: 14,
+                    value: 8.0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_bigwig_deep_trees() {
+        // enough chromosomes and blocks for three-level trees
+        let chroms: Vec<(String, Vec<f64>)> = (0..70_000)
+            .map(|i| (format!("contig_{}", i), vec![i as f64, f64::NAN, 0.5]))
+            .collect();
+        let chroms: Vec<(&str, Vec<f64>)> = chroms
+            .iter()
+            .map(|(name, values)| (name.as_str(), values.clone()))
+            .collect();
+        let writer = BigWigWriter::new(Cursor::new(Vec::new()))
+            .unwrap()
+            .with_compression(false)
+            .with_items_per_slot(1)
+            .with_threads(4);
+        let mut reader = round_trip(writer, &chroms);
+        assert_eq!(reader.chroms().len(), 70_000);
+        for id in [0, 255, 256, 65_535, 65_536, 69_999] {
+            let name = format!("contig_{}", id);
+            assert_eq!(reader.chroms()[id].name, name);
+            assert_eq!(reader.chroms()[id].length, 3);
+            let values = reader.values(&name, 0, 3).unwrap();
+            assert_eq!(values.len(), 2);
+            assert_eq!(values[0].value, id as f32);
+            assert_eq!((values[1].start, values[1].value), (2, 0.5));
+        }
+    }
+
+    #[test]
+    fn test_write_bigwig_header() {
+        let mut writer = BigWigWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.start_chrom("chr1").unwrap();
+        writer.push_missing(10).unwrap();
+        for value in [1.0, 1.0, 3.0] {
+            writer.push(value).unwrap();
+        }
+        writer.start_chrom("chr2").unwrap();
+        let file = writer.finish().unwrap().into_inner();
+        let u64_at = |at: usize| u64::from_le_bytes(file[at..at + 8].try_into().unwrap());
+        let f64_at = |at: usize| f64::from_le_bytes(file[at..at + 8].try_into().unwrap());
+        assert_eq!(&file[..4], &BIGWIG_MAGIC.to_le_bytes());
+        assert_eq!(&file[file.len() - 4..], &BIGWIG_MAGIC.to_le_bytes());
+        // no zoom levels, so no zoom headers between the header and the total summary
+        assert_eq!(u16::from_le_bytes([file[6], file[7]]), 0);
+        assert_eq!(u64_at(16), 104);
+        assert_eq!(u64_at(44), 64);
+        // total summary and block count
+        assert_eq!(u64_at(64), 3);
+        assert_eq!((f64_at(72), f64_at(80)), (1.0, 3.0));
+        assert_eq!((f64_at(88), f64_at(96)), (5.0, 11.0));
+        assert_eq!(u64_at(104), 1);
+        let mut reader = BigWigReader::new(Cursor::new(file)).unwrap();
+        assert_eq!(reader.chroms()[0].length, 13);
+        assert_eq!(reader.chroms()[1].length, 0);
+        assert!(reader.values("chr2", 0, 10).unwrap().is_empty());
+        assert_eq!(reader.comments().unwrap(), None);
+    }
+
+    #[test]
+    fn test_write_bigwig_provenance() {
+        let provenance = Provenance {
+            version: "0.1.0".to_string(),
+            parameters: vec![("curve_step".to_string(), "15".to_string())],
+            matrices: "builtin".to_string(),
+        };
+        let mut writer = BigWigWriter::new(Cursor::new(Vec::new()))
+            .unwrap()
+            .with_provenance(&provenance);
+        writer.start_chrom("chr1").unwrap();
+        writer.push(0.5).unwrap();
+        let file = writer.finish().unwrap().into_inner();
+        assert_eq!(&file[file.len() - 4..], &BIGWIG_MAGIC.to_le_bytes());
+        // no autoSql, which readers take for a bigBed's, and an extension header sized to hold
+        // the comments after its fields
+        let u64_at = |at: usize| u64::from_le_bytes(file[at..at + 8].try_into().unwrap());
+        assert_eq!(u64_at(36), 0);
+        let extension = u64_at(56) as usize;
+        let size = u16::from_le_bytes([file[extension], file[extension + 1]]) as usize;
+        assert_eq!(extension + size + 4, file.len());
+        assert!(file[extension + 2..extension + 64]
+            .iter()
+            .all(|&byte| byte == 0));
+        assert!(file[extension + 64..].starts_with(b"# symcurve version: 0.1.0\n"));
+        let mut reader = BigWigReader::new(Cursor::new(file)).unwrap();
+        let comments = reader.comments().unwrap().unwrap();
+        let recorded = Provenance::from_comments(comments.lines(), "#").unwrap();
+        assert_eq!(recorded.provenance, provenance);
+        assert!(recorded.hash_matches());
+        assert_eq!(reader.values("chr1", 0, 1).unwrap()[0].value, 0.5);
+        // comments the extension header's size cannot cover
+        let provenance = Provenance {
+            matrices: "m".repeat(1 << 16),
+            ..provenance
+        };
+        let writer = BigWigWriter::new(Cursor::new(Vec::new()))
+            .unwrap()
+            .with_provenance(&provenance);
+        let err = writer.finish().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_write_bigwig_errors() {
+        let mut writer = BigWigWriter::new(Cursor::new(Vec::new())).unwrap();
+        assert!(writer.push(1.0).is_err());
+        writer.start_chrom("chr1").unwrap();
+        writer.push(1.0).unwrap();
+        writer.start_chrom("chr1").unwrap();
+        let e = writer.finish().unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert!(e.to_string().contains("chr1 appears more than once"));
+        let mut writer = BigWigWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.start_chrom("chr1").unwrap();
+        writer.push_missing(u32::MAX as u64 + 1).unwrap();
+        assert!(writer.finish().is_err());
+    }
+}
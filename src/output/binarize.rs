@@ -0,0 +1,120 @@
+//! Thresholded copies of a track.
+//!
+//! `--binarize THRESH` writes, next to the continuous output, either a 0/1 track or a BED file of
+//! the intervals above the threshold, which is handier for set operations with bedtools than
+//! thresholding the continuous values downstream.
+
+use clap::ValueEnum;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// The form the thresholded output takes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum BinarizeFormat {
+    /// a track of 1 above the threshold and 0 elsewhere, in the same format as the main output
+    #[default]
+    Track,
+    /// a BED file of the intervals above the threshold
+    Bed,
+}
+
+impl BinarizeFormat {
+    /// Derives the path of the thresholded output from the main output path.
+    ///
+    /// The track keeps the main output's extension with `.binary` inserted before it, so that
+    /// `out.bw` becomes `out.binary.bw`; the BED file replaces it, giving `out.above.bed`.
+    pub fn path(&self, output: &Path) -> PathBuf {
+        let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match (self, output.extension()) {
+            (BinarizeFormat::Track, Some(ext)) => {
+                format!("{}.binary.{}", stem, ext.to_string_lossy())
+            }
+            (BinarizeFormat::Track, None) => format!("{}.binary", stem),
+            (BinarizeFormat::Bed, _) => format!("{}.above.bed", stem),
+        };
+        output.with_file_name(name)
+    }
+}
+
+/// Thresholds one value: `1.0` if it is above `threshold`, `0.0` if not, and `NaN` stays `NaN`
+/// so that missing positions are still reported as missing.
+pub fn binarize(value: f64, threshold: f64) -> f64 {
+    if value.is_nan() {
+        f64::NAN
+    } else if value > threshold {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Finds the runs of values above `threshold`.
+///
+/// # Returns
+///
+/// The 0-based, half-open index ranges of the maximal runs of values strictly above `threshold`,
+/// in order. `NaN` values end a run.
+pub fn above_threshold_intervals<I>(values: I, threshold: f64) -> Vec<Range<usize>>
+where
+    I: IntoIterator<Item = f64>,
+{
+    let mut intervals = Vec::new();
+    let mut start = None;
+    let mut len = 0;
+    for (i, value) in values.into_iter().enumerate() {
+        match (value > threshold, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                intervals.push(s..i);
+                start = None;
+            }
+            _ => {}
+        }
+        len = i + 1;
+    }
+    if let Some(s) = start {
+        intervals.push(s..len);
+    }
+    intervals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binarize() {
+        assert_eq!(binarize(2.0, 1.5), 1.0);
+        assert_eq!(binarize(1.5, 1.5), 0.0);
+        assert_eq!(binarize(-3.0, 1.5), 0.0);
+        assert!(binarize(f64::NAN, 1.5).is_nan());
+    }
+
+    #[test]
+    fn test_above_threshold_intervals() {
+        let values = vec![0.0, 2.0, 3.0, f64::NAN, 2.0, 1.0, 5.0, 6.0];
+        assert_eq!(
+            above_threshold_intervals(values, 1.0),
+            vec![1..3, 4..5, 6..8]
+        );
+        assert!(above_threshold_intervals(vec![0.0, 1.0], 1.0).is_empty());
+        assert!(above_threshold_intervals(Vec::new(), 1.0).is_empty());
+    }
+
+    #[test]
+    fn test_binarize_path() {
+        let output = Path::new("results/out.bw");
+        assert_eq!(
+            BinarizeFormat::Track.path(output),
+            PathBuf::from("results/out.binary.bw")
+        );
+        assert_eq!(
+            BinarizeFormat::Bed.path(output),
+            PathBuf::from("results/out.above.bed")
+        );
+        assert_eq!(
+            BinarizeFormat::Track.path(Path::new("out")),
+            PathBuf::from("out.binary")
+        );
+    }
+}
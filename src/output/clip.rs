@@ -0,0 +1,68 @@
+//! Clipping values to a range before they are written.
+//!
+//! Windows in unusual repeats can produce curvature far outside the usual range, which blows out
+//! browser auto-scaling for the rest of the track. `--clip MIN:MAX` saturates values at the ends
+//! of the range, and the number of positions clipped is reported in the run stats.
+
+use crate::output::track::ViewLimits;
+use crate::stats::ClipStats;
+use std::fmt;
+use std::str::FromStr;
+
+/// The range values are clipped to, written as `MIN:MAX`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Clip {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Clip {
+    /// Clips one value to the range and counts it if it was changed. `NaN` is passed through.
+    pub fn apply(&self, value: f64, stats: &mut ClipStats) -> f64 {
+        if value < self.min {
+            stats.below += 1;
+            self.min
+        } else if value > self.max {
+            stats.above += 1;
+            self.max
+        } else {
+            value
+        }
+    }
+}
+
+impl FromStr for Clip {
+    type Err = String;
+
+    /// Parses `MIN:MAX`, where `MIN` must be less than `MAX`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ViewLimits { min, max } = s.parse()?;
+        Ok(Self { min, max })
+    }
+}
+
+impl fmt::Display for Clip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.min, self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clip() {
+        let clip: Clip = "-1:2.5".parse().unwrap();
+        let mut stats = ClipStats::default();
+        let values: Vec<f64> = [-3.0, 0.0, 2.5, 9.0, 10.0]
+            .into_iter()
+            .map(|v| clip.apply(v, &mut stats))
+            .collect();
+        assert_eq!(values, vec![-1.0, 0.0, 2.5, 2.5, 2.5]);
+        assert!(clip.apply(f64::NAN, &mut stats).is_nan());
+        assert_eq!((stats.below, stats.above), (1, 2));
+        assert_eq!(stats.total(), 3);
+        assert!("2:1".parse::<Clip>().is_err());
+    }
+}
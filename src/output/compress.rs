@@ -0,0 +1,163 @@
+//! Compression of text and tabular outputs.
+//!
+//! TSV and bedGraph tracks at base resolution run to tens of gigabytes for a mammalian genome.
+//! `--compression` wraps their writers in a gzip or Zstandard encoder and adds `.gz` or `.zst` to
+//! their paths; the zstd encoder compresses frames on all available threads, so it keeps up with
+//! the pipeline where gzip would not, at the cost of somewhat larger files.
+
+use crate::output::zstd::ZstdWriter;
+use crate::resources::Resources;
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// How text outputs are compressed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    /// write plain text
+    #[default]
+    None,
+    /// gzip, on one thread
+    Gzip,
+    /// Zstandard, with frames compressed in parallel
+    Zstd,
+}
+
+impl Compression {
+    /// The extension conventionally added to compressed file names, if any.
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gz"),
+            Compression::Zstd => Some("zst"),
+        }
+    }
+
+    /// Appends the compression's extension to a path, unless the path already has it.
+    pub fn output_path(&self, path: &Path) -> PathBuf {
+        match self.extension() {
+            Some(ext) if path.extension().is_none_or(|e| e != ext) => {
+                let mut name = path.as_os_str().to_os_string();
+                name.push(".");
+                name.push(ext);
+                PathBuf::from(name)
+            }
+            _ => path.to_path_buf(),
+        }
+    }
+
+    /// Wraps a writer in this compression, using the threads detected for the run for zstd.
+    pub fn writer<W: Write>(&self, writer: W) -> CompressedWriter<W> {
+        self.writer_with_threads(writer, Resources::detect().threads)
+    }
+
+    /// Wraps a writer in this compression.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Where the compressed output goes.
+    /// * `threads` - The number of threads the zstd encoder compresses frames on.
+    pub fn writer_with_threads<W: Write>(&self, writer: W, threads: usize) -> CompressedWriter<W> {
+        match self {
+            Compression::None => CompressedWriter::Plain(writer),
+            Compression::Gzip => {
+                let encoder = GzEncoder::new(writer, flate2::Compression::default());
+                CompressedWriter::Gzip(BufWriter::new(encoder))
+            }
+            Compression::Zstd => CompressedWriter::Zstd(ZstdWriter::new(writer, threads)),
+        }
+    }
+}
+
+/// A writer that compresses what is written to it, or passes it through.
+///
+/// The gzip encoder is buffered: the track writers write a field at a time, and deflating each
+/// of those separately is many times slower than deflating the same bytes in large blocks.
+pub enum CompressedWriter<W: Write> {
+    Plain(W),
+    Gzip(BufWriter<GzEncoder<W>>),
+    Zstd(ZstdWriter<W>),
+}
+
+impl<W: Write> CompressedWriter<W> {
+    /// Compresses and writes whatever is buffered, ends the stream and returns the inner writer.
+    /// Dropping the writer without calling this loses the end of the output.
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            CompressedWriter::Plain(mut writer) => {
+                writer.flush()?;
+                Ok(writer)
+            }
+            CompressedWriter::Gzip(encoder) => {
+                encoder.into_inner().map_err(|e| e.into_error())?.finish()
+            }
+            CompressedWriter::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            CompressedWriter::Plain(writer) => writer.write(buf),
+            CompressedWriter::Gzip(encoder) => encoder.write(buf),
+            CompressedWriter::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            CompressedWriter::Plain(writer) => writer.flush(),
+            CompressedWriter::Gzip(encoder) => encoder.flush(),
+            CompressedWriter::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_output_path() {
+        assert_eq!(
+            Compression::None.output_path(Path::new("out.tsv")),
+            Path::new("out.tsv")
+        );
+        assert_eq!(
+            Compression::Zstd.output_path(Path::new("out.tsv")),
+            Path::new("out.tsv.zst")
+        );
+        assert_eq!(
+            Compression::Gzip.output_path(Path::new("out.tsv.gz")),
+            Path::new("out.tsv.gz")
+        );
+    }
+
+    #[test]
+    fn test_writers() {
+        let data = (0..10_000)
+            .map(|i| format!("chr1\t{}\t{:.4}\n", i, (i as f64).sin()))
+            .collect::<String>();
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let mut writer = compression.writer_with_threads(Vec::new(), 2);
+            writer.write_all(data.as_bytes()).unwrap();
+            let out = writer.finish().unwrap();
+            match compression {
+                Compression::None => assert_eq!(out, data.as_bytes()),
+                Compression::Gzip => {
+                    let mut text = String::new();
+                    GzDecoder::new(&out[..]).read_to_string(&mut text).unwrap();
+                    assert_eq!(text, data);
+                }
+                Compression::Zstd => {
+                    assert_eq!(&out[..4], &[0x28, 0xB5, 0x2F, 0xFD]);
+                    assert!(out.len() < data.len() / 2);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Sequence dictionary output.
+//!
+//! A Picard-style `.dict` file lists each input sequence with its length and the MD5 of its
+//! bases, in the `@SQ` header-line format of the SAM specification. GATK/Picard-based pipelines
+//! use it to check that a track was computed against the reference they expect.
+
+use crate::checksum::Md5;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// One `@SQ` line of a sequence dictionary.
+///
+/// # Fields
+///
+/// * `name`: The sequence name.
+/// * `length`: The number of bases.
+/// * `md5`: The MD5 of the bases as defined for the SAM `M5` tag.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SequenceEntry {
+    pub name: String,
+    pub length: u64,
+    pub md5: String,
+}
+
+/// Streaming digest of a sequence for its dictionary entry.
+///
+/// Following the SAM specification, the `M5` digest is taken over the bases uppercased, with
+/// anything outside the printable ASCII range (such as stray line-ending bytes) left out.
+///
+/// # Fields
+///
+/// * `md5`: The running MD5 of the normalized bases.
+/// * `length`: The number of bases counted.
+/// * `chunk`: Scratch space for normalizing bases before they are digested.
+#[derive(Clone, Debug, Default)]
+pub struct SequenceDigest {
+    md5: Md5,
+    length: u64,
+    chunk: Vec<u8>,
+}
+
+impl SequenceDigest {
+    /// Constructor for `SequenceDigest`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds bases of the sequence.
+    pub fn update(&mut self, bases: &[u8]) {
+        self.chunk.clear();
+        self.chunk.extend(
+            bases
+                .iter()
+                .filter(|b| (b'!'..=b'~').contains(*b))
+                .map(u8::to_ascii_uppercase),
+        );
+        self.length += self.chunk.len() as u64;
+        self.md5.update(&self.chunk);
+    }
+
+    /// Finishes the digest as the dictionary entry for `name`.
+    pub fn finish(self, name: &str) -> SequenceEntry {
+        SequenceEntry {
+            name: name.to_string(),
+            length: self.length,
+            md5: self.md5.hex_digest(),
+        }
+    }
+}
+
+/// Derives the path of the sequence dictionary from the output path, replacing its extension so
+/// that `out.bw` gets `out.dict`.
+pub fn dict_path(output: &Path) -> PathBuf {
+    output.with_extension("dict")
+}
+
+/// Writes a sequence dictionary.
+///
+/// # Arguments
+///
+/// * `writer` - Where the dictionary is written.
+/// * `entries` - The sequences, in the order they appear in the input.
+/// * `uri` - The location of the input FASTA, written as the `UR` tag of each entry if given.
+pub fn write_dict<W: Write>(
+    writer: &mut W,
+    entries: &[SequenceEntry],
+    uri: Option<&str>,
+) -> io::Result<()> {
+    writeln!(writer, "@HD\tVN:1.6")?;
+    for entry in entries {
+        write!(
+            writer,
+            "@SQ\tSN:{}\tLN:{}\tM5:{}",
+            entry.name, entry.length, entry.md5
+        )?;
+        if let Some(uri) = uri {
+            write!(writer, "\tUR:{}", uri)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_digest() {
+        let mut digest = SequenceDigest::new();
+        digest.update(b"acgt");
+        digest.update(b"NNAC\r");
+        let entry = digest.finish("chr1");
+        // uppercased, and the stray \r is left out of both the digest and the length
+        let mut expected = Md5::new();
+        expected.update(b"ACGTNNAC");
+        assert_eq!(entry.length, 8);
+        assert_eq!(entry.md5, expected.hex_digest());
+    }
+
+    #[test]
+    fn test_write_dict() {
+        let entries = vec![
+            SequenceEntry {
+                name: "chr1".to_string(),
+                length: 4,
+                md5: "f1f8f4bf413b16ad135722aa4591043e".to_string(),
+            },
+            SequenceEntry {
+                name: "chrM".to_string(),
+                length: 16569,
+                md5: "c68f52674c9fb33aef52dcf399755519".to_string(),
+            },
+        ];
+        let mut buf = Vec::new();
+        write_dict(&mut buf, &entries, Some("file:/data/ref.fa")).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "@HD\tVN:1.6\n\
+             @SQ\tSN:chr1\tLN:4\tM5:f1f8f4bf413b16ad135722aa4591043e\tUR:file:/data/ref.fa\n\
+             @SQ\tSN:chrM\tLN:16569\tM5:c68f52674c9fb33aef52dcf399755519\tUR:file:/data/ref.fa\n"
+        );
+    }
+
+    #[test]
+    fn test_dict_path() {
+        assert_eq!(
+            dict_path(Path::new("results/out.bw")),
+            PathBuf::from("results/out.dict")
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Output layout for workflow managers.
+//!
+//! Nextflow and Snakemake run each job in a directory of their own and expect to be told, or to
+//! predict, exactly which files it produces. With `--work-dir` every output path must be relative
+//! and is resolved inside that directory, temporary files are kept in a fixed subdirectory of it,
+//! and with `--emit-outputs json` the files produced are listed on stdout when the run finishes,
+//! in the order they were written, with paths relative to the work directory.
+
+use crate::json::Json;
+use clap::ValueEnum;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// The subdirectory of the work directory temporary files are kept in.
+pub const TEMP_DIR: &str = ".symcurve-tmp";
+
+/// How the list of produced files is written to stdout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EmitOutputs {
+    /// a JSON object with the work directory and an `outputs` array of `{kind, path}` objects
+    Json,
+}
+
+/// A file written by a run.
+///
+/// # Fields
+///
+/// * `kind`: What the file holds, e.g. `track` or `dict`.
+/// * `path`: Where it is, relative to the work directory if there is one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProducedFile {
+    pub kind: &'static str,
+    pub path: PathBuf,
+}
+
+/// Where a run's outputs go, and the record of those written.
+///
+/// # Fields
+///
+/// * `work_dir`: The directory relative output paths are resolved in, if given.
+/// * `produced`: The files written so far, in order.
+#[derive(Clone, Debug, Default)]
+pub struct OutputLayout {
+    work_dir: Option<PathBuf>,
+    produced: Vec<ProducedFile>,
+}
+
+impl OutputLayout {
+    /// Constructor for `OutputLayout`.
+    pub fn new(work_dir: Option<PathBuf>) -> Self {
+        Self {
+            work_dir,
+            produced: Vec::new(),
+        }
+    }
+
+    /// The work directory, if given.
+    pub fn work_dir(&self) -> Option<&Path> {
+        self.work_dir.as_deref()
+    }
+
+    /// Resolves an output path. Without a work directory the path is used as given.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if there is a work directory and the path is absolute or
+    /// climbs out of it with `..`.
+    pub fn resolve(&self, path: &Path) -> io::Result<PathBuf> {
+        match &self.work_dir {
+            Some(dir) => {
+                check_relative(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                Ok(dir.join(path))
+            }
+            None => Ok(path.to_path_buf()),
+        }
+    }
+
+    /// The directory for temporary files such as spilled values: `spill_dir` if given, otherwise
+    /// `TEMP_DIR` inside the work directory, or `None` to keep values in memory.
+    pub fn temp_dir(&self, spill_dir: Option<&Path>) -> Option<PathBuf> {
+        match spill_dir {
+            Some(dir) => Some(dir.to_path_buf()),
+            None => self.work_dir.as_ref().map(|dir| dir.join(TEMP_DIR)),
+        }
+    }
+
+    /// Records a file written by the run. Paths inside the work directory are recorded relative
+    /// to it.
+    pub fn record(&mut self, kind: &'static str, path: &Path) {
+        let path = match &self.work_dir {
+            Some(dir) => path.strip_prefix(dir).unwrap_or(path),
+            None => path,
+        };
+        self.produced.push(ProducedFile {
+            kind,
+            path: path.to_path_buf(),
+        });
+    }
+
+    /// The files written so far, in order.
+    pub fn produced(&self) -> &[ProducedFile] {
+        &self.produced
+    }
+
+    /// The list of produced files as JSON.
+    pub fn to_json(&self) -> Json {
+        let outputs = self
+            .produced
+            .iter()
+            .map(|file| {
+                Json::object()
+                    .with("kind", file.kind)
+                    .with("path", file.path.to_string_lossy().as_ref())
+            })
+            .collect::<Vec<_>>();
+        let work_dir = match &self.work_dir {
+            Some(dir) => Json::from(dir.to_string_lossy().as_ref()),
+            None => Json::Null,
+        };
+        Json::object()
+            .with("work_dir", work_dir)
+            .with("outputs", outputs)
+    }
+
+    /// Writes the list of produced files in the requested form, on one line.
+    pub fn emit<W: Write>(&self, writer: &mut W, format: EmitOutputs) -> io::Result<()> {
+        match format {
+            EmitOutputs::Json => writeln!(writer, "{}", self.to_json()),
+        }
+    }
+}
+
+/// Checks that a path is relative and stays below the directory it is resolved in.
+///
+/// # Errors
+///
+/// Returns a message naming the path if it is absolute or has a `..` component.
+pub fn check_relative(path: &Path) -> Result<(), String> {
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => {
+                return Err(format!(
+                    "output path {} must be relative to --work-dir",
+                    path.display()
+                ))
+            }
+            Component::ParentDir => {
+                return Err(format!(
+                    "output path {} must not leave --work-dir with '..'",
+                    path.display()
+                ))
+            }
+            Component::CurDir | Component::Normal(_) => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let layout = OutputLayout::new(Some(PathBuf::from("work/job1")));
+        assert_eq!(
+            layout.resolve(Path::new("tracks/out.bw")).unwrap(),
+            Path::new("work/job1/tracks/out.bw")
+        );
+        assert!(layout.resolve(Path::new("/data/out.bw")).is_err());
+        assert!(layout.resolve(Path::new("tracks/../../out.bw")).is_err());
+        assert_eq!(
+            layout.temp_dir(None),
+            Some(PathBuf::from("work/job1/.symcurve-tmp"))
+        );
+        assert_eq!(
+            layout.temp_dir(Some(Path::new("/scratch"))),
+            Some(PathBuf::from("/scratch"))
+        );
+        // without a work directory paths are used as given
+        let layout = OutputLayout::new(None);
+        assert_eq!(
+            layout.resolve(Path::new("/data/out.bw")).unwrap(),
+            Path::new("/data/out.bw")
+        );
+        assert_eq!(layout.temp_dir(None), None);
+    }
+
+    #[test]
+    fn test_emit() {
+        let mut layout = OutputLayout::new(Some(PathBuf::from("work")));
+        layout.record("track", Path::new("work/out.bw"));
+        layout.record("dict", Path::new("work/out.dict"));
+        let mut out = Vec::new();
+        layout.emit(&mut out, EmitOutputs::Json).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"work_dir\":\"work\",\"outputs\":[{\"kind\":\"track\",\"path\":\"out.bw\"},\
+             {\"kind\":\"dict\",\"path\":\"out.dict\"}]}\n"
+        );
+        let json: Json = layout.to_json().to_string().parse().unwrap();
+        assert_eq!(
+            json.get("outputs").and_then(Json::as_array).map(<[_]>::len),
+            Some(2)
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Sub-sampled preview tracks.
+//!
+//! A full run over a mammalian genome takes a while, which is a slow way to find out that a
+//! parameter choice looks wrong in a browser. `--preview` makes a small track instead: either every
+//! Nth window, which covers the whole genome coarsely, or only the first stretch of each
+//! chromosome at full resolution. The preview is written instead of the full track, to the output
+//! path with `.preview` inserted before its extension, so it never overwrites a full track.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Which windows a preview keeps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preview {
+    /// Every Nth window of each sequence, starting with the first.
+    Every(u64),
+    /// Only the windows starting in the first this many bases of each sequence.
+    Prefix(u64),
+}
+
+impl Preview {
+    /// The number of bases of a sequence to read for the preview.
+    ///
+    /// # Arguments
+    ///
+    /// * `seq_len` - The length of the sequence.
+    /// * `window` - The number of bases each value is computed over, so that the last kept window
+    ///   of a prefix preview is complete.
+    pub fn bases(&self, seq_len: u64, window: u64) -> u64 {
+        match self {
+            Preview::Every(_) => seq_len,
+            Preview::Prefix(bases) => seq_len.min(bases.saturating_add(window.saturating_sub(1))),
+        }
+    }
+
+    /// Whether the value of the window at a 0-based index is kept.
+    pub fn keeps(&self, index: u64) -> bool {
+        match self {
+            Preview::Every(n) => index.is_multiple_of(*n),
+            Preview::Prefix(bases) => index < *bases,
+        }
+    }
+
+    /// The distance between kept values, which tracks written as fixed steps use as their step.
+    pub fn step(&self) -> u64 {
+        match self {
+            Preview::Every(n) => *n,
+            Preview::Prefix(_) => 1,
+        }
+    }
+
+    /// Keeps the values of one sequence that belong in the preview.
+    ///
+    /// # Returns
+    ///
+    /// The kept values with their 0-based window indexes.
+    pub fn sample<I>(&self, values: I) -> impl Iterator<Item = (u64, f64)>
+    where
+        I: IntoIterator<Item = f64>,
+    {
+        let preview = *self;
+        (0u64..)
+            .zip(values)
+            .take_while(move |&(i, _)| !matches!(preview, Preview::Prefix(bases) if i >= bases))
+            .filter(move |&(i, _)| preview.keeps(i))
+    }
+
+    /// Derives the path of the preview from the main output path, so that `out.bw` becomes
+    /// `out.preview.bw`.
+    pub fn path(output: &Path) -> PathBuf {
+        let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match output.extension() {
+            Some(ext) => format!("{}.preview.{}", stem, ext.to_string_lossy()),
+            None => format!("{}.preview", stem),
+        };
+        output.with_file_name(name)
+    }
+}
+
+impl fmt::Display for Preview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Preview::Every(n) => write!(f, "one window in {}", n),
+            Preview::Prefix(bases) => write!(f, "first {} bp of each sequence", bases),
+        }
+    }
+}
+
+impl FromStr for Preview {
+    type Err = String;
+
+    /// Parses a plain number `N` as every Nth window, and a length with a `kb`, `Mb` or `Gb`
+    /// suffix as that many bases from the start of each sequence, e.g. `20` or `5Mb`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid preview '{}': expected N for every Nth window or a length such as 5Mb",
+                s
+            )
+        };
+        let lower = s.trim().to_ascii_lowercase();
+        let (number, scale) = if let Some(n) = lower.strip_suffix("gb") {
+            (n, Some(1_000_000_000.0))
+        } else if let Some(n) = lower.strip_suffix("mb") {
+            (n, Some(1_000_000.0))
+        } else if let Some(n) = lower.strip_suffix("kb") {
+            (n, Some(1_000.0))
+        } else if let Some(n) = lower.strip_suffix("bp") {
+            (n, Some(1.0))
+        } else {
+            (lower.as_str(), None)
+        };
+        match scale {
+            None => match number.parse::<u64>() {
+                Ok(n) if n > 0 => Ok(Preview::Every(n)),
+                _ => Err(invalid()),
+            },
+            Some(scale) => {
+                let value: f64 = number.trim().parse().map_err(|_| invalid())?;
+                let bases = (value * scale).round();
+                if !bases.is_finite() || bases < 1.0 || bases > u64::MAX as f64 {
+                    return Err(invalid());
+                }
+                Ok(Preview::Prefix(bases as u64))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("20".parse(), Ok(Preview::Every(20)));
+        assert_eq!("5Mb".parse(), Ok(Preview::Prefix(5_000_000)));
+        assert_eq!("2.5kb".parse(), Ok(Preview::Prefix(2_500)));
+        assert_eq!("1gb".parse(), Ok(Preview::Prefix(1_000_000_000)));
+        assert_eq!("300bp".parse(), Ok(Preview::Prefix(300)));
+        assert!("0".parse::<Preview>().is_err());
+        assert!("-3".parse::<Preview>().is_err());
+        assert!("5MB per chrom".parse::<Preview>().is_err());
+        assert!("0Mb".parse::<Preview>().is_err());
+    }
+
+    #[test]
+    fn test_sample() {
+        let values = (0..10).map(|i| i as f64);
+        let kept: Vec<_> = Preview::Every(4).sample(values.clone()).collect();
+        assert_eq!(kept, vec![(0, 0.0), (4, 4.0), (8, 8.0)]);
+        let kept: Vec<_> = Preview::Prefix(3).sample(values).collect();
+        assert_eq!(kept, vec![(0, 0.0), (1, 1.0), (2, 2.0)]);
+        assert_eq!(Preview::Every(4).step(), 4);
+        assert_eq!(Preview::Prefix(3).step(), 1);
+    }
+
+    #[test]
+    fn test_bases() {
+        // the last kept window of a prefix must be read in full
+        assert_eq!(Preview::Prefix(1000).bases(1_000_000, 51), 1050);
+        assert_eq!(Preview::Prefix(1000).bases(500, 51), 500);
+        assert_eq!(Preview::Every(10).bases(1_000_000, 51), 1_000_000);
+    }
+
+    #[test]
+    fn test_path() {
+        assert_eq!(
+            Preview::path(Path::new("results/out.bw")),
+            PathBuf::from("results/out.preview.bw")
+        );
+        assert_eq!(
+            Preview::path(Path::new("out")),
+            PathBuf::from("out.preview")
+        );
+    }
+}
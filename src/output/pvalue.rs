@@ -0,0 +1,118 @@
+//! A -log10(p) signal track against a background model.
+//!
+//! Peak callers and the ENCODE pipelines that consume MACS-style output expect a signal p-value
+//! track next to the raw signal. Given `--background`, a file of curvature values computed on
+//! background sequence (a shuffled genome, say, or intergenic regions), each value is also
+//! written as `-log10(p)`, where `p` is the fraction of background values at least as large.
+//!
+//! The background file holds one value per line, or per row as the last column of a TSV or
+//! bedGraph track, so earlier symcurve outputs can be used as they are. Blank lines, `#`
+//! comments, `track` and `browser` lines and missing values (`NaN`, `NA`, `.`) are skipped.
+
+use crate::output::bed::EmpiricalNull;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+/// Reads a background model.
+///
+/// # Errors
+///
+/// Returns an `io::ErrorKind::InvalidData` error naming the line if a value is not a number, or
+/// if the file has no values at all.
+pub fn read_background<R: BufRead>(reader: R) -> io::Result<EmpiricalNull> {
+    let mut values = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        let field = line.split_whitespace().last().unwrap_or_default();
+        if matches!(field, "NaN" | "nan" | "NA" | ".") {
+            continue;
+        }
+        let value = field.parse::<f64>().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "background line {}: '{}' is not a number",
+                    number + 1,
+                    field
+                ),
+            )
+        })?;
+        values.push(value);
+    }
+    if values.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "background has no values",
+        ));
+    }
+    Ok(EmpiricalNull::new(values))
+}
+
+/// Converts values to `-log10(p)` against a background model. `NaN` is passed through.
+pub fn neg_log10_p<'a, I>(values: I, null: &'a EmpiricalNull) -> impl Iterator<Item = f64> + 'a
+where
+    I: Iterator<Item = f64> + 'a,
+{
+    values.map(move |value| {
+        if value.is_nan() {
+            value
+        } else {
+            -null.p_value(value).log10()
+        }
+    })
+}
+
+/// Derives the path of the p-value track from the main output path, so that `out.bw` becomes
+/// `out.pval.bw`.
+pub fn pvalue_path(output: &Path) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    match output.extension() {
+        Some(ext) => output.with_file_name(format!("{}.pval.{}", stem, ext.to_string_lossy())),
+        None => output.with_file_name(format!("{}.pval", stem)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_read_background() {
+        let text =
+            "# shuffled chr1\ntrack type=bedGraph\n1.5\n\nchr1\t10\t11\t3\nchr1\t11\t12\tNaN\n2\n";
+        let null = read_background(text.as_bytes()).unwrap();
+        // three values, one of them at least 3
+        assert_relative_eq!(null.p_value(3.0), 2.0 / 4.0);
+        let err = read_background("1.0\nhigh\n".as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+        assert!(read_background("# nothing\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_neg_log10_p() {
+        let null = EmpiricalNull::new((0..99).map(f64::from));
+        let track: Vec<f64> =
+            neg_log10_p(vec![1000.0, -1.0, f64::NAN].into_iter(), &null).collect();
+        assert_relative_eq!(track[0], 2.0);
+        assert_relative_eq!(track[1], 0.0);
+        assert!(track[2].is_nan());
+    }
+
+    #[test]
+    fn test_pvalue_path() {
+        assert_eq!(
+            pvalue_path(Path::new("dir/out.bw")),
+            PathBuf::from("dir/out.pval.bw")
+        );
+        assert_eq!(pvalue_path(Path::new("out")), PathBuf::from("out.pval"));
+    }
+}
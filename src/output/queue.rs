@@ -0,0 +1,200 @@
+//! A bounded hand-off between computation and output writing.
+//!
+//! Computed values wait in the queue until the writer gets to them. On a slow network filesystem
+//! the writer can fall far behind, so the queue is bounded by the total size of what it holds
+//! (`--max-memory`) rather than by a number of items: when it is full, computation blocks until the
+//! writer catches up instead of letting memory use grow without limit.
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Condvar, Mutex};
+
+/// The contents of a `BoundedQueue`.
+///
+/// # Fields
+///
+/// * `items`: The queued items with their sizes in bytes.
+/// * `bytes`: The total size of the queued items.
+/// * `peak_bytes`: The largest total size held at once.
+/// * `closed`: Whether the producer has finished.
+struct QueueState<T> {
+    items: VecDeque<(T, usize)>,
+    bytes: usize,
+    peak_bytes: usize,
+    closed: bool,
+}
+
+/// A multi-producer, multi-consumer queue bounded by the total size of its items.
+///
+/// # Fields
+///
+/// * `capacity`: The most bytes the queue holds before `push` blocks.
+/// * `state`: The queued items and bookkeeping.
+/// * `not_full`: Signalled when items are taken out.
+/// * `not_empty`: Signalled when items are put in or the queue is closed.
+pub struct BoundedQueue<T> {
+    capacity: usize,
+    state: Mutex<QueueState<T>>,
+    not_full: Condvar,
+    not_empty: Condvar,
+}
+
+impl<T> BoundedQueue<T> {
+    /// Constructor for `BoundedQueue`.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The most bytes the queue holds before `push` blocks.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(QueueState {
+                items: VecDeque::new(),
+                bytes: 0,
+                peak_bytes: 0,
+                closed: false,
+            }),
+            not_full: Condvar::new(),
+            not_empty: Condvar::new(),
+        }
+    }
+
+    /// Adds an item, blocking while it would take the queue over capacity.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The item.
+    /// * `bytes` - The size of the item in bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::ErrorKind::InvalidInput` error if the item is larger than the whole
+    /// capacity, which the queue could never hold; producers split their items to fit.
+    pub fn push(&self, item: T, bytes: usize) -> io::Result<()> {
+        if bytes > self.capacity {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} bytes cannot be held within a memory cap of {} bytes",
+                    bytes, self.capacity
+                ),
+            ));
+        }
+        let mut state = self.state.lock().unwrap();
+        while state.bytes + bytes > self.capacity {
+            state = self.not_full.wait(state).unwrap();
+        }
+        state.bytes += bytes;
+        state.peak_bytes = state.peak_bytes.max(state.bytes);
+        state.items.push_back((item, bytes));
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Takes the oldest item, blocking until there is one. Returns `None` once the queue is closed
+    /// and empty.
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some((item, bytes)) = state.items.pop_front() {
+                state.bytes -= bytes;
+                self.not_full.notify_all();
+                return Some(item);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.not_empty.wait(state).unwrap();
+        }
+    }
+
+    /// Marks the queue as finished: consumers get the remaining items and then `None`.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_empty.notify_all();
+    }
+
+    /// The largest total size the queue has held at once, in bytes.
+    pub fn peak_bytes(&self) -> usize {
+        self.state.lock().unwrap().peak_bytes
+    }
+}
+
+/// Parses a memory size such as `512M`, `2G` or `1.5GiB` into bytes.
+///
+/// Suffixes `K`, `M`, `G` and `T` are binary multiples, optionally followed by `B` or `iB`; a
+/// plain number is in bytes.
+pub fn parse_memory_size(s: &str) -> Result<usize, String> {
+    let upper = s.trim().to_ascii_uppercase();
+    let number = upper.trim_end_matches("IB").trim_end_matches('B');
+    let (digits, shift) = match number.chars().last() {
+        Some('K') => (&number[..number.len() - 1], 10),
+        Some('M') => (&number[..number.len() - 1], 20),
+        Some('G') => (&number[..number.len() - 1], 30),
+        Some('T') => (&number[..number.len() - 1], 40),
+        _ => (number, 0),
+    };
+    let value: f64 = digits
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid memory size '{}': expected e.g. 512M or 2G", s))?;
+    let bytes = value * (1u64 << shift) as f64;
+    if !bytes.is_finite() || bytes < 1.0 || bytes > usize::MAX as f64 {
+        return Err(format!("invalid memory size '{}'", s));
+    }
+    Ok(bytes as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_queue_bounded() {
+        let queue = BoundedQueue::new(64);
+        let received = thread::scope(|scope| {
+            let consumer = scope.spawn(|| {
+                let mut received = Vec::new();
+                while let Some(chunk) = queue.pop() {
+                    // a slow writer
+                    thread::sleep(std::time::Duration::from_micros(200));
+                    received.push(chunk);
+                }
+                received
+            });
+            for i in 0..100u64 {
+                queue.push(vec![i as f64; 2], 16).unwrap();
+            }
+            queue.close();
+            consumer.join().unwrap()
+        });
+        assert_eq!(received.len(), 100);
+        assert_eq!(received[99], vec![99.0, 99.0]);
+        assert!(queue.peak_bytes() <= 64);
+    }
+
+    #[test]
+    fn test_queue_oversized_item() {
+        let queue = BoundedQueue::new(8);
+        let err = queue.push("big", 100).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        queue.push("small", 8).unwrap();
+        queue.close();
+        assert_eq!(queue.pop(), Some("small"));
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.peak_bytes(), 8);
+    }
+
+    #[test]
+    fn test_parse_memory_size() {
+        assert_eq!(parse_memory_size("1024"), Ok(1024));
+        assert_eq!(parse_memory_size("64K"), Ok(64 << 10));
+        assert_eq!(parse_memory_size("512m"), Ok(512 << 20));
+        assert_eq!(parse_memory_size("2GB"), Ok(2 << 30));
+        assert_eq!(parse_memory_size("1.5GiB"), Ok(3 << 29));
+        assert!(parse_memory_size("lots").is_err());
+        assert!(parse_memory_size("0").is_err());
+        assert!(parse_memory_size("-1G").is_err());
+    }
+}
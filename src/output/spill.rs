@@ -0,0 +1,295 @@
+//! Spilling computed values to disk.
+//!
+//! Records written in another order than the input's, or corrected once the whole input is
+//! known, are held until it is read, and holding a chromosome of `f64` values costs 8 bytes per
+//! base: about 2 GB for human chr1, and far more for some plant chromosomes. With `--spill-dir`,
+//! they are instead appended to a temporary file and read back one chromosome at a time. Records
+//! that are only sorted stream their values into the file as they are computed and back out of
+//! it straight into the outputs, so peak memory no longer depends on chromosome size. Records
+//! corrected for composition are computed and corrected whole, so the largest of them is still
+//! held once, bases and all, but no more than that.
+//!
+//! Values are stored as little-endian `f64`, so they read back exactly as computed, and bases as
+//! they were read. The file is
+//! read with ordinary buffered reads rather than memory-mapped: the values are only ever read in
+//! order, which the page cache already serves well, and it keeps the crate free of `unsafe`.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The size of a stored value, in bytes.
+const VALUE_BYTES: u64 = 8;
+
+/// Distinguishes the spill files of one process.
+static SPILL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A chromosome stored in a `SpillFile`.
+///
+/// # Fields
+///
+/// * `name`: The chromosome name.
+/// * `start`: The offset of its first value or base in the file, in bytes.
+/// * `len`: The number of values or bases.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpilledChrom {
+    pub name: String,
+    pub start: u64,
+    pub len: u64,
+}
+
+/// A temporary file of computed values, removed when dropped.
+///
+/// # Fields
+///
+/// * `writer`: Buffered writer appending to the file.
+/// * `path`: Where the file is.
+/// * `chroms`: The values of the chromosomes stored so far, in the order they were written.
+/// * `bases`: The bases of the chromosomes stored so far, in the order they were written.
+/// * `bytes`: The size of the file.
+pub struct SpillFile {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    chroms: Vec<SpilledChrom>,
+    bases: Vec<SpilledChrom>,
+    bytes: u64,
+}
+
+impl SpillFile {
+    /// Creates an empty spill file in `dir`.
+    pub fn create(dir: &Path) -> io::Result<Self> {
+        let path = dir.join(format!(
+            ".symcurve.{}.{}.spill",
+            process::id(),
+            SPILL_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            path,
+            chroms: Vec::new(),
+            bases: Vec::new(),
+            bytes: 0,
+        })
+    }
+
+    /// Where the file is.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The chromosomes stored so far.
+    pub fn chroms(&self) -> &[SpilledChrom] {
+        &self.chroms
+    }
+
+    /// The size of the stored values and bases, in bytes.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Starts storing the values of a chromosome, which `push` then appends one by one. They are
+    /// stored in one piece, so all of them are pushed before anything else is written.
+    pub fn start_chrom(&mut self, name: &str) {
+        self.chroms.push(SpilledChrom {
+            name: name.to_string(),
+            start: self.bytes,
+            len: 0,
+        });
+    }
+
+    /// Appends a value to the chromosome started last.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no chromosome was started.
+    pub fn push(&mut self, value: f64) -> io::Result<()> {
+        let chrom = self
+            .chroms
+            .last_mut()
+            .expect("a chromosome is started before its values");
+        self.writer.write_all(&value.to_le_bytes())?;
+        self.bytes += VALUE_BYTES;
+        chrom.len += 1;
+        Ok(())
+    }
+
+    /// Appends the values of a chromosome.
+    ///
+    /// # Returns
+    ///
+    /// The number of values written.
+    pub fn write_chrom<I: Iterator<Item = f64>>(
+        &mut self,
+        name: &str,
+        values: I,
+    ) -> io::Result<u64> {
+        self.start_chrom(name);
+        for value in values {
+            self.push(value)?;
+        }
+        Ok(self.chroms.last().map_or(0, |chrom| chrom.len))
+    }
+
+    /// Appends the bases of a chromosome, which are kept apart from its values.
+    pub fn write_bases(&mut self, name: &str, bases: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bases)?;
+        self.bases.push(SpilledChrom {
+            name: name.to_string(),
+            start: self.bytes,
+            len: bases.len() as u64,
+        });
+        self.bytes += bases.len() as u64;
+        Ok(())
+    }
+
+    /// Reads back the bases of a stored chromosome.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `NotFound` error if no bases of that name were stored, or any error from
+    /// flushing or reading the file.
+    pub fn read_bases(&mut self, name: &str) -> io::Result<Vec<u8>> {
+        let (mut file, len) = self.open(Spilled::Bases, name)?;
+        let mut bases = vec![0; len as usize];
+        file.read_exact(&mut bases)?;
+        Ok(bases)
+    }
+
+    /// Reads back the values of a stored chromosome, in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `NotFound` error if no chromosome of that name was stored, or any error from
+    /// flushing the file or opening it for reading.
+    pub fn read_chrom(&mut self, name: &str) -> io::Result<SpillReader> {
+        let (file, len) = self.open(Spilled::Values, name)?;
+        Ok(SpillReader {
+            reader: BufReader::new(file),
+            remaining: len,
+        })
+    }
+
+    /// Helper to `read_chrom()` and `read_bases()` that opens the file at the stored values or
+    /// bases of `name`, giving their number too.
+    fn open(&mut self, spilled: Spilled, name: &str) -> io::Result<(File, u64)> {
+        let list = match spilled {
+            Spilled::Values => &self.chroms,
+            Spilled::Bases => &self.bases,
+        };
+        let chrom = list
+            .iter()
+            .find(|chrom| chrom.name == name)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, format!("{} was not spilled", name))
+            })?;
+        self.writer.flush()?;
+        // a handle of its own, since a cloned one would share the writer's position
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(chrom.start))?;
+        Ok((file, chrom.len))
+    }
+}
+
+/// What of a chromosome a `SpillFile` is asked for.
+#[derive(Clone, Copy)]
+enum Spilled {
+    Values,
+    Bases,
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Iterator over the values of one chromosome of a `SpillFile`.
+///
+/// # Fields
+///
+/// * `reader`: Buffered reader positioned at the next value.
+/// * `remaining`: The number of values left.
+pub struct SpillReader {
+    reader: BufReader<File>,
+    remaining: u64,
+}
+
+impl Iterator for SpillReader {
+    type Item = io::Result<f64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let mut bytes = [0u8; VALUE_BYTES as usize];
+        Some(
+            self.reader
+                .read_exact(&mut bytes)
+                .map(|()| f64::from_le_bytes(bytes)),
+        )
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.remaining as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spill_round_trip() {
+        let dir = std::env::temp_dir();
+        let mut spill = SpillFile::create(&dir).unwrap();
+        let path = spill.path().to_path_buf();
+        let chr1: Vec<f64> = (0..10_000).map(|i| (i as f64).sqrt()).collect();
+        assert_eq!(
+            spill.write_chrom("chr1", chr1.iter().copied()).unwrap(),
+            10_000
+        );
+        let chr2 = [f64::NAN, -0.5, f64::INFINITY];
+        spill.write_chrom("chr2", chr2.into_iter()).unwrap();
+        assert_eq!(spill.bytes(), 10_003 * 8);
+        assert_eq!(spill.chroms()[1].start, 10_000 * 8);
+        // chromosomes read back exactly, in any order
+        let values: Vec<f64> = spill
+            .read_chrom("chr2")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert!(values[0].is_nan());
+        assert_eq!(values[1..], chr2[1..]);
+        let values: Vec<f64> = spill
+            .read_chrom("chr1")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(values, chr1);
+        // and writing can carry on after reading, value by value and with bases
+        spill.start_chrom("chr3");
+        spill.push(1.0).unwrap();
+        spill.push(2.0).unwrap();
+        spill.write_bases("chr3", b"ACGTN").unwrap();
+        let values: Vec<f64> = spill
+            .read_chrom("chr3")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(values, [1.0, 2.0]);
+        assert_eq!(spill.read_bases("chr3").unwrap(), b"ACGTN");
+        assert!(spill.read_bases("chr1").is_err());
+        assert!(spill.read_chrom("chrM").is_err());
+        assert!(path.exists());
+        drop(spill);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,593 @@
+//! SQLite database output.
+//!
+//! Values are written to a table `curvature(chrom, pos, value)` keyed by `(chrom, pos)`, so
+//! regions can be queried with SQL, e.g. `SELECT pos, value FROM curvature WHERE chrom = 'chr2'
+//! AND pos BETWEEN 1000 AND 2000`, without converting formats first. `pos` is 1-based like the
+//! TSV outputs and `value` is `NULL` where no value could be computed. DuckDB reads the file
+//! through its `sqlite` extension.
+//!
+//! The crate has no SQLite dependency, so the database file is written directly in the SQLite
+//! file format. The table is declared `WITHOUT ROWID`, which makes its primary key the b-tree the
+//! rows are stored in: no separate index is needed, and the rows are found by region in
+//! logarithmic time. A b-tree has to be loaded in key order, but sequences arrive in input order,
+//! so values are spilled to a temporary file as they come and the tree is built bottom-up from
+//! the sorted sequences when the output is finished.
+
+use crate::output::atomic::AtomicFile;
+use crate::output::spill::SpillFile;
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The page size of the database.
+pub const PAGE_SIZE: usize = 4096;
+
+/// The name of the table.
+pub const TABLE: &str = "curvature";
+
+/// The statement the table is declared with.
+const CREATE_TABLE: &str = "CREATE TABLE curvature(chrom TEXT NOT NULL, pos INTEGER NOT NULL, \
+                            value REAL, PRIMARY KEY (chrom, pos)) WITHOUT ROWID";
+
+/// The version of SQLite the file format follows, as recorded in the header.
+const SQLITE_VERSION_NUMBER: u32 = 3_040_001;
+
+/// B-tree page types.
+const INDEX_INTERIOR: u8 = 0x02;
+const INDEX_LEAF: u8 = 0x0A;
+const TABLE_LEAF: u8 = 0x0D;
+
+/// The largest payload an index b-tree cell holds without overflow pages, which this writer does
+/// not produce.
+const MAX_LOCAL: usize = (PAGE_SIZE - 12) * 64 / 255 - 23;
+
+/// Writes values to a SQLite database.
+///
+/// Values are spilled as they are written; the database appears at its path only when
+/// [`SqliteWriter::finish`] is called.
+///
+/// # Fields
+///
+/// * `path`: Where the database is written.
+/// * `spill`: The values written so far.
+/// * `offsets`: The 0-based position of the first value of each sequence, in spill order.
+/// * `names`: The sequence names written so far, to reject duplicates.
+pub struct SqliteWriter {
+    path: PathBuf,
+    spill: SpillFile,
+    offsets: Vec<u64>,
+    names: HashSet<String>,
+}
+
+impl SqliteWriter {
+    /// Starts a database at `path`, spilling values to a temporary file in `spill_dir`.
+    pub fn create(path: &Path, spill_dir: &Path) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            spill: SpillFile::create(spill_dir)?,
+            offsets: Vec::new(),
+            names: HashSet::new(),
+        })
+    }
+
+    /// Where the database is written.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds the values of one sequence.
+    ///
+    /// # Arguments
+    ///
+    /// * `chrom` - The sequence name written in the `chrom` column.
+    /// * `offset` - The 0-based position of the first value within `chrom`.
+    /// * `values` - One value per position; `NaN` is written as `NULL`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidInput` error if a sequence of the same name was already written, which
+    /// would repeat keys, or if the name is too long for a b-tree cell.
+    pub fn write_chrom(&mut self, chrom: &str, offset: u64, values: &[f64]) -> io::Result<()> {
+        if record_capacity(chrom.len()) > MAX_LOCAL {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: sequence name too long for the SQLite output", chrom),
+            ));
+        }
+        if !self.names.insert(chrom.to_string()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: sequence written twice to the SQLite output", chrom),
+            ));
+        }
+        self.spill.write_chrom(chrom, values.iter().copied())?;
+        self.offsets.push(offset);
+        Ok(())
+    }
+
+    /// Builds the database from the spilled values and moves it to its path.
+    ///
+    /// # Returns
+    ///
+    /// The number of rows written.
+    pub fn finish(mut self) -> io::Result<u64> {
+        let mut chroms: Vec<(String, u64, u64)> = self
+            .spill
+            .chroms()
+            .iter()
+            .zip(&self.offsets)
+            .map(|(chrom, &offset)| (chrom.name.clone(), offset, chrom.len))
+            .collect();
+        // the BINARY collation compares text bytewise, as `str` ordering does
+        chroms.sort();
+        let rows: u64 = chroms.iter().map(|(_, _, len)| len).sum();
+        let longest = chroms
+            .iter()
+            .map(|(name, _, _)| name.len())
+            .max()
+            .unwrap_or(0);
+        let plan = TreePlan::new(rows, longest);
+        let mut file = AtomicFile::create(&self.path)?;
+        file.write_all(&schema_page(plan.pages(), plan.root()))?;
+        let mut builder = TreeBuilder::new(plan, file);
+        for (name, offset, _) in &chroms {
+            let values = self.spill.read_chrom(name)?;
+            for (i, value) in values.enumerate() {
+                builder.add(encode_record(name, offset + i as u64 + 1, value?))?;
+            }
+        }
+        builder.finish()?.commit()?;
+        Ok(rows)
+    }
+}
+
+/// The largest record of a row with a sequence name of `name_len` bytes, with the varint of its
+/// size, which bounds the size of its cell.
+fn record_capacity(name_len: usize) -> usize {
+    let header = 1 + varint_len(2 * name_len as u64 + 13) + 2;
+    let payload = header + name_len + 16;
+    varint_len(payload as u64) + payload
+}
+
+/// Splits `total` items as evenly as possible over `parts`, returning the share of part `index`.
+fn share(total: u64, parts: u64, index: u64) -> u64 {
+    total / parts + u64::from(index < total % parts)
+}
+
+/// The shape of the b-tree holding a number of rows.
+///
+/// Every page is given an even share of its level, which also keeps every page but an empty
+/// root non-empty, as SQLite requires.
+///
+/// # Fields
+///
+/// * `rows`: The number of rows.
+/// * `levels`: The number of pages on each level, leaves first and the root last.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct TreePlan {
+    rows: u64,
+    levels: Vec<u64>,
+}
+
+impl TreePlan {
+    /// Plans the tree for `rows` rows, whose sequence names are at most `longest` bytes.
+    fn new(rows: u64, longest: usize) -> Self {
+        let cell = record_capacity(longest);
+        let leaf_cells = ((PAGE_SIZE - 8) / (cell + 2)) as u64;
+        let interior_cells = ((PAGE_SIZE - 12) / (cell + 4 + 2)) as u64;
+        // every leaf but the last is followed by a row moved up as a divider
+        let mut levels = vec![(rows + 1).div_ceil(leaf_cells + 1)];
+        while let Some(&pages) = levels.last().filter(|&&pages| pages > 1) {
+            levels.push(pages.div_ceil(interior_cells + 1));
+        }
+        Self { rows, levels }
+    }
+
+    /// The number of pages of the database, the schema page included.
+    fn pages(&self) -> u64 {
+        1 + self.levels.iter().sum::<u64>()
+    }
+
+    /// The page number of the root, which is written last.
+    fn root(&self) -> u64 {
+        self.pages()
+    }
+
+    /// The number of children of page `index` on level `level`, or of rows for a leaf.
+    fn fill(&self, level: usize, index: u64) -> u64 {
+        match level {
+            // dividers take one row for every leaf but the last
+            0 => share(self.rows + 1 - self.levels[0], self.levels[0], index),
+            _ => share(self.levels[level - 1], self.levels[level], index),
+        }
+    }
+}
+
+/// A page being filled with cells from the end, its cell pointers from the start.
+///
+/// # Fields
+///
+/// * `cells`: The encoded cells, in key order.
+/// * `index`: The index of the page on its level.
+/// * `children`: The number of children added, for interior pages.
+struct PageBuilder {
+    cells: Vec<Vec<u8>>,
+    index: u64,
+    children: u64,
+}
+
+impl PageBuilder {
+    fn new() -> Self {
+        Self {
+            cells: Vec::new(),
+            index: 0,
+            children: 0,
+        }
+    }
+
+    /// Encodes the page.
+    fn encode(&self, page_type: u8, right_child: Option<u32>) -> Vec<u8> {
+        let mut page = vec![0u8; PAGE_SIZE];
+        let header_len = if right_child.is_some() { 12 } else { 8 };
+        let mut content = PAGE_SIZE;
+        for (i, cell) in self.cells.iter().enumerate() {
+            content -= cell.len();
+            page[content..content + cell.len()].copy_from_slice(cell);
+            let pointer = header_len + 2 * i;
+            page[pointer..pointer + 2].copy_from_slice(&(content as u16).to_be_bytes());
+        }
+        page[0] = page_type;
+        page[3..5].copy_from_slice(&(self.cells.len() as u16).to_be_bytes());
+        // a content area starting at 65536 would be written as 0
+        page[5..7].copy_from_slice(&(content as u16).to_be_bytes());
+        if let Some(child) = right_child {
+            page[8..12].copy_from_slice(&child.to_be_bytes());
+        }
+        page
+    }
+}
+
+/// Builds a b-tree bottom-up from rows given in key order, following a `TreePlan`.
+///
+/// Pages are numbered in the order they are completed, from 2, and written as they complete.
+///
+/// # Fields
+///
+/// * `plan`: The shape of the tree.
+/// * `levels`: The page being filled on each level.
+/// * `writer`: Where pages are written.
+/// * `next_page`: The number the next completed page gets.
+/// * `divider`: The level and left child of the next divider, when the next row is one.
+struct TreeBuilder<W: Write> {
+    plan: TreePlan,
+    levels: Vec<PageBuilder>,
+    writer: W,
+    next_page: u32,
+    divider: Option<(usize, u32)>,
+}
+
+impl<W: Write> TreeBuilder<W> {
+    fn new(plan: TreePlan, writer: W) -> Self {
+        let levels = plan.levels.iter().map(|_| PageBuilder::new()).collect();
+        Self {
+            plan,
+            levels,
+            writer,
+            next_page: 2,
+            divider: None,
+        }
+    }
+
+    /// Helper to `add()` and `finish()` that writes a completed page and returns its number.
+    fn write_page(&mut self, level: usize, right_child: Option<u32>) -> io::Result<u32> {
+        let page_type = if level == 0 {
+            INDEX_LEAF
+        } else {
+            INDEX_INTERIOR
+        };
+        let page = &mut self.levels[level];
+        self.writer
+            .write_all(&page.encode(page_type, right_child))?;
+        page.cells.clear();
+        page.index += 1;
+        page.children = 0;
+        self.next_page += 1;
+        Ok(self.next_page - 1)
+    }
+
+    /// Adds the next row in key order.
+    fn add(&mut self, record: Vec<u8>) -> io::Result<()> {
+        if let Some((level, left_child)) = self.divider.take() {
+            let mut cell = left_child.to_be_bytes().to_vec();
+            push_varint(&mut cell, record.len() as u64);
+            cell.extend_from_slice(&record);
+            self.levels[level].cells.push(cell);
+            return Ok(());
+        }
+        let mut cell = Vec::with_capacity(record.len() + 2);
+        push_varint(&mut cell, record.len() as u64);
+        cell.extend_from_slice(&record);
+        let leaf = &mut self.levels[0];
+        leaf.cells.push(cell);
+        if leaf.cells.len() as u64 == self.plan.fill(0, leaf.index) {
+            let mut child = self.write_page(0, None)?;
+            // complete the pages this leaf was the last child of, up to the level that takes the
+            // next row as a divider
+            for level in 1..self.levels.len() {
+                let page = &mut self.levels[level];
+                page.children += 1;
+                if page.children < self.plan.fill(level, page.index) {
+                    self.divider = Some((level, child));
+                    return Ok(());
+                }
+                child = self.write_page(level, Some(child))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the root if the tree has no rows, and returns the writer.
+    fn finish(mut self) -> io::Result<W> {
+        if self.plan.rows == 0 {
+            self.write_page(0, None)?;
+        }
+        debug_assert_eq!(u64::from(self.next_page), self.plan.pages() + 1);
+        Ok(self.writer)
+    }
+}
+
+/// Encodes a row as a record: the header of serial types, then the values.
+fn encode_record(chrom: &str, pos: u64, value: f64) -> Vec<u8> {
+    let pos = pos as i64;
+    let (pos_type, pos_bytes) = match pos {
+        -0x80..=0x7f => (1, 1),
+        -0x8000..=0x7fff => (2, 2),
+        -0x80_0000..=0x7f_ffff => (3, 3),
+        -0x8000_0000..=0x7fff_ffff => (4, 4),
+        -0x8000_0000_0000..=0x7fff_ffff_ffff => (5, 6),
+        _ => (6, 8),
+    };
+    let value_type = if value.is_nan() { 0 } else { 7 };
+    let mut types = Vec::with_capacity(4);
+    push_varint(&mut types, 2 * chrom.len() as u64 + 13);
+    types.push(pos_type);
+    types.push(value_type);
+    // the header size counts itself, and stays below 128 so its varint is one byte
+    let mut record = vec![(types.len() + 1) as u8];
+    record.extend_from_slice(&types);
+    record.extend_from_slice(chrom.as_bytes());
+    record.extend_from_slice(&pos.to_be_bytes()[8 - pos_bytes..]);
+    if !value.is_nan() {
+        record.extend_from_slice(&value.to_be_bytes());
+    }
+    record
+}
+
+/// The length of a SQLite varint.
+fn varint_len(value: u64) -> usize {
+    let mut bytes = Vec::new();
+    push_varint(&mut bytes, value);
+    bytes.len()
+}
+
+/// Appends a SQLite varint: big-endian groups of 7 bits, the high bit set on all but the last,
+/// with a ninth byte holding 8 bits.
+fn push_varint(out: &mut Vec<u8>, value: u64) {
+    if value > 0x00ff_ffff_ffff_ffff {
+        let mut bytes = [0u8; 9];
+        bytes[8] = value as u8;
+        let mut rest = value >> 8;
+        for byte in bytes[..8].iter_mut().rev() {
+            *byte = (rest & 0x7f) as u8 | 0x80;
+            rest >>= 7;
+        }
+        out.extend_from_slice(&bytes);
+        return;
+    }
+    let mut groups = Vec::with_capacity(8);
+    let mut rest = value;
+    loop {
+        groups.push((rest & 0x7f) as u8);
+        rest >>= 7;
+        if rest == 0 {
+            break;
+        }
+    }
+    for (i, group) in groups.iter().enumerate().rev() {
+        out.push(if i > 0 { group | 0x80 } else { *group });
+    }
+}
+
+/// The first page: the database header, then the schema table with the one table.
+fn schema_page(pages: u64, root: u64) -> Vec<u8> {
+    let mut types = Vec::new();
+    let mut values = Vec::new();
+    for text in ["table", TABLE, TABLE] {
+        push_varint(&mut types, 2 * text.len() as u64 + 13);
+        values.extend_from_slice(text.as_bytes());
+    }
+    types.push(4);
+    values.extend_from_slice(&(root as u32).to_be_bytes());
+    push_varint(&mut types, 2 * CREATE_TABLE.len() as u64 + 13);
+    values.extend_from_slice(CREATE_TABLE.as_bytes());
+    let mut record = vec![(types.len() + 1) as u8];
+    record.extend_from_slice(&types);
+    record.extend_from_slice(&values);
+    let mut cell = Vec::new();
+    push_varint(&mut cell, record.len() as u64);
+    // rowid 1
+    cell.push(1);
+    cell.extend_from_slice(&record);
+    let mut page = vec![0u8; PAGE_SIZE];
+    page[..16].copy_from_slice(b"SQLite format 3\0");
+    page[16..18].copy_from_slice(&(PAGE_SIZE as u16).to_be_bytes());
+    // file format versions, reserved space and the payload fractions
+    page[18..24].copy_from_slice(&[1, 1, 0, 64, 32, 32]);
+    // file change counter, matched by the version-valid-for number below
+    page[24..28].copy_from_slice(&1u32.to_be_bytes());
+    page[28..32].copy_from_slice(&(pages as u32).to_be_bytes());
+    // schema cookie and schema format
+    page[40..44].copy_from_slice(&1u32.to_be_bytes());
+    page[44..48].copy_from_slice(&4u32.to_be_bytes());
+    // UTF-8 text
+    page[56..60].copy_from_slice(&1u32.to_be_bytes());
+    page[92..96].copy_from_slice(&1u32.to_be_bytes());
+    page[96..100].copy_from_slice(&SQLITE_VERSION_NUMBER.to_be_bytes());
+    let content = PAGE_SIZE - cell.len();
+    page[content..].copy_from_slice(&cell);
+    page[100] = TABLE_LEAF;
+    page[103..105].copy_from_slice(&1u16.to_be_bytes());
+    page[105..107].copy_from_slice(&(content as u16).to_be_bytes());
+    page[108..110].copy_from_slice(&(content as u16).to_be_bytes());
+    page
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn read_varint(bytes: &[u8], at: &mut usize) -> u64 {
+        let mut value = 0u64;
+        for i in 0..9 {
+            let byte = bytes[*at];
+            *at += 1;
+            if i == 8 {
+                return (value << 8) | u64::from(byte);
+            }
+            value = (value << 7) | u64::from(byte & 0x7f);
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        value
+    }
+
+    /// Decodes a record of this table.
+    fn decode_record(record: &[u8]) -> (String, u64, Option<f64>) {
+        let mut at = 1;
+        let text_type = read_varint(record, &mut at);
+        let (pos_type, value_type) = (record[at], record[at + 1]);
+        let mut at = record[0] as usize;
+        let len = ((text_type - 13) / 2) as usize;
+        let chrom = String::from_utf8(record[at..at + len].to_vec()).unwrap();
+        at += len;
+        let pos_len = [0, 1, 2, 3, 4, 6, 8][pos_type as usize];
+        let pos = record[at..at + pos_len]
+            .iter()
+            .fold(0u64, |pos, &b| (pos << 8) | u64::from(b));
+        at += pos_len;
+        let value =
+            (value_type == 7).then(|| f64::from_be_bytes(record[at..at + 8].try_into().unwrap()));
+        (chrom, pos, value)
+    }
+
+    /// Reads the rows of the b-tree rooted at `page` in order, returning its depth.
+    fn walk(db: &[u8], page: usize, rows: &mut Vec<(String, u64, Option<f64>)>) -> usize {
+        let base = (page - 1) * PAGE_SIZE;
+        let page_type = db[base];
+        let cells = u16::from_be_bytes([db[base + 3], db[base + 4]]) as usize;
+        let header_len = if page_type == INDEX_INTERIOR { 12 } else { 8 };
+        let mut depths = Vec::new();
+        for i in 0..cells {
+            let pointer = base + header_len + 2 * i;
+            let mut at = base + u16::from_be_bytes([db[pointer], db[pointer + 1]]) as usize;
+            if page_type == INDEX_INTERIOR {
+                let child = u32::from_be_bytes(db[at..at + 4].try_into().unwrap());
+                depths.push(walk(db, child as usize, rows));
+                at += 4;
+            } else {
+                assert_eq!(page_type, INDEX_LEAF);
+            }
+            let len = read_varint(db, &mut at) as usize;
+            rows.push(decode_record(&db[at..at + len]));
+        }
+        if page_type == INDEX_INTERIOR {
+            assert!(cells > 0);
+            let child = u32::from_be_bytes(db[base + 8..base + 12].try_into().unwrap());
+            depths.push(walk(db, child as usize, rows));
+            assert!(depths.windows(2).all(|d| d[0] == d[1]), "unbalanced tree");
+            return depths[0] + 1;
+        }
+        0
+    }
+
+    fn read_db(path: &Path) -> (Vec<(String, u64, Option<f64>)>, usize) {
+        let db = fs::read(path).unwrap();
+        assert!(db.starts_with(b"SQLite format 3\0"));
+        let pages = u32::from_be_bytes(db[28..32].try_into().unwrap()) as usize;
+        assert_eq!(db.len(), pages * PAGE_SIZE);
+        // the root page is the last field of the one schema record, before the SQL text
+        let cell = u16::from_be_bytes([db[108], db[109]]) as usize;
+        let sql = CREATE_TABLE.as_bytes();
+        let sql_at = db[cell..]
+            .windows(sql.len())
+            .position(|w| w == sql)
+            .unwrap()
+            + cell;
+        let root = u32::from_be_bytes(db[sql_at - 4..sql_at].try_into().unwrap()) as usize;
+        let mut rows = Vec::new();
+        let depth = walk(&db, root, &mut rows);
+        (rows, depth)
+    }
+
+    #[test]
+    fn test_varint() {
+        for (value, len) in [
+            (0, 1),
+            (127, 1),
+            (128, 2),
+            (16_383, 2),
+            (16_384, 3),
+            (u64::MAX, 9),
+        ] {
+            let mut bytes = Vec::new();
+            push_varint(&mut bytes, value);
+            assert_eq!(bytes.len(), len);
+            assert_eq!(read_varint(&bytes, &mut 0), value);
+        }
+        assert_eq!(encode_record("chr1", 300, 0.5)[..5], [4, 21, 2, 7, b'c']);
+    }
+
+    #[test]
+    fn test_tree_plan() {
+        for rows in [0, 1, 150, 151, 152, 10_000, 1_000_000] {
+            let plan = TreePlan::new(rows, 5);
+            let leaves: u64 = (0..plan.levels[0]).map(|i| plan.fill(0, i)).sum();
+            assert_eq!(leaves + plan.levels[0] - 1, rows);
+            assert_eq!(*plan.levels.last().unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn test_sqlite_output() {
+        let dir = std::env::temp_dir().join("symcurve_test_sqlite");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.sqlite");
+        let mut writer = SqliteWriter::create(&path, &dir).unwrap();
+        let chr2: Vec<f64> = (0..50_000).map(|i| i as f64 / 8.0).collect();
+        writer.write_chrom("chr2", 10, &chr2).unwrap();
+        writer.write_chrom("chr10", 0, &[f64::NAN, 1.5]).unwrap();
+        assert!(writer.write_chrom("chr2", 0, &[1.0]).is_err());
+        assert_eq!(writer.finish().unwrap(), 50_002);
+        let (rows, depth) = read_db(&path);
+        assert!(depth >= 2);
+        assert_eq!(rows.len(), 50_002);
+        // in key order: names bytewise, then positions
+        assert_eq!(rows[0], ("chr10".to_string(), 1, None));
+        assert_eq!(rows[1], ("chr10".to_string(), 2, Some(1.5)));
+        assert_eq!(rows[2], ("chr2".to_string(), 11, Some(0.0)));
+        assert_eq!(
+            rows[50_001],
+            ("chr2".to_string(), 50_010, Some(49_999.0 / 8.0))
+        );
+        assert!(rows
+            .windows(2)
+            .all(|w| (&w[0].0, w[0].1) < (&w[1].0, w[1].1)));
+        // an empty database is a lone root leaf
+        let writer = SqliteWriter::create(&path, &dir).unwrap();
+        assert_eq!(writer.finish().unwrap(), 0);
+        assert_eq!(read_db(&path), (Vec::new(), 0));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
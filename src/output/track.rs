@@ -0,0 +1,197 @@
+//! Track lines for text outputs.
+//!
+//! UCSC and IGV read display settings from the `track` line at the top of a wig or bedGraph file.
+//! The `--track-*`, `--view-limits` and `--auto-scale` options fill it in, so outputs can be
+//! loaded as custom tracks without editing their headers by hand.
+
+use clap::ValueEnum;
+use std::fmt;
+use std::str::FromStr;
+
+/// A track color, written as `R,G,B`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrackColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl FromStr for TrackColor {
+    type Err = String;
+
+    /// Parses `R,G,B` with each component from 0 to 255.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let components = s
+            .split(',')
+            .map(|c| c.trim().parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("invalid color '{}': components must be 0-255", s))?;
+        match components[..] {
+            [r, g, b] => Ok(Self { r, g, b }),
+            _ => Err(format!("invalid color '{}': expected R,G,B", s)),
+        }
+    }
+}
+
+impl fmt::Display for TrackColor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{},{}", self.r, self.g, self.b)
+    }
+}
+
+/// The default vertical range of a track, written as `MIN:MAX`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ViewLimits {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl FromStr for ViewLimits {
+    type Err = String;
+
+    /// Parses `MIN:MAX`, where `MIN` must be less than `MAX`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min, max) = s
+            .split_once(':')
+            .ok_or_else(|| format!("invalid range '{}': expected MIN:MAX", s))?;
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|v| v.is_finite())
+                .ok_or_else(|| format!("invalid range '{}': '{}' is not a number", s, v))
+        };
+        let (min, max) = (parse(min)?, parse(max)?);
+        if min >= max {
+            return Err(format!("invalid range '{}': MIN must be less than MAX", s));
+        }
+        Ok(Self { min, max })
+    }
+}
+
+impl fmt::Display for ViewLimits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.min, self.max)
+    }
+}
+
+/// Whether the browser rescales the track to the values in view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum AutoScale {
+    On,
+    Off,
+}
+
+/// Checks that track-line text can be written between double quotes.
+pub fn parse_track_text(s: &str) -> Result<String, String> {
+    if s.contains(['"', '\n', '\r']) {
+        Err("track text cannot contain double quotes or line breaks".to_owned())
+    } else {
+        Ok(s.to_owned())
+    }
+}
+
+/// The display settings written to the track line.
+///
+/// # Fields
+///
+/// * `name`: The track name shown in the browser.
+/// * `description`: The longer label shown above the track.
+/// * `color`: The track color.
+/// * `view_limits`: The default vertical range.
+/// * `auto_scale`: Whether the vertical range follows the values in view.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrackLine {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub color: Option<TrackColor>,
+    pub view_limits: Option<ViewLimits>,
+    pub auto_scale: Option<AutoScale>,
+}
+
+impl TrackLine {
+    /// Formats the track line, without a trailing newline.
+    ///
+    /// # Arguments
+    ///
+    /// * `track_type` - The track type, e.g. `bedGraph` or `wiggle_0`.
+    ///
+    /// # Returns
+    ///
+    /// The `track` line with the type first and only the settings that were given, e.g.
+    /// `track type=bedGraph name="curvature" color=0,0,255 autoScale=off`.
+    pub fn format(&self, track_type: &str) -> String {
+        let mut line = format!("track type={}", track_type);
+        if let Some(name) = &self.name {
+            line.push_str(&format!(" name=\"{}\"", name));
+        }
+        if let Some(description) = &self.description {
+            line.push_str(&format!(" description=\"{}\"", description));
+        }
+        if let Some(color) = &self.color {
+            line.push_str(&format!(" color={}", color));
+        }
+        if let Some(view_limits) = &self.view_limits {
+            line.push_str(&format!(" viewLimits={}", view_limits));
+        }
+        if let Some(auto_scale) = &self.auto_scale {
+            let value = match auto_scale {
+                AutoScale::On => "on",
+                AutoScale::Off => "off",
+            };
+            line.push_str(&format!(" autoScale={}", value));
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_track_line() {
+        assert_eq!(
+            TrackLine::default().format("bedGraph"),
+            "track type=bedGraph"
+        );
+        let track = TrackLine {
+            name: Some("curvature".to_string()),
+            description: Some("DNA curvature, sacCer3".to_string()),
+            color: Some("0,0,255".parse().unwrap()),
+            view_limits: Some("0:12.5".parse().unwrap()),
+            auto_scale: Some(AutoScale::Off),
+        };
+        assert_eq!(
+            track.format("wiggle_0"),
+            "track type=wiggle_0 name=\"curvature\" description=\"DNA curvature, sacCer3\" \
+             color=0,0,255 viewLimits=0:12.5 autoScale=off"
+        );
+    }
+
+    #[test]
+    fn test_parse_settings() {
+        assert_eq!(
+            "255, 128,0".parse::<TrackColor>(),
+            Ok(TrackColor {
+                r: 255,
+                g: 128,
+                b: 0
+            })
+        );
+        assert!("256,0,0".parse::<TrackColor>().is_err());
+        assert!("0,0".parse::<TrackColor>().is_err());
+        assert_eq!(
+            "-1.5:3".parse::<ViewLimits>(),
+            Ok(ViewLimits {
+                min: -1.5,
+                max: 3.0
+            })
+        );
+        assert!("3:1".parse::<ViewLimits>().is_err());
+        assert!("1-3".parse::<ViewLimits>().is_err());
+        assert!("nan:3".parse::<ViewLimits>().is_err());
+        assert!(parse_track_text("my \"track\"").is_err());
+        assert_eq!(parse_track_text("my track"), Ok("my track".to_string()));
+    }
+}
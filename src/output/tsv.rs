@@ -0,0 +1,319 @@
+//! Tab-separated text output.
+//!
+//! TSV files carry one row per position with a 1-based coordinate, so they can be loaded
+//! straight into R/pandas or joined against other per-base tables.
+
+use crate::output::{FloatFormat, NaValue};
+use crate::provenance::Provenance;
+use std::io::{self, Write};
+
+/// Writes the per-position bend vectors of one sequence as TSV rows.
+///
+/// Each row is `chrom`, the 1-based position of the central base of the triplet, then `dx` and
+/// `dy`. No header is written so that rows from several sequences can be concatenated; use
+/// [`write_bend_header`] once at the top of the file.
+///
+/// # Arguments
+///
+/// * `writer` - Where the rows are written.
+/// * `chrom` - The sequence name written in the first column.
+/// * `offset` - The 0-based position of the first base of the sequence within `chrom`.
+/// * `bends` - The `(dx, dy)` vectors, the first one belonging to the triplet at `offset`.
+/// * `float` - How the vectors are written.
+pub fn write_bend_vectors<W, I>(
+    writer: &mut W,
+    chrom: &str,
+    offset: u64,
+    bends: I,
+    float: FloatFormat,
+) -> io::Result<()>
+where
+    W: Write,
+    I: Iterator<Item = (f64, f64)>,
+{
+    for (i, (dx, dy)) in bends.enumerate() {
+        // triplet i is centered on base i + 1, plus one more for 1-based output
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            chrom,
+            offset + i as u64 + 2,
+            float.format(dx),
+            float.format(dy)
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes a per-position track of one sequence as `chrom`, 1-based position, value rows.
+///
+/// Positions where no value could be computed are passed in as `NaN` and written according to
+/// `na`, which may leave those rows out entirely.
+///
+/// # Arguments
+///
+/// * `writer` - Where the rows are written.
+/// * `chrom` - The sequence name written in the first column.
+/// * `offset` - The 0-based position of the first value within `chrom`.
+/// * `values` - One value per position.
+/// * `na` - How missing values are written.
+/// * `float` - How the other values are written.
+pub fn write_track<W, I>(
+    writer: &mut W,
+    chrom: &str,
+    offset: u64,
+    values: I,
+    na: NaValue,
+    float: FloatFormat,
+) -> io::Result<()>
+where
+    W: Write,
+    I: Iterator<Item = f64>,
+{
+    for (i, value) in values.enumerate() {
+        if let Some(text) = na.format(value, float) {
+            writeln!(writer, "{}\t{}\t{}", chrom, offset + i as u64 + 1, text)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the curvature magnitude and accumulated direction change of each window of one sequence
+/// as TSV rows.
+///
+/// Each row is `chrom`, the 1-based position the window is reported at, then the magnitude and
+/// the signed direction change in radians. No header is written so that rows from several
+/// sequences can be concatenated; use [`write_magnitude_phase_header`] once at the top of the file.
+///
+/// # Arguments
+///
+/// * `writer` - Where the rows are written.
+/// * `chrom` - The sequence name written in the first column.
+/// * `offset` - The 0-based position the first window is reported at within `chrom`.
+/// * `rows` - The `(magnitude, phase)` pairs, one per window.
+/// * `float` - How the values are written.
+pub fn write_magnitude_phase<W, I>(
+    writer: &mut W,
+    chrom: &str,
+    offset: u64,
+    rows: I,
+    float: FloatFormat,
+) -> io::Result<()>
+where
+    W: Write,
+    I: Iterator<Item = (f64, f64)>,
+{
+    for (i, (magnitude, phase)) in rows.enumerate() {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            chrom,
+            offset + i as u64 + 1,
+            float.format(magnitude),
+            float.format(phase)
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the header for magnitude and phase TSV output: `#` comment lines with the provenance of
+/// the run, if given, followed by the column names.
+pub fn write_magnitude_phase_header<W: Write>(
+    writer: &mut W,
+    provenance: Option<&Provenance>,
+) -> io::Result<()> {
+    if let Some(provenance) = provenance {
+        provenance.write_comments(writer, "#")?;
+    }
+    writeln!(writer, "chrom\tpos\tmagnitude\tphase")
+}
+
+/// Writes rows of envelope TSV output.
+///
+/// Each row is `chrom`, the 1-based position the window is reported at, then the lower and upper
+/// envelope. No header is written so that rows from several sequences can be concatenated; use
+/// [`write_envelope_header`] once at the top of the file.
+///
+/// # Arguments
+///
+/// * `writer` - Where the rows are written.
+/// * `chrom` - The sequence name written in the first column.
+/// * `offset` - The 0-based position the first window is reported at within `chrom`.
+/// * `rows` - The `(lower, upper)` pairs, one per window.
+/// * `float` - How the values are written.
+pub fn write_envelope<W, I>(
+    writer: &mut W,
+    chrom: &str,
+    offset: u64,
+    rows: I,
+    float: FloatFormat,
+) -> io::Result<()>
+where
+    W: Write,
+    I: Iterator<Item = (f64, f64)>,
+{
+    for (i, (lower, upper)) in rows.enumerate() {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            chrom,
+            offset + i as u64 + 1,
+            float.format(lower),
+            float.format(upper)
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the header for envelope TSV output: `#` comment lines with the provenance of the run, if
+/// given, followed by the column names.
+pub fn write_envelope_header<W: Write>(
+    writer: &mut W,
+    provenance: Option<&Provenance>,
+) -> io::Result<()> {
+    if let Some(provenance) = provenance {
+        provenance.write_comments(writer, "#")?;
+    }
+    writeln!(writer, "chrom\tpos\tlower\tupper")
+}
+
+/// Writes the header for bend vector TSV output: `#` comment lines with the provenance of the
+/// run, if given, followed by the column names.
+pub fn write_bend_header<W: Write>(
+    writer: &mut W,
+    provenance: Option<&Provenance>,
+) -> io::Result<()> {
+    if let Some(provenance) = provenance {
+        provenance.write_comments(writer, "#")?;
+    }
+    writeln!(writer, "chrom\tpos\tdx\tdy")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_bend_vectors() {
+        let mut buf = Vec::new();
+        write_bend_header(&mut buf, None).unwrap();
+        write_bend_vectors(
+            &mut buf,
+            "chr1",
+            0,
+            vec![(0.5, -1.0), (0.0, 2.25)].into_iter(),
+            FloatFormat::Shortest,
+        )
+        .unwrap();
+        write_bend_vectors(
+            &mut buf,
+            "chr1",
+            100,
+            vec![(1.0, 1.0)].into_iter(),
+            FloatFormat::Shortest,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "chrom\tpos\tdx\tdy\nchr1\t2\t0.5\t-1\nchr1\t3\t0\t2.25\nchr1\t102\t1\t1\n"
+        );
+    }
+
+    #[test]
+    fn test_write_bend_header_provenance() {
+        let provenance = Provenance {
+            version: "0.1.0".to_string(),
+            parameters: Vec::new(),
+            matrices: "builtin".to_string(),
+        };
+        let mut buf = Vec::new();
+        write_bend_header(&mut buf, Some(&provenance)).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert!(text.starts_with("# symcurve version: 0.1.0\n"));
+        assert!(text.ends_with("\nchrom\tpos\tdx\tdy\n"));
+    }
+
+    #[test]
+    fn test_write_magnitude_phase() {
+        let mut buf = Vec::new();
+        write_magnitude_phase_header(&mut buf, None).unwrap();
+        write_magnitude_phase(
+            &mut buf,
+            "chr1",
+            21,
+            vec![(2.5, -0.125), (3.0, 0.5)].into_iter(),
+            FloatFormat::Shortest,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "chrom\tpos\tmagnitude\tphase\nchr1\t22\t2.5\t-0.125\nchr1\t23\t3\t0.5\n"
+        );
+    }
+
+    #[test]
+    fn test_write_envelope() {
+        let mut buf = Vec::new();
+        write_envelope_header(&mut buf, None).unwrap();
+        write_envelope(
+            &mut buf,
+            "chr1",
+            50,
+            vec![(0.25, 4.0)].into_iter(),
+            FloatFormat::Fixed(2),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "chrom\tpos\tlower\tupper\nchr1\t51\t0.25\t4.00\n"
+        );
+    }
+
+    #[test]
+    fn test_write_track_na() {
+        let values = vec![f64::NAN, 1.5, f64::NAN];
+        let mut buf = Vec::new();
+        write_track(
+            &mut buf,
+            "chr2",
+            10,
+            values.clone().into_iter(),
+            NaValue::Dot,
+            FloatFormat::Shortest,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "chr2\t11\t.\nchr2\t12\t1.5\nchr2\t13\t.\n"
+        );
+        let mut buf = Vec::new();
+        write_track(
+            &mut buf,
+            "chr2",
+            10,
+            values.into_iter(),
+            NaValue::Omit,
+            FloatFormat::Fixed(3),
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "chr2\t12\t1.500\n");
+    }
+
+    #[test]
+    fn test_write_track_large_offset() {
+        let mut buf = Vec::new();
+        write_track(
+            &mut buf,
+            "scaffold_1",
+            4_999_999_999,
+            vec![1.5].into_iter(),
+            NaValue::NaN,
+            FloatFormat::Shortest,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "scaffold_1\t5000000000\t1.5\n"
+        );
+    }
+}
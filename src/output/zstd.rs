@@ -0,0 +1,759 @@
+//! Zstandard compression of text outputs.
+//!
+//! Curvature TSV and bedGraph files are long runs of similar numeric lines. The encoder here
+//! leaves them somewhat larger than gzip does, since it has a small feature set, but costs a
+//! fraction of the time, and Zstandard decoders accept any number of concatenated frames. So output is cut into chunks of `FRAME_SIZE` bytes, each chunk
+//! is compressed on a worker thread into an independent frame, and the frames are written in
+//! order: `zstd -d`, `zstdcat` and the zstd libraries read the result as one stream.
+//!
+//! The crate has no Zstandard dependency, so the encoder here implements the format directly,
+//! with a deliberately small feature set: matches are found with a single hash table and the last
+//! offset, literals are Huffman coded, and sequences are coded with the format's predefined FSE
+//! tables, which need no table description in the block. That keeps it simple while still
+//! capturing the long repeats that dominate these files.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::{self, Write};
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// The size of the chunks compressed into independent frames.
+pub const FRAME_SIZE: usize = 1 << 20;
+
+/// The largest block of a frame.
+const BLOCK_SIZE: usize = 128 << 10;
+
+/// The magic number that starts every frame.
+const MAGIC: u32 = 0xFD2F_B528;
+
+/// The shortest match at a new offset worth coding as a sequence; shorter ones cost more than
+/// their Huffman coded literals.
+const MIN_MATCH: usize = 7;
+
+/// The number of bits of the match finder's hash.
+const HASH_LOG: u32 = 16;
+
+/// The longest Huffman code for literals the format allows.
+const MAX_HUFFMAN_BITS: u8 = 11;
+
+/// Block types.
+const BLOCK_RAW: u32 = 0;
+const BLOCK_COMPRESSED: u32 = 2;
+
+/// The predefined distribution of literal length codes, with its accuracy log.
+const LL_NORM: [i16; 36] = [
+    4, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, 1, 1, 1, 1, 1,
+    -1, -1, -1, -1,
+];
+const LL_LOG: u32 = 6;
+
+/// The predefined distribution of match length codes, with its accuracy log.
+const ML_NORM: [i16; 53] = [
+    1, 4, 3, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1, -1, -1,
+];
+const ML_LOG: u32 = 6;
+
+/// The predefined distribution of offset codes, with its accuracy log.
+const OF_NORM: [i16; 29] = [
+    1, 1, 1, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1,
+];
+const OF_LOG: u32 = 5;
+
+/// The smallest literal length of each literal length code, and its number of extra bits.
+const LL_BASE: [u32; 36] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 18, 20, 22, 24, 28, 32, 40, 48, 64,
+    128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768, 65536,
+];
+const LL_BITS: [u32; 36] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 3, 3, 4, 6, 7, 8, 9, 10, 11,
+    12, 13, 14, 15, 16,
+];
+
+/// The smallest match length of each match length code, and its number of extra bits.
+const ML_BASE: [u32; 53] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27,
+    28, 29, 30, 31, 32, 33, 34, 35, 37, 39, 41, 43, 47, 51, 59, 67, 83, 99, 131, 259, 515, 1027,
+    2051, 4099, 8195, 16387, 32771, 65539,
+];
+const ML_BITS: [u32; 53] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    1, 1, 1, 1, 2, 2, 3, 3, 4, 4, 5, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+];
+
+/// Writes Zstandard frames, compressing chunks on up to `threads` threads.
+///
+/// Call [`ZstdWriter::finish`] to write the last frame. Flushing also ends the current frame, so
+/// frequent flushes cost compression.
+///
+/// # Fields
+///
+/// * `writer`: Where the frames are written.
+/// * `chunk`: The data not yet compressed.
+/// * `full`: Chunks of `FRAME_SIZE` bytes waiting for a batch to be compressed.
+/// * `threads`: The number of chunks compressed at once.
+/// * `frames`: The number of frames written.
+pub struct ZstdWriter<W: Write> {
+    writer: W,
+    chunk: Vec<u8>,
+    full: Vec<Vec<u8>>,
+    threads: usize,
+    frames: usize,
+}
+
+impl<W: Write> ZstdWriter<W> {
+    /// Constructor for `ZstdWriter`.
+    pub fn new(writer: W, threads: usize) -> Self {
+        Self {
+            writer,
+            chunk: Vec::with_capacity(FRAME_SIZE),
+            full: Vec::new(),
+            threads: threads.max(1),
+            frames: 0,
+        }
+    }
+
+    /// The number of frames written so far.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Helper to `write()` and `flush()` that compresses the waiting chunks and writes their
+    /// frames in order.
+    fn write_frames(&mut self) -> io::Result<()> {
+        let chunks = mem::take(&mut self.full);
+        for frame in compress_frames(&chunks, self.threads) {
+            self.writer.write_all(&frame)?;
+            self.frames += 1;
+        }
+        Ok(())
+    }
+
+    /// Writes the remaining data as a last frame, and returns the writer. A stream with no data
+    /// gets one empty frame, so it is still valid Zstandard.
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.chunk.is_empty() || self.frames + self.full.len() == 0 {
+            let chunk = mem::take(&mut self.chunk);
+            self.full.push(chunk);
+        }
+        self.write_frames()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for ZstdWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(FRAME_SIZE - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..n]);
+        if self.chunk.len() == FRAME_SIZE {
+            let chunk = mem::replace(&mut self.chunk, Vec::with_capacity(FRAME_SIZE));
+            self.full.push(chunk);
+            if self.full.len() == self.threads {
+                self.write_frames()?;
+            }
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.chunk.is_empty() {
+            let chunk = mem::take(&mut self.chunk);
+            self.full.push(chunk);
+        }
+        self.write_frames()?;
+        self.writer.flush()
+    }
+}
+
+/// Compresses chunks into frames on up to `threads` threads, keeping their order.
+pub fn compress_frames(chunks: &[Vec<u8>], threads: usize) -> Vec<Vec<u8>> {
+    if threads < 2 || chunks.len() < 2 {
+        return chunks.iter().map(|chunk| compress_frame(chunk)).collect();
+    }
+    let results: Vec<Mutex<Vec<u8>>> = chunks.iter().map(|_| Mutex::default()).collect();
+    let next = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for _ in 0..threads.min(chunks.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(chunk) = chunks.get(i) else {
+                    break;
+                };
+                *results[i].lock().unwrap() = compress_frame(chunk);
+            });
+        }
+    });
+    results
+        .into_iter()
+        .map(|result| result.into_inner().unwrap())
+        .collect()
+}
+
+/// Compresses data into one frame.
+///
+/// The frame is a single segment whose content size is recorded in its header, so decoders know
+/// the window is the whole chunk and matches may reach back anywhere in it.
+pub fn compress_frame(data: &[u8]) -> Vec<u8> {
+    let mut frame = MAGIC.to_le_bytes().to_vec();
+    let size = data.len() as u64;
+    // the frame content size field: 1, 2 (less 256), 4 or 8 bytes
+    match size {
+        0..=255 => frame.extend_from_slice(&[0x20, size as u8]),
+        256..=65_791 => {
+            frame.push(0x60);
+            frame.extend_from_slice(&((size - 256) as u16).to_le_bytes());
+        }
+        65_792..=0xFFFF_FFFF => {
+            frame.push(0xA0);
+            frame.extend_from_slice(&(size as u32).to_le_bytes());
+        }
+        _ => {
+            frame.push(0xE0);
+            frame.extend_from_slice(&size.to_le_bytes());
+        }
+    }
+    let mut state = MatchState::new();
+    let mut start = 0;
+    loop {
+        let end = (start + BLOCK_SIZE).min(data.len());
+        let last = end == data.len();
+        let compressed = compress_block(data, start, end, &mut state);
+        match compressed {
+            Some(block) if block.len() < end - start => {
+                push_block_header(&mut frame, last, BLOCK_COMPRESSED, block.len());
+                frame.extend_from_slice(&block);
+            }
+            _ => {
+                push_block_header(&mut frame, last, BLOCK_RAW, end - start);
+                frame.extend_from_slice(&data[start..end]);
+            }
+        }
+        if last {
+            return frame;
+        }
+        start = end;
+    }
+}
+
+/// Appends a block header: the last-block flag, the block type and the block size.
+fn push_block_header(frame: &mut Vec<u8>, last: bool, block_type: u32, size: usize) {
+    let header = u32::from(last) | (block_type << 1) | ((size as u32) << 3);
+    frame.extend_from_slice(&header.to_le_bytes()[..3]);
+}
+
+/// A match found in the data: the number of literals before it, how far back it is, and its
+/// length.
+///
+/// The offset is coded as the format codes it: 1 to 3 name recently used offsets, and larger
+/// values are the offset plus 3.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Sequence {
+    literals: u32,
+    offset_value: u32,
+    length: u32,
+}
+
+/// What the match finder carries across the blocks of a frame.
+///
+/// # Fields
+///
+/// * `table`: One more than the last position each hash was seen at, or 0.
+/// * `offsets`: The recently used offsets, most recent first, as decoders track them.
+struct MatchState {
+    table: Vec<u32>,
+    offsets: [u32; 3],
+}
+
+impl MatchState {
+    fn new() -> Self {
+        Self {
+            table: vec![0; 1 << HASH_LOG],
+            offsets: [1, 4, 8],
+        }
+    }
+}
+
+/// Compresses `data[start..end]` as the content of a compressed block, finding matches anywhere
+/// earlier in `data`. Returns `None` if there is nothing to compress.
+///
+/// # Arguments
+///
+/// * `data` - The data of the whole frame.
+/// * `start` - Where the block starts.
+/// * `end` - Where the block ends.
+/// * `state` - The match finder's state, carried across the blocks of the frame.
+fn compress_block(
+    data: &[u8],
+    start: usize,
+    end: usize,
+    state: &mut MatchState,
+) -> Option<Vec<u8>> {
+    if end == start {
+        return None;
+    }
+    let mut literals = Vec::new();
+    let mut sequences = Vec::new();
+    let mut anchor = start;
+    let mut i = start;
+    while i + MIN_MATCH <= end {
+        let key = read_u32(data, i);
+        let hash = (key.wrapping_mul(2_654_435_761) >> (32 - HASH_LOG)) as usize;
+        let candidate = state.table[hash] as usize;
+        state.table[hash] = (i + 1) as u32;
+        // the last offset again costs almost nothing to code, so it is tried first; its code
+        // means something else after no literals
+        let rep = state.offsets[0] as usize;
+        let found = if i > anchor && rep <= i && read_u32(data, i - rep) == key {
+            Some((i - rep, 1))
+        } else if candidate > 0 && read_u32(data, candidate - 1) == key {
+            Some((candidate - 1, i - candidate + 1 + 3))
+        } else {
+            None
+        };
+        let Some((from, offset_value)) = found else {
+            i += 1;
+            continue;
+        };
+        let mut length = 4;
+        while i + length < end && data[from + length] == data[i + length] {
+            length += 1;
+        }
+        if offset_value > 3 {
+            if length < MIN_MATCH {
+                i += 1;
+                continue;
+            }
+            state.offsets = [(i - from) as u32, state.offsets[0], state.offsets[1]];
+        }
+        literals.extend_from_slice(&data[anchor..i]);
+        sequences.push(Sequence {
+            literals: (i - anchor) as u32,
+            offset_value: offset_value as u32,
+            length: length as u32,
+        });
+        i += length;
+        anchor = i;
+    }
+    literals.extend_from_slice(&data[anchor..end]);
+    let mut block = encode_literals(&literals);
+    let count = sequences.len();
+    match count {
+        0..=127 => block.push(count as u8),
+        128..=0x7EFF => block.extend_from_slice(&[((count >> 8) + 0x80) as u8, count as u8]),
+        _ => {
+            block.push(0xFF);
+            block.extend_from_slice(&((count - 0x7F00) as u16).to_le_bytes());
+        }
+    }
+    if count > 0 {
+        // predefined tables for all three codes
+        block.push(0);
+        block.extend_from_slice(&encode_sequences(&sequences));
+    }
+    Some(block)
+}
+
+/// Codes the literals section of a block: Huffman-coded when that is smaller, raw otherwise.
+fn encode_literals(literals: &[u8]) -> Vec<u8> {
+    if let Some(section) = huffman_literals(literals) {
+        if section.len() < literals.len() {
+            return section;
+        }
+    }
+    // raw literals, with a 1, 2 or 3 byte header
+    let n = literals.len();
+    let mut section = match n {
+        0..=31 => vec![(n << 3) as u8],
+        32..=4095 => vec![((n << 4) | 0b0100) as u8, (n >> 4) as u8],
+        _ => vec![((n << 4) | 0b1100) as u8, (n >> 4) as u8, (n >> 12) as u8],
+    };
+    section.extend_from_slice(literals);
+    section
+}
+
+/// Codes literals with a Huffman code described by directly stored weights, or returns `None`
+/// when that form does not apply: fewer than two distinct bytes, or bytes above 128, whose
+/// weights do not fit the direct form.
+fn huffman_literals(literals: &[u8]) -> Option<Vec<u8>> {
+    let mut counts = [0u32; 256];
+    for &b in literals {
+        counts[b as usize] += 1;
+    }
+    let last = counts.iter().rposition(|&c| c > 0)?;
+    if last > 128 || counts.iter().filter(|&&c| c > 0).count() < 2 {
+        return None;
+    }
+    let lengths = huffman_lengths(&counts, MAX_HUFFMAN_BITS);
+    let max_bits = *lengths.iter().max()? as u32;
+    // codes are assigned in order of length, longest first, then of byte value
+    let mut order: Vec<usize> = (0..256).filter(|&s| lengths[s] > 0).collect();
+    order.sort_by_key(|&s| (Reverse(lengths[s]), s));
+    let mut codes = [0u32; 256];
+    let mut next = 0u32;
+    for s in order {
+        let shift = max_bits - lengths[s] as u32;
+        codes[s] = next >> shift;
+        next += 1 << shift;
+    }
+    // the tree description: the weights of every byte below the last, 4 bits each
+    let weight = |s: usize| match lengths[s] {
+        0 => 0,
+        len => (max_bits + 1 - len as u32) as u8,
+    };
+    let mut description = vec![(127 + last) as u8];
+    for pair in (0..last).collect::<Vec<_>>().chunks(2) {
+        let low = pair.get(1).map_or(0, |&s| weight(s));
+        description.push((weight(pair[0]) << 4) | low);
+    }
+    let encode = |segment: &[u8]| {
+        let mut bits = BitWriter::default();
+        // symbols are read back from the end, so they are written last first
+        for &b in segment.iter().rev() {
+            bits.add(codes[b as usize], lengths[b as usize] as u32);
+        }
+        bits.close()
+    };
+    let n = literals.len();
+    let mut payload = description;
+    let single = n <= 1023;
+    if single {
+        payload.extend_from_slice(&encode(literals));
+    } else {
+        let streams: Vec<Vec<u8>> = literals.chunks(n.div_ceil(4)).map(encode).collect();
+        if streams.len() != 4 {
+            return None;
+        }
+        for stream in &streams[..3] {
+            payload.extend_from_slice(&u16::try_from(stream.len()).ok()?.to_le_bytes());
+        }
+        for stream in &streams {
+            payload.extend_from_slice(stream);
+        }
+    }
+    let (n, c) = (n as u64, payload.len() as u64);
+    // type 2, then the size format and the regenerated and compressed sizes
+    let mut section = match (single, n.max(c)) {
+        (true, 0..=1023) => (2 | (n << 4) | (c << 14)).to_le_bytes()[..3].to_vec(),
+        (false, 0..=1023) => (2 | (1 << 2) | (n << 4) | (c << 14)).to_le_bytes()[..3].to_vec(),
+        (false, 0..=16_383) => (2 | (2 << 2) | (n << 4) | (c << 18)).to_le_bytes()[..4].to_vec(),
+        (false, 0..=262_143) => (2 | (3 << 2) | (n << 4) | (c << 22)).to_le_bytes()[..5].to_vec(),
+        _ => return None,
+    };
+    section.extend_from_slice(&payload);
+    Some(section)
+}
+
+/// Huffman code lengths for byte counts, at most `max_bits` long. Counts are halved until the
+/// plain Huffman code fits, which costs little since only rare bytes have long codes.
+fn huffman_lengths(counts: &[u32; 256], max_bits: u8) -> [u8; 256] {
+    let mut counts = *counts;
+    loop {
+        let lengths = huffman_depths(&counts);
+        if lengths.iter().all(|&len| len <= max_bits) {
+            return lengths;
+        }
+        for count in counts.iter_mut().filter(|c| **c > 0) {
+            *count = (*count >> 1).max(1);
+        }
+    }
+}
+
+/// Helper to `huffman_lengths()` that gives the depth of each byte in a Huffman tree.
+fn huffman_depths(counts: &[u32; 256]) -> [u8; 256] {
+    let mut parents: Vec<usize> = Vec::with_capacity(512);
+    let mut heap = BinaryHeap::new();
+    let mut leaves = Vec::new();
+    for (s, &count) in counts.iter().enumerate().filter(|(_, &c)| c > 0) {
+        leaves.push((s, parents.len()));
+        heap.push(Reverse((u64::from(count), parents.len())));
+        parents.push(usize::MAX);
+    }
+    while heap.len() > 1 {
+        let Reverse((a, i)) = heap.pop().unwrap();
+        let Reverse((b, j)) = heap.pop().unwrap();
+        let node = parents.len();
+        parents.push(usize::MAX);
+        parents[i] = node;
+        parents[j] = node;
+        heap.push(Reverse((a + b, node)));
+    }
+    let mut lengths = [0u8; 256];
+    for (s, leaf) in leaves {
+        let mut node = leaf;
+        while parents[node] != usize::MAX {
+            node = parents[node];
+            lengths[s] += 1;
+        }
+    }
+    lengths
+}
+
+/// Reads 4 bytes as a little-endian `u32`.
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+/// The code of a value in a table of code baselines, and the value's extra bits.
+fn code(base: &[u32], value: u32) -> (usize, u32) {
+    let code = base.partition_point(|&b| b <= value) - 1;
+    (code, value - base[code])
+}
+
+/// Codes sequences into the sequence bitstream, last sequence first, as decoders read it back to
+/// front.
+fn encode_sequences(sequences: &[Sequence]) -> Vec<u8> {
+    let ll_table = FseTable::new(&LL_NORM, LL_LOG);
+    let ml_table = FseTable::new(&ML_NORM, ML_LOG);
+    let of_table = FseTable::new(&OF_NORM, OF_LOG);
+    let codes: Vec<_> = sequences
+        .iter()
+        .map(|seq| {
+            let (ll, ll_extra) = code(&LL_BASE, seq.literals);
+            let (ml, ml_extra) = code(&ML_BASE, seq.length);
+            let value = seq.offset_value;
+            let of = 31 - value.leading_zeros();
+            (ll, ll_extra, ml, ml_extra, of as usize, value - (1 << of))
+        })
+        .collect();
+    let mut bits = BitWriter::default();
+    let (ll, ll_extra, ml, ml_extra, of, of_extra) = codes[codes.len() - 1];
+    let mut ml_state = ml_table.init(ml);
+    let mut of_state = of_table.init(of);
+    let mut ll_state = ll_table.init(ll);
+    bits.add(ll_extra, LL_BITS[ll]);
+    bits.add(ml_extra, ML_BITS[ml]);
+    bits.add(of_extra, of as u32);
+    for &(ll, ll_extra, ml, ml_extra, of, of_extra) in codes.iter().rev().skip(1) {
+        of_table.encode(&mut bits, &mut of_state, of);
+        ml_table.encode(&mut bits, &mut ml_state, ml);
+        ll_table.encode(&mut bits, &mut ll_state, ll);
+        bits.add(ll_extra, LL_BITS[ll]);
+        bits.add(ml_extra, ML_BITS[ml]);
+        bits.add(of_extra, of as u32);
+    }
+    bits.add(ml_state, ml_table.log);
+    bits.add(of_state, of_table.log);
+    bits.add(ll_state, ll_table.log);
+    bits.close()
+}
+
+/// A bitstream written from its least significant bit up, ended by a 1 bit so decoders can find
+/// where it starts when reading it back to front.
+///
+/// # Fields
+///
+/// * `bytes`: The completed bytes.
+/// * `acc`: The bits not yet in a completed byte.
+/// * `len`: The number of bits in `acc`.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    len: u32,
+}
+
+impl BitWriter {
+    /// Adds the low `n` bits of `value`.
+    fn add(&mut self, value: u32, n: u32) {
+        self.acc |= (u64::from(value) & ((1 << n) - 1)) << self.len;
+        self.len += n;
+        while self.len >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    /// Adds the end mark and returns the bytes.
+    fn close(mut self) -> Vec<u8> {
+        self.add(1, 1);
+        if self.len > 0 {
+            self.bytes.push(self.acc as u8);
+        }
+        self.bytes
+    }
+}
+
+/// An FSE coding table built from a normalized distribution, as decoders build it.
+///
+/// # Fields
+///
+/// * `log`: The accuracy log; the table has `1 << log` states.
+/// * `states`: The next state for each symbol, grouped by symbol.
+/// * `symbols`: For each symbol, the offset of its states in `states` and the term that gives
+///   the number of bits written for it from the current state.
+struct FseTable {
+    log: u32,
+    states: Vec<u32>,
+    symbols: Vec<(i64, u32)>,
+}
+
+impl FseTable {
+    /// Builds the table for a distribution in which -1 marks a "less than one" probability.
+    fn new(norm: &[i16], log: u32) -> Self {
+        let size = 1usize << log;
+        let mut high = size - 1;
+        let mut cumul = vec![0usize; norm.len() + 1];
+        let mut cells = vec![0usize; size];
+        for (s, &n) in norm.iter().enumerate() {
+            if n == -1 {
+                cells[high] = s;
+                high -= 1;
+                cumul[s + 1] = cumul[s] + 1;
+            } else {
+                cumul[s + 1] = cumul[s] + n as usize;
+            }
+        }
+        let step = (size >> 1) + (size >> 3) + 3;
+        let mut position = 0;
+        for (s, &n) in norm.iter().enumerate() {
+            for _ in 0..n.max(0) {
+                cells[position] = s;
+                position = (position + step) & (size - 1);
+                while position > high {
+                    position = (position + step) & (size - 1);
+                }
+            }
+        }
+        let mut next = cumul.clone();
+        let mut states = vec![0u32; size];
+        for (u, &s) in cells.iter().enumerate() {
+            states[next[s]] = (size + u) as u32;
+            next[s] += 1;
+        }
+        let symbols = norm
+            .iter()
+            .enumerate()
+            .map(|(s, &n)| match n {
+                -1 | 1 => (cumul[s] as i64 - 1, (log << 16) - (1 << log)),
+                n if n > 1 => {
+                    let n = n as u32;
+                    let max_bits = log - (31 - (n - 1).leading_zeros());
+                    (
+                        cumul[s] as i64 - n as i64,
+                        (max_bits << 16) - (n << max_bits),
+                    )
+                }
+                _ => (0, ((log + 1) << 16) - (1 << log)),
+            })
+            .collect();
+        Self {
+            log,
+            states,
+            symbols,
+        }
+    }
+
+    /// The state to start from when `symbol` is the first coded, which writes no bits.
+    fn init(&self, symbol: usize) -> u32 {
+        let (find, delta_bits) = self.symbols[symbol];
+        let bits = (delta_bits + (1 << 15)) >> 16;
+        let value = (bits << 16) - delta_bits;
+        self.states[((value >> bits) as i64 + find) as usize]
+    }
+
+    /// Codes `symbol`, writing the low bits of the current state and moving to the next.
+    fn encode(&self, out: &mut BitWriter, state: &mut u32, symbol: usize) {
+        let (find, delta_bits) = self.symbols[symbol];
+        let bits = (*state + delta_bits) >> 16;
+        out.add(*state, bits);
+        *state = self.states[((*state >> bits) as i64 + find) as usize];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Text like a curvature TSV: repetitive, but not trivially so.
+    fn tsv(lines: usize) -> Vec<u8> {
+        let mut text = String::new();
+        for i in 0..lines {
+            let value = ((i as f64) * 0.37).sin() * 4.0;
+            text.push_str(&format!("chr1\t{}\t{:.4}\n", i + 1, value));
+        }
+        text.into_bytes()
+    }
+
+    #[test]
+    fn test_frame_header() {
+        let frame = compress_frame(b"");
+        // magic, single segment with a 1-byte size of 0, then one empty last raw block
+        assert_eq!(frame, [0x28, 0xB5, 0x2F, 0xFD, 0x20, 0, 1, 0, 0]);
+        let frame = compress_frame(&[b'a'; 300]);
+        assert_eq!(frame[4..7], [0x60, 44, 0]);
+        assert!(frame.len() < 30);
+    }
+
+    #[test]
+    fn test_codes() {
+        assert_eq!(code(&LL_BASE, 15), (15, 0));
+        assert_eq!(code(&LL_BASE, 27), (20, 3));
+        assert_eq!(code(&ML_BASE, 4), (1, 0));
+        assert_eq!(code(&ML_BASE, 100), (42, 1));
+        assert_eq!(code(&ML_BASE, 131_074), (52, 65_535));
+    }
+
+    #[test]
+    fn test_fse_table() {
+        let table = FseTable::new(&LL_NORM, LL_LOG);
+        let mut states = table.states.clone();
+        states.sort_unstable();
+        // every state appears once
+        assert_eq!(states, (64..128).collect::<Vec<u32>>());
+        // a symbol with probability 4/64 writes 4 bits from any state
+        let mut bits = BitWriter::default();
+        let mut state = table.init(0);
+        table.encode(&mut bits, &mut state, 0);
+        assert_eq!(bits.len, 4);
+    }
+
+    #[test]
+    fn test_compresses() {
+        let data = tsv(200_000);
+        let mut writer = ZstdWriter::new(Vec::new(), 2);
+        writer.write_all(&data).unwrap();
+        assert_eq!(writer.frames(), 2);
+        let out = writer.finish().unwrap();
+        assert!(out.len() < data.len() / 2);
+        assert_eq!(out[..4], MAGIC.to_le_bytes());
+    }
+
+    #[test]
+    fn test_round_trip_with_zstd() {
+        // checked against the reference decoder when it is installed
+        let Ok(status) = std::process::Command::new("zstd").arg("--version").output() else {
+            return;
+        };
+        if !status.status.success() {
+            return;
+        }
+        let dir = std::env::temp_dir().join("symcurve_test_zstd");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.tsv.zst");
+        let mut data = tsv(200_000);
+        data.extend((0..70_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8));
+        data.extend(vec![b'N'; 300_000]);
+        let mut writer = ZstdWriter::new(std::fs::File::create(&path).unwrap(), 3);
+        for piece in data.chunks(7_777) {
+            writer.write_all(piece).unwrap();
+        }
+        writer.finish().unwrap();
+        let output = std::process::Command::new("zstd")
+            .args(["-d", "-c"])
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(output.stdout == data);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
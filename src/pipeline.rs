@@ -0,0 +1,2597 @@
+//! # Pipeline module
+//! The curvature pipeline over any buffered source, for library users whose sequences come from
+//! sockets, in-memory buffers or decompressors rather than files on disk.
+//!
+//! ```
+//! let fasta = b">seq1\nCCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC\n";
+//! let mut out = Vec::new();
+//! let summary = symcurve::run_pipeline(&fasta[..], &mut out, &Default::default()).unwrap();
+//! assert_eq!(summary.values, 8);
+//! ```
+
+use crate::chunk::{with_chunked_curve, ChunkPlan};
+use crate::curve::iters::{CurveBuffers, CurveIter, EdgeMode, MeanConvention};
+use crate::curve::matrix::RollType;
+use crate::curve::memo::{memoized_curvature, MemoStats};
+use crate::curve::symmetry::SymmetryScores;
+use crate::fasta::index::{FaiRecord, FastaIndex};
+use crate::fasta::validate::{check_record, checked_bases};
+use crate::fasta::{open_input_member, StreamingReader, STDIN};
+use crate::issues::{sequence_issues, AmbiguityTracker, IssueLog};
+use crate::output::atomic::AtomicFile;
+use crate::output::bedgraph::BedGraphWriter;
+use crate::output::bigwig::{Aggregate, BigWigWriter};
+use crate::output::compress::{CompressedWriter, Compression};
+use crate::output::queue::BoundedQueue;
+use crate::output::spill::SpillFile;
+use crate::output::track::TrackLine;
+use crate::output::{
+    check_overwrite, checkpoint_path, write_checkpoint, Anchor, FloatFormat, NaValue, OutputFormat,
+    SortContigs,
+};
+use crate::provenance::Provenance;
+use crate::signal::{self, CancelToken};
+use crate::stats::{MemStats, Stage, Timings};
+use crate::trace::{Span, Tracer};
+use crate::view::Region;
+use crate::warnings::Warnings;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufRead, Seek, Write};
+use std::iter;
+use std::mem;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Instant;
+
+/// The parameters of a pipeline run.
+///
+/// # Fields
+///
+/// * `roll_type`: The type of roll (either simple or activated).
+/// * `step_b`: Half of the rolling mean window size minus one (`--curve-step-one` minus one).
+/// * `step_two`: The half-width of the fully weighted part of the legacy rolling mean window
+///   (`--curve-step-two`), or `None` for the half-weighted-ends mean of `2 * step_b + 1`
+///   coordinates.
+/// * `step_c`: The distance from the midpoint to the ends of the curvature window
+///   (`--curve-step`).
+/// * `curve_scale`: The factor curvature is scaled by.
+/// * `edge_mode`: How windows are handled at the ends of each sequence piece.
+/// * `convention`: The averaging convention of the rolling mean.
+/// * `anchor`: Where each value is reported relative to its window.
+/// * `na`: What is written at positions without a value.
+/// * `float_format`: How values are written.
+/// * `track_line`: The settings of the track line at the top of a bedGraph output, which is only
+///   written if one of them is given.
+/// * `symmetry_window`: The number of curvature values in each window of the symmetry track
+///   (`--symcurve-win`).
+/// * `symmetry_step`: The distance between the starts of consecutive symmetry windows
+///   (`--symcurve-step`).
+/// * `span`: The number of bases each value of a bigWig output covers (`--span`).
+/// * `aggregate`: How the values in each span of a bigWig output are combined (`--aggregate`).
+/// * `bigwig_threads`: The number of threads the data blocks of a bigWig output are compressed
+///   on, which leaves the file the same byte for byte.
+/// * `chunking`: How long pieces are split into chunks computed in parallel (`--chunked`), or
+///   `None` to compute each piece on the reading thread.
+/// * `cancel`: Stops the run with an `io::ErrorKind::Interrupted` error once cancelled, for
+///   programs embedding the library.
+/// * `regions`: The only regions of the input to compute (`--regions`), or empty for all of it.
+///   Only [`run_file`] reads by region, since it needs the input's FASTA index.
+/// * `archive_member`: The one member of an archive input [`run_file`] reads
+///   (`--archive-member`), or `None` for every FASTA member.
+/// * `compression`: How TSV and bedGraph tracks are compressed (`--compression`). The file
+///   pipelines write to the paths they are given; [`run`](crate::run::run) adds the extension of
+///   the compression to them, as [`track_path`] gives it.
+/// * `provenance`: The provenance embedded in every track written: as `#` comment lines at the
+///   top of TSV and bedGraph tracks, and in bigWig files as
+///   [`BigWigWriter::with_provenance`] keeps it. `None` embeds none.
+#[derive(Clone, Debug)]
+pub struct PipelineParams {
+    pub roll_type: RollType,
+    pub step_b: usize,
+    pub step_two: Option<usize>,
+    pub step_c: usize,
+    pub curve_scale: f64,
+    pub edge_mode: EdgeMode,
+    pub convention: MeanConvention,
+    pub anchor: Anchor,
+    pub na: NaValue,
+    pub float_format: FloatFormat,
+    pub track_line: TrackLine,
+    pub symmetry_window: usize,
+    pub symmetry_step: usize,
+    pub span: u64,
+    pub aggregate: Aggregate,
+    pub bigwig_threads: usize,
+    pub chunking: Option<ChunkPlan>,
+    pub cancel: CancelToken,
+    pub regions: Vec<Region>,
+    pub archive_member: Option<String>,
+    pub compression: Compression,
+    pub provenance: Option<Provenance>,
+}
+
+impl Default for PipelineParams {
+    /// The command-line defaults.
+    fn default() -> Self {
+        Self {
+            roll_type: RollType::Simple,
+            step_b: 5,
+            step_two: None,
+            step_c: 15,
+            curve_scale: 0.33335,
+            edge_mode: EdgeMode::Drop,
+            convention: MeanConvention::Legacy,
+            anchor: Anchor::Center,
+            na: NaValue::NaN,
+            float_format: FloatFormat::Shortest,
+            track_line: TrackLine::default(),
+            symmetry_window: 101,
+            symmetry_step: 1,
+            span: 1,
+            aggregate: Aggregate::Mean,
+            bigwig_threads: 1,
+            chunking: None,
+            cancel: CancelToken::default(),
+            regions: Vec::new(),
+            archive_member: None,
+            compression: Compression::None,
+            provenance: None,
+        }
+    }
+}
+
+impl PipelineParams {
+    /// The number of bases on either side of the central base of each curvature window, as
+    /// [`CurveIter::flank`] gives it for these parameters.
+    pub fn flank(&self) -> usize {
+        let extent = CurveIter::<iter::Empty<u8>>::rolling_extent(
+            self.step_b,
+            self.step_two,
+            self.convention,
+        );
+        CurveIter::<iter::Empty<u8>>::flank(extent, self.step_c, self.edge_mode)
+    }
+}
+
+/// What a pipeline run processed.
+///
+/// # Fields
+///
+/// * `records`: The number of FASTA records read.
+/// * `bases`: The number of bases read, including gaps.
+/// * `values`: The number of curvature values computed.
+/// * `gap_bases`: The number of `N` bases, which are gaps without a value.
+/// * `ambiguous_bases`: The number of gap bases other than `N`, such as IUPAC ambiguity codes.
+/// * `short_records`: The number of records with bases but no piece long enough for a value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PipelineSummary {
+    pub records: u64,
+    pub bases: u64,
+    pub values: u64,
+    pub gap_bases: u64,
+    pub ambiguous_bases: u64,
+    pub short_records: u64,
+}
+
+impl PipelineSummary {
+    /// What was processed since `earlier`, a copy of this summary taken before.
+    pub(crate) fn since(&self, earlier: &PipelineSummary) -> PipelineSummary {
+        PipelineSummary {
+            records: self.records - earlier.records,
+            bases: self.bases - earlier.bases,
+            values: self.values - earlier.values,
+            gap_bases: self.gap_bases - earlier.gap_bases,
+            ambiguous_bases: self.ambiguous_bases - earlier.ambiguous_bases,
+            short_records: self.short_records - earlier.short_records,
+        }
+    }
+}
+
+/// Runs the curvature pipeline from FASTA text to per-position TSV rows.
+///
+/// Records are streamed: memory use does not grow with sequence length. Each record is split at
+/// gaps (`N` and any other base that is not `A`, `C`, `G` or `T`, case-insensitively) and
+/// curvature is computed for each piece separately. One `chrom`, 1-based position, value row is
+/// written per base, as with [`tsv::write_track`](crate::output::tsv::write_track), with
+/// positions that have no value written as `params.na` says, after the provenance of
+/// `params.provenance` as `#` comment lines.
+///
+/// # Arguments
+///
+/// * `reader` - The FASTA text.
+/// * `writer` - Where the rows are written.
+/// * `params` - The parameters of the run.
+///
+/// # Errors
+///
+/// Returns any error from reading or writing, or an `io::ErrorKind::Interrupted` error if
+/// `params.cancel` is cancelled, after which the rows written so far are incomplete.
+pub fn run_pipeline<R: BufRead, W: Write>(
+    reader: R,
+    mut writer: W,
+    params: &PipelineParams,
+) -> io::Result<PipelineSummary> {
+    if let Some(provenance) = &params.provenance {
+        provenance.write_comments(&mut writer, "#")?;
+    }
+    let mut fasta = StreamingReader::new(reader);
+    let mut summary = PipelineSummary::default();
+    let mut scratch = Scratch::default();
+    while let Some(chrom) = fasta.next_record()? {
+        write_record_with(
+            &mut writer,
+            &chrom,
+            fasta.bases(),
+            params,
+            &mut summary,
+            &mut scratch,
+        )?;
+    }
+    writer.flush()?;
+    Ok(summary)
+}
+
+/// Runs the curvature pipeline from a FASTA file to an output file, and optionally to a
+/// symmetry track of the curvature in the same format.
+///
+/// The symmetry track scores windows of `params.symmetry_window` curvature values, starting
+/// every `params.symmetry_step` values, for mirror symmetry around their centers (see
+/// [`SymmetryScores`]), and reports each score at the center of its window. Windows reaching
+/// past a gap or the flanks of a piece have no score.
+///
+/// The outputs only appear at their paths once they are complete (see
+/// [`AtomicFile`](crate::output::atomic::AtomicFile)).
+///
+/// Once an interrupt is requested (see [`signal::interrupted`]), the run stops after the record
+/// it is on, finalizes the outputs with the records completed so far, lists those records in a
+/// checkpoint next to the output (see [`checkpoint_path`]) and returns an
+/// `io::ErrorKind::Interrupted` error.
+///
+/// With `params.regions`, only those regions are read, through the input's FASTA index (its
+/// `.fai`, or one built by scanning the file), along with enough bases around them to cover the
+/// windows of every value in them. The values agree with a whole-file run to within rounding:
+/// the rolling sums start at a different base, so the last few bits can differ. Outputs keep
+/// chromosome coordinates, and bigWig outputs the full chromosome lengths. No symmetry track is
+/// written for regions.
+///
+/// # Arguments
+///
+/// * `input` - The FASTA file, or `-` for standard input.
+/// * `output` - The output path.
+/// * `symmetry` - The symmetry track output path, or `None` to write no symmetry track.
+/// * `format` - The format of the outputs.
+/// * `params` - The parameters of the run.
+/// * `force` - Whether existing outputs may be overwritten.
+///
+/// # Errors
+///
+/// Returns an error if an output exists and `force` is not set, any error from reading or
+/// writing, an `io::ErrorKind::InvalidData` error at the first malformed record (see
+/// [`check_record`]), or an `io::ErrorKind::InvalidInput` error if regions are given with a
+/// symmetry track or an input that cannot be indexed, or if `params.span` is more than one base
+/// for a format other than bigWig.
+pub fn run_file(
+    input: &Path,
+    output: &Path,
+    symmetry: Option<&Path>,
+    format: OutputFormat,
+    params: &PipelineParams,
+    force: bool,
+) -> io::Result<PipelineSummary> {
+    run_file_with(
+        input,
+        output,
+        symmetry,
+        format,
+        params,
+        force,
+        &mut FileRun::default(),
+    )
+}
+
+/// What `run_file_with()` keeps track of besides the summary, for the report of
+/// [`run`](crate::run::run).
+///
+/// # Fields
+///
+/// * `completed`: The names of the records finished, in order.
+/// * `memory`: The largest buffers of the run.
+/// * `warnings`: The anomalies of each record finished.
+/// * `issues`: Where the issues of each record finished are reported, if anywhere.
+/// * `max_memory`: The most bytes of computed rows left waiting to be written, if the outputs
+///   are written on a thread of their own (`--max-memory`); see [`queued_tracks`].
+/// * `timings`: The time spent in each stage (`--timings`), if it is kept; see [`read_tracks`].
+/// * `tracer`: The trace a span of each record and of its stages is added to (`--trace`), if
+///   one is kept. The writer of `max_memory` runs on a thread the trace does not follow.
+/// * `sort_contigs`: The order records are written in (`--sort-contigs`). Any order but the
+///   input's spills the values of every record to `spill_dir` until the input is read; see
+///   [`sorted_tracks`].
+/// * `spill_dir`: The directory of the [`SpillFile`] of `sort_contigs` (`--spill-dir`).
+#[derive(Default)]
+pub(crate) struct FileRun<'a> {
+    pub(crate) completed: Vec<String>,
+    pub(crate) memory: MemStats,
+    pub(crate) warnings: Warnings,
+    pub(crate) issues: Option<IssueLog<AtomicFile>>,
+    pub(crate) max_memory: Option<usize>,
+    pub(crate) timings: Option<Timings>,
+    pub(crate) tracer: Option<&'a Tracer>,
+    pub(crate) sort_contigs: SortContigs,
+    pub(crate) spill_dir: Option<PathBuf>,
+}
+
+impl FileRun<'_> {
+    /// Whether the stages are timed, for the timings or the trace.
+    fn timed(&self) -> bool {
+        self.timings.is_some() || self.tracer.is_some()
+    }
+}
+
+/// [`run_file`], keeping track of the run in `state`.
+pub(crate) fn run_file_with(
+    input: &Path,
+    output: &Path,
+    symmetry: Option<&Path>,
+    format: OutputFormat,
+    params: &PipelineParams,
+    force: bool,
+    state: &mut FileRun<'_>,
+) -> io::Result<PipelineSummary> {
+    check_format_params(format, params)?;
+    check_overwrite(output, force)?;
+    if let Some(symmetry) = symmetry {
+        check_overwrite(symmetry, force)?;
+    }
+    if !params.regions.is_empty() {
+        if symmetry.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no symmetry track can be written for regions",
+            ));
+        }
+        if state.sort_contigs != SortContigs::Input {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--sort-contigs cannot be used with --regions",
+            ));
+        }
+        let mut file = AtomicFile::create(output)?;
+        let summary = match format {
+            OutputFormat::BigWig => {
+                run_region_tracks(input, bigwig_writer(&mut file, params)?, params)?
+            }
+            OutputFormat::BedGraph => {
+                let mut text = params.compression.writer(&mut file);
+                let curve = bedgraph_writer(&mut text, &params.track_line, params)?;
+                let summary = run_region_tracks(input, curve, params)?;
+                text.finish()?;
+                summary
+            }
+            OutputFormat::Tsv => {
+                let mut text = params.compression.writer(&mut file);
+                let summary = run_region_tracks(input, TsvTrack::new(&mut text, params)?, params)?;
+                text.finish()?;
+                summary
+            }
+        };
+        file.commit()?;
+        return Ok(summary);
+    }
+    let reader = open_input_member(input, params.archive_member.as_deref())?;
+    let mut file = AtomicFile::create(output)?;
+    let mut symmetry_file = symmetry.map(AtomicFile::create).transpose()?;
+    let summary = match format {
+        OutputFormat::BigWig => {
+            let symmetry = symmetry_file
+                .as_mut()
+                .map(|file| bigwig_writer(file, params))
+                .transpose()?;
+            let curve = bigwig_writer(&mut file, params)?;
+            file_tracks(reader, curve, symmetry, params, state)?
+        }
+        OutputFormat::BedGraph => {
+            let mut text = params.compression.writer(&mut file);
+            let mut symmetry_text = symmetry_file
+                .as_mut()
+                .map(|file| params.compression.writer(file));
+            let symmetry = symmetry_text
+                .as_mut()
+                .map(|text| bedgraph_writer(text, &TrackLine::default(), params))
+                .transpose()?;
+            let curve = bedgraph_writer(&mut text, &params.track_line, params)?;
+            let summary = file_tracks(reader, curve, symmetry, params, state)?;
+            finish_text(text, symmetry_text)?;
+            summary
+        }
+        OutputFormat::Tsv => {
+            let mut text = params.compression.writer(&mut file);
+            let mut symmetry_text = symmetry_file
+                .as_mut()
+                .map(|file| params.compression.writer(file));
+            let symmetry = symmetry_text
+                .as_mut()
+                .map(|text| TsvTrack::new(text, params))
+                .transpose()?;
+            let curve = TsvTrack::new(&mut text, params)?;
+            let summary = file_tracks(reader, curve, symmetry, params, state)?;
+            finish_text(text, symmetry_text)?;
+            summary
+        }
+    };
+    file.commit()?;
+    if let Some(symmetry_file) = symmetry_file {
+        symmetry_file.commit()?;
+    }
+    if signal::interrupted() {
+        return Err(interrupted_error(output, &state.completed));
+    }
+    Ok(summary)
+}
+
+/// Helper to `run_file_with()` and [`run`](crate::run::run) that checks no parameter only
+/// another output format writes is given for `format`.
+///
+/// # Errors
+///
+/// Returns an `io::ErrorKind::InvalidInput` error for a span other than 1 without a bigWig
+/// output, or track line settings without a bedGraph output.
+pub(crate) fn check_format_params(format: OutputFormat, params: &PipelineParams) -> io::Result<()> {
+    if params.span > 1 && format != OutputFormat::BigWig {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--span only applies to bigWig outputs",
+        ));
+    }
+    if params.track_line != TrackLine::default() && format != OutputFormat::BedGraph {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--track-name, --track-description, --track-color, --view-limits and --auto-scale \
+             only apply to bedGraph outputs",
+        ));
+    }
+    Ok(())
+}
+
+/// Helper to `run_file_with()` that ends the compressed streams of its text outputs.
+fn finish_text<W: Write>(
+    curve: CompressedWriter<W>,
+    symmetry: Option<CompressedWriter<W>>,
+) -> io::Result<()> {
+    curve.finish()?;
+    if let Some(symmetry) = symmetry {
+        symmetry.finish()?;
+    }
+    Ok(())
+}
+
+/// The path a track output in `format` is written at: `path`, with the extension of
+/// `params.compression` added for the text formats it compresses.
+pub(crate) fn track_path(path: &Path, format: OutputFormat, params: &PipelineParams) -> PathBuf {
+    match format {
+        OutputFormat::BigWig => path.to_path_buf(),
+        OutputFormat::BedGraph | OutputFormat::Tsv => params.compression.output_path(path),
+    }
+}
+
+/// Helper to `run_file_with()` and [`run`](crate::run::run) that lists the records completed before
+/// an interrupt in the checkpoint next to `output`, and gives the error the run returns.
+pub(crate) fn interrupted_error(output: &Path, completed: &[String]) -> io::Error {
+    if let Err(e) = write_checkpoint(output, completed) {
+        return e;
+    }
+    io::Error::new(
+        io::ErrorKind::Interrupted,
+        format!(
+            "interrupted after {} records; they are listed in {}",
+            completed.len(),
+            checkpoint_path(output).display()
+        ),
+    )
+}
+
+/// Computes the values of one record in memory, one per position with `NaN` where there is none,
+/// and counts the record in `summary` as the file pipelines do.
+///
+/// The values are those the file pipelines write, chunked if `params.chunking` says so, and the
+/// run stops with an `io::ErrorKind::Interrupted` error if `params.cancel` is cancelled.
+pub(crate) fn record_track(
+    chrom: &str,
+    bases: &[u8],
+    params: &PipelineParams,
+    summary: &mut PipelineSummary,
+    buffers: &mut CurveBuffers,
+) -> io::Result<Vec<f64>> {
+    let mut rows = ValueRows(Vec::with_capacity(bases.len()));
+    record_values(
+        &mut rows,
+        chrom,
+        bases.iter().copied().map(Ok),
+        params,
+        summary,
+        buffers,
+    )?;
+    Ok(rows.0)
+}
+
+/// [`record_track`], with the values of windows repeating the window one repeat period earlier
+/// reused rather than computed (see [`memoized_curvature`]), and the counts of each added to
+/// `stats`.
+///
+/// Windows are only reused when they are dropped at the ends of pieces; with `EdgeMode::Shrink`
+/// every value is computed, as [`position_values`] computes it.
+pub(crate) fn memoized_record_track(
+    bases: &[u8],
+    params: &PipelineParams,
+    summary: &mut PipelineSummary,
+    stats: &mut MemoStats,
+) -> io::Result<Vec<f64>> {
+    params.cancel.check()?;
+    let values = match params.edge_mode {
+        EdgeMode::Drop => {
+            let flank = params.flank() as u64;
+            let mut values = vec![f64::NAN; bases.len()];
+            for (start, piece) in pieces(bases) {
+                let piece: Vec<u8> = piece.iter().map(u8::to_ascii_uppercase).collect();
+                let curve = memoized_curvature(
+                    &piece,
+                    params.roll_type.clone(),
+                    params.step_b,
+                    params.step_two,
+                    params.step_c,
+                    params.curve_scale,
+                    params.convention,
+                    stats,
+                );
+                for (i, value) in curve.into_iter().enumerate() {
+                    values[start + params.anchor.position(i as u64, flank) as usize] = value;
+                }
+            }
+            values
+        }
+        EdgeMode::Shrink => position_values(bases, params),
+    };
+    count_record(summary, bases, &values);
+    Ok(values)
+}
+
+/// Counts a record whose values were computed in memory in `summary`, as `record_values()`
+/// counts the records it computes.
+pub(crate) fn count_record(summary: &mut PipelineSummary, bases: &[u8], values: &[f64]) {
+    summary.records += 1;
+    summary.bases += bases.len() as u64;
+    let computed = values.iter().filter(|value| !value.is_nan()).count() as u64;
+    summary.values += computed;
+    summary.gap_bases += bases
+        .iter()
+        .filter(|&&base| base.eq_ignore_ascii_case(&b'N'))
+        .count() as u64;
+    summary.ambiguous_bases += bases
+        .iter()
+        .filter(|&&base| !is_acgt(base) && !base.eq_ignore_ascii_case(&b'N'))
+        .count() as u64;
+    if computed == 0 && bases.iter().any(|&base| is_acgt(base)) {
+        summary.short_records += 1;
+    }
+}
+
+/// Scores the mirror symmetry of a record's curvature as the symmetry track does, one score per
+/// position at the center of its window, with `NaN` where there is none.
+pub(crate) fn symmetry_values(values: &[f64], params: &PipelineParams) -> Vec<f64> {
+    let mut scores = SymmetryScores::new(params.symmetry_window, params.symmetry_step);
+    let mut symmetry = vec![f64::NAN; values.len()];
+    for &value in values {
+        if let (Some(score), Some(center)) = (scores.push(value), scores.last_center()) {
+            symmetry[center as usize] = score;
+        }
+    }
+    symmetry
+}
+
+/// An output file in the format of the run, taking each record's values whole, for the outputs
+/// [`run`](crate::run::run) computes in memory.
+pub(crate) enum TrackFile<'a> {
+    BigWig(BigWigWriter<AtomicFile>),
+    BedGraph(BedGraphWriter<CompressedWriter<AtomicFile>>),
+    Tsv(TsvTrack<'a, CompressedWriter<AtomicFile>>),
+}
+
+impl<'a> TrackFile<'a> {
+    /// Starts the output at `path`, which only appears there once committed.
+    pub(crate) fn create(
+        path: &Path,
+        format: OutputFormat,
+        params: &'a PipelineParams,
+    ) -> io::Result<Self> {
+        let file = AtomicFile::create(path)?;
+        Ok(match format {
+            OutputFormat::BigWig => TrackFile::BigWig(bigwig_writer(file, params)?),
+            OutputFormat::BedGraph => {
+                let text = params.compression.writer(file);
+                TrackFile::BedGraph(bedgraph_writer(text, &params.track_line, params)?)
+            }
+            OutputFormat::Tsv => {
+                TrackFile::Tsv(TsvTrack::new(params.compression.writer(file), params)?)
+            }
+        })
+    }
+
+    /// Writes the values of the next record, one per position, `NaN` where there is none.
+    pub(crate) fn write_record(&mut self, chrom: &str, values: &[f64]) -> io::Result<()> {
+        match self {
+            TrackFile::BigWig(track) => write_values(track, chrom, values),
+            TrackFile::BedGraph(track) => write_values(track, chrom, values),
+            TrackFile::Tsv(track) => write_values(track, chrom, values),
+        }
+    }
+
+    /// Writes whatever is left of the output and moves it to its path.
+    pub(crate) fn commit(self) -> io::Result<()> {
+        let file = match self {
+            TrackFile::BigWig(track) => track.finish()?,
+            TrackFile::BedGraph(track) => track.finish()?.finish()?,
+            TrackFile::Tsv(track) => track.writer.finish()?,
+        };
+        file.commit()
+    }
+}
+
+/// Helper to `TrackFile` that writes one record's values to a track.
+fn write_values<T: Track>(track: &mut T, chrom: &str, values: &[f64]) -> io::Result<()> {
+    track.start_chrom(chrom)?;
+    for (pos, &value) in (0..).zip(values) {
+        track.value(chrom, pos, value)?;
+    }
+    track.end_record(chrom, values.len() as u64)
+}
+
+/// Buffers reused from one record to the next.
+///
+/// With millions of small records, such as the contigs of a metagenome assembly, allocating the
+/// window buffers and the text of every row afresh adds up. Pass the same `Scratch` to each
+/// [`write_record_with`] call instead; its contents never carry over between records.
+///
+/// # Fields
+///
+/// * `curve`: The window buffers of the curvature iterator stack.
+/// * `line`: The text of the row being written.
+#[derive(Default)]
+pub struct Scratch {
+    curve: CurveBuffers,
+    line: String,
+}
+
+impl Scratch {
+    /// The memory held by the buffers, in bytes.
+    pub fn capacity_bytes(&self) -> usize {
+        self.curve.capacity_bytes() + self.line.capacity()
+    }
+}
+
+/// Runs the curvature pipeline on one record and writes its rows.
+///
+/// This is the per-record part of [`run_pipeline`], for callers that already have their
+/// sequences in memory or read them some other way. It allocates its buffers for this record
+/// only; use [`write_record_with`] to reuse them across records.
+///
+/// # Arguments
+///
+/// * `writer` - Where the rows are written.
+/// * `chrom` - The record name written in the first column.
+/// * `bases` - The bases of the record.
+/// * `params` - The parameters of the run.
+/// * `summary` - Updated with what the record contained.
+///
+/// # Errors
+///
+/// Returns the first error from `bases`, or any error from writing.
+pub fn write_record<W, B>(
+    writer: &mut W,
+    chrom: &str,
+    bases: B,
+    params: &PipelineParams,
+    summary: &mut PipelineSummary,
+) -> io::Result<()>
+where
+    W: Write,
+    B: Iterator<Item = io::Result<u8>>,
+{
+    write_record_with(
+        writer,
+        chrom,
+        bases,
+        params,
+        summary,
+        &mut Scratch::default(),
+    )
+}
+
+/// Runs the curvature pipeline on one record and writes its rows, reusing the buffers in
+/// `scratch`.
+///
+/// The rows are the same as [`write_record`] writes.
+///
+/// # Arguments
+///
+/// * `writer` - Where the rows are written.
+/// * `chrom` - The record name written in the first column.
+/// * `bases` - The bases of the record.
+/// * `params` - The parameters of the run.
+/// * `summary` - Updated with what the record contained.
+/// * `scratch` - The buffers to reuse.
+///
+/// # Errors
+///
+/// Returns the first error from `bases`, or any error from writing.
+pub fn write_record_with<W, B>(
+    writer: &mut W,
+    chrom: &str,
+    bases: B,
+    params: &PipelineParams,
+    summary: &mut PipelineSummary,
+    scratch: &mut Scratch,
+) -> io::Result<()>
+where
+    W: Write,
+    B: Iterator<Item = io::Result<u8>>,
+{
+    let mut rows = TextRows {
+        writer,
+        line: &mut scratch.line,
+        params,
+    };
+    record_values(&mut rows, chrom, bases, params, summary, &mut scratch.curve)
+}
+
+/// Runs the curvature pipeline from FASTA text to a bigWig file.
+///
+/// Records are streamed as in [`run_pipeline`], and each becomes a chromosome of the bigWig
+/// file, with the length of the record and a value at each base [`run_pipeline`] writes a value
+/// for.
+///
+/// # Arguments
+///
+/// * `reader` - The FASTA text.
+/// * `writer` - Where the file is written, from its start.
+/// * `params` - The parameters of the run; the text formatting ones are not used.
+///
+/// # Errors
+///
+/// Returns any error from reading or writing, or an `io::ErrorKind::InvalidInput` error if a
+/// record is too long for a bigWig file or two records have the same name.
+pub fn run_pipeline_bigwig<R: BufRead, W: Write + Seek>(
+    reader: R,
+    writer: W,
+    params: &PipelineParams,
+) -> io::Result<PipelineSummary> {
+    run_tracks(reader, bigwig_writer(writer, params)?, None, params, None)
+}
+
+/// Helper to `run_pipeline_bigwig()` and `run_file()` that starts a bigWig output with the span,
+/// aggregation, threads and provenance of `params`.
+fn bigwig_writer<W: Write + Seek>(
+    writer: W,
+    params: &PipelineParams,
+) -> io::Result<BigWigWriter<W>> {
+    let bigwig = BigWigWriter::new(writer)?
+        .with_span(params.span, params.aggregate)
+        .with_threads(params.bigwig_threads);
+    Ok(match &params.provenance {
+        Some(provenance) => bigwig.with_provenance(provenance),
+        None => bigwig,
+    })
+}
+
+/// Runs the curvature pipeline from FASTA text to bedGraph lines.
+///
+/// Records are streamed as in [`run_pipeline`]. Each line covers a run of bases whose values are
+/// written the same with `params.float_format`, and bases without a value are left out. A track
+/// line comes first if `params.track_line` has any settings, then the provenance of
+/// `params.provenance` as `#` comment lines.
+///
+/// # Arguments
+///
+/// * `reader` - The FASTA text.
+/// * `writer` - Where the lines are written.
+/// * `params` - The parameters of the run; `params.na` is not used.
+///
+/// # Errors
+///
+/// Returns any error from reading or writing.
+pub fn run_pipeline_bedgraph<R: BufRead, W: Write>(
+    reader: R,
+    writer: W,
+    params: &PipelineParams,
+) -> io::Result<PipelineSummary> {
+    let curve = bedgraph_writer(writer, &params.track_line, params)?;
+    run_tracks(reader, curve, None, params, None)
+}
+
+/// Helper to `run_pipeline_bedgraph()` and `run_file()` that starts a bedGraph output with
+/// `track_line`, if it has any settings, and the provenance of `params`, if there is one.
+fn bedgraph_writer<W: Write>(
+    writer: W,
+    track_line: &TrackLine,
+    params: &PipelineParams,
+) -> io::Result<BedGraphWriter<W>> {
+    let mut bedgraph = BedGraphWriter::new(writer, params.float_format);
+    if *track_line != TrackLine::default() {
+        bedgraph.write_track_line(track_line)?;
+    }
+    if let Some(provenance) = &params.provenance {
+        bedgraph.write_provenance(provenance)?;
+    }
+    Ok(bedgraph)
+}
+
+/// Helper to the file pipelines that streams the records of `reader` into the curvature
+/// output `curve`, and into the symmetry output `symmetry` if there is one.
+///
+/// With `state`, the name, the anomalies of each record finished and the size of the curvature
+/// buffers are kept there, as are the times of the stages if it keeps them, the issues of each
+/// record are reported to its log, and the run stops between records once an interrupt is
+/// requested, as `run_file()` does. The library pipelines pass `None` and read to the end.
+///
+/// A malformed record stops the run with an error, as records are written while they are read.
+/// [`run`](crate::run::run) reads them whole to leave them out instead.
+fn run_tracks<R, T>(
+    reader: R,
+    curve: T,
+    symmetry: Option<T>,
+    params: &PipelineParams,
+    mut state: Option<&mut FileRun<'_>>,
+) -> io::Result<PipelineSummary>
+where
+    R: BufRead,
+    T: Track,
+{
+    let timed = state.as_ref().is_some_and(|state| state.timed());
+    let mut tracks = CurveTracks::new(curve, symmetry, params, timed);
+    let summary = read_tracks(reader, &mut tracks, params, state.as_deref_mut())?;
+    let _span = stage_span(state.as_deref(), Stage::OutputWriting);
+    let mut timings = state.and_then(|state| state.timings.as_mut());
+    timed_stage(&mut timings, Stage::OutputWriting, || tracks.finish())?;
+    Ok(summary)
+}
+
+/// Helper to `run_file_with()` that runs [`sorted_tracks`] if `state.sort_contigs` is not the
+/// input order, otherwise [`queued_tracks`] if `state.max_memory` is set, otherwise
+/// [`run_tracks`]. Sorted records are written from their spill file, so no computed values wait
+/// on the writer for `max_memory` to bound.
+fn file_tracks<R, T>(
+    reader: R,
+    curve: T,
+    symmetry: Option<T>,
+    params: &PipelineParams,
+    state: &mut FileRun<'_>,
+) -> io::Result<PipelineSummary>
+where
+    R: BufRead,
+    T: Track + Send,
+{
+    if state.sort_contigs != SortContigs::Input {
+        return sorted_tracks(reader, curve, symmetry, params, state);
+    }
+    match state.max_memory {
+        Some(capacity) => queued_tracks(reader, curve, symmetry, params, state, capacity),
+        None => run_tracks(reader, curve, symmetry, params, Some(state)),
+    }
+}
+
+/// Helper to `file_tracks()` that streams the records of `reader` as [`run_tracks`] does, but
+/// writes them in the order of `state.sort_contigs`.
+///
+/// The values of each record are appended to a [`SpillFile`] in `state.spill_dir` as they are
+/// computed, and once the input is read each record is streamed back out of it straight into
+/// the outputs, so that neither the bases nor the values of a record are ever held whole.
+/// `state.completed` lists the records written, which are none if the run is interrupted before
+/// the input is read.
+///
+/// # Errors
+///
+/// Returns any error `run_tracks()` would, or from writing or reading the spill file, which
+/// needs `state.spill_dir`.
+fn sorted_tracks<R, T>(
+    reader: R,
+    curve: T,
+    symmetry: Option<T>,
+    params: &PipelineParams,
+    state: &mut FileRun<'_>,
+) -> io::Result<PipelineSummary>
+where
+    R: BufRead,
+    T: Track,
+{
+    let dir = state.spill_dir.as_deref().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "records are sorted as they stream through a --spill-dir",
+        )
+    })?;
+    let timed = state.timed();
+    let mut rows = SpillRows {
+        spill: SpillFile::create(dir)?,
+        records: Vec::new(),
+        clock: timed.then(StageClock::default),
+    };
+    let summary = read_tracks(reader, &mut rows, params, Some(state))?;
+    state.completed.clear();
+    // the records are written back out of the spill file as one stage
+    let tracer = state.tracer;
+    let _span = tracer.map(|tracer| tracer.span(Stage::OutputWriting.name()));
+    let writing = Instant::now();
+    let mut tracks = CurveTracks::new(curve, symmetry, params, timed);
+    if !signal::interrupted() {
+        let SpillRows {
+            mut spill, records, ..
+        } = rows;
+        // spilled records are stored under their index, as names can repeat
+        let mut records: Vec<_> = records.into_iter().enumerate().collect();
+        state
+            .sort_contigs
+            .sort(&mut records, |(_, (chrom, len))| (chrom, *len));
+        for (index, (chrom, len)) in records {
+            if signal::interrupted() {
+                break;
+            }
+            tracks.start_chrom(&chrom)?;
+            let mut written = 0;
+            for (offset, value) in (0..).zip(spill.read_chrom(&index.to_string())?) {
+                let value = value?;
+                if !value.is_nan() {
+                    tracks.missing(&chrom, written, offset - written)?;
+                    tracks.value(&chrom, offset, value)?;
+                    written = offset + 1;
+                }
+            }
+            tracks.missing(&chrom, written, len - written)?;
+            tracks.end_record(&chrom, len)?;
+            state.completed.push(chrom);
+        }
+    }
+    let symmetry = tracks.take_timings().get(Stage::Symmetry);
+    tracks.finish()?;
+    if let Some(timings) = &mut state.timings {
+        timings.add(Stage::Symmetry, symmetry);
+        timings.add(
+            Stage::OutputWriting,
+            writing.elapsed().saturating_sub(symmetry),
+        );
+    }
+    Ok(summary)
+}
+
+/// The rows of the records, appended to a spill file one value per position to be written in
+/// another order once the input is read.
+///
+/// # Fields
+///
+/// * `spill`: Where the values are stored, under the index of their record.
+/// * `records`: The name and length of each record, in input order.
+/// * `clock`: The time spent spilling, as output writing, if it is kept.
+struct SpillRows {
+    spill: SpillFile,
+    records: Vec<(String, u64)>,
+    clock: Option<StageClock>,
+}
+
+impl SpillRows {
+    /// Runs `f` on the spill file as output writing.
+    fn timed(&mut self, f: impl FnOnce(&mut SpillFile) -> io::Result<()>) -> io::Result<()> {
+        match &mut self.clock {
+            Some(clock) => clock.time(Stage::OutputWriting, || f(&mut self.spill)),
+            None => f(&mut self.spill),
+        }
+    }
+}
+
+impl Rows for SpillRows {
+    fn missing(&mut self, _chrom: &str, _offset: u64, count: u64) -> io::Result<()> {
+        self.timed(|spill| (0..count).try_for_each(|_| spill.push(f64::NAN)))
+    }
+
+    fn value(&mut self, _chrom: &str, _offset: u64, value: f64) -> io::Result<()> {
+        self.timed(|spill| spill.push(value))
+    }
+
+    fn end_record(&mut self, _chrom: &str, len: u64) -> io::Result<()> {
+        if let Some((_, record_len)) = self.records.last_mut() {
+            *record_len = len;
+        }
+        Ok(())
+    }
+}
+
+impl Track for SpillRows {
+    fn start_chrom(&mut self, chrom: &str) -> io::Result<()> {
+        self.spill.start_chrom(&self.records.len().to_string());
+        self.records.push((chrom.to_string(), 0));
+        Ok(())
+    }
+
+    fn finish(self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn take_timings(&mut self) -> Timings {
+        self.clock
+            .as_mut()
+            .map(StageClock::take)
+            .unwrap_or_default()
+    }
+}
+
+/// Helper to `file_tracks()` that streams the records of `reader` as [`run_tracks`] does, but
+/// writes the outputs on a thread of their own.
+///
+/// Rows are handed to the writer in chunks through a [`BoundedQueue`] of `capacity` bytes, and
+/// computation waits while it is full, so a writer falling behind holds up the run rather than
+/// its memory. No chunk is larger than a quarter of `capacity`, nor than [`QUEUE_CHUNK_BYTES`].
+/// The largest size the queue held is kept in `state.memory` as its `queue` buffer. The stages
+/// of the writer, timed on its own thread, are added to those of the computation.
+///
+/// # Errors
+///
+/// Returns any error `run_tracks()` would, or an `io::ErrorKind::InvalidInput` error if
+/// `capacity` is too small to hold a single row.
+fn queued_tracks<R, T>(
+    reader: R,
+    curve: T,
+    symmetry: Option<T>,
+    params: &PipelineParams,
+    state: &mut FileRun<'_>,
+    capacity: usize,
+) -> io::Result<PipelineSummary>
+where
+    R: BufRead,
+    T: Track + Send,
+{
+    let queue = BoundedQueue::new(capacity);
+    let timed = state.timed();
+    let tracks = CurveTracks::new(curve, symmetry, params, timed);
+    let (read, written) = thread::scope(|scope| {
+        let writer = scope.spawn(|| write_queued(&queue, tracks));
+        let chunk_bytes = (capacity / 4).min(QUEUE_CHUNK_BYTES);
+        let mut rows = QueuedRows {
+            queue: &queue,
+            calls: Vec::with_capacity(chunk_bytes / mem::size_of::<TrackCall>()),
+            bytes: 0,
+            chunk_bytes,
+            timings: timed.then(Timings::new),
+        };
+        let read = read_tracks(reader, &mut rows, params, Some(&mut *state));
+        let read = read.and_then(|summary| rows.finish().map(|()| summary));
+        queue.close();
+        (read, writer.join().unwrap())
+    });
+    state.memory.record_buffer("queue", queue.peak_bytes());
+    let summary = read?;
+    let written = written?;
+    if let Some(timings) = &mut state.timings {
+        timings.merge(&written);
+    }
+    Ok(summary)
+}
+
+/// Helper to `run_tracks()`, `sorted_tracks()` and `queued_tracks()` that reads the records of
+/// `reader` into `tracks`, leaving them to be finished by the caller.
+///
+/// The stages of a streamed record run interleaved, value by value, so when `state` keeps their
+/// times or a trace the bases are timed as they are read, `tracks` times what it does with the
+/// rows, and the rest of the record's time is its curvature. The trace gets a span of each
+/// record, holding a span of each of its stages with the time summed over the record, laid end
+/// to end.
+fn read_tracks<R, T>(
+    reader: R,
+    tracks: &mut T,
+    params: &PipelineParams,
+    mut state: Option<&mut FileRun<'_>>,
+) -> io::Result<PipelineSummary>
+where
+    R: BufRead,
+    T: Track,
+{
+    let mut fasta = StreamingReader::new(reader);
+    let mut summary = PipelineSummary::default();
+    let mut buffers = CurveBuffers::default();
+    let mut index = 0;
+    let timed = state.as_ref().is_some_and(|state| state.timed());
+    let tracer = state.as_ref().and_then(|state| state.tracer);
+    while state.is_none() || !signal::interrupted() {
+        let decoding_span = tracer.map(|tracer| tracer.span(Stage::FastaDecoding.name()));
+        let header = Instant::now();
+        let Some(chrom) = fasta.next_record()? else {
+            break;
+        };
+        let header = header.elapsed();
+        drop(decoding_span);
+        let _record_span = tracer.map(|tracer| tracer.span(chrom.as_str()));
+        let start = Instant::now();
+        index += 1;
+        check_record(index, &chrom, &[])?;
+        let before = summary;
+        let mut ambiguity = AmbiguityTracker::new();
+        let mut decoding = StageClock::default();
+        let bases = TimedBases {
+            bases: fasta.bases(),
+            clock: timed.then_some(&mut decoding),
+        };
+        let bases = checked_bases(index, &chrom, bases).inspect(|base| {
+            if let Ok(base) = base {
+                ambiguity.push(*base);
+            }
+        });
+        tracks.start_chrom(&chrom)?;
+        record_values(tracks, &chrom, bases, params, &mut summary, &mut buffers)?;
+        let mut stages = tracks.take_timings();
+        stages.merge(&decoding.take());
+        stages.add(
+            Stage::Curvature,
+            start.elapsed().saturating_sub(stages.total()),
+        );
+        if let Some(tracer) = tracer {
+            let mut at = start;
+            for stage in Stage::ALL {
+                let elapsed = stages.get(stage);
+                if !elapsed.is_zero() {
+                    tracer.add_span(stage.name(), at, elapsed);
+                    at += elapsed;
+                }
+            }
+        }
+        stages.add(Stage::FastaDecoding, header);
+        if let Some(state) = state.as_mut() {
+            if let Some(timings) = &mut state.timings {
+                timings.merge(&stages);
+            }
+            state
+                .memory
+                .record_buffer("curvature", buffers.capacity_bytes());
+            let since = summary.since(&before);
+            state.warnings.record_sequence(&chrom, &since);
+            if let Some(issues) = &mut state.issues {
+                for issue in sequence_issues(&chrom, ambiguity.finish(), &since) {
+                    issues.report(&issue)?;
+                }
+            }
+            state.completed.push(chrom);
+        }
+    }
+    Ok(summary)
+}
+
+/// The bases of a record, with the time spent reading them kept by `clock` if there is one.
+struct TimedBases<'a, I> {
+    bases: I,
+    clock: Option<&'a mut StageClock>,
+}
+
+impl<I: Iterator> Iterator for TimedBases<'_, I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        match &mut self.clock {
+            Some(clock) => clock.time(Stage::FastaDecoding, || self.bases.next()),
+            None => self.bases.next(),
+        }
+    }
+}
+
+/// The number of calls of a stage `StageClock` times one of.
+const SAMPLED_CALLS: u32 = 16;
+
+/// The time of the stages of a streamed record, which are called value by value.
+///
+/// Timing every call would take about as long as many of the calls themselves, so one call in
+/// every [`SAMPLED_CALLS`] of each stage is timed and counted for all of them.
+///
+/// # Fields
+///
+/// * `timings`: The time counted for each stage so far.
+/// * `calls`: The number of calls of each stage so far, in the order of [`Stage::ALL`].
+#[derive(Default)]
+struct StageClock {
+    timings: Timings,
+    calls: [u32; Stage::ALL.len()],
+}
+
+impl StageClock {
+    /// Counts a call of `stage`, giving when it starts if it is one to time.
+    fn start(&mut self, stage: Stage) -> Option<Instant> {
+        let calls = &mut self.calls[stage as usize];
+        *calls = calls.wrapping_add(1);
+        calls.is_multiple_of(SAMPLED_CALLS).then(Instant::now)
+    }
+
+    /// Counts the time of a call of `stage` that started at `start` for all the calls it
+    /// stands for.
+    fn stop(&mut self, stage: Stage, start: Instant) {
+        self.timings.add(stage, start.elapsed() * SAMPLED_CALLS);
+    }
+
+    /// Runs `f` as a call of `stage`.
+    fn time<R>(&mut self, stage: Stage, f: impl FnOnce() -> R) -> R {
+        let start = self.start(stage);
+        let result = f();
+        if let Some(start) = start {
+            self.stop(stage, start);
+        }
+        result
+    }
+
+    /// Gives the time counted so far, starting again from none.
+    fn take(&mut self) -> Timings {
+        mem::take(&mut self.timings)
+    }
+}
+
+/// Helper to the file pipelines that opens a span of `stage` in the trace of `state`, if there
+/// is one.
+fn stage_span<'a>(state: Option<&FileRun<'a>>, stage: Stage) -> Option<Span<'a>> {
+    state
+        .and_then(|state| state.tracer)
+        .map(|tracer| tracer.span(stage.name()))
+}
+
+/// Runs `f`, adding its time to `stage` of `timings` if they are kept.
+fn timed_stage<R>(timings: &mut Option<&mut Timings>, stage: Stage, f: impl FnOnce() -> R) -> R {
+    match timings {
+        Some(timings) => timings.time(stage, f),
+        None => f(),
+    }
+}
+
+/// The most bytes of rows `queued_tracks()` hands to its writer at once.
+const QUEUE_CHUNK_BYTES: usize = 1 << 20;
+
+/// A call to the methods of a [`Track`], queued for the thread writing it.
+enum TrackCall {
+    Start(String),
+    Missing(u64, u64),
+    Skip(u64, u64),
+    Value(u64, f64),
+    End(u64),
+}
+
+/// The rows of the records, queued in chunks for the thread writing the outputs.
+///
+/// # Fields
+///
+/// * `queue`: Where the chunks are handed to the writer.
+/// * `calls`: The chunk being filled.
+/// * `bytes`: The size of `calls` in bytes.
+/// * `chunk_bytes`: The size a chunk is handed on at, unless a single call is larger.
+/// * `timings`: The time spent handing chunks on, waiting on the writer included, as output
+///   writing, if it is kept.
+struct QueuedRows<'a> {
+    queue: &'a BoundedQueue<Vec<TrackCall>>,
+    calls: Vec<TrackCall>,
+    bytes: usize,
+    chunk_bytes: usize,
+    timings: Option<Timings>,
+}
+
+impl QueuedRows<'_> {
+    /// Adds a call to the chunk, handing the chunk on first if the call would take it over
+    /// `chunk_bytes`.
+    fn call(&mut self, call: TrackCall) -> io::Result<()> {
+        let bytes = match &call {
+            TrackCall::Start(chrom) => mem::size_of::<TrackCall>() + chrom.len(),
+            _ => mem::size_of::<TrackCall>(),
+        };
+        if self.bytes + bytes > self.chunk_bytes {
+            self.flush()?;
+        }
+        self.calls.push(call);
+        self.bytes += bytes;
+        Ok(())
+    }
+
+    /// Hands the chunk to the writer, waiting while the queue is full.
+    fn flush(&mut self) -> io::Result<()> {
+        if self.calls.is_empty() {
+            return Ok(());
+        }
+        let calls = mem::replace(
+            &mut self.calls,
+            Vec::with_capacity(self.chunk_bytes / mem::size_of::<TrackCall>()),
+        );
+        let (queue, bytes) = (self.queue, mem::take(&mut self.bytes));
+        timed_stage(&mut self.timings.as_mut(), Stage::OutputWriting, || {
+            queue.push(calls, bytes)
+        })
+    }
+}
+
+impl Rows for QueuedRows<'_> {
+    fn missing(&mut self, _chrom: &str, offset: u64, count: u64) -> io::Result<()> {
+        self.call(TrackCall::Missing(offset, count))
+    }
+
+    fn skip(&mut self, _chrom: &str, offset: u64, count: u64) -> io::Result<()> {
+        self.call(TrackCall::Skip(offset, count))
+    }
+
+    fn value(&mut self, _chrom: &str, offset: u64, value: f64) -> io::Result<()> {
+        self.call(TrackCall::Value(offset, value))
+    }
+
+    fn end_record(&mut self, _chrom: &str, len: u64) -> io::Result<()> {
+        self.call(TrackCall::End(len))
+    }
+}
+
+impl Track for QueuedRows<'_> {
+    fn start_chrom(&mut self, chrom: &str) -> io::Result<()> {
+        self.call(TrackCall::Start(chrom.to_string()))
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.flush()
+    }
+
+    fn take_timings(&mut self) -> Timings {
+        self.timings.as_mut().map(mem::take).unwrap_or_default()
+    }
+}
+
+/// Helper to `queued_tracks()` that makes the calls taken from `queue` on `tracks` and finishes
+/// them once the queue is closed, giving the times of the stages `tracks` kept.
+fn write_queued<T: Track>(
+    queue: &BoundedQueue<Vec<TrackCall>>,
+    mut tracks: T,
+) -> io::Result<Timings> {
+    // keep taking chunks after a failure, so that computation never waits on a writer that has
+    // stopped
+    let mut chrom = String::new();
+    let mut written = Ok(());
+    while let Some(calls) = queue.pop() {
+        if written.is_ok() {
+            written = calls.into_iter().try_for_each(|call| match call {
+                TrackCall::Start(name) => {
+                    chrom = name;
+                    tracks.start_chrom(&chrom)
+                }
+                TrackCall::Missing(offset, count) => tracks.missing(&chrom, offset, count),
+                TrackCall::Skip(offset, count) => tracks.skip(&chrom, offset, count),
+                TrackCall::Value(offset, value) => tracks.value(&chrom, offset, value),
+                TrackCall::End(len) => tracks.end_record(&chrom, len),
+            });
+        }
+    }
+    written?;
+    let mut timings = tracks.take_timings();
+    let finish = Instant::now();
+    tracks.finish()?;
+    timings.add(Stage::OutputWriting, finish.elapsed());
+    Ok(timings)
+}
+
+/// Helper to `run_file()` that computes only `params.regions` of a FASTA file into the
+/// curvature output `curve`.
+///
+/// Each region is computed from its bases and `2 * flank` more on either side, which covers the
+/// windows of every value in it. Each region counts as a record of the summary, and its bases
+/// include that context.
+fn run_region_tracks<T: Track>(
+    input: &Path,
+    mut curve: T,
+    params: &PipelineParams,
+) -> io::Result<PipelineSummary> {
+    if input.as_os_str() == STDIN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "regions can only be read from a FASTA file, not from standard input",
+        ));
+    }
+    let index = FastaIndex::load(input)?;
+    let mut file = File::open(input)?;
+    let mut summary = PipelineSummary::default();
+    let mut buffers = CurveBuffers::default();
+    let pad = 2 * params.flank() as u64;
+    for (record, ranges) in region_ranges(&index, &params.regions)? {
+        let chrom = record.name.as_str();
+        curve.start_chrom(chrom)?;
+        let mut pos = 0;
+        for range in ranges {
+            curve.skip(chrom, pos, range.start - pos)?;
+            let context = range.start.saturating_sub(pad)..(range.end + pad).min(record.length);
+            let bases = FastaIndex::fetch(&mut file, record, context.start, context.end)?;
+            let mut rows = RegionRows {
+                track: &mut curve,
+                offset: context.start,
+                region: range.clone(),
+            };
+            record_values(
+                &mut rows,
+                chrom,
+                bases.into_iter().map(Ok),
+                params,
+                &mut summary,
+                &mut buffers,
+            )?;
+            pos = range.end;
+        }
+        curve.skip(chrom, pos, record.length - pos)?;
+        curve.end_record(chrom, record.length)?;
+    }
+    curve.finish()?;
+    Ok(summary)
+}
+
+/// The regions to compute on each sequence, as `region_ranges()` gives them.
+type RegionRanges<'a> = Vec<(&'a FaiRecord, Vec<Range<u64>>)>;
+
+/// Helper to `run_region_tracks()` that sorts regions into the order of the index, clamped to
+/// their sequences, with overlapping and adjacent ones merged and empty ones left out.
+///
+/// # Errors
+///
+/// Returns an `io::ErrorKind::NotFound` error if a region's sequence is not in the index.
+fn region_ranges<'a>(index: &'a FastaIndex, regions: &[Region]) -> io::Result<RegionRanges<'a>> {
+    let mut ranges: Vec<(usize, Range<u64>)> = Vec::new();
+    for region in regions {
+        let (i, record) = index
+            .records
+            .iter()
+            .enumerate()
+            .find(|(_, record)| record.name == region.chrom)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("sequence {} not found", region.chrom),
+                )
+            })?;
+        let end = region.end.unwrap_or(record.length).min(record.length);
+        if region.start < end {
+            ranges.push((i, region.start..end));
+        }
+    }
+    ranges.sort_by_key(|(i, range)| (*i, range.start));
+    let mut merged: RegionRanges = Vec::new();
+    for (i, range) in ranges {
+        let record = &index.records[i];
+        match merged.last_mut() {
+            Some((last, chrom_ranges)) if std::ptr::eq(*last, record) => {
+                match chrom_ranges.last_mut() {
+                    Some(last_range) if range.start <= last_range.end => {
+                        last_range.end = last_range.end.max(range.end);
+                    }
+                    _ => chrom_ranges.push(range),
+                }
+            }
+            _ => merged.push((record, vec![range])),
+        }
+    }
+    Ok(merged)
+}
+
+/// The rows of a region computed with bases around it, passed on to a track in chromosome
+/// coordinates with the positions outside the region left out.
+///
+/// # Fields
+///
+/// * `track`: The output.
+/// * `offset`: The chromosome position of the first base computed.
+/// * `region`: The region, in chromosome coordinates.
+struct RegionRows<'a, T> {
+    track: &'a mut T,
+    offset: u64,
+    region: Range<u64>,
+}
+
+impl<T: Rows> Rows for RegionRows<'_, T> {
+    fn missing(&mut self, chrom: &str, offset: u64, count: u64) -> io::Result<()> {
+        let start = (self.offset + offset).max(self.region.start);
+        let end = (self.offset + offset + count).min(self.region.end);
+        if start < end {
+            self.track.missing(chrom, start, end - start)?;
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, chrom: &str, offset: u64, value: f64) -> io::Result<()> {
+        let pos = self.offset + offset;
+        if self.region.contains(&pos) {
+            self.track.value(chrom, pos, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the values of a record go, position by position.
+trait Rows {
+    /// Takes a run of positions without values.
+    fn missing(&mut self, chrom: &str, offset: u64, count: u64) -> io::Result<()>;
+
+    /// Takes a run of positions outside the regions computed, which outputs listing every
+    /// position leave out.
+    fn skip(&mut self, chrom: &str, offset: u64, count: u64) -> io::Result<()> {
+        self.missing(chrom, offset, count)
+    }
+
+    /// Takes the value at one position, which may be `NaN`.
+    fn value(&mut self, chrom: &str, offset: u64, value: f64) -> io::Result<()>;
+
+    /// Takes the end of a record, after all of its `len` positions.
+    fn end_record(&mut self, _chrom: &str, _len: u64) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An output file of the pipeline, taking the records one after another.
+trait Track: Rows {
+    /// Starts the next record.
+    fn start_chrom(&mut self, chrom: &str) -> io::Result<()>;
+
+    /// Writes whatever is left of the output.
+    fn finish(self) -> io::Result<()>;
+
+    /// Gives the time spent in each stage since it was last asked, if the output keeps it.
+    fn take_timings(&mut self) -> Timings {
+        Timings::new()
+    }
+}
+
+/// The values of a record, collected one per position.
+struct ValueRows(Vec<f64>);
+
+impl Rows for ValueRows {
+    fn missing(&mut self, _chrom: &str, _offset: u64, count: u64) -> io::Result<()> {
+        self.0.extend(iter::repeat_n(f64::NAN, count as usize));
+        Ok(())
+    }
+
+    fn value(&mut self, _chrom: &str, _offset: u64, value: f64) -> io::Result<()> {
+        self.0.push(value);
+        Ok(())
+    }
+}
+
+/// The TSV output, owning its writer.
+///
+/// # Fields
+///
+/// * `writer`: Where the rows are written.
+/// * `line`: The text of the row being written.
+/// * `params`: How missing values and numbers are written.
+pub(crate) struct TsvTrack<'a, W> {
+    writer: W,
+    line: String,
+    params: &'a PipelineParams,
+}
+
+impl<'a, W: Write> TsvTrack<'a, W> {
+    /// Starts the output with the provenance of `params` as `#` comment lines, if there is one.
+    fn new(mut writer: W, params: &'a PipelineParams) -> io::Result<Self> {
+        if let Some(provenance) = &params.provenance {
+            provenance.write_comments(&mut writer, "#")?;
+        }
+        Ok(Self {
+            writer,
+            line: String::new(),
+            params,
+        })
+    }
+}
+
+impl<W: Write> Rows for TsvTrack<'_, W> {
+    fn missing(&mut self, chrom: &str, offset: u64, count: u64) -> io::Result<()> {
+        write_missing(
+            &mut self.writer,
+            &mut self.line,
+            chrom,
+            offset,
+            count,
+            self.params.na,
+        )
+    }
+
+    fn skip(&mut self, _chrom: &str, _offset: u64, _count: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn value(&mut self, chrom: &str, offset: u64, value: f64) -> io::Result<()> {
+        write_row(
+            &mut self.writer,
+            &mut self.line,
+            chrom,
+            offset,
+            value,
+            self.params,
+        )
+    }
+}
+
+impl<W: Write> Track for TsvTrack<'_, W> {
+    fn start_chrom(&mut self, _chrom: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Track for BedGraphWriter<W> {
+    fn start_chrom(&mut self, chrom: &str) -> io::Result<()> {
+        BedGraphWriter::start_chrom(self, chrom)
+    }
+
+    fn finish(self) -> io::Result<()> {
+        BedGraphWriter::finish(self).map(drop)
+    }
+}
+
+impl<W: Write + Seek> Track for BigWigWriter<W> {
+    fn start_chrom(&mut self, chrom: &str) -> io::Result<()> {
+        BigWigWriter::start_chrom(self, chrom)
+    }
+
+    fn finish(self) -> io::Result<()> {
+        BigWigWriter::finish(self).map(drop)
+    }
+}
+
+/// The curvature output, with the mirror symmetry of its rows scored into the symmetry output
+/// if there is one.
+///
+/// # Fields
+///
+/// * `curve`: The curvature output.
+/// * `symmetry`: The symmetry output.
+/// * `scores`: The sliding symmetry windows over the record's curvature.
+/// * `written`: The number of positions of the record written to `symmetry` so far.
+/// * `clock`: The time spent scoring symmetry and writing, if it is kept.
+struct CurveTracks<T> {
+    curve: T,
+    symmetry: Option<T>,
+    scores: SymmetryScores,
+    written: u64,
+    clock: Option<StageClock>,
+}
+
+impl<T: Track> CurveTracks<T> {
+    /// Constructor for `CurveTracks`, with the symmetry windows of `params`, keeping the time
+    /// of its stages if `timed`.
+    fn new(curve: T, symmetry: Option<T>, params: &PipelineParams, timed: bool) -> Self {
+        Self {
+            curve,
+            symmetry,
+            scores: SymmetryScores::new(params.symmetry_window, params.symmetry_step),
+            written: 0,
+            clock: timed.then(StageClock::default),
+        }
+    }
+
+    /// Runs `f` as a call of `stage`, timed if the times are kept.
+    fn timed<R>(&mut self, stage: Stage, f: impl FnOnce(&mut Self) -> R) -> R {
+        let start = self.clock.as_mut().and_then(|clock| clock.start(stage));
+        let result = f(self);
+        if let (Some(clock), Some(start)) = (&mut self.clock, start) {
+            clock.stop(stage, start);
+        }
+        result
+    }
+
+    /// Writes a score at the center of the window the latest position completed, and the
+    /// positions without a score before it.
+    fn write_score(&mut self, chrom: &str, score: f64) -> io::Result<()> {
+        let Some(center) = self.scores.last_center().filter(|_| !score.is_nan()) else {
+            return Ok(());
+        };
+        let Some(symmetry) = self.symmetry.as_mut() else {
+            return Ok(());
+        };
+        symmetry.missing(chrom, self.written, center - self.written)?;
+        symmetry.value(chrom, center, score)?;
+        self.written = center + 1;
+        Ok(())
+    }
+}
+
+impl<T: Track> Rows for CurveTracks<T> {
+    fn missing(&mut self, chrom: &str, offset: u64, count: u64) -> io::Result<()> {
+        self.timed(Stage::OutputWriting, |tracks| {
+            tracks.curve.missing(chrom, offset, count)
+        })?;
+        if self.symmetry.is_some() {
+            self.timed(Stage::Symmetry, |tracks| tracks.scores.push_missing(count));
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, chrom: &str, offset: u64, value: f64) -> io::Result<()> {
+        self.timed(Stage::OutputWriting, |tracks| {
+            tracks.curve.value(chrom, offset, value)
+        })?;
+        if self.symmetry.is_none() {
+            return Ok(());
+        }
+        match self.timed(Stage::Symmetry, |tracks| tracks.scores.push(value)) {
+            Some(score) => self.timed(Stage::OutputWriting, |tracks| {
+                tracks.write_score(chrom, score)
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn end_record(&mut self, chrom: &str, len: u64) -> io::Result<()> {
+        self.timed(Stage::OutputWriting, |tracks| {
+            tracks.curve.end_record(chrom, len)?;
+            if let Some(symmetry) = tracks.symmetry.as_mut() {
+                symmetry.missing(chrom, tracks.written, len - tracks.written)?;
+                tracks.written = len;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<T: Track> Track for CurveTracks<T> {
+    fn start_chrom(&mut self, chrom: &str) -> io::Result<()> {
+        self.timed(Stage::OutputWriting, |tracks| {
+            tracks.curve.start_chrom(chrom)?;
+            if let Some(symmetry) = tracks.symmetry.as_mut() {
+                symmetry.start_chrom(chrom)?;
+                tracks.scores.reset();
+                tracks.written = 0;
+            }
+            Ok(())
+        })
+    }
+
+    fn finish(self) -> io::Result<()> {
+        self.curve.finish()?;
+        if let Some(symmetry) = self.symmetry {
+            symmetry.finish()?;
+        }
+        Ok(())
+    }
+
+    fn take_timings(&mut self) -> Timings {
+        self.clock
+            .as_mut()
+            .map(StageClock::take)
+            .unwrap_or_default()
+    }
+}
+
+/// The rows of the TSV output.
+///
+/// # Fields
+///
+/// * `writer`: Where the rows are written.
+/// * `line`: The text of the row being written.
+/// * `params`: How missing values and numbers are written.
+struct TextRows<'a, W> {
+    writer: &'a mut W,
+    line: &'a mut String,
+    params: &'a PipelineParams,
+}
+
+impl<W: Write> Rows for TextRows<'_, W> {
+    fn missing(&mut self, chrom: &str, offset: u64, count: u64) -> io::Result<()> {
+        write_missing(self.writer, self.line, chrom, offset, count, self.params.na)
+    }
+
+    fn value(&mut self, chrom: &str, offset: u64, value: f64) -> io::Result<()> {
+        write_row(self.writer, self.line, chrom, offset, value, self.params)
+    }
+}
+
+impl<W: Write> Rows for BedGraphWriter<W> {
+    fn missing(&mut self, _chrom: &str, _offset: u64, count: u64) -> io::Result<()> {
+        self.push_missing(count)
+    }
+
+    fn value(&mut self, _chrom: &str, _offset: u64, value: f64) -> io::Result<()> {
+        self.push(value)
+    }
+}
+
+impl<W: Write + Seek> Rows for BigWigWriter<W> {
+    fn missing(&mut self, _chrom: &str, _offset: u64, count: u64) -> io::Result<()> {
+        self.push_missing(count)
+    }
+
+    fn value(&mut self, _chrom: &str, _offset: u64, value: f64) -> io::Result<()> {
+        self.push(value)
+    }
+}
+
+/// The number of values computed between checks of `PipelineParams::cancel`.
+const CANCEL_CHECK_VALUES: usize = 1 << 16;
+
+/// Helper to `write_record_with()` and the bigWig and bedGraph pipelines that runs the pipeline on one
+/// record and hands every position of it to `rows`, in order.
+fn record_values<S, B>(
+    rows: &mut S,
+    chrom: &str,
+    bases: B,
+    params: &PipelineParams,
+    summary: &mut PipelineSummary,
+    buffers: &mut CurveBuffers,
+) -> io::Result<()>
+where
+    S: Rows,
+    B: Iterator<Item = io::Result<u8>>,
+{
+    params.cancel.check()?;
+    let flank = params.flank() as u64;
+    summary.records += 1;
+    let mut cursor = Cursor {
+        bases,
+        pending: None,
+        error: None,
+    };
+    let mut pos = 0;
+    let values_before = summary.values;
+    let mut has_bases = false;
+    loop {
+        // the gap before the next piece
+        let mut gap = 0;
+        while let Some(base) = cursor.next_base() {
+            if is_acgt(base) {
+                cursor.pending = Some(base);
+                break;
+            }
+            if base.eq_ignore_ascii_case(&b'N') {
+                summary.gap_bases += 1;
+            } else {
+                summary.ambiguous_bases += 1;
+            }
+            gap += 1;
+        }
+        cursor.check()?;
+        rows.missing(chrom, pos, gap)?;
+        pos += gap;
+        if cursor.pending.is_none() {
+            break;
+        }
+        // the piece itself, written as its values arrive
+        has_bases = true;
+        let mut piece = PieceBases {
+            cursor: &mut cursor,
+            len: 0,
+            done: false,
+        };
+        let mut written = 0;
+        let mut write_values = |curve: &mut dyn Iterator<Item = f64>| -> io::Result<()> {
+            for (i, value) in curve.enumerate() {
+                if i % CANCEL_CHECK_VALUES == 0 {
+                    params.cancel.check()?;
+                }
+                let at = params.anchor.position(i as u64, flank);
+                rows.missing(chrom, pos + written, at - written)?;
+                rows.value(chrom, pos + at, value)?;
+                written = at + 1;
+                summary.values += 1;
+            }
+            Ok(())
+        };
+        match params.chunking.filter(|plan| plan.threads > 1) {
+            Some(plan) => with_chunked_curve(&mut piece, params, &plan, write_values)?,
+            None => {
+                let mut curve = CurveIter::with_buffers(
+                    &mut piece,
+                    params.roll_type.clone(),
+                    params.step_b,
+                    params.step_c,
+                    params.curve_scale,
+                    params.edge_mode,
+                    params.convention,
+                    mem::take(buffers),
+                )
+                .with_step_two(params.step_two);
+                write_values(&mut curve)?;
+                *buffers = curve.into_buffers();
+            }
+        }
+        piece.by_ref().for_each(drop);
+        let len = piece.len;
+        cursor.check()?;
+        rows.missing(chrom, pos + written, len - written)?;
+        pos += len;
+    }
+    rows.end_record(chrom, pos)?;
+    summary.bases += pos;
+    if has_bases && summary.values == values_before {
+        summary.short_records += 1;
+    }
+    Ok(())
+}
+
+/// Computes the curvature of an in-memory sequence as one value per base.
+///
+/// Values are placed where [`write_record`] would report them, and positions without a value
+/// (gaps and the flanks of each piece) are `NaN`.
+///
+/// # Arguments
+///
+/// * `seq` - The bases of the sequence, in any case, with gaps as any non-`ACGT` byte.
+/// * `params` - The parameters of the run.
+pub fn position_values(seq: &[u8], params: &PipelineParams) -> Vec<f64> {
+    let flank = params.flank() as u64;
+    let mut values = vec![f64::NAN; seq.len()];
+    for (start, piece) in pieces(seq) {
+        let curve = CurveIter::new(
+            piece.iter().map(u8::to_ascii_uppercase),
+            params.roll_type.clone(),
+            params.step_b,
+            params.step_c,
+            params.curve_scale,
+            params.edge_mode,
+            params.convention,
+        )
+        .with_step_two(params.step_two);
+        for (i, value) in curve.enumerate() {
+            values[start + params.anchor.position(i as u64, flank) as usize] = value;
+        }
+    }
+    values
+}
+
+/// The gap-free pieces of an in-memory sequence with the 0-based position each starts at, split
+/// at every base that is not `A`, `C`, `G` or `T` as the pipelines split them. Empty pieces
+/// between adjacent gap bases are left out.
+pub(crate) fn pieces(seq: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    seq.split(|&base| !is_acgt(base))
+        .scan(0, |start, piece| {
+            let piece_start = *start;
+            *start += piece.len() + 1;
+            Some((piece_start, piece))
+        })
+        .filter(|(_, piece)| !piece.is_empty())
+}
+
+/// Whether a base is one the roll matrices have values for. Lowercase (soft-masked) bases count.
+fn is_acgt(base: u8) -> bool {
+    matches!(base.to_ascii_uppercase(), b'A' | b'C' | b'G' | b'T')
+}
+
+/// Helper to `TextRows` that writes a run of positions without values.
+fn write_missing<W: Write>(
+    writer: &mut W,
+    line: &mut String,
+    chrom: &str,
+    offset: u64,
+    count: u64,
+    na: NaValue,
+) -> io::Result<()> {
+    let Some(text) = na.text() else {
+        return Ok(());
+    };
+    for pos in offset..offset + count {
+        line.clear();
+        // writing to a String cannot fail
+        let _ = writeln!(line, "{}\t{}\t{}", chrom, pos + 1, text);
+        writer.write_all(line.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Helper to `TextRows` that writes one value as a `chrom`, 1-based position, value
+/// row, as `tsv::write_track` would, staging the text in `line`.
+fn write_row<W: Write>(
+    writer: &mut W,
+    line: &mut String,
+    chrom: &str,
+    offset: u64,
+    value: f64,
+    params: &PipelineParams,
+) -> io::Result<()> {
+    if value.is_nan() {
+        return write_missing(writer, line, chrom, offset, 1, params.na);
+    }
+    line.clear();
+    let _ = write!(line, "{}\t{}\t", chrom, offset + 1);
+    let _ = params.float_format.write_to(line, value);
+    line.push('\n');
+    writer.write_all(line.as_bytes())
+}
+
+/// The bases of one record, with one base of lookahead and read errors held until checked.
+///
+/// # Fields
+///
+/// * `bases`: The bases of the record.
+/// * `pending`: A base that was read but belongs to what comes next.
+/// * `error`: The first read error, which ends the record.
+struct Cursor<B> {
+    bases: B,
+    pending: Option<u8>,
+    error: Option<io::Error>,
+}
+
+impl<B: Iterator<Item = io::Result<u8>>> Cursor<B> {
+    fn next_base(&mut self) -> Option<u8> {
+        if let Some(base) = self.pending.take() {
+            return Some(base);
+        }
+        if self.error.is_some() {
+            return None;
+        }
+        match self.bases.next()? {
+            Ok(base) => Some(base),
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+
+    /// Returns the read error that ended the record, if there was one.
+    fn check(&mut self) -> io::Result<()> {
+        self.error.take().map_or(Ok(()), Err)
+    }
+}
+
+/// Iterator over the uppercased bases of one gap-free piece of a record.
+///
+/// # Fields
+///
+/// * `cursor`: The bases of the record.
+/// * `len`: The number of bases yielded so far.
+/// * `done`: Whether the piece has ended, at a gap or the end of the record.
+struct PieceBases<'a, B> {
+    cursor: &'a mut Cursor<B>,
+    len: u64,
+    done: bool,
+}
+
+impl<B: Iterator<Item = io::Result<u8>>> Iterator for PieceBases<'_, B> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.cursor.next_base() {
+            Some(base) if is_acgt(base) => {
+                self.len += 1;
+                Some(base.to_ascii_uppercase())
+            }
+            other => {
+                // the gap base is left for the caller
+                self.cursor.pending = other;
+                self.done = true;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::ChunkSource;
+    use crate::curve::symmetry::SymmetryIterator;
+    use crate::output::tsv::write_track;
+    use crate::view::bigwig::BigWigReader;
+    use std::io::Cursor;
+
+    const SEQ: &str = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+
+    fn run(fasta: &str, params: &PipelineParams) -> (PipelineSummary, Vec<String>) {
+        let mut out = Vec::new();
+        let summary = run_pipeline(fasta.as_bytes(), &mut out, params).unwrap();
+        let rows = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        (summary, rows)
+    }
+
+    #[test]
+    fn test_run_pipeline() {
+        let params = PipelineParams::default();
+        let (summary, rows) = run(&format!(">chr1 test\n{}\n", SEQ), &params);
+        assert_eq!(
+            summary,
+            PipelineSummary {
+                records: 1,
+                bases: 50,
+                values: 8,
+                gap_bases: 0,
+                ambiguous_bases: 0,
+                short_records: 0,
+            }
+        );
+        // one row per base, values centered on their 43 bp windows
+        assert_eq!(rows.len(), 50);
+        assert_eq!(rows[20], "chr1\t21\tNaN");
+        let expected: Vec<f64> = CurveIter::new(
+            SEQ.bytes(),
+            RollType::Simple,
+            5,
+            15,
+            0.33335,
+            EdgeMode::Drop,
+            MeanConvention::Legacy,
+        )
+        .collect();
+        assert_eq!(rows[21], format!("chr1\t22\t{}", expected[0]));
+        assert_eq!(rows[28], format!("chr1\t29\t{}", expected[7]));
+        assert_eq!(rows[29], "chr1\t30\tNaN");
+    }
+
+    #[test]
+    fn test_position_values() {
+        let params = PipelineParams::default();
+        let seq = format!("{}NN{}", SEQ, SEQ.to_lowercase());
+        let values = position_values(seq.as_bytes(), &params);
+        assert_eq!(values.len(), 102);
+        let (_, rows) = run(&format!(">chr1\n{}\n", seq), &params);
+        for (value, row) in values.iter().zip(&rows) {
+            assert_eq!(row.rsplit('\t').next().unwrap(), value.to_string());
+        }
+        assert_eq!(values.iter().filter(|v| !v.is_nan()).count(), 16);
+    }
+
+    #[test]
+    fn test_run_pipeline_bigwig() {
+        let params = PipelineParams::default();
+        let chr2 = format!("{}NN{}", SEQ, SEQ.to_lowercase());
+        let fasta = format!(">chr1\n{}\n>chr2\n{}\n>short\nACGT\n", SEQ, chr2);
+        let mut file = Cursor::new(Vec::new());
+        let summary = run_pipeline_bigwig(fasta.as_bytes(), &mut file, &params).unwrap();
+        assert_eq!(summary, run(&fasta, &params).0);
+        file.set_position(0);
+        let mut reader = BigWigReader::new(file).unwrap();
+        let lengths: Vec<u32> = reader.chroms().iter().map(|c| c.length).collect();
+        assert_eq!(lengths, vec![50, 102, 4]);
+        for (chrom, seq) in [("chr1", SEQ), ("chr2", chr2.as_str())] {
+            let mut read = vec![f64::NAN; seq.len()];
+            for interval in reader.values(chrom, 0, seq.len() as u64).unwrap() {
+                for pos in interval.start..interval.end {
+                    read[pos as usize] = interval.value as f64;
+                }
+            }
+            let expected = position_values(seq.as_bytes(), &params);
+            for (a, b) in read.iter().zip(&expected) {
+                assert!(a.is_nan() == b.is_nan() && (a.is_nan() || *a == *b as f32 as f64));
+            }
+        }
+        assert!(reader.values("short", 0, 4).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_run_pipeline_bigwig_threads() {
+        // enough values for the blocks to be compressed on every thread
+        let fasta = format!(">chr1\n{}\n>chr2\n{}\n", SEQ.repeat(1000), SEQ.repeat(300));
+        let write = |bigwig_threads| {
+            let params = PipelineParams {
+                bigwig_threads,
+                ..Default::default()
+            };
+            let mut file = Cursor::new(Vec::new());
+            run_pipeline_bigwig(fasta.as_bytes(), &mut file, &params).unwrap();
+            file.into_inner()
+        };
+        assert_eq!(write(4), write(1));
+    }
+
+    #[test]
+    fn test_run_pipeline_bedgraph() {
+        let params = PipelineParams {
+            float_format: FloatFormat::Fixed(0),
+            track_line: TrackLine {
+                name: Some("curvature".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let seq = format!("{}NN{}", SEQ, SEQ.to_lowercase());
+        let fasta = format!(">chr1\n{}\n", seq);
+        let mut out = Vec::new();
+        let summary = run_pipeline_bedgraph(fasta.as_bytes(), &mut out, &params).unwrap();
+        assert_eq!(summary, run(&fasta, &params).0);
+        let text = String::from_utf8(out).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some("track type=bedGraph name=\"curvature\""));
+        let mut read = vec![None; seq.len()];
+        let mut count = 0;
+        for line in lines {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let (start, end): (usize, usize) =
+                (fields[1].parse().unwrap(), fields[2].parse().unwrap());
+            read[start..end].fill(Some(fields[3].to_string()));
+            count += 1;
+        }
+        let expected: Vec<Option<String>> = position_values(seq.as_bytes(), &params)
+            .into_iter()
+            .map(|v| (!v.is_nan()).then(|| params.float_format.format(v)))
+            .collect();
+        assert_eq!(read, expected);
+        // whole numbers merge neighbouring values into fewer lines than values
+        assert!(count < summary.values);
+    }
+
+    #[test]
+    fn test_run_pipeline_chunked() {
+        let seq = format!(
+            "{}NN{}{}",
+            SEQ.repeat(20),
+            SEQ.to_lowercase(),
+            SEQ.repeat(3)
+        );
+        let fasta = format!(">chr1\n{}\n>chr2\nACGT\n", seq);
+        let params = PipelineParams {
+            chunking: Some(ChunkPlan {
+                size: 64,
+                threads: 3,
+                source: ChunkSource::User,
+            }),
+            ..Default::default()
+        };
+        // rows are written as shortest round-trip numbers, so equal text means equal bits
+        assert_eq!(run(&fasta, &params), run(&fasta, &Default::default()));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let chunked = Some(ChunkPlan {
+            size: 4096,
+            threads: 2,
+            source: ChunkSource::User,
+        });
+        for chunking in [None, chunked] {
+            let params = PipelineParams {
+                chunking,
+                ..Default::default()
+            };
+            // cancelled from the middle of a long record, as from another thread
+            let bases = (0..1_000_000).map(|i| {
+                if i == 100_000 {
+                    params.cancel.cancel();
+                }
+                Ok(b"ACGTTTAAAGGC"[i % 12])
+            });
+            let mut summary = PipelineSummary::default();
+            let err = write_record(&mut Vec::new(), "chr1", bases, &params, &mut summary);
+            assert_eq!(err.unwrap_err().kind(), io::ErrorKind::Interrupted);
+            assert!(summary.values < 1_000_000);
+        }
+        // a cancelled run puts no output in place
+        let dir = std::env::temp_dir().join("symcurve_test_cancel");
+        std::fs::create_dir_all(&dir).unwrap();
+        let (input, output) = (dir.join("in.fa"), dir.join("out.tsv"));
+        std::fs::write(&input, format!(">chr1\n{}\n", SEQ)).unwrap();
+        let _ = std::fs::remove_file(&output);
+        let params = PipelineParams::default();
+        params.cancel.cancel();
+        let err = run_file(&input, &output, None, OutputFormat::Tsv, &params, false);
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::Interrupted);
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn test_scratch_reused() {
+        let params = PipelineParams {
+            float_format: FloatFormat::Fixed(3),
+            na: NaValue::Dot,
+            ..Default::default()
+        };
+        let records = [SEQ.to_string(), format!("ACGTN{}", SEQ), "AC".to_string()];
+        let mut scratch = Scratch::default();
+        let (mut shared, mut fresh) = (Vec::new(), Vec::new());
+        let (mut summary, mut expected) = (PipelineSummary::default(), PipelineSummary::default());
+        for (i, seq) in records.iter().enumerate() {
+            let chrom = format!("contig{}", i);
+            let bases = || seq.bytes().map(Ok);
+            write_record_with(
+                &mut shared,
+                &chrom,
+                bases(),
+                &params,
+                &mut summary,
+                &mut scratch,
+            )
+            .unwrap();
+            write_record(&mut fresh, &chrom, bases(), &params, &mut expected).unwrap();
+        }
+        assert!(scratch.capacity_bytes() > 0);
+        assert_eq!(summary, expected);
+        assert_eq!(shared, fresh);
+        // and the same text as writing each value on its own
+        let text = String::from_utf8(shared).unwrap();
+        let values = position_values(SEQ.as_bytes(), &params);
+        let mut expected = Vec::new();
+        write_track(
+            &mut expected,
+            "contig0",
+            0,
+            values.into_iter(),
+            params.na,
+            params.float_format,
+        )
+        .unwrap();
+        assert!(text.starts_with(&String::from_utf8(expected).unwrap()));
+    }
+
+    #[test]
+    fn test_run_pipeline_gaps() {
+        let params = PipelineParams {
+            na: NaValue::Omit,
+            ..Default::default()
+        };
+        // soft-masked bases count, and the pieces either side of the gap are computed separately
+        let fasta = format!(
+            ">chr1\n{}\nNNnr\n{}\n>chr2\nACGT\n>chr3\nNNNN\n",
+            SEQ.to_ascii_lowercase(),
+            SEQ
+        );
+        let (summary, rows) = run(&fasta, &params);
+        assert_eq!(summary.records, 3);
+        assert_eq!(summary.bases, 112);
+        assert_eq!(summary.gap_bases, 7);
+        assert_eq!(summary.ambiguous_bases, 1);
+        assert_eq!(summary.short_records, 1);
+        assert_eq!(summary.values, 16);
+        assert_eq!(rows.len(), 16);
+        assert!(rows[0].starts_with("chr1\t22\t"));
+        assert!(rows[8].starts_with("chr1\t76\t"));
+        assert_eq!(rows[0][8..], rows[8][8..]);
+    }
+
+    #[test]
+    fn test_run_pipeline_start_anchor() {
+        let params = PipelineParams {
+            anchor: Anchor::Start,
+            ..Default::default()
+        };
+        let (_, rows) = run(&format!(">chr1\n{}\n", SEQ), &params);
+        assert_eq!(rows.len(), 50);
+        assert!(!rows[0].ends_with("NaN"));
+        assert!(rows[8].ends_with("NaN"));
+    }
+
+    #[test]
+    fn test_run_file() {
+        let input = std::env::temp_dir().join("symcurve_test_run_file.fa");
+        let output = std::env::temp_dir().join("symcurve_test_run_file.tsv");
+        std::fs::write(&input, format!(">chr1\n{}\n", SEQ)).unwrap();
+        let _ = std::fs::remove_file(&output);
+        let params = PipelineParams::default();
+        let summary = run_file(&input, &output, None, OutputFormat::Tsv, &params, false).unwrap();
+        assert_eq!(summary.values, 8);
+        let written = std::fs::read_to_string(&output).unwrap();
+        let (_, rows) = run(&format!(">chr1\n{}\n", SEQ), &params);
+        assert_eq!(written.lines().collect::<Vec<_>>(), rows);
+        // an existing output is only replaced with force
+        assert!(run_file(&input, &output, None, OutputFormat::Tsv, &params, false).is_err());
+        assert!(run_file(&input, &output, None, OutputFormat::Tsv, &params, true).is_ok());
+        // spans are only written by the bigWig writer
+        let spanned = PipelineParams {
+            span: 10,
+            ..Default::default()
+        };
+        let err = run_file(&input, &output, None, OutputFormat::Tsv, &spanned, true);
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        // and track lines only by the bedGraph writer
+        let named = PipelineParams {
+            track_line: TrackLine {
+                name: Some("curvature".to_string()),
+                ..TrackLine::default()
+            },
+            ..Default::default()
+        };
+        for format in [OutputFormat::Tsv, OutputFormat::BigWig] {
+            let err = run_file(&input, &output, None, format, &named, true);
+            assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn test_run_file_regions() {
+        let dir = std::env::temp_dir().join("symcurve_test_run_file_regions");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.fa");
+        let chr2 = format!("{}NN{}", SEQ.repeat(4), SEQ.to_lowercase());
+        let fasta = format!(">chr1\n{}\n>chr2\n{}\n", SEQ, chr2);
+        std::fs::write(&input, &fasta).unwrap();
+        let _ = std::fs::remove_file(dir.join("in.fa.fai"));
+        let region = |s: &str| s.parse::<Region>().unwrap();
+        let params = PipelineParams {
+            // out of order and overlapping, and chr2 before chr1
+            regions: vec![
+                region("chr2:150-220"),
+                region("chr2:31-100"),
+                region("chr2:90-120"),
+                region("chr1:1-10"),
+            ],
+            ..Default::default()
+        };
+        let output = dir.join("out.tsv");
+        run_file(&input, &output, None, OutputFormat::Tsv, &params, true).unwrap();
+        let rows = std::fs::read_to_string(&output).unwrap();
+        let rows: Vec<&str> = rows.lines().collect();
+        // the rows of a whole-file run within the regions, with the same values up to rounding
+        let (_, all) = run(&fasta, &Default::default());
+        let expected: Vec<&String> = all
+            .iter()
+            .filter(|row| {
+                let fields: Vec<&str> = row.split('\t').collect();
+                let pos: u64 = fields[1].parse().unwrap();
+                match fields[0] {
+                    "chr1" => pos <= 10,
+                    _ => (31..=120).contains(&pos) || (150..=220).contains(&pos),
+                }
+            })
+            .collect();
+        assert_eq!(rows.len(), expected.len());
+        for (row, expected) in rows.iter().zip(expected) {
+            let (fields, expected): (Vec<&str>, Vec<&str>) =
+                (row.split('\t').collect(), expected.split('\t').collect());
+            assert_eq!(fields[..2], expected[..2]);
+            let (value, expected): (f64, f64) =
+                (fields[2].parse().unwrap(), expected[2].parse().unwrap());
+            assert_eq!(value.is_nan(), expected.is_nan());
+            assert!(value.is_nan() || (value - expected).abs() < 1e-9);
+        }
+        // bigWig outputs keep the full chromosome lengths
+        let output = dir.join("out.bw");
+        run_file(&input, &output, None, OutputFormat::BigWig, &params, true).unwrap();
+        let reader = BigWigReader::new(std::fs::File::open(&output).unwrap()).unwrap();
+        let lengths: Vec<u32> = reader.chroms().iter().map(|c| c.length).collect();
+        assert_eq!(lengths, vec![50, 252]);
+        // errors
+        let unknown = PipelineParams {
+            regions: vec![region("chr3:1-10")],
+            ..Default::default()
+        };
+        let err = run_file(&input, &output, None, OutputFormat::Tsv, &unknown, true);
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::NotFound);
+        let symmetry = dir.join("out.symcurve.tsv");
+        let err = run_file(
+            &input,
+            &output,
+            Some(&symmetry),
+            OutputFormat::Tsv,
+            &params,
+            true,
+        );
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let err = run_file(
+            Path::new("-"),
+            &output,
+            None,
+            OutputFormat::Tsv,
+            &params,
+            true,
+        );
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_run_file_symmetry() {
+        let dir = std::env::temp_dir().join("symcurve_test_run_file_symmetry");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.fa");
+        let seq = format!("{}NN{}{}", SEQ, SEQ.to_lowercase(), SEQ);
+        std::fs::write(&input, format!(">chr1\n{}\n>short\nACGT\n", seq)).unwrap();
+        let params = PipelineParams {
+            symmetry_window: 6,
+            symmetry_step: 2,
+            ..Default::default()
+        };
+        // each window starting on the stride is scored at its center
+        let mut expected = vec![f64::NAN; seq.len()];
+        let values = position_values(seq.as_bytes(), &params);
+        for (i, score) in values.into_iter().symmetry_iter(6, 2).enumerate() {
+            expected[i * 2 + 2] = score;
+        }
+        assert!(expected.iter().filter(|v| !v.is_nan()).count() > 4);
+        let (output, symmetry) = (dir.join("out.tsv"), dir.join("out.symcurve.tsv"));
+        run_file(
+            &input,
+            &output,
+            Some(&symmetry),
+            OutputFormat::Tsv,
+            &params,
+            true,
+        )
+        .unwrap();
+        let written = std::fs::read_to_string(&symmetry).unwrap();
+        let rows: Vec<&str> = written.lines().collect();
+        assert_eq!(rows.len(), seq.len() + 4);
+        for (row, score) in rows.iter().zip(&expected) {
+            assert_eq!(row.rsplit('\t').next().unwrap(), score.to_string());
+        }
+        let (output, symmetry) = (dir.join("out.bw"), dir.join("out.symcurve.bw"));
+        run_file(
+            &input,
+            &output,
+            Some(&symmetry),
+            OutputFormat::BigWig,
+            &params,
+            true,
+        )
+        .unwrap();
+        let mut reader = BigWigReader::open(&symmetry).unwrap();
+        let mut read = vec![f64::NAN; seq.len()];
+        for interval in reader.values("chr1", 0, seq.len() as u64).unwrap() {
+            for pos in interval.start..interval.end {
+                read[pos as usize] = interval.value as f64;
+            }
+        }
+        for (a, b) in read.iter().zip(&expected) {
+            assert!(a.is_nan() == b.is_nan() && (a.is_nan() || *a == *b as f32 as f64));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_file_provenance() {
+        let dir = std::env::temp_dir().join("symcurve_test_run_file_provenance");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.fa");
+        std::fs::write(&input, format!(">chr1\n{}\n", SEQ.repeat(3))).unwrap();
+        let provenance = Provenance {
+            version: "0.1.0".to_string(),
+            parameters: vec![("curve_step".to_string(), "15".to_string())],
+            matrices: "builtin".to_string(),
+        };
+        let params = PipelineParams {
+            symmetry_window: 6,
+            provenance: Some(provenance.clone()),
+            ..Default::default()
+        };
+        let named = PipelineParams {
+            track_line: TrackLine {
+                name: Some("curve".to_string()),
+                ..TrackLine::default()
+            },
+            ..params.clone()
+        };
+        for (format, extension, params) in [
+            (OutputFormat::Tsv, "tsv", &params),
+            (OutputFormat::BedGraph, "bedGraph", &named),
+            (OutputFormat::BigWig, "bw", &params),
+        ] {
+            let output = dir.join(format!("out.{}", extension));
+            let symmetry = dir.join(format!("out.symcurve.{}", extension));
+            run_file(&input, &output, Some(&symmetry), format, params, true).unwrap();
+            for path in [&output, &symmetry] {
+                let mut out = Vec::new();
+                crate::info::info(path, &mut out).unwrap();
+                let text = String::from_utf8(out).unwrap();
+                assert!(text.contains("symcurve version: 0.1.0\n"), "{}", text);
+                assert!(text.contains("(matches parameters)"), "{}", text);
+                assert!(text.contains("contigs: 1\n  chr1\t"), "{}", text);
+            }
+        }
+        // the track line stays first, and the values follow the comments
+        let bedgraph = std::fs::read_to_string(dir.join("out.bedGraph")).unwrap();
+        let mut lines = bedgraph.lines();
+        assert!(lines.next().unwrap().starts_with("track type=bedGraph"));
+        assert_eq!(lines.next(), Some("# symcurve version: 0.1.0"));
+        let tsv = std::fs::read_to_string(dir.join("out.tsv")).unwrap();
+        let (_, rows) = run(&format!(">chr1\n{}\n", SEQ.repeat(3)), &params);
+        assert_eq!(tsv.lines().collect::<Vec<_>>(), rows);
+        assert_eq!(rows[0], "# symcurve version: 0.1.0");
+        assert_eq!(tsv.lines().filter(|line| line.starts_with('#')).count(), 4);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_step_two() {
+        // a step two other than the Perl default widens the window the flank covers
+        let params = PipelineParams {
+            step_two: Some(8),
+            ..Default::default()
+        };
+        assert_eq!(params.flank(), PipelineParams::default().flank() + 3);
+        let (summary, rows) = run(&format!(">chr1\n{}\n", SEQ), &params);
+        assert_eq!(summary.values, 2);
+        assert!(rows[23].ends_with("NaN"));
+        assert!(!rows[24].ends_with("NaN"));
+        let values = position_values(SEQ.as_bytes(), &params);
+        assert_eq!(values.iter().filter(|v| !v.is_nan()).count(), 2);
+    }
+}
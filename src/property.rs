@@ -0,0 +1,102 @@
+//! # Property module
+//! A small harness for property-based tests: a property is checked on many randomly generated
+//! cases, each from its own seed, and a failure names the seed so the case can be replayed.
+//!
+//! The number of cases defaults to [`DEFAULT_CASES`] and can be raised for a longer search with
+//! the `SYMCURVE_PROPERTY_CASES` environment variable.
+
+pub(crate) use crate::design::Rng;
+
+/// The number of cases each property is checked on by default.
+pub(crate) const DEFAULT_CASES: u64 = 64;
+
+/// The number of cases to check, from `SYMCURVE_PROPERTY_CASES` if set.
+fn cases() -> u64 {
+    std::env::var("SYMCURVE_PROPERTY_CASES")
+        .ok()
+        .and_then(|cases| cases.parse().ok())
+        .unwrap_or(DEFAULT_CASES)
+}
+
+/// Checks a property on randomly generated cases.
+///
+/// # Arguments
+///
+/// * `name` - The name of the property, for the failure message.
+/// * `property` - Generates a case from the random generator and checks it, describing the case
+///   and what went wrong if it fails.
+///
+/// # Panics
+///
+/// Panics on the first failing case, with its seed and description.
+pub(crate) fn check<F>(name: &str, mut property: F)
+where
+    F: FnMut(&mut Rng) -> Result<(), String>,
+{
+    for seed in 0..cases() {
+        if let Err(message) = property(&mut Rng::new(seed)) {
+            panic!("property '{}' failed for seed {}: {}", name, seed, message);
+        }
+    }
+}
+
+/// A random sequence of `A`, `C`, `G` and `T`.
+pub(crate) fn bases(rng: &mut Rng, length: usize) -> Vec<u8> {
+    (0..length).map(|_| b"ACGT"[rng.below(4)]).collect()
+}
+
+/// A number in `lo..=hi`.
+pub(crate) fn between(rng: &mut Rng, lo: usize, hi: usize) -> usize {
+    lo + rng.below(hi - lo + 1)
+}
+
+/// Whether two values agree to within `epsilon`, relative to their size when that is above 1.
+/// `NaN` agrees only with `NaN`.
+pub(crate) fn close(a: f64, b: f64, epsilon: f64) -> bool {
+    if a.is_nan() || b.is_nan() {
+        return a.is_nan() && b.is_nan();
+    }
+    (a - b).abs() <= epsilon * a.abs().max(b.abs()).max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_is_reproducible() {
+        let mut first = Vec::new();
+        check("collect", |rng| {
+            first.push(rng.next_u64());
+            Ok(())
+        });
+        let mut second = Vec::new();
+        check("collect", |rng| {
+            second.push(rng.next_u64());
+            Ok(())
+        });
+        assert_eq!(first, second);
+        assert!(first.len() as u64 >= 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "property 'fails' failed for seed 0: always")]
+    fn test_check_names_the_seed() {
+        check("fails", |_| Err("always".to_string()));
+    }
+
+    #[test]
+    fn test_generators() {
+        let mut rng = Rng::new(7);
+        let seq = bases(&mut rng, 100);
+        assert_eq!(seq.len(), 100);
+        assert!(seq.iter().all(|b| b"ACGT".contains(b)));
+        for _ in 0..100 {
+            assert!((3..=5).contains(&between(&mut rng, 3, 5)));
+        }
+        assert!(close(1000.0, 1000.0 + 1e-7, 1e-9));
+        assert!(!close(1.0, 1.1, 1e-9));
+        assert!(close(f64::NAN, f64::NAN, 0.0));
+        assert!(!close(f64::NAN, 0.0, 1.0));
+    }
+}
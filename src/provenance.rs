@@ -0,0 +1,371 @@
+//! # Provenance module
+//! Metadata identifying exactly how an output was produced: the tool version, a hash of the
+//! parameters, and which matrices were used. It is embedded in every output format that has room
+//! for it (track-line comments, TSV headers, the run manifest), so a track found on disk long
+//! after the run can be traced back to it.
+//!
+//! How the binary itself was built (git commit, build date, cargo features and library versions)
+//! is recorded by the build script and reported by `symcurve -V --verbose` and the run manifest.
+
+use crate::checksum::{digest_file, Sha256};
+use crate::cli::Cli;
+use crate::json::Json;
+use std::io::{self, Write};
+
+/// Provenance of a run.
+///
+/// # Fields
+///
+/// * `version`: The symcurve version.
+/// * `parameters`: The parameters that affect computed values, as name/value pairs in a fixed
+///   order.
+/// * `matrices`: Identity of the matrix set, `builtin` or the matrices file with its SHA-256.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Provenance {
+    pub version: String,
+    pub parameters: Vec<(String, String)>,
+    pub matrices: String,
+}
+
+/// Provenance read back from an earlier output, with the parameter hash recorded alongside it.
+///
+/// # Fields
+///
+/// * `provenance`: The provenance as recorded.
+/// * `parameter_hash`: The recorded parameter hash, if there was one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedProvenance {
+    pub provenance: Provenance,
+    pub parameter_hash: Option<String>,
+}
+
+impl RecordedProvenance {
+    /// Whether the recorded hash agrees with the recorded parameters and matrices. A mismatch
+    /// means the metadata was edited after it was written, or comes from an incompatible version.
+    pub fn hash_matches(&self) -> bool {
+        self.parameter_hash.as_deref() == Some(self.provenance.parameter_hash().as_str())
+    }
+}
+
+impl Provenance {
+    /// Gathers provenance from the command line, checksumming the matrices file if one is given.
+    pub fn from_cli(cli: &Cli) -> io::Result<Self> {
+        let matrices = match &cli.matrices {
+            Some(path) => format!("{} sha256:{}", path.display(), digest_file(path)?.sha256),
+            None => "builtin".to_string(),
+        };
+        Ok(Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            parameters: cli.parameters(),
+            matrices,
+        })
+    }
+
+    /// A short hash of the parameters and matrix identity: the first 16 hex digits of the
+    /// SHA-256 of the `name=value` lines.
+    pub fn parameter_hash(&self) -> String {
+        let mut sha256 = Sha256::new();
+        for (name, value) in &self.parameters {
+            sha256.update(format!("{}={}\n", name, value).as_bytes());
+        }
+        sha256.update(format!("matrices={}\n", self.matrices).as_bytes());
+        sha256.hex_digest()[..16].to_string()
+    }
+
+    /// Writes the provenance as comment lines, each starting with `prefix`.
+    pub fn write_comments<W: Write>(&self, writer: &mut W, prefix: &str) -> io::Result<()> {
+        writeln!(writer, "{} symcurve version: {}", prefix, self.version)?;
+        writeln!(
+            writer,
+            "{} parameter hash: {}",
+            prefix,
+            self.parameter_hash()
+        )?;
+        writeln!(writer, "{} matrices: {}", prefix, self.matrices)?;
+        let parameters: Vec<_> = self
+            .parameters
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        writeln!(writer, "{} parameters: {}", prefix, parameters.join(" "))
+    }
+
+    /// Reads provenance back from comment lines written by `write_comments`.
+    ///
+    /// Lines that don't start with `prefix` or aren't provenance are ignored, so the whole header
+    /// of a file can be passed in. Returns `None` if no version line is found.
+    pub fn from_comments<'a, I>(lines: I, prefix: &str) -> Option<RecordedProvenance>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut version = None;
+        let mut parameter_hash = None;
+        let mut matrices = String::new();
+        let mut parameters = Vec::new();
+        for line in lines {
+            let Some(comment) = line.strip_prefix(prefix) else {
+                continue;
+            };
+            let Some((key, value)) = comment.trim().split_once(": ") else {
+                continue;
+            };
+            match key {
+                "symcurve version" => version = Some(value.to_string()),
+                "parameter hash" => parameter_hash = Some(value.to_string()),
+                "matrices" => matrices = value.to_string(),
+                "parameters" => {
+                    parameters = value
+                        .split_whitespace()
+                        .filter_map(|pair| pair.split_once('='))
+                        .map(|(name, value)| (name.to_string(), value.to_string()))
+                        .collect()
+                }
+                _ => {}
+            }
+        }
+        Some(RecordedProvenance {
+            provenance: Provenance {
+                version: version?,
+                parameters,
+                matrices,
+            },
+            parameter_hash,
+        })
+    }
+
+    /// Reads provenance back from the JSON written by `to_json`. Returns `None` if the JSON
+    /// doesn't have the expected shape.
+    pub fn from_json(json: &Json) -> Option<RecordedProvenance> {
+        let parameters = json
+            .get("parameters")?
+            .entries()?
+            .iter()
+            .map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+            .collect::<Option<Vec<_>>>()?;
+        Some(RecordedProvenance {
+            provenance: Provenance {
+                version: json.get("symcurve_version")?.as_str()?.to_string(),
+                parameters,
+                matrices: json.get("matrices")?.as_str()?.to_string(),
+            },
+            parameter_hash: json
+                .get("parameter_hash")
+                .and_then(Json::as_str)
+                .map(str::to_string),
+        })
+    }
+
+    /// Converts the provenance to JSON.
+    pub fn to_json(&self) -> Json {
+        let parameters = self
+            .parameters
+            .iter()
+            .fold(Json::object(), |json, (name, value)| {
+                json.with(name.as_str(), value.as_str())
+            });
+        Json::object()
+            .with("symcurve_version", self.version.as_str())
+            .with("parameter_hash", self.parameter_hash())
+            .with("matrices", self.matrices.as_str())
+            .with("parameters", parameters)
+    }
+}
+
+/// How the running binary was built, as recorded by the build script.
+///
+/// # Fields
+///
+/// * `version`: The symcurve version.
+/// * `commit`: The git commit built from, with `-dirty` if the tree had changes, or `unknown`.
+/// * `date`: The UTC build date as `YYYY-MM-DD`.
+/// * `features`: The enabled cargo features.
+/// * `libraries`: The versions of the linked libraries, as name/version pairs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BuildInfo {
+    pub version: String,
+    pub commit: String,
+    pub date: String,
+    pub features: Vec<String>,
+    pub libraries: Vec<(String, String)>,
+}
+
+impl BuildInfo {
+    /// The build of the running binary.
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            commit: env!("SYMCURVE_GIT_COMMIT").to_string(),
+            date: env!("SYMCURVE_BUILD_DATE").to_string(),
+            features: env!("SYMCURVE_FEATURES")
+                .split(',')
+                .filter(|feature| !feature.is_empty())
+                .map(str::to_string)
+                .collect(),
+            libraries: env!("SYMCURVE_LIBRARIES")
+                .split(',')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(name, version)| (name.to_string(), version.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Writes the version line, followed by the build details if `verbose`.
+    pub fn write<W: Write>(&self, writer: &mut W, verbose: bool) -> io::Result<()> {
+        writeln!(writer, "symcurve {}", self.version)?;
+        if !verbose {
+            return Ok(());
+        }
+        writeln!(writer, "commit: {}", self.commit)?;
+        writeln!(writer, "build date: {}", self.date)?;
+        let features = if self.features.is_empty() {
+            "none".to_string()
+        } else {
+            self.features.join(", ")
+        };
+        writeln!(writer, "features: {}", features)?;
+        for (name, version) in &self.libraries {
+            writeln!(writer, "{}: {}", name, version)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the build back from the JSON written by `to_json`. Returns `None` if the JSON
+    /// doesn't have the expected shape.
+    pub fn from_json(json: &Json) -> Option<Self> {
+        Some(Self {
+            version: json.get("symcurve_version")?.as_str()?.to_string(),
+            commit: json.get("commit")?.as_str()?.to_string(),
+            date: json.get("date")?.as_str()?.to_string(),
+            features: json
+                .get("features")?
+                .as_array()?
+                .iter()
+                .map(|feature| Some(feature.as_str()?.to_string()))
+                .collect::<Option<Vec<_>>>()?,
+            libraries: json
+                .get("libraries")?
+                .entries()?
+                .iter()
+                .map(|(name, version)| Some((name.clone(), version.as_str()?.to_string())))
+                .collect::<Option<Vec<_>>>()?,
+        })
+    }
+
+    /// Converts the build to JSON.
+    pub fn to_json(&self) -> Json {
+        let features = self
+            .features
+            .iter()
+            .map(|feature| Json::from(feature.as_str()))
+            .collect::<Vec<_>>();
+        let libraries = self
+            .libraries
+            .iter()
+            .fold(Json::object(), |json, (name, version)| {
+                json.with(name.as_str(), version.as_str())
+            });
+        Json::object()
+            .with("symcurve_version", self.version.as_str())
+            .with("commit", self.commit.as_str())
+            .with("date", self.date.as_str())
+            .with("features", features)
+            .with("libraries", libraries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_provenance_from_cli() {
+        let cli = Cli::parse_from(["symcurve", "in.fa", "out.bw"]);
+        let provenance = Provenance::from_cli(&cli).unwrap();
+        assert_eq!(provenance.matrices, "builtin");
+        assert_eq!(provenance.parameter_hash().len(), 16);
+        let other = Provenance::from_cli(&Cli::parse_from([
+            "symcurve",
+            "other.fa",
+            "other.bw",
+            "--curve-step",
+            "10",
+        ]))
+        .unwrap();
+        assert_ne!(provenance.parameter_hash(), other.parameter_hash());
+        // file paths are not part of the hash, only what changes the values
+        let same = Provenance::from_cli(&Cli::parse_from(["symcurve", "a.fa", "b.bw"])).unwrap();
+        assert_eq!(provenance.parameter_hash(), same.parameter_hash());
+    }
+
+    #[test]
+    fn test_write_comments() {
+        let provenance = Provenance {
+            version: "0.1.0".to_string(),
+            parameters: vec![("curve_step".to_string(), "15".to_string())],
+            matrices: "builtin".to_string(),
+        };
+        let mut buf = Vec::new();
+        provenance.write_comments(&mut buf, "#").unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], "# symcurve version: 0.1.0");
+        assert_eq!(
+            lines[1],
+            format!("# parameter hash: {}", provenance.parameter_hash())
+        );
+        assert_eq!(lines[2], "# matrices: builtin");
+        assert_eq!(lines[3], "# parameters: curve_step=15");
+        let json = provenance.to_json();
+        assert_eq!(
+            json.get("parameters").unwrap().get("curve_step"),
+            Some(&Json::from("15"))
+        );
+    }
+
+    #[test]
+    fn test_read_back() {
+        let cli = Cli::parse_from(["symcurve", "in.fa", "out.bw"]);
+        let provenance = Provenance::from_cli(&cli).unwrap();
+        let mut buf = b"track type=bedGraph\n".to_vec();
+        provenance.write_comments(&mut buf, "#").unwrap();
+        buf.extend(b"chr1\t0\t1\t0.5\n");
+        let text = String::from_utf8(buf).unwrap();
+        let recorded = Provenance::from_comments(text.lines(), "#").unwrap();
+        assert_eq!(recorded.provenance, provenance);
+        assert!(recorded.hash_matches());
+        let recorded = Provenance::from_json(&provenance.to_json()).unwrap();
+        assert_eq!(recorded.provenance, provenance);
+        assert!(recorded.hash_matches());
+        // edited after the fact
+        let edited = text.replace("curve_step=15", "curve_step=10");
+        assert!(!Provenance::from_comments(edited.lines(), "#")
+            .unwrap()
+            .hash_matches());
+        assert_eq!(Provenance::from_comments(["chr1\t0\t1\t0.5"], "#"), None);
+    }
+
+    #[test]
+    fn test_build_info() {
+        let build = BuildInfo::current();
+        assert_eq!(build.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(build.date.len(), 10);
+        assert!(build
+            .libraries
+            .iter()
+            .any(|(name, _)| name == "noodles-fasta"));
+        let mut buf = Vec::new();
+        build.write(&mut buf, false).unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            format!("symcurve {}\n", build.version)
+        );
+        let mut buf = Vec::new();
+        build.write(&mut buf, true).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert!(text.contains(&format!("commit: {}\n", build.commit)));
+        assert!(text.contains("noodles-fasta: "));
+        let json = build.to_json().to_string().parse().unwrap();
+        assert_eq!(BuildInfo::from_json(&json), Some(build));
+    }
+}
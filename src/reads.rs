@@ -0,0 +1,453 @@
+//! # Reads module
+//! The `symcurve reads` subcommand, which screens single-molecule datasets: one row per read of a
+//! FASTQ or unaligned BAM file with its length and curvature statistics, instead of a track.
+//!
+//! For each read, the curvature is computed piece by piece between ambiguous bases as in the main
+//! pipeline, and summarized as the number of values, their mean and maximum, and the number of
+//! symmetric windows: the read's values are cut into consecutive tiles of `--symcurve-win`
+//! values, and a tile counts when its SymCurv score around its middle is at least
+//! `--symmetry-threshold`. Reads are processed in batches across threads and written in input
+//! order. Gzipped FASTQ and the BGZF compression of BAM are read directly.
+
+pub mod bam;
+pub mod fastq;
+
+use crate::curve::iters::{CurveBuffers, CurveIter};
+use crate::curve::symmetry::mirror_score;
+use crate::pipeline::PipelineParams;
+use crate::resources::Resources;
+use crate::stats::streaming::RunningStats;
+use bam::{BamReader, BAM_MAGIC};
+use fastq::FastqReader;
+use flate2::read::MultiGzDecoder;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::mem;
+use std::path::Path;
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::thread;
+
+/// The number of bases read into a batch before it is handed to a thread.
+pub const BATCH_BASES: usize = 4 * 1024 * 1024;
+
+/// One read.
+///
+/// # Fields
+///
+/// * `name`: The read name.
+/// * `seq`: The bases, uppercase.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReadRecord {
+    pub name: String,
+    pub seq: Vec<u8>,
+}
+
+/// The formats reads are read from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadFormat {
+    Fastq,
+    Bam,
+}
+
+impl ReadFormat {
+    /// Detects the format from the first decompressed bytes.
+    pub fn detect(head: &[u8]) -> Option<Self> {
+        if head.starts_with(BAM_MAGIC) {
+            Some(ReadFormat::Bam)
+        } else if head.starts_with(b"@") {
+            Some(ReadFormat::Fastq)
+        } else {
+            None
+        }
+    }
+}
+
+/// An iterator over reads.
+pub type Reads = Box<dyn Iterator<Item = io::Result<ReadRecord>> + Send>;
+
+/// Opens reads from a source, decompressing it first if it is gzip or BGZF.
+///
+/// # Errors
+///
+/// Returns an `InvalidData` error if the content is neither FASTQ nor BAM. An empty source gives
+/// no reads.
+pub fn open_reads<R: Read + Send + 'static>(source: R) -> io::Result<Reads> {
+    let mut raw = BufReader::with_capacity(1 << 20, source);
+    let reader: Box<dyn Read + Send> = if raw.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Box::new(MultiGzDecoder::new(raw))
+    } else {
+        Box::new(raw)
+    };
+    let mut reader = BufReader::with_capacity(1 << 20, reader);
+    let head = reader.fill_buf()?;
+    if head.is_empty() {
+        return Ok(Box::new(std::iter::empty()));
+    }
+    match ReadFormat::detect(head) {
+        Some(ReadFormat::Fastq) => Ok(Box::new(FastqReader::new(reader))),
+        Some(ReadFormat::Bam) => Ok(Box::new(BamReader::new(reader))),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "expected FASTQ or BAM reads",
+        )),
+    }
+}
+
+/// The curvature summary of one read.
+///
+/// # Fields
+///
+/// * `name`: The read name.
+/// * `length`: The number of bases.
+/// * `values`: The statistics of the curvature values.
+/// * `symmetric_windows`: The number of tiles of values at or above the symmetry threshold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReadSummary {
+    pub name: String,
+    pub length: u64,
+    pub values: RunningStats,
+    pub symmetric_windows: u64,
+}
+
+/// The settings of a summary run.
+///
+/// # Fields
+///
+/// * `curve`: The curvature parameters.
+/// * `window`: The number of values in each symmetry tile.
+/// * `threshold`: The SymCurv score, as [`mirror_score`] gives it, a tile needs to count as
+///   symmetric.
+#[derive(Clone, Debug)]
+pub struct SummaryParams {
+    pub curve: PipelineParams,
+    pub window: usize,
+    pub threshold: f64,
+}
+
+/// Counts the tiles of values that score as symmetric around their middle.
+///
+/// # Arguments
+///
+/// * `values` - The values of one piece of a read.
+/// * `window` - The number of values in each tile; a shorter tail is left out.
+/// * `threshold` - The mirror score a tile needs.
+pub fn count_symmetric(values: &[f64], window: usize, threshold: f64) -> u64 {
+    if window < 3 {
+        return 0;
+    }
+    values
+        .chunks_exact(window)
+        .filter(|tile| {
+            mirror_score(tile, window - 1, (window - 1) / 2).is_some_and(|s| s >= threshold)
+        })
+        .count() as u64
+}
+
+/// The per-thread state of a run.
+///
+/// # Fields
+///
+/// * `buffers`: The window buffers, reused for every piece of every read.
+/// * `values`: The values of the current piece, reused.
+#[derive(Default)]
+struct Worker {
+    buffers: CurveBuffers,
+    values: Vec<f64>,
+}
+
+impl Worker {
+    /// Summarizes one read.
+    fn summarize(&mut self, read: ReadRecord, params: &SummaryParams) -> ReadSummary {
+        let curve = &params.curve;
+        let mut stats = RunningStats::new();
+        let mut symmetric_windows = 0;
+        for piece in read
+            .seq
+            .split(|base| !matches!(base, b'A' | b'C' | b'G' | b'T'))
+        {
+            let mut iter = CurveIter::with_buffers(
+                piece.iter().copied(),
+                curve.roll_type.clone(),
+                curve.step_b,
+                curve.step_c,
+                curve.curve_scale,
+                curve.edge_mode,
+                curve.convention,
+                mem::take(&mut self.buffers),
+            )
+            .with_step_two(curve.step_two);
+            self.values.clear();
+            self.values.extend(iter.by_ref());
+            self.buffers = iter.into_buffers();
+            self.values.iter().for_each(|&v| stats.add(v));
+            symmetric_windows += count_symmetric(&self.values, params.window, params.threshold);
+        }
+        ReadSummary {
+            name: read.name,
+            length: read.seq.len() as u64,
+            values: stats,
+            symmetric_windows,
+        }
+    }
+}
+
+/// Writes the header of the summary table.
+pub fn write_header<W: Write>(writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "read\tlength\tvalues\tmean\tmax\tsymmetric_windows")
+}
+
+/// Writes one row of the summary table. Reads too short for a value have `NA` statistics.
+pub fn write_summary<W: Write>(
+    writer: &mut W,
+    summary: &ReadSummary,
+    params: &PipelineParams,
+) -> io::Result<()> {
+    let stats = &summary.values;
+    let (mean, max) = if stats.count() == 0 {
+        ("NA".to_string(), "NA".to_string())
+    } else {
+        (
+            params.float_format.format(stats.mean()),
+            params.float_format.format(stats.max()),
+        )
+    };
+    writeln!(
+        writer,
+        "{}\t{}\t{}\t{}\t{}\t{}",
+        summary.name,
+        summary.length,
+        stats.count(),
+        mean,
+        max,
+        summary.symmetric_windows
+    )
+}
+
+/// Summarizes every read and writes the table in input order.
+///
+/// # Arguments
+///
+/// * `reads` - The reads.
+/// * `params` - The summary settings.
+/// * `threads` - The number of threads computing curvature, besides the one reading.
+/// * `writer` - Where the table is written.
+///
+/// # Returns
+///
+/// The number of reads.
+///
+/// # Errors
+///
+/// Returns the first error reading or writing.
+pub fn summarize<W: Write>(
+    reads: Reads,
+    params: &SummaryParams,
+    threads: usize,
+    writer: &mut W,
+) -> io::Result<u64> {
+    let threads = threads.max(1);
+    let (batch_sender, batch_receiver) = mpsc::sync_channel::<(u64, Vec<ReadRecord>)>(2 * threads);
+    let (result_sender, result_receiver) = mpsc::channel::<(u64, Vec<ReadSummary>)>();
+    let batch_receiver = Mutex::new(batch_receiver);
+    write_header(writer)?;
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let result_sender = result_sender.clone();
+            let batch_receiver = &batch_receiver;
+            scope.spawn(move || {
+                let mut worker = Worker::default();
+                loop {
+                    let next = batch_receiver.lock().unwrap().recv();
+                    let Ok((index, batch)) = next else {
+                        break;
+                    };
+                    let summaries = batch
+                        .into_iter()
+                        .map(|read| worker.summarize(read, params))
+                        .collect();
+                    if result_sender.send((index, summaries)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(result_sender);
+        let reader = scope.spawn(move || -> io::Result<()> {
+            let mut batch = Vec::new();
+            let mut bases = 0;
+            let mut index = 0;
+            for read in reads {
+                let read = read?;
+                bases += read.seq.len();
+                batch.push(read);
+                if bases >= BATCH_BASES {
+                    if batch_sender.send((index, mem::take(&mut batch))).is_err() {
+                        return Ok(());
+                    }
+                    index += 1;
+                    bases = 0;
+                }
+            }
+            if !batch.is_empty() {
+                let _ = batch_sender.send((index, batch));
+            }
+            Ok(())
+        });
+        // batches finish out of order; hold each until the ones before it are written
+        let mut pending: BTreeMap<u64, Vec<ReadSummary>> = BTreeMap::new();
+        let mut next = 0;
+        let mut count = 0;
+        let mut written: io::Result<()> = Ok(());
+        for (index, summaries) in result_receiver {
+            pending.insert(index, summaries);
+            while let Some(summaries) = pending.remove(&next) {
+                next += 1;
+                for summary in &summaries {
+                    count += 1;
+                    if written.is_ok() {
+                        written = write_summary(writer, summary, &params.curve);
+                    }
+                }
+            }
+        }
+        reader.join().unwrap()?;
+        written?;
+        Ok(count)
+    })
+}
+
+/// Runs `symcurve reads`.
+///
+/// # Arguments
+///
+/// * `path` - The FASTQ or BAM file.
+/// * `params` - The summary settings.
+/// * `threads` - The number of threads computing curvature; all the cores the process may use if
+///   not given.
+/// * `writer` - Where the table is written.
+pub fn reads<W: Write>(
+    path: &Path,
+    params: &SummaryParams,
+    threads: Option<usize>,
+    writer: &mut W,
+) -> io::Result<()> {
+    let reads = open_reads(File::open(path)?)?;
+    let threads = threads.unwrap_or_else(|| Resources::detect().threads);
+    summarize(reads, params, threads, writer)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    const SEQ: &str = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+
+    fn params() -> SummaryParams {
+        SummaryParams {
+            curve: PipelineParams::default(),
+            window: 5,
+            threshold: 0.9,
+        }
+    }
+
+    #[test]
+    fn test_count_symmetric() {
+        let values = [1.0, 2.0, 3.0, 2.0, 1.0, 1.0, 5.0, 3.0, 2.0, 9.0, 4.0];
+        // the second tile is lopsided and the tail is too short
+        assert_eq!(count_symmetric(&values, 5, 0.9), 1);
+        assert_eq!(count_symmetric(&values, 5, 0.0), 2);
+        assert_eq!(count_symmetric(&values, 2, 0.0), 0);
+    }
+
+    #[test]
+    fn test_open_reads() {
+        let fastq = format!("@r1\n{}\n+\n{}\n", SEQ, "!".repeat(SEQ.len()));
+        let mut gz = GzEncoder::new(Vec::new(), Compression::fast());
+        gz.write_all(fastq.as_bytes()).unwrap();
+        let gz = gz.finish().unwrap();
+        let bam = bam::tests::bam(&[("r1", 4, SEQ.as_bytes())]);
+        for source in [fastq.into_bytes(), gz, bam] {
+            let reads: Vec<ReadRecord> = open_reads(io::Cursor::new(source))
+                .unwrap()
+                .collect::<io::Result<_>>()
+                .unwrap();
+            assert_eq!(reads.len(), 1);
+            assert_eq!(reads[0].seq, SEQ.as_bytes());
+        }
+        assert!(open_reads(io::Cursor::new(b">r1\nACGT\n".to_vec())).is_err());
+        assert_eq!(open_reads(io::empty()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_summarize() {
+        let mut fastq = String::new();
+        let lengths = [SEQ.len(), 10, 2 * SEQ.len() + 1];
+        for (i, &len) in lengths.iter().enumerate() {
+            let seq: String = SEQ.chars().cycle().take(len).collect();
+            fastq.push_str(&format!("@r{}\n{}\n+\n{}\n", i, seq, "!".repeat(len)));
+        }
+        let reads = open_reads(io::Cursor::new(fastq.into_bytes())).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(summarize(reads, &params(), 3, &mut out).unwrap(), 3);
+        let table = String::from_utf8(out).unwrap();
+        let rows: Vec<Vec<&str>> = table.lines().map(|l| l.split('\t').collect()).collect();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0][0], "read");
+        assert_eq!(rows[1][..3], ["r0", "50", "8"]);
+        // a read too short for any value
+        assert_eq!(rows[2][1..], ["10", "0", "NA", "NA", "0"]);
+        assert_eq!(rows[3][0], "r2");
+        let expected: Vec<f64> = CurveIter::new(
+            SEQ.bytes(),
+            PipelineParams::default().roll_type,
+            5,
+            15,
+            0.33335,
+            PipelineParams::default().edge_mode,
+            PipelineParams::default().convention,
+        )
+        .collect();
+        let mean = expected.iter().sum::<f64>() / expected.len() as f64;
+        assert_relative_eq!(rows[1][3].parse::<f64>().unwrap(), mean, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_summarize_params() {
+        // the values are those of the parameters given, step two included
+        let curve = PipelineParams {
+            step_b: 7,
+            step_two: Some(4),
+            ..Default::default()
+        };
+        let fastq = format!("@r0\n{}\n+\n{}\n", SEQ, "!".repeat(SEQ.len()));
+        let reads = open_reads(io::Cursor::new(fastq.into_bytes())).unwrap();
+        let params = SummaryParams {
+            curve: curve.clone(),
+            ..params()
+        };
+        let mut out = Vec::new();
+        summarize(reads, &params, 1, &mut out).unwrap();
+        let table = String::from_utf8(out).unwrap();
+        let row: Vec<&str> = table.lines().nth(1).unwrap().split('\t').collect();
+        let expected: Vec<f64> = CurveIter::new(
+            SEQ.bytes(),
+            curve.roll_type.clone(),
+            curve.step_b,
+            curve.step_c,
+            curve.curve_scale,
+            curve.edge_mode,
+            curve.convention,
+        )
+        .with_step_two(curve.step_two)
+        .collect();
+        let mean = expected.iter().sum::<f64>() / expected.len() as f64;
+        assert_eq!(row[2], expected.len().to_string());
+        assert_relative_eq!(row[3].parse::<f64>().unwrap(), mean, epsilon = 1e-12);
+    }
+}
@@ -0,0 +1,251 @@
+//! Unaligned BAM reads.
+//!
+//! Basecallers increasingly write unaligned BAM instead of FASTQ, to keep modified-base tags with
+//! the reads. Only the read names and sequences are needed here, so the records are decoded
+//! directly from the BAM layout (SAMv1 section 4.2) rather than through a full BAM library. The
+//! BGZF compression around them is ordinary multi-member gzip and is removed before this reader
+//! sees the bytes. Secondary and supplementary records are skipped, so a BAM that was aligned
+//! after all still gives each read once.
+
+use crate::reads::ReadRecord;
+use std::io::{self, Read};
+
+/// The magic bytes at the start of decompressed BAM.
+pub const BAM_MAGIC: &[u8; 4] = b"BAM\x01";
+
+/// The bases of the 4-bit sequence encoding.
+const SEQ_CODES: &[u8; 16] = b"=ACMGRSVTWYHKDBN";
+
+/// The flag of a secondary alignment.
+const FLAG_SECONDARY: u16 = 0x100;
+
+/// The flag of a supplementary alignment.
+const FLAG_SUPPLEMENTARY: u16 = 0x800;
+
+/// The length of the fixed part of a record, after its block size.
+const FIXED_LEN: usize = 32;
+
+/// Iterator over the reads of decompressed BAM.
+///
+/// # Fields
+///
+/// * `inner`: The decompressed BAM bytes.
+/// * `header_read`: Whether the header has been read past.
+/// * `block`: A reused record buffer.
+pub struct BamReader<R: Read> {
+    inner: R,
+    header_read: bool,
+    block: Vec<u8>,
+}
+
+/// An `InvalidData` error about the BAM input.
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("BAM: {}", message))
+}
+
+impl<R: Read> BamReader<R> {
+    /// Constructor for `BamReader`.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            header_read: false,
+            block: Vec::new(),
+        }
+    }
+
+    /// Reads a little-endian `u32`, or `None` if the input ends before its first byte.
+    fn read_u32(&mut self) -> io::Result<Option<u32>> {
+        let mut bytes = [0u8; 4];
+        let mut filled = 0;
+        while filled < 4 {
+            match self.inner.read(&mut bytes[filled..])? {
+                0 if filled == 0 => return Ok(None),
+                0 => return Err(invalid("the input ends inside a length")),
+                n => filled += n,
+            }
+        }
+        Ok(Some(u32::from_le_bytes(bytes)))
+    }
+
+    /// Reads a length that must be there.
+    fn read_len(&mut self) -> io::Result<usize> {
+        self.read_u32()?
+            .map(|n| n as usize)
+            .ok_or_else(|| invalid("the header is cut short"))
+    }
+
+    /// Skips a number of bytes.
+    fn skip(&mut self, len: usize) -> io::Result<()> {
+        let skipped = io::copy(&mut (&mut self.inner).take(len as u64), &mut io::sink())?;
+        if skipped as usize != len {
+            return Err(invalid("the header is cut short"));
+        }
+        Ok(())
+    }
+
+    /// Reads past the magic, the SAM header text and the reference list.
+    fn read_header(&mut self) -> io::Result<()> {
+        let mut magic = [0u8; 4];
+        self.inner
+            .read_exact(&mut magic)
+            .map_err(|_| invalid("the input is too short"))?;
+        if &magic != BAM_MAGIC {
+            return Err(invalid("bad magic bytes"));
+        }
+        let text_len = self.read_len()?;
+        self.skip(text_len)?;
+        let references = self.read_len()?;
+        for _ in 0..references {
+            let name_len = self.read_len()?;
+            self.skip(name_len + 4)?;
+        }
+        self.header_read = true;
+        Ok(())
+    }
+
+    /// Reads the next primary read, or `None` at the end of the input.
+    fn read_record(&mut self) -> io::Result<Option<ReadRecord>> {
+        if !self.header_read {
+            self.read_header()?;
+        }
+        loop {
+            let Some(block_size) = self.read_u32()? else {
+                return Ok(None);
+            };
+            let block_size = block_size as usize;
+            if block_size < FIXED_LEN {
+                return Err(invalid("a record is shorter than its fixed fields"));
+            }
+            self.block.resize(block_size, 0);
+            self.inner
+                .read_exact(&mut self.block)
+                .map_err(|_| invalid("the input ends inside a record"))?;
+            if let Some(read) = parse_record(&self.block)? {
+                return Ok(Some(read));
+            }
+        }
+    }
+}
+
+/// Decodes the name and sequence of one record, the bytes after its block size.
+///
+/// # Returns
+///
+/// The read, or `None` for a secondary or supplementary record.
+///
+/// # Errors
+///
+/// Returns an `InvalidData` error if the variable-length fields overrun the record.
+fn parse_record(block: &[u8]) -> io::Result<Option<ReadRecord>> {
+    let u16_at = |i: usize| u16::from_le_bytes([block[i], block[i + 1]]);
+    let name_len = block[8] as usize;
+    let cigar_ops = u16_at(12) as usize;
+    let flag = u16_at(14);
+    let seq_len = u32::from_le_bytes([block[16], block[17], block[18], block[19]]) as usize;
+    if flag & (FLAG_SECONDARY | FLAG_SUPPLEMENTARY) != 0 {
+        return Ok(None);
+    }
+    let seq_start = FIXED_LEN + name_len + 4 * cigar_ops;
+    let seq_end = seq_start + seq_len.div_ceil(2);
+    if seq_end + seq_len > block.len() || name_len == 0 {
+        return Err(invalid("a record's fields overrun it"));
+    }
+    let name = &block[FIXED_LEN..FIXED_LEN + name_len - 1];
+    let seq = (0..seq_len)
+        .map(|i| {
+            let byte = block[seq_start + i / 2];
+            let code = if i % 2 == 0 { byte >> 4 } else { byte & 0x0F };
+            SEQ_CODES[code as usize]
+        })
+        .collect();
+    Ok(Some(ReadRecord {
+        name: String::from_utf8_lossy(name).into_owned(),
+        seq,
+    }))
+}
+
+impl<R: Read> Iterator for BamReader<R> {
+    type Item = io::Result<ReadRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Encodes an unmapped record with the given name, flag and bases.
+    fn record(name: &str, flag: u16, seq: &[u8]) -> Vec<u8> {
+        let mut block = Vec::new();
+        block.extend_from_slice(&(-1i32).to_le_bytes()); // refID
+        block.extend_from_slice(&(-1i32).to_le_bytes()); // pos
+        block.push(name.len() as u8 + 1);
+        block.push(255); // mapq
+        block.extend_from_slice(&4680u16.to_le_bytes()); // bin
+        block.extend_from_slice(&0u16.to_le_bytes()); // n_cigar_op
+        block.extend_from_slice(&flag.to_le_bytes());
+        block.extend_from_slice(&(seq.len() as u32).to_le_bytes());
+        block.extend_from_slice(&(-1i32).to_le_bytes()); // next refID
+        block.extend_from_slice(&(-1i32).to_le_bytes()); // next pos
+        block.extend_from_slice(&0i32.to_le_bytes()); // tlen
+        block.extend_from_slice(name.as_bytes());
+        block.push(0);
+        let code = |b: u8| SEQ_CODES.iter().position(|&c| c == b).unwrap() as u8;
+        for pair in seq.chunks(2) {
+            let low = pair.get(1).map_or(0, |&b| code(b));
+            block.push(code(pair[0]) << 4 | low);
+        }
+        block.extend(std::iter::repeat_n(30u8, seq.len()));
+        block.extend_from_slice(b"RGZrun1\0");
+        let mut out = (block.len() as u32).to_le_bytes().to_vec();
+        out.extend(block);
+        out
+    }
+
+    /// Encodes decompressed BAM with one reference and the given reads as `(name, flag, bases)`.
+    pub(crate) fn bam(reads: &[(&str, u16, &[u8])]) -> Vec<u8> {
+        let text = "@HD\tVN:1.6\tSO:unknown\n";
+        let mut out = BAM_MAGIC.to_vec();
+        out.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        out.extend_from_slice(text.as_bytes());
+        out.extend_from_slice(&1u32.to_le_bytes());
+        out.extend_from_slice(&5u32.to_le_bytes());
+        out.extend_from_slice(b"chr1\0");
+        out.extend_from_slice(&1000u32.to_le_bytes());
+        for (name, flag, seq) in reads {
+            out.extend(record(name, *flag, seq));
+        }
+        out
+    }
+
+    #[test]
+    fn test_bam_reader() {
+        let bytes = bam(&[
+            ("read1", 4, b"ACGTN"),
+            ("read1", 4 | FLAG_SECONDARY, b"ACG"),
+            ("read2", 4, b"TTGAC"),
+        ]);
+        let reads: Vec<ReadRecord> = BamReader::new(bytes.as_slice())
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(reads.len(), 2);
+        assert_eq!(reads[0].name, "read1");
+        assert_eq!(reads[0].seq, b"ACGTN");
+        assert_eq!(reads[1].seq, b"TTGAC");
+    }
+
+    #[test]
+    fn test_truncated() {
+        let bytes = bam(&[("read1", 4, b"ACGTACGT")]);
+        let mut reader = BamReader::new(&bytes[..bytes.len() - 3]);
+        let err = reader.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "BAM: the input ends inside a record");
+        let mut reader = BamReader::new(&b"BAM\x02"[..]);
+        assert_eq!(
+            reader.next().unwrap().unwrap_err().to_string(),
+            "BAM: bad magic bytes"
+        );
+    }
+}
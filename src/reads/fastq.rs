@@ -0,0 +1,131 @@
+//! FASTQ reads.
+//!
+//! Long-read FASTQ files from basecallers are four lines per read: the `@` header, the bases, a
+//! `+` separator and the qualities. Sequence lines are not wrapped in practice, so only that
+//! layout is read; a wrapped file is reported as malformed rather than misread.
+
+use crate::reads::ReadRecord;
+use std::io::{self, BufRead};
+
+/// Iterator over the reads of FASTQ text.
+///
+/// # Fields
+///
+/// * `inner`: The buffered FASTQ text.
+/// * `line`: The 1-based number of the last line read, for error messages.
+/// * `buf`: A reused line buffer.
+pub struct FastqReader<R: BufRead> {
+    inner: R,
+    line: u64,
+    buf: String,
+}
+
+impl<R: BufRead> FastqReader<R> {
+    /// Constructor for `FastqReader`.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            line: 0,
+            buf: String::new(),
+        }
+    }
+
+    /// Reads the next line without its line ending, or `None` at the end of the input.
+    fn next_line(&mut self) -> io::Result<Option<&str>> {
+        self.buf.clear();
+        if self.inner.read_line(&mut self.buf)? == 0 {
+            return Ok(None);
+        }
+        self.line += 1;
+        Ok(Some(self.buf.trim_end_matches(['\n', '\r'])))
+    }
+
+    /// An `InvalidData` error at the current line.
+    fn invalid(&self, message: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("FASTQ line {}: {}", self.line, message),
+        )
+    }
+
+    /// Reads one read, or `None` at the end of the input.
+    fn read_record(&mut self) -> io::Result<Option<ReadRecord>> {
+        let name = loop {
+            match self.next_line()? {
+                None => return Ok(None),
+                Some("") => continue,
+                Some(header) => match header.strip_prefix('@') {
+                    Some(header) => {
+                        break header
+                            .split_whitespace()
+                            .next()
+                            .unwrap_or_default()
+                            .to_string()
+                    }
+                    None => return Err(self.invalid("expected a header starting with '@'")),
+                },
+            }
+        };
+        let seq = match self.next_line()? {
+            Some(seq) => seq.as_bytes().to_ascii_uppercase(),
+            None => return Err(self.invalid("the read has no sequence")),
+        };
+        match self.next_line()? {
+            Some(separator) if separator.starts_with('+') => {}
+            _ => return Err(self.invalid("expected a '+' separator line")),
+        }
+        let qualities = self.next_line()?.map(str::len);
+        if qualities != Some(seq.len()) {
+            return Err(self.invalid("the qualities are not as long as the sequence"));
+        }
+        Ok(Some(ReadRecord { name, seq }))
+    }
+}
+
+impl<R: BufRead> Iterator for FastqReader<R> {
+    type Item = io::Result<ReadRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fastq_reader() {
+        let text = "@read1 runid=abc\nACGTn\n+\n!!!!!\n\n@read2\nTTGA\n+read2\n####\n";
+        let reads: Vec<ReadRecord> = FastqReader::new(text.as_bytes())
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(reads.len(), 2);
+        assert_eq!(reads[0].name, "read1");
+        assert_eq!(reads[0].seq, b"ACGTN");
+        assert_eq!(reads[1].seq, b"TTGA");
+    }
+
+    #[test]
+    fn test_malformed() {
+        let first_error = |text: &str| {
+            FastqReader::new(text.as_bytes())
+                .find_map(Result::err)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            first_error(">read1\nACGT\n"),
+            "FASTQ line 1: expected a header starting with '@'"
+        );
+        assert_eq!(
+            first_error("@read1\nACGT\n+\n!!!\n"),
+            "FASTQ line 4: the qualities are not as long as the sequence"
+        );
+        // a wrapped sequence
+        assert_eq!(
+            first_error("@read1\nACGT\nACGT\n+\n!!!!!!!!\n"),
+            "FASTQ line 3: expected a '+' separator line"
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! # Resources module
+//! Detection of the CPU and memory a run may use, so runs inside Kubernetes pods, Slurm jobs and
+//! other containers size their thread pools and buffers to their own limits rather than to the
+//! host, which would oversubscribe the CPUs and invite the out-of-memory killer.
+//!
+//! The thread count is the smallest of the CPUs the process may run on, the cgroup CPU quota
+//! rounded up, and `SLURM_CPUS_PER_TASK` when Slurm sets it. Both cgroup v2 (`cpu.max`,
+//! `memory.max`) and v1 (`cpu.cfs_quota_us`, `memory.limit_in_bytes`) hierarchies are read.
+
+use crate::stats::format_bytes;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// Where cgroup hierarchies are mounted.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// v1 memory limits at or above this mean no limit; unlimited is reported as the largest page
+/// multiple that fits in an `i64`.
+const UNLIMITED_MEMORY: u64 = 1 << 60;
+
+/// The share of the memory limit that computed values may hold while waiting to be written,
+/// when `--max-memory` is not given.
+pub const MEMORY_SHARE: usize = 4;
+
+/// What limited the thread count.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuSource {
+    /// The CPUs available to the process on the host.
+    Host,
+    /// The cgroup CPU quota.
+    Cgroup,
+    /// `SLURM_CPUS_PER_TASK`.
+    Slurm,
+}
+
+/// The CPU and memory a run may use.
+///
+/// # Fields
+///
+/// * `threads`: The number of threads to run.
+/// * `cpu_source`: What limited `threads`.
+/// * `memory_limit`: The cgroup memory limit in bytes, if there is one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Resources {
+    pub threads: usize,
+    pub cpu_source: CpuSource,
+    pub memory_limit: Option<usize>,
+}
+
+impl Resources {
+    /// Detects the limits of this process.
+    pub fn detect() -> Self {
+        let host = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let (cpus, memory) = match fs::read_to_string("/proc/self/cgroup") {
+            Ok(cgroup) => cgroup_limits(&cgroup, Path::new(CGROUP_ROOT)),
+            Err(_) => (None, None),
+        };
+        let slurm = env::var("SLURM_CPUS_PER_TASK")
+            .ok()
+            .and_then(|cpus| cpus.trim().parse().ok());
+        Self::from_limits(host, cpus, slurm, memory)
+    }
+
+    /// Combines limits found elsewhere.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The CPUs available on the host.
+    /// * `cgroup_cpus` - The cgroup CPU quota, in CPUs, if there is one.
+    /// * `slurm_cpus` - The CPUs Slurm allocated per task, if known.
+    /// * `memory_limit` - The cgroup memory limit in bytes, if there is one.
+    pub fn from_limits(
+        host: usize,
+        cgroup_cpus: Option<f64>,
+        slurm_cpus: Option<usize>,
+        memory_limit: Option<usize>,
+    ) -> Self {
+        let mut threads = host.max(1);
+        let mut cpu_source = CpuSource::Host;
+        if let Some(cpus) = cgroup_cpus {
+            // a quota of 1.5 CPUs still keeps two threads busy part of the time
+            let cpus = (cpus.ceil() as usize).max(1);
+            if cpus < threads {
+                threads = cpus;
+                cpu_source = CpuSource::Cgroup;
+            }
+        }
+        if let Some(cpus) = slurm_cpus.filter(|&cpus| cpus > 0 && cpus < threads) {
+            threads = cpus;
+            cpu_source = CpuSource::Slurm;
+        }
+        Self {
+            threads,
+            cpu_source,
+            memory_limit,
+        }
+    }
+
+    /// The cap on computed values waiting to be written: `--max-memory` if given, otherwise a
+    /// quarter of the memory limit, if there is one.
+    pub fn max_memory(&self, max_memory: Option<usize>) -> Option<usize> {
+        max_memory.or(self.memory_limit.map(|limit| limit / MEMORY_SHARE))
+    }
+}
+
+impl fmt::Display for Resources {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let source = match self.cpu_source {
+            CpuSource::Host => "host CPUs",
+            CpuSource::Cgroup => "cgroup CPU quota",
+            CpuSource::Slurm => "SLURM_CPUS_PER_TASK",
+        };
+        write!(f, "{} threads ({})", self.threads, source)?;
+        match self.memory_limit {
+            Some(limit) => write!(f, ", memory limit {}", format_bytes(limit)),
+            None => write!(f, ", no memory limit"),
+        }
+    }
+}
+
+/// Reads the CPU quota and memory limit of the cgroups listed in a `/proc/<pid>/cgroup` file.
+///
+/// # Arguments
+///
+/// * `cgroup` - The contents of `/proc/<pid>/cgroup`.
+/// * `root` - Where the cgroup hierarchies are mounted.
+///
+/// # Returns
+///
+/// The CPU quota in CPUs and the memory limit in bytes, each `None` if there is no limit.
+pub fn cgroup_limits(cgroup: &str, root: &Path) -> (Option<f64>, Option<usize>) {
+    let mut cpus = None;
+    let mut memory = None;
+    for line in cgroup.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (Some(_), Some(controllers), Some(path)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        if controllers.is_empty() {
+            // cgroup v2: one unified hierarchy
+            cpus = cpus
+                .or_else(|| read_limit(root, "", path, "cpu.max").and_then(|s| parse_cpu_max(&s)));
+            memory = memory.or_else(|| {
+                read_limit(root, "", path, "memory.max").and_then(|s| parse_memory_limit(&s))
+            });
+        } else if controllers.split(',').any(|c| c == "cpu") {
+            cpus = cpus.or_else(|| {
+                let quota = read_limit(root, controllers, path, "cpu.cfs_quota_us")?;
+                let period = read_limit(root, controllers, path, "cpu.cfs_period_us")?;
+                parse_cfs_quota(&quota, &period)
+            });
+        } else if controllers.split(',').any(|c| c == "memory") {
+            memory = memory.or_else(|| {
+                read_limit(root, controllers, path, "memory.limit_in_bytes")
+                    .and_then(|s| parse_memory_limit(&s))
+            });
+        }
+    }
+    (cpus, memory)
+}
+
+/// Helper to `cgroup_limits()` that reads a control file of a cgroup. Inside a container the
+/// process's own cgroup is usually mounted as the root of the hierarchy, so the root is tried
+/// when the full path does not exist.
+fn read_limit(root: &Path, controllers: &str, path: &str, file: &str) -> Option<String> {
+    let hierarchy = root.join(controllers);
+    let own: PathBuf = hierarchy.join(path.trim_start_matches('/')).join(file);
+    fs::read_to_string(own)
+        .or_else(|_| fs::read_to_string(hierarchy.join(file)))
+        .ok()
+}
+
+/// Parses a cgroup v2 `cpu.max`, `QUOTA PERIOD` or `max PERIOD`, as a number of CPUs.
+fn parse_cpu_max(cpu_max: &str) -> Option<f64> {
+    let mut fields = cpu_max.split_whitespace();
+    let quota = fields.next()?;
+    let period: f64 = fields.next().map_or(Some(100_000.0), |p| p.parse().ok())?;
+    if quota == "max" || period <= 0.0 {
+        return None;
+    }
+    Some(quota.parse::<f64>().ok()? / period)
+}
+
+/// Parses cgroup v1 `cpu.cfs_quota_us` and `cpu.cfs_period_us` as a number of CPUs; a quota of
+/// -1 means no limit.
+fn parse_cfs_quota(quota: &str, period: &str) -> Option<f64> {
+    let quota: f64 = quota.trim().parse().ok()?;
+    let period: f64 = period.trim().parse().ok()?;
+    (quota > 0.0 && period > 0.0).then(|| quota / period)
+}
+
+/// Parses a cgroup v2 `memory.max` or v1 `memory.limit_in_bytes`.
+fn parse_memory_limit(limit: &str) -> Option<usize> {
+    let limit = limit.trim();
+    if limit == "max" {
+        return None;
+    }
+    let bytes: u64 = limit.parse().ok()?;
+    (bytes < UNLIMITED_MEMORY).then_some(bytes as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_limits() {
+        assert_eq!(parse_cpu_max("250000 100000\n"), Some(2.5));
+        assert_eq!(parse_cpu_max("max 100000\n"), None);
+        assert_eq!(parse_cfs_quota("200000\n", "100000\n"), Some(2.0));
+        assert_eq!(parse_cfs_quota("-1\n", "100000\n"), None);
+        assert_eq!(parse_memory_limit("2147483648\n"), Some(2 << 30));
+        assert_eq!(parse_memory_limit("max\n"), None);
+        assert_eq!(parse_memory_limit("9223372036854771712\n"), None);
+    }
+
+    #[test]
+    fn test_cgroup_limits() {
+        let root = std::env::temp_dir().join("symcurve_test_cgroup");
+        // v2, with the pod's cgroup mounted as the root of the hierarchy
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("cpu.max"), "150000 100000\n").unwrap();
+        fs::write(root.join("memory.max"), "1073741824\n").unwrap();
+        assert_eq!(
+            cgroup_limits("0::/kubepods/burstable/pod1/abc\n", &root),
+            (Some(1.5), Some(1 << 30))
+        );
+        // v1, with the job's cgroup below the hierarchy root
+        let cpu = root.join("cpu,cpuacct/slurm/job_7");
+        let memory = root.join("memory/slurm/job_7");
+        fs::create_dir_all(&cpu).unwrap();
+        fs::create_dir_all(&memory).unwrap();
+        fs::write(cpu.join("cpu.cfs_quota_us"), "400000\n").unwrap();
+        fs::write(cpu.join("cpu.cfs_period_us"), "100000\n").unwrap();
+        fs::write(memory.join("memory.limit_in_bytes"), "536870912\n").unwrap();
+        let cgroup = "12:memory:/slurm/job_7\n4:cpu,cpuacct:/slurm/job_7\n1:name=systemd:/\n";
+        assert_eq!(cgroup_limits(cgroup, &root), (Some(4.0), Some(512 << 20)));
+        assert_eq!(cgroup_limits("", &root), (None, None));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_from_limits() {
+        let host = Resources::from_limits(64, None, None, None);
+        assert_eq!((host.threads, host.cpu_source), (64, CpuSource::Host));
+        assert_eq!(host.max_memory(None), None);
+        assert_eq!(host.to_string(), "64 threads (host CPUs), no memory limit");
+        let pod = Resources::from_limits(64, Some(1.5), None, Some(4 << 30));
+        assert_eq!((pod.threads, pod.cpu_source), (2, CpuSource::Cgroup));
+        assert_eq!(pod.max_memory(None), Some(1 << 30));
+        assert_eq!(pod.max_memory(Some(100)), Some(100));
+        assert_eq!(
+            pod.to_string(),
+            "2 threads (cgroup CPU quota), memory limit 4.0 GiB"
+        );
+        let job = Resources::from_limits(64, Some(8.0), Some(4), None);
+        assert_eq!((job.threads, job.cpu_source), (4, CpuSource::Slurm));
+        // a quota above the host count and a zero Slurm count change nothing
+        let host = Resources::from_limits(4, Some(16.0), Some(0), None);
+        assert_eq!((host.threads, host.cpu_source), (4, CpuSource::Host));
+    }
+}
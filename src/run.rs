@@ -0,0 +1,3175 @@
+//! # Run module
+//! A whole run of the `symcurve` binary over one input: the curvature output, and whatever the
+//! command line asks for alongside it.
+//!
+//! A plain run streams through [`run_file`](crate::pipeline::run_file), so memory use does not
+//! grow with sequence length. Outputs that need more of a record than the value at hand, such as
+//! the two strands of `--stranded`, are computed instead from each record read whole into memory,
+//! one record at a time. Their values agree with a streamed run's.
+
+use crate::curve::composition::{CompositionFit, Covariates};
+use crate::curve::divergence::{divergence_path, strand_divergence};
+use crate::curve::ensemble::{ensemble, ensemble_paths, MatrixSet};
+use crate::curve::envelope::envelope;
+use crate::curve::iters::{bend_vectors, CurveBuffers, CurveIter, EdgeMode};
+use crate::curve::matrix::RollType;
+use crate::curve::memo::MemoStats;
+use crate::curve::metric::magnitude_phase;
+use crate::curve::symmetry::{axis_path, symmetry_axis};
+use crate::dedup::{duplicate_warning, ContigDigest, DuplicateContigs, DuplicateMode};
+use crate::expr::{kept_ranges, DerivedTrack, Expr, VARIABLES};
+use crate::fasta::mask::MaskTracker;
+use crate::fasta::validate::{check_record, MalformedRecord, OnMalformed};
+use crate::fasta::{open_input_member, reverse_complement, StreamingReader};
+use crate::issues::{ambiguous_runs, issues_path, sequence_issues, Issue, IssueLog, Severity};
+use crate::manifest::{manifest_path, Manifest};
+use crate::monitor::Monitor;
+use crate::output::arrow::ArrowWriter;
+use crate::output::atomic::AtomicFile;
+use crate::output::bed::{
+    write_axes, write_intervals, write_named_intervals, BedFields, EmpiricalNull, NameTemplate,
+    ScoreStat,
+};
+use crate::output::binarize::{above_threshold_intervals, binarize, BinarizeFormat};
+use crate::output::clip::Clip;
+use crate::output::compress::Compression;
+use crate::output::dict::{dict_path, write_dict, SequenceDigest, SequenceEntry};
+use crate::output::preview::Preview;
+use crate::output::pvalue::{neg_log10_p, pvalue_path, read_background};
+use crate::output::spill::SpillFile;
+use crate::output::sqlite::SqliteWriter;
+use crate::output::tsv::{
+    write_bend_header, write_bend_vectors, write_envelope, write_envelope_header,
+    write_magnitude_phase, write_magnitude_phase_header,
+};
+use crate::output::{check_overwrite, stranded_path, OutputFormat, SortContigs, Strand};
+use crate::pipeline::{
+    check_format_params, count_record, interrupted_error, memoized_record_track, pieces,
+    position_values, record_track, run_file_with, symmetry_values, track_path, FileRun,
+    PipelineParams, PipelineSummary, TrackFile,
+};
+use crate::provenance::Provenance;
+use crate::resources::Resources;
+use crate::signal;
+use crate::stats::streaming::DistributionSummary;
+use crate::stats::{ClipStats, MemStats, Stage, Timings};
+use crate::trace::{TraceFormat, Tracer};
+use crate::warnings::{WarningKind, Warnings};
+use std::cell::OnceCell;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter};
+use std::iter;
+use std::mem;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// What a run writes besides the curvature output and its symmetry track.
+///
+/// # Fields
+///
+/// * `stranded`: Whether the curvature of each strand is written to its own output in place of
+///   the one output (`--stranded`); see [`stranded_path`].
+/// * `bend_vectors`: The path of a TSV output of the bend vector of each triplet
+///   (`--bend-vectors`); see [`write_bend_vectors`].
+/// * `timings`: Whether the wall-clock time of each stage of the pipeline is kept for the report
+///   (`--timings`). Streamed stages run interleaved, value by value, so a sample of the calls of
+///   each is timed and counted for all of them.
+/// * `trace`: The path of a trace of a span per record and stage (`--trace`). Streamed stages
+///   are timed as for `timings`, so the spans of a record's stages are laid end to end.
+/// * `trace_format`: The format of the trace (`--trace-format`).
+/// * `binarize`: The threshold of a thresholded copy of the curvature output (`--binarize`).
+/// * `binarize_format`: The form of the thresholded copy (`--binarize-format`), whose path
+///   [`BinarizeFormat::path`] gives.
+/// * `bed_name`: The template of the name column of the BED intervals of `binarize`
+///   (`--bed-name`).
+/// * `bed_score`: The statistic written to the score column of the BED intervals of `binarize`
+///   (`--bed-score`). [`ScoreStat::NegLog10P`] scores against every value of the run, so the
+///   intervals are held until the input is read.
+/// * `memoize`: Whether the values of exactly repeated windows are reused rather than computed
+///   (`--memoize`); see [`memo`](crate::curve::memo).
+/// * `duplicate_contigs`: What is done about records with the same sequence as an earlier one
+///   (`--duplicate-contigs`); see [`dedup`](crate::dedup).
+/// * `dict`: Whether a sequence dictionary of the input is written next to the output
+///   (`--dict`); see [`dict_path`].
+/// * `sort_contigs`: The order records are written in (`--sort-contigs`). Any order but the
+///   input's holds every record's values until the input is read, or spills them to
+///   `spill_dir`.
+/// * `derive`: The tracks computed per position from an expression (`--derive`), each written
+///   in the format of the run at [`DerivedTrack::path`].
+/// * `filter`: The expression a position must be true for to be written (`--filter`); every
+///   other position is left without a value in each per-position output.
+/// * `clip`: The range curvature is clipped to as it is computed (`--clip`), so that every output
+///   of it, the symmetry scores included, sees the clipped values. Residuals of
+///   `composition_correction` are clipped in place of the raw values.
+/// * `strand_divergence`: Whether the divergence between the curvature of the two strands is
+///   written in the format of the run (`--strand-divergence`); see [`divergence_path`].
+/// * `max_memory`: The most bytes of computed rows left waiting to be written (`--max-memory`).
+///   The outputs are then written on a thread of their own, and computation waits while that
+///   many bytes are waiting; see [`run_file_with`]. Options that read records whole cannot be
+///   given with it, as a whole record is more than the cap bounds. Without it, a run that
+///   streams its records is capped by the memory limit of its container, if there is one; see
+///   [`Resources::max_memory`].
+/// * `symmetry_axis`: Whether the inferred symmetry axis of each symmetry window is written as
+///   BED (`--symmetry-axis`); see [`axis_path`].
+/// * `magnitude_phase`: The path of a TSV output of the magnitude and phase of each curvature
+///   window (`--magnitude-phase`); see [`magnitude_phase`].
+/// * `composition_correction`: The composition covariates the curvature is regressed on, the
+///   residuals being written in place of it (`--composition-correction`); see
+///   [`composition`](crate::curve::composition). The fit is learned from the whole input, so
+///   every record's values are held until the input is read.
+/// * `envelope`: The path of a TSV output of the rolling minimum and maximum of the curvature
+///   written (`--envelope`); see [`envelope`].
+/// * `envelope_window`: The number of values in each envelope window (`--envelope-window`).
+/// * `background`: The path of background curvature values, against which each written value is
+///   also written as `-log10(p)` in the format of the run (`--background`); see [`pvalue_path`].
+/// * `stats_json`: The path of a JSON summary of the distribution of the values written
+///   (`--stats-json`); see [`DistributionSummary`].
+/// * `spill_dir`: The directory of a [`SpillFile`] the records held until the input is read, for
+///   `sort_contigs` or `composition_correction`, are kept in rather than in memory
+///   (`--spill-dir`). Records only sorted stream through it, so that no record is held whole;
+///   see [`run_file_with`]. Records an option reads whole are spilled with their bases once
+///   computed and read back one at a time. Records written as they are read are not spilled.
+/// * `tui`: Whether a live [`Monitor`] of the records computed is drawn on stderr (`--tui`).
+/// * `arrow`: The path of an Arrow IPC stream of the values written, `-` for stdout (`--arrow`);
+///   see [`ArrowWriter`].
+/// * `sqlite`: The path of a SQLite database of the values written (`--sqlite`), built from
+///   values spilled to `spill_dir`, or next to the database without it; see [`SqliteWriter`].
+/// * `preview`: The windows of each record kept in a quick preview (`--preview`), which is
+///   written in place of every output, at paths derived from [`Preview::path`] of the output.
+///   Only the bases a prefix preview needs are computed.
+/// * `masked_bed`: The path of a BED output of the soft-masked (lowercase) intervals of each
+///   record (`--masked-bed`); see [`MaskTracker`].
+/// * `matrix_set`: The matrix sets the per-position mean and standard deviation of curvature are
+///   computed across, each written in the format of the run (`--matrix-set`); see
+///   [`ensemble_paths`].
+/// * `issues`: Whether the issues of the run are also written as JSON lines next to the output
+///   (`--issues`); see [`issues_path`]. They are reported on stderr either way.
+/// * `manifest`: Whether a run manifest of the size and checksums of every output, and of the
+///   build and provenance (`params.provenance`) that wrote them, is written next to the output once they are all finished
+///   (`--manifest`); see [`manifest_path`].
+/// * `warning_limit`: The most warnings of one kind shown on stderr for each contig, the rest
+///   being summarized at the end of the run (`--warning-limit`); `None` shows them all. The JSON
+///   lines of `issues` have every one.
+/// * `on_malformed`: What is done with a malformed record (`--on-malformed`); see
+///   [`check_record`]. Leaving records out reads each record whole, so that none of a record is
+///   written before it is checked.
+#[derive(Clone, Debug, Default)]
+pub struct RunOptions {
+    pub stranded: bool,
+    pub bend_vectors: Option<PathBuf>,
+    pub timings: bool,
+    pub trace: Option<PathBuf>,
+    pub trace_format: TraceFormat,
+    pub binarize: Option<f64>,
+    pub binarize_format: BinarizeFormat,
+    pub bed_name: Option<NameTemplate>,
+    pub bed_score: Option<ScoreStat>,
+    pub memoize: bool,
+    pub duplicate_contigs: DuplicateMode,
+    pub dict: bool,
+    pub sort_contigs: SortContigs,
+    pub derive: Vec<DerivedTrack>,
+    pub filter: Option<Expr>,
+    pub clip: Option<Clip>,
+    pub strand_divergence: bool,
+    pub max_memory: Option<usize>,
+    pub symmetry_axis: bool,
+    pub magnitude_phase: Option<PathBuf>,
+    pub composition_correction: Option<Covariates>,
+    pub envelope: Option<PathBuf>,
+    pub envelope_window: usize,
+    pub background: Option<PathBuf>,
+    pub stats_json: Option<PathBuf>,
+    pub spill_dir: Option<PathBuf>,
+    pub tui: bool,
+    pub arrow: Option<PathBuf>,
+    pub sqlite: Option<PathBuf>,
+    pub preview: Option<Preview>,
+    pub masked_bed: Option<PathBuf>,
+    pub matrix_set: Option<MatrixSet>,
+    pub issues: bool,
+    pub manifest: bool,
+    pub warning_limit: Option<u64>,
+    pub on_malformed: OnMalformed,
+}
+
+impl RunOptions {
+    /// The flag of the first option that needs each record in memory, or `None` if the run can
+    /// stream.
+    fn in_memory(&self) -> Option<&'static str> {
+        [
+            (self.stranded, "--stranded"),
+            (self.bend_vectors.is_some(), "--bend-vectors"),
+            (self.binarize.is_some(), "--binarize"),
+            (self.memoize, "--memoize"),
+            (
+                self.duplicate_contigs != DuplicateMode::Off,
+                "--duplicate-contigs",
+            ),
+            (self.dict, "--dict"),
+            (
+                self.sort_contigs != SortContigs::Input && self.spill_dir.is_none(),
+                "--sort-contigs",
+            ),
+            (!self.derive.is_empty(), "--derive"),
+            (self.filter.is_some(), "--filter"),
+            (self.clip.is_some(), "--clip"),
+            (self.strand_divergence, "--strand-divergence"),
+            (self.symmetry_axis, "--symmetry-axis"),
+            (self.magnitude_phase.is_some(), "--magnitude-phase"),
+            (
+                self.composition_correction.is_some(),
+                "--composition-correction",
+            ),
+            (self.envelope.is_some(), "--envelope"),
+            (self.background.is_some(), "--background"),
+            (self.stats_json.is_some(), "--stats-json"),
+            (self.tui, "--tui"),
+            (self.arrow.is_some(), "--arrow"),
+            (self.sqlite.is_some(), "--sqlite"),
+            (self.preview.is_some(), "--preview"),
+            (self.masked_bed.is_some(), "--masked-bed"),
+            (self.matrix_set.is_some(), "--matrix-set"),
+            (
+                self.on_malformed == OnMalformed::Skip,
+                "--on-malformed skip",
+            ),
+        ]
+        .into_iter()
+        .find_map(|(given, flag)| given.then_some(flag))
+    }
+
+    /// What an output of a run with these options holds, as `--emit-outputs` lists it, e.g.
+    /// `track` or `dict`; `output` if the path is none the options write. Tracks are found with
+    /// or without the extension of a compression.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the output, as the report of the run gives it.
+    /// * `output` - The path of the main output of the run.
+    /// * `symmetry` - The path of the symmetry track of the run, if one was written.
+    pub fn output_kind(&self, path: &Path, output: &Path, symmetry: Option<&Path>) -> &'static str {
+        let (output, symmetry) = self.preview_paths(output, symmetry);
+        let (output, symmetry) = (output.as_path(), symmetry.as_deref());
+        let stranded = |path: &Path| {
+            [path.to_path_buf()]
+                .into_iter()
+                .chain([Strand::Plus, Strand::Minus].map(|strand| stranded_path(path, strand)))
+                .collect::<Vec<_>>()
+        };
+        let mut kinds: Vec<(PathBuf, &'static str)> = Vec::new();
+        kinds.extend(stranded(output).into_iter().map(|path| (path, "track")));
+        if let Some(symmetry) = symmetry {
+            kinds.extend(
+                stranded(symmetry)
+                    .into_iter()
+                    .map(|path| (path, "symmetry")),
+            );
+        }
+        let named = [
+            (&self.bend_vectors, "bend_vectors"),
+            (&self.trace, "trace"),
+            (&self.magnitude_phase, "magnitude_phase"),
+            (&self.envelope, "envelope"),
+            (&self.stats_json, "stats"),
+            (&self.arrow, "arrow"),
+            (&self.sqlite, "sqlite"),
+            (&self.masked_bed, "masked"),
+        ];
+        for (path, kind) in named {
+            kinds.extend(path.clone().map(|path| (path, kind)));
+        }
+        if self.binarize.is_some() {
+            kinds.push((self.binarize_format.path(output), "binarized"));
+        }
+        for track in &self.derive {
+            kinds.push((track.path(output), "derived"));
+        }
+        if self.matrix_set.is_some() {
+            let (mean, sd) = ensemble_paths(output);
+            kinds.extend([(mean, "ensemble_mean"), (sd, "ensemble_sd")]);
+        }
+        kinds.extend([
+            (issues_path(output), "issues"),
+            (manifest_path(output), "manifest"),
+            (divergence_path(output), "divergence"),
+            (axis_path(output), "symmetry_axis"),
+            (pvalue_path(output), "pvalue"),
+            (dict_path(output), "dict"),
+        ]);
+        let compressions = [Compression::None, Compression::Gzip, Compression::Zstd];
+        kinds
+            .into_iter()
+            .find_map(|(candidate, kind)| {
+                compressions
+                    .iter()
+                    .any(|compression| compression.output_path(&candidate) == path)
+                    .then_some(kind)
+            })
+            .unwrap_or("output")
+    }
+
+    /// The paths the outputs of a run are derived from: those given, or with `preview` those of
+    /// the preview.
+    fn preview_paths(&self, output: &Path, symmetry: Option<&Path>) -> (PathBuf, Option<PathBuf>) {
+        match self.preview {
+            Some(_) if output.as_os_str() != "-" => {
+                (Preview::path(output), symmetry.map(Preview::path))
+            }
+            _ => (output.to_path_buf(), symmetry.map(Path::to_path_buf)),
+        }
+    }
+}
+
+/// What a run processed and wrote.
+///
+/// # Fields
+///
+/// * `summary`: What the pipeline processed.
+/// * `outputs`: The paths of the files written, the curvature output first.
+/// * `memory`: The largest buffers of the run, reported with `--mem-stats`.
+/// * `timings`: The wall-clock time of each stage, kept with `options.timings`.
+/// * `memo`: How many values were computed and how many reused, with `options.memoize`.
+/// * `warnings`: The non-fatal anomalies of the run, counted by kind.
+/// * `clip`: The positions clipped by `options.clip`, in the records as read.
+/// * `skipped`: The malformed records left out with `options.on_malformed`, in input order.
+#[derive(Debug, Default)]
+pub struct RunReport {
+    pub summary: PipelineSummary,
+    pub outputs: Vec<PathBuf>,
+    pub memory: MemStats,
+    pub timings: Timings,
+    pub memo: MemoStats,
+    pub warnings: Warnings,
+    pub clip: ClipStats,
+    pub skipped: Vec<MalformedRecord>,
+}
+
+/// Runs the curvature pipeline from a FASTA file to its outputs, as
+/// [`run_file`](crate::pipeline::run_file) does, along with the outputs `options` asks for.
+///
+/// # Arguments
+///
+/// * `input` - The FASTA file, or `-` for standard input.
+/// * `output` - The output path.
+/// * `symmetry` - The symmetry track output path, or `None` to write no symmetry track.
+/// * `format` - The format of the outputs.
+/// * `params` - The parameters of the run.
+/// * `options` - The outputs written besides the curvature.
+/// * `force` - Whether existing outputs may be overwritten.
+///
+/// # Errors
+///
+/// Returns any error `run_file()` would, or an `io::ErrorKind::InvalidInput` error if an option
+/// that reads records whole is given with `params.regions`, BED columns without a BED output
+/// for them, `options.max_memory` with an option that reads records whole,
+/// `options.composition_correction` with `options.stranded`,
+/// whose minus strand is computed as it is written, `options.arrow` on stdout with an output
+/// that is too, or `options.issues` or `options.manifest` with an output on stdout. An error is
+/// also written to the issues file, which is kept when the other outputs are not.
+pub fn run(
+    input: &Path,
+    output: &Path,
+    symmetry: Option<&Path>,
+    format: OutputFormat,
+    params: &PipelineParams,
+    options: &RunOptions,
+    force: bool,
+) -> io::Result<RunReport> {
+    for (given, flag) in [
+        (options.issues, "--issues"),
+        (options.manifest, "--manifest"),
+    ] {
+        if given && output.as_os_str() == "-" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} cannot be used with an output written to stdout", flag),
+            ));
+        }
+    }
+    let manifest = options.manifest.then(|| manifest_path(output));
+    if let Some(path) = &manifest {
+        check_overwrite(path, force)?;
+    }
+    let jsonl = match options.issues {
+        true => {
+            let path = issues_path(output);
+            check_overwrite(&path, force)?;
+            Some(AtomicFile::create(&path)?)
+        }
+        false => None,
+    };
+    let mut issues = IssueLog::new(jsonl).with_limit(options.warning_limit);
+    let mut result = run_logged(
+        input,
+        output,
+        symmetry,
+        format,
+        params,
+        options,
+        force,
+        &mut issues,
+    );
+    if let Err(e) = &result {
+        // the error itself is shown by the caller
+        let issue = Issue::new(Severity::Error, "run_failed", e.to_string());
+        issues.report_to(&issue, &mut io::sink())?;
+    }
+    if let Some(file) = issues.finish()? {
+        let path = file.path().to_path_buf();
+        file.commit()?;
+        if let Ok(report) = &mut result {
+            report.outputs.push(path);
+        }
+    }
+    let mut report = result?;
+    if let Some(path) = manifest {
+        let mut manifest = Manifest::new();
+        manifest.provenance = params.provenance.clone();
+        for output in report.outputs.iter().filter(|path| path.as_os_str() != "-") {
+            manifest.add_output(output)?;
+        }
+        for record in &report.skipped {
+            manifest.add_skipped(record);
+        }
+        manifest.write(&path)?;
+        report.outputs.push(path);
+    }
+    Ok(report)
+}
+
+/// Helper to `run()` that runs the pipeline, reporting the issues of each record to `issues`.
+#[allow(clippy::too_many_arguments)]
+fn run_logged(
+    input: &Path,
+    output: &Path,
+    symmetry: Option<&Path>,
+    format: OutputFormat,
+    params: &PipelineParams,
+    options: &RunOptions,
+    force: bool,
+    issues: &mut IssueLog<AtomicFile>,
+) -> io::Result<RunReport> {
+    if (options.bed_name.is_some() || options.bed_score.is_some())
+        && (options.binarize.is_none() || options.binarize_format != BinarizeFormat::Bed)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--bed-name and --bed-score only apply to --binarize with --binarize-format bed",
+        ));
+    }
+    if let Some(path) = &options.trace {
+        check_overwrite(path, force)?;
+    }
+    let Some(flag) = options.in_memory() else {
+        let tracer = options.trace.as_ref().map(|_| Tracer::new());
+        let run_span = tracer.as_ref().map(|tracer| tracer.span("run"));
+        let mut state = FileRun {
+            issues: Some(mem::take(issues)),
+            max_memory: Resources::detect().max_memory(options.max_memory),
+            timings: options.timings.then(Timings::new),
+            tracer: tracer.as_ref(),
+            sort_contigs: options.sort_contigs,
+            spill_dir: options.spill_dir.clone(),
+            ..FileRun::default()
+        };
+        let output = track_path(output, format, params);
+        let symmetry = symmetry.map(|path| track_path(path, format, params));
+        let summary = run_file_with(
+            input,
+            &output,
+            symmetry.as_deref(),
+            format,
+            params,
+            force,
+            &mut state,
+        );
+        *issues = state.issues.take().unwrap_or_default();
+        let summary = summary?;
+        let mut outputs: Vec<PathBuf> = [Some(output), symmetry].into_iter().flatten().collect();
+        drop(run_span);
+        if let (Some(tracer), Some(path)) = (&tracer, &options.trace) {
+            let mut file = AtomicFile::create(path)?;
+            tracer.write(&mut file, options.trace_format)?;
+            file.commit()?;
+            outputs.push(path.clone());
+        }
+        return Ok(RunReport {
+            summary,
+            outputs,
+            memory: state.memory,
+            timings: state.timings.unwrap_or_default(),
+            warnings: state.warnings,
+            ..RunReport::default()
+        });
+    };
+    if !params.regions.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} cannot be used with --regions", flag),
+        ));
+    }
+    if options.max_memory.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "--max-memory cannot be used with {}, which reads each record whole",
+                flag
+            ),
+        ));
+    }
+    check_format_params(format, params)?;
+    let (output, symmetry) = options.preview_paths(output, symmetry);
+    let (output, symmetry) = (output.as_path(), symmetry.as_deref());
+    let strands = if options.stranded {
+        vec![Some(Strand::Plus), Some(Strand::Minus)]
+    } else {
+        vec![None]
+    };
+    let mut outputs: Vec<Box<dyn RecordOutput>> = Vec::new();
+    for strand in strands {
+        let path = strand.map_or_else(|| output.to_path_buf(), |s| stranded_path(output, s));
+        let path = track_path(&path, format, params);
+        let symmetry = symmetry.map(|path| match strand {
+            Some(strand) => track_path(&stranded_path(path, strand), format, params),
+            None => track_path(path, format, params),
+        });
+        check_overwrite(&path, force)?;
+        if let Some(symmetry) = &symmetry {
+            check_overwrite(symmetry, force)?;
+        }
+        outputs.push(Box::new(StrandTracks::create(
+            strand,
+            path,
+            symmetry,
+            format,
+            params,
+            options.clip,
+        )?));
+    }
+    if let Some(path) = &options.bend_vectors {
+        check_overwrite(path, force)?;
+        outputs.push(Box::new(BendVectors::create(
+            path,
+            params.provenance.as_ref(),
+        )?));
+    }
+    let fields = BedFields {
+        name: options.bed_name.clone(),
+        score: options.bed_score,
+        float: params.float_format,
+    };
+    if let Some(threshold) = options.binarize {
+        let path = match options.binarize_format {
+            BinarizeFormat::Track => {
+                track_path(&options.binarize_format.path(output), format, params)
+            }
+            BinarizeFormat::Bed => options.binarize_format.path(output),
+        };
+        check_overwrite(&path, force)?;
+        outputs.push(Box::new(Binarized::create(
+            path,
+            threshold,
+            options.binarize_format,
+            fields,
+            format,
+            params,
+        )?));
+    }
+    for track in &options.derive {
+        let path = track_path(&track.path(output), format, params);
+        check_overwrite(&path, force)?;
+        outputs.push(Box::new(Derived {
+            expr: &track.expr,
+            file: TrackFile::create(&path, format, params)?,
+            path,
+        }));
+    }
+    if let Some(set) = &options.matrix_set {
+        let (mean, sd) = ensemble_paths(output);
+        let mean = track_path(&mean, format, params);
+        let sd = track_path(&sd, format, params);
+        check_overwrite(&mean, force)?;
+        check_overwrite(&sd, force)?;
+        outputs.push(Box::new(Ensemble {
+            roll_types: set.roll_types()?,
+            mean: TrackFile::create(&mean, format, params)?,
+            sd: TrackFile::create(&sd, format, params)?,
+            paths: vec![mean, sd],
+        }));
+    }
+    if options.strand_divergence {
+        let path = track_path(&divergence_path(output), format, params);
+        check_overwrite(&path, force)?;
+        outputs.push(Box::new(Divergence {
+            file: TrackFile::create(&path, format, params)?,
+            path,
+        }));
+    }
+    if let Some(background) = &options.background {
+        let null = read_background(BufReader::new(File::open(background)?))?;
+        let path = track_path(&pvalue_path(output), format, params);
+        check_overwrite(&path, force)?;
+        outputs.push(Box::new(PValues {
+            null,
+            file: TrackFile::create(&path, format, params)?,
+            path,
+        }));
+    }
+    if options.symmetry_axis {
+        let path = axis_path(output);
+        check_overwrite(&path, force)?;
+        outputs.push(Box::new(SymmetryAxes(AtomicFile::create(&path)?)));
+    }
+    if let Some(path) = &options.masked_bed {
+        check_overwrite(path, force)?;
+        outputs.push(Box::new(MaskedIntervals {
+            tracker: MaskTracker::new(),
+            file: AtomicFile::create(path)?,
+        }));
+    }
+    if let Some(path) = &options.magnitude_phase {
+        check_overwrite(path, force)?;
+        outputs.push(Box::new(MagnitudePhase::create(
+            path,
+            params.provenance.as_ref(),
+        )?));
+    }
+    if let Some(path) = &options.envelope {
+        check_overwrite(path, force)?;
+        outputs.push(Box::new(Envelope::create(
+            path,
+            options.envelope_window,
+            params.provenance.as_ref(),
+        )?));
+    }
+    if let Some(path) = &options.arrow {
+        let stream = if path.as_os_str() == "-" {
+            ArrowStream::Stdout(ArrowWriter::new(BufWriter::new(io::stdout()))?)
+        } else {
+            check_overwrite(path, force)?;
+            ArrowStream::File(ArrowWriter::new(AtomicFile::create(path)?)?)
+        };
+        outputs.push(Box::new(Arrow {
+            stream,
+            path: path.clone(),
+        }));
+    }
+    if let Some(path) = &options.sqlite {
+        check_overwrite(path, force)?;
+        let spill_dir = match &options.spill_dir {
+            Some(dir) => dir.clone(),
+            None => match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            },
+        };
+        outputs.push(Box::new(Sqlite(SqliteWriter::create(path, &spill_dir)?)));
+    }
+    if let Some(path) = &options.stats_json {
+        check_overwrite(path, force)?;
+        outputs.push(Box::new(Distribution {
+            summary: DistributionSummary::new(),
+            path: path.clone(),
+        }));
+    }
+    if options.dict {
+        let path = dict_path(output);
+        check_overwrite(&path, force)?;
+        outputs.push(Box::new(SequenceDict::new(path, input)));
+    }
+    if options.composition_correction.is_some() && options.stranded {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--composition-correction cannot be used with --stranded",
+        ));
+    }
+    if output.as_os_str() == "-"
+        && options
+            .arrow
+            .as_deref()
+            .is_some_and(|path| path.as_os_str() == "-")
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--arrow - cannot be used with an output written to stdout",
+        ));
+    }
+    let tracer = options.trace.as_ref().map(|_| Tracer::new());
+    let run_span = tracer.as_ref().map(|tracer| tracer.span("run"));
+    let reader = open_input_member(input, params.archive_member.as_deref())?;
+    let mut fasta = StreamingReader::new(reader);
+    let mut computation = Computation::new(issues);
+    if options.tui {
+        computation.monitor = Some(Monitor::new());
+    }
+    let mut writing = Writing {
+        outputs,
+        symmetry: symmetry.is_some(),
+        filter: options.filter.as_ref(),
+        params,
+        timings: Timings::new(),
+        completed: Vec::new(),
+    };
+    computation.read_all(
+        &mut fasta,
+        options,
+        params,
+        tracer.as_ref(),
+        &mut |record| writing.write(record, tracer.as_ref()),
+    )?;
+    let Computation {
+        summary,
+        memory,
+        mut timings,
+        memo,
+        mut warnings,
+        clip,
+        skipped,
+        ..
+    } = computation;
+    for stage in Stage::ALL {
+        timings.add(stage, writing.timings.get(stage));
+    }
+    let mut paths = Vec::new();
+    for output in writing.outputs {
+        let _span = tracer
+            .as_ref()
+            .map(|tracer| tracer.span(Stage::OutputWriting.name()));
+        paths.extend(timings.time(Stage::OutputWriting, || output.commit())?);
+    }
+    drop(run_span);
+    if let (Some(tracer), Some(path)) = (&tracer, &options.trace) {
+        let mut file = AtomicFile::create(path)?;
+        tracer.write(&mut file, options.trace_format)?;
+        file.commit()?;
+        paths.push(path.clone());
+    }
+    if signal::interrupted() {
+        return Err(interrupted_error(output, &writing.completed));
+    }
+    warnings.record_clipped(&clip);
+    Ok(RunReport {
+        summary,
+        outputs: paths,
+        memory,
+        timings,
+        memo,
+        warnings,
+        clip,
+        skipped,
+    })
+}
+
+/// A record read whole, with its name, bases and curvature.
+type Record = (String, Vec<u8>, Vec<f64>);
+
+/// The reading and computing side of an in-memory run.
+///
+/// # Fields
+///
+/// * `summary`: What has been processed so far.
+/// * `buffers`: The buffers curvature is computed in, reused across records.
+/// * `memory`: The largest buffers so far.
+/// * `timings`: The time spent reading and computing.
+/// * `memo`: The values computed and reused with `--memoize`.
+/// * `warnings`: The anomalies seen so far.
+/// * `clip`: The positions clipped so far with `--clip`.
+/// * `contigs`: The digest of each record so far with `--duplicate-contigs`, with its values if
+///   they are reused.
+/// * `monitor`: The live monitor of `--tui`, if shown.
+/// * `skipped`: The malformed records left out so far with `--on-malformed skip`.
+/// * `issues`: Where the issues of each record are reported.
+struct Computation<'a> {
+    summary: PipelineSummary,
+    buffers: CurveBuffers,
+    memory: MemStats,
+    timings: Timings,
+    memo: MemoStats,
+    warnings: Warnings,
+    clip: ClipStats,
+    contigs: DuplicateContigs<Vec<f64>>,
+    monitor: Option<Monitor>,
+    skipped: Vec<MalformedRecord>,
+    issues: &'a mut IssueLog<AtomicFile>,
+}
+
+impl<'a> Computation<'a> {
+    /// Constructor for `Computation`.
+    fn new(issues: &'a mut IssueLog<AtomicFile>) -> Self {
+        Self {
+            summary: PipelineSummary::default(),
+            buffers: CurveBuffers::default(),
+            memory: MemStats::new(),
+            timings: Timings::new(),
+            memo: MemoStats::default(),
+            warnings: Warnings::new(),
+            clip: ClipStats::default(),
+            contigs: DuplicateContigs::new(),
+            monitor: None,
+            skipped: Vec::new(),
+            issues,
+        }
+    }
+
+    /// Reads each record of the input, computes its curvature and hands it to `sink`, in the
+    /// order `options.sort_contigs` gives. Stops early, without an error, if the run is
+    /// interrupted. A malformed record stops the run with an `io::ErrorKind::InvalidData` error,
+    /// or is left out with a warning under `--on-malformed skip`.
+    ///
+    /// # Arguments
+    ///
+    /// * `fasta` - The input.
+    /// * `options` - The outputs written besides the curvature.
+    /// * `params` - The parameters of the run.
+    /// * `tracer` - The trace the spans of reading and computing are added to, if one is kept.
+    /// * `sink` - Takes each record once its values are computed.
+    fn read_all<R: BufRead>(
+        &mut self,
+        fasta: &mut StreamingReader<R>,
+        options: &RunOptions,
+        params: &PipelineParams,
+        tracer: Option<&Tracer>,
+        sink: &mut dyn FnMut(Record) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let span = |name: &str| tracer.map(|tracer| tracer.span(name));
+        let mut fit = options.composition_correction.map(CompositionFit::new);
+        let held = options.sort_contigs != SortContigs::Input || fit.is_some();
+        let mut spill = match &options.spill_dir {
+            Some(dir) if held => Some(SpillFile::create(dir)?),
+            _ => None,
+        };
+        let mut sorted = Vec::new();
+        let mut index = 0;
+        while !signal::interrupted() {
+            let decoding = span(Stage::FastaDecoding.name());
+            let read = self.timings.time(Stage::FastaDecoding, || {
+                let Some(chrom) = fasta.next_record()? else {
+                    return Ok(None);
+                };
+                let bases = fasta.bases().collect::<io::Result<Vec<u8>>>()?;
+                Ok::<_, io::Error>(Some((chrom, bases)))
+            })?;
+            drop(decoding);
+            let Some((chrom, mut bases)) = read else {
+                break;
+            };
+            index += 1;
+            if let Err(record) = check_record(index, &chrom, &bases) {
+                if options.on_malformed == OnMalformed::Error {
+                    return Err(record.into());
+                }
+                self.issues.report(&Issue::malformed(&record))?;
+                self.warnings.record_malformed(&record);
+                self.skipped.push(record);
+                continue;
+            }
+            let _record_span = span(&chrom);
+            let curvature = span(Stage::Curvature.name());
+            if let Some(preview) = &options.preview {
+                let window = 2 * params.flank() as u64 + 1;
+                bases.truncate(preview.bases(bases.len() as u64, window) as usize);
+            }
+            let mut values = self.compute(&chrom, &bases, options, params)?;
+            if let Some(preview) = &options.preview {
+                let mut kept = vec![f64::NAN; values.len()];
+                for (i, value) in preview.sample(values) {
+                    kept[i as usize] = value;
+                }
+                values = kept;
+            }
+            drop(curvature);
+            if let Some(monitor) = &mut self.monitor {
+                monitor.start_contig(&chrom, bases.len() as u64);
+                monitor.advance(bases.len() as u64, &values);
+                monitor.draw(&mut io::stderr(), &self.warnings)?;
+            }
+            if let Some(fit) = &mut fit {
+                fit.add_record(&bases, &values, params);
+            }
+            if held {
+                // spilled records are stored under their index, as names can repeat
+                let index = sorted.len();
+                let len = bases.len() as u64;
+                let record = match &mut spill {
+                    Some(spill) => {
+                        spill.write_chrom(&index.to_string(), values.into_iter())?;
+                        spill.write_bases(&index.to_string(), &bases)?;
+                        (chrom, Vec::new(), Vec::new())
+                    }
+                    None => (chrom, bases, values),
+                };
+                sorted.push((record, len, index));
+            } else {
+                let values = self.clip(values, options);
+                sink((chrom, bases, values))?;
+            }
+        }
+        if signal::interrupted() {
+            return Ok(());
+        }
+        let model = fit.and_then(|fit| fit.solve());
+        options
+            .sort_contigs
+            .sort(&mut sorted, |((chrom, _, _), len, _)| (chrom, *len));
+        for ((chrom, mut bases, mut values), _, index) in sorted {
+            let _record_span = span(&chrom);
+            if let Some(spill) = &mut spill {
+                values = spill
+                    .read_chrom(&index.to_string())?
+                    .collect::<io::Result<_>>()?;
+                bases = spill.read_bases(&index.to_string())?;
+            }
+            if let Some(model) = &model {
+                model.correct_record(&bases, &mut values, params);
+            }
+            let values = self.clip(values, options);
+            sink((chrom, bases, values))?;
+        }
+        Ok(())
+    }
+
+    /// Clips the values of a record with `--clip`, counting the values clipped.
+    fn clip(&mut self, mut values: Vec<f64>, options: &RunOptions) -> Vec<f64> {
+        if let Some(range) = &options.clip {
+            for value in &mut values {
+                *value = range.apply(*value, &mut self.clip);
+            }
+        }
+        values
+    }
+
+    /// Computes the curvature of one record, reusing an earlier record's values if it is a
+    /// duplicate that `--duplicate-contigs reuse` lets through.
+    fn compute(
+        &mut self,
+        chrom: &str,
+        bases: &[u8],
+        options: &RunOptions,
+        params: &PipelineParams,
+    ) -> io::Result<Vec<f64>> {
+        let digest = (options.duplicate_contigs != DuplicateMode::Off).then(|| {
+            let mut digest = ContigDigest::new();
+            digest.update(bases);
+            digest.finish()
+        });
+        let earlier = digest
+            .as_ref()
+            .and_then(|digest| self.contigs.earlier(digest));
+        if let Some((name, _)) = earlier {
+            self.issues
+                .report(&duplicate_warning(chrom, name, options.duplicate_contigs))?;
+            self.warnings
+                .record(WarningKind::DuplicateContigs, 1, chrom);
+        }
+        let reused = earlier
+            .filter(|_| options.duplicate_contigs == DuplicateMode::Reuse)
+            .map(|(_, values)| values.clone());
+        let before = self.summary;
+        let Self {
+            summary,
+            buffers,
+            memo,
+            ..
+        } = self;
+        let values = self.timings.time(Stage::Curvature, || {
+            if let Some(values) = reused {
+                count_record(summary, bases, &values);
+                Ok(values)
+            } else if options.memoize {
+                memoized_record_track(bases, params, summary, memo)
+            } else {
+                record_track(chrom, bases, params, summary, buffers)
+            }
+        })?;
+        let since = self.summary.since(&before);
+        self.warnings.record_sequence(chrom, &since);
+        for issue in sequence_issues(chrom, ambiguous_runs(bases), &since) {
+            self.issues.report(&issue)?;
+        }
+        if let Some(digest) = digest {
+            let kept = match options.duplicate_contigs {
+                DuplicateMode::Reuse => values.clone(),
+                _ => Vec::new(),
+            };
+            self.contigs.record(digest, chrom, kept);
+        }
+        self.memory
+            .record_buffer("curvature", self.buffers.capacity_bytes());
+        self.memory.record_buffer(
+            "record",
+            bases.len() + values.capacity() * mem::size_of::<f64>(),
+        );
+        Ok(values)
+    }
+}
+
+/// The writing side of an in-memory run.
+///
+/// # Fields
+///
+/// * `outputs`: The outputs of the run.
+/// * `symmetry`: Whether a symmetry track is written.
+/// * `filter`: The expression a position must be true for to be written, if any.
+/// * `params`: The parameters of the run.
+/// * `timings`: The time spent on symmetry scores and writing.
+/// * `completed`: The names of the records written, in order.
+struct Writing<'a> {
+    outputs: Vec<Box<dyn RecordOutput + 'a>>,
+    symmetry: bool,
+    filter: Option<&'a Expr>,
+    params: &'a PipelineParams,
+    timings: Timings,
+    completed: Vec<String>,
+}
+
+impl Writing<'_> {
+    /// Writes a record to every output, computing its symmetry scores first if a symmetry track
+    /// is written or the filter needs them, so that they are timed as their own stage.
+    ///
+    /// # Arguments
+    ///
+    /// * `(chrom, bases, values)` - The record.
+    /// * `tracer` - The trace the spans of the stages are added to, if one is kept.
+    fn write(&mut self, (chrom, bases, values): Record, tracer: Option<&Tracer>) -> io::Result<()> {
+        let span = |name: &str| tracer.map(|tracer| tracer.span(name));
+        let params = self.params;
+        let filtered;
+        let mut record = RecordValues {
+            chrom: &chrom,
+            bases: &bases,
+            values: &values,
+            symmetry: OnceCell::new(),
+            kept: None,
+        };
+        if self.symmetry || self.filter.is_some() {
+            let _span = span(Stage::Symmetry.name());
+            self.timings
+                .time(Stage::Symmetry, || record.symmetry(params));
+        }
+        if let Some(filter) = self.filter {
+            let kept = kept_ranges(filter, record.rows(params));
+            let mut scores = record.symmetry.take().unwrap_or_default();
+            mask_outside(&mut scores, &kept);
+            let mut values = values.clone();
+            mask_outside(&mut values, &kept);
+            filtered = values;
+            record.values = &filtered;
+            record.symmetry = OnceCell::from(scores);
+            record.kept = Some(kept);
+        }
+        let writing = span(Stage::OutputWriting.name());
+        let outputs = &mut self.outputs;
+        self.timings.time(Stage::OutputWriting, || {
+            outputs
+                .iter_mut()
+                .try_for_each(|output| output.write_record(&record, params))
+        })?;
+        drop(writing);
+        drop(record);
+        self.completed.push(chrom);
+        Ok(())
+    }
+}
+
+/// An output of an in-memory run, taking the records one after another.
+trait RecordOutput {
+    /// Writes what the output has of a record.
+    fn write_record(&mut self, record: &RecordValues, params: &PipelineParams) -> io::Result<()>;
+
+    /// Writes whatever is left of the output, moves it to its path and gives the paths written.
+    fn commit(self: Box<Self>) -> io::Result<Vec<PathBuf>>;
+}
+
+/// One record of an in-memory run.
+///
+/// # Fields
+///
+/// * `chrom`: The name of the record.
+/// * `bases`: The bases of the record, as read.
+/// * `values`: The curvature at each position, `NaN` where there is none.
+/// * `symmetry`: The symmetry score at each position, computed when first asked for.
+/// * `kept`: The runs of positions the filter keeps, or `None` if every position is written.
+///   `values` and `symmetry` are already without values outside them.
+struct RecordValues<'a> {
+    chrom: &'a str,
+    bases: &'a [u8],
+    values: &'a [f64],
+    symmetry: OnceCell<Vec<f64>>,
+    kept: Option<Vec<Range<usize>>>,
+}
+
+impl RecordValues<'_> {
+    /// The symmetry score at each position, as the symmetry track has it.
+    fn symmetry(&self, params: &PipelineParams) -> &[f64] {
+        self.symmetry
+            .get_or_init(|| symmetry_values(self.values, params))
+    }
+
+    /// The variables of an expression at each position, in [`VARIABLES`] order: the curvature,
+    /// the symmetry score and the 0-based position.
+    fn rows(&self, params: &PipelineParams) -> impl Iterator<Item = [f64; VARIABLES.len()]> + '_ {
+        let symmetry = self.symmetry(params);
+        iter::zip(self.values, symmetry)
+            .enumerate()
+            .map(|(pos, (&curve, &sym))| [curve, sym, pos as f64])
+    }
+
+    /// Leaves the positions of values computed apart from `values` that the filter drops
+    /// without a value, as `values` already are.
+    fn filter(&self, values: &mut [f64]) {
+        if let Some(kept) = &self.kept {
+            mask_outside(values, kept);
+        }
+    }
+}
+
+/// Sets every value outside the `kept` ranges to `NaN`.
+fn mask_outside(values: &mut [f64], kept: &[Range<usize>]) {
+    let mut start = 0;
+    for range in kept.iter().chain(iter::once(&(values.len()..values.len()))) {
+        values[start..range.start].fill(f64::NAN);
+        start = range.end;
+    }
+}
+
+/// The curvature output of one strand, or of the record as read, with its symmetry track.
+///
+/// # Fields
+///
+/// * `strand`: The strand computed, or `None` for the record as read.
+/// * `curve`: The curvature output and its path.
+/// * `symmetry`: The symmetry track and its path, if one is written.
+/// * `clip`: The range the minus strand is clipped to, as the record as read already is.
+struct StrandTracks<'a> {
+    strand: Option<Strand>,
+    curve: (TrackFile<'a>, PathBuf),
+    symmetry: Option<(TrackFile<'a>, PathBuf)>,
+    clip: Option<Clip>,
+}
+
+impl<'a> StrandTracks<'a> {
+    /// Starts the outputs at `path` and, if given, `symmetry`.
+    fn create(
+        strand: Option<Strand>,
+        path: PathBuf,
+        symmetry: Option<PathBuf>,
+        format: OutputFormat,
+        params: &'a PipelineParams,
+        clip: Option<Clip>,
+    ) -> io::Result<Self> {
+        let symmetry = symmetry
+            .map(|path| Ok::<_, io::Error>((TrackFile::create(&path, format, params)?, path)))
+            .transpose()?;
+        Ok(Self {
+            strand,
+            curve: (TrackFile::create(&path, format, params)?, path),
+            symmetry,
+            clip,
+        })
+    }
+}
+
+impl RecordOutput for StrandTracks<'_> {
+    /// Writes a record's values on the strand, in the coordinates of the record as read.
+    ///
+    /// The minus strand is computed from the reverse complement and its values and scores are
+    /// reversed back onto the record, so position `i` of both outputs covers the same base.
+    fn write_record(&mut self, record: &RecordValues, params: &PipelineParams) -> io::Result<()> {
+        if self.strand != Some(Strand::Minus) {
+            self.curve.0.write_record(record.chrom, record.values)?;
+            if let Some((symmetry, _)) = &mut self.symmetry {
+                symmetry.write_record(record.chrom, record.symmetry(params))?;
+            }
+            return Ok(());
+        }
+        let mut minus = position_values(&reverse_complement(record.bases), params);
+        if let Some(clip) = &self.clip {
+            // the clipped positions are counted once, on the record as read
+            let mut stats = ClipStats::default();
+            for value in &mut minus {
+                *value = clip.apply(*value, &mut stats);
+            }
+        }
+        let mut values = minus.clone();
+        values.reverse();
+        record.filter(&mut values);
+        self.curve.0.write_record(record.chrom, &values)?;
+        if let Some((symmetry, _)) = &mut self.symmetry {
+            let mut scores = symmetry_values(&minus, params);
+            scores.reverse();
+            record.filter(&mut scores);
+            symmetry.write_record(record.chrom, &scores)?;
+        }
+        Ok(())
+    }
+
+    fn commit(self: Box<Self>) -> io::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for (track, path) in iter::once(self.curve).chain(self.symmetry) {
+            track.commit()?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
+
+/// The thresholded copy of the curvature output.
+///
+/// # Fields
+///
+/// * `threshold`: The values above it are 1 and the rest 0.
+/// * `file`: The 0/1 track in the format of the run, or the BED file of the intervals above the
+///   threshold.
+/// * `path`: Where the output is written.
+struct Binarized<'a> {
+    threshold: f64,
+    file: BinarizedFile<'a>,
+    path: PathBuf,
+}
+
+/// The forms of the thresholded output, as [`BinarizeFormat`] names them.
+enum BinarizedFile<'a> {
+    Track(Box<TrackFile<'a>>),
+    Bed(Box<BedIntervals>),
+}
+
+/// The BED intervals of the thresholded output.
+///
+/// # Fields
+///
+/// * `file`: The BED file.
+/// * `fields`: The name and score columns written.
+/// * `index`: The number of intervals written so far.
+/// * `held`: With [`ScoreStat::NegLog10P`], the name, values and intervals of each record so far,
+///   written once every value of the run is known.
+struct BedIntervals {
+    file: AtomicFile,
+    fields: BedFields,
+    index: u64,
+    held: Option<Vec<HeldIntervals>>,
+}
+
+/// The intervals of a record held for their scores, with its name and values.
+type HeldIntervals = (String, Vec<f64>, Vec<Range<usize>>);
+
+impl<'a> Binarized<'a> {
+    /// Starts the output at `path` in the form `binarize_format` gives, with the BED columns of
+    /// `fields`.
+    fn create(
+        path: PathBuf,
+        threshold: f64,
+        binarize_format: BinarizeFormat,
+        fields: BedFields,
+        format: OutputFormat,
+        params: &'a PipelineParams,
+    ) -> io::Result<Self> {
+        let file = match binarize_format {
+            BinarizeFormat::Track => {
+                BinarizedFile::Track(Box::new(TrackFile::create(&path, format, params)?))
+            }
+            BinarizeFormat::Bed => BinarizedFile::Bed(Box::new(BedIntervals {
+                file: AtomicFile::create(&path)?,
+                held: (fields.score == Some(ScoreStat::NegLog10P)).then(Vec::new),
+                fields,
+                index: 0,
+            })),
+        };
+        Ok(Self {
+            threshold,
+            file,
+            path,
+        })
+    }
+}
+
+impl RecordOutput for Binarized<'_> {
+    fn write_record(&mut self, record: &RecordValues, _params: &PipelineParams) -> io::Result<()> {
+        match &mut self.file {
+            BinarizedFile::Track(track) => {
+                let values: Vec<f64> = record
+                    .values
+                    .iter()
+                    .map(|&value| binarize(value, self.threshold))
+                    .collect();
+                track.write_record(record.chrom, &values)
+            }
+            BinarizedFile::Bed(bed) => {
+                let intervals =
+                    above_threshold_intervals(record.values.iter().copied(), self.threshold);
+                if let Some(held) = &mut bed.held {
+                    held.push((record.chrom.to_string(), record.values.to_vec(), intervals));
+                    return Ok(());
+                }
+                write_named_intervals(
+                    &mut bed.file,
+                    record.chrom,
+                    0,
+                    intervals,
+                    record.values,
+                    &bed.fields,
+                    &EmpiricalNull::default(),
+                    &mut bed.index,
+                )
+            }
+        }
+    }
+
+    fn commit(self: Box<Self>) -> io::Result<Vec<PathBuf>> {
+        match self.file {
+            BinarizedFile::Track(track) => track.commit()?,
+            BinarizedFile::Bed(mut bed) => {
+                if let Some(held) = bed.held.take() {
+                    let null = EmpiricalNull::new(
+                        held.iter()
+                            .flat_map(|(_, values, _)| values.iter().copied()),
+                    );
+                    for (chrom, values, intervals) in held {
+                        write_named_intervals(
+                            &mut bed.file,
+                            &chrom,
+                            0,
+                            intervals,
+                            &values,
+                            &bed.fields,
+                            &null,
+                            &mut bed.index,
+                        )?;
+                    }
+                }
+                bed.file.commit()?
+            }
+        }
+        Ok(vec![self.path])
+    }
+}
+
+/// A track computed per position from an expression.
+///
+/// # Fields
+///
+/// * `expr`: The expression over [`VARIABLES`].
+/// * `file`: The track, in the format of the run.
+/// * `path`: Where the track is written.
+struct Derived<'a> {
+    expr: &'a Expr,
+    file: TrackFile<'a>,
+    path: PathBuf,
+}
+
+impl RecordOutput for Derived<'_> {
+    fn write_record(&mut self, record: &RecordValues, params: &PipelineParams) -> io::Result<()> {
+        let mut values: Vec<f64> = record
+            .rows(params)
+            .map(|row| self.expr.eval(&row))
+            .collect();
+        record.filter(&mut values);
+        self.file.write_record(record.chrom, &values)
+    }
+
+    fn commit(self: Box<Self>) -> io::Result<Vec<PathBuf>> {
+        self.file.commit()?;
+        Ok(vec![self.path])
+    }
+}
+
+/// The mean and standard deviation tracks of `--matrix-set`.
+///
+/// # Fields
+///
+/// * `roll_types`: The members of the ensemble.
+/// * `mean`: The track of the mean, in the format of the run.
+/// * `sd`: The track of the standard deviation, in the format of the run.
+/// * `paths`: Where the two tracks are written.
+struct Ensemble<'a> {
+    roll_types: Vec<RollType>,
+    mean: TrackFile<'a>,
+    sd: TrackFile<'a>,
+    paths: Vec<PathBuf>,
+}
+
+impl RecordOutput for Ensemble<'_> {
+    fn write_record(&mut self, record: &RecordValues, params: &PipelineParams) -> io::Result<()> {
+        let (mut mean, mut sd) = ensemble(record.bases, &self.roll_types, params);
+        record.filter(&mut mean);
+        record.filter(&mut sd);
+        self.mean.write_record(record.chrom, &mean)?;
+        self.sd.write_record(record.chrom, &sd)
+    }
+
+    fn commit(self: Box<Self>) -> io::Result<Vec<PathBuf>> {
+        self.mean.commit()?;
+        self.sd.commit()?;
+        Ok(self.paths)
+    }
+}
+
+/// The p-value track of `--background`.
+///
+/// # Fields
+///
+/// * `null`: The background values.
+/// * `file`: The track, in the format of the run.
+/// * `path`: Where the track is written.
+struct PValues<'a> {
+    null: EmpiricalNull,
+    file: TrackFile<'a>,
+    path: PathBuf,
+}
+
+impl RecordOutput for PValues<'_> {
+    fn write_record(&mut self, record: &RecordValues, _: &PipelineParams) -> io::Result<()> {
+        let values: Vec<f64> = neg_log10_p(record.values.iter().copied(), &self.null).collect();
+        self.file.write_record(record.chrom, &values)
+    }
+
+    fn commit(self: Box<Self>) -> io::Result<Vec<PathBuf>> {
+        self.file.commit()?;
+        Ok(vec![self.path])
+    }
+}
+
+/// The divergence track of `--strand-divergence`.
+///
+/// # Fields
+///
+/// * `file`: The track, in the format of the run.
+/// * `path`: Where the track is written.
+struct Divergence<'a> {
+    file: TrackFile<'a>,
+    path: PathBuf,
+}
+
+impl RecordOutput for Divergence<'_> {
+    /// Writes the divergence of each gap-free piece at the positions its curvature is written
+    /// at.
+    fn write_record(&mut self, record: &RecordValues, params: &PipelineParams) -> io::Result<()> {
+        let flank = params.flank() as u64;
+        let mut values = vec![f64::NAN; record.bases.len()];
+        for (start, piece) in pieces(record.bases) {
+            let divergence = strand_divergence(
+                &piece.to_ascii_uppercase(),
+                params.roll_type.clone(),
+                params.step_b,
+                params.step_two,
+                params.step_c,
+                params.curve_scale,
+                params.edge_mode,
+                params.convention,
+            );
+            for (i, value) in divergence.into_iter().enumerate() {
+                values[start + params.anchor.position(i as u64, flank) as usize] = value;
+            }
+        }
+        record.filter(&mut values);
+        self.file.write_record(record.chrom, &values)
+    }
+
+    fn commit(self: Box<Self>) -> io::Result<Vec<PathBuf>> {
+        self.file.commit()?;
+        Ok(vec![self.path])
+    }
+}
+
+/// The BED output of the symmetry axis of each symmetry window.
+///
+/// The axis of a window is searched for over its middle half, each candidate compared over a
+/// quarter of the window either side, so that every candidate's pairs stay inside the window.
+struct SymmetryAxes(AtomicFile);
+
+impl RecordOutput for SymmetryAxes {
+    fn write_record(&mut self, record: &RecordValues, params: &PipelineParams) -> io::Result<()> {
+        let window = params.symmetry_window;
+        let starts =
+            (0..record.values.len().saturating_sub(window - 1)).step_by(params.symmetry_step);
+        let axes = starts.filter_map(|start| {
+            let axis = symmetry_axis(&record.values[start..start + window], window / 4)?;
+            Some((start as u64, axis))
+        });
+        write_axes(&mut self.0, record.chrom, axes, params.float_format)
+    }
+
+    fn commit(self: Box<Self>) -> io::Result<Vec<PathBuf>> {
+        let path = self.0.path().to_path_buf();
+        self.0.commit()?;
+        Ok(vec![path])
+    }
+}
+
+/// The BED output of the soft-masked intervals of each record.
+///
+/// # Fields
+///
+/// * `tracker`: Finds the lowercase runs of each record's bases.
+/// * `file`: The BED file.
+struct MaskedIntervals {
+    tracker: MaskTracker,
+    file: AtomicFile,
+}
+
+impl RecordOutput for MaskedIntervals {
+    fn write_record(&mut self, record: &RecordValues, _: &PipelineParams) -> io::Result<()> {
+        self.tracker.extend(record.bases);
+        write_intervals(&mut self.file, record.chrom, 0, self.tracker.finish())
+    }
+
+    fn commit(self: Box<Self>) -> io::Result<Vec<PathBuf>> {
+        let path = self.file.path().to_path_buf();
+        self.file.commit()?;
+        Ok(vec![path])
+    }
+}
+
+/// The magnitude and phase TSV output.
+///
+/// Windows are those of [`EdgeMode::Drop`] in each gap-free piece, whatever the edge mode of the
+/// run, each reported at the position its curvature value would be.
+struct MagnitudePhase(AtomicFile);
+
+impl MagnitudePhase {
+    /// Starts the output at `path` with its header.
+    fn create(path: &Path, provenance: Option<&Provenance>) -> io::Result<Self> {
+        let mut file = AtomicFile::create(path)?;
+        write_magnitude_phase_header(&mut file, provenance)?;
+        Ok(Self(file))
+    }
+}
+
+impl RecordOutput for MagnitudePhase {
+    fn write_record(&mut self, record: &RecordValues, params: &PipelineParams) -> io::Result<()> {
+        let extent = CurveIter::<iter::Empty<u8>>::rolling_extent(
+            params.step_b,
+            params.step_two,
+            params.convention,
+        );
+        let flank = CurveIter::<iter::Empty<u8>>::flank(extent, params.step_c, EdgeMode::Drop);
+        for (start, piece) in pieces(record.bases) {
+            let rows = magnitude_phase(
+                piece.iter().map(u8::to_ascii_uppercase),
+                params.roll_type.clone(),
+                params.step_b,
+                params.step_two,
+                params.step_c,
+                params.curve_scale,
+                params.convention,
+            );
+            write_magnitude_phase(
+                &mut self.0,
+                record.chrom,
+                start as u64 + params.anchor.position(0, flank as u64),
+                rows,
+                params.float_format,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn commit(self: Box<Self>) -> io::Result<Vec<PathBuf>> {
+        let path = self.0.path().to_path_buf();
+        self.0.commit()?;
+        Ok(vec![path])
+    }
+}
+
+/// The envelope TSV output.
+///
+/// Windows never span a position without a value, so each run of written values, between gaps,
+/// flanks and the positions `--filter` drops, has its own envelope.
+///
+/// # Fields
+///
+/// * `file`: The TSV output.
+/// * `window`: The number of values in each envelope window.
+struct Envelope {
+    file: AtomicFile,
+    window: usize,
+}
+
+impl Envelope {
+    /// Starts the output at `path` with its header.
+    fn create(path: &Path, window: usize, provenance: Option<&Provenance>) -> io::Result<Self> {
+        let mut file = AtomicFile::create(path)?;
+        write_envelope_header(&mut file, provenance)?;
+        Ok(Self { file, window })
+    }
+}
+
+impl RecordOutput for Envelope {
+    fn write_record(&mut self, record: &RecordValues, params: &PipelineParams) -> io::Result<()> {
+        let values = record.values;
+        let mut start = 0;
+        while start < values.len() {
+            if values[start].is_nan() {
+                start += 1;
+                continue;
+            }
+            let end = values[start..]
+                .iter()
+                .position(|value| value.is_nan())
+                .map_or(values.len(), |len| start + len);
+            write_envelope(
+                &mut self.file,
+                record.chrom,
+                (start + self.window / 2) as u64,
+                envelope(values[start..end].iter().copied(), self.window),
+                params.float_format,
+            )?;
+            start = end;
+        }
+        Ok(())
+    }
+
+    fn commit(self: Box<Self>) -> io::Result<Vec<PathBuf>> {
+        let path = self.file.path().to_path_buf();
+        self.file.commit()?;
+        Ok(vec![path])
+    }
+}
+
+/// The Arrow IPC stream of `--arrow`.
+///
+/// # Fields
+///
+/// * `stream`: The stream.
+/// * `path`: Where the stream is written, `-` for stdout.
+struct Arrow {
+    stream: ArrowStream,
+    path: PathBuf,
+}
+
+/// Where an Arrow IPC stream is written.
+enum ArrowStream {
+    File(ArrowWriter<AtomicFile>),
+    Stdout(ArrowWriter<BufWriter<io::Stdout>>),
+}
+
+impl RecordOutput for Arrow {
+    fn write_record(&mut self, record: &RecordValues, _: &PipelineParams) -> io::Result<()> {
+        match &mut self.stream {
+            ArrowStream::File(writer) => writer.write_chrom(record.chrom, 0, record.values),
+            ArrowStream::Stdout(writer) => writer.write_chrom(record.chrom, 0, record.values),
+        }
+    }
+
+    fn commit(self: Box<Self>) -> io::Result<Vec<PathBuf>> {
+        match self.stream {
+            ArrowStream::File(writer) => writer.finish()?.commit()?,
+            ArrowStream::Stdout(writer) => {
+                writer.finish()?;
+            }
+        }
+        Ok(vec![self.path])
+    }
+}
+
+/// The SQLite database of `--sqlite`.
+struct Sqlite(SqliteWriter);
+
+impl RecordOutput for Sqlite {
+    fn write_record(&mut self, record: &RecordValues, _: &PipelineParams) -> io::Result<()> {
+        self.0.write_chrom(record.chrom, 0, record.values)
+    }
+
+    fn commit(self: Box<Self>) -> io::Result<Vec<PathBuf>> {
+        let path = self.0.path().to_path_buf();
+        self.0.finish()?;
+        Ok(vec![path])
+    }
+}
+
+/// The distribution summary of `--stats-json`.
+///
+/// # Fields
+///
+/// * `summary`: The summary of the values written so far.
+/// * `path`: Where the summary is written.
+struct Distribution {
+    summary: DistributionSummary,
+    path: PathBuf,
+}
+
+impl RecordOutput for Distribution {
+    fn write_record(&mut self, record: &RecordValues, _: &PipelineParams) -> io::Result<()> {
+        for &value in record.values {
+            self.summary.add(value);
+        }
+        Ok(())
+    }
+
+    fn commit(self: Box<Self>) -> io::Result<Vec<PathBuf>> {
+        self.summary.write(&self.path)?;
+        Ok(vec![self.path])
+    }
+}
+
+/// The sequence dictionary of the input.
+///
+/// # Fields
+///
+/// * `entries`: The entry of each record so far.
+/// * `path`: Where the dictionary is written.
+/// * `uri`: The `file:` URI of the input, or `None` for standard input.
+struct SequenceDict {
+    entries: Vec<SequenceEntry>,
+    path: PathBuf,
+    uri: Option<String>,
+}
+
+impl SequenceDict {
+    /// Starts the dictionary of `input`, written at `path` once every record is read.
+    fn new(path: PathBuf, input: &Path) -> Self {
+        let uri = (input != Path::new("-"))
+            .then(|| input.canonicalize().ok())
+            .flatten()
+            .map(|input| format!("file:{}", input.display()));
+        Self {
+            entries: Vec::new(),
+            path,
+            uri,
+        }
+    }
+}
+
+impl RecordOutput for SequenceDict {
+    fn write_record(&mut self, record: &RecordValues, _params: &PipelineParams) -> io::Result<()> {
+        let mut digest = SequenceDigest::new();
+        digest.update(record.bases);
+        self.entries.push(digest.finish(record.chrom));
+        Ok(())
+    }
+
+    fn commit(self: Box<Self>) -> io::Result<Vec<PathBuf>> {
+        let mut file = AtomicFile::create(&self.path)?;
+        write_dict(&mut file, &self.entries, self.uri.as_deref())?;
+        file.commit()?;
+        Ok(vec![self.path])
+    }
+}
+
+/// The bend vector TSV output.
+///
+/// Vectors are computed per gap-free piece, so the triplets of a piece never reach into a gap.
+struct BendVectors(AtomicFile);
+
+impl BendVectors {
+    /// Starts the output at `path` with its header.
+    fn create(path: &Path, provenance: Option<&Provenance>) -> io::Result<Self> {
+        let mut file = AtomicFile::create(path)?;
+        write_bend_header(&mut file, provenance)?;
+        Ok(Self(file))
+    }
+}
+
+impl RecordOutput for BendVectors {
+    fn write_record(&mut self, record: &RecordValues, params: &PipelineParams) -> io::Result<()> {
+        for (start, piece) in pieces(record.bases) {
+            let bases = piece.iter().map(u8::to_ascii_uppercase);
+            write_bend_vectors(
+                &mut self.0,
+                record.chrom,
+                start as u64,
+                bend_vectors(bases, params.roll_type.clone()),
+                params.float_format,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn commit(self: Box<Self>) -> io::Result<Vec<PathBuf>> {
+        let path = self.0.path().to_path_buf();
+        self.0.commit()?;
+        Ok(vec![path])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::composition::residual_curvature;
+    use crate::json::Json;
+    use crate::provenance::BuildInfo;
+    use approx::assert_relative_eq;
+    use flate2::read::GzDecoder;
+    use std::fs;
+    use std::io::Read;
+    use std::time::Duration;
+
+    /// The files of a test run: its FASTA input, written to a directory of the test's own, and
+    /// the path of its main output there. The directory is removed with the fixture.
+    struct Fixture {
+        dir: PathBuf,
+        input: PathBuf,
+        output: PathBuf,
+    }
+
+    impl Fixture {
+        /// Writes `fasta` as the input of test `name`, with its output at `out.tsv`.
+        fn new(name: &str, fasta: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("symcurve_test_{}", name));
+            fs::create_dir_all(&dir).unwrap();
+            let input = dir.join("in.fa");
+            fs::write(&input, fasta).unwrap();
+            let output = dir.join("out.tsv");
+            Self { dir, input, output }
+        }
+
+        /// The fixture with its output at `name` instead, in the format of its extension.
+        fn with_output(mut self, name: &str) -> Self {
+            self.output = self.path(name);
+            self
+        }
+
+        /// The path of `name` in the fixture's directory.
+        fn path(&self, name: impl AsRef<Path>) -> PathBuf {
+            self.dir.join(name)
+        }
+
+        /// Runs the input to the output with the default parameters and `options`.
+        fn run(&self, options: &RunOptions) -> io::Result<RunReport> {
+            self.run_with(&PipelineParams::default(), options)
+        }
+
+        /// Runs the input to the output with `params` and `options`.
+        fn run_with(&self, params: &PipelineParams, options: &RunOptions) -> io::Result<RunReport> {
+            let format = OutputFormat::from_path(&self.output);
+            run(
+                &self.input,
+                &self.output,
+                None,
+                format,
+                params,
+                options,
+                true,
+            )
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn tsv_values(text: &str) -> Vec<String> {
+        text.lines()
+            .map(|line| line.rsplit('\t').next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_run_streams() {
+        let fixture = Fixture::new(
+            "run_streams",
+            &format!(">chr1\n{}\n", "ACGTTTAAAGGC".repeat(10)),
+        );
+        let report = fixture.run(&RunOptions::default()).unwrap();
+        assert_eq!(report.outputs, vec![fixture.output.clone()]);
+        assert_eq!(report.summary.bases, 120);
+        assert!(report.memory.buffer("curvature").unwrap() > 0);
+        // the bases at the ends without a window are not warned of
+        assert!(report.summary.values < 120);
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_run_stranded() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC".repeat(3);
+        let fixture = Fixture::new("run_stranded", &format!(">chr1\n{}\n", seq));
+        let params = PipelineParams::default();
+        let options = RunOptions {
+            stranded: true,
+            ..RunOptions::default()
+        };
+        let report = fixture.run_with(&params, &options).unwrap();
+        let plus = fixture.path("out.plus.tsv");
+        let minus = fixture.path("out.minus.tsv");
+        assert_eq!(report.outputs, vec![plus.clone(), minus.clone()]);
+        assert!(!fixture.output.exists());
+        // the plus strand is the unstranded output
+        let mut streamed = Vec::new();
+        crate::pipeline::run_pipeline(
+            format!(">chr1\n{}\n", seq).as_bytes(),
+            &mut streamed,
+            &params,
+        )
+        .unwrap();
+        let plus = fs::read_to_string(&plus).unwrap();
+        assert_eq!(plus, String::from_utf8(streamed).unwrap());
+        // the minus strand is the reverse complement's output, read backwards
+        let mut reversed = Vec::new();
+        let rc = String::from_utf8(reverse_complement(seq.as_bytes())).unwrap();
+        crate::pipeline::run_pipeline(
+            format!(">chr1\n{}\n", rc).as_bytes(),
+            &mut reversed,
+            &params,
+        )
+        .unwrap();
+        let mut expected = tsv_values(&String::from_utf8(reversed).unwrap());
+        expected.reverse();
+        let minus = fs::read_to_string(&minus).unwrap();
+        assert!(minus.starts_with("chr1\t1\t"));
+        assert_eq!(tsv_values(&minus), expected);
+        assert_ne!(tsv_values(&minus), tsv_values(&plus));
+        // regions are read by index, which the stranded outputs do not do
+        let params = PipelineParams {
+            regions: vec!["chr1:1-50".parse().unwrap()],
+            ..PipelineParams::default()
+        };
+        let err = fixture.run_with(&params, &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_run_timings() {
+        let fasta = format!(">chr1\n{}\n", "ACGTTTAAAGGC".repeat(100));
+        let fixture = Fixture::new("run_timings", &fasta);
+        let symmetry = fixture.path("out.symcurve.tsv");
+        let params = PipelineParams::default();
+        let options = RunOptions {
+            timings: true,
+            ..RunOptions::default()
+        };
+        let report = run(
+            &fixture.input,
+            &fixture.output,
+            Some(&symmetry),
+            OutputFormat::Tsv,
+            &params,
+            &options,
+            true,
+        )
+        .unwrap();
+        assert!(report.timings.get(Stage::FastaDecoding) > Duration::ZERO);
+        assert!(report.timings.get(Stage::Curvature) > Duration::ZERO);
+        assert!(report.timings.get(Stage::Symmetry) > Duration::ZERO);
+        assert!(report.timings.get(Stage::OutputWriting) > Duration::ZERO);
+        // the writer's stages are timed on its own thread
+        let queued = fixture.path("queued.tsv");
+        let options = RunOptions {
+            timings: true,
+            max_memory: Some(1 << 20),
+            ..RunOptions::default()
+        };
+        let report = run(
+            &fixture.input,
+            &queued,
+            Some(&fixture.path("queued.symcurve.tsv")),
+            OutputFormat::Tsv,
+            &params,
+            &options,
+            true,
+        )
+        .unwrap();
+        assert!(report.timings.get(Stage::Symmetry) > Duration::ZERO);
+        assert!(report.timings.get(Stage::OutputWriting) > Duration::ZERO);
+        assert_eq!(
+            fs::read(&queued).unwrap(),
+            fs::read(&fixture.output).unwrap()
+        );
+        // the outputs are those of a streamed run
+        let streamed = fixture.path("streamed.tsv");
+        let streamed_symmetry = fixture.path("streamed.symcurve.tsv");
+        run_file_with(
+            &fixture.input,
+            &streamed,
+            Some(&streamed_symmetry),
+            OutputFormat::Tsv,
+            &params,
+            true,
+            &mut FileRun::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read(&fixture.output).unwrap(),
+            fs::read(&streamed).unwrap()
+        );
+        assert_eq!(
+            fs::read(&symmetry).unwrap(),
+            fs::read(&streamed_symmetry).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_run_trace() {
+        let seq = "ACGTTTAAAGGC".repeat(20);
+        let fixture = Fixture::new("run_trace", &format!(">chr1\n{}\n>chr2\nACGT\n", seq))
+            .with_output("out.bedGraph");
+        let trace = fixture.path("trace.folded");
+        let options = RunOptions {
+            trace: Some(trace.clone()),
+            trace_format: TraceFormat::Folded,
+            ..RunOptions::default()
+        };
+        let report = fixture.run(&options).unwrap();
+        assert_eq!(report.outputs, vec![fixture.output.clone(), trace.clone()]);
+        let text = fs::read_to_string(&trace).unwrap();
+        let stacks: Vec<&str> = text
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0)
+            .collect();
+        for stack in [
+            "run;FASTA decoding",
+            "run;chr1;curvature",
+            "run;chr1;output writing",
+            "run;chr2",
+            "run",
+        ] {
+            assert!(
+                stacks.contains(&stack),
+                "{} missing from {:?}",
+                stack,
+                stacks
+            );
+        }
+
+        // a queued run traces the records it reads as well
+        let options = RunOptions {
+            max_memory: Some(1 << 20),
+            ..options
+        };
+        fixture.run(&options).unwrap();
+        let text = fs::read_to_string(&trace).unwrap();
+        assert!(text
+            .lines()
+            .any(|line| line.starts_with("run;chr1;curvature ")));
+    }
+
+    #[test]
+    fn test_run_binarize() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC".repeat(2);
+        let fixture =
+            Fixture::new("run_binarize", &format!(">chr1\n{}\n", seq)).with_output("out.bedGraph");
+        let params = PipelineParams::default();
+        let values = position_values(seq.as_bytes(), &params);
+        let threshold = 0.2;
+        for binarize_format in [BinarizeFormat::Track, BinarizeFormat::Bed] {
+            let options = RunOptions {
+                binarize: Some(threshold),
+                binarize_format,
+                ..RunOptions::default()
+            };
+            let report = fixture.run(&options).unwrap();
+            let path = binarize_format.path(&fixture.output);
+            assert_eq!(report.outputs, vec![fixture.output.clone(), path.clone()]);
+            let text = fs::read_to_string(&path).unwrap();
+            let mut expected = Vec::new();
+            match binarize_format {
+                BinarizeFormat::Track => {
+                    let binary: Vec<f64> = values.iter().map(|&v| binarize(v, threshold)).collect();
+                    write_values_bedgraph(&mut expected, &binary);
+                }
+                BinarizeFormat::Bed => {
+                    let intervals = above_threshold_intervals(values.iter().copied(), threshold);
+                    assert!(!intervals.is_empty());
+                    write_intervals(&mut expected, "chr1", 0, intervals).unwrap();
+                }
+            }
+            assert_eq!(text, String::from_utf8(expected).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_run_bed_fields() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC".repeat(2);
+        let fixture = Fixture::new(
+            "run_bed_fields",
+            &format!(">chr1\n{}\n>chr2\n{}\n", seq, seq),
+        )
+        .with_output("out.bedGraph");
+        let params = PipelineParams::default();
+        let values = position_values(seq.as_bytes(), &params);
+        let threshold = 5.0;
+        let intervals = above_threshold_intervals(values.iter().copied(), threshold);
+        assert!(!intervals.is_empty());
+        for score in [ScoreStat::Max, ScoreStat::NegLog10P] {
+            let options = RunOptions {
+                binarize: Some(threshold),
+                binarize_format: BinarizeFormat::Bed,
+                bed_name: Some("peak_{chrom}_{index}".parse().unwrap()),
+                bed_score: Some(score),
+                ..RunOptions::default()
+            };
+            fixture.run(&options).unwrap();
+            let text = fs::read_to_string(BinarizeFormat::Bed.path(&fixture.output)).unwrap();
+            let fields = BedFields {
+                name: options.bed_name.clone(),
+                score: Some(score),
+                float: params.float_format,
+            };
+            let null = EmpiricalNull::new(values.iter().chain(&values).copied());
+            let mut expected = Vec::new();
+            let mut index = 0;
+            for chrom in ["chr1", "chr2"] {
+                write_named_intervals(
+                    &mut expected,
+                    chrom,
+                    0,
+                    intervals.clone(),
+                    &values,
+                    &fields,
+                    &null,
+                    &mut index,
+                )
+                .unwrap();
+            }
+            assert_eq!(text, String::from_utf8(expected).unwrap());
+            assert!(text.starts_with("chr1\t"));
+            assert!(text.lines().last().unwrap().contains("\tpeak_chr2_"));
+        }
+        let options = RunOptions {
+            bed_score: Some(ScoreStat::Mean),
+            ..RunOptions::default()
+        };
+        let err = fixture.run(&options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    fn write_values_bedgraph(out: &mut Vec<u8>, values: &[f64]) {
+        let track = crate::track::CurveTrack {
+            offset: 0,
+            values: values.to_vec(),
+        };
+        out.extend(
+            track
+                .to_bedgraph("chr1", crate::output::FloatFormat::Shortest)
+                .bytes(),
+        );
+    }
+
+    #[test]
+    fn test_run_memoize() {
+        let unique = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let seq = format!(
+            "{}{}N{}{}",
+            unique,
+            "TTAGGG".repeat(50),
+            unique,
+            "CA".repeat(40)
+        );
+        let fixture = Fixture::new("run_memoize", &format!(">chr1\n{}\n>chr2\nACGT\n", seq));
+        let params = PipelineParams {
+            step_two: Some(4),
+            ..PipelineParams::default()
+        };
+        let options = RunOptions {
+            memoize: true,
+            ..RunOptions::default()
+        };
+        let report = fixture.run_with(&params, &options).unwrap();
+        assert!(report.memo.reused > report.memo.computed);
+        let streamed = fixture.path("streamed.tsv");
+        let summary = run_file_with(
+            &fixture.input,
+            &streamed,
+            None,
+            OutputFormat::Tsv,
+            &params,
+            true,
+            &mut FileRun::default(),
+        )
+        .unwrap();
+        assert_eq!(report.summary, summary);
+        let memoized = fs::read_to_string(&fixture.output).unwrap();
+        let streamed = fs::read_to_string(&streamed).unwrap();
+        assert_eq!(memoized.lines().count(), streamed.lines().count());
+        for (memoized, streamed) in memoized.lines().zip(streamed.lines()) {
+            let (position, value) = memoized.rsplit_once('\t').unwrap();
+            let (expected_position, expected) = streamed.rsplit_once('\t').unwrap();
+            assert_eq!(position, expected_position);
+            let (value, expected): (f64, f64) = (value.parse().unwrap(), expected.parse().unwrap());
+            assert!(
+                (value - expected).abs() < 1e-9 || (value.is_nan() && expected.is_nan()),
+                "{} != {}",
+                memoized,
+                streamed
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_duplicate_contigs() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let fixture = Fixture::new(
+            "run_duplicate_contigs",
+            &format!(">chr1\n{}\n>chr2\nACGTACGT\n>chr1_fix\n{}\n", seq, seq),
+        );
+        let streamed = fixture.path("streamed.tsv");
+        let params = PipelineParams::default();
+        let summary = run_file_with(
+            &fixture.input,
+            &streamed,
+            None,
+            OutputFormat::Tsv,
+            &params,
+            true,
+            &mut FileRun::default(),
+        )
+        .unwrap();
+        for mode in [DuplicateMode::Warn, DuplicateMode::Reuse] {
+            let options = RunOptions {
+                duplicate_contigs: mode,
+                ..RunOptions::default()
+            };
+            let report = fixture.run(&options).unwrap();
+            assert_eq!(report.warnings.count(WarningKind::DuplicateContigs), 1);
+            assert_eq!(report.summary, summary);
+            assert_eq!(
+                fs::read(&fixture.output).unwrap(),
+                fs::read(&streamed).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_run_dict() {
+        let fixture = Fixture::new("run_dict", ">chr1\nacgtNN\n>chr2\nACGTACGTAC\n");
+        let options = RunOptions {
+            dict: true,
+            ..RunOptions::default()
+        };
+        let report = fixture.run(&options).unwrap();
+        let path = fixture.path("out.dict");
+        assert_eq!(report.outputs, vec![fixture.output.clone(), path.clone()]);
+        let dict = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = dict.lines().collect();
+        assert_eq!(lines.len(), 3);
+        let mut digest = SequenceDigest::new();
+        digest.update(b"ACGTNN");
+        let md5 = digest.finish("chr1").md5;
+        assert!(lines[1].starts_with(&format!("@SQ\tSN:chr1\tLN:6\tM5:{}\tUR:file:", md5)));
+        assert!(lines[2].starts_with("@SQ\tSN:chr2\tLN:10\t"));
+    }
+
+    #[test]
+    fn test_run_sort_contigs() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let fixture = Fixture::new(
+            "run_sort_contigs",
+            &format!(
+                ">chr10\n{}\n>chr2\n{}\n>chrX\n{}\n",
+                seq,
+                seq.repeat(3),
+                seq.repeat(2)
+            ),
+        )
+        .with_output("out.bedGraph");
+        let chroms = |sort_contigs: SortContigs| {
+            let options = RunOptions {
+                sort_contigs,
+                ..RunOptions::default()
+            };
+            let report = fixture.run(&options).unwrap();
+            assert_eq!(report.summary.records, 3);
+            let mut chroms: Vec<String> = Vec::new();
+            for line in fs::read_to_string(&fixture.output).unwrap().lines() {
+                let chrom = line.split('\t').next().unwrap();
+                if chroms.last().map(String::as_str) != Some(chrom) {
+                    chroms.push(chrom.to_string());
+                }
+            }
+            chroms
+        };
+        assert_eq!(chroms(SortContigs::Name), ["chr2", "chr10", "chrX"]);
+        assert_eq!(chroms(SortContigs::Length), ["chr2", "chrX", "chr10"]);
+    }
+
+    #[test]
+    fn test_run_derive() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC".repeat(3);
+        let fixture = Fixture::new("run_derive", &format!(">chr1\n{}\n", seq));
+        let options = RunOptions {
+            derive: vec![
+                "double=curve * 2".parse().unwrap(),
+                "at=pos".parse().unwrap(),
+            ],
+            ..RunOptions::default()
+        };
+        let report = fixture.run(&options).unwrap();
+        let double = fixture.path("out.double.tsv");
+        let at = fixture.path("out.at.tsv");
+        assert_eq!(
+            report.outputs,
+            vec![fixture.output.clone(), double.clone(), at.clone()]
+        );
+        let curve = tsv_values(&fs::read_to_string(&fixture.output).unwrap());
+        let double = tsv_values(&fs::read_to_string(&double).unwrap());
+        assert_eq!(curve.len(), double.len());
+        for (curve, double) in curve.iter().zip(&double) {
+            match (curve.parse::<f64>(), double.parse::<f64>()) {
+                (Ok(curve), Ok(double)) if !curve.is_nan() => {
+                    assert!((double - curve * 2.0).abs() < 1e-9)
+                }
+                _ => assert_eq!(curve, double),
+            }
+        }
+        let at = tsv_values(&fs::read_to_string(&at).unwrap());
+        assert_eq!(at.last().unwrap(), &(seq.len() - 1).to_string());
+    }
+
+    #[test]
+    fn test_run_matrix_set() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let fixture = Fixture::new(
+            "run_matrix_set",
+            &format!(">chr1\n{}\n>chr2\n{}NNNN{}\n", seq, seq, seq),
+        );
+        let options = RunOptions {
+            matrix_set: Some("simple,active".parse().unwrap()),
+            ..RunOptions::default()
+        };
+        let params = PipelineParams::default();
+        let report = fixture.run(&options).unwrap();
+        let (mean_path, sd_path) = (fixture.path("out.mean.tsv"), fixture.path("out.sd.tsv"));
+        assert_eq!(
+            report.outputs,
+            vec![fixture.output.clone(), mean_path.clone(), sd_path.clone()]
+        );
+        let read = |path: &Path| -> Vec<f64> {
+            tsv_values(&fs::read_to_string(path).unwrap())
+                .iter()
+                .map(|value| value.parse().unwrap())
+                .collect()
+        };
+        let roll_types = [RollType::Simple, RollType::Active];
+        let mut expected = (Vec::new(), Vec::new());
+        for bases in [seq.to_string(), format!("{}NNNN{}", seq, seq)] {
+            let (mean, sd) = ensemble(bases.as_bytes(), &roll_types, &params);
+            expected.0.extend(mean);
+            expected.1.extend(sd);
+        }
+        let same = |written: Vec<f64>, expected: &[f64]| {
+            assert_eq!(written.len(), expected.len());
+            for (value, expected) in written.iter().zip(expected) {
+                assert!(value == expected || value.is_nan() && expected.is_nan());
+            }
+        };
+        same(read(&mean_path), &expected.0);
+        same(read(&sd_path), &expected.1);
+        assert!(read(&sd_path).iter().any(|&sd| sd > 0.0));
+    }
+
+    #[test]
+    fn test_mask_outside() {
+        let mut values = vec![1.0; 6];
+        mask_outside(&mut values, &[1..2, 3..5]);
+        let kept: Vec<bool> = values.iter().map(|value| !value.is_nan()).collect();
+        assert_eq!(kept, [false, true, false, true, true, false]);
+        let mut values = vec![1.0; 3];
+        mask_outside(&mut values, &[]);
+        assert!(values.iter().all(|value| value.is_nan()));
+    }
+
+    #[test]
+    fn test_run_filter() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC".repeat(3);
+        let fixture = Fixture::new("run_filter", &format!(">chr1\n{}\n", seq));
+        let values = |filter: Option<&str>| {
+            let options = RunOptions {
+                filter: filter.map(|filter| filter.parse().unwrap()),
+                derive: vec!["pos=pos".parse().unwrap()],
+                ..RunOptions::default()
+            };
+            fixture.run(&options).unwrap();
+            let parse = |text: String| -> Vec<f64> {
+                tsv_values(&text)
+                    .iter()
+                    .map(|value| value.parse().unwrap())
+                    .collect()
+            };
+            (
+                parse(fs::read_to_string(&fixture.output).unwrap()),
+                parse(fs::read_to_string(fixture.path("out.pos.tsv")).unwrap()),
+            )
+        };
+        let (all, _) = values(None);
+        let (kept, pos) = values(Some("curve > 5"));
+        assert_eq!(all.len(), kept.len());
+        let expected: Vec<bool> = all.iter().map(|&value| value > 5.0).collect();
+        assert!(expected.contains(&true) && expected.contains(&false));
+        let written: Vec<bool> = kept.iter().map(|value| !value.is_nan()).collect();
+        assert_eq!(written, expected);
+        let written: Vec<bool> = pos.iter().map(|value| !value.is_nan()).collect();
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn test_run_preview() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC".repeat(3);
+        let fixture = Fixture::new("run_preview", &format!(">chr1\n{}\n", seq));
+        let params = PipelineParams::default();
+        let preview = |preview: Preview| {
+            let options = RunOptions {
+                preview: Some(preview),
+                ..RunOptions::default()
+            };
+            let report = fixture.run(&options).unwrap();
+            let path = fixture.path("out.preview.tsv");
+            assert_eq!(report.outputs, vec![path.clone()]);
+            tsv_values(&fs::read_to_string(&path).unwrap())
+                .iter()
+                .map(|value| value.parse().unwrap())
+                .collect::<Vec<f64>>()
+        };
+        let all = position_values(seq.as_bytes(), &params);
+        let every = preview(Preview::Every(4));
+        assert_eq!(every.len(), all.len());
+        for (i, (value, expected)) in every.iter().zip(&all).enumerate() {
+            if i % 4 == 0 {
+                assert!(value == expected || value.is_nan() && expected.is_nan());
+            } else {
+                assert!(value.is_nan());
+            }
+        }
+        let prefix = preview(Preview::Prefix(40));
+        assert_eq!(prefix.len(), 40 + 2 * params.flank());
+        let written: Vec<f64> = prefix.iter().copied().filter(|v| !v.is_nan()).collect();
+        let expected: Vec<f64> = all[..40].iter().copied().filter(|v| !v.is_nan()).collect();
+        assert!(!written.is_empty());
+        assert_eq!(written, expected);
+        assert!(!fixture.output.exists());
+    }
+
+    #[test]
+    fn test_run_clip() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC".repeat(3);
+        let fixture = Fixture::new("run_clip", &format!(">chr1\n{}\n", seq));
+        let values = |clip: Option<Clip>| {
+            let options = RunOptions {
+                clip,
+                ..RunOptions::default()
+            };
+            let report = fixture.run(&options).unwrap();
+            let values: Vec<f64> = tsv_values(&fs::read_to_string(&fixture.output).unwrap())
+                .iter()
+                .map(|value| value.parse().unwrap())
+                .collect();
+            (values, report)
+        };
+        let (all, _) = values(None);
+        let (clipped, report) = values(Some("4:6".parse().unwrap()));
+        let below = all.iter().filter(|&&value| value < 4.0).count() as u64;
+        let above = all.iter().filter(|&&value| value > 6.0).count() as u64;
+        assert!(below > 0 && above > 0);
+        assert_eq!(report.clip, ClipStats { below, above });
+        assert_eq!(
+            report.warnings.count(WarningKind::ClippedValues),
+            below + above
+        );
+        for (value, clipped) in all.iter().zip(&clipped) {
+            if value.is_nan() {
+                assert!(clipped.is_nan());
+            } else {
+                assert_eq!(*clipped, value.clamp(4.0, 6.0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_strand_divergence() {
+        let seq = format!(
+            "{}NN{}",
+            "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC".repeat(2),
+            "ggcactagcacctatctaccctgaatcccaacattttgactttttggga"
+        );
+        let fixture = Fixture::new("run_strand_divergence", &format!(">chr1\n{}\n", seq));
+        let options = RunOptions {
+            strand_divergence: true,
+            ..RunOptions::default()
+        };
+        let params = PipelineParams::default();
+        let report = fixture.run(&options).unwrap();
+        let path = fixture.path("out.divergence.tsv");
+        assert_eq!(report.outputs, vec![fixture.output.clone(), path.clone()]);
+        let divergence: Vec<f64> = tsv_values(&fs::read_to_string(&path).unwrap())
+            .iter()
+            .map(|value| value.parse().unwrap())
+            .collect();
+        let plus = position_values(seq.as_bytes(), &params);
+        let mut minus = position_values(&reverse_complement(seq.as_bytes()), &params);
+        minus.reverse();
+        assert_eq!(divergence.len(), seq.len());
+        for ((value, plus), minus) in divergence.iter().zip(&plus).zip(&minus) {
+            if plus.is_nan() {
+                assert!(value.is_nan());
+            } else {
+                approx::assert_abs_diff_eq!(*value, (plus - minus).abs(), epsilon = 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_run_background() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC".repeat(2);
+        let fixture = Fixture::new("run_background", &format!(">chr1\n{}\n", seq));
+        let background = fixture.path("background.txt");
+        let lines: String = (0..100)
+            .map(|i| format!("{}\n", f64::from(i) / 10.0))
+            .collect();
+        fs::write(&background, lines).unwrap();
+        let options = RunOptions {
+            background: Some(background.clone()),
+            ..RunOptions::default()
+        };
+        let report = fixture.run(&options).unwrap();
+        let path = fixture.path("out.pval.tsv");
+        assert_eq!(report.outputs, vec![fixture.output.clone(), path.clone()]);
+        let parse = |path: &Path| -> Vec<f64> {
+            tsv_values(&fs::read_to_string(path).unwrap())
+                .iter()
+                .map(|value| value.parse().unwrap())
+                .collect()
+        };
+        let curve = parse(&fixture.output);
+        let pvalues = parse(&path);
+        assert_eq!(pvalues.len(), curve.len());
+        let null = read_background(fs::read_to_string(&background).unwrap().as_bytes()).unwrap();
+        let expected: Vec<f64> = neg_log10_p(curve.iter().copied(), &null).collect();
+        for (value, expected) in pvalues.iter().zip(&expected) {
+            if expected.is_nan() {
+                assert!(value.is_nan());
+            } else {
+                assert_relative_eq!(value, expected, epsilon = 1e-3);
+            }
+        }
+        fs::write(&background, "# empty\n").unwrap();
+        let err = fixture.run(&options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_run_stats_json() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC".repeat(2);
+        let fixture = Fixture::new(
+            "run_stats_json",
+            &format!(">chr1\n{}\n>chr2\n{}\n", seq, seq),
+        );
+        let path = fixture.path("stats.json");
+        let options = RunOptions {
+            stats_json: Some(path.clone()),
+            ..RunOptions::default()
+        };
+        let report = fixture.run(&options).unwrap();
+        assert_eq!(report.outputs, vec![fixture.output.clone(), path.clone()]);
+        let json: Json = fs::read_to_string(&path).unwrap().parse().unwrap();
+        assert_eq!(
+            json.get("count").and_then(Json::as_f64),
+            Some(report.summary.values as f64)
+        );
+        let values: Vec<f64> = tsv_values(&fs::read_to_string(&fixture.output).unwrap())
+            .iter()
+            .map(|value| value.parse::<f64>().unwrap())
+            .filter(|value| !value.is_nan())
+            .collect();
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        assert_relative_eq!(
+            json.get("max").and_then(Json::as_f64).unwrap(),
+            max,
+            epsilon = 1e-3
+        );
+    }
+
+    #[test]
+    fn test_run_spill_dir() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        // a repeated name, which the spill file must keep apart
+        let fixture = Fixture::new(
+            "run_spill_dir",
+            &format!(
+                ">chr2\n{}\n>chr1\n{}\n>chr2\n{}\n",
+                seq.repeat(2),
+                seq.repeat(3),
+                seq
+            ),
+        );
+        let spill_dir = fixture.path("spill");
+        fs::create_dir_all(&spill_dir).unwrap();
+        let params = PipelineParams::default();
+        let output = |name: &str, format: OutputFormat, options: RunOptions| {
+            let output = fixture.path(name);
+            let symmetry = fixture.path(format!("sym.{}", name));
+            run(
+                &fixture.input,
+                &output,
+                Some(&symmetry),
+                format,
+                &params,
+                &options,
+                true,
+            )
+            .unwrap();
+            (fs::read(&output).unwrap(), fs::read(&symmetry).unwrap())
+        };
+        for (name, format) in [
+            ("bedGraph", OutputFormat::BedGraph),
+            ("tsv", OutputFormat::Tsv),
+        ] {
+            // streamed through the spill file, and held by the in-memory run
+            let spilled = RunOptions {
+                sort_contigs: SortContigs::Name,
+                spill_dir: Some(spill_dir.clone()),
+                ..RunOptions::default()
+            };
+            let held = RunOptions {
+                sort_contigs: SortContigs::Name,
+                ..RunOptions::default()
+            };
+            assert_eq!(
+                output(&format!("spilled.{}", name), format, spilled),
+                output(&format!("held.{}", name), format, held),
+                "{}",
+                name
+            );
+        }
+        // records held for a correction are spilled with their bases
+        let spilled = RunOptions {
+            composition_correction: Some(Covariates::Gc),
+            spill_dir: Some(spill_dir.clone()),
+            ..RunOptions::default()
+        };
+        let held = RunOptions {
+            composition_correction: Some(Covariates::Gc),
+            ..RunOptions::default()
+        };
+        assert_eq!(
+            output(
+                "corrected.spilled.bedGraph",
+                OutputFormat::BedGraph,
+                spilled
+            ),
+            output("corrected.held.bedGraph", OutputFormat::BedGraph, held)
+        );
+        // the spill file is removed once the run is done
+        assert_eq!(fs::read_dir(&spill_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_run_max_memory() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let fasta: String = (1..=3)
+            .map(|i| format!(">chr{}\n{}\n", i, seq.repeat(1000 * i)))
+            .collect();
+        let fixture = Fixture::new("run_max_memory", &fasta);
+        let params = PipelineParams::default();
+        let output = |name: &str, options: &RunOptions| {
+            let output = fixture.path(name);
+            let report = run(
+                &fixture.input,
+                &output,
+                Some(&fixture.path(format!("sym.{}", name))),
+                OutputFormat::BedGraph,
+                &params,
+                options,
+                true,
+            );
+            report.map(|report| (report, fs::read(&output).unwrap()))
+        };
+        let (_, streamed) = output("streamed.bedGraph", &RunOptions::default()).unwrap();
+        // far less than a record's values wait to be written at once
+        let options = RunOptions {
+            max_memory: Some(64 << 10),
+            ..RunOptions::default()
+        };
+        let (report, queued) = output("queued.bedGraph", &options).unwrap();
+        assert_eq!(report.summary.records, 3);
+        assert_eq!(queued, streamed);
+        let peak = report.memory.buffer("queue").unwrap();
+        assert!(peak > 0 && peak <= 64 << 10, "{}", peak);
+        assert_eq!(
+            fs::read(fixture.path("sym.queued.bedGraph")).unwrap(),
+            fs::read(fixture.path("sym.streamed.bedGraph")).unwrap()
+        );
+        // a cap too small for a single row
+        let options = RunOptions {
+            max_memory: Some(1),
+            ..RunOptions::default()
+        };
+        let err = output("tiny.bedGraph", &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        // records read whole are more than the cap bounds
+        let options = RunOptions {
+            max_memory: Some(1 << 20),
+            stranded: true,
+            ..RunOptions::default()
+        };
+        let err = output("stranded.bedGraph", &options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("--stranded"), "{}", err);
+    }
+
+    #[test]
+    fn test_run_warnings() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let fixture = Fixture::new(
+            "run_warnings",
+            &format!(">chr1\n{}RYNNN{}\n>chrUn\nACGT\n", seq, seq),
+        );
+        let warnings = |options: &RunOptions| {
+            let report = fixture.run(options).unwrap();
+            assert_eq!(report.warnings.count(WarningKind::SkippedBases), 3);
+            report.warnings.to_string()
+        };
+        let streamed = warnings(&RunOptions::default());
+        assert!(streamed.contains("2 ambiguous bases (treated as gaps), first in chr1\n"));
+        assert!(streamed.contains("1 sequences too short for a value, first in chrUn\n"));
+        let options = RunOptions {
+            timings: true,
+            ..RunOptions::default()
+        };
+        assert_eq!(warnings(&options), streamed);
+    }
+
+    #[test]
+    fn test_run_on_malformed() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let fixture = Fixture::new(
+            "run_on_malformed",
+            &format!(">chr1\n{}\n>chr2\nACG*T\n>chr3\n{}\n", seq, seq),
+        );
+        let run_with = |options: &RunOptions| fixture.run(options);
+        for options in [
+            RunOptions::default(),
+            RunOptions {
+                timings: true,
+                ..RunOptions::default()
+            },
+        ] {
+            let err = run_with(&options).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(
+                err.to_string(),
+                "malformed FASTA record 2 (chr2): stray character '*' at base 4"
+            );
+            assert!(!fixture.output.exists());
+        }
+        let options = RunOptions {
+            on_malformed: OnMalformed::Skip,
+            ..RunOptions::default()
+        };
+        let report = run_with(&options).unwrap();
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].label(), "record 2 (chr2)");
+        assert_eq!(report.warnings.count(WarningKind::MalformedRecords), 1);
+        assert_eq!(report.summary.records, 2);
+        let text = fs::read_to_string(&fixture.output).unwrap();
+        let chroms: Vec<&str> = text
+            .lines()
+            .map(|line| line.split('\t').next().unwrap())
+            .collect();
+        assert_eq!(chroms.len(), 2 * seq.len());
+        assert!(chroms
+            .iter()
+            .all(|&chrom| chrom == "chr1" || chrom == "chr3"));
+    }
+
+    #[test]
+    fn test_run_issues() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let fixture = Fixture::new(
+            "run_issues",
+            &format!(">chr1\n{}RY{}\n>chrUn\nACGT\n", seq, seq),
+        );
+        let path = fixture.path("out.issues.jsonl");
+        let issues = |options: &RunOptions| {
+            let result = fixture.run(options);
+            let text = fs::read_to_string(&path).unwrap();
+            let records: Vec<Json> = text.lines().map(|line| line.parse().unwrap()).collect();
+            (result, records)
+        };
+        let streamed = RunOptions {
+            issues: true,
+            ..RunOptions::default()
+        };
+        let (report, records) = issues(&streamed);
+        assert_eq!(
+            report.unwrap().outputs,
+            vec![fixture.output.clone(), path.clone()]
+        );
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[0].to_string(),
+            format!(
+                "{{\"severity\":\"warning\",\"kind\":\"ambiguous_bases\",\"contig\":\"chr1\",\
+                 \"start\":{},\"end\":{},\"count\":2,\
+                 \"message\":\"2 ambiguous bases (treated as gaps)\"}}",
+                seq.len(),
+                seq.len() + 2
+            )
+        );
+        assert_eq!(
+            records[1].get("kind").and_then(Json::as_str),
+            Some("short_contigs")
+        );
+        let in_memory = RunOptions {
+            timings: true,
+            ..streamed.clone()
+        };
+        assert_eq!(issues(&in_memory).1, records);
+        fs::write(&fixture.input, format!(">chr1\n{}\n>chr2\nAC*GT\n", seq)).unwrap();
+        let (result, records) = issues(&streamed);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0].get("severity").and_then(Json::as_str),
+            Some("error")
+        );
+        assert_eq!(
+            records[0].get("kind").and_then(Json::as_str),
+            Some("run_failed")
+        );
+    }
+
+    #[test]
+    fn test_run_manifest() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let fixture = Fixture::new("run_manifest", &format!(">chr1\n{}\n>chr2\nAC*GT\n", seq));
+        let path = fixture.path("out.tsv.manifest.json");
+        let options = RunOptions {
+            manifest: true,
+            issues: true,
+            on_malformed: OnMalformed::Skip,
+            ..RunOptions::default()
+        };
+        let params = PipelineParams::default();
+        let report = fixture.run(&options).unwrap();
+        let issues = fixture.path("out.issues.jsonl");
+        assert_eq!(
+            report.outputs,
+            vec![fixture.output.clone(), issues.clone(), path.clone()]
+        );
+        let json: Json = fs::read_to_string(&path).unwrap().parse().unwrap();
+        let manifest = Manifest::from_json(&json).unwrap();
+        let paths: Vec<&Path> = manifest.outputs.iter().map(|o| o.path.as_path()).collect();
+        assert_eq!(paths, vec![fixture.output.as_path(), issues.as_path()]);
+        assert_eq!(
+            manifest.outputs[0].digests.bytes,
+            fs::metadata(&fixture.output).unwrap().len()
+        );
+        assert_eq!(manifest.skipped.len(), 1);
+        assert_eq!(manifest.skipped[0].name, "chr2");
+        assert_eq!(manifest.build, Some(BuildInfo::current()));
+        let stdout = run(
+            &fixture.input,
+            Path::new("-"),
+            None,
+            OutputFormat::Tsv,
+            &params,
+            &options,
+            true,
+        );
+        assert_eq!(stdout.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_run_symmetry_axis() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC".repeat(3);
+        let fixture = Fixture::new("run_symmetry_axis", &format!(">chr1\n{}\n", seq));
+        let options = RunOptions {
+            symmetry_axis: true,
+            ..RunOptions::default()
+        };
+        let params = PipelineParams {
+            symmetry_window: 21,
+            symmetry_step: 10,
+            ..PipelineParams::default()
+        };
+        let report = fixture.run_with(&params, &options).unwrap();
+        let path = fixture.path("out.axis.bed");
+        assert_eq!(report.outputs, vec![fixture.output.clone(), path.clone()]);
+        let values = position_values(seq.as_bytes(), &params);
+        let mut expected = Vec::new();
+        let axes = (0..values.len() - 20).step_by(10).filter_map(|start| {
+            symmetry_axis(&values[start..start + 21], 5).map(|axis| (start as u64, axis))
+        });
+        write_axes(&mut expected, "chr1", axes, params.float_format).unwrap();
+        assert!(!expected.is_empty());
+        assert_eq!(fs::read(&path).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_run_masked_bed() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let fixture = Fixture::new(
+            "run_masked_bed",
+            &format!(">chr1\nacgt{}\nAC\ngtNNnn\n>chr2\n{}\n", seq, seq),
+        );
+        let path = fixture.path("out.masked.bed");
+        let options = RunOptions {
+            masked_bed: Some(path.clone()),
+            ..RunOptions::default()
+        };
+        let report = fixture.run(&options).unwrap();
+        assert_eq!(report.outputs, vec![fixture.output.clone(), path.clone()]);
+        let end = 4 + seq.len() + 2;
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!(
+                "chr1\t0\t4\nchr1\t{}\t{}\nchr1\t{}\t{}\n",
+                end,
+                end + 2,
+                end + 4,
+                end + 6
+            )
+        );
+        // the intervals are noted on the way past, leaving the curvature as a plain run's
+        let masked = fs::read_to_string(&fixture.output).unwrap();
+        fixture.run(&RunOptions::default()).unwrap();
+        assert_eq!(fs::read_to_string(&fixture.output).unwrap(), masked);
+    }
+
+    #[test]
+    fn test_run_magnitude_phase() {
+        let seq = format!(
+            "{}NN{}",
+            "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC",
+            "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC".repeat(2)
+        );
+        let fixture = Fixture::new("run_magnitude_phase", &format!(">chr1\n{}\n", seq));
+        let path = fixture.path("mp.tsv");
+        let options = RunOptions {
+            magnitude_phase: Some(path.clone()),
+            ..RunOptions::default()
+        };
+        let report = fixture.run(&options).unwrap();
+        assert_eq!(report.outputs, vec![fixture.output.clone(), path.clone()]);
+        let text = fs::read_to_string(&path).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some("chrom\tpos\tmagnitude\tphase"));
+        // each magnitude is the curvature written at the same position
+        let curve = fs::read_to_string(&fixture.output).unwrap();
+        let curve: Vec<&str> = curve.lines().collect();
+        let mut rows = 0;
+        for line in lines {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let pos: usize = fields[1].parse().unwrap();
+            assert_eq!(curve[pos - 1].rsplit('\t').next(), Some(fields[2]));
+            rows += 1;
+        }
+        assert_eq!(rows as u64, report.summary.values);
+    }
+
+    #[test]
+    fn test_run_composition_correction() {
+        let seq =
+            "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATCCCAACATTTTGACTTTTTGGGAGGGCACTAGCACC";
+        let fixture = Fixture::new(
+            "run_composition_correction",
+            &format!(">chr1\n{}\n>chr2\n{}\n", seq, &seq[10..]),
+        );
+        let params = PipelineParams::default();
+        let options = RunOptions {
+            composition_correction: Some(Covariates::Dinucleotide),
+            ..RunOptions::default()
+        };
+        fixture.run(&options).unwrap();
+        let written: Vec<f64> = tsv_values(&fs::read_to_string(&fixture.output).unwrap())
+            .iter()
+            .filter_map(|value| value.parse::<f64>().ok().filter(|v| !v.is_nan()))
+            .collect();
+        let (expected, _) = residual_curvature(
+            &[seq.as_bytes(), &seq.as_bytes()[10..]],
+            &params,
+            Covariates::Dinucleotide,
+        );
+        let expected = expected.concat();
+        assert_eq!(written.len(), expected.len());
+        for (written, expected) in written.iter().zip(&expected) {
+            assert_relative_eq!(written, expected, epsilon = 1e-3);
+        }
+        let options = RunOptions {
+            stranded: true,
+            ..options
+        };
+        let err = fixture.run(&options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_run_envelope() {
+        let seq = format!(
+            "{}NN{}",
+            "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC".repeat(2),
+            "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC"
+        );
+        let fixture = Fixture::new("run_envelope", &format!(">chr1\n{}\n", seq));
+        let path = fixture.path("envelope.tsv");
+        let options = RunOptions {
+            envelope: Some(path.clone()),
+            envelope_window: 5,
+            ..RunOptions::default()
+        };
+        let params = PipelineParams::default();
+        let report = fixture.run(&options).unwrap();
+        assert_eq!(report.outputs, vec![fixture.output.clone(), path.clone()]);
+        let curve: Vec<f64> = tsv_values(&fs::read_to_string(&fixture.output).unwrap())
+            .iter()
+            .map(|value| value.parse().unwrap())
+            .collect();
+        let text = fs::read_to_string(&path).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some("chrom\tpos\tlower\tupper"));
+        let mut rows = 0;
+        for line in lines {
+            let fields: Vec<&str> = line.split('\t').collect();
+            // each window is the five values centered on its position
+            let pos: usize = fields[1].parse().unwrap();
+            let window = &curve[pos - 3..pos + 2];
+            assert!(window.iter().all(|value| !value.is_nan()));
+            let lower = window.iter().copied().fold(f64::INFINITY, f64::min);
+            let upper = window.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            assert_eq!(fields[2], params.float_format.format(lower));
+            assert_eq!(fields[3], params.float_format.format(upper));
+            rows += 1;
+        }
+        // two pieces, each with four values fewer windows than values
+        assert_eq!(rows as u64, report.summary.values - 8);
+    }
+
+    #[test]
+    fn test_output_kind() {
+        let output = Path::new("dir/out.bw");
+        let symmetry = Path::new("dir/out.symcurve.bw");
+        let options = RunOptions {
+            stranded: true,
+            dict: true,
+            envelope: Some(PathBuf::from("env.tsv")),
+            ..RunOptions::default()
+        };
+        let kind = |path: &str| options.output_kind(Path::new(path), output, Some(symmetry));
+        assert_eq!(kind("dir/out.bw"), "track");
+        assert_eq!(kind("dir/out.minus.bw"), "track");
+        assert_eq!(kind("dir/out.symcurve.plus.bw"), "symmetry");
+        assert_eq!(kind("dir/out.dict"), "dict");
+        assert_eq!(kind("env.tsv"), "envelope");
+        assert_eq!(kind("elsewhere.txt"), "output");
+        let options = RunOptions {
+            preview: Some(Preview::Every(10)),
+            ..RunOptions::default()
+        };
+        let kind = |path: &str| options.output_kind(Path::new(path), output, Some(symmetry));
+        assert_eq!(kind("dir/out.preview.bw"), "track");
+        assert_eq!(kind("dir/out.symcurve.preview.bw"), "symmetry");
+    }
+
+    #[test]
+    fn test_run_compression() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let fixture = Fixture::new(
+            "run_compression",
+            &format!(">chr1\n{}\n>chr2\n{}\n", seq, seq),
+        );
+        let run_with = |compression, options: &RunOptions| {
+            let params = PipelineParams {
+                compression,
+                ..PipelineParams::default()
+            };
+            fixture.run_with(&params, options).unwrap()
+        };
+        run_with(Compression::None, &RunOptions::default());
+        let expected = fs::read_to_string(&fixture.output).unwrap();
+        let streaming = RunOptions::default();
+        let in_memory = RunOptions {
+            timings: true,
+            ..RunOptions::default()
+        };
+        let gz = fixture.path("out.tsv.gz");
+        for options in [&streaming, &in_memory] {
+            let report = run_with(Compression::Gzip, options);
+            assert_eq!(report.outputs, vec![gz.clone()]);
+            let mut text = String::new();
+            GzDecoder::new(File::open(&gz).unwrap())
+                .read_to_string(&mut text)
+                .unwrap();
+            assert_eq!(text, expected);
+        }
+        let report = run_with(Compression::Zstd, &streaming);
+        let zst = fixture.path("out.tsv.zst");
+        assert_eq!(report.outputs, vec![zst.clone()]);
+        assert_eq!(&fs::read(&zst).unwrap()[..4], &[0x28, 0xB5, 0x2F, 0xFD]);
+    }
+
+    #[test]
+    fn test_run_arrow() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let fixture = Fixture::new("run_arrow", &format!(">chr1\n{}\n>chr2\n{}\n", seq, seq));
+        let path = fixture.path("out.arrow");
+        let options = RunOptions {
+            arrow: Some(path.clone()),
+            ..RunOptions::default()
+        };
+        let params = PipelineParams::default();
+        let report = fixture.run(&options).unwrap();
+        assert_eq!(report.outputs, vec![fixture.output.clone(), path.clone()]);
+        let mut expected = ArrowWriter::new(Vec::new()).unwrap();
+        let values = position_values(seq.as_bytes(), &params);
+        expected.write_chrom("chr1", 0, &values).unwrap();
+        expected.write_chrom("chr2", 0, &values).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), expected.finish().unwrap());
+        let err = run(
+            &fixture.input,
+            Path::new("-"),
+            None,
+            OutputFormat::Tsv,
+            &params,
+            &RunOptions {
+                arrow: Some(PathBuf::from("-")),
+                ..RunOptions::default()
+            },
+            true,
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_run_sqlite() {
+        let seq = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let fixture = Fixture::new("run_sqlite", &format!(">chr1\n{}\n>chr2\n{}\n", seq, seq));
+        let path = fixture.path("out.sqlite");
+        let options = RunOptions {
+            sqlite: Some(path.clone()),
+            ..RunOptions::default()
+        };
+        let report = fixture.run(&options).unwrap();
+        assert_eq!(report.outputs, vec![fixture.output.clone(), path.clone()]);
+        assert!(fs::read(&path).unwrap().starts_with(b"SQLite format 3\0"));
+        // the values spilled next to the database are removed with it built
+        let names: Vec<_> = fs::read_dir(&fixture.dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names.len(), 3);
+    }
+
+    #[test]
+    fn test_run_bend_vectors() {
+        let fixture = Fixture::new("run_bend_vectors", ">chr1\nACGTNNacgta\n>chr2\nAC\n");
+        let bends = fixture.path("bends.tsv");
+        let options = RunOptions {
+            bend_vectors: Some(bends.clone()),
+            ..RunOptions::default()
+        };
+        let report = fixture.run(&options).unwrap();
+        assert_eq!(report.outputs, vec![fixture.output.clone(), bends.clone()]);
+        assert!(report.memory.buffer("record").unwrap() >= 11 + 11 * 8);
+        let text = fs::read_to_string(&bends).unwrap();
+        let rows: Vec<(&str, &str)> = text
+            .lines()
+            .map(|line| {
+                let mut fields = line.split('\t');
+                (fields.next().unwrap(), fields.next().unwrap())
+            })
+            .collect();
+        // one vector per triplet of each piece, at its middle base; none across the gap
+        assert_eq!(
+            rows,
+            vec![
+                ("chrom", "pos"),
+                ("chr1", "2"),
+                ("chr1", "3"),
+                ("chr1", "8"),
+                ("chr1", "9"),
+                ("chr1", "10"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! # Self-test module
+//! Embedded benchmark sequences and expected results, checked by `symcurve --self-test` so a build
+//! on a new machine (or with new compiler flags) can be verified to reproduce known curvature
+//! before it is trusted with real data.
+//!
+//! Two kinds of check are embedded:
+//!
+//! * **Reference vectors** are exact curvature values with the default windows, worked through
+//!   by hand in the tables of the `curve::iters` tests. They catch any numerical drift.
+//! * **Literature checks** are the classic bent-DNA benchmarks. A-tracts phased with the helical
+//!   repeat (every 10 bp, as in the `(CA₆CG)ₙ`-style constructs of Koo, Wu & Crothers, 1986) give
+//!   strong macroscopic curvature, the same A-tracts out of phase (every 15 bp) give much less,
+//!   and homopolymer poly(dA) is straight. Published curvature magnitudes are on each method's
+//!   own scale, so these are checked as the qualitative results they establish rather than as
+//!   absolute numbers.
+
+use crate::curve::iters::{CurveIter, EdgeMode, MeanConvention};
+use crate::curve::matrix::RollType;
+use std::fmt;
+
+/// The default windows the embedded expectations were computed with.
+const STEP_B: usize = 5;
+const STEP_C: usize = 15;
+const CURVE_SCALE: f64 = 0.33335;
+
+/// The sequence from the `curve::iters` test tables.
+const REFERENCE_SEQ: &[u8] = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+
+/// Curvature of `REFERENCE_SEQ` with the simple roll matrix and default windows.
+const REFERENCE_CURVES: [f64; 8] = [
+    6.3674, 5.9168, 5.4776, 5.0218, 4.4147, 3.7726, 3.3483, 3.1042,
+];
+
+/// The result of one embedded check.
+///
+/// # Fields
+///
+/// * `name`: Short name of the check.
+/// * `passed`: Whether the pipeline reproduced the expected result.
+/// * `detail`: What was compared, with the observed values.
+#[derive(Clone, Debug)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String,
+}
+
+/// The results of all embedded checks.
+#[derive(Clone, Debug)]
+pub struct SelfTestReport {
+    pub checks: Vec<CheckResult>,
+}
+
+impl SelfTestReport {
+    /// Whether every check passed.
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for check in &self.checks {
+            let status = if check.passed { "ok" } else { "FAILED" };
+            writeln!(f, "{:<22} {:<6} {}", check.name, status, check.detail)?;
+        }
+        let passed = self.checks.iter().filter(|check| check.passed).count();
+        writeln!(f, "{} of {} checks passed", passed, self.checks.len())
+    }
+}
+
+/// Computes curvature with the default windows and the simple roll matrix.
+fn curvature(seq: &[u8]) -> Vec<f64> {
+    CurveIter::new(
+        seq.iter().cloned(),
+        RollType::Simple,
+        STEP_B,
+        STEP_C,
+        CURVE_SCALE,
+        EdgeMode::Drop,
+        MeanConvention::Legacy,
+    )
+    .collect()
+}
+
+/// Mean curvature of a sequence.
+fn mean_curvature(seq: &[u8]) -> f64 {
+    let curves = curvature(seq);
+    curves.iter().sum::<f64>() / curves.len() as f64
+}
+
+/// A 10 bp unit with a 6 bp A-tract.
+const A_TRACT: &[u8] = b"GGCAAAAAAC";
+
+/// The same A-tract unit padded out to 15 bp, putting successive A-tracts out of phase.
+const A_TRACT_UNPHASED: &[u8] = b"GGCAAAAAACGCGCG";
+
+/// Checks the reference vector within a tolerance of `1e-4`.
+fn check_reference() -> CheckResult {
+    let curves = curvature(REFERENCE_SEQ);
+    let max_diff = curves
+        .iter()
+        .zip(REFERENCE_CURVES.iter())
+        .map(|(observed, expected)| (observed - expected).abs())
+        .fold(0.0, f64::max);
+    CheckResult {
+        name: "reference-50bp",
+        passed: curves.len() == REFERENCE_CURVES.len() && max_diff < 1e-4,
+        detail: format!(
+            "{} values, max abs difference {:.2e}",
+            curves.len(),
+            max_diff
+        ),
+    }
+}
+
+/// Checks that phased A-tracts are much more curved than unphased ones.
+fn check_phased_a_tracts() -> CheckResult {
+    let phased = mean_curvature(&A_TRACT.repeat(10));
+    let unphased = mean_curvature(&A_TRACT_UNPHASED.repeat(7));
+    CheckResult {
+        name: "phased-a-tracts",
+        passed: phased > 3.0 * unphased,
+        detail: format!(
+            "mean curvature phased {:.4} vs unphased {:.4} (expect > 3x)",
+            phased, unphased
+        ),
+    }
+}
+
+/// Checks that a homopolymer is essentially straight.
+fn check_homopolymer() -> CheckResult {
+    let poly_a = mean_curvature(&[b'A'; 100]);
+    CheckResult {
+        name: "homopolymer-straight",
+        passed: poly_a < 0.1,
+        detail: format!("mean curvature of poly(dA) {:.4} (expect < 0.1)", poly_a),
+    }
+}
+
+/// Runs every embedded check.
+pub fn run_self_test() -> SelfTestReport {
+    SelfTestReport {
+        checks: vec![
+            check_reference(),
+            check_phased_a_tracts(),
+            check_homopolymer(),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_test_passes() {
+        let report = run_self_test();
+        assert!(report.passed(), "{}", report);
+        assert_eq!(report.checks.len(), 3);
+        assert!(report.to_string().ends_with("3 of 3 checks passed\n"));
+    }
+
+    #[test]
+    fn test_report_failure() {
+        let report = SelfTestReport {
+            checks: vec![CheckResult {
+                name: "broken",
+                passed: false,
+                detail: "nope".to_string(),
+            }],
+        };
+        assert!(!report.passed());
+        assert!(report.to_string().contains("FAILED"));
+    }
+}
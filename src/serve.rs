@@ -0,0 +1,733 @@
+//! # Serve module
+//! The `symcurve serve` subcommand, a small HTTP server that recomputes curvature on demand for
+//! the region a genome browser asks for, with parameters overridden per request. Pointing a
+//! browser track at it makes parameter exploration interactive: changing `curve-step` in the URL
+//! redraws the track without a genome-wide run.
+//!
+//! Endpoints, all `GET`:
+//!
+//! * `/sequences`: the sequence names and lengths, tab-separated.
+//! * `/curvature?region=chr1:10001-20000`: bedGraph of the region, one line per base with a value.
+//!   The query may override `preset`, `curve-step`, `curve-step-one`, `curve-step-two`,
+//!   `curve-scale`, `roll-type` (`simple` or `active`), `edge-mode`, `mean-convention`, `anchor`
+//!   and `float-format`, with the same meanings as the command-line flags. Parameters not in the
+//!   query are those given to `symcurve serve`, and those set explicitly win over the preset.
+//!
+//! The FASTA file is read at random through its `.fai` index, built in memory at startup if there
+//! is none. The bases around the region are read too, so values near its ends have their full
+//! windows, as in a whole-genome run. Responses allow any origin, so browser-based viewers such as igv.js can
+//! fetch them. There is no TLS or authentication: serve on localhost or a trusted network.
+//! Requests with headers longer than [`MAX_HEADER_BYTES`] are refused, and so are connections
+//! beyond the limit of concurrent ones.
+//!
+//! Browsers ask for the same region again and again as the view is panned and zoomed, so computed
+//! responses are kept in a least-recently-used cache keyed by the region and a hash of the
+//! parameters, and a repeated request is answered without reading or computing anything.
+
+use crate::cli::Preset;
+use crate::curve::iters::{EdgeMode, MeanConvention};
+use crate::curve::matrix::BuiltinRoll;
+use crate::fasta::index::FastaIndex;
+use crate::output::Anchor;
+use crate::pipeline::{position_values, PipelineParams};
+use crate::view::Region;
+use clap::ValueEnum;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How long reading a request may take before the connection is dropped.
+pub const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The longest request line and headers read, in bytes, before the request is refused.
+pub const MAX_HEADER_BYTES: u64 = 16 * 1024;
+
+/// The settings of the server.
+///
+/// # Fields
+///
+/// * `input`: The FASTA file served.
+/// * `listen`: The address and port listened on.
+/// * `max_region`: The longest region computed for one request, in bases.
+/// * `cache_size`: The number of computed responses kept for repeated requests.
+/// * `max_connections`: The most connections handled at once; more are refused.
+/// * `curve`: The curvature parameters of requests that do not override them.
+#[derive(Clone, Debug)]
+pub struct ServeParams {
+    pub input: PathBuf,
+    pub listen: String,
+    pub max_region: u64,
+    pub cache_size: usize,
+    pub max_connections: usize,
+    pub curve: PipelineParams,
+}
+
+/// An HTTP response.
+///
+/// # Fields
+///
+/// * `status`: The status code and reason, e.g. `200 OK`.
+/// * `body`: The plain-text body.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    pub status: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    /// A `200 OK` response.
+    fn ok(body: String) -> Self {
+        Self {
+            status: "200 OK",
+            body,
+        }
+    }
+
+    /// A `400 Bad Request` response.
+    fn bad_request(message: String) -> Self {
+        Self {
+            status: "400 Bad Request",
+            body: message + "\n",
+        }
+    }
+
+    /// A response refusing the request before it is read through.
+    fn refused(status: &'static str, message: &str) -> Self {
+        Self {
+            status,
+            body: format!("{}\n", message),
+        }
+    }
+
+    /// Writes the response with its headers.
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(
+            writer,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\n\
+             Access-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            self.body.len(),
+            self.body
+        )?;
+        writer.flush()
+    }
+}
+
+/// Decodes `%XX` escapes and `+` in a query component.
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Splits a request target into its path and decoded query parameters.
+pub fn parse_target(target: &str) -> (String, Vec<(String, String)>) {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let params = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect();
+    (percent_decode(path), params)
+}
+
+/// Applies the parameter overrides of a query to the parameters of the server. A preset is
+/// applied first, so the steps given explicitly win over it, as on the command line.
+///
+/// # Errors
+///
+/// Returns a message naming the first parameter that is unknown or has an invalid value.
+pub fn apply_overrides(
+    params: &mut PipelineParams,
+    query: &[(String, String)],
+) -> Result<(), String> {
+    if let Some((key, value)) = query.iter().rev().find(|(key, _)| key == "preset") {
+        let preset =
+            Preset::from_str(value, true).map_err(|_| format!("invalid {} '{}'", key, value))?;
+        for (id, value) in preset.values() {
+            let value = usize::from(value);
+            match id {
+                "curve_step" => params.step_c = value,
+                "curve_step_one" => params.step_b = value - 1,
+                "curve_step_two" => params.step_two = Some(value),
+                // the symmetry window and linker size play no part in the curvature
+                _ => {}
+            }
+        }
+    }
+    for (key, value) in query {
+        let invalid = || format!("invalid {} '{}'", key, value);
+        let step = || match value.parse::<usize>() {
+            Ok(step) if step >= 1 => Ok(step),
+            _ => Err(invalid()),
+        };
+        match key.as_str() {
+            "region" | "preset" => {}
+            "curve-step" => params.step_c = step()?,
+            // the rolling mean half-window is one less than the command-line step
+            "curve-step-one" => params.step_b = step()? - 1,
+            "curve-step-two" => params.step_two = Some(step()?),
+            "curve-scale" => {
+                params.curve_scale = value
+                    .parse()
+                    .ok()
+                    .filter(|v: &f64| v.is_finite() && *v > 0.0)
+                    .ok_or_else(invalid)?
+            }
+            "roll-type" => {
+                params.roll_type = BuiltinRoll::from_str(value, true)
+                    .map_err(|_| invalid())?
+                    .into()
+            }
+            "edge-mode" => {
+                params.edge_mode = EdgeMode::from_str(value, true).map_err(|_| invalid())?
+            }
+            "mean-convention" => {
+                params.convention = MeanConvention::from_str(value, true).map_err(|_| invalid())?
+            }
+            "anchor" => params.anchor = Anchor::from_str(value, true).map_err(|_| invalid())?,
+            "float-format" => params.float_format = value.parse().map_err(|_| invalid())?,
+            _ => return Err(format!("unknown parameter '{}'", key)),
+        }
+    }
+    Ok(())
+}
+
+/// What a cached response was computed for: the sequence, start and end of the region, and a
+/// hash of the parameters.
+type CacheKey = (String, u64, u64, u64);
+
+/// A least-recently-used cache of computed responses.
+///
+/// # Fields
+///
+/// * `capacity`: The most responses kept. A cache of capacity 0 keeps nothing.
+/// * `entries`: The responses, least recently used first.
+/// * `hits`: The number of lookups answered from the cache.
+#[derive(Debug, Default)]
+pub struct ResponseCache {
+    capacity: usize,
+    entries: VecDeque<(CacheKey, Arc<String>)>,
+    hits: u64,
+}
+
+impl ResponseCache {
+    /// Constructor for `ResponseCache`.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    /// Looks a response up, marking it as the most recently used.
+    fn get(&mut self, key: &CacheKey) -> Option<Arc<String>> {
+        let i = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(i)?;
+        let body = Arc::clone(&entry.1);
+        self.entries.push_back(entry);
+        self.hits += 1;
+        Some(body)
+    }
+
+    /// Adds a response, dropping the least recently used one if the cache is full.
+    fn insert(&mut self, key: CacheKey, body: Arc<String>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.entries.retain(|(k, _)| *k != key);
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((key, body));
+    }
+
+    /// The number of responses held.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no response is held.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The number of lookups answered from the cache so far.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+}
+
+/// A hash of everything in the parameters, so that responses computed with any difference in
+/// them are cached apart.
+fn params_hash(params: &PipelineParams) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    format!("{:?}", params).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The server state shared by the connection threads.
+///
+/// # Fields
+///
+/// * `input`: The FASTA file.
+/// * `index`: Its index.
+/// * `max_region`: The longest region computed for one request.
+/// * `params`: The parameters requests override.
+/// * `cache`: Responses computed for earlier requests.
+pub struct Server {
+    input: PathBuf,
+    index: FastaIndex,
+    max_region: u64,
+    params: PipelineParams,
+    cache: Mutex<ResponseCache>,
+}
+
+impl Server {
+    /// Loads the index of a FASTA file and sets up a server for it with the default parameters,
+    /// without a response cache.
+    pub fn new(input: &Path, max_region: u64) -> io::Result<Self> {
+        Ok(Self {
+            input: input.to_path_buf(),
+            index: FastaIndex::load(input)?,
+            max_region,
+            params: PipelineParams::default(),
+            cache: Mutex::new(ResponseCache::new(0)),
+        })
+    }
+
+    /// Computes with `params` wherever a request does not override them.
+    pub fn with_params(mut self, params: PipelineParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Keeps up to `capacity` computed responses for repeated requests.
+    pub fn with_cache_size(mut self, capacity: usize) -> Self {
+        self.cache = Mutex::new(ResponseCache::new(capacity));
+        self
+    }
+
+    /// The response cache, for reporting on it.
+    pub fn cache(&self) -> &Mutex<ResponseCache> {
+        &self.cache
+    }
+
+    /// Answers a request target, e.g. `/curvature?region=chr1:1-1000`.
+    pub fn respond(&self, target: &str) -> Response {
+        let (path, query) = parse_target(target);
+        match path.as_str() {
+            "/sequences" => {
+                let mut body = String::new();
+                for record in &self.index.records {
+                    let _ = writeln!(body, "{}\t{}", record.name, record.length);
+                }
+                Response::ok(body)
+            }
+            "/curvature" => match self.curvature(&query) {
+                Ok(body) => Response::ok(body.to_string()),
+                Err(message) => Response::bad_request(message),
+            },
+            _ => Response {
+                status: "404 Not Found",
+                body: "endpoints: /sequences, /curvature?region=CHROM:START-END\n".to_string(),
+            },
+        }
+    }
+
+    /// Computes the bedGraph of the region of a query, or takes it from the cache.
+    fn curvature(&self, query: &[(String, String)]) -> Result<Arc<String>, String> {
+        let region: Region = query
+            .iter()
+            .find(|(key, _)| key == "region")
+            .ok_or("missing region, e.g. region=chr1:10001-20000")?
+            .1
+            .parse()?;
+        let mut params = self.params.clone();
+        apply_overrides(&mut params, query)?;
+        let record = self
+            .index
+            .get(&region.chrom)
+            .ok_or_else(|| format!("unknown sequence {}", region.chrom))?;
+        let end = region.end.unwrap_or(record.length).min(record.length);
+        if region.start >= end {
+            return Err(format!("region {} is empty", region.chrom));
+        }
+        if end - region.start > self.max_region {
+            return Err(format!(
+                "region is longer than the limit of {} bases",
+                self.max_region
+            ));
+        }
+        let key = (
+            region.chrom.clone(),
+            region.start,
+            end,
+            params_hash(&params),
+        );
+        // the cache is not locked while computing, so other requests are answered meanwhile
+        if let Some(body) = self.cache.lock().unwrap().get(&key) {
+            return Ok(body);
+        }
+        // read enough around the region for every value in it to have its full window
+        // the full windows, as they are dropped, even where the request shrinks them at the ends
+        let full = PipelineParams {
+            edge_mode: EdgeMode::Drop,
+            ..params.clone()
+        };
+        let pad = 2 * full.flank() as u64;
+        let read_start = region.start.saturating_sub(pad);
+        let mut file = File::open(&self.input).map_err(|e| e.to_string())?;
+        let bases = FastaIndex::fetch(&mut file, record, read_start, end + pad)
+            .map_err(|e| e.to_string())?;
+        let values = position_values(&bases, &params);
+        let mut body = String::new();
+        for pos in region.start..end {
+            let value = values[(pos - read_start) as usize];
+            if !value.is_nan() {
+                let _ = writeln!(
+                    body,
+                    "{}\t{}\t{}\t{}",
+                    region.chrom,
+                    pos,
+                    pos + 1,
+                    params.float_format.format(value)
+                );
+            }
+        }
+        let body = Arc::new(body);
+        self.cache.lock().unwrap().insert(key, Arc::clone(&body));
+        Ok(body)
+    }
+
+    /// Handles one connection: reads the request line and headers, up to [`MAX_HEADER_BYTES`],
+    /// and answers `GET` requests.
+    fn handle(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?.take(MAX_HEADER_BYTES));
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut header = String::new();
+        let mut complete = false;
+        while reader.read_line(&mut header)? > 0 {
+            if header.trim_end().is_empty() && header.ends_with('\n') {
+                complete = true;
+                break;
+            }
+            header.clear();
+        }
+        let mut parts = request_line.split_whitespace();
+        let response = match (parts.next(), parts.next()) {
+            _ if !complete && reader.get_ref().limit() == 0 => Response::refused(
+                "431 Request Header Fields Too Large",
+                &format!("headers are longer than {} bytes", MAX_HEADER_BYTES),
+            ),
+            (Some("GET"), Some(target)) => self.respond(target),
+            _ => Response::refused("405 Method Not Allowed", "only GET is supported"),
+        };
+        let mut stream = stream;
+        response.write_to(&mut stream)
+    }
+}
+
+/// Runs `symcurve serve` until the process is stopped.
+///
+/// # Errors
+///
+/// Returns an error if the FASTA file cannot be indexed or the address cannot be listened on.
+pub fn serve(params: &ServeParams) -> io::Result<()> {
+    let server = Arc::new(
+        Server::new(&params.input, params.max_region)?
+            .with_params(params.curve.clone())
+            .with_cache_size(params.cache_size),
+    );
+    let connections = Arc::new(AtomicUsize::new(0));
+    let listener = TcpListener::bind(&params.listen)?;
+    eprintln!(
+        "serving {} sequences of {} on http://{}",
+        server.index.records.len(),
+        params.input.display(),
+        listener.local_addr()?
+    );
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        if connections.fetch_add(1, Ordering::SeqCst) >= params.max_connections {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            let _ = Response::refused("503 Service Unavailable", "too many connections")
+                .write_to(&mut stream);
+            continue;
+        }
+        let server = Arc::clone(&server);
+        let connections = Arc::clone(&connections);
+        thread::spawn(move || {
+            if let Err(e) = server.handle(stream) {
+                eprintln!("warning: request failed: {}", e);
+            }
+            connections.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::matrix::RollType;
+    use crate::output::FloatFormat;
+    use approx::assert_relative_eq;
+    use std::io::Read;
+
+    const SEQ: &str = "CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+
+    /// Writes the test genome to a FASTA file and serves it.
+    fn server(name: &str) -> (PathBuf, Server) {
+        let path = std::env::temp_dir().join(format!("symcurve_test_serve_{}.fa", name));
+        let seq = SEQ.repeat(4);
+        let lines: Vec<&str> = seq
+            .as_bytes()
+            .chunks(60)
+            .map(|line| std::str::from_utf8(line).unwrap())
+            .collect();
+        std::fs::write(&path, format!(">chr1\n{}\n>chr2\nACGT\n", lines.join("\n"))).unwrap();
+        let server = Server::new(&path, 1000).unwrap();
+        (path, server)
+    }
+
+    #[test]
+    fn test_parse_target() {
+        let (path, query) = parse_target("/curvature?region=chr1%3A1-100&curve-step=20&flag");
+        assert_eq!(path, "/curvature");
+        assert_eq!(
+            query,
+            vec![
+                ("region".to_string(), "chr1:1-100".to_string()),
+                ("curve-step".to_string(), "20".to_string()),
+                ("flag".to_string(), String::new()),
+            ]
+        );
+        assert_eq!(percent_decode("a+b%2"), "a b%2");
+    }
+
+    #[test]
+    fn test_apply_overrides() {
+        let mut params = PipelineParams::default();
+        let query = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        apply_overrides(
+            &mut params,
+            &query(&[
+                ("curve-step", "20"),
+                ("curve-step-one", "8"),
+                ("roll-type", "active"),
+                ("edge-mode", "shrink"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!((params.step_c, params.step_b), (20, 7));
+        assert!(matches!(params.roll_type, RollType::Active));
+        assert_eq!(params.edge_mode, EdgeMode::Shrink);
+        // a preset sets the steps the query does not give
+        apply_overrides(
+            &mut params,
+            &query(&[
+                ("curve-step-two", "6"),
+                ("preset", "promoter"),
+                ("float-format", "fixed:2"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(
+            (params.step_c, params.step_b, params.step_two),
+            (10, 4, Some(6))
+        );
+        assert_eq!(params.float_format, FloatFormat::Fixed(2));
+        assert_eq!(
+            apply_overrides(&mut params, &query(&[("preset", "huge")])),
+            Err("invalid preset 'huge'".to_string())
+        );
+        assert_eq!(
+            apply_overrides(&mut params, &query(&[("curve-step", "0")])),
+            Err("invalid curve-step '0'".to_string())
+        );
+        assert_eq!(
+            apply_overrides(&mut params, &query(&[("step", "1")])),
+            Err("unknown parameter 'step'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_curvature() {
+        let (path, server) = server("curvature");
+        let response = server.respond("/sequences");
+        assert_eq!(response.body, "chr1\t200\nchr2\t4\n");
+        // values inside the region match a run over the whole sequence
+        let whole = position_values(SEQ.repeat(4).as_bytes(), &PipelineParams::default());
+        let response = server.respond("/curvature?region=chr1:71-80");
+        assert_eq!(response.status, "200 OK");
+        let lines: Vec<&str> = response.body.lines().collect();
+        assert_eq!(lines.len(), 10);
+        let fields: Vec<&str> = lines[0].split('\t').collect();
+        assert_eq!(fields[..3], ["chr1", "70", "71"]);
+        assert_relative_eq!(fields[3].parse::<f64>().unwrap(), whole[70], epsilon = 1e-9);
+        // the ends of the sequence have no values
+        let response = server.respond("/curvature?region=chr1:1-5");
+        assert!(response.body.is_empty());
+        let response = server.respond("/curvature?region=chrX:1-5");
+        assert_eq!(response.status, "400 Bad Request");
+        let response = server.respond("/curvature?region=chr1");
+        assert_eq!(response.status, "200 OK");
+        assert_eq!(server.respond("/").status, "404 Not Found");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_curvature_params() {
+        // requests start from the parameters of the server, as the command line gives them
+        let base = PipelineParams {
+            step_two: Some(4),
+            ..Default::default()
+        };
+        let (path, server) = server("params");
+        let server = server.with_params(base.clone());
+        let params = PipelineParams { step_b: 7, ..base };
+        let whole = position_values(SEQ.repeat(4).as_bytes(), &params);
+        for (region, range) in [
+            ("chr1:23", 22..23),
+            ("chr1:23-40", 22..40),
+            ("chr1:101-110", 100..110),
+        ] {
+            let response =
+                server.respond(&format!("/curvature?region={}&curve-step-one=8", region));
+            assert_eq!(response.status, "200 OK");
+            let expected: Vec<usize> = range.filter(|&pos| !whole[pos].is_nan()).collect();
+            let lines: Vec<&str> = response.body.lines().collect();
+            assert_eq!(lines.len(), expected.len());
+            for (line, pos) in lines.iter().zip(expected) {
+                let fields: Vec<&str> = line.split('\t').collect();
+                assert_eq!(fields[1], pos.to_string());
+                assert_relative_eq!(
+                    fields[3].parse::<f64>().unwrap(),
+                    whole[pos],
+                    epsilon = 1e-9
+                );
+            }
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_curvature_cache() {
+        let (path, server) = server("cache");
+        let server = server.with_cache_size(2);
+        let first = server.respond("/curvature?region=chr1:71-80");
+        // the same region and parameters are answered from the cache
+        assert_eq!(server.respond("/curvature?region=chr1:71-80"), first);
+        assert_eq!(server.cache().lock().unwrap().hits(), 1);
+        // other parameters are computed apart
+        let other = server.respond("/curvature?region=chr1:71-80&curve-step=10");
+        assert_ne!(other, first);
+        assert_eq!(server.cache().lock().unwrap().hits(), 1);
+        assert_eq!(server.cache().lock().unwrap().len(), 2);
+        // errors are not cached
+        server.respond("/curvature?region=chrX:1-5");
+        assert_eq!(server.cache().lock().unwrap().len(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_response_cache_lru() {
+        let key = |start| ("chr1".to_string(), start, start + 10, 0);
+        let body = |text: &str| Arc::new(text.to_string());
+        let mut cache = ResponseCache::new(2);
+        cache.insert(key(0), body("a"));
+        cache.insert(key(10), body("b"));
+        assert_eq!(cache.get(&key(0)).as_deref().map(String::as_str), Some("a"));
+        // the least recently used response goes first
+        cache.insert(key(20), body("c"));
+        assert!(cache.get(&key(10)).is_none());
+        assert!(cache.get(&key(0)).is_some());
+        assert_eq!((cache.len(), cache.hits()), (2, 2));
+        let mut cache = ResponseCache::new(0);
+        cache.insert(key(0), body("a"));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_serve_request() {
+        let (path, server) = server("request");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            server.handle(stream).unwrap();
+        });
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /sequences HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        handle.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Access-Control-Allow-Origin: *\r\n"));
+        assert!(response.ends_with("\r\n\r\nchr1\t200\nchr2\t4\n"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_serve_long_headers() {
+        let (path, server) = server("headers");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            server.handle(stream).unwrap();
+        });
+        let mut stream = TcpStream::connect(addr).unwrap();
+        // headers that fill the limit without ending
+        let mut request = b"GET /sequences HTTP/1.1\r\nX-Pad: ".to_vec();
+        request.resize(MAX_HEADER_BYTES as usize, b'x');
+        stream.write_all(&request).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        handle.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
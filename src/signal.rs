@@ -0,0 +1,139 @@
+//! # Signal module
+//! Interrupt handling, so that a run stopped with Ctrl-C (or a scheduler's SIGTERM) can finish
+//! the record it is on, finalize its output files, and record how far it got, instead of leaving
+//! corrupt partial files behind.
+//!
+//! The handler only sets a flag; the pipeline polls [`interrupted`] between records and chunks
+//! and winds down from there.
+//!
+//! Programs embedding the library, such as a GUI or a server, stop one computation rather than
+//! the whole process: they pass a [`CancelToken`] in the
+//! [`PipelineParams`](crate::pipeline::PipelineParams) of a run and cancel it from another
+//! thread.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+mod sys {
+    use std::os::raw::c_int;
+
+    pub const SIGINT: c_int = 2;
+    pub const SIGTERM: c_int = 15;
+
+    extern "C" {
+        pub fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn handle_signal(_signum: std::os::raw::c_int) {
+    // only async-signal-safe work is allowed in here, which an atomic store is
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Installs handlers for SIGINT and SIGTERM that request a graceful stop.
+///
+/// On platforms other than unix this does nothing and runs can only be killed outright.
+pub fn install_interrupt_handler() {
+    #[cfg(unix)]
+    // SAFETY: `handle_signal` only performs an atomic store, which is async-signal-safe.
+    unsafe {
+        sys::signal(sys::SIGINT, handle_signal);
+        sys::signal(sys::SIGTERM, handle_signal);
+    }
+}
+
+/// Whether a graceful stop has been requested.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Requests a graceful stop, as if an interrupt signal had been received.
+pub fn request_interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// A flag that asks a running computation to stop, shared by all its clones.
+///
+/// The pipeline checks the token between records and every few thousand values, so a long
+/// chromosome stops within a fraction of a second of [`CancelToken::cancel`]. The run then
+/// returns an `io::ErrorKind::Interrupted` error: whatever it wrote is incomplete and should be
+/// discarded, as `run_file` does by never putting its outputs in place.
+///
+/// ```
+/// use symcurve::signal::CancelToken;
+/// use symcurve::{run_pipeline, PipelineParams};
+///
+/// let params = PipelineParams::default();
+/// let token = params.cancel.clone();
+/// token.cancel();
+/// let err = run_pipeline(&b">chr1\nACGTACGTACGTACGTACGT\n"[..], Vec::new(), &params);
+/// assert_eq!(err.unwrap_err().kind(), std::io::ErrorKind::Interrupted);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Constructor for `CancelToken`, not yet cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks every computation holding a clone of the token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fails if the token has been cancelled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::ErrorKind::Interrupted` error once the token is cancelled.
+    pub fn check(&self) -> io::Result<()> {
+        if self.is_cancelled() {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "the computation was cancelled",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interrupt_flag() {
+        install_interrupt_handler();
+        assert!(!interrupted());
+        request_interrupt();
+        assert!(interrupted());
+        INTERRUPTED.store(false, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_cancel_token() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+        assert!(clone.check().is_ok());
+        token.cancel();
+        assert!(clone.is_cancelled());
+        assert_eq!(
+            clone.check().unwrap_err().kind(),
+            io::ErrorKind::Interrupted
+        );
+        assert!(!CancelToken::new().is_cancelled());
+    }
+}
@@ -0,0 +1,280 @@
+//! # Stats module
+//! Instrumentation gathered over a run and reported at the end of it, for users tuning
+//! parameters and resources on shared machines.
+
+pub mod streaming;
+pub mod usage;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::time::{Duration, Instant};
+
+/// Memory usage collected over a run.
+///
+/// Per-stage buffer sizes are recorded as the pipeline runs, keeping the largest size seen for
+/// each stage. Peak resident set size is read from the operating system when the report is made.
+///
+/// # Fields
+///
+/// * `buffers`: The largest buffer size seen for each stage, in bytes.
+#[derive(Debug, Default)]
+pub struct MemStats {
+    buffers: BTreeMap<&'static str, usize>,
+}
+
+impl MemStats {
+    /// Constructor for `MemStats`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the size of a stage buffer, keeping the maximum seen so far for that stage.
+    pub fn record_buffer(&mut self, stage: &'static str, bytes: usize) {
+        let entry = self.buffers.entry(stage).or_insert(0);
+        *entry = (*entry).max(bytes);
+    }
+
+    /// The largest buffer size recorded for a stage, in bytes.
+    pub fn buffer(&self, stage: &str) -> Option<usize> {
+        self.buffers.get(stage).copied()
+    }
+}
+
+impl fmt::Display for MemStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match peak_rss() {
+            Some(bytes) => writeln!(f, "peak RSS: {}", format_bytes(bytes))?,
+            None => writeln!(f, "peak RSS: unavailable")?,
+        }
+        for (stage, bytes) in &self.buffers {
+            writeln!(f, "{} buffer: {}", stage, format_bytes(*bytes))?;
+        }
+        Ok(())
+    }
+}
+
+/// The peak resident set size of this process in bytes.
+///
+/// Read from the `VmHWM` line of `/proc/self/status`, so this returns `None` on platforms
+/// without procfs.
+pub fn peak_rss() -> Option<usize> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    parse_vm_hwm(&status)
+}
+
+/// Helper to `peak_rss()` that pulls the `VmHWM` value out of a `/proc/<pid>/status` file.
+fn parse_vm_hwm(status: &str) -> Option<usize> {
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: usize = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Formats a byte count with a binary unit suffix.
+pub(crate) fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Counts of values changed by `--clip`.
+///
+/// # Fields
+///
+/// * `below`: The number of positions raised to the minimum.
+/// * `above`: The number of positions lowered to the maximum.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClipStats {
+    pub below: u64,
+    pub above: u64,
+}
+
+impl ClipStats {
+    /// The number of positions clipped at either end.
+    pub fn total(&self) -> u64 {
+        self.below + self.above
+    }
+}
+
+impl fmt::Display for ClipStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "clipped positions: {} ({} below, {} above)",
+            self.total(),
+            self.below,
+            self.above
+        )
+    }
+}
+
+/// The stages of the pipeline that wall-clock time is attributed to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    FastaDecoding,
+    Curvature,
+    Symmetry,
+    Normalization,
+    OutputWriting,
+}
+
+impl Stage {
+    /// All stages, in pipeline order.
+    pub const ALL: [Stage; 5] = [
+        Stage::FastaDecoding,
+        Stage::Curvature,
+        Stage::Symmetry,
+        Stage::Normalization,
+        Stage::OutputWriting,
+    ];
+
+    /// The name of the stage as printed in the timings report.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::FastaDecoding => "FASTA decoding",
+            Stage::Curvature => "curvature",
+            Stage::Symmetry => "symmetry",
+            Stage::Normalization => "normalization",
+            Stage::OutputWriting => "output writing",
+        }
+    }
+}
+
+/// Wall-clock time accumulated per pipeline stage.
+///
+/// Stages are timed many times over a run (once per record or chunk), so durations are summed.
+///
+/// # Fields
+///
+/// * `elapsed`: The total time spent in each stage so far.
+#[derive(Debug, Default)]
+pub struct Timings {
+    elapsed: BTreeMap<Stage, Duration>,
+}
+
+impl Timings {
+    /// Constructor for `Timings`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a duration to the total for a stage.
+    pub fn add(&mut self, stage: Stage, duration: Duration) {
+        *self.elapsed.entry(stage).or_default() += duration;
+    }
+
+    /// Runs a closure and attributes its wall-clock time to a stage.
+    pub fn time<T>(&mut self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.add(stage, start.elapsed());
+        result
+    }
+
+    /// Adds the times of every stage of `other`.
+    pub fn merge(&mut self, other: &Timings) {
+        for (stage, duration) in &other.elapsed {
+            self.add(*stage, *duration);
+        }
+    }
+
+    /// The total time attributed to a stage.
+    pub fn get(&self, stage: Stage) -> Duration {
+        self.elapsed.get(&stage).copied().unwrap_or_default()
+    }
+
+    /// The total time attributed to all stages.
+    pub fn total(&self) -> Duration {
+        self.elapsed.values().sum()
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total().as_secs_f64();
+        for stage in Stage::ALL {
+            let secs = self.get(stage).as_secs_f64();
+            let percent = if total > 0.0 {
+                100.0 * secs / total
+            } else {
+                0.0
+            };
+            writeln!(f, "{:<15} {:>10.3}s {:>5.1}%", stage.name(), secs, percent)?;
+        }
+        writeln!(f, "{:<15} {:>10.3}s", "total", total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_buffer_keeps_max() {
+        let mut stats = MemStats::new();
+        stats.record_buffer("curve", 100);
+        stats.record_buffer("curve", 50);
+        stats.record_buffer("triplet", 3);
+        assert_eq!(stats.buffer("curve"), Some(100));
+        assert_eq!(stats.buffer("triplet"), Some(3));
+        assert_eq!(stats.buffer("roll_mean"), None);
+        let report = stats.to_string();
+        assert!(report.contains("curve buffer: 100 B\n"));
+        assert!(report.contains("triplet buffer: 3 B\n"));
+    }
+
+    #[test]
+    fn test_parse_vm_hwm() {
+        let status = "Name:\tsymcurve\nVmPeak:\t  20000 kB\nVmHWM:\t    1536 kB\n";
+        assert_eq!(parse_vm_hwm(status), Some(1536 * 1024));
+        assert_eq!(parse_vm_hwm("Name:\tsymcurve\n"), None);
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024), "3.0 MiB");
+    }
+
+    #[test]
+    fn test_clip_stats() {
+        let stats = ClipStats { below: 2, above: 5 };
+        assert_eq!(
+            stats.to_string(),
+            "clipped positions: 7 (2 below, 5 above)\n"
+        );
+    }
+
+    #[test]
+    fn test_timings() {
+        let mut timings = Timings::new();
+        timings.add(Stage::Curvature, Duration::from_millis(750));
+        timings.add(Stage::Curvature, Duration::from_millis(250));
+        timings.add(Stage::OutputWriting, Duration::from_secs(1));
+        let value = timings.time(Stage::FastaDecoding, || 42);
+        assert_eq!(value, 42);
+        assert_eq!(timings.get(Stage::Curvature), Duration::from_secs(1));
+        assert_eq!(timings.get(Stage::Symmetry), Duration::ZERO);
+        assert!(timings.total() >= Duration::from_secs(2));
+        let report = timings.to_string();
+        assert_eq!(report.lines().count(), Stage::ALL.len() + 1);
+        assert!(report.starts_with("FASTA decoding"));
+        assert!(report.contains("symmetry             0.000s   0.0%"));
+    }
+}
@@ -0,0 +1,512 @@
+//! Streaming estimators of a value distribution.
+//!
+//! Normalizing a track by its genome-wide mean and spread, or mapping values to quantiles, needs
+//! statistics of every value before the first one can be written. Holding whole chromosomes of
+//! values for that does not scale, so the first pass feeds values through these estimators, which
+//! take constant memory, and the second pass recomputes the values and normalizes them as they
+//! stream out. Both estimators can be merged, so threads can each keep their own and combine them
+//! at the end.
+//!
+//! [`DistributionSummary`] combines the two into the `--stats-json` summary of a run's values:
+//! moments, common percentiles, and the t-digest itself, which can be loaded back with
+//! [`TDigest::from_json`] to find any other percentile without re-reading the output.
+
+use crate::json::Json;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Count, mean, variance and range of a stream of values, by Welford's online algorithm.
+///
+/// # Fields
+///
+/// * `count`: The number of values added.
+/// * `mean`: The running mean.
+/// * `m2`: The running sum of squared differences from the mean.
+/// * `min`: The smallest value added.
+/// * `max`: The largest value added.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RunningStats {
+    count: u64,
+    mean: f64,
+    m2: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for RunningStats {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl RunningStats {
+    /// Constructor for `RunningStats`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a value. `NaN` is ignored.
+    pub fn add(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Combines the statistics of another stream into these, as if its values had been added.
+    pub fn merge(&mut self, other: &RunningStats) {
+        if other.count == 0 {
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.m2 +=
+            other.m2 + delta * delta * (self.count as f64 * other.count as f64) / count as f64;
+        self.count = count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// The number of values added.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The mean, or `NaN` if no values were added.
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            f64::NAN
+        } else {
+            self.mean
+        }
+    }
+
+    /// The population variance, or `NaN` if no values were added.
+    pub fn variance(&self) -> f64 {
+        self.m2 / self.count as f64
+    }
+
+    /// The population standard deviation, or `NaN` if no values were added.
+    pub fn sd(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// The smallest value, or `NaN` if no values were added.
+    pub fn min(&self) -> f64 {
+        if self.count == 0 {
+            f64::NAN
+        } else {
+            self.min
+        }
+    }
+
+    /// The largest value, or `NaN` if no values were added.
+    pub fn max(&self) -> f64 {
+        if self.count == 0 {
+            f64::NAN
+        } else {
+            self.max
+        }
+    }
+
+    /// The z-score of a value against these statistics.
+    pub fn z_score(&self, value: f64) -> f64 {
+        (value - self.mean()) / self.sd()
+    }
+}
+
+/// A cluster of nearby values in a `TDigest`.
+///
+/// # Fields
+///
+/// * `mean`: The mean of the values.
+/// * `weight`: The number of values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// An approximate quantile sketch of a stream of values: a merging t-digest.
+///
+/// Values are summarized as centroids that are small near the tails and larger in the middle, so
+/// extreme quantiles stay accurate while memory is bounded by the compression, whatever the
+/// number of values.
+///
+/// # Fields
+///
+/// * `compression`: The compression parameter δ; about `δ / 2` centroids are kept, and larger
+///   values give more accurate quantiles.
+/// * `centroids`: The merged centroids, in order of their means.
+/// * `buffer`: Values and centroids waiting to be merged.
+/// * `min`: The smallest value added.
+/// * `max`: The largest value added.
+#[derive(Clone, Debug)]
+pub struct TDigest {
+    compression: f64,
+    centroids: Vec<Centroid>,
+    buffer: Vec<Centroid>,
+    min: f64,
+    max: f64,
+}
+
+impl Default for TDigest {
+    fn default() -> Self {
+        Self::new(100.0)
+    }
+}
+
+impl TDigest {
+    /// Constructor for `TDigest`.
+    ///
+    /// # Arguments
+    ///
+    /// * `compression` - The compression parameter δ; 100 gives quantiles accurate to well
+    ///   under 1% of rank in the middle of the distribution and far better in the tails.
+    pub fn new(compression: f64) -> Self {
+        Self {
+            compression: compression.max(10.0),
+            centroids: Vec::new(),
+            buffer: Vec::new(),
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Adds a value. `NaN` is ignored.
+    pub fn add(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.buffer.push(Centroid {
+            mean: value,
+            weight: 1.0,
+        });
+        if self.buffer.len() >= 5 * self.compression as usize {
+            self.compress();
+        }
+    }
+
+    /// Combines another digest into this one.
+    pub fn merge(&mut self, other: &TDigest) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.buffer.extend_from_slice(&other.centroids);
+        self.buffer.extend_from_slice(&other.buffer);
+        self.compress();
+    }
+
+    /// The number of values added.
+    pub fn count(&self) -> u64 {
+        let weight: f64 = self
+            .centroids
+            .iter()
+            .chain(&self.buffer)
+            .map(|c| c.weight)
+            .sum();
+        weight as u64
+    }
+
+    /// The scale function `k₁`, which maps a quantile to the index space centroids are sized in.
+    fn scale(&self, q: f64) -> f64 {
+        self.compression / (2.0 * std::f64::consts::PI) * (2.0 * q - 1.0).asin()
+    }
+
+    /// Merges the buffer into the centroids.
+    fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut all = std::mem::take(&mut self.centroids);
+        all.append(&mut self.buffer);
+        all.sort_by(|a, b| a.mean.total_cmp(&b.mean));
+        let total: f64 = all.iter().map(|c| c.weight).sum();
+        let mut merged: Vec<Centroid> = Vec::with_capacity(self.compression as usize);
+        let mut done = 0.0;
+        let mut k_low = self.scale(0.0);
+        for centroid in all {
+            if let Some(last) = merged.last_mut() {
+                let q = (done + last.weight + centroid.weight) / total;
+                if self.scale(q) - k_low <= 1.0 {
+                    last.weight += centroid.weight;
+                    last.mean += (centroid.mean - last.mean) * centroid.weight / last.weight;
+                    continue;
+                }
+                done += last.weight;
+                k_low = self.scale(done / total);
+            }
+            merged.push(centroid);
+        }
+        self.centroids = merged;
+    }
+
+    /// Returns the centroids with the buffer merged in, without changing the digest.
+    fn merged_centroids(&self) -> Vec<Centroid> {
+        if self.buffer.is_empty() {
+            self.centroids.clone()
+        } else {
+            let mut digest = self.clone();
+            digest.compress();
+            digest.centroids
+        }
+    }
+
+    /// The approximate value at quantile `q`, from 0 (the minimum) to 1 (the maximum).
+    ///
+    /// Returns `NaN` if no values were added or `q` is outside 0 to 1.
+    pub fn quantile(&self, q: f64) -> f64 {
+        let centroids = self.merged_centroids();
+        if centroids.is_empty() || !(0.0..=1.0).contains(&q) {
+            return f64::NAN;
+        }
+        let total: f64 = centroids.iter().map(|c| c.weight).sum();
+        let rank = q * total;
+        // each centroid's values are centered on its mean; interpolate between the centers, and
+        // between the outermost centers and the extremes
+        let mut previous = (0.0, self.min);
+        let mut cumulative = 0.0;
+        for centroid in &centroids {
+            let center = cumulative + centroid.weight / 2.0;
+            if rank < center {
+                let (r0, v0) = previous;
+                return v0
+                    + (centroid.mean - v0) * (rank - r0) / (center - r0).max(f64::MIN_POSITIVE);
+            }
+            previous = (center, centroid.mean);
+            cumulative += centroid.weight;
+        }
+        let (r0, v0) = previous;
+        v0 + (self.max - v0) * (rank - r0) / (total - r0).max(f64::MIN_POSITIVE)
+    }
+
+    /// The approximate fraction of values at or below `value`.
+    ///
+    /// Returns `NaN` if no values were added.
+    pub fn cdf(&self, value: f64) -> f64 {
+        let centroids = self.merged_centroids();
+        if centroids.is_empty() {
+            return f64::NAN;
+        }
+        if value < self.min {
+            return 0.0;
+        }
+        if value >= self.max {
+            return 1.0;
+        }
+        let total: f64 = centroids.iter().map(|c| c.weight).sum();
+        let mut previous = (0.0, self.min);
+        let mut cumulative = 0.0;
+        for centroid in &centroids {
+            let center = cumulative + centroid.weight / 2.0;
+            if value < centroid.mean {
+                let (r0, v0) = previous;
+                let span = (centroid.mean - v0).max(f64::MIN_POSITIVE);
+                return (r0 + (center - r0) * (value - v0) / span) / total;
+            }
+            previous = (center, centroid.mean);
+            cumulative += centroid.weight;
+        }
+        let (r0, v0) = previous;
+        let span = (self.max - v0).max(f64::MIN_POSITIVE);
+        (r0 + (total - r0) * (value - v0) / span) / total
+    }
+}
+
+impl TDigest {
+    /// Converts the digest to JSON: its compression, range, and centroids as `[mean, weight]`
+    /// pairs in order of their means.
+    pub fn to_json(&self) -> Json {
+        let centroids = self
+            .merged_centroids()
+            .iter()
+            .map(|c| Json::from(vec![Json::from(c.mean), Json::from(c.weight)]))
+            .collect::<Vec<_>>();
+        Json::object()
+            .with("compression", self.compression)
+            .with("min", self.min)
+            .with("max", self.max)
+            .with("centroids", centroids)
+    }
+
+    /// Parses a digest written by [`TDigest::to_json`].
+    pub fn from_json(json: &Json) -> Option<Self> {
+        let mut digest = TDigest::new(json.get("compression")?.as_f64()?);
+        digest.min = json.get("min")?.as_f64()?;
+        digest.max = json.get("max")?.as_f64()?;
+        for centroid in json.get("centroids")?.as_array()? {
+            match centroid.as_array()? {
+                [mean, weight] => digest.centroids.push(Centroid {
+                    mean: mean.as_f64()?,
+                    weight: weight.as_f64()?,
+                }),
+                _ => return None,
+            }
+        }
+        Some(digest)
+    }
+}
+
+/// The percentiles written in a distribution summary.
+const PERCENTILES: [f64; 9] = [0.1, 1.0, 5.0, 25.0, 50.0, 75.0, 95.0, 99.0, 99.9];
+
+/// A summary of the distribution of a run's values, for the `--stats-json` output.
+///
+/// # Fields
+///
+/// * `stats`: The count, moments and range.
+/// * `digest`: The quantile sketch.
+#[derive(Clone, Debug, Default)]
+pub struct DistributionSummary {
+    pub stats: RunningStats,
+    pub digest: TDigest,
+}
+
+impl DistributionSummary {
+    /// Constructor for `DistributionSummary`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a value. `NaN` is ignored.
+    pub fn add(&mut self, value: f64) {
+        self.stats.add(value);
+        self.digest.add(value);
+    }
+
+    /// Combines another summary into this one.
+    pub fn merge(&mut self, other: &DistributionSummary) {
+        self.stats.merge(&other.stats);
+        self.digest.merge(&other.digest);
+    }
+
+    /// Converts the summary to JSON. Percentiles are keyed like `p50` and `p99.9`; statistics of
+    /// an empty summary are `null`.
+    pub fn to_json(&self) -> Json {
+        let percentiles = PERCENTILES.iter().fold(Json::object(), |json, &p| {
+            json.with(format!("p{}", p), self.digest.quantile(p / 100.0))
+        });
+        Json::object()
+            .with("count", self.stats.count())
+            .with("mean", self.stats.mean())
+            .with("sd", self.stats.sd())
+            .with("min", self.stats.min())
+            .with("max", self.stats.max())
+            .with("percentiles", percentiles)
+            .with("tdigest", self.digest.to_json())
+    }
+
+    /// Writes the summary as pretty-printed JSON.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_json().pretty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_running_stats() {
+        let mut stats = RunningStats::new();
+        assert!(stats.mean().is_nan());
+        for value in [2.0, 4.0, 4.0, 4.0, 5.0, f64::NAN, 5.0, 7.0, 9.0] {
+            stats.add(value);
+        }
+        assert_eq!(stats.count(), 8);
+        assert_relative_eq!(stats.mean(), 5.0);
+        assert_relative_eq!(stats.sd(), 2.0);
+        assert_eq!((stats.min(), stats.max()), (2.0, 9.0));
+        assert_relative_eq!(stats.z_score(9.0), 2.0);
+    }
+
+    #[test]
+    fn test_running_stats_merge() {
+        let values: Vec<f64> = (0..100).map(|i| (i as f64 * 0.37).sin() * 10.0).collect();
+        let mut whole = RunningStats::new();
+        values.iter().for_each(|&v| whole.add(v));
+        let (mut left, mut right) = (RunningStats::new(), RunningStats::new());
+        values[..30].iter().for_each(|&v| left.add(v));
+        values[30..].iter().for_each(|&v| right.add(v));
+        left.merge(&right);
+        left.merge(&RunningStats::new());
+        assert_eq!(left.count(), 100);
+        assert_relative_eq!(left.mean(), whole.mean(), epsilon = 1e-12);
+        assert_relative_eq!(left.variance(), whole.variance(), epsilon = 1e-9);
+        assert_eq!((left.min(), left.max()), (whole.min(), whole.max()));
+    }
+
+    #[test]
+    fn test_tdigest_quantiles() {
+        let mut digest = TDigest::new(100.0);
+        assert!(digest.quantile(0.5).is_nan());
+        // a shuffled 0..100000
+        for i in 0..100_000u64 {
+            digest.add(((i * 7919) % 100_000) as f64);
+        }
+        assert_eq!(digest.count(), 100_000);
+        assert_eq!(digest.quantile(0.0), 0.0);
+        assert_eq!(digest.quantile(1.0), 99_999.0);
+        for q in [0.001, 0.01, 0.25, 0.5, 0.75, 0.99, 0.999] {
+            let error = (digest.quantile(q) - q * 100_000.0).abs() / 100_000.0;
+            assert!(error < 0.005, "q {} off by {}", q, error);
+            assert_relative_eq!(digest.cdf(q * 100_000.0), q, epsilon = 0.005);
+        }
+        assert!(digest.centroids.len() <= 100);
+    }
+
+    #[test]
+    fn test_tdigest_merge() {
+        let (mut left, mut right) = (TDigest::default(), TDigest::default());
+        for i in 0..5000 {
+            left.add(i as f64);
+            right.add((i + 5000) as f64);
+        }
+        left.merge(&right);
+        assert_eq!(left.count(), 10_000);
+        assert_relative_eq!(left.quantile(0.5), 5000.0, epsilon = 50.0);
+        assert_eq!(left.cdf(-1.0), 0.0);
+        assert_eq!(left.cdf(20_000.0), 1.0);
+    }
+
+    #[test]
+    fn test_distribution_summary_json() {
+        let mut summary = DistributionSummary::new();
+        for i in 0..1000 {
+            summary.add(i as f64 / 10.0);
+        }
+        summary.add(f64::NAN);
+        let json: Json = summary.to_json().pretty().parse().unwrap();
+        assert_eq!(json.get("count").and_then(Json::as_f64), Some(1000.0));
+        assert_eq!(json.get("max").and_then(Json::as_f64), Some(99.9));
+        let median = json
+            .get("percentiles")
+            .and_then(|p| p.get("p50"))
+            .and_then(Json::as_f64)
+            .unwrap();
+        assert_relative_eq!(median, 50.0, epsilon = 0.5);
+        assert!(json.get("percentiles").unwrap().get("p99.9").is_some());
+        // the sketch loads back and answers other percentiles
+        let digest = TDigest::from_json(json.get("tdigest").unwrap()).unwrap();
+        assert_eq!(digest.count(), 1000);
+        assert_relative_eq!(digest.quantile(0.9), 90.0, epsilon = 0.5);
+        // an empty summary writes nulls rather than NaN
+        let empty = DistributionSummary::new().to_json().to_string();
+        assert!(empty.contains("\"mean\":null"));
+    }
+}
@@ -0,0 +1,205 @@
+//! Usage statistics accumulated across runs in a local file.
+//!
+//! Core facilities running symcurve for many users want to know how much it is used for capacity
+//! planning: how many bases go through it, in which output formats, and how fast. With
+//! `--stats-file`, each successful run adds its numbers to a small JSON file the facility points
+//! every run at, and each failed run is counted apart. Nothing is sent over the network, and
+//! without the flag nothing is recorded.
+//!
+//! The file is read, updated and replaced atomically at the end of each run. Runs finishing at the
+//! same moment can lose one update, which is an acceptable error for aggregate counts.
+
+use crate::cli::value_name;
+use crate::json::Json;
+use crate::output::atomic::AtomicFile;
+use crate::output::OutputFormat;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Usage totals across runs.
+///
+/// # Fields
+///
+/// * `runs`: The number of successful runs recorded.
+/// * `bases`: The total number of bases processed, including gaps.
+/// * `seconds`: The total wall-clock time of the runs.
+/// * `formats`: The number of runs per output format, by its command-line name.
+/// * `failed`: The number of failed runs, which are left out of the other totals.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UsageStats {
+    pub runs: u64,
+    pub bases: u64,
+    pub seconds: f64,
+    pub formats: BTreeMap<String, u64>,
+    pub failed: u64,
+}
+
+impl UsageStats {
+    /// Reads the totals from a stats file, or starts from zero if there is no file yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from reading the file, or an `io::ErrorKind::InvalidData` error if it is
+    /// not a stats file, so a mistyped path never overwrites another file.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        text.parse::<Json>()
+            .ok()
+            .and_then(|json| Self::from_json(&json))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a usage stats file"))
+    }
+
+    /// Adds one run that processed `bases` bases into `format` in `elapsed` time.
+    pub fn record(&mut self, format: OutputFormat, bases: u64, elapsed: Duration) {
+        self.runs += 1;
+        self.bases += bases;
+        self.seconds += elapsed.as_secs_f64();
+        *self.formats.entry(value_name(&format)).or_default() += 1;
+    }
+
+    /// Adds one failed run.
+    pub fn record_failure(&mut self) {
+        self.failed += 1;
+    }
+
+    /// The average throughput over all runs in bases per second, or `None` before any time has
+    /// been recorded.
+    pub fn throughput(&self) -> Option<f64> {
+        (self.seconds > 0.0).then(|| self.bases as f64 / self.seconds)
+    }
+
+    /// Converts the totals to JSON. The average throughput is included for readers of the file,
+    /// and recomputed from the totals when it is read back.
+    pub fn to_json(&self) -> Json {
+        let formats = self
+            .formats
+            .iter()
+            .fold(Json::object(), |json, (format, &runs)| {
+                json.with(format.as_str(), runs)
+            });
+        let mut json = Json::object()
+            .with("runs", self.runs)
+            .with("bases", self.bases)
+            .with("seconds", self.seconds);
+        if let Some(throughput) = self.throughput() {
+            json = json.with("bases_per_second", throughput);
+        }
+        json.with("runs_per_format", formats)
+            .with("failed_runs", self.failed)
+    }
+
+    /// Reads the totals back from their JSON. Returns `None` if the JSON doesn't have the expected
+    /// shape. Files written before failed runs were counted have none.
+    pub fn from_json(json: &Json) -> Option<Self> {
+        let formats = json
+            .get("runs_per_format")?
+            .entries()?
+            .iter()
+            .map(|(format, runs)| Some((format.clone(), runs.as_f64()? as u64)))
+            .collect::<Option<BTreeMap<_, _>>>()?;
+        Some(Self {
+            runs: json.get("runs")?.as_f64()? as u64,
+            bases: json.get("bases")?.as_f64()? as u64,
+            seconds: json.get("seconds")?.as_f64()?,
+            formats,
+            failed: match json.get("failed_runs") {
+                Some(failed) => failed.as_f64()? as u64,
+                None => 0,
+            },
+        })
+    }
+
+    /// Writes the totals as pretty-printed JSON, replacing the file atomically.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut file = AtomicFile::create(path)?;
+        file.write_all(self.to_json().pretty().as_bytes())?;
+        file.commit()
+    }
+}
+
+/// Adds one run to the stats file at `path`, creating it if needed.
+///
+/// # Errors
+///
+/// Returns any error from [`UsageStats::read`] or from writing the file.
+pub fn record_run(
+    path: &Path,
+    format: OutputFormat,
+    bases: u64,
+    elapsed: Duration,
+) -> io::Result<()> {
+    let mut stats = UsageStats::read(path)?;
+    stats.record(format, bases, elapsed);
+    stats.write(path)
+}
+
+/// Adds one failed run to the stats file at `path`, creating it if needed.
+///
+/// # Errors
+///
+/// Returns any error from [`UsageStats::read`] or from writing the file.
+pub fn record_failed_run(path: &Path) -> io::Result<()> {
+    let mut stats = UsageStats::read(path)?;
+    stats.record_failure();
+    stats.write(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_run() {
+        let dir = std::env::temp_dir().join("symcurve_test_usage_stats");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("usage.json");
+        let _ = fs::remove_file(&path);
+        record_run(&path, OutputFormat::BigWig, 1000, Duration::from_secs(2)).unwrap();
+        record_run(&path, OutputFormat::BedGraph, 500, Duration::from_secs(1)).unwrap();
+        record_run(&path, OutputFormat::BigWig, 1500, Duration::from_secs(2)).unwrap();
+        let stats = UsageStats::read(&path).unwrap();
+        assert_eq!((stats.runs, stats.bases, stats.seconds), (3, 3000, 5.0));
+        assert_eq!(stats.throughput(), Some(600.0));
+        assert_eq!(stats.formats.get("bigwig"), Some(&2));
+        assert_eq!(stats.formats.get("bedgraph"), Some(&1));
+        let json: Json = fs::read_to_string(&path).unwrap().parse().unwrap();
+        assert_eq!(json.get("bases_per_second").unwrap().as_f64(), Some(600.0));
+        assert_eq!(stats.failed, 0);
+
+        // failed runs are counted apart from the totals
+        record_failed_run(&path).unwrap();
+        let failed = UsageStats::read(&path).unwrap();
+        assert_eq!(failed, UsageStats { failed: 1, ..stats });
+
+        // files from before failed runs were counted still read
+        let json = Json::object()
+            .with("runs", 1u64)
+            .with("bases", 10u64)
+            .with("seconds", 1.0)
+            .with("runs_per_format", Json::object());
+        fs::write(&path, json.pretty()).unwrap();
+        assert_eq!(UsageStats::read(&path).unwrap().failed, 0);
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let dir = std::env::temp_dir().join("symcurve_test_usage_stats_errors");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("usage.json");
+        let _ = fs::remove_file(&path);
+        assert_eq!(UsageStats::read(&path).unwrap(), UsageStats::default());
+        assert_eq!(UsageStats::default().throughput(), None);
+        fs::write(&path, ">chr1\nACGT\n").unwrap();
+        let err = UsageStats::read(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(record_run(&path, OutputFormat::Tsv, 4, Duration::ZERO).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), ">chr1\nACGT\n");
+    }
+}
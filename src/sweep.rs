@@ -0,0 +1,344 @@
+//! # Sweep module
+//! The `symcurve sweep` subcommand, which computes curvature over a grid of parameter values on
+//! one sequence or region and prints a table of summary statistics per combination, to help pick
+//! window sizes empirically before running a whole genome.
+//!
+//! Ranges are given as `--param NAME=START..END:STEP` with `END` included, e.g.
+//! `--param curve-step=5..30:5`; several `--param` options sweep the full grid.
+
+use crate::curve::iters::CurveIter;
+use crate::fasta::{open_input, StreamingReader};
+use crate::pipeline::PipelineParams;
+use crate::view::Region;
+use clap::ValueEnum;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// The parameters that can be swept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SweepParam {
+    /// `--curve-step`
+    CurveStep,
+    /// `--curve-step-one`
+    CurveStepOne,
+    /// `--curve-scale`
+    CurveScale,
+}
+
+impl SweepParam {
+    /// Sets the parameter on a set of pipeline parameters.
+    fn apply(&self, params: &mut PipelineParams, value: f64) {
+        match self {
+            SweepParam::CurveStep => params.step_c = value as usize,
+            // the rolling mean half-window is one less than the command-line step
+            SweepParam::CurveStepOne => params.step_b = value as usize - 1,
+            SweepParam::CurveScale => params.curve_scale = value,
+        }
+    }
+
+    /// Whether the parameter only takes whole numbers of at least 1.
+    fn is_step(&self) -> bool {
+        !matches!(self, SweepParam::CurveScale)
+    }
+}
+
+impl fmt::Display for SweepParam {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.to_possible_value().unwrap();
+        write!(f, "{}", name.get_name())
+    }
+}
+
+/// One parameter's range of values, written `NAME=START..END:STEP`.
+///
+/// # Fields
+///
+/// * `param`: The parameter swept.
+/// * `values`: The values, from `START` to `END` inclusive in steps of `STEP`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParamRange {
+    pub param: SweepParam,
+    pub values: Vec<f64>,
+}
+
+impl FromStr for ParamRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |why: &str| format!("invalid sweep range '{}': {}", s, why);
+        let (name, range) = s
+            .split_once('=')
+            .ok_or_else(|| invalid("expected NAME=START..END:STEP"))?;
+        let param = SweepParam::from_str(name, true).map_err(|_| {
+            invalid("the parameter must be curve-step, curve-step-one or curve-scale")
+        })?;
+        let (bounds, step) = range.split_once(':').unwrap_or((range, "1"));
+        let (start, end) = bounds
+            .split_once("..")
+            .ok_or_else(|| invalid("expected START..END"))?;
+        let number = |v: &str| v.trim().parse::<f64>().map_err(|_| invalid("not a number"));
+        let (start, end, step) = (number(start)?, number(end)?, number(step)?);
+        if [start, end, step].iter().any(|v| !v.is_finite()) || step <= 0.0 || start > end {
+            return Err(invalid("STEP must be positive and START no more than END"));
+        }
+        if param.is_step() && (start < 1.0 || [start, step].iter().any(|v| v.fract() != 0.0)) {
+            return Err(invalid("steps are whole numbers of at least 1"));
+        }
+        if param == SweepParam::CurveStepOne && start < 2.0 {
+            // a step of 1 leaves an empty rolling mean
+            return Err(invalid("curve-step-one starts at 2 or more"));
+        }
+        // count the steps up front so floating-point steps don't drift past END
+        let count = ((end - start) / step + 1e-9).floor() as usize + 1;
+        let values = (0..count).map(|i| start + i as f64 * step).collect();
+        Ok(Self { param, values })
+    }
+}
+
+/// Expands ranges into every combination of their values, the last range varying fastest.
+pub fn grid(ranges: &[ParamRange]) -> Vec<Vec<(SweepParam, f64)>> {
+    ranges.iter().fold(vec![Vec::new()], |combos, range| {
+        combos
+            .iter()
+            .flat_map(|combo| {
+                range.values.iter().map(move |&value| {
+                    let mut combo = combo.clone();
+                    combo.push((range.param, value));
+                    combo
+                })
+            })
+            .collect()
+    })
+}
+
+/// Summary statistics of the values computed for one combination.
+///
+/// # Fields
+///
+/// * `count`: The number of values.
+/// * `mean`: The mean value.
+/// * `sd`: The population standard deviation.
+/// * `min`: The smallest value.
+/// * `max`: The largest value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub mean: f64,
+    pub sd: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Summary {
+    /// Summarizes values. All statistics are `NaN` if there are none.
+    pub fn of(values: &[f64]) -> Self {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+        let (min, max) = if values.is_empty() {
+            (f64::NAN, f64::NAN)
+        } else {
+            (
+                values.iter().copied().fold(f64::INFINITY, f64::min),
+                values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            )
+        };
+        Self {
+            count: values.len(),
+            mean,
+            sd: variance.sqrt(),
+            min,
+            max,
+        }
+    }
+}
+
+/// Reads the bases of one region of a FASTA file, or the first record if no region is given.
+///
+/// # Errors
+///
+/// Returns an `io::ErrorKind::NotFound` error if the region's sequence is not in the file.
+pub fn read_region<R: BufRead>(reader: R, region: Option<&Region>) -> io::Result<Vec<u8>> {
+    let mut fasta = StreamingReader::new(reader);
+    while let Some(name) = fasta.next_record()? {
+        let (start, end) = match region {
+            Some(region) if region.chrom != name => continue,
+            Some(region) => (region.start, region.end.unwrap_or(u64::MAX)),
+            None => (0, u64::MAX),
+        };
+        let mut bases = Vec::new();
+        for (pos, base) in (0u64..).zip(fasta.bases()) {
+            let base = base?;
+            if pos >= end {
+                break;
+            }
+            if pos >= start {
+                bases.push(base.to_ascii_uppercase());
+            }
+        }
+        return Ok(bases);
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        match region {
+            Some(region) => format!("sequence {} not found", region.chrom),
+            None => "no sequences found".to_string(),
+        },
+    ))
+}
+
+/// Computes the curvature values of a sequence for one set of parameters, piece by piece between
+/// gaps.
+fn curvature(seq: &[u8], params: &PipelineParams) -> Vec<f64> {
+    seq.split(|base| !matches!(base, b'A' | b'C' | b'G' | b'T'))
+        .flat_map(|piece| {
+            CurveIter::new(
+                piece.iter().cloned(),
+                params.roll_type.clone(),
+                params.step_b,
+                params.step_c,
+                params.curve_scale,
+                params.edge_mode,
+                params.convention,
+            )
+            .with_step_two(params.step_two)
+        })
+        .collect()
+}
+
+/// Runs the sweep over a sequence and writes the comparison table.
+///
+/// The table is tab-separated with a header: one column per swept parameter, then `count`,
+/// `mean`, `sd`, `min` and `max` of the curvature values.
+///
+/// # Arguments
+///
+/// * `seq` - The bases, uppercase.
+/// * `ranges` - The parameter ranges.
+/// * `base` - The values of the parameters that are not swept.
+/// * `writer` - Where the table is written.
+pub fn write_sweep<W: Write>(
+    seq: &[u8],
+    ranges: &[ParamRange],
+    base: &PipelineParams,
+    writer: &mut W,
+) -> io::Result<()> {
+    let names: Vec<String> = ranges.iter().map(|r| r.param.to_string()).collect();
+    writeln!(writer, "{}\tcount\tmean\tsd\tmin\tmax", names.join("\t"))?;
+    for combo in grid(ranges) {
+        let mut params = base.clone();
+        for &(param, value) in &combo {
+            param.apply(&mut params, value);
+        }
+        let summary = Summary::of(&curvature(seq, &params));
+        let values: Vec<String> = combo.iter().map(|(_, v)| v.to_string()).collect();
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            values.join("\t"),
+            summary.count,
+            params.float_format.format(summary.mean),
+            params.float_format.format(summary.sd),
+            params.float_format.format(summary.min),
+            params.float_format.format(summary.max)
+        )?;
+    }
+    Ok(())
+}
+
+/// Runs `symcurve sweep`.
+///
+/// # Arguments
+///
+/// * `path` - The FASTA file.
+/// * `region` - The region to sweep over, or `None` for the first sequence.
+/// * `ranges` - The parameter ranges.
+/// * `base` - The values of the parameters that are not swept, from the command line.
+/// * `writer` - Where the table is written.
+pub fn sweep<W: Write>(
+    path: &Path,
+    region: Option<&Region>,
+    ranges: &[ParamRange],
+    base: &PipelineParams,
+    writer: &mut W,
+) -> io::Result<()> {
+    let seq = read_region(open_input(path)?, region)?;
+    write_sweep(&seq, ranges, base, writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_param_range() {
+        let range: ParamRange = "curve-step=5..30:5".parse().unwrap();
+        assert_eq!(range.param, SweepParam::CurveStep);
+        assert_eq!(range.values, vec![5.0, 10.0, 15.0, 20.0, 25.0, 30.0]);
+        let range: ParamRange = "curve-scale=0.1..0.3:0.1".parse().unwrap();
+        assert_eq!(range.values.len(), 3);
+        let range: ParamRange = "curve-step-one=4..6".parse().unwrap();
+        assert_eq!(range.values, vec![4.0, 5.0, 6.0]);
+        assert!("curve-step=0..10:5".parse::<ParamRange>().is_err());
+        assert!("curve-step-one=1..3".parse::<ParamRange>().is_err());
+        assert!("curve-step=5..10:2.5".parse::<ParamRange>().is_err());
+        assert!("curve-step=10..5:1".parse::<ParamRange>().is_err());
+        assert!("symcurve-win=5..10:1".parse::<ParamRange>().is_err());
+        assert!("curve-step=5".parse::<ParamRange>().is_err());
+    }
+
+    #[test]
+    fn test_grid() {
+        let ranges = [
+            "curve-step=5..10:5".parse().unwrap(),
+            "curve-step-one=4..6:2".parse().unwrap(),
+        ];
+        let combos = grid(&ranges);
+        assert_eq!(combos.len(), 4);
+        assert_eq!(
+            combos[1],
+            vec![
+                (SweepParam::CurveStep, 5.0),
+                (SweepParam::CurveStepOne, 6.0)
+            ]
+        );
+        assert_eq!(grid(&[]), vec![Vec::new()]);
+    }
+
+    #[test]
+    fn test_read_region() {
+        let fasta = b">chr1\nACGTACGT\n>chr2\nccccGGGG\nTTTT\n";
+        assert_eq!(read_region(&fasta[..], None).unwrap(), b"ACGTACGT");
+        let region: Region = "chr2:4-9".parse().unwrap();
+        assert_eq!(read_region(&fasta[..], Some(&region)).unwrap(), b"CGGGGT");
+        let region: Region = "chr3".parse().unwrap();
+        let err = read_region(&fasta[..], Some(&region)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_write_sweep() {
+        let seq = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATCNNCCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+        let ranges = ["curve-step=10..15:5".parse().unwrap()];
+        let mut out = Vec::new();
+        write_sweep(seq, &ranges, &PipelineParams::default(), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "curve-step\tcount\tmean\tsd\tmin\tmax");
+        // 50 bp pieces give 50 - 2 * (5 + 10 + 1) values each at curve-step 10, 8 at 15
+        assert!(lines[1].starts_with("10\t36\t"));
+        assert!(lines[2].starts_with("15\t16\t"));
+    }
+
+    #[test]
+    fn test_summary() {
+        let summary = Summary::of(&[1.0, 2.0, 3.0, 6.0]);
+        assert_eq!(summary.count, 4);
+        assert_eq!(summary.mean, 3.0);
+        assert_eq!(summary.sd, 3.5f64.sqrt());
+        assert_eq!((summary.min, summary.max), (1.0, 6.0));
+        assert!(Summary::of(&[]).mean.is_nan());
+    }
+}
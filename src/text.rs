@@ -0,0 +1,78 @@
+//! # Text module
+//! Line handling shared by the text input parsers.
+//!
+//! Files passed between collaborators often come from Windows editors and spreadsheets, with CRLF
+//! line endings, a UTF-8 byte order mark at the start and trailing spaces or tabs. Left in place,
+//! these turn into a first sequence name or chromosome that matches nothing, or into extra gap
+//! bases that shift every coordinate after them. Every parser cleans its lines with
+//! [`clean_line`], and the FASTA readers skip the mark with [`skip_bom`], so such files read the
+//! same as their plain counterparts.
+//!
+//! Numbers are parsed the same whatever the system locale, always with `.` as the decimal point;
+//! [`not_a_number`] points out a decimal comma rather than just rejecting the value.
+
+use std::io::{self, BufRead};
+
+/// The UTF-8 byte order mark.
+pub const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Strips a leading byte order mark and trailing whitespace, including the `\r` of a CRLF line
+/// ending, from a line.
+pub fn clean_line(line: &str) -> &str {
+    line.strip_prefix('\u{feff}').unwrap_or(line).trim_end()
+}
+
+/// Skips a byte order mark at the current position of a reader, normally its start.
+///
+/// # Returns
+///
+/// The number of bytes skipped.
+pub fn skip_bom<R: BufRead>(reader: &mut R) -> io::Result<usize> {
+    if reader.fill_buf()?.starts_with(BOM) {
+        reader.consume(BOM.len());
+        return Ok(BOM.len());
+    }
+    Ok(0)
+}
+
+/// The message for a value that does not parse as a number, which suggests a decimal point if
+/// the value has a decimal comma.
+pub fn not_a_number(value: &str) -> String {
+    if value.contains(',') && value.replacen(',', ".", 1).parse::<f64>().is_ok() {
+        format!("'{}' is not a number: use '.' as the decimal point", value)
+    } else {
+        format!("'{}' is not a number", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_line() {
+        assert_eq!(clean_line("\u{feff}>chr1 first\r"), ">chr1 first");
+        assert_eq!(clean_line("chr1\t0\t10\t1.5 \t"), "chr1\t0\t10\t1.5");
+        assert_eq!(clean_line("\r"), "");
+        assert_eq!(clean_line("  AAA: 1"), "  AAA: 1");
+    }
+
+    #[test]
+    fn test_skip_bom() {
+        let mut reader = &b"\xEF\xBB\xBF>chr1\n"[..];
+        assert_eq!(skip_bom(&mut reader).unwrap(), 3);
+        assert_eq!(reader, b">chr1\n");
+        assert_eq!(skip_bom(&mut reader).unwrap(), 0);
+        assert_eq!(skip_bom(&mut &b""[..]).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_not_a_number() {
+        assert_eq!(
+            not_a_number("0,598"),
+            "'0,598' is not a number: use '.' as the decimal point"
+        );
+        assert_eq!(not_a_number("x"), "'x' is not a number");
+        assert_eq!(not_a_number("1,2,3"), "'1,2,3' is not a number");
+    }
+}
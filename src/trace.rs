@@ -0,0 +1,232 @@
+//! # Trace module
+//! Lightweight span recording for performance investigations.
+//!
+//! Spans are recorded for each contig and pipeline stage and can be written out either as a
+//! Chrome trace-event JSON file (loadable in `chrome://tracing` or Perfetto, the same format
+//! `tracing-chrome` produces) or as folded stacks (the `tracing-flame` format that `inferno` and
+//! `flamegraph.pl` turn into a flamegraph). This gives a profile without external profilers.
+
+use crate::json::escape;
+use clap::ValueEnum;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// File formats the span trace can be written in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum TraceFormat {
+    /// Chrome trace-event JSON, for chrome://tracing or Perfetto
+    #[default]
+    Chrome,
+    /// folded stacks, for flamegraph tools
+    Folded,
+}
+
+/// A completed span.
+///
+/// # Fields
+///
+/// * `stack`: The names of the enclosing spans, outermost first, ending with this span.
+/// * `start_us`: Microseconds from the start of the trace to when the span was entered.
+/// * `duration_us`: Microseconds the span was open for.
+#[derive(Clone, Debug)]
+struct SpanRecord {
+    stack: Vec<String>,
+    start_us: u128,
+    duration_us: u128,
+}
+
+/// Records nested spans over a run.
+///
+/// # Fields
+///
+/// * `origin`: When the trace was started; span times are relative to this.
+/// * `stack`: The names of the currently open spans, outermost first.
+/// * `spans`: The spans that have been closed so far.
+#[derive(Debug)]
+pub struct Tracer {
+    origin: Instant,
+    stack: RefCell<Vec<String>>,
+    spans: RefCell<Vec<SpanRecord>>,
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tracer {
+    /// Constructor for `Tracer`.
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            stack: RefCell::new(Vec::new()),
+            spans: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Opens a span nested inside any spans currently open. The span closes when the returned
+    /// guard is dropped.
+    pub fn span(&self, name: impl Into<String>) -> Span<'_> {
+        self.stack.borrow_mut().push(name.into());
+        Span {
+            tracer: self,
+            start: Instant::now(),
+        }
+    }
+
+    /// Adds a closed span of `duration` from `start`, nested inside the spans currently open, for
+    /// work timed apart from them rather than between a guard's opening and closing.
+    pub fn add_span(&self, name: impl Into<String>, start: Instant, duration: Duration) {
+        let mut stack = self.stack.borrow().clone();
+        stack.push(name.into());
+        self.spans.borrow_mut().push(SpanRecord {
+            stack,
+            start_us: start.duration_since(self.origin).as_micros(),
+            duration_us: duration.as_micros(),
+        });
+    }
+
+    /// The number of spans closed so far.
+    pub fn len(&self) -> usize {
+        self.spans.borrow().len()
+    }
+
+    /// Whether no spans have been closed yet.
+    pub fn is_empty(&self) -> bool {
+        self.spans.borrow().is_empty()
+    }
+
+    /// Writes the closed spans in `format`.
+    pub fn write<W: Write>(&self, writer: &mut W, format: TraceFormat) -> io::Result<()> {
+        match format {
+            TraceFormat::Chrome => self.write_chrome_json(writer),
+            TraceFormat::Folded => self.write_folded(writer),
+        }
+    }
+
+    /// Writes the closed spans as a Chrome trace-event JSON array of complete (`"X"`) events.
+    pub fn write_chrome_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "[")?;
+        let spans = self.spans.borrow();
+        for (i, span) in spans.iter().enumerate() {
+            let sep = if i + 1 < spans.len() { "," } else { "" };
+            writeln!(
+                writer,
+                "{{\"name\":\"{}\",\"cat\":\"symcurve\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":1,\"tid\":1}}{}",
+                escape(span.stack.last().unwrap()),
+                span.start_us,
+                span.duration_us,
+                sep
+            )?;
+        }
+        writeln!(writer, "]")
+    }
+
+    /// Writes the closed spans as folded stacks, one `outer;inner microseconds` line per span.
+    ///
+    /// Only the time not covered by child spans is attributed to each line, so the totals add up
+    /// the way flamegraph tools expect.
+    pub fn write_folded<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let spans = self.spans.borrow();
+        for span in spans.iter() {
+            let children: u128 = spans
+                .iter()
+                .filter(|other| {
+                    other.stack.len() == span.stack.len() + 1
+                        && other.stack.starts_with(&span.stack)
+                        && other.start_us >= span.start_us
+                        && other.start_us < span.start_us + span.duration_us.max(1)
+                })
+                .map(|other| other.duration_us)
+                .sum();
+            let own = span.duration_us.saturating_sub(children);
+            writeln!(writer, "{} {}", span.stack.join(";"), own)?;
+        }
+        Ok(())
+    }
+}
+
+/// Guard for an open span; closes the span when dropped.
+pub struct Span<'a> {
+    tracer: &'a Tracer,
+    start: Instant,
+}
+
+impl Drop for Span<'_> {
+    fn drop(&mut self) {
+        let stack = self.tracer.stack.borrow().clone();
+        self.tracer.stack.borrow_mut().pop();
+        self.tracer.spans.borrow_mut().push(SpanRecord {
+            stack,
+            start_us: self.start.duration_since(self.tracer.origin).as_micros(),
+            duration_us: self.start.elapsed().as_micros(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_spans() {
+        let tracer = Tracer::new();
+        assert!(tracer.is_empty());
+        {
+            let _contig = tracer.span("chr1");
+            {
+                let _stage = tracer.span("curvature");
+            }
+            {
+                let _stage = tracer.span("output writing");
+            }
+        }
+        assert_eq!(tracer.len(), 3);
+        let mut folded = Vec::new();
+        tracer.write_folded(&mut folded).unwrap();
+        let folded = String::from_utf8(folded).unwrap();
+        let stacks: Vec<_> = folded
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0)
+            .collect();
+        assert_eq!(
+            stacks,
+            vec!["chr1;curvature", "chr1;output writing", "chr1"]
+        );
+    }
+
+    #[test]
+    fn test_add_span() {
+        let tracer = Tracer::new();
+        {
+            let _contig = tracer.span("chr1");
+            tracer.add_span("curvature", Instant::now(), Duration::from_millis(3));
+        }
+        let mut folded = Vec::new();
+        tracer.write_folded(&mut folded).unwrap();
+        let folded = String::from_utf8(folded).unwrap();
+        let lines: Vec<_> = folded.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "chr1;curvature 3000");
+        assert!(lines[1].starts_with("chr1 "));
+    }
+
+    #[test]
+    fn test_write_chrome_json() {
+        let tracer = Tracer::new();
+        {
+            let _span = tracer.span("chr\"1\"");
+        }
+        {
+            let _span = tracer.span("chr2");
+        }
+        let mut json = Vec::new();
+        tracer.write_chrome_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.starts_with("[\n{\"name\":\"chr\\\"1\\\"\",\"cat\":\"symcurve\",\"ph\":\"X\""));
+        assert_eq!(json.matches("\"ph\":\"X\"").count(), 2);
+        assert!(json.ends_with("}\n]\n"));
+    }
+}
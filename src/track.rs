@@ -0,0 +1,182 @@
+//! In-memory curvature tracks.
+//!
+//! [`position_values`] gives the curvature of a sequence as a bare `Vec<f64>`, which leaves the
+//! caller to remember where the sequence starts and which values are missing. [`compute_track`]
+//! wraps the same values in a [`CurveTrack`] that keeps its offset, so a track of a region can
+//! be sliced, iterated and written as bedGraph in the coordinates of its chromosome.
+//!
+//! ```
+//! use symcurve::output::FloatFormat;
+//! use symcurve::{compute_track, PipelineParams};
+//!
+//! let seq = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATC";
+//! let track = compute_track(seq, &PipelineParams::default()).with_offset(1000);
+//! assert_eq!(track.len(), seq.len());
+//! let (first, _) = track.iter_positions().next().unwrap();
+//! assert!(first > 1000);
+//! assert!(track.to_bedgraph("chr1", FloatFormat::Shortest).starts_with("chr1\t"));
+//! ```
+
+use crate::output::bedgraph::BedGraphWriter;
+use crate::output::FloatFormat;
+use crate::pipeline::{position_values, PipelineParams};
+use std::ops::Range;
+
+/// The curvature of a sequence, one value per base.
+///
+/// # Fields
+///
+/// * `offset`: The 0-based position of the first base, in the coordinates of the chromosome the
+///   sequence comes from.
+/// * `values`: The value at each base, `NaN` where there is none (gaps and the flanks of each
+///   piece).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CurveTrack {
+    pub offset: u64,
+    pub values: Vec<f64>,
+}
+
+/// Computes the curvature of an in-memory sequence as a track starting at position 0.
+///
+/// The values are those of [`position_values`]; use [`CurveTrack::with_offset`] to place the
+/// track of a region within its chromosome.
+///
+/// # Arguments
+///
+/// * `seq` - The bases of the sequence, in any case, with gaps as any non-`ACGT` byte.
+/// * `params` - The parameters of the run.
+pub fn compute_track(seq: &[u8], params: &PipelineParams) -> CurveTrack {
+    CurveTrack {
+        offset: 0,
+        values: position_values(seq, params),
+    }
+}
+
+impl CurveTrack {
+    /// Moves the track to start at `offset`.
+    pub fn with_offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// The number of bases the track covers, with or without values.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Whether the track covers no bases.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The exclusive end of the track.
+    pub fn end(&self) -> u64 {
+        self.offset + self.values.len() as u64
+    }
+
+    /// The value at a 0-based position, or `None` if the position is outside the track or has no
+    /// value.
+    pub fn get(&self, pos: u64) -> Option<f64> {
+        let i = usize::try_from(pos.checked_sub(self.offset)?).ok()?;
+        self.values.get(i).copied().filter(|value| !value.is_nan())
+    }
+
+    /// The 0-based positions that have values, with their values, in order.
+    pub fn iter_positions(&self) -> impl Iterator<Item = (u64, f64)> + '_ {
+        (self.offset..)
+            .zip(self.values.iter().copied())
+            .filter(|(_, value)| !value.is_nan())
+    }
+
+    /// The part of the track within a range of 0-based positions, clamped to the track.
+    pub fn slice(&self, range: Range<u64>) -> CurveTrack {
+        let start = range.start.clamp(self.offset, self.end());
+        let end = range.end.clamp(start, self.end());
+        let first = (start - self.offset) as usize;
+        let last = (end - self.offset) as usize;
+        CurveTrack {
+            offset: start,
+            values: self.values[first..last].to_vec(),
+        }
+    }
+
+    /// Writes the track as bedGraph lines on `chrom`, merging adjacent bases whose values are
+    /// written the same, as the bedGraph output does.
+    pub fn to_bedgraph(&self, chrom: &str, float: FloatFormat) -> String {
+        let mut writer = BedGraphWriter::new(Vec::new(), float);
+        // writing to a Vec cannot fail, and a sequence is always started
+        let _ = writer.start_chrom(chrom);
+        let _ = writer.push_missing(self.offset);
+        for &value in &self.values {
+            let _ = writer.push(value);
+        }
+        let bytes = writer.finish().unwrap_or_default();
+        String::from_utf8(bytes).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track() -> CurveTrack {
+        CurveTrack {
+            offset: 10,
+            values: vec![f64::NAN, 0.5, 0.5, f64::NAN, 0.25],
+        }
+    }
+
+    #[test]
+    fn test_compute_track() {
+        let seq = b"CCAACATTTTGACTTTTTGGGAGGGCACTAGCACCTATCTACCCTGAATCNNNNACGT";
+        let params = PipelineParams::default();
+        let track = compute_track(seq, &params);
+        assert_eq!(track.offset, 0);
+        let values = position_values(seq, &params);
+        assert_eq!(track.len(), values.len());
+        let positions: Vec<(u64, f64)> = track.iter_positions().collect();
+        let expected: Vec<(u64, f64)> = (0..)
+            .zip(values)
+            .filter(|(_, value)| !value.is_nan())
+            .collect();
+        assert_eq!(positions, expected);
+        assert!(!positions.is_empty());
+    }
+
+    #[test]
+    fn test_positions() {
+        let track = track();
+        assert_eq!((track.len(), track.end()), (5, 15));
+        assert_eq!(
+            track.iter_positions().collect::<Vec<_>>(),
+            vec![(11, 0.5), (12, 0.5), (14, 0.25)]
+        );
+        assert_eq!(track.get(11), Some(0.5));
+        assert_eq!(track.get(10), None);
+        assert_eq!(track.get(9), None);
+        assert_eq!(track.get(15), None);
+    }
+
+    #[test]
+    fn test_slice() {
+        let track = track();
+        let slice = track.slice(12..14);
+        assert_eq!(slice.offset, 12);
+        assert_eq!(slice.values[0], 0.5);
+        assert_eq!(slice.len(), 2);
+        assert_eq!(track.slice(0..100).len(), 5);
+        assert!(track.slice(20..30).is_empty());
+    }
+
+    #[test]
+    fn test_to_bedgraph() {
+        assert_eq!(
+            track().to_bedgraph("chr1", FloatFormat::Shortest),
+            "chr1\t11\t13\t0.5\nchr1\t14\t15\t0.25\n"
+        );
+        assert_eq!(
+            CurveTrack::default().to_bedgraph("chr1", FloatFormat::Shortest),
+            ""
+        );
+    }
+}
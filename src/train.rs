@@ -0,0 +1,314 @@
+//! # Train module
+//! The `symcurve train` subcommand, which adjusts the roll matrix so that curvature separates two
+//! labeled sets of sequences, such as nucleosome-bound sequences and linkers.
+//!
+//! Each sequence is scored by its mean curvature, and a roll matrix is scored by the standardized
+//! difference (Cohen's d) between the mean scores of the positive and the negative sequences. The
+//! search starts from the simple roll matrix and is gradient-free: every triplet is nudged up and
+//! down by a step together with its reverse complement, keeping the matrix strand-symmetric, and a
+//! nudge is kept when it improves the score. When a full pass keeps nothing the step is halved.
+//! The fitted matrices are written in the YAML matrix format read by `--matrices`, with the twist
+//! and tilt matrices unchanged.
+
+use crate::calibrate::Curves;
+use crate::curve::iters::CurveIter;
+use crate::curve::matrix::{self, NucMatrix, RollType};
+use crate::fasta::StreamingReader;
+use crate::pipeline::PipelineParams;
+use crate::stats::streaming::RunningStats;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The smallest step tried before the search stops.
+pub const MIN_STEP: f64 = 1e-3;
+
+/// The settings of a training run.
+///
+/// # Fields
+///
+/// * `positive`: FASTA file of the sequences that should score high, e.g. nucleosome-bound.
+/// * `negative`: FASTA file of the sequences that should score low, e.g. linkers.
+/// * `iterations`: The most passes over the matrix.
+/// * `step`: The first amount each roll value is nudged by.
+/// * `output`: Where the YAML matrix file is written.
+#[derive(Clone, Debug)]
+pub struct TrainParams {
+    pub positive: PathBuf,
+    pub negative: PathBuf,
+    pub iterations: usize,
+    pub step: f64,
+    pub output: PathBuf,
+}
+
+/// How well a roll matrix separates the two sets.
+///
+/// # Fields
+///
+/// * `effect_size`: Cohen's d of the positive against the negative mean curvatures.
+/// * `auc_roc`: The area under the ROC curve of mean curvature as a predictor of the label.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Separation {
+    pub effect_size: f64,
+    pub auc_roc: f64,
+}
+
+/// The result of a training run.
+///
+/// # Fields
+///
+/// * `roll`: The fitted roll matrix.
+/// * `initial`: The separation with the starting matrix.
+/// * `fitted`: The separation with the fitted matrix.
+/// * `passes`: The number of passes made over the matrix.
+#[derive(Clone, Debug)]
+pub struct Training {
+    pub roll: NucMatrix,
+    pub initial: Separation,
+    pub fitted: Separation,
+    pub passes: usize,
+}
+
+/// Reads every record of a FASTA file, uppercased.
+fn read_sequences(path: &Path) -> io::Result<Vec<Vec<u8>>> {
+    let mut fasta = StreamingReader::new(BufReader::new(File::open(path)?));
+    let mut sequences = Vec::new();
+    while fasta.next_record()?.is_some() {
+        let bases = fasta
+            .bases()
+            .map(|base| base.map(|b| b.to_ascii_uppercase()))
+            .collect::<io::Result<Vec<u8>>>()?;
+        sequences.push(bases);
+    }
+    Ok(sequences)
+}
+
+/// The mean curvature of a sequence with a roll matrix, over the pieces between gaps, or `None`
+/// if the sequence is too short for any value.
+pub fn mean_curvature(seq: &[u8], roll: &Arc<NucMatrix>) -> Option<f64> {
+    let params = PipelineParams::default();
+    let mut stats = RunningStats::new();
+    for piece in seq.split(|base| !matches!(base, b'A' | b'C' | b'G' | b'T')) {
+        let values = CurveIter::new(
+            piece.iter().cloned(),
+            RollType::Custom(Arc::clone(roll)),
+            params.step_b,
+            params.step_c,
+            params.curve_scale,
+            params.edge_mode,
+            params.convention,
+        );
+        for value in values {
+            stats.add(value);
+        }
+    }
+    (stats.count() > 0).then(|| stats.mean())
+}
+
+/// Cohen's d of two samples, using the pooled standard deviation; 0 if it is 0.
+pub fn effect_size(positive: &[f64], negative: &[f64]) -> f64 {
+    let stats = |values: &[f64]| {
+        let mut stats = RunningStats::new();
+        values.iter().for_each(|&v| stats.add(v));
+        stats
+    };
+    let (pos, neg) = (stats(positive), stats(negative));
+    let (n_pos, n_neg) = (pos.count() as f64, neg.count() as f64);
+    let pooled = ((pos.variance() * n_pos + neg.variance() * n_neg) / (n_pos + n_neg)).sqrt();
+    if pooled > 0.0 && pooled.is_finite() {
+        (pos.mean() - neg.mean()) / pooled
+    } else {
+        0.0
+    }
+}
+
+/// Scores a roll matrix against the two sets. Sequences too short for a value are left out.
+pub fn separation(roll: &NucMatrix, positive: &[Vec<u8>], negative: &[Vec<u8>]) -> Separation {
+    let roll = Arc::new(*roll);
+    let scores = |seqs: &[Vec<u8>]| -> Vec<f64> {
+        seqs.iter()
+            .filter_map(|seq| mean_curvature(seq, &roll))
+            .collect()
+    };
+    let (pos, neg) = (scores(positive), scores(negative));
+    let labels: Vec<bool> = pos
+        .iter()
+        .map(|_| true)
+        .chain(neg.iter().map(|_| false))
+        .collect();
+    let all: Vec<f64> = pos.iter().chain(&neg).cloned().collect();
+    Separation {
+        effect_size: effect_size(&pos, &neg),
+        auc_roc: Curves::new(&all, &labels).auc_roc(),
+    }
+}
+
+/// The triplets of the matrix as pairs of indexes of a triplet and its reverse complement, one
+/// pair per strand-symmetric value. In the A, T, G, C index order the complement of index `i` is
+/// `i ^ 1`, and no triplet is its own reverse complement.
+pub fn strand_pairs() -> Vec<([usize; 3], [usize; 3])> {
+    let mut pairs = Vec::new();
+    for i in 0..4 {
+        for j in 0..4 {
+            for k in 0..4 {
+                let triplet = [i, j, k];
+                let reverse = [k ^ 1, j ^ 1, i ^ 1];
+                if triplet < reverse {
+                    pairs.push((triplet, reverse));
+                }
+            }
+        }
+    }
+    pairs
+}
+
+/// Fits the roll matrix by pattern search, starting from the simple roll matrix.
+///
+/// Roll values are kept at 0 or above.
+///
+/// # Arguments
+///
+/// * `positive` - The sequences that should score high, uppercase.
+/// * `negative` - The sequences that should score low, uppercase.
+/// * `iterations` - The most passes over the matrix.
+/// * `step` - The first amount each value is nudged by.
+pub fn fit(positive: &[Vec<u8>], negative: &[Vec<u8>], iterations: usize, step: f64) -> Training {
+    let mut roll = matrix::ROLL_SIMPLE;
+    let initial = separation(&roll, positive, negative);
+    let mut best = initial;
+    let mut step = step;
+    let mut passes = 0;
+    let pairs = strand_pairs();
+    while passes < iterations && step >= MIN_STEP {
+        passes += 1;
+        let mut improved = false;
+        for &([i, j, k], [ri, rj, rk]) in &pairs {
+            let current = roll[i][j][k];
+            for candidate in [current + step, (current - step).max(0.0)] {
+                if candidate == current {
+                    continue;
+                }
+                let mut trial = roll;
+                trial[i][j][k] = candidate;
+                trial[ri][rj][rk] = candidate;
+                let score = separation(&trial, positive, negative);
+                if score.effect_size > best.effect_size {
+                    roll = trial;
+                    best = score;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        if !improved {
+            step /= 2.0;
+        }
+    }
+    Training {
+        roll,
+        initial,
+        fitted: best,
+        passes,
+    }
+}
+
+/// Runs `symcurve train`: fits the roll matrix, writes the YAML matrix file and reports the
+/// separation before and after.
+///
+/// # Errors
+///
+/// Returns an error if either FASTA file cannot be read or has no sequences, or if the output
+/// cannot be written.
+pub fn train<W: Write>(params: &TrainParams, writer: &mut W) -> io::Result<()> {
+    let positive = read_sequences(&params.positive)?;
+    let negative = read_sequences(&params.negative)?;
+    for (path, seqs) in [(&params.positive, &positive), (&params.negative, &negative)] {
+        if seqs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("no sequences found in {}", path.display()),
+            ));
+        }
+    }
+    let training = fit(&positive, &negative, params.iterations, params.step);
+    let mut file = BufWriter::new(File::create(&params.output)?);
+    let comment = format!(
+        "roll matrix fitted by symcurve train\npositive: {}\nnegative: {}\n\
+         effect size {} -> {}, AUC {} -> {}",
+        params.positive.display(),
+        params.negative.display(),
+        training.initial.effect_size,
+        training.fitted.effect_size,
+        training.initial.auc_roc,
+        training.fitted.auc_roc,
+    );
+    matrix::write_matrices_yaml(
+        &mut file,
+        &comment,
+        &matrix::TWIST,
+        &training.roll,
+        &matrix::TILT,
+    )?;
+    file.flush()?;
+    writeln!(writer, "positive\t{}", positive.len())?;
+    writeln!(writer, "negative\t{}", negative.len())?;
+    writeln!(writer, "passes\t{}", training.passes)?;
+    writeln!(
+        writer,
+        "effect_size\t{}\t{}",
+        training.initial.effect_size, training.fitted.effect_size
+    )?;
+    writeln!(
+        writer,
+        "auc_roc\t{}\t{}",
+        training.initial.auc_roc, training.fitted.auc_roc
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_strand_pairs() {
+        let pairs = strand_pairs();
+        assert_eq!(pairs.len(), 32);
+        // the built-in roll matrix is strand-symmetric
+        for ([i, j, k], [ri, rj, rk]) in pairs {
+            assert_eq!(
+                matrix::ROLL_SIMPLE[i][j][k],
+                matrix::ROLL_SIMPLE[ri][rj][rk]
+            );
+        }
+    }
+
+    #[test]
+    fn test_effect_size() {
+        assert_relative_eq!(effect_size(&[2.0, 4.0], &[0.0, 2.0]), 2.0);
+        assert_eq!(effect_size(&[1.0, 1.0], &[1.0, 1.0]), 0.0);
+    }
+
+    #[test]
+    fn test_fit() {
+        let positive = vec![
+            b"AAAATTTTGCAAAATTTTGCAAAATTTTGCAAAATTTTGCAAAATTTTGCAAAATTTTGC".to_vec(),
+            b"AAAAATTTTTCAAAAATTTTTCAAAAATTTTTCAAAAATTTTTCAAAAATTTTTCGGAA".to_vec(),
+        ];
+        let negative = vec![
+            b"GCGCGATCGCGATCGGCGCGATCGCGATCGGCGCGATCGCGATCGGCGCGATCGCGATC".to_vec(),
+            b"CGGCCGATTAGCGCCGTAGCATCGGCGATCAGCGCTAGCGGCATCGACGTCAGCGCGTA".to_vec(),
+        ];
+        let training = fit(&positive, &negative, 3, 1.0);
+        assert_eq!(training.passes, 3);
+        assert!(training.fitted.effect_size > training.initial.effect_size);
+        assert!(training.roll.iter().flatten().flatten().all(|&v| v >= 0.0));
+        for ([i, j, k], [ri, rj, rk]) in strand_pairs() {
+            assert_eq!(training.roll[i][j][k], training.roll[ri][rj][rk]);
+        }
+        // a sequence with no value is left out
+        assert_eq!(mean_curvature(b"ACGT", &Arc::new(training.roll)), None);
+    }
+}
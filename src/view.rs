@@ -0,0 +1,239 @@
+//! # View module
+//! The `symcurve view` subcommand, which reads values back out of an existing output so results
+//! can be spot-checked without loading them into a genome browser.
+//!
+//! bigWig files are recognized by their magic number; anything else is read as bedGraph. Values
+//! are printed as bedGraph lines, clipped to the requested region.
+
+pub mod bedgraph;
+pub mod bigwig;
+
+use crate::numeric::parse_count;
+use crate::view::bigwig::{is_bigwig, BigWigReader};
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// A run of bases sharing one value.
+///
+/// Values are single precision, which is what bigWig stores.
+///
+/// # Fields
+///
+/// * `start`: The 0-based start of the run.
+/// * `end`: The 0-based, exclusive end of the run.
+/// * `value`: The value over the run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interval {
+    pub start: u64,
+    pub end: u64,
+    pub value: f32,
+}
+
+/// A region given on the command line as `chrom` or `chrom:start-end`.
+///
+/// Like samtools regions, the coordinates on the command line are 1-based and inclusive and may
+/// contain commas, and they also take the forms of [`parse_count`], as in `chr1:1M-2M`; they are stored here 0-based and half-open. A chromosome name that contains
+/// `:` followed by digits can be given in braces, as in `{HLA-A*01:01}:1-100`.
+///
+/// # Fields
+///
+/// * `chrom`: The chromosome name.
+/// * `start`: The 0-based start of the region.
+/// * `end`: The 0-based, exclusive end of the region, or `None` for the end of the chromosome.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub chrom: String,
+    pub start: u64,
+    pub end: Option<u64>,
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(rest) = s.strip_prefix('{') {
+            let (chrom, range) = rest
+                .split_once('}')
+                .ok_or_else(|| format!("invalid region {}: unclosed brace", s))?;
+            return match range.strip_prefix(':') {
+                Some(range) => {
+                    Self::from_str(&format!("{}:{}", chrom, range)).map(|region| Region {
+                        chrom: chrom.to_string(),
+                        ..region
+                    })
+                }
+                None if range.is_empty() => Ok(Region {
+                    chrom: chrom.to_string(),
+                    start: 0,
+                    end: None,
+                }),
+                None => Err(format!("invalid region {}", s)),
+            };
+        }
+        let whole = Region {
+            chrom: s.to_string(),
+            start: 0,
+            end: None,
+        };
+        // chromosome names may contain ':' themselves, so only a parseable range counts
+        let Some((chrom, range)) = s.rsplit_once(':') else {
+            return Ok(whole);
+        };
+        let parse = |text: &str| parse_count(&text.replace(',', "")).ok();
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse(start), parse(end)),
+            None => (parse(range), parse(range)),
+        };
+        match (start, end) {
+            (Some(start), Some(end)) if start >= 1 && end >= start => Ok(Region {
+                chrom: chrom.to_string(),
+                start: start - 1,
+                end: Some(end),
+            }),
+            (Some(_), Some(_)) => Err(format!("invalid region {}: start must be 1 to end", s)),
+            _ => Ok(whole),
+        }
+    }
+}
+
+/// Prints the values of an output file, or of one region of it, as bedGraph lines.
+///
+/// # Arguments
+///
+/// * `path` - The bigWig or bedGraph file.
+/// * `region` - The region to print, or `None` for the whole file.
+/// * `writer` - Where the lines are written.
+pub fn view<W: Write>(path: &Path, region: Option<&Region>, writer: &mut W) -> io::Result<()> {
+    for (chrom, interval) in read_track(path, region)? {
+        write_interval(writer, &chrom, &interval)?;
+    }
+    Ok(())
+}
+
+/// Reads the values of a bigWig or bedGraph file, or of one region of it.
+///
+/// # Arguments
+///
+/// * `path` - The bigWig or bedGraph file.
+/// * `region` - The region to read, or `None` for the whole file.
+///
+/// # Returns
+///
+/// The chromosome name and interval of each run of values, in file order, with the intervals
+/// clipped to the region.
+pub fn read_track(path: &Path, region: Option<&Region>) -> io::Result<Vec<(String, Interval)>> {
+    let start = region.map_or(0, |region| region.start);
+    let end = region.and_then(|region| region.end).unwrap_or(u64::MAX);
+    if is_bigwig(path)? {
+        let mut reader = BigWigReader::open(path)?;
+        let chroms: Vec<String> = match region {
+            Some(region) => vec![region.chrom.clone()],
+            None => reader
+                .chroms()
+                .iter()
+                .map(|chrom| chrom.name.clone())
+                .collect(),
+        };
+        let mut intervals = Vec::new();
+        for chrom in chroms {
+            for interval in reader.values(&chrom, start, end)? {
+                intervals.push((chrom.clone(), interval));
+            }
+        }
+        Ok(intervals)
+    } else {
+        let chrom = region.map(|region| region.chrom.as_str());
+        let reader = BufReader::new(File::open(path)?);
+        bedgraph::read_bedgraph(reader, chrom, start, end)
+    }
+}
+
+/// Writes one interval as a bedGraph line.
+fn write_interval<W: Write>(writer: &mut W, chrom: &str, interval: &Interval) -> io::Result<()> {
+    writeln!(
+        writer,
+        "{}\t{}\t{}\t{}",
+        chrom, interval.start, interval.end, interval.value
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_parse_region() {
+        assert_eq!(
+            "chr1:1,000-2,000".parse::<Region>().unwrap(),
+            Region {
+                chrom: "chr1".to_string(),
+                start: 999,
+                end: Some(2000)
+            }
+        );
+        assert_eq!(
+            "chr1:5".parse::<Region>().unwrap(),
+            Region {
+                chrom: "chr1".to_string(),
+                start: 4,
+                end: Some(5)
+            }
+        );
+        assert_eq!("chrM".parse::<Region>().unwrap().end, None);
+        assert_eq!(
+            "HLA-A*01:01:01:01N".parse::<Region>().unwrap().chrom,
+            "HLA-A*01:01:01:01N"
+        );
+        assert_eq!(
+            "{HLA-A*01:01}".parse::<Region>().unwrap(),
+            Region {
+                chrom: "HLA-A*01:01".to_string(),
+                start: 0,
+                end: None
+            }
+        );
+        assert_eq!(
+            "{HLA-A*01:01}:11-20".parse::<Region>().unwrap(),
+            Region {
+                chrom: "HLA-A*01:01".to_string(),
+                start: 10,
+                end: Some(20)
+            }
+        );
+        assert_eq!(
+            "chr2:1M-2_000k".parse::<Region>().unwrap(),
+            Region {
+                chrom: "chr2".to_string(),
+                start: 999_999,
+                end: Some(2_000_000)
+            }
+        );
+        assert!("{HLA-A*01:01".parse::<Region>().is_err());
+        assert!("chr1:2000-1000".parse::<Region>().is_err());
+        assert!("chr1:0-10".parse::<Region>().is_err());
+    }
+
+    #[test]
+    fn test_view_bedgraph() {
+        let path = std::env::temp_dir().join("symcurve_test_view.bedGraph");
+        fs::write(
+            &path,
+            "chr1\t0\t10\t1.5\nchr1\t10\t20\t2.5\nchr2\t0\t10\t3\n",
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        let region = "chr1:6-12".parse::<Region>().unwrap();
+        view(&path, Some(&region), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "chr1\t5\t10\t1.5\nchr1\t10\t12\t2.5\n"
+        );
+        let mut out = Vec::new();
+        view(&path, None, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 3);
+        fs::remove_file(&path).unwrap();
+    }
+}
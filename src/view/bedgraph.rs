@@ -0,0 +1,103 @@
+//! Reading values back from bedGraph files.
+
+use crate::text::clean_line;
+use crate::view::Interval;
+use std::io::{self, BufRead};
+
+/// Reads the values of a bedGraph file that overlap a region.
+///
+/// `track` and `browser` lines, `#` comments and blank lines are skipped. Lines are expected in
+/// `chrom start end value` form with 0-based, half-open coordinates; CRLF line endings, a byte
+/// order mark and trailing whitespace are ignored.
+///
+/// # Arguments
+///
+/// * `reader` - The bedGraph text.
+/// * `chrom` - The chromosome to read, or `None` for all of them.
+/// * `start` - The 0-based start of the region.
+/// * `end` - The 0-based, exclusive end of the region.
+///
+/// # Returns
+///
+/// The chromosome name and interval of each line overlapping the region, in file order, with the
+/// intervals clipped to the region.
+///
+/// # Errors
+///
+/// Returns an `io::ErrorKind::InvalidData` error naming the line number for a malformed line.
+pub fn read_bedgraph<R: BufRead>(
+    reader: R,
+    chrom: Option<&str>,
+    start: u64,
+    end: u64,
+) -> io::Result<Vec<(String, Interval)>> {
+    let mut intervals = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = clean_line(&line);
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid bedGraph line {}: {}", number + 1, line),
+            )
+        };
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err(invalid());
+        }
+        if chrom.is_some_and(|chrom| chrom != fields[0]) {
+            continue;
+        }
+        let line_start: u64 = fields[1].parse().map_err(|_| invalid())?;
+        let line_end: u64 = fields[2].parse().map_err(|_| invalid())?;
+        let value: f32 = fields[3].parse().map_err(|_| invalid())?;
+        if line_start < end && line_end > start {
+            intervals.push((
+                fields[0].to_string(),
+                Interval {
+                    start: line_start.max(start),
+                    end: line_end.min(end),
+                    value,
+                },
+            ));
+        }
+    }
+    Ok(intervals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_bedgraph() {
+        let text = "track type=bedGraph name=curve\n\
+                    # symcurve version: 0.1.0\n\
+                    chr1\t0\t10\t1.5\n\
+                    chr1\t10\t20\tnan\n\
+                    chr2\t0\t5\t3\n";
+        let all = read_bedgraph(text.as_bytes(), None, 0, u64::MAX).unwrap();
+        assert_eq!(all.len(), 3);
+        assert!(all[1].1.value.is_nan());
+        let some = read_bedgraph(text.as_bytes(), Some("chr1"), 5, 12).unwrap();
+        assert_eq!(some.len(), 2);
+        assert_eq!(
+            (some[0].1.start, some[0].1.end, some[0].1.value),
+            (5, 10, 1.5)
+        );
+        assert_eq!((some[1].1.start, some[1].1.end), (10, 12));
+        let err = read_bedgraph("chr1\t0\tten\t1\n".as_bytes(), None, 0, 10).unwrap_err();
+        assert!(err.to_string().contains("line 1"));
+        let windows = "\u{feff}chr1\t0\t10\t1.5 \r\nchr1\t10\t20\t2\r\n";
+        let all = read_bedgraph(windows.as_bytes(), None, 0, 20).unwrap();
+        assert_eq!((all[0].0.as_str(), all[0].1.value), ("chr1", 1.5));
+        assert_eq!(all.len(), 2);
+    }
+}
@@ -0,0 +1,529 @@
+//! Reading values back from bigWig files.
+//!
+//! Only what `symcurve view` needs is implemented: the chromosome B+ tree, the R-tree index over
+//! the full-resolution data, and the three kinds of data section (bedGraph, variableStep and
+//! fixedStep), along with the provenance comments symcurve keeps in the extension header. Zoom
+//! levels and summaries are ignored. Files written on either byte order are read, as the format allows.
+
+use crate::view::Interval;
+use flate2::read::ZlibDecoder;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The magic number at the start of every bigWig file.
+pub const BIGWIG_MAGIC: u32 = 0x888F_FC26;
+/// The magic number at the start of the chromosome B+ tree.
+pub const CHROM_TREE_MAGIC: u32 = 0x78CA_8C91;
+/// The magic number at the start of the R-tree index.
+pub const R_TREE_MAGIC: u32 = 0x2468_ACE0;
+/// The size of the fields of the extension header, which the provenance comments follow.
+pub const EXTENSION_HEADER_BYTES: u64 = 64;
+
+/// A chromosome listed in a bigWig file.
+///
+/// # Fields
+///
+/// * `name`: The chromosome name.
+/// * `id`: The id data sections and the index refer to the chromosome by.
+/// * `length`: The chromosome length.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChromInfo {
+    pub name: String,
+    pub id: u32,
+    pub length: u32,
+}
+
+/// Decodes fixed-size fields in the byte order of the file.
+#[derive(Clone, Copy, Debug)]
+struct Fields {
+    big_endian: bool,
+}
+
+impl Fields {
+    fn u16(&self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    fn u64(&self, bytes: &[u8]) -> u64 {
+        let mut array = [0u8; 8];
+        array.copy_from_slice(&bytes[..8]);
+        if self.big_endian {
+            u64::from_be_bytes(array)
+        } else {
+            u64::from_le_bytes(array)
+        }
+    }
+
+    fn f32(&self, bytes: &[u8]) -> f32 {
+        f32::from_bits(self.u32(bytes))
+    }
+}
+
+/// An error for a file that is not laid out the way the bigWig format says.
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid bigWig: {}", message),
+    )
+}
+
+/// Reader for the full-resolution data of a bigWig file.
+///
+/// # Fields
+///
+/// * `inner`: The file.
+/// * `fields`: The byte order of the file.
+/// * `chroms`: The chromosomes, in id order.
+/// * `full_index_offset`: Where the R-tree index of the data sections starts.
+/// * `compressed`: Whether data sections are zlib-compressed.
+/// * `extension_offset`: Where the extension header starts, or 0 if there is none.
+pub struct BigWigReader<R: Read + Seek> {
+    inner: R,
+    fields: Fields,
+    chroms: Vec<ChromInfo>,
+    full_index_offset: u64,
+    compressed: bool,
+    extension_offset: u64,
+}
+
+impl BigWigReader<BufReader<File>> {
+    /// Opens a bigWig file.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> BigWigReader<R> {
+    /// Reads the header and chromosome list of a bigWig file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::ErrorKind::InvalidData` error if the input is not a bigWig file.
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let header = read_at(&mut inner, 0, 64)?;
+        let magic = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let fields = if magic == BIGWIG_MAGIC {
+            Fields { big_endian: false }
+        } else if magic.swap_bytes() == BIGWIG_MAGIC {
+            Fields { big_endian: true }
+        } else {
+            return Err(invalid("bad magic number"));
+        };
+        let chrom_tree_offset = fields.u64(&header[8..]);
+        let full_index_offset = fields.u64(&header[24..]);
+        let compressed = fields.u32(&header[52..]) > 0;
+        let extension_offset = fields.u64(&header[56..]);
+        let mut reader = Self {
+            inner,
+            fields,
+            chroms: Vec::new(),
+            full_index_offset,
+            compressed,
+            extension_offset,
+        };
+        reader.read_chrom_tree(chrom_tree_offset)?;
+        reader.chroms.sort_by_key(|chrom| chrom.id);
+        Ok(reader)
+    }
+
+    /// The chromosomes in the file, in id order.
+    pub fn chroms(&self) -> &[ChromInfo] {
+        &self.chroms
+    }
+
+    /// The provenance comment lines symcurve wrote after the fields of the extension header (see
+    /// [`BigWigWriter::with_provenance`]), or `None` if the file has none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::ErrorKind::InvalidData` error if the text is not UTF-8, or any error from
+    /// reading.
+    ///
+    /// [`BigWigWriter::with_provenance`]: crate::output::bigwig::BigWigWriter::with_provenance
+    pub fn comments(&mut self) -> io::Result<Option<String>> {
+        if self.extension_offset == 0 {
+            return Ok(None);
+        }
+        let header = read_at(&mut self.inner, self.extension_offset, 2)?;
+        let size = u64::from(self.fields.u16(&header));
+        if size <= EXTENSION_HEADER_BYTES {
+            return Ok(None);
+        }
+        let text = read_at(
+            &mut self.inner,
+            self.extension_offset + EXTENSION_HEADER_BYTES,
+            (size - EXTENSION_HEADER_BYTES) as usize,
+        )?;
+        String::from_utf8(text)
+            .map(Some)
+            .map_err(|_| invalid("extension header comments are not UTF-8"))
+    }
+
+    /// Reads the chromosome B+ tree.
+    fn read_chrom_tree(&mut self, offset: u64) -> io::Result<()> {
+        let header = read_at(&mut self.inner, offset, 32)?;
+        if self.fields.u32(&header) != CHROM_TREE_MAGIC {
+            return Err(invalid("bad chromosome tree"));
+        }
+        let key_size = self.fields.u32(&header[8..]) as usize;
+        self.read_chrom_node(offset + 32, key_size)
+    }
+
+    /// Reads one node of the chromosome B+ tree and everything below it.
+    fn read_chrom_node(&mut self, offset: u64, key_size: usize) -> io::Result<()> {
+        let node = read_at(&mut self.inner, offset, 4)?;
+        let is_leaf = node[0] == 1;
+        let count = self.fields.u16(&node[2..]) as usize;
+        let item_size = key_size + 8;
+        let items = read_at(&mut self.inner, offset + 4, count * item_size)?;
+        for item in items.chunks_exact(item_size) {
+            if is_leaf {
+                let key = &item[..key_size];
+                let end = key.iter().position(|&b| b == 0).unwrap_or(key_size);
+                self.chroms.push(ChromInfo {
+                    name: String::from_utf8_lossy(&key[..end]).into_owned(),
+                    id: self.fields.u32(&item[key_size..]),
+                    length: self.fields.u32(&item[key_size + 4..]),
+                });
+            } else {
+                let child = self.fields.u64(&item[key_size..]);
+                self.read_chrom_node(child, key_size)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the values overlapping a region.
+    ///
+    /// # Arguments
+    ///
+    /// * `chrom` - The chromosome name.
+    /// * `start` - The 0-based start of the region.
+    /// * `end` - The 0-based, exclusive end of the region.
+    ///
+    /// # Returns
+    ///
+    /// The intervals overlapping the region, in order, clipped to it. An unknown chromosome
+    /// gives an `io::ErrorKind::NotFound` error.
+    pub fn values(&mut self, chrom: &str, start: u64, end: u64) -> io::Result<Vec<Interval>> {
+        let id = self
+            .chroms
+            .iter()
+            .find(|info| info.name == chrom)
+            .map(|info| info.id)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no chromosome {} in the bigWig file", chrom),
+                )
+            })?;
+        let header = read_at(&mut self.inner, self.full_index_offset, 48)?;
+        if self.fields.u32(&header) != R_TREE_MAGIC {
+            return Err(invalid("bad index"));
+        }
+        let mut blocks = Vec::new();
+        let query = (id, start, end);
+        self.find_blocks(self.full_index_offset + 48, query, &mut blocks)?;
+        let mut intervals = Vec::new();
+        for (offset, size) in blocks {
+            self.read_block(offset, size, query, &mut intervals)?;
+        }
+        intervals.sort_by_key(|interval| interval.start);
+        Ok(intervals)
+    }
+
+    /// Collects the data blocks under an R-tree node that overlap the query.
+    fn find_blocks(
+        &mut self,
+        offset: u64,
+        query: (u32, u64, u64),
+        blocks: &mut Vec<(u64, u64)>,
+    ) -> io::Result<()> {
+        let node = read_at(&mut self.inner, offset, 4)?;
+        let is_leaf = node[0] == 1;
+        let count = self.fields.u16(&node[2..]) as usize;
+        let item_size = if is_leaf { 32 } else { 24 };
+        let items = read_at(&mut self.inner, offset + 4, count * item_size)?;
+        let (id, start, end) = query;
+        for item in items.chunks_exact(item_size) {
+            let item_start = (self.fields.u32(item), self.fields.u32(&item[4..]) as u64);
+            let item_end = (
+                self.fields.u32(&item[8..]),
+                self.fields.u32(&item[12..]) as u64,
+            );
+            if item_start >= (id, end) || item_end <= (id, start) {
+                continue;
+            }
+            if is_leaf {
+                blocks.push((self.fields.u64(&item[16..]), self.fields.u64(&item[24..])));
+            } else {
+                self.find_blocks(self.fields.u64(&item[16..]), query, blocks)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes one data block and collects its values that overlap the query.
+    fn read_block(
+        &mut self,
+        offset: u64,
+        size: u64,
+        query: (u32, u64, u64),
+        intervals: &mut Vec<Interval>,
+    ) -> io::Result<()> {
+        let raw = read_at(&mut self.inner, offset, size as usize)?;
+        let data = if self.compressed {
+            let mut data = Vec::new();
+            ZlibDecoder::new(&raw[..]).read_to_end(&mut data)?;
+            data
+        } else {
+            raw
+        };
+        if data.len() < 24 {
+            return Err(invalid("short data section"));
+        }
+        let fields = self.fields;
+        let (id, start, end) = query;
+        if fields.u32(&data) != id {
+            return Ok(());
+        }
+        let section_start = fields.u32(&data[4..]) as u64;
+        let step = fields.u32(&data[12..]) as u64;
+        let span = fields.u32(&data[16..]) as u64;
+        let kind = data[20];
+        let count = fields.u16(&data[22..]) as usize;
+        let items = &data[24..];
+        let item_size = match kind {
+            1 => 12,
+            2 => 8,
+            3 => 4,
+            _ => return Err(invalid("unknown data section type")),
+        };
+        if items.len() < count * item_size {
+            return Err(invalid("short data section"));
+        }
+        for (i, item) in items.chunks_exact(item_size).take(count).enumerate() {
+            let (item_start, item_end, value) = match kind {
+                1 => (
+                    fields.u32(item) as u64,
+                    fields.u32(&item[4..]) as u64,
+                    fields.f32(&item[8..]),
+                ),
+                2 => {
+                    let item_start = fields.u32(item) as u64;
+                    (item_start, item_start + span, fields.f32(&item[4..]))
+                }
+                _ => {
+                    let item_start = section_start + i as u64 * step;
+                    (item_start, item_start + span, fields.f32(item))
+                }
+            };
+            if item_start < end && item_end > start {
+                intervals.push(Interval {
+                    start: item_start.max(start),
+                    end: item_end.min(end),
+                    value,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads `len` bytes starting at `offset`.
+fn read_at<R: Read + Seek>(inner: &mut R, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    inner.seek(SeekFrom::Start(offset))?;
+    let mut buf = vec![0u8; len];
+    inner.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Whether a file starts with the bigWig magic number, in either byte order.
+pub fn is_bigwig(path: &Path) -> io::Result<bool> {
+    let mut magic = [0u8; 4];
+    let mut file = File::open(path)?;
+    match file.read_exact(&mut magic) {
+        Ok(()) => {
+            let magic = u32::from_le_bytes(magic);
+            Ok(magic == BIGWIG_MAGIC || magic.swap_bytes() == BIGWIG_MAGIC)
+        }
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::{Cursor, Write};
+
+    /// Builds a small little-endian bigWig with one chromosome and the given data sections, each
+    /// in its own block, following the layout in the UCSC bigWig specification.
+    fn build_bigwig(chrom: &str, length: u32, sections: &[Vec<u8>], compress: bool) -> Vec<u8> {
+        let key_size = chrom.len() as u32;
+        let mut file = vec![0u8; 64];
+        // chromosome B+ tree with a single leaf
+        let chrom_tree_offset = file.len() as u64;
+        file.extend(CHROM_TREE_MAGIC.to_le_bytes());
+        file.extend(256u32.to_le_bytes());
+        file.extend(key_size.to_le_bytes());
+        file.extend(8u32.to_le_bytes());
+        file.extend(1u64.to_le_bytes());
+        file.extend(0u64.to_le_bytes());
+        file.extend([1u8, 0]);
+        file.extend(1u16.to_le_bytes());
+        file.extend(chrom.as_bytes());
+        file.extend(0u32.to_le_bytes());
+        file.extend(length.to_le_bytes());
+        // data sections
+        let full_data_offset = file.len() as u64;
+        file.extend((sections.len() as u64).to_le_bytes());
+        let mut blocks = Vec::new();
+        for section in sections {
+            let data = if compress {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(section).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                section.clone()
+            };
+            let start = u32::from_le_bytes(section[4..8].try_into().unwrap());
+            let end = u32::from_le_bytes(section[8..12].try_into().unwrap());
+            blocks.push((start, end, file.len() as u64, data.len() as u64));
+            file.extend(data);
+        }
+        // R-tree index with a single leaf
+        let full_index_offset = file.len() as u64;
+        file.extend(R_TREE_MAGIC.to_le_bytes());
+        file.extend([0u8; 44]);
+        file.extend([1u8, 0]);
+        file.extend((blocks.len() as u16).to_le_bytes());
+        for (start, end, offset, size) in blocks {
+            file.extend(0u32.to_le_bytes());
+            file.extend(start.to_le_bytes());
+            file.extend(0u32.to_le_bytes());
+            file.extend(end.to_le_bytes());
+            file.extend(offset.to_le_bytes());
+            file.extend(size.to_le_bytes());
+        }
+        // header
+        file[0..4].copy_from_slice(&BIGWIG_MAGIC.to_le_bytes());
+        file[4..6].copy_from_slice(&4u16.to_le_bytes());
+        file[8..16].copy_from_slice(&chrom_tree_offset.to_le_bytes());
+        file[16..24].copy_from_slice(&full_data_offset.to_le_bytes());
+        file[24..32].copy_from_slice(&full_index_offset.to_le_bytes());
+        let buf_size: u32 = if compress { 32768 } else { 0 };
+        file[52..56].copy_from_slice(&buf_size.to_le_bytes());
+        file
+    }
+
+    /// Builds a data section header followed by its items.
+    fn section(
+        kind: u8,
+        start: u32,
+        end: u32,
+        step: u32,
+        span: u32,
+        items: &[u8],
+        count: u16,
+    ) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(0u32.to_le_bytes());
+        data.extend(start.to_le_bytes());
+        data.extend(end.to_le_bytes());
+        data.extend(step.to_le_bytes());
+        data.extend(span.to_le_bytes());
+        data.extend([kind, 0]);
+        data.extend(count.to_le_bytes());
+        data.extend(items);
+        data
+    }
+
+    fn test_sections() -> Vec<Vec<u8>> {
+        let mut bedgraph = Vec::new();
+        for (start, end, value) in [(0u32, 10u32, 1.5f32), (10, 20, 2.5)] {
+            bedgraph.extend(start.to_le_bytes());
+            bedgraph.extend(end.to_le_bytes());
+            bedgraph.extend(value.to_le_bytes());
+        }
+        let mut var_step = Vec::new();
+        for (start, value) in [(20u32, 3.0f32), (30, 4.0)] {
+            var_step.extend(start.to_le_bytes());
+            var_step.extend(value.to_le_bytes());
+        }
+        let fixed_step: Vec<u8> = [5.0f32, 6.0, 7.0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        vec![
+            section(1, 0, 20, 0, 0, &bedgraph, 2),
+            section(2, 20, 35, 0, 5, &var_step, 2),
+            section(3, 40, 43, 1, 1, &fixed_step, 3),
+        ]
+    }
+
+    #[test]
+    fn test_read_bigwig() {
+        for compress in [false, true] {
+            let file = build_bigwig("chr1", 100, &test_sections(), compress);
+            let mut reader = BigWigReader::new(Cursor::new(file)).unwrap();
+            assert_eq!(
+                reader.chroms(),
+                &[ChromInfo {
+                    name: "chr1".to_string(),
+                    id: 0,
+                    length: 100
+                }]
+            );
+            let all = reader.values("chr1", 0, 100).unwrap();
+            let expected = [
+                (0, 10, 1.5),
+                (10, 20, 2.5),
+                (20, 25, 3.0),
+                (30, 35, 4.0),
+                (40, 41, 5.0),
+                (41, 42, 6.0),
+                (42, 43, 7.0),
+            ];
+            assert_eq!(all.len(), expected.len());
+            for (interval, (start, end, value)) in all.iter().zip(expected) {
+                assert_eq!(
+                    (interval.start, interval.end, interval.value),
+                    (start, end, value)
+                );
+            }
+            // clipped to the region
+            let some = reader.values("chr1", 15, 41).unwrap();
+            assert_eq!(some.len(), 4);
+            assert_eq!((some[0].start, some[0].end), (15, 20));
+            assert_eq!((some[3].start, some[3].end), (40, 41));
+            assert_eq!(
+                reader.values("chr2", 0, 10).unwrap_err().kind(),
+                io::ErrorKind::NotFound
+            );
+        }
+    }
+
+    #[test]
+    fn test_not_bigwig() {
+        let err = BigWigReader::new(Cursor::new(vec![0u8; 64])).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,183 @@
+//! # Warnings module
+//! Non-fatal anomalies found over a run, counted by category and reported once at the end, so a
+//! genome with thousands of odd contigs produces a short summary rather than thousands of lines.
+
+use crate::fasta::validate::MalformedRecord;
+use crate::pipeline::PipelineSummary;
+use crate::stats::ClipStats;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The categories of non-fatal anomalies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WarningKind {
+    /// IUPAC ambiguity codes and other bases that are neither `A`, `C`, `G`, `T` nor `N`.
+    AmbiguousBases,
+    /// `N` bases, which are gaps with no value. The bases too close to the end of a sequence
+    /// piece for a window have none either, but are expected and not counted.
+    SkippedBases,
+    /// Sequences too short to have a single value.
+    ShortContigs,
+    /// Values changed by `--clip`.
+    ClippedValues,
+    /// Sequences identical to one earlier in the input.
+    DuplicateContigs,
+    /// Malformed records left out with `--on-malformed skip`.
+    MalformedRecords,
+}
+
+impl WarningKind {
+    /// What is being counted, as printed in the summary.
+    pub fn description(&self) -> &'static str {
+        match self {
+            WarningKind::AmbiguousBases => "ambiguous bases (treated as gaps)",
+            WarningKind::SkippedBases => "N bases without a value",
+            WarningKind::ShortContigs => "sequences too short for a value",
+            WarningKind::ClippedValues => "values clipped",
+            WarningKind::DuplicateContigs => "sequences duplicating an earlier one",
+            WarningKind::MalformedRecords => "malformed records skipped",
+        }
+    }
+
+    /// A stable snake_case name, as used in `.issues.jsonl` records.
+    pub fn code(&self) -> &'static str {
+        match self {
+            WarningKind::AmbiguousBases => "ambiguous_bases",
+            WarningKind::SkippedBases => "skipped_bases",
+            WarningKind::ShortContigs => "short_contigs",
+            WarningKind::ClippedValues => "clipped_values",
+            WarningKind::DuplicateContigs => "duplicate_contigs",
+            WarningKind::MalformedRecords => "malformed_records",
+        }
+    }
+}
+
+/// Counts of non-fatal anomalies by category.
+///
+/// # Fields
+///
+/// * `counts`: The number of occurrences of each kind seen so far.
+/// * `examples`: Where each kind was first seen, e.g. a sequence name.
+#[derive(Debug, Default)]
+pub struct Warnings {
+    counts: BTreeMap<WarningKind, u64>,
+    examples: BTreeMap<WarningKind, String>,
+}
+
+impl Warnings {
+    /// Constructor for `Warnings`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts occurrences of an anomaly.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The category.
+    /// * `count` - How many occurrences; nothing is recorded for 0.
+    /// * `context` - Where they were seen, kept for the first occurrence of each kind.
+    pub fn record(&mut self, kind: WarningKind, count: u64, context: &str) {
+        if count == 0 {
+            return;
+        }
+        *self.counts.entry(kind).or_insert(0) += count;
+        self.examples
+            .entry(kind)
+            .or_insert_with(|| context.to_string());
+    }
+
+    /// Counts the anomalies in what one sequence produced.
+    pub fn record_sequence(&mut self, chrom: &str, summary: &PipelineSummary) {
+        self.record(WarningKind::AmbiguousBases, summary.ambiguous_bases, chrom);
+        self.record(WarningKind::SkippedBases, summary.gap_bases, chrom);
+        self.record(WarningKind::ShortContigs, summary.short_records, chrom);
+    }
+
+    /// Counts the values changed by `--clip`.
+    pub fn record_clipped(&mut self, stats: &ClipStats) {
+        self.record(WarningKind::ClippedValues, stats.total(), "--clip");
+    }
+
+    /// Counts a malformed record that was skipped.
+    pub fn record_malformed(&mut self, record: &MalformedRecord) {
+        self.record(WarningKind::MalformedRecords, 1, &record.label());
+    }
+
+    /// The number of occurrences of a kind.
+    pub fn count(&self, kind: WarningKind) -> u64 {
+        self.counts.get(&kind).copied().unwrap_or(0)
+    }
+
+    /// The kinds recorded so far with their counts, in a fixed order.
+    pub fn iter(&self) -> impl Iterator<Item = (WarningKind, u64)> + '_ {
+        self.counts.iter().map(|(&kind, &count)| (kind, count))
+    }
+
+    /// Whether nothing was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+}
+
+impl fmt::Display for Warnings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total: u64 = self.counts.values().sum();
+        writeln!(f, "warnings: {}", total)?;
+        for (kind, count) in &self.counts {
+            write!(f, "  {:>10} {}", count, kind.description())?;
+            match self.examples.get(kind) {
+                Some(example) => writeln!(f, ", first in {}", example)?,
+                None => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fasta::validate::Malformation;
+
+    #[test]
+    fn test_warnings_summary() {
+        let mut warnings = Warnings::new();
+        assert!(warnings.is_empty());
+        warnings.record(WarningKind::ShortContigs, 1, "chrUn_1");
+        warnings.record(WarningKind::ShortContigs, 2, "chrUn_7");
+        warnings.record(WarningKind::DuplicateContigs, 0, "chrM");
+        warnings.record_clipped(&ClipStats { below: 0, above: 4 });
+        warnings.record_malformed(&MalformedRecord {
+            index: 12,
+            name: "chr9".to_string(),
+            problem: Malformation::EmptyName,
+        });
+        assert_eq!(warnings.count(WarningKind::ShortContigs), 3);
+        assert_eq!(warnings.count(WarningKind::DuplicateContigs), 0);
+        assert_eq!(
+            warnings.to_string(),
+            "warnings: 8\n\
+             \x20          3 sequences too short for a value, first in chrUn_1\n\
+             \x20          4 values clipped, first in --clip\n\
+             \x20          1 malformed records skipped, first in record 12 (chr9)\n"
+        );
+    }
+
+    #[test]
+    fn test_record_sequence() {
+        let mut warnings = Warnings::new();
+        let summary = PipelineSummary {
+            records: 1,
+            bases: 60,
+            values: 10,
+            gap_bases: 8,
+            ambiguous_bases: 2,
+            short_records: 0,
+        };
+        warnings.record_sequence("chr1", &summary);
+        assert_eq!(warnings.count(WarningKind::SkippedBases), 8);
+        assert_eq!(warnings.count(WarningKind::AmbiguousBases), 2);
+        assert_eq!(warnings.count(WarningKind::ShortContigs), 0);
+    }
+}
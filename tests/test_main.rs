@@ -9,3 +9,462 @@ fn test_app_runs() {
         .expect("Failed to execute command");
     assert!(String::from_utf8_lossy(&output.stdout).starts_with("symcurve"));
 }
+
+#[test]
+fn test_version_verbose() {
+    let output = Command::new("target/debug/symcurve")
+        .args(["-V", "--verbose"])
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("symcurve"));
+    assert!(stdout.contains("\ncommit: "));
+    assert!(stdout.contains("\nnoodles-fasta: "));
+}
+
+#[test]
+fn test_self_test() {
+    let output = Command::new("target/debug/symcurve")
+        .arg("--self-test")
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("3 of 3 checks passed"));
+}
+
+#[test]
+fn test_view() {
+    let path = std::env::temp_dir().join("symcurve_test_main_view.bedGraph");
+    std::fs::write(&path, "chr1\t0\t10\t1.5\nchr1\t10\t20\t2.5\n").unwrap();
+    let output = Command::new("target/debug/symcurve")
+        .arg("view")
+        .arg(&path)
+        .arg("chr1:11-15")
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "chr1\t10\t15\t2.5\n"
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_stats_file() {
+    let dir = std::env::temp_dir().join("symcurve_test_main_stats_file");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("in.fa");
+    let stats = dir.join("usage.json");
+    let _ = std::fs::remove_file(&stats);
+    std::fs::write(&input, format!(">chr1\n{}\n", "ACGTTTAAAGGC".repeat(10))).unwrap();
+    for output in ["out.bedGraph", "out.tsv"] {
+        let output = Command::new("target/debug/symcurve")
+            .arg("--force")
+            .arg("--stats-file")
+            .arg(&stats)
+            .arg(&input)
+            .arg(dir.join(output))
+            .output()
+            .expect("Failed to execute command");
+        assert!(output.status.success());
+    }
+    // a run failing before it reads the input is counted too
+    let output = Command::new("target/debug/symcurve")
+        .arg("--force")
+        .arg("--stats-file")
+        .arg(&stats)
+        .arg("--matrices")
+        .arg(dir.join("missing.yaml"))
+        .arg(&input)
+        .arg(dir.join("out.tsv"))
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    let text = std::fs::read_to_string(&stats).unwrap();
+    assert!(text.contains("\"runs\": 2"));
+    assert!(text.contains("\"bases\": 240"));
+    assert!(text.contains("\"bedgraph\": 1"));
+    assert!(text.contains("\"failed_runs\": 1"));
+}
+
+#[test]
+fn test_stdin_input() {
+    use std::io::Write;
+    use std::process::Stdio;
+    let dir = std::env::temp_dir().join("symcurve_test_main_stdin_input");
+    std::fs::create_dir_all(&dir).unwrap();
+    let fasta = format!(">chr1\n{}\n", "ACGTTTAAAGGC".repeat(10));
+    let input = dir.join("in.fa");
+    std::fs::write(&input, &fasta).unwrap();
+    let from_file = dir.join("file.bedGraph");
+    let output = Command::new("target/debug/symcurve")
+        .arg("--force")
+        .arg(&input)
+        .arg(&from_file)
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    let from_stdin = dir.join("stdin.bedGraph");
+    let mut child = Command::new("target/debug/symcurve")
+        .arg("--force")
+        .arg("-")
+        .arg(&from_stdin)
+        .stdin(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute command");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(fasta.as_bytes())
+        .unwrap();
+    assert!(child.wait().unwrap().success());
+    assert_eq!(
+        std::fs::read(&from_stdin).unwrap(),
+        std::fs::read(&from_file).unwrap()
+    );
+}
+
+/// Builds a tar archive of `(name, contents)` members.
+fn tar(members: &[(&str, &str)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for &(name, contents) in members {
+        let mut header = [0u8; 512];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..107].copy_from_slice(b"0000644");
+        header[124..135].copy_from_slice(format!("{:011o}", contents.len()).as_bytes());
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[148..156].fill(b' ');
+        let sum: u32 = header.iter().map(|&b| b as u32).sum();
+        header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        out.extend_from_slice(&header);
+        out.extend_from_slice(contents.as_bytes());
+        out.resize(out.len().div_ceil(512) * 512, 0);
+    }
+    out.resize(out.len() + 1024, 0);
+    out
+}
+
+#[test]
+fn test_archive_input() {
+    let dir = std::env::temp_dir().join("symcurve_test_main_archive_input");
+    std::fs::create_dir_all(&dir).unwrap();
+    let seq = "ACGTTTAAAGGC".repeat(10);
+    let (chr1, chr2) = (format!(">chr1\n{}\n", seq), format!(">chr2\n{}\n", seq));
+    // recognized by its content, whatever its name
+    let input = dir.join("bundle.dat");
+    std::fs::write(
+        &input,
+        tar(&[("bundle/chr1.fa", &chr1), ("bundle/chr2.fa", &chr2)]),
+    )
+    .unwrap();
+    let run = |extra: &[&str]| {
+        let output = dir.join("out.tsv");
+        let status = Command::new("target/debug/symcurve")
+            .arg("--force")
+            .args(extra)
+            .arg(&input)
+            .arg(&output)
+            .status()
+            .expect("Failed to execute command");
+        assert!(status.success());
+        let text = std::fs::read_to_string(&output).unwrap();
+        let mut chroms: Vec<String> = text
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| line.split('\t').next().unwrap().to_string())
+            .collect();
+        chroms.dedup();
+        chroms
+    };
+    assert_eq!(run(&[]), vec!["chr1", "chr2"]);
+    assert_eq!(run(&["--archive-member", "bundle/chr2.fa"]), vec!["chr2"]);
+    // a member of an input that is not an archive is an error
+    let plain = dir.join("in.fa");
+    std::fs::write(&plain, &chr1).unwrap();
+    let status = Command::new("target/debug/symcurve")
+        .args(["--force", "--archive-member", "chr1.fa"])
+        .arg(&plain)
+        .arg(dir.join("plain.tsv"))
+        .status()
+        .expect("Failed to execute command");
+    assert!(!status.success());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_interrupt_checkpoint() {
+    use std::io::Write;
+    use std::process::Stdio;
+    use std::thread;
+    use std::time::Duration;
+    let dir = std::env::temp_dir().join("symcurve_test_main_interrupt");
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = dir.join("out.tsv");
+    let checkpoint = dir.join("out.tsv.checkpoint");
+    let _ = std::fs::remove_file(&checkpoint);
+    let mut child = Command::new("target/debug/symcurve")
+        .arg("--force")
+        .arg("-")
+        .arg(&output)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute command");
+    let mut stdin = child.stdin.take().unwrap();
+    let seq = "ACGTTTAAAGGC".repeat(10);
+    writeln!(stdin, ">chr1\n{}", seq).unwrap();
+    stdin.flush().unwrap();
+    // interrupted while chr1 is being read, the run finishes chr1 and stops before chr2
+    thread::sleep(Duration::from_millis(500));
+    let killed = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    thread::sleep(Duration::from_millis(200));
+    writeln!(stdin, ">chr2\n{}\n>chr3\n{}", seq, seq).unwrap();
+    drop(stdin);
+    let result = child.wait_with_output().unwrap();
+    assert!(!result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("interrupted after 1 records"));
+    assert_eq!(std::fs::read_to_string(&checkpoint).unwrap(), "chr1\n");
+    let text = std::fs::read_to_string(&output).unwrap();
+    let rows: Vec<&str> = text.lines().filter(|line| !line.starts_with('#')).collect();
+    assert!(rows.iter().all(|line| line.starts_with("chr1\t")));
+    assert_eq!(rows.len(), seq.len());
+}
+
+#[test]
+fn test_stranded() {
+    let dir = std::env::temp_dir().join("symcurve_test_main_stranded");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("in.fa");
+    std::fs::write(&input, format!(">chr1\n{}\n", "ACGTTTAAAGGC".repeat(10))).unwrap();
+    let output = Command::new("target/debug/symcurve")
+        .args(["--force", "--stranded"])
+        .arg(&input)
+        .arg(dir.join("out.bedGraph"))
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    assert!(!dir.join("out.bedGraph").exists());
+    for strand in ["plus", "minus"] {
+        let path = dir.join(format!("out.{}.bedGraph", strand));
+        let text = std::fs::read_to_string(&path).unwrap();
+        // the provenance of the run comes first
+        assert!(text.starts_with("# symcurve version: "));
+        assert!(text
+            .lines()
+            .find(|line| !line.starts_with('#'))
+            .unwrap()
+            .starts_with("chr1\t"));
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_warnings_summary() {
+    let dir = std::env::temp_dir().join("symcurve_test_main_warnings_summary");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("in.fa");
+    let seq = "ACGTTTAAAGGC".repeat(10);
+    std::fs::write(&input, format!(">chr1\n{}R{}\n>chrUn\nACGT\n", seq, seq)).unwrap();
+    let output = Command::new("target/debug/symcurve")
+        .arg("--force")
+        .arg(&input)
+        .arg(dir.join("out.bedGraph"))
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // each issue as it is found, then the summary
+    assert!(stderr.starts_with("warning: chr1:121-121: 1 ambiguous bases (treated as gaps)\n"));
+    assert!(stderr.contains("warning: chrUn: 1 sequences too short for a value\nwarnings: "));
+    assert!(stderr.contains("1 ambiguous bases (treated as gaps), first in chr1\n"));
+    assert!(stderr.contains("1 sequences too short for a value, first in chrUn\n"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_warning_limit() {
+    let dir = std::env::temp_dir().join("symcurve_test_main_warning_limit");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("in.fa");
+    let seq = "ACGTTTAAAGGC".repeat(5);
+    std::fs::write(&input, format!(">chr1\n{}\n", [seq.as_str(); 6].join("R"))).unwrap();
+    let output = Command::new("target/debug/symcurve")
+        .arg("--force")
+        .arg("--issues")
+        .arg("--warning-limit")
+        .arg("2")
+        .arg(&input)
+        .arg(dir.join("out.bedGraph"))
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let shown = stderr
+        .lines()
+        .filter(|line| line.ends_with("1 ambiguous bases (treated as gaps)"))
+        .count();
+    assert_eq!(shown, 2);
+    assert!(stderr
+        .contains("warning: chr1: 3 more ambiguous_bases warnings not shown (3 occurrences)\n"));
+    let issues = std::fs::read_to_string(dir.join("out.issues.jsonl")).unwrap();
+    assert_eq!(issues.lines().count(), 5);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_manifest() {
+    let dir = std::env::temp_dir().join("symcurve_test_main_manifest");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("in.fa");
+    std::fs::write(&input, format!(">chr1\n{}\n", "ACGTTTAAAGGC".repeat(10))).unwrap();
+    let output = dir.join("out.bedGraph");
+    let status = Command::new("target/debug/symcurve")
+        .arg("--force")
+        .arg("--manifest")
+        .arg(&input)
+        .arg(&output)
+        .status()
+        .expect("Failed to execute command");
+    assert!(status.success());
+    let json = std::fs::read_to_string(dir.join("out.bedGraph.manifest.json")).unwrap();
+    assert!(json.contains(&format!("\"path\": \"{}\"", output.display())));
+    assert!(json.contains("\"sha256\": "));
+    // the build recorded is the one -V --verbose reports
+    let version = Command::new("target/debug/symcurve")
+        .args(["-V", "--verbose"])
+        .output()
+        .expect("Failed to execute command");
+    let version = String::from_utf8_lossy(&version.stdout);
+    let commit = version
+        .lines()
+        .find_map(|line| line.strip_prefix("commit: "))
+        .unwrap();
+    assert!(json.contains(&format!("\"commit\": \"{}\"", commit)));
+    assert!(json.contains("\"features\": "));
+    assert!(json.contains("\"parameter_hash\": "));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_notify_url() {
+    let dir = std::env::temp_dir().join("symcurve_test_main_notify_url");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("in.fa");
+    let done = dir.join("run.done");
+    std::fs::write(&input, format!(">chr1\n{}\n", "ACGTTTAAAGGC".repeat(10))).unwrap();
+    let output = dir.join("out.bedGraph");
+    let status = Command::new("target/debug/symcurve")
+        .arg("--force")
+        .arg("--notify-url")
+        .arg(format!("file://{}", done.display()))
+        .arg(&input)
+        .arg(&output)
+        .status()
+        .expect("Failed to execute command");
+    assert!(status.success());
+    let json = std::fs::read_to_string(&done).unwrap();
+    assert!(json.contains("\"status\": \"success\""));
+    assert!(json.contains(&format!("\"path\": \"{}\"", output.display())));
+    // a failed run is notified too, with its error
+    let status = Command::new("target/debug/symcurve")
+        .arg("--force")
+        .arg("--notify-url")
+        .arg(format!("file://{}", done.display()))
+        .arg(dir.join("missing.fa"))
+        .arg(&output)
+        .status()
+        .expect("Failed to execute command");
+    assert!(!status.success());
+    let json = std::fs::read_to_string(&done).unwrap();
+    assert!(json.contains("\"status\": \"failure\""));
+    assert!(json.contains("missing.fa"));
+    // so is a run failing before it starts, here on a work directory that is a file
+    std::fs::remove_file(&done).unwrap();
+    let status = Command::new("target/debug/symcurve")
+        .arg("--force")
+        .arg("--notify-url")
+        .arg(format!("file://{}", done.display()))
+        .arg("--work-dir")
+        .arg(&input)
+        .arg(&input)
+        .arg("out.bedGraph")
+        .status()
+        .expect("Failed to execute command");
+    assert!(!status.success());
+    let json = std::fs::read_to_string(&done).unwrap();
+    assert!(json.contains("\"status\": \"failure\""));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_work_dir() {
+    let dir = std::env::temp_dir().join("symcurve_test_main_work_dir");
+    let work_dir = dir.join("work");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("in.fa");
+    std::fs::write(&input, format!(">chr1\n{}\n", "ACGTTTAAAGGC".repeat(10))).unwrap();
+    let output = Command::new("target/debug/symcurve")
+        .arg("--force")
+        .arg("--dict")
+        .arg("--work-dir")
+        .arg(&work_dir)
+        .arg("--emit-outputs")
+        .arg("json")
+        .arg(&input)
+        .arg("tracks/out.bedGraph")
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "{{\"work_dir\":\"{}\",\"outputs\":[{{\"kind\":\"track\",\"path\":\"tracks/out.bedGraph\"}},\
+             {{\"kind\":\"dict\",\"path\":\"tracks/out.dict\"}}]}}\n",
+            work_dir.display()
+        )
+    );
+    assert!(work_dir.join("tracks/out.bedGraph").exists());
+    // the temporary directory is left only if something is in it
+    assert!(!work_dir.join(".symcurve-tmp").exists());
+    let output = Command::new("target/debug/symcurve")
+        .arg("--force")
+        .arg("--work-dir")
+        .arg(&work_dir)
+        .arg(&input)
+        .arg(dir.join("out.bedGraph"))
+        .output()
+        .expect("Failed to execute command");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("must be relative to --work-dir"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_tui() {
+    let dir = std::env::temp_dir().join("symcurve_test_main_tui");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("in.fa");
+    let seq = "ACGTTTAAAGGC".repeat(10);
+    std::fs::write(&input, format!(">chr1\n{}\n", seq)).unwrap();
+    let output = Command::new("target/debug/symcurve")
+        .arg("--force")
+        .arg("--tui")
+        .arg(&input)
+        .arg(dir.join("out.bedGraph"))
+        .output()
+        .expect("Failed to execute command");
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // the first frame is drawn as soon as the first record is computed
+    assert!(stderr.starts_with("\x1b[H\x1b[Jsymcurve  "));
+    assert!(stderr.contains("  chr1  ["));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
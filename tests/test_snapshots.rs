@@ -0,0 +1,213 @@
+//! Golden snapshot tests: the binary is run on the bundled mini-genome in `tests/data` and its
+//! output, on stdout or in the file it writes, compared byte for byte with the files in
+//! `tests/snapshots`.
+//!
+//! After an intended change of output, regenerate the snapshots with
+//!
+//! ```text
+//! SYMCURVE_UPDATE_SNAPSHOTS=1 cargo test --test test_snapshots
+//! ```
+//!
+//! and review the differences with `git diff tests/snapshots`.
+use std::path::Path;
+use std::process::Command;
+
+/// Runs symcurve with the given arguments from the crate root, and returns its standard output.
+fn run(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_symcurve"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("Failed to execute command");
+    assert!(
+        output.status.success(),
+        "symcurve {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Checks the standard output of symcurve with the given arguments against
+/// `tests/snapshots/<name>.txt`.
+fn snapshot(name: &str, args: &[&str]) {
+    check(name, args, &run(args));
+}
+
+/// Checks the file symcurve writes with the given arguments against
+/// `tests/snapshots/<name>.txt`. The output path is a temporary `.tsv` file put in place of the
+/// `{output}` argument.
+fn snapshot_output(name: &str, args: &[&str]) {
+    let path = std::env::temp_dir().join(format!("symcurve_test_snapshot_{}.tsv", name));
+    let path_arg = path.to_string_lossy();
+    let args: Vec<&str> = args
+        .iter()
+        .map(|&arg| if arg == "{output}" { &path_arg } else { arg })
+        .collect();
+    let _ = std::fs::remove_file(&path);
+    run(&args);
+    let actual = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    check(name, &args, &actual);
+}
+
+/// Compares output with its snapshot, or rewrites the snapshot if `SYMCURVE_UPDATE_SNAPSHOTS`
+/// is set.
+fn check(name: &str, args: &[&str], actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(format!("{}.txt", name));
+    if std::env::var_os("SYMCURVE_UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "no snapshot {}; run with SYMCURVE_UPDATE_SNAPSHOTS=1 to create it",
+            path.display()
+        )
+    });
+    if actual != expected {
+        let line = actual
+            .lines()
+            .zip(expected.lines())
+            .position(|(a, e)| a != e)
+            .unwrap_or_else(|| actual.lines().count().min(expected.lines().count()));
+        panic!(
+            "output of symcurve {} differs from {} at line {}:\n   actual: {}\n expected: {}",
+            args.join(" "),
+            path.display(),
+            line + 1,
+            actual.lines().nth(line).unwrap_or("<end of output>"),
+            expected.lines().nth(line).unwrap_or("<end of snapshot>")
+        );
+    }
+}
+
+#[test]
+fn test_snapshot_run() {
+    snapshot_output("run", &["tests/data/mini.fa", "{output}"]);
+}
+
+#[test]
+fn test_snapshot_run_preset() {
+    snapshot_output(
+        "run_fine",
+        &[
+            "--preset",
+            "fine",
+            "--edge-mode",
+            "shrink",
+            "tests/data/mini.fa",
+            "{output}",
+        ],
+    );
+}
+
+#[test]
+fn test_snapshot_run_bedgraph() {
+    snapshot_output(
+        "run_bedgraph",
+        &[
+            "--output-format",
+            "bedgraph",
+            "--float-format",
+            "fixed:2",
+            "tests/data/mini.fa",
+            "{output}",
+        ],
+    );
+}
+
+#[test]
+fn test_snapshot_run_bigwig() {
+    let path = std::env::temp_dir().join("symcurve_test_snapshot_run.bw");
+    let path_arg = path.to_string_lossy();
+    let _ = std::fs::remove_file(&path);
+    run(&["tests/data/mini.fa", &path_arg]);
+    let mut actual = String::new();
+    for region in ["chrA:1-400", "chrB:1-250"] {
+        actual.push_str(&run(&["view", &path_arg, region]));
+    }
+    std::fs::remove_file(&path).unwrap();
+    check("run_bigwig", &["view", "{output}"], &actual);
+}
+
+#[test]
+fn test_snapshot_sweep() {
+    snapshot(
+        "sweep",
+        &[
+            "sweep",
+            "--param",
+            "curve-step=10..20:5",
+            "--region",
+            "chrA:1-300",
+            "tests/data/mini.fa",
+        ],
+    );
+}
+
+#[test]
+fn test_snapshot_explain() {
+    snapshot(
+        "explain",
+        &["explain", "tests/data/mini.fa", "chrA:101-130"],
+    );
+}
+
+#[test]
+fn test_snapshot_scan_mutations() {
+    snapshot(
+        "scan_mutations",
+        &[
+            "scan-mutations",
+            "--region",
+            "chrA:181-200",
+            "tests/data/mini.fa",
+        ],
+    );
+}
+
+#[test]
+fn test_snapshot_motifs() {
+    snapshot(
+        "motifs",
+        &[
+            "motifs",
+            "--threshold",
+            "8",
+            "--width",
+            "11",
+            "tests/data/mini.fa",
+        ],
+    );
+}
+
+#[test]
+fn test_snapshot_metagenome() {
+    snapshot(
+        "metagenome",
+        &["metagenome", "--threads", "1", "tests/data/mini.fa"],
+    );
+}
+
+#[test]
+fn test_snapshot_reads() {
+    snapshot(
+        "reads",
+        &[
+            "reads",
+            "--symcurve-win",
+            "21",
+            "--threads",
+            "2",
+            "tests/data/mini.fastq",
+        ],
+    );
+}
+
+#[test]
+fn test_snapshot_view() {
+    snapshot("view", &["view", "tests/data/mini.bedGraph", "chrA:50-150"]);
+}